@@ -0,0 +1,162 @@
+'use strict';
+
+let selectedTable = null;
+
+function escapeHtml(x) {
+  return String(x)
+    .replace(/&/g, '&amp;')
+    .replace(/</g, '&lt;')
+    .replace(/>/g, '&gt;')
+    .replace(/"/g, '&quot;');
+}
+
+async function call(method, path, params) {
+  let url = path;
+  const init = {method};
+  if (method === 'GET') {
+    if (params) {
+      url += '?' + new URLSearchParams(params).toString();
+    }
+  } else {
+    init.body = JSON.stringify(params || {});
+  }
+  const resp = await fetch(url, init);
+  const body = await resp.json();
+  if (!resp.ok) {
+    throw new Error(body.message || resp.statusText);
+  }
+  return body;
+}
+
+function renderRows(headers, rows) {
+  const head = headers.map((h) => `<th>${escapeHtml(h)}</th>`).join('');
+  const body = rows
+    .map((row) => '<tr>' + row.map((x) => `<td>${escapeHtml(x)}</td>`).join('') + '</tr>')
+    .join('');
+  return `<table><thead><tr>${head}</tr></thead><tbody>${body}</tbody></table>`;
+}
+
+function partitionString(partition) {
+  const names = Object.keys(partition).sort();
+  if (names.length === 0) {
+    return '(none)';
+  }
+  return names.map((name) => `${name}=${partition[name]}`).join(', ');
+}
+
+async function loadTables() {
+  const resp = await call('GET', '/rest/list_tables');
+  const names = resp.tables.map((t) => t.tableName).sort();
+  const list = document.getElementById('tables');
+  list.innerHTML = '';
+  for (const name of names) {
+    const li = document.createElement('li');
+    li.textContent = name;
+    if (name === selectedTable) {
+      li.className = 'selected';
+    }
+    li.onclick = () => selectTable(name);
+    list.appendChild(li);
+  }
+}
+
+async function loadTableDetail(tableName) {
+  const detail = document.getElementById('table-detail');
+  const [schemaResp, segmentsResp] = await Promise.all([
+    call('GET', '/rest/get_schema', {tableName}),
+    call('GET', '/rest/list_segments', {tableName}),
+  ]);
+  const schema = schemaResp.schema;
+
+  const partitionRows = Object.keys(schema.partitioning).sort()
+    .map((name) => [name, schema.partitioning[name].dtype]);
+  const columnRows = Object.keys(schema.columns).sort()
+    .map((name) => {
+      const meta = schema.columns[name];
+      return [name, meta.dtype, meta.nestedListDepth || 0];
+    });
+
+  const segmentsByPartition = new Map();
+  for (const segment of segmentsResp.segments) {
+    const key = partitionString(segment.partition);
+    if (!segmentsByPartition.has(key)) {
+      segmentsByPartition.set(key, []);
+    }
+    segmentsByPartition.get(key).push(segment);
+  }
+  const segmentRows = [];
+  for (const key of [...segmentsByPartition.keys()].sort()) {
+    for (const segment of segmentsByPartition.get(key)) {
+      const stats = segment.stats || {};
+      segmentRows.push([
+        key,
+        segment.segmentId,
+        stats.rowCount ?? '',
+        stats.allTimeDeletedN ?? '',
+        stats.stagedN ?? '',
+        stats.uncompressedBytes ?? '',
+        stats.readVersion ?? '',
+        stats.isCold ?? '',
+        stats.lastFlushAt ?? '',
+      ]);
+    }
+  }
+
+  detail.innerHTML = `
+    <h2>${escapeHtml(tableName)}</h2>
+    <button id="compact">Compact table</button>
+    <span id="compact-status" class="muted"></span>
+    <h3>Partitioning</h3>
+    ${partitionRows.length ? renderRows(['Name', 'Type'], partitionRows) : '<p class="muted">Not partitioned.</p>'}
+    <h3>Columns</h3>
+    ${renderRows(['Name', 'Type', 'Nested list depth'], columnRows)}
+    <h3>Segments (${segmentRows.length} in ${segmentsByPartition.size} partitions)</h3>
+    ${renderRows(
+      ['Partition', 'Segment', 'Rows', 'Deleted', 'Staged', 'Uncompressed bytes', 'Version', 'Cold', 'Last flush'],
+      segmentRows,
+    )}
+  `;
+  document.getElementById('compact').onclick = () => compactTable(tableName);
+}
+
+async function compactTable(tableName) {
+  const status = document.getElementById('compact-status');
+  status.className = 'muted';
+  status.textContent = 'compacting...';
+  try {
+    const resp = await call('POST', '/admin/compact_table', {tableName});
+    status.textContent = `compacted ${resp.nSegmentsCompacted} of ${resp.nSegmentsChecked} segments`;
+    await loadTableDetail(tableName);
+  } catch (e) {
+    status.className = 'error';
+    status.textContent = e.message;
+  }
+}
+
+async function loadErrors() {
+  const resp = await call('GET', '/admin/recent_errors');
+  const rows = resp.errors.slice().reverse().map((e) => [e.at, e.message]);
+  document.getElementById('errors').innerHTML = rows
+    .map((row) => '<tr>' + row.map((x) => `<td>${escapeHtml(x)}</td>`).join('') + '</tr>')
+    .join('');
+}
+
+async function selectTable(tableName) {
+  selectedTable = tableName;
+  await refresh();
+}
+
+async function refresh() {
+  const detail = document.getElementById('table-detail');
+  try {
+    await Promise.all([loadTables(), loadErrors()]);
+    if (selectedTable !== null) {
+      await loadTableDetail(selectedTable);
+    }
+  } catch (e) {
+    detail.innerHTML = `<p class="error">${escapeHtml(e.message)}</p>`;
+  }
+}
+
+document.getElementById('refresh').onclick = refresh;
+refresh();
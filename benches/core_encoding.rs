@@ -0,0 +1,106 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use pancake_db_core::compression;
+use pancake_db_core::encoding;
+use pancake_db_idl::dml::{FieldValue, RepeatedFieldValue};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use prost_types::Timestamp;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+const N_ROWS: usize = 10_000;
+const DTYPES: [DataType; 7] = [
+  DataType::Int64,
+  DataType::String,
+  DataType::Float32,
+  DataType::Float64,
+  DataType::Bytes,
+  DataType::Bool,
+  DataType::TimestampMicros,
+];
+
+// roughly what event data looks like: small ints, short strings from a
+// small vocabulary, recent timestamps, and some nulls
+fn atom(rng: &mut StdRng, dtype: DataType) -> Value {
+  match dtype {
+    DataType::Int64 => Value::Int64Val(rng.gen_range(0..100_000)),
+    DataType::String => Value::StringVal(format!("user_{}", rng.gen_range(0..1000))),
+    DataType::Float32 => Value::Float32Val(rng.gen()),
+    DataType::Float64 => Value::Float64Val(rng.gen()),
+    DataType::Bytes => Value::BytesVal((0..16).map(|_| rng.gen()).collect()),
+    DataType::Bool => Value::BoolVal(rng.gen()),
+    DataType::TimestampMicros => Value::TimestampVal(Timestamp {
+      seconds: 1_640_000_000 + rng.gen_range(0..86_400),
+      nanos: rng.gen_range(0..1_000_000) * 1000,
+    }),
+  }
+}
+
+fn field_values(dtype: DataType, nested_list_depth: u8) -> Vec<FieldValue> {
+  let mut rng = StdRng::seed_from_u64(0);
+  (0..N_ROWS)
+    .map(|_| {
+      if rng.gen_bool(0.1) {
+        return FieldValue::default();
+      }
+      let value = if nested_list_depth == 0 {
+        atom(&mut rng, dtype)
+      } else {
+        let len = rng.gen_range(0..5);
+        Value::ListVal(RepeatedFieldValue {
+          vals: (0..len)
+            .map(|_| FieldValue {
+              value: Some(atom(&mut rng, dtype)),
+            })
+            .collect(),
+        })
+      };
+      FieldValue {
+        value: Some(value),
+      }
+    })
+    .collect()
+}
+
+fn bench_name(dtype: DataType, nested_list_depth: u8) -> String {
+  if nested_list_depth == 0 {
+    format!("{:?}", dtype)
+  } else {
+    format!("{:?}List", dtype)
+  }
+}
+
+// Flush files are encoded and compaction files are compressed, so these
+// cover both sides of each.
+fn encoding_benches(c: &mut Criterion) {
+  for &dtype in &DTYPES {
+    for nested_list_depth in 0..2 {
+      let values = field_values(dtype, nested_list_depth);
+      let name = bench_name(dtype, nested_list_depth);
+      let encoder = encoding::new_encoder(dtype, nested_list_depth);
+      let decoder = encoding::new_field_value_decoder(dtype, nested_list_depth);
+      let codec = compression::new_codec(dtype, &compression::choose_codec(dtype)).unwrap();
+      let encoded = encoder.encode(&values).unwrap();
+      let compressed = codec.compress(&values, nested_list_depth).unwrap();
+
+      let mut group = c.benchmark_group("core");
+      group.throughput(Throughput::Elements(N_ROWS as u64));
+      group.bench_function(BenchmarkId::new("encode", &name), |b| {
+        b.iter(|| encoder.encode(&values).unwrap())
+      });
+      group.bench_function(BenchmarkId::new("decode", &name), |b| {
+        b.iter(|| decoder.decode(&encoded).unwrap())
+      });
+      group.bench_function(BenchmarkId::new("compress", &name), |b| {
+        b.iter(|| codec.compress(&values, nested_list_depth).unwrap())
+      });
+      group.bench_function(BenchmarkId::new("decompress", &name), |b| {
+        b.iter(|| codec.decompress(&compressed, nested_list_depth).unwrap())
+      });
+      group.finish();
+    }
+  }
+}
+
+criterion_group!(benches, encoding_benches);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use pancake_db_client_ext::{PancakeClient, PartitionBuilder, RowBuilder, SchemaBuilder};
+use pancake_db_idl::ddl::CreateTableRequest;
+use pancake_db_idl::dml::WriteToPartitionRequest;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_server::Embedded;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tokio::runtime::Runtime;
+
+const TABLE_NAME: &str = "bench_events";
+const N_ROWS: usize = 10_000;
+// so the first compaction after a flush is always due
+const ARGS: [&str; 4] = [
+  "--min-rows-for-compaction", "1",
+  "--min-compaction-intermission-seconds", "0",
+];
+
+// Starts an in-memory server with one segment of N_ROWS staged rows. The
+// background loops wait a full interval before their first iteration, so
+// they don't get to these rows before the benchmarks do.
+async fn start_with_staged_rows() -> Embedded {
+  let embedded = Embedded::start_in_memory_with_args(&ARGS).await.unwrap();
+  let mut client = embedded.client();
+  client.create_table(CreateTableRequest {
+    table_name: TABLE_NAME.to_string(),
+    schema: Some(
+      SchemaBuilder::new()
+        .column("user_id", DataType::String)
+        .column("cents_amount", DataType::Int64)
+        .column("score", DataType::Float64)
+        .column("is_refund", DataType::Bool)
+        .column("tags", DataType::String)
+        .build()
+    ),
+    ..Default::default()
+  }).await.unwrap();
+
+  let mut rng = StdRng::seed_from_u64(0);
+  let rows = (0..N_ROWS)
+    .map(|_| {
+      RowBuilder::new()
+        .field("user_id", format!("user_{}", rng.gen_range(0..1000)))
+        .field("cents_amount", rng.gen_range(0..100_000_i64))
+        .field("score", rng.gen::<f64>())
+        .field("is_refund", rng.gen_bool(0.05))
+        .field("tags", "checkout")
+        .build()
+    })
+    .collect();
+  client.write_to_partition(WriteToPartitionRequest {
+    table_name: TABLE_NAME.to_string(),
+    partition: PartitionBuilder::new().build(),
+    rows,
+  }).await.unwrap();
+  embedded
+}
+
+async fn time_flushes(iters: u64) -> Duration {
+  let mut total = Duration::ZERO;
+  for _ in 0..iters {
+    let embedded = start_with_staged_rows().await;
+    let start = Instant::now();
+    embedded.flush().await.unwrap();
+    total += start.elapsed();
+  }
+  total
+}
+
+async fn time_compactions(iters: u64) -> Duration {
+  let mut total = Duration::ZERO;
+  for _ in 0..iters {
+    let embedded = start_with_staged_rows().await;
+    embedded.flush().await.unwrap();
+    let start = Instant::now();
+    embedded.compact().await.unwrap();
+    total += start.elapsed();
+  }
+  total
+}
+
+// Each iteration gets its own in-memory server, so only the op is timed,
+// without disk noise or fsyncs.
+fn server_benches(c: &mut Criterion) {
+  let runtime = Runtime::new().unwrap();
+  let mut group = c.benchmark_group("server");
+  group.sample_size(10);
+  group.throughput(Throughput::Elements(N_ROWS as u64));
+  group.bench_function("flush", |b| {
+    b.to_async(&runtime).iter_custom(time_flushes)
+  });
+  group.bench_function("compact", |b| {
+    b.to_async(&runtime).iter_custom(time_compactions)
+  });
+  group.finish();
+}
+
+criterion_group!(benches, server_benches);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use pancake_db_client::errors::ClientError;
+use pancake_db_client_ext::{ClientErrorExt, ErrorCode};
+
+#[derive(Debug)]
+pub struct CliError {
+  pub message: String,
+  // for errors from the server
+  pub code: Option<ErrorCode>,
+  pub is_retryable: bool,
+}
+
+impl CliError {
+  pub fn new(message: impl Into<String>) -> Self {
+    CliError {
+      message: message.into(),
+      code: None,
+      is_retryable: false,
+    }
+  }
+
+  // Lets scripts tell errors apart: 2 for invalid requests, 3 for missing
+  // tables and such, and 75 (EX_TEMPFAIL) for errors worth retrying.
+  pub fn exit_code(&self) -> i32 {
+    match self.code {
+      _ if self.is_retryable => 75,
+      Some(code) if code.is_validation() => 2,
+      Some(code) if code.is_not_found() => 3,
+      _ => 1,
+    }
+  }
+}
+
+impl Display for CliError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self.code {
+      Some(code) => write!(f, "{} ({})", self.message, code),
+      None => f.write_str(&self.message),
+    }
+  }
+}
+
+impl std::error::Error for CliError {}
+
+trait CliUpcastableError: std::error::Error {}
+impl CliUpcastableError for std::io::Error {}
+impl CliUpcastableError for serde_json::Error {}
+impl CliUpcastableError for toml::de::Error {}
+impl CliUpcastableError for chrono::ParseError {}
+
+impl<E> From<E> for CliError where E: CliUpcastableError {
+  fn from(e: E) -> Self {
+    CliError::new(e.to_string())
+  }
+}
+
+impl From<ClientError> for CliError {
+  fn from(mut e: ClientError) -> Self {
+    let code = e.error_code();
+    let is_retryable = e.is_retryable();
+    // the code is shown separately
+    if let Some((_, rest)) = ErrorCode::split_message(&e.message) {
+      e.message = rest.to_string();
+    }
+    CliError {
+      message: e.to_string(),
+      code,
+      is_retryable,
+    }
+  }
+}
+
+pub type CliResult<T> = Result<T, CliError>;
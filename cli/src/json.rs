@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use pancake_db_idl::dml::{FieldValue, PartitionFieldValue, RepeatedFieldValue, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::Schema;
+use prost_types::Timestamp;
+use serde_json::{Map, Number, Value as JsonValue};
+
+use crate::errors::{CliError, CliResult};
+
+// JSON values follow the same conventions as the server's REST API:
+// timestamps are RFC 3339 strings and bytes are base 64 strings.
+
+fn parse_timestamp(s: &str) -> CliResult<Timestamp> {
+  let t = DateTime::parse_from_rfc3339(s)?;
+  Ok(Timestamp::from(SystemTime::from(t)))
+}
+
+fn timestamp_to_json(t: &Timestamp) -> CliResult<JsonValue> {
+  let t = Utc.timestamp_opt(t.seconds, t.nanos as u32)
+    .single()
+    .ok_or_else(|| CliError::new(format!("timestamp {}s {}ns is out of range", t.seconds, t.nanos)))?;
+  Ok(JsonValue::String(t.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+}
+
+fn number_to_i64(n: &Number) -> CliResult<i64> {
+  n.as_i64().ok_or_else(|| CliError::new(format!("{} is not an int64", n)))
+}
+
+fn parse_field_value(json_value: &JsonValue, dtype: DataType) -> CliResult<FieldValue> {
+  let value = match (json_value, dtype) {
+    (JsonValue::Null, _) => return Ok(FieldValue::default()),
+    (JsonValue::String(s), DataType::String) => Value::StringVal(s.to_string()),
+    (JsonValue::String(s), DataType::TimestampMicros) => Value::TimestampVal(parse_timestamp(s)?),
+    (JsonValue::String(s), DataType::Bytes) => Value::BytesVal(
+      base64::decode(s).map_err(|_| CliError::new(format!("{} is not base 64", s)))?
+    ),
+    (JsonValue::Number(n), DataType::Int64) => Value::Int64Val(number_to_i64(n)?),
+    (JsonValue::Number(n), DataType::Float32) => Value::Float32Val(n.as_f64().unwrap_or(f64::NAN) as f32),
+    (JsonValue::Number(n), DataType::Float64) => Value::Float64Val(n.as_f64().unwrap_or(f64::NAN)),
+    (JsonValue::Bool(b), DataType::Bool) => Value::BoolVal(*b),
+    (JsonValue::Array(a), _) => {
+      let vals = a.iter()
+        .map(|x| parse_field_value(x, dtype))
+        .collect::<CliResult<Vec<_>>>()?;
+      Value::ListVal(RepeatedFieldValue { vals })
+    },
+    _ => return Err(CliError::new(format!(
+      "unsupported value {} for {:?}",
+      json_value,
+      dtype,
+    ))),
+  };
+  Ok(FieldValue { value: Some(value) })
+}
+
+fn parse_partition_field_value(json_value: &JsonValue, dtype: PartitionDataType) -> CliResult<PartitionFieldValue> {
+  let value = match (json_value, dtype) {
+    (JsonValue::String(s), PartitionDataType::String) => PartitionValue::StringVal(s.to_string()),
+    (JsonValue::String(s), PartitionDataType::TimestampMinute) => PartitionValue::TimestampVal(parse_timestamp(s)?),
+    (JsonValue::Number(n), PartitionDataType::Int64) => PartitionValue::Int64Val(number_to_i64(n)?),
+    (JsonValue::Bool(b), PartitionDataType::Bool) => PartitionValue::BoolVal(*b),
+    _ => return Err(CliError::new(format!(
+      "unsupported partition value {} for {:?}",
+      json_value,
+      dtype,
+    ))),
+  };
+  Ok(PartitionFieldValue { value: Some(value) })
+}
+
+pub fn parse_row(json_str: &str, schema: &Schema) -> CliResult<Row> {
+  let fields: HashMap<String, JsonValue> = serde_json::from_str(json_str)?;
+  let mut row = Row::default();
+  for (name, json_value) in &fields {
+    let col_meta = schema.columns.get(name)
+      .ok_or_else(|| CliError::new(format!("column {} does not exist", name)))?;
+    row.fields.insert(name.to_string(), parse_field_value(json_value, col_meta.dtype())?);
+  }
+  Ok(row)
+}
+
+pub fn parse_partition(json_str: &str, schema: &Schema) -> CliResult<HashMap<String, PartitionFieldValue>> {
+  let fields: HashMap<String, JsonValue> = serde_json::from_str(json_str)?;
+  let mut partition = HashMap::new();
+  for (name, json_value) in &fields {
+    let meta = schema.partitioning.get(name)
+      .ok_or_else(|| CliError::new(format!("partition field {} does not exist", name)))?;
+    partition.insert(name.to_string(), parse_partition_field_value(json_value, meta.dtype())?);
+  }
+  Ok(partition)
+}
+
+pub fn field_value_to_json(fv: &FieldValue) -> CliResult<JsonValue> {
+  let res = match &fv.value {
+    None => JsonValue::Null,
+    Some(Value::StringVal(x)) => JsonValue::String(x.clone()),
+    Some(Value::BoolVal(x)) => JsonValue::Bool(*x),
+    Some(Value::BytesVal(x)) => JsonValue::String(base64::encode(x)),
+    Some(Value::Int64Val(x)) => JsonValue::from(*x),
+    Some(Value::Float32Val(x)) => JsonValue::from(*x),
+    Some(Value::Float64Val(x)) => JsonValue::from(*x),
+    Some(Value::TimestampVal(x)) => timestamp_to_json(x)?,
+    Some(Value::ListVal(x)) => JsonValue::Array(
+      x.vals.iter().map(field_value_to_json).collect::<CliResult<_>>()?
+    ),
+  };
+  Ok(res)
+}
+
+pub fn partition_field_value_to_json(pfv: &PartitionFieldValue) -> CliResult<JsonValue> {
+  let res = match &pfv.value {
+    None => JsonValue::Null,
+    Some(PartitionValue::StringVal(x)) => JsonValue::String(x.clone()),
+    Some(PartitionValue::BoolVal(x)) => JsonValue::Bool(*x),
+    Some(PartitionValue::Int64Val(x)) => JsonValue::from(*x),
+    Some(PartitionValue::TimestampVal(x)) => timestamp_to_json(x)?,
+  };
+  Ok(res)
+}
+
+pub fn partition_to_json(partition: &HashMap<String, PartitionFieldValue>) -> CliResult<JsonValue> {
+  let map = partition.iter()
+    .map(|(name, pfv)| Ok((name.to_string(), partition_field_value_to_json(pfv)?)))
+    .collect::<CliResult<Map<_, _>>>()?;
+  Ok(JsonValue::Object(map))
+}
+
+// includes the partition fields alongside the row's own fields
+pub fn row_to_json(row: &Row, partition: &HashMap<String, PartitionFieldValue>) -> CliResult<JsonValue> {
+  let mut map = Map::new();
+  for (name, pfv) in partition {
+    map.insert(name.to_string(), partition_field_value_to_json(pfv)?);
+  }
+  for (name, fv) in &row.fields {
+    map.insert(name.to_string(), field_value_to_json(fv)?);
+  }
+  Ok(JsonValue::Object(map))
+}
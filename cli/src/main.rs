@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use pancake_db_client::{Client, SegmentKey};
+use pancake_db_client_ext::ClientErrorExt;
+use pancake_db_idl::ddl::{CreateTableRequest, DropTableRequest, GetSchemaRequest, ListTablesRequest};
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{ListSegmentsRequest, PartitionFieldValue, Row, Segment, WriteToPartitionRequest};
+use pancake_db_idl::schema::Schema;
+use structopt::StructOpt;
+
+use crate::errors::{CliError, CliResult};
+
+mod errors;
+mod json;
+mod schema;
+
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "pancake-cli", about = "Command line client for PancakeDB")]
+struct Opt {
+  // host of the server's GRPC listener
+  #[structopt(long, default_value = "127.0.0.1")]
+  host: IpAddr,
+  // port of the server's GRPC listener
+  #[structopt(long, default_value = "3842")]
+  port: u16,
+  #[structopt(subcommand)]
+  command: Command,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+enum Command {
+  /// Lists all tables
+  ListTables,
+  /// Creates a table from a JSON or TOML (.toml) schema file
+  CreateTable {
+    table_name: String,
+    #[structopt(long)]
+    schema_file: PathBuf,
+    /// One of fail-if-exists, ok-if-exact, or add-new-columns
+    #[structopt(long, default_value = "fail-if-exists", parse(try_from_str = parse_schema_mode))]
+    mode: SchemaMode,
+  },
+  /// Prints a table's schema as JSON
+  GetSchema {
+    table_name: String,
+  },
+  /// Drops a table and all its data
+  DropTable {
+    table_name: String,
+    /// Succeed without dropping anything if the table doesn't exist
+    #[structopt(long)]
+    if_exists: bool,
+  },
+  /// Writes JSON lines rows from stdin
+  Write {
+    table_name: String,
+    /// The partition to write to as a JSON object, e.g. '{"day": "2022-01-01T00:00:00Z"}'
+    #[structopt(long, default_value = "{}")]
+    partition: String,
+    #[structopt(long, default_value = "256")]
+    batch_size: usize,
+  },
+  /// Lists a table's segments as JSON lines
+  ListSegments {
+    table_name: String,
+    #[structopt(long)]
+    include_metadata: bool,
+  },
+  /// Prints every row in a table as JSON lines, including partition fields
+  Read {
+    table_name: String,
+    /// Stop after printing this many rows
+    #[structopt(long)]
+    limit: Option<usize>,
+  },
+  /// Polls a table and prints new rows as JSON lines as they arrive
+  ///
+  /// Rows are tracked by position within each segment, so rows deleted
+  /// while tailing may cause later rows to be skipped.
+  Tail {
+    table_name: String,
+    #[structopt(long, default_value = "5")]
+    interval_seconds: u64,
+    /// Print the rows already in the table before tailing
+    #[structopt(long)]
+    from_beginning: bool,
+  },
+}
+
+fn parse_schema_mode(s: &str) -> CliResult<SchemaMode> {
+  match s {
+    "fail-if-exists" => Ok(SchemaMode::FailIfExists),
+    "ok-if-exact" => Ok(SchemaMode::OkIfExact),
+    "add-new-columns" => Ok(SchemaMode::AddNewColumns),
+    _ => Err(CliError::new(format!("unknown schema mode {}", s))),
+  }
+}
+
+fn print_json(value: &serde_json::Value) -> CliResult<()> {
+  let mut stdout = std::io::stdout();
+  writeln!(stdout, "{}", value)?;
+  Ok(())
+}
+
+async fn get_schema(client: &mut Client, table_name: &str) -> CliResult<Schema> {
+  let resp = client.get_schema(GetSchemaRequest {
+    table_name: table_name.to_string(),
+  }).await?;
+  resp.schema.ok_or_else(|| CliError::new("server returned no schema"))
+}
+
+async fn list_segments(client: &mut Client, table_name: &str, include_metadata: bool) -> CliResult<Vec<Segment>> {
+  let resp = client.list_segments(ListSegmentsRequest {
+    table_name: table_name.to_string(),
+    include_metadata,
+    ..Default::default()
+  }).await?;
+  Ok(resp.segments)
+}
+
+fn segment_key(table_name: &str, segment: &Segment) -> SegmentKey {
+  SegmentKey {
+    table_name: table_name.to_string(),
+    partition: segment.partition.clone(),
+    segment_id: segment.segment_id.clone(),
+  }
+}
+
+async fn write(
+  client: &mut Client,
+  table_name: &str,
+  partition_str: &str,
+  batch_size: usize,
+) -> CliResult<()> {
+  let schema = get_schema(client, table_name).await?;
+  let partition = json::parse_partition(partition_str, &schema)?;
+  let batch_size = batch_size.max(1);
+
+  let mut n_written = 0;
+  let mut rows = Vec::with_capacity(batch_size);
+  let stdin = std::io::stdin();
+  for (line_idx, line_res) in stdin.lock().lines().enumerate() {
+    let line = line_res?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    let row = json::parse_row(&line, &schema)
+      .map_err(|e| CliError::new(format!("line {}: {}", line_idx + 1, e)))?;
+    rows.push(row);
+    if rows.len() >= batch_size {
+      write_batch(client, table_name, &partition, std::mem::take(&mut rows), &mut n_written).await?;
+    }
+  }
+  if !rows.is_empty() {
+    write_batch(client, table_name, &partition, rows, &mut n_written).await?;
+  }
+  eprintln!("wrote {} rows", n_written);
+  Ok(())
+}
+
+async fn write_batch(
+  client: &mut Client,
+  table_name: &str,
+  partition: &HashMap<String, PartitionFieldValue>,
+  rows: Vec<Row>,
+  n_written: &mut usize,
+) -> CliResult<()> {
+  let n_rows = rows.len();
+  let res = client.write_to_partition(WriteToPartitionRequest {
+    table_name: table_name.to_string(),
+    partition: partition.clone(),
+    rows,
+  }).await;
+  match res {
+    Ok(_) => {
+      *n_written += n_rows;
+      Ok(())
+    },
+    Err(e) => {
+      // only rejected batches are known not to have been written
+      if e.is_validation() {
+        eprintln!("wrote {} rows before a batch of {} was rejected", n_written, n_rows);
+      }
+      Err(e.into())
+    },
+  }
+}
+
+async fn read(client: &mut Client, table_name: &str, limit: Option<usize>) -> CliResult<()> {
+  let schema = get_schema(client, table_name).await?;
+  let mut n_printed = 0;
+  for segment in &list_segments(client, table_name, false).await? {
+    let rows = client.decode_segment(&segment_key(table_name, segment), &schema.columns).await?;
+    for row in &rows {
+      if limit.map(|l| n_printed >= l).unwrap_or(false) {
+        return Ok(());
+      }
+      print_json(&json::row_to_json(row, &segment.partition)?)?;
+      n_printed += 1;
+    }
+  }
+  Ok(())
+}
+
+async fn tail(
+  client: &mut Client,
+  table_name: &str,
+  interval: Duration,
+  from_beginning: bool,
+) -> CliResult<()> {
+  let schema = get_schema(client, table_name).await?;
+  // number of rows already printed (or skipped) per segment ID
+  let mut seen = HashMap::new();
+  let mut is_first_poll = true;
+  loop {
+    match tail_poll(client, table_name, &schema, &mut seen, is_first_poll && !from_beginning).await {
+      Ok(()) => is_first_poll = false,
+      // keep tailing through restarts and overload; rows are only marked
+      // seen once printed, so none are skipped
+      Err(e) if e.is_retryable => eprintln!("warning: {}; retrying", e),
+      Err(e) => return Err(e),
+    }
+    tokio::time::sleep(interval).await;
+  }
+}
+
+async fn tail_poll(
+  client: &mut Client,
+  table_name: &str,
+  schema: &Schema,
+  seen: &mut HashMap<String, usize>,
+  skip_existing: bool,
+) -> CliResult<()> {
+  for segment in &list_segments(client, table_name, true).await? {
+    let row_count = segment.metadata.as_ref()
+      .map(|meta| meta.row_count as usize)
+      .unwrap_or_default();
+    let n_seen = seen.entry(segment.segment_id.clone()).or_insert(0);
+    if skip_existing {
+      *n_seen = row_count;
+      continue;
+    }
+    if row_count <= *n_seen {
+      continue;
+    }
+
+    let rows = client.decode_segment(&segment_key(table_name, segment), &schema.columns).await?;
+    for row in rows.iter().skip(*n_seen) {
+      print_json(&json::row_to_json(row, &segment.partition)?)?;
+    }
+    *n_seen = (*n_seen).max(rows.len());
+  }
+  Ok(())
+}
+
+async fn run(opt: Opt) -> CliResult<()> {
+  let mut client = Client::connect(format!("http://{}:{}", opt.host, opt.port)).await?;
+  match opt.command {
+    Command::ListTables => {
+      let resp = client.list_tables(ListTablesRequest {}).await?;
+      for table in &resp.tables {
+        println!("{}", table.table_name);
+      }
+    },
+    Command::CreateTable { table_name, schema_file, mode } => {
+      let schema = schema::read_schema_file(&schema_file)?;
+      let resp = client.create_table(CreateTableRequest {
+        table_name,
+        schema: Some(schema),
+        mode: mode as i32,
+      }).await?;
+      print_json(&serde_json::json!({
+        "alreadyExists": resp.already_exists,
+        "columnsAdded": resp.columns_added,
+      }))?;
+    },
+    Command::GetSchema { table_name } => {
+      let schema = get_schema(&mut client, &table_name).await?;
+      print_json(&schema::schema_to_json(&schema))?;
+    },
+    Command::DropTable { table_name, if_exists } => {
+      match client.drop_table(DropTableRequest { table_name }).await {
+        Err(e) if if_exists && e.is_not_found() => (),
+        res => {
+          res?;
+        },
+      }
+    },
+    Command::Write { table_name, partition, batch_size } => {
+      write(&mut client, &table_name, &partition, batch_size).await?;
+    },
+    Command::ListSegments { table_name, include_metadata } => {
+      for segment in &list_segments(&mut client, &table_name, include_metadata).await? {
+        let mut value = serde_json::json!({
+          "partition": json::partition_to_json(&segment.partition)?,
+          "segmentId": segment.segment_id,
+        });
+        if let Some(meta) = &segment.metadata {
+          value["rowCount"] = meta.row_count.into();
+        }
+        print_json(&value)?;
+      }
+    },
+    Command::Read { table_name, limit } => {
+      read(&mut client, &table_name, limit).await?;
+    },
+    Command::Tail { table_name, interval_seconds, from_beginning } => {
+      tail(
+        &mut client,
+        &table_name,
+        Duration::from_secs(interval_seconds),
+        from_beginning,
+      ).await?;
+    },
+  }
+  Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+  let opt = Opt::from_args();
+  if let Err(e) = run(opt).await {
+    eprintln!("error: {}", e);
+    std::process::exit(e.exit_code());
+  }
+}
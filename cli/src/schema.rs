@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, PartitionMeta, Schema};
+use serde::Deserialize;
+
+use crate::errors::{CliError, CliResult};
+
+// Schema files use the same format as the server's REST API, e.g.
+// {"partitioning": {"day": {"dtype": "timestampMinute"}},
+//  "columns": {"user_id": {"dtype": "string"}}}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ColumnMetaFile {
+  dtype: String,
+  #[serde(default)]
+  nested_list_depth: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartitionMetaFile {
+  dtype: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaFile {
+  #[serde(default)]
+  partitioning: HashMap<String, PartitionMetaFile>,
+  columns: HashMap<String, ColumnMetaFile>,
+}
+
+fn parse_dtype(s: &str) -> CliResult<DataType> {
+  match s {
+    "bool" => Ok(DataType::Bool),
+    "bytes" => Ok(DataType::Bytes),
+    "float32" => Ok(DataType::Float32),
+    "float64" => Ok(DataType::Float64),
+    "int64" => Ok(DataType::Int64),
+    "string" => Ok(DataType::String),
+    "timestampMicros" => Ok(DataType::TimestampMicros),
+    _ => Err(CliError::new(format!("unknown column dtype {}", s))),
+  }
+}
+
+fn parse_partition_dtype(s: &str) -> CliResult<PartitionDataType> {
+  match s {
+    "bool" => Ok(PartitionDataType::Bool),
+    "int64" => Ok(PartitionDataType::Int64),
+    "string" => Ok(PartitionDataType::String),
+    "timestampMinute" => Ok(PartitionDataType::TimestampMinute),
+    _ => Err(CliError::new(format!("unknown partition dtype {}", s))),
+  }
+}
+
+pub fn dtype_name(dtype: DataType) -> &'static str {
+  match dtype {
+    DataType::Bool => "bool",
+    DataType::Bytes => "bytes",
+    DataType::Float32 => "float32",
+    DataType::Float64 => "float64",
+    DataType::Int64 => "int64",
+    DataType::String => "string",
+    DataType::TimestampMicros => "timestampMicros",
+  }
+}
+
+pub fn partition_dtype_name(dtype: PartitionDataType) -> &'static str {
+  match dtype {
+    PartitionDataType::Bool => "bool",
+    PartitionDataType::Int64 => "int64",
+    PartitionDataType::String => "string",
+    PartitionDataType::TimestampMinute => "timestampMinute",
+  }
+}
+
+// reads a .toml schema file, or a JSON one for any other extension
+pub fn read_schema_file(path: &Path) -> CliResult<Schema> {
+  let contents = std::fs::read_to_string(path)?;
+  let is_toml = path.extension()
+    .map(|ext| ext == "toml")
+    .unwrap_or(false);
+  let schema_file: SchemaFile = if is_toml {
+    toml::from_str(&contents)?
+  } else {
+    serde_json::from_str(&contents)?
+  };
+
+  let mut schema = Schema::default();
+  for (name, meta) in &schema_file.partitioning {
+    schema.partitioning.insert(name.to_string(), PartitionMeta {
+      dtype: parse_partition_dtype(&meta.dtype)? as i32,
+    });
+  }
+  for (name, meta) in &schema_file.columns {
+    schema.columns.insert(name.to_string(), ColumnMeta {
+      dtype: parse_dtype(&meta.dtype)? as i32,
+      nested_list_depth: meta.nested_list_depth,
+    });
+  }
+  Ok(schema)
+}
+
+pub fn schema_to_json(schema: &Schema) -> serde_json::Value {
+  let partitioning: serde_json::Map<_, _> = schema.partitioning.iter()
+    .map(|(name, meta)| (
+      name.to_string(),
+      serde_json::json!({"dtype": partition_dtype_name(meta.dtype())}),
+    ))
+    .collect();
+  let columns: serde_json::Map<_, _> = schema.columns.iter()
+    .map(|(name, meta)| (
+      name.to_string(),
+      serde_json::json!({
+        "dtype": dtype_name(meta.dtype()),
+        "nestedListDepth": meta.nested_list_depth,
+      }),
+    ))
+    .collect();
+  serde_json::json!({
+    "partitioning": partitioning,
+    "columns": columns,
+  })
+}
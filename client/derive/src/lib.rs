@@ -0,0 +1,94 @@
+//! `#[derive(PancakeRow)]`, re-exported by `pancake-db-client-ext`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr, parse_macro_input};
+
+// Implements PancakeRow for a struct with named fields, one column per
+// field. A field's column name can be changed with
+// #[pancake(rename = "column_name")].
+#[proc_macro_derive(PancakeRow, attributes(pancake))]
+pub fn derive_pancake_row(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match pancake_row_impl(&input) {
+    Ok(tokens) => tokens.into(),
+    Err(e) => e.to_compile_error().into(),
+  }
+}
+
+fn column_name(field: &syn::Field) -> syn::Result<String> {
+  let mut name = field.ident.as_ref()
+    .expect("named fields have idents")
+    .to_string();
+  for attr in &field.attrs {
+    if !attr.path().is_ident("pancake") {
+      continue;
+    }
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("rename") {
+        name = meta.value()?.parse::<LitStr>()?.value();
+        Ok(())
+      } else {
+        Err(meta.error("unsupported pancake attribute"))
+      }
+    })?;
+  }
+  Ok(name)
+}
+
+fn pancake_row_impl(input: &DeriveInput) -> syn::Result<TokenStream2> {
+  let fields = match &input.data {
+    Data::Struct(data) => match &data.fields {
+      Fields::Named(fields) => &fields.named,
+      _ => return Err(syn::Error::new_spanned(
+        input,
+        "PancakeRow can only be derived for structs with named fields",
+      )),
+    },
+    _ => return Err(syn::Error::new_spanned(
+      input,
+      "PancakeRow can only be derived for structs",
+    )),
+  };
+
+  let mut idents = Vec::new();
+  let mut types = Vec::new();
+  let mut names = Vec::new();
+  for field in fields {
+    idents.push(field.ident.clone().expect("named fields have idents"));
+    types.push(field.ty.clone());
+    names.push(column_name(field)?);
+  }
+
+  let struct_ident = &input.ident;
+  let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+  Ok(quote! {
+    impl #impl_generics ::pancake_db_client_ext::PancakeRow for #struct_ident #type_generics #where_clause {
+      fn schema() -> ::pancake_db_client_ext::__private::Schema {
+        ::pancake_db_client_ext::SchemaBuilder::new()
+          #(.list_column(
+            #names,
+            <#types as ::pancake_db_client_ext::ColumnValue>::DTYPE,
+            <#types as ::pancake_db_client_ext::ColumnValue>::NESTED_LIST_DEPTH,
+          ))*
+          .build()
+      }
+
+      fn to_row(&self) -> ::pancake_db_client_ext::__private::Row {
+        ::pancake_db_client_ext::RowBuilder::new()
+          #(.field(
+            #names,
+            ::pancake_db_client_ext::ColumnValue::to_field_value(&self.#idents),
+          ))*
+          .build()
+      }
+
+      fn from_row(row: &::pancake_db_client_ext::__private::Row) -> ::pancake_db_client_ext::__private::ClientResult<Self> {
+        Ok(#struct_ident {
+          #(#idents: ::pancake_db_client_ext::row::column_from_row::<#types>(row, #names)?,)*
+        })
+      }
+    }
+  })
+}
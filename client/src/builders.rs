@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use pancake_db_idl::dml::{PartitionFieldValue, Row};
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, PartitionMeta, Schema};
+
+use crate::values::{IntoFieldValue, IntoPartitionFieldValue};
+
+/// Builds a `Schema`:
+///
+/// ```
+/// use pancake_db_client_ext::SchemaBuilder;
+/// use pancake_db_idl::dtype::DataType;
+/// use pancake_db_idl::partition_dtype::PartitionDataType;
+///
+/// let schema = SchemaBuilder::new()
+///   .partition("day", PartitionDataType::TimestampMinute)
+///   .column("user_id", DataType::String)
+///   .list_column("tags", DataType::String, 1)
+///   .build();
+/// assert_eq!(schema.columns.len(), 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SchemaBuilder {
+  schema: Schema,
+}
+
+impl SchemaBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn partition(mut self, name: impl Into<String>, dtype: PartitionDataType) -> Self {
+    self.schema.partitioning.insert(name.into(), PartitionMeta {
+      dtype: dtype as i32,
+    });
+    self
+  }
+
+  pub fn column(self, name: impl Into<String>, dtype: DataType) -> Self {
+    self.list_column(name, dtype, 0)
+  }
+
+  /// A column whose values are lists nested `nested_list_depth` deep.
+  pub fn list_column(mut self, name: impl Into<String>, dtype: DataType, nested_list_depth: u32) -> Self {
+    self.schema.columns.insert(name.into(), ColumnMeta {
+      dtype: dtype as i32,
+      nested_list_depth,
+    });
+    self
+  }
+
+  pub fn build(self) -> Schema {
+    self.schema
+  }
+}
+
+/// Starts from an existing schema, like a derived `PancakeRow::schema()`,
+/// to add partitioning or more columns to it.
+impl From<Schema> for SchemaBuilder {
+  fn from(schema: Schema) -> Self {
+    SchemaBuilder { schema }
+  }
+}
+
+/// Builds a `Row` from Rust values:
+///
+/// ```
+/// use pancake_db_client_ext::RowBuilder;
+///
+/// let row = RowBuilder::new()
+///   .field("user_id", "abc")
+///   .field("cents_amount", 1234_i64)
+///   .field("tags", vec!["new", "mobile"])
+///   .field("coupon", None::<String>)
+///   .build();
+/// assert_eq!(row.fields.len(), 4);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RowBuilder {
+  row: Row,
+}
+
+impl RowBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn field(mut self, name: impl Into<String>, value: impl IntoFieldValue) -> Self {
+    self.row.fields.insert(name.into(), value.into_field_value());
+    self
+  }
+
+  /// Like `field`, but leaves the field out entirely when `value` is
+  /// `None`, which the server also treats as null.
+  pub fn maybe_field<T: IntoFieldValue>(self, name: impl Into<String>, value: Option<T>) -> Self {
+    match value {
+      Some(value) => self.field(name, value),
+      None => self,
+    }
+  }
+
+  pub fn build(self) -> Row {
+    self.row
+  }
+}
+
+/// Builds the partition of a write or read request.
+#[derive(Clone, Debug, Default)]
+pub struct PartitionBuilder {
+  partition: HashMap<String, PartitionFieldValue>,
+}
+
+impl PartitionBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn field(mut self, name: impl Into<String>, value: impl IntoPartitionFieldValue) -> Self {
+    self.partition.insert(name.into(), value.into_partition_field_value());
+    self
+  }
+
+  pub fn build(self) -> HashMap<String, PartitionFieldValue> {
+    self.partition
+  }
+}
@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use pancake_db_client::Client;
+use pancake_db_client::errors::{ClientError, ClientResult};
+use pancake_db_idl::ddl::{AlterTableRequest, AlterTableResponse, CreateTableRequest, CreateTableResponse, DropTableRequest, DropTableResponse, GetSchemaRequest, GetSchemaResponse, ListTablesRequest, ListTablesResponse};
+use pancake_db_idl::dml::{DeleteFromSegmentRequest, DeleteFromSegmentResponse, ListSegmentsRequest, ListSegmentsResponse, ReadSegmentColumnRequest, ReadSegmentColumnResponse, ReadSegmentDeletionsRequest, ReadSegmentDeletionsResponse, WriteToPartitionRequest, WriteToPartitionResponse};
+
+pub type ReadSegmentColumnStream = BoxStream<'static, ClientResult<ReadSegmentColumnResponse>>;
+
+/// The PancakeDB API, implemented both by the network `Client` and by the
+/// client of a server embedded in the same process, so code written
+/// against it works with either.
+#[async_trait]
+pub trait PancakeClient: Send {
+  async fn alter_table(&mut self, req: AlterTableRequest) -> ClientResult<AlterTableResponse>;
+  async fn create_table(&mut self, req: CreateTableRequest) -> ClientResult<CreateTableResponse>;
+  async fn drop_table(&mut self, req: DropTableRequest) -> ClientResult<DropTableResponse>;
+  async fn get_schema(&mut self, req: GetSchemaRequest) -> ClientResult<GetSchemaResponse>;
+  async fn delete_from_segment(&mut self, req: DeleteFromSegmentRequest) -> ClientResult<DeleteFromSegmentResponse>;
+  async fn list_tables(&mut self, req: ListTablesRequest) -> ClientResult<ListTablesResponse>;
+  async fn list_segments(&mut self, req: ListSegmentsRequest) -> ClientResult<ListSegmentsResponse>;
+  async fn read_segment_deletions(&mut self, req: ReadSegmentDeletionsRequest) -> ClientResult<ReadSegmentDeletionsResponse>;
+  async fn write_to_partition(&mut self, req: WriteToPartitionRequest) -> ClientResult<WriteToPartitionResponse>;
+
+  /// Streams the column's data, one response per continuation.
+  async fn read_segment_column(&mut self, req: ReadSegmentColumnRequest) -> ClientResult<ReadSegmentColumnStream>;
+}
+
+#[async_trait]
+impl PancakeClient for Client {
+  async fn alter_table(&mut self, req: AlterTableRequest) -> ClientResult<AlterTableResponse> {
+    Client::alter_table(self, req).await
+  }
+
+  async fn create_table(&mut self, req: CreateTableRequest) -> ClientResult<CreateTableResponse> {
+    Client::create_table(self, req).await
+  }
+
+  async fn drop_table(&mut self, req: DropTableRequest) -> ClientResult<DropTableResponse> {
+    Client::drop_table(self, req).await
+  }
+
+  async fn get_schema(&mut self, req: GetSchemaRequest) -> ClientResult<GetSchemaResponse> {
+    Client::get_schema(self, req).await
+  }
+
+  async fn delete_from_segment(&mut self, req: DeleteFromSegmentRequest) -> ClientResult<DeleteFromSegmentResponse> {
+    Client::delete_from_segment(self, req).await
+  }
+
+  async fn list_tables(&mut self, req: ListTablesRequest) -> ClientResult<ListTablesResponse> {
+    Client::list_tables(self, req).await
+  }
+
+  async fn list_segments(&mut self, req: ListSegmentsRequest) -> ClientResult<ListSegmentsResponse> {
+    Client::list_segments(self, req).await
+  }
+
+  async fn read_segment_deletions(&mut self, req: ReadSegmentDeletionsRequest) -> ClientResult<ReadSegmentDeletionsResponse> {
+    Client::read_segment_deletions(self, req).await
+  }
+
+  async fn write_to_partition(&mut self, req: WriteToPartitionRequest) -> ClientResult<WriteToPartitionResponse> {
+    Client::write_to_partition(self, req).await
+  }
+
+  async fn read_segment_column(&mut self, req: ReadSegmentColumnRequest) -> ClientResult<ReadSegmentColumnStream> {
+    let stream = self.grpc.read_segment_column(req).await?.into_inner();
+    Ok(stream.map(|res| res.map_err(ClientError::from)).boxed())
+  }
+}
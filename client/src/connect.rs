@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use pancake_db_client::Client;
+use pancake_db_client::errors::{ClientError, ClientResult};
+use pancake_db_idl::service::pancake_db_client::PancakeDbClient;
+use tonic::transport::Endpoint;
+
+/// Connects a `Client` with its deadlines, keepalives, and flow control
+/// tuned, where `Client::connect` uses tonic's defaults:
+///
+/// ```no_run
+/// # async fn connect() -> pancake_db_client::errors::ClientResult<()> {
+/// use std::time::Duration;
+/// use pancake_db_client_ext::ClientBuilder;
+///
+/// let client = ClientBuilder::new("http://localhost:3842")
+///   .deadline(Duration::from_secs(30))
+///   .keep_alive(Duration::from_secs(20), Duration::from_secs(10))
+///   .window_size(8 << 20)
+///   .connect()
+///   .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+  dst: String,
+  deadline: Option<Duration>,
+  connect_timeout: Option<Duration>,
+  keep_alive: Option<(Duration, Duration)>,
+  keep_alive_while_idle: bool,
+  tcp_keepalive: Option<Duration>,
+  window_size: Option<u32>,
+  adaptive_window: bool,
+}
+
+impl ClientBuilder {
+  /// `dst` is an endpoint like `"http://$HOST:$PORT"`.
+  pub fn new(dst: impl Into<String>) -> Self {
+    ClientBuilder {
+      dst: dst.into(),
+      deadline: None,
+      connect_timeout: None,
+      keep_alive: None,
+      keep_alive_while_idle: false,
+      tcp_keepalive: None,
+      window_size: None,
+      adaptive_window: false,
+    }
+  }
+
+  /// The deadline for every call, which the server is told about and stops
+  /// working on the call after. Calls made through `client.grpc` can set
+  /// their own with `with_deadline`.
+  pub fn deadline(mut self, deadline: Duration) -> Self {
+    self.deadline = Some(deadline);
+    self
+  }
+
+  pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+    self.connect_timeout = Some(timeout);
+    self
+  }
+
+  /// Sends an HTTP/2 ping every `interval`, dropping the connection if one
+  /// goes unanswered for `timeout`, so dead connections through flaky
+  /// networks or idle-killing proxies are noticed.
+  pub fn keep_alive(mut self, interval: Duration, timeout: Duration) -> Self {
+    self.keep_alive = Some((interval, timeout));
+    self
+  }
+
+  /// Whether to keep pinging while no calls are in flight.
+  pub fn keep_alive_while_idle(mut self, enabled: bool) -> Self {
+    self.keep_alive_while_idle = enabled;
+    self
+  }
+
+  pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+    self.tcp_keepalive = Some(interval);
+    self
+  }
+
+  /// How many bytes the server may send on each call, and on the whole
+  /// connection, before the client acknowledges them. Raising it speeds up
+  /// reading large segment pages over high-latency networks. Messages
+  /// themselves have no size limit either way.
+  pub fn window_size(mut self, bytes: u32) -> Self {
+    self.window_size = Some(bytes);
+    self
+  }
+
+  /// Lets HTTP/2 grow the window size on its own as it measures the
+  /// network, instead of keeping it fixed.
+  pub fn adaptive_window(mut self, enabled: bool) -> Self {
+    self.adaptive_window = enabled;
+    self
+  }
+
+  fn endpoint(&self) -> ClientResult<Endpoint> {
+    let mut endpoint = Endpoint::from_shared(self.dst.clone())
+      .map_err(|e| ClientError::other(format!("invalid endpoint {}: {}", self.dst, e)))?
+      .keep_alive_while_idle(self.keep_alive_while_idle)
+      .tcp_keepalive(self.tcp_keepalive)
+      .initial_stream_window_size(self.window_size)
+      .initial_connection_window_size(self.window_size)
+      .http2_adaptive_window(self.adaptive_window);
+    if let Some(deadline) = self.deadline {
+      endpoint = endpoint.timeout(deadline);
+    }
+    if let Some(connect_timeout) = self.connect_timeout {
+      endpoint = endpoint.connect_timeout(connect_timeout);
+    }
+    if let Some((interval, timeout)) = self.keep_alive {
+      endpoint = endpoint
+        .http2_keep_alive_interval(interval)
+        .keep_alive_timeout(timeout);
+    }
+    Ok(endpoint)
+  }
+
+  pub async fn connect(&self) -> ClientResult<Client> {
+    let channel = self.endpoint()?.connect().await?;
+    Ok(Client {
+      grpc: PancakeDbClient::new(channel),
+    })
+  }
+}
+
+/// Wraps a request to `client.grpc` with a deadline for just that call,
+/// overriding the builder's:
+///
+/// ```no_run
+/// # async fn list(client: &mut pancake_db_client::Client) -> Result<(), tonic::Status> {
+/// use std::time::Duration;
+/// use pancake_db_client_ext::connect::with_deadline;
+/// use pancake_db_idl::ddl::ListTablesRequest;
+///
+/// let req = with_deadline(ListTablesRequest::default(), Duration::from_secs(1));
+/// let tables = client.grpc.list_tables(req).await?.into_inner().tables;
+/// # Ok(())
+/// # }
+/// ```
+pub fn with_deadline<T>(message: T, deadline: Duration) -> tonic::Request<T> {
+  let mut req = tonic::Request::new(message);
+  req.set_timeout(deadline);
+  req
+}
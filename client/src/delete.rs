@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+use pancake_db_client::SegmentKey;
+use pancake_db_client::errors::{ClientError, ClientResult};
+use pancake_db_idl::dml::{DeleteFromSegmentRequest, ListSegmentsRequest, PartitionFieldValue, PartitionFilter, Row};
+use pancake_db_idl::dml::field_value::Value;
+
+use crate::schema_cache::{CachingClient, ROW_ID_COLUMN_NAME};
+
+/// Where a `Deletion` is after finishing a segment.
+#[derive(Clone, Debug)]
+pub struct DeleteProgress {
+  pub partition: HashMap<String, PartitionFieldValue>,
+  pub segment_id: String,
+  pub n_segments_done: usize,
+  pub n_segments: usize,
+  /// rows deleted from this segment
+  pub n_deleted: u32,
+  /// rows deleted from all segments so far
+  pub n_deleted_total: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeleteSummary {
+  pub n_segments: usize,
+  pub n_deleted: u64,
+  /// segments re-read to check their deleted rows were gone
+  pub n_segments_verified: usize,
+}
+
+type Predicate<'a> = Box<dyn Fn(&Row) -> bool + Send + Sync + 'a>;
+type ProgressCallback<'a> = Box<dyn FnMut(&DeleteProgress) + Send + 'a>;
+
+/// Deletes the rows of a table matching a predicate, doing the usual steps
+/// for each segment: reading its rows along with their `_row_id`s, deleting
+/// the matching ones, and optionally reading them back to check they're
+/// gone:
+///
+/// ```no_run
+/// # async fn delete(mut client: pancake_db_client_ext::CachingClient) -> pancake_db_client::errors::ClientResult<()> {
+/// use pancake_db_client_ext::delete::Deletion;
+/// use pancake_db_client_ext::row::column_from_row;
+///
+/// let summary = Deletion::new("my_purchase_table", |row| {
+///   column_from_row::<String>(row, "user_id").map(|id| id == "abc").unwrap_or(false)
+/// })
+///   .columns(&["user_id"])
+///   .verify(true)
+///   .on_progress(|progress| println!("{}/{} segments", progress.n_segments_done, progress.n_segments))
+///   .run(&mut client)
+///   .await?;
+/// println!("deleted {} rows", summary.n_deleted);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Segments are handled one at a time, in the order they're listed. Rows
+/// written to a segment after it's read aren't deleted, and an error
+/// leaves the segments before it deleted from.
+pub struct Deletion<'a> {
+  table_name: String,
+  predicate: Predicate<'a>,
+  partition_filters: Vec<PartitionFilter>,
+  column_names: Vec<String>,
+  verify: bool,
+  on_progress: Option<ProgressCallback<'a>>,
+}
+
+impl<'a> Deletion<'a> {
+  pub fn new(table_name: impl Into<String>, predicate: impl Fn(&Row) -> bool + Send + Sync + 'a) -> Self {
+    Deletion {
+      table_name: table_name.into(),
+      predicate: Box::new(predicate),
+      partition_filters: Vec::new(),
+      column_names: Vec::new(),
+      verify: false,
+      on_progress: None,
+    }
+  }
+
+  /// Only considers segments in partitions passing these filters.
+  pub fn partition_filters(mut self, partition_filters: Vec<PartitionFilter>) -> Self {
+    self.partition_filters = partition_filters;
+    self
+  }
+
+  /// The columns the predicate looks at; by default, all of them.
+  pub fn columns<S: AsRef<str>>(mut self, column_names: &[S]) -> Self {
+    self.column_names = column_names.iter()
+      .map(|name| name.as_ref().to_string())
+      .collect();
+    self
+  }
+
+  /// Whether to re-read each segment's row ids after deleting from it,
+  /// failing if any deleted row is still there.
+  pub fn verify(mut self, verify: bool) -> Self {
+    self.verify = verify;
+    self
+  }
+
+  /// Called after each segment, whether or not it had rows to delete.
+  pub fn on_progress(mut self, on_progress: impl FnMut(&DeleteProgress) + Send + 'a) -> Self {
+    self.on_progress = Some(Box::new(on_progress));
+    self
+  }
+
+  pub async fn run(mut self, client: &mut CachingClient) -> ClientResult<DeleteSummary> {
+    let segments = client.client.list_segments(ListSegmentsRequest {
+      table_name: self.table_name.clone(),
+      partition_filter: self.partition_filters.clone(),
+      ..Default::default()
+    }).await?.segments;
+    // with no columns named, the predicate sees all of them
+    let mut column_names = self.column_names.clone();
+    if !column_names.is_empty() {
+      column_names.push(ROW_ID_COLUMN_NAME.to_string());
+    }
+
+    let mut summary = DeleteSummary {
+      n_segments: segments.len(),
+      ..Default::default()
+    };
+    for (segment_idx, segment) in segments.into_iter().enumerate() {
+      let segment_key = SegmentKey {
+        table_name: self.table_name.clone(),
+        partition: segment.partition,
+        segment_id: segment.segment_id,
+      };
+      let (row_ids, n_deleted) = self.delete_from_segment(client, &segment_key, &column_names).await?;
+      if self.verify && !row_ids.is_empty() {
+        verify_deleted(client, &segment_key, &row_ids).await?;
+        summary.n_segments_verified += 1;
+      }
+      summary.n_deleted += n_deleted as u64;
+
+      if let Some(on_progress) = &mut self.on_progress {
+        on_progress(&DeleteProgress {
+          partition: segment_key.partition,
+          segment_id: segment_key.segment_id,
+          n_segments_done: segment_idx + 1,
+          n_segments: summary.n_segments,
+          n_deleted,
+          n_deleted_total: summary.n_deleted,
+        });
+      }
+    }
+    Ok(summary)
+  }
+
+  // returns the ids of the rows asked to be deleted and how many were
+  async fn delete_from_segment(
+    &self,
+    client: &mut CachingClient,
+    segment_key: &SegmentKey,
+    column_names: &[String],
+  ) -> ClientResult<(Vec<u32>, u32)> {
+    let rows = if column_names.is_empty() {
+      let mut all_column_names: Vec<String> = client.schema(&self.table_name).await?
+        .columns
+        .into_keys()
+        .collect();
+      all_column_names.push(ROW_ID_COLUMN_NAME.to_string());
+      client.decode_segment_columns(segment_key, &all_column_names).await?
+    } else {
+      client.decode_segment_columns(segment_key, column_names).await?
+    };
+    let row_ids = rows.iter()
+      .filter(|row| (self.predicate)(row))
+      .map(row_id)
+      .collect::<ClientResult<Vec<u32>>>()?;
+    if row_ids.is_empty() {
+      return Ok((row_ids, 0));
+    }
+
+    let resp = client.client.delete_from_segment(DeleteFromSegmentRequest {
+      table_name: segment_key.table_name.clone(),
+      partition: segment_key.partition.clone(),
+      segment_id: segment_key.segment_id.clone(),
+      row_ids: row_ids.clone(),
+    }).await?;
+    Ok((row_ids, resp.n_deleted))
+  }
+}
+
+// Decoding the segment applies its deletions as read_segment_deletions
+// reports them, so none of the deleted row ids should come back.
+async fn verify_deleted(
+  client: &mut CachingClient,
+  segment_key: &SegmentKey,
+  row_ids: &[u32],
+) -> ClientResult<()> {
+  let live_row_ids = client.decode_segment_columns(segment_key, &[ROW_ID_COLUMN_NAME]).await?
+    .iter()
+    .map(row_id)
+    .collect::<ClientResult<HashSet<u32>>>()?;
+  let n_undeleted = row_ids.iter()
+    .filter(|id| live_row_ids.contains(id))
+    .count();
+  if n_undeleted > 0 {
+    return Err(ClientError::other(format!(
+      "{} of {} deleted rows were still live in segment {} of {}",
+      n_undeleted,
+      row_ids.len(),
+      segment_key.segment_id,
+      segment_key.table_name,
+    )));
+  }
+  Ok(())
+}
+
+fn row_id(row: &Row) -> ClientResult<u32> {
+  match row.fields.get(ROW_ID_COLUMN_NAME).and_then(|field_value| field_value.value.as_ref()) {
+    Some(Value::Int64Val(x)) => u32::try_from(*x)
+      .map_err(|_| ClientError::other(format!("{} {} is out of range", ROW_ID_COLUMN_NAME, x))),
+    _ => Err(ClientError::other(format!("row is missing its {}", ROW_ID_COLUMN_NAME))),
+  }
+}
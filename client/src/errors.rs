@@ -0,0 +1,203 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use pancake_db_client::errors::{ClientError, ClientErrorKind};
+use tonic::Code;
+
+/// Stable, machine-readable codes the server sends along with error
+/// messages. Most are just the error's kind, but some errors have a more
+/// specific code. Codes may be added but never renamed.
+///
+/// The server also uses this enum, so the two can't drift apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+  Invalid,
+  DoesNotExist,
+  ReadOnly,
+  Unauthenticated,
+  PermissionDenied,
+  TooManyRequests,
+  TooLarge,
+  QuotaExceeded,
+  Cancelled,
+  TimedOut,
+  Aborted,
+  Internal,
+  Corrupt,
+  ChecksumMismatch,
+  // more specific codes
+  TableNotFound,
+  ColumnNotFound,
+  PartitionNotFound,
+  SegmentNotFound,
+  TableAlreadyExists,
+  SchemaMismatch,
+  InvalidRows,
+  RestartRead,
+}
+
+impl ErrorCode {
+  pub fn parse(s: &str) -> Option<Self> {
+    let res = match s {
+      "INVALID" => ErrorCode::Invalid,
+      "DOES_NOT_EXIST" => ErrorCode::DoesNotExist,
+      "READ_ONLY" => ErrorCode::ReadOnly,
+      "UNAUTHENTICATED" => ErrorCode::Unauthenticated,
+      "PERMISSION_DENIED" => ErrorCode::PermissionDenied,
+      "TOO_MANY_REQUESTS" => ErrorCode::TooManyRequests,
+      "TOO_LARGE" => ErrorCode::TooLarge,
+      "QUOTA_EXCEEDED" => ErrorCode::QuotaExceeded,
+      "CANCELLED" => ErrorCode::Cancelled,
+      "TIMED_OUT" => ErrorCode::TimedOut,
+      "ABORTED" => ErrorCode::Aborted,
+      "INTERNAL" => ErrorCode::Internal,
+      "CORRUPT" => ErrorCode::Corrupt,
+      "CHECKSUM_MISMATCH" => ErrorCode::ChecksumMismatch,
+      "TABLE_NOT_FOUND" => ErrorCode::TableNotFound,
+      "COLUMN_NOT_FOUND" => ErrorCode::ColumnNotFound,
+      "PARTITION_NOT_FOUND" => ErrorCode::PartitionNotFound,
+      "SEGMENT_NOT_FOUND" => ErrorCode::SegmentNotFound,
+      "TABLE_ALREADY_EXISTS" => ErrorCode::TableAlreadyExists,
+      "SCHEMA_MISMATCH" => ErrorCode::SchemaMismatch,
+      "INVALID_ROWS" => ErrorCode::InvalidRows,
+      "RESTART_READ" => ErrorCode::RestartRead,
+      _ => return None,
+    };
+    Some(res)
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ErrorCode::Invalid => "INVALID",
+      ErrorCode::DoesNotExist => "DOES_NOT_EXIST",
+      ErrorCode::ReadOnly => "READ_ONLY",
+      ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+      ErrorCode::PermissionDenied => "PERMISSION_DENIED",
+      ErrorCode::TooManyRequests => "TOO_MANY_REQUESTS",
+      ErrorCode::TooLarge => "TOO_LARGE",
+      ErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+      ErrorCode::Cancelled => "CANCELLED",
+      ErrorCode::TimedOut => "TIMED_OUT",
+      ErrorCode::Aborted => "ABORTED",
+      ErrorCode::Internal => "INTERNAL",
+      ErrorCode::Corrupt => "CORRUPT",
+      ErrorCode::ChecksumMismatch => "CHECKSUM_MISMATCH",
+      ErrorCode::TableNotFound => "TABLE_NOT_FOUND",
+      ErrorCode::ColumnNotFound => "COLUMN_NOT_FOUND",
+      ErrorCode::PartitionNotFound => "PARTITION_NOT_FOUND",
+      ErrorCode::SegmentNotFound => "SEGMENT_NOT_FOUND",
+      ErrorCode::TableAlreadyExists => "TABLE_ALREADY_EXISTS",
+      ErrorCode::SchemaMismatch => "SCHEMA_MISMATCH",
+      ErrorCode::InvalidRows => "INVALID_ROWS",
+      ErrorCode::RestartRead => "RESTART_READ",
+    }
+  }
+
+  /// Worth retrying as is, after a pause; a restarted read is retried from
+  /// its first page.
+  pub fn is_retryable(&self) -> bool {
+    matches!(
+      self,
+      ErrorCode::TooManyRequests | ErrorCode::TimedOut | ErrorCode::Aborted | ErrorCode::RestartRead
+    )
+  }
+
+  /// The request itself was wrong, so retrying it won't help.
+  pub fn is_validation(&self) -> bool {
+    matches!(
+      self,
+      ErrorCode::Invalid | ErrorCode::TooLarge | ErrorCode::TableAlreadyExists |
+        ErrorCode::SchemaMismatch | ErrorCode::InvalidRows
+    )
+  }
+
+  pub fn is_not_found(&self) -> bool {
+    matches!(
+      self,
+      ErrorCode::DoesNotExist | ErrorCode::TableNotFound | ErrorCode::ColumnNotFound |
+        ErrorCode::PartitionNotFound | ErrorCode::SegmentNotFound
+    )
+  }
+
+  /// Splits a GRPC error message into its `[CODE]` prefix and the rest of
+  /// the message. The server sends codes in GRPC status details too, but
+  /// `pancake_db_client` only keeps the status code and message.
+  pub fn split_message(message: &str) -> Option<(Self, &str)> {
+    let (code, rest) = message.strip_prefix('[')?.split_once("] ")?;
+    Some((ErrorCode::parse(code)?, rest))
+  }
+
+  // the closest code for servers that don't send one, or send one this
+  // client doesn't know
+  fn from_grpc_code(code: Code) -> Option<Self> {
+    let res = match code {
+      Code::InvalidArgument => ErrorCode::Invalid,
+      Code::NotFound => ErrorCode::DoesNotExist,
+      Code::FailedPrecondition => ErrorCode::ReadOnly,
+      Code::Unauthenticated => ErrorCode::Unauthenticated,
+      Code::PermissionDenied => ErrorCode::PermissionDenied,
+      Code::ResourceExhausted => ErrorCode::TooManyRequests,
+      Code::Cancelled => ErrorCode::Cancelled,
+      Code::DeadlineExceeded => ErrorCode::TimedOut,
+      Code::Aborted => ErrorCode::Aborted,
+      Code::Internal => ErrorCode::Internal,
+      Code::DataLoss => ErrorCode::ChecksumMismatch,
+      _ => return None,
+    };
+    Some(res)
+  }
+}
+
+impl Display for ErrorCode {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Classifies client errors by the server's error codes, so callers can
+/// branch on them without matching messages:
+///
+/// ```no_run
+/// # async fn get(client: &mut pancake_db_client::Client) -> pancake_db_client::errors::ClientResult<()> {
+/// use pancake_db_client_ext::ClientErrorExt;
+/// use pancake_db_idl::ddl::GetSchemaRequest;
+///
+/// let req = GetSchemaRequest { table_name: "my_purchase_table".to_string() };
+/// match client.get_schema(req).await {
+///   Err(e) if e.is_not_found() => println!("no such table"),
+///   res => { res?; },
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub trait ClientErrorExt {
+  fn error_code(&self) -> Option<ErrorCode>;
+  fn is_retryable(&self) -> bool;
+
+  fn is_validation(&self) -> bool {
+    self.error_code().map(|code| code.is_validation()).unwrap_or(false)
+  }
+
+  fn is_not_found(&self) -> bool {
+    self.error_code().map(|code| code.is_not_found()).unwrap_or(false)
+  }
+}
+
+impl ClientErrorExt for ClientError {
+  fn error_code(&self) -> Option<ErrorCode> {
+    match &self.kind {
+      ClientErrorKind::Grpc { code } => ErrorCode::split_message(&self.message)
+        .map(|(error_code, _)| error_code)
+        .or_else(|| ErrorCode::from_grpc_code(*code)),
+      _ => None,
+    }
+  }
+
+  // the server may just be restarting, so connection errors are retryable
+  fn is_retryable(&self) -> bool {
+    match &self.kind {
+      ClientErrorKind::Connection => true,
+      _ => self.error_code().map(|code| code.is_retryable()).unwrap_or(false),
+    }
+  }
+}
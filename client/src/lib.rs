@@ -0,0 +1,28 @@
+//! Helpers on top of `pancake-db-client` that make requests less verbose
+//! to build than with the protobuf structs alone.
+
+pub use builders::{PartitionBuilder, RowBuilder, SchemaBuilder};
+pub use client::PancakeClient;
+pub use connect::ClientBuilder;
+pub use errors::{ClientErrorExt, ErrorCode};
+pub use pancake_db_client_derive::PancakeRow;
+pub use row::{ColumnValue, PancakeRow};
+pub use schema_cache::CachingClient;
+pub use values::{IntoFieldValue, IntoPartitionFieldValue};
+
+pub mod builders;
+pub mod client;
+pub mod connect;
+pub mod delete;
+pub mod errors;
+pub mod row;
+pub mod schema_cache;
+pub mod values;
+
+// paths for code generated by the derive macros
+#[doc(hidden)]
+pub mod __private {
+  pub use pancake_db_client::errors::ClientResult;
+  pub use pancake_db_idl::dml::Row;
+  pub use pancake_db_idl::schema::Schema;
+}
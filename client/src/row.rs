@@ -0,0 +1,182 @@
+use std::convert::TryFrom;
+use std::time::SystemTime;
+
+use pancake_db_client::errors::{ClientError, ClientResult};
+use pancake_db_core::primitives::Primitive;
+use pancake_db_idl::dml::{FieldValue, RepeatedFieldValue, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::Schema;
+use prost_types::Timestamp;
+use q_compress::data_types::TimestampMicros;
+
+/// A Rust type that can be a column of a `PancakeRow`.
+///
+/// Dtypes come from `pancake_db_core::primitives`, and each `Vec` other
+/// than `Vec<u8>` adds a level of nested list depth. `Option<T>` is a
+/// nullable `T`; other types fail to read from null.
+pub trait ColumnValue: Sized {
+  const DTYPE: DataType;
+  const NESTED_LIST_DEPTH: u32;
+
+  fn to_field_value(&self) -> FieldValue;
+  fn try_from_field_value(field_value: &FieldValue) -> ClientResult<Self>;
+}
+
+/// A struct whose fields are the columns of a table, usually derived:
+///
+/// ```
+/// use std::time::SystemTime;
+/// use pancake_db_client_ext::PancakeRow;
+///
+/// #[derive(PancakeRow)]
+/// struct Purchase {
+///   user_id: String,
+///   cents_amount: i64,
+///   #[pancake(rename = "purchased_at")]
+///   time: SystemTime,
+///   tags: Vec<String>,
+///   coupon: Option<String>,
+/// }
+///
+/// let purchase = Purchase {
+///   user_id: "abc".to_string(),
+///   cents_amount: 1234,
+///   time: SystemTime::now(),
+///   tags: vec!["mobile".to_string()],
+///   coupon: None,
+/// };
+/// let row = purchase.to_row();
+/// assert!(row.fields.contains_key("purchased_at"));
+/// assert_eq!(Purchase::schema().columns["tags"].nested_list_depth, 1);
+/// assert_eq!(Purchase::from_row(&row).unwrap().cents_amount, 1234);
+/// ```
+pub trait PancakeRow: Sized {
+  /// The columns of the struct, without any partitioning.
+  fn schema() -> Schema;
+  fn to_row(&self) -> Row;
+  fn from_row(row: &Row) -> ClientResult<Self>;
+}
+
+fn null_error() -> ClientError {
+  ClientError::other("value is null".to_string())
+}
+
+fn try_primitive_from_field_value<P: Primitive>(field_value: &FieldValue) -> ClientResult<P> {
+  match &field_value.value {
+    Some(value) => Ok(P::try_from_value(value)?),
+    None => Err(null_error()),
+  }
+}
+
+macro_rules! primitive_column_value {
+  ($t:ty) => {
+    impl ColumnValue for $t {
+      const DTYPE: DataType = <$t as Primitive>::DTYPE;
+      const NESTED_LIST_DEPTH: u32 = 0;
+
+      fn to_field_value(&self) -> FieldValue {
+        FieldValue { value: Some(self.to_value()) }
+      }
+
+      fn try_from_field_value(field_value: &FieldValue) -> ClientResult<Self> {
+        try_primitive_from_field_value(field_value)
+      }
+    }
+  };
+}
+
+primitive_column_value!(bool);
+primitive_column_value!(i64);
+primitive_column_value!(f32);
+primitive_column_value!(f64);
+primitive_column_value!(String);
+primitive_column_value!(Vec<u8>);
+primitive_column_value!(TimestampMicros);
+
+// Timestamps are stored to the microsecond, so these round trip through
+// TimestampMicros the way the server would truncate them.
+impl ColumnValue for Timestamp {
+  const DTYPE: DataType = <TimestampMicros as Primitive>::DTYPE;
+  const NESTED_LIST_DEPTH: u32 = 0;
+
+  fn to_field_value(&self) -> FieldValue {
+    FieldValue { value: Some(Value::TimestampVal(self.clone())) }
+  }
+
+  fn try_from_field_value(field_value: &FieldValue) -> ClientResult<Self> {
+    let micros = try_primitive_from_field_value::<TimestampMicros>(field_value)?;
+    match micros.to_value() {
+      Value::TimestampVal(t) => Ok(t),
+      _ => unreachable!("timestamps always become timestamp values"),
+    }
+  }
+}
+
+impl ColumnValue for SystemTime {
+  const DTYPE: DataType = <Timestamp as ColumnValue>::DTYPE;
+  const NESTED_LIST_DEPTH: u32 = 0;
+
+  fn to_field_value(&self) -> FieldValue {
+    Timestamp::from(*self).to_field_value()
+  }
+
+  fn try_from_field_value(field_value: &FieldValue) -> ClientResult<Self> {
+    let t = Timestamp::try_from_field_value(field_value)?;
+    SystemTime::try_from(t)
+      .map_err(|_| ClientError::other("timestamp is out of range for SystemTime".to_string()))
+  }
+}
+
+impl<T: ColumnValue> ColumnValue for Option<T> {
+  const DTYPE: DataType = T::DTYPE;
+  const NESTED_LIST_DEPTH: u32 = T::NESTED_LIST_DEPTH;
+
+  fn to_field_value(&self) -> FieldValue {
+    match self {
+      Some(x) => x.to_field_value(),
+      None => FieldValue::default(),
+    }
+  }
+
+  fn try_from_field_value(field_value: &FieldValue) -> ClientResult<Self> {
+    match field_value.value {
+      Some(_) => Ok(Some(T::try_from_field_value(field_value)?)),
+      None => Ok(None),
+    }
+  }
+}
+
+impl<T: ColumnValue> ColumnValue for Vec<T> {
+  const DTYPE: DataType = T::DTYPE;
+  const NESTED_LIST_DEPTH: u32 = T::NESTED_LIST_DEPTH + 1;
+
+  fn to_field_value(&self) -> FieldValue {
+    let vals = self.iter()
+      .map(ColumnValue::to_field_value)
+      .collect();
+    FieldValue { value: Some(Value::ListVal(RepeatedFieldValue { vals })) }
+  }
+
+  fn try_from_field_value(field_value: &FieldValue) -> ClientResult<Self> {
+    match &field_value.value {
+      Some(Value::ListVal(list)) => list.vals.iter()
+        .map(T::try_from_field_value)
+        .collect(),
+      Some(_) => Err(ClientError::other("cannot read list from value".to_string())),
+      None => Err(null_error()),
+    }
+  }
+}
+
+// Used by derived from_row implementations. Missing fields are null, and
+// errors say which column they came from.
+#[doc(hidden)]
+pub fn column_from_row<T: ColumnValue>(row: &Row, column_name: &str) -> ClientResult<T> {
+  let null = FieldValue::default();
+  let field_value = row.fields.get(column_name).unwrap_or(&null);
+  T::try_from_field_value(field_value).map_err(|e| ClientError {
+    message: format!("column {}: {}", column_name, e.message),
+    kind: e.kind,
+  })
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::{future, stream, StreamExt};
+use futures::stream::BoxStream;
+use pancake_db_client::{Client, SegmentKey};
+use pancake_db_client::errors::{ClientError, ClientResult};
+use pancake_db_idl::ddl::GetSchemaRequest;
+use pancake_db_idl::dml::{FieldValue, ListSegmentsRequest, PartitionFieldValue, PartitionFilter, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+
+use crate::errors::{ClientErrorExt, ErrorCode};
+
+pub const ROW_ID_COLUMN_NAME: &str = "_row_id";
+pub const WRITTEN_AT_COLUMN_NAME: &str = "_written_at";
+const DEFAULT_READ_CONCURRENCY: usize = 8;
+
+pub type RowStream = BoxStream<'static, ClientResult<Row>>;
+
+/// Whether an error means the request was made against a different schema
+/// than the table's current one, so a cached schema is out of date.
+pub fn is_stale_schema_error(err: &ClientError) -> bool {
+  matches!(err.error_code(), Some(ErrorCode::SchemaMismatch | ErrorCode::ColumnNotFound))
+}
+
+// the columns every table has besides its own
+fn augmented_column_meta(column_name: &str) -> Option<ColumnMeta> {
+  let dtype = match column_name {
+    ROW_ID_COLUMN_NAME => DataType::Int64,
+    WRITTEN_AT_COLUMN_NAME => DataType::TimestampMicros,
+    _ => return None,
+  };
+  Some(ColumnMeta {
+    dtype: dtype as i32,
+    ..Default::default()
+  })
+}
+
+/// A `Client` that remembers each table's schema, so segments can be
+/// decoded by naming just the columns wanted, or none for all of them:
+///
+/// ```no_run
+/// # async fn read(segment_key: pancake_db_client::SegmentKey) -> pancake_db_client::errors::ClientResult<()> {
+/// use pancake_db_client::Client;
+/// use pancake_db_client_ext::CachingClient;
+///
+/// let mut client = CachingClient::new(Client::connect("http://localhost:3842").await?);
+/// let rows = client.decode_segment(&segment_key).await?;
+/// let ids = client.decode_segment_columns(&segment_key, &["_row_id", "user_id"]).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A schema is fetched the first time its table is used, and fetched again
+/// whenever the server says a request didn't match it, after which the
+/// request is retried once. Columns added since it was cached are left
+/// out until then; `invalidate` forgets a table's schema early. Clones
+/// share the cache.
+#[derive(Clone)]
+pub struct CachingClient {
+  pub client: Client,
+  schemas: Arc<Mutex<HashMap<String, Schema>>>,
+  read_concurrency: usize,
+}
+
+impl CachingClient {
+  pub fn new(client: Client) -> Self {
+    CachingClient {
+      client,
+      schemas: Arc::default(),
+      read_concurrency: DEFAULT_READ_CONCURRENCY,
+    }
+  }
+
+  /// How many segments `read_table` decodes at once; 8 by default.
+  pub fn read_concurrency(mut self, n_segments: usize) -> Self {
+    self.read_concurrency = n_segments.max(1);
+    self
+  }
+
+  /// The table's schema, fetching it only if it isn't cached.
+  pub async fn schema(&mut self, table_name: &str) -> ClientResult<Schema> {
+    let maybe_schema = self.schemas.lock().unwrap().get(table_name).cloned();
+    match maybe_schema {
+      Some(schema) => Ok(schema),
+      None => self.refresh_schema(table_name).await,
+    }
+  }
+
+  /// Fetches and caches the table's current schema.
+  pub async fn refresh_schema(&mut self, table_name: &str) -> ClientResult<Schema> {
+    let schema = self.client.get_schema(GetSchemaRequest {
+      table_name: table_name.to_string(),
+    }).await?
+      .schema
+      .ok_or_else(|| ClientError::other(format!("get schema response for {} is missing schema", table_name)))?;
+    self.schemas.lock().unwrap().insert(table_name.to_string(), schema.clone());
+    Ok(schema)
+  }
+
+  pub fn invalidate(&mut self, table_name: &str) {
+    self.schemas.lock().unwrap().remove(table_name);
+  }
+
+  pub fn clear(&mut self) {
+    self.schemas.lock().unwrap().clear();
+  }
+
+  /// Decodes the segment's live rows with all of its table's columns.
+  pub async fn decode_segment(&mut self, segment_key: &SegmentKey) -> ClientResult<Vec<Row>> {
+    self.decode_segment_columns(segment_key, &[] as &[&str]).await
+  }
+
+  /// Decodes the segment's live rows with just the named columns, which
+  /// may include `_row_id` and `_written_at`. An empty list means all of
+  /// the table's columns.
+  pub async fn decode_segment_columns<S: AsRef<str>>(
+    &mut self,
+    segment_key: &SegmentKey,
+    column_names: &[S],
+  ) -> ClientResult<Vec<Row>> {
+    let table_name = &segment_key.table_name;
+    let columns = self.columns(table_name, column_names).await?;
+    match self.client.decode_segment(segment_key, &columns).await {
+      Err(e) if is_stale_schema_error(&e) => {
+        let schema = self.refresh_schema(table_name).await?;
+        self.client.decode_segment(segment_key, &select_columns(&schema, column_names)?).await
+      },
+      res => res,
+    }
+  }
+
+  /// Streams the live rows of every segment of the table in partitions
+  /// passing the filters, decoding up to `read_concurrency` segments at
+  /// once, in the order they're listed. Each row has the named columns (or
+  /// all of them, given none) along with its partition's fields. Like
+  /// `decode_segment_columns`, each segment is retried once with a fresh
+  /// schema if the table changed during the read.
+  ///
+  /// ```no_run
+  /// # async fn read(mut client: pancake_db_client_ext::CachingClient) -> pancake_db_client::errors::ClientResult<()> {
+  /// use futures::StreamExt;
+  ///
+  /// let mut rows = client.read_table("my_purchase_table", Vec::new(), &["user_id"]).await?;
+  /// while let Some(row) = rows.next().await {
+  ///   println!("{:?}", row?);
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub async fn read_table<S: AsRef<str>>(
+    &mut self,
+    table_name: &str,
+    partition_filters: Vec<PartitionFilter>,
+    column_names: &[S],
+  ) -> ClientResult<RowStream> {
+    // unknown columns fail here rather than in the stream
+    self.columns(table_name, column_names).await?;
+    let segments = self.client.list_segments(ListSegmentsRequest {
+      table_name: table_name.to_string(),
+      partition_filter: partition_filters,
+      ..Default::default()
+    }).await?.segments;
+
+    let client = self.clone();
+    let table_name = table_name.to_string();
+    let column_names: Vec<String> = column_names.iter()
+      .map(|name| name.as_ref().to_string())
+      .collect();
+    let rows = stream::iter(segments)
+      .map(move |segment| {
+        let mut client = client.clone();
+        let column_names = column_names.clone();
+        let segment_key = SegmentKey {
+          table_name: table_name.clone(),
+          partition: segment.partition,
+          segment_id: segment.segment_id,
+        };
+        async move {
+          let rows = client.decode_segment_columns(&segment_key, &column_names).await?;
+          Ok::<_, ClientError>(with_partition_fields(rows, &segment_key.partition))
+        }
+      })
+      .buffered(self.read_concurrency)
+      .flat_map(|res| match res {
+        Ok(rows) => stream::iter(rows.into_iter().map(Ok)).boxed(),
+        Err(e) => stream::once(future::ready(Err(e))).boxed(),
+      });
+    Ok(rows.boxed())
+  }
+
+  // the columns to decode, refetching the schema in case a column unknown
+  // to the cached one has been added since
+  async fn columns<S: AsRef<str>>(
+    &mut self,
+    table_name: &str,
+    column_names: &[S],
+  ) -> ClientResult<HashMap<String, ColumnMeta>> {
+    let cached_schema = self.schema(table_name).await?;
+    match select_columns(&cached_schema, column_names) {
+      Ok(columns) => Ok(columns),
+      Err(_) => select_columns(&self.refresh_schema(table_name).await?, column_names),
+    }
+  }
+}
+
+fn with_partition_fields(mut rows: Vec<Row>, partition: &HashMap<String, PartitionFieldValue>) -> Vec<Row> {
+  for row in &mut rows {
+    for (name, partition_value) in partition {
+      let value = partition_value.value.clone().map(|value| match value {
+        PartitionValue::StringVal(x) => Value::StringVal(x),
+        PartitionValue::Int64Val(x) => Value::Int64Val(x),
+        PartitionValue::BoolVal(x) => Value::BoolVal(x),
+        PartitionValue::TimestampVal(x) => Value::TimestampVal(x),
+      });
+      row.fields.insert(name.clone(), FieldValue { value });
+    }
+  }
+  rows
+}
+
+fn select_columns<S: AsRef<str>>(
+  schema: &Schema,
+  column_names: &[S],
+) -> ClientResult<HashMap<String, ColumnMeta>> {
+  if column_names.is_empty() {
+    return Ok(schema.columns.clone());
+  }
+
+  let mut res = HashMap::with_capacity(column_names.len());
+  for column_name in column_names {
+    let column_name = column_name.as_ref();
+    let column_meta = schema.columns.get(column_name)
+      .cloned()
+      .or_else(|| augmented_column_meta(column_name))
+      .ok_or_else(|| ClientError::other(format!("unknown column {}", column_name)))?;
+    res.insert(column_name.to_string(), column_meta);
+  }
+  Ok(res)
+}
@@ -0,0 +1,171 @@
+use std::time::SystemTime;
+
+use pancake_db_idl::dml::{FieldValue, PartitionFieldValue, RepeatedFieldValue};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use prost_types::Timestamp;
+
+// The orphan rule keeps us from implementing From for the protobuf
+// structs, or pancake_db_client's FieldValueConverter for more types, so
+// conversions go through these traits instead. They accept what
+// make_row! and make_partition! do, plus &str, i32, slices, and Timestamp.
+
+/// Converts a Rust value into the `FieldValue` of a row.
+///
+/// `None` becomes null, `Vec<u8>` and `&[u8]` become bytes, and any other
+/// `Vec<T>` becomes a list, so `Vec<Vec<String>>` fills a string column
+/// with a nested list depth of 2.
+pub trait IntoFieldValue {
+  fn into_field_value(self) -> FieldValue;
+}
+
+/// Converts a Rust value into the `PartitionFieldValue` of a partition.
+pub trait IntoPartitionFieldValue {
+  fn into_partition_field_value(self) -> PartitionFieldValue;
+}
+
+fn field_value(value: Value) -> FieldValue {
+  FieldValue { value: Some(value) }
+}
+
+fn partition_field_value(value: PartitionValue) -> PartitionFieldValue {
+  PartitionFieldValue { value: Some(value) }
+}
+
+impl IntoFieldValue for FieldValue {
+  fn into_field_value(self) -> FieldValue {
+    self
+  }
+}
+
+impl IntoFieldValue for bool {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::BoolVal(self))
+  }
+}
+
+impl IntoFieldValue for i64 {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::Int64Val(self))
+  }
+}
+
+impl IntoFieldValue for i32 {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::Int64Val(self as i64))
+  }
+}
+
+impl IntoFieldValue for f32 {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::Float32Val(self))
+  }
+}
+
+impl IntoFieldValue for f64 {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::Float64Val(self))
+  }
+}
+
+impl IntoFieldValue for String {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::StringVal(self))
+  }
+}
+
+impl IntoFieldValue for &str {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::StringVal(self.to_string()))
+  }
+}
+
+impl IntoFieldValue for Vec<u8> {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::BytesVal(self))
+  }
+}
+
+impl IntoFieldValue for &[u8] {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::BytesVal(self.to_vec()))
+  }
+}
+
+impl IntoFieldValue for Timestamp {
+  fn into_field_value(self) -> FieldValue {
+    field_value(Value::TimestampVal(self))
+  }
+}
+
+impl IntoFieldValue for SystemTime {
+  fn into_field_value(self) -> FieldValue {
+    Timestamp::from(self).into_field_value()
+  }
+}
+
+impl<T: IntoFieldValue> IntoFieldValue for Option<T> {
+  fn into_field_value(self) -> FieldValue {
+    match self {
+      Some(x) => x.into_field_value(),
+      None => FieldValue::default(),
+    }
+  }
+}
+
+impl<T: IntoFieldValue> IntoFieldValue for Vec<T> {
+  fn into_field_value(self) -> FieldValue {
+    let vals = self.into_iter()
+      .map(IntoFieldValue::into_field_value)
+      .collect();
+    field_value(Value::ListVal(RepeatedFieldValue { vals }))
+  }
+}
+
+impl IntoPartitionFieldValue for PartitionFieldValue {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    self
+  }
+}
+
+impl IntoPartitionFieldValue for bool {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    partition_field_value(PartitionValue::BoolVal(self))
+  }
+}
+
+impl IntoPartitionFieldValue for i64 {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    partition_field_value(PartitionValue::Int64Val(self))
+  }
+}
+
+impl IntoPartitionFieldValue for i32 {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    partition_field_value(PartitionValue::Int64Val(self as i64))
+  }
+}
+
+impl IntoPartitionFieldValue for String {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    partition_field_value(PartitionValue::StringVal(self))
+  }
+}
+
+impl IntoPartitionFieldValue for &str {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    partition_field_value(PartitionValue::StringVal(self.to_string()))
+  }
+}
+
+impl IntoPartitionFieldValue for Timestamp {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    partition_field_value(PartitionValue::TimestampVal(self))
+  }
+}
+
+impl IntoPartitionFieldValue for SystemTime {
+  fn into_partition_field_value(self) -> PartitionFieldValue {
+    Timestamp::from(self).into_partition_field_value()
+  }
+}
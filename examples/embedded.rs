@@ -0,0 +1,83 @@
+use futures::StreamExt;
+use pancake_db_client::errors::ClientResult;
+use pancake_db_client::new_correlation_id;
+use pancake_db_client_ext::{PancakeClient, PancakeRow, PartitionBuilder};
+use pancake_db_idl::ddl::CreateTableRequest;
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{ListSegmentsRequest, ReadSegmentColumnRequest, WriteToPartitionRequest};
+use pancake_db_server::Embedded;
+use structopt::StructOpt;
+
+const TABLE_NAME: &str = "embedded_purchases";
+
+#[derive(Clone, Debug, StructOpt)]
+struct Opt {
+  #[structopt(long, default_value="/tmp/pancake_db_embedded_example")]
+  dir: String,
+  #[structopt(long, default_value="100")]
+  n_rows: i64,
+  // ignores dir, keeping everything in memory
+  #[structopt(long)]
+  in_memory: bool,
+}
+
+#[derive(PancakeRow)]
+struct Purchase {
+  user_id: String,
+  cents_amount: i64,
+}
+
+// works the same against a network Client
+async fn write_and_count<C: PancakeClient>(client: &mut C, n_rows: i64) -> ClientResult<u32> {
+  client.create_table(CreateTableRequest {
+    table_name: TABLE_NAME.to_string(),
+    schema: Some(Purchase::schema()),
+    mode: SchemaMode::AddNewColumns as i32,
+  }).await?;
+
+  let rows = (0..n_rows)
+    .map(|i| Purchase {
+      user_id: format!("user_{}", i % 7),
+      cents_amount: i * 100,
+    }.to_row())
+    .collect();
+  client.write_to_partition(WriteToPartitionRequest {
+    table_name: TABLE_NAME.to_string(),
+    partition: PartitionBuilder::new().build(),
+    rows,
+  }).await?;
+
+  let segments = client.list_segments(ListSegmentsRequest {
+    table_name: TABLE_NAME.to_string(),
+    ..Default::default()
+  }).await?.segments;
+  let mut row_count = 0;
+  for segment in segments {
+    let mut stream = client.read_segment_column(ReadSegmentColumnRequest {
+      table_name: TABLE_NAME.to_string(),
+      partition: segment.partition,
+      segment_id: segment.segment_id,
+      column_name: "cents_amount".to_string(),
+      correlation_id: new_correlation_id(),
+    }).await?;
+    while let Some(resp) = stream.next().await {
+      row_count += resp?.row_count;
+    }
+  }
+  Ok(row_count)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let opt: Opt = Opt::from_args();
+  let embedded = if opt.in_memory {
+    Embedded::start_in_memory().await?
+  } else {
+    Embedded::start(&opt.dir).await?
+  };
+  let mut client = embedded.client();
+  let row_count = write_and_count(&mut client, opt.n_rows).await?;
+  println!("{} has {} rows in flushed or compacted files", TABLE_NAME, row_count);
+  embedded.stop().await;
+  Ok(())
+}
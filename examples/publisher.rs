@@ -1,21 +1,20 @@
-use std::collections::HashMap;
 use std::net::IpAddr;
 use std::time::SystemTime;
 
 use pancake_db_client::Client;
 use pancake_db_client::errors::ClientResult;
+use pancake_db_client_ext::{PancakeRow, PartitionBuilder, RowBuilder, SchemaBuilder};
 use pancake_db_idl::ddl::create_table_request::SchemaMode;
 use pancake_db_idl::ddl::CreateTableRequest;
-use pancake_db_idl::dml::{field_value, FieldValue, partition_field_value, RepeatedFieldValue};
-use pancake_db_idl::dml::{PartitionFieldValue, Row, WriteToPartitionRequest};
+use pancake_db_idl::dml::{Row, WriteToPartitionRequest};
 use pancake_db_idl::dtype::DataType;
 use pancake_db_idl::partition_dtype::PartitionDataType;
-use pancake_db_idl::schema::{ColumnMeta, PartitionMeta, Schema};
+use pancake_db_idl::schema::Schema;
 use prost_types::Timestamp;
 use rand::Rng;
 use rand::rngs::ThreadRng;
 use structopt::StructOpt;
-use tokio::time::{Duration, Instant};
+use tokio::time::Instant;
 
 const TABLE_NAME: &str = "publisher_test";
 const PERFORMANCE_TABLE_NAME: &str = "publisher_performance";
@@ -33,118 +32,55 @@ struct Opt {
 }
 
 fn generate_row(rng: &mut ThreadRng, words: &[String], timestamp: Timestamp) -> Row {
-  let mut row = Row::default();
-  fn maybe_insert(rng: &mut ThreadRng, row: &mut Row, name: &str, value: field_value::Value) {
+  fn maybe<T>(rng: &mut ThreadRng, value: T) -> Option<T> {
     if rng.gen_bool(0.5) {
-      row.fields.insert(name.to_string(), FieldValue {
-        value: Some(value),
-        ..Default::default()
-      });
+      Some(value)
+    } else {
+      None
     }
   }
   let b = rng.gen_bool(0.001);
-  maybe_insert(rng, &mut row, "bool_col", field_value::Value::BoolVal(b));
   let byte = rng.gen::<u8>();
-  let mut bytes = Vec::new();
-  for _ in 0..rng.gen_range(0..20) {
-    bytes.push(byte);
-  }
-  maybe_insert(rng, &mut row, "bytes_col", field_value::Value::BytesVal(bytes));
-  let i = rng.gen_range(0..101);
-  maybe_insert(rng, &mut row, "int_col", field_value::Value::Int64Val(i));
-  let f = rng.gen_range(1.0..2.0);
-  maybe_insert(rng, &mut row, "float_col", field_value::Value::Float64Val(f));
-  let mut list = RepeatedFieldValue::default();
-  for _ in 0..rng.gen_range(0..3) {
-    let word_idx = rng.gen_range(0..words.len());
-    list.vals.push(FieldValue {
-      value: Some(field_value::Value::StringVal(words[word_idx].to_string())),
-      ..Default::default()
-    })
-  }
-  maybe_insert(rng, &mut row, "list_col", field_value::Value::ListVal(list));
-  maybe_insert(rng, &mut row, "timestamp_col", field_value::Value::TimestampVal(timestamp));
-  row
+  let bytes = vec![byte; rng.gen_range(0..20)];
+  let i: i64 = rng.gen_range(0..101);
+  let f: f64 = rng.gen_range(1.0..2.0);
+  let list = (0..rng.gen_range(0..3))
+    .map(|_| words[rng.gen_range(0..words.len())].to_string())
+    .collect::<Vec<_>>();
+  RowBuilder::new()
+    .maybe_field("bool_col", maybe(rng, b))
+    .maybe_field("bytes_col", maybe(rng, bytes))
+    .maybe_field("int_col", maybe(rng, i))
+    .maybe_field("float_col", maybe(rng, f))
+    .maybe_field("list_col", maybe(rng, list))
+    .maybe_field("timestamp_col", maybe(rng, timestamp))
+    .build()
 }
 
-fn make_performance_row(duration: Duration, concurrency: usize, errors: usize) -> Row {
-  let mut row = Row::default();
-  row.fields.insert("response_time".to_string(), FieldValue {
-    value: Some(field_value::Value::Float32Val(duration.as_secs_f32())),
-    ..Default::default()
-  });
-  row.fields.insert("write_start_at".to_string(), FieldValue {
-    value: Some(field_value::Value::TimestampVal(Timestamp::from(SystemTime::now()))),
-    ..Default::default()
-  });
-  row.fields.insert("concurrency".to_string(), FieldValue {
-    value: Some(field_value::Value::Int64Val(concurrency as i64)),
-    ..Default::default()
-  });
-  row.fields.insert("errors".to_string(), FieldValue {
-    value: Some(field_value::Value::Int64Val(errors as i64)),
-    ..Default::default()
-  });
-  row
+#[derive(PancakeRow)]
+struct PerformanceRow {
+  response_time: f32,
+  write_start_at: SystemTime,
+  concurrency: i64,
+  errors: i64,
 }
 
 fn make_schema() -> Schema {
-  let mut schema = Schema::default();
-  schema.partitioning.insert("time_bucket".to_string(), PartitionMeta {
-    dtype: PartitionDataType::TimestampMinute as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("bool_col".to_string(), ColumnMeta {
-    dtype: DataType::Bool as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("bytes_col".to_string(), ColumnMeta {
-    dtype: DataType::Bytes as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("float_col".to_string(), ColumnMeta {
-    dtype: DataType::Float64 as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("int_col".to_string(), ColumnMeta {
-    dtype: DataType::Int64 as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("list_col".to_string(), ColumnMeta {
-    dtype: DataType::String as i32,
-    nested_list_depth: 1,
-    ..Default::default()
-  });
-  schema.columns.insert("timestamp_col".to_string(), ColumnMeta {
-    dtype: DataType::TimestampMicros as i32,
-    ..Default::default()
-  });
-  schema
+  SchemaBuilder::new()
+    .partition("time_bucket", PartitionDataType::TimestampMinute)
+    .column("bool_col", DataType::Bool)
+    .column("bytes_col", DataType::Bytes)
+    .column("float_col", DataType::Float64)
+    .column("int_col", DataType::Int64)
+    .list_column("list_col", DataType::String, 1)
+    .column("timestamp_col", DataType::TimestampMicros)
+    .build()
 }
 
 fn make_performance_schema() -> Schema {
-  let mut schema = Schema::default();
-  schema.partitioning.insert("action".to_string(), PartitionMeta {
-    dtype: PartitionDataType::String as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("response_time".to_string(), ColumnMeta {
-    dtype: DataType::Float32 as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("concurrency".to_string(), ColumnMeta {
-    dtype: DataType::Int64 as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("errors".to_string(), ColumnMeta {
-    dtype: DataType::Int64 as i32,
-    ..Default::default()
-  });
-  schema.columns.insert("write_start_at".to_string(), ColumnMeta {
-    dtype: DataType::TimestampMicros as i32,
-    ..Default::default()
-  });
-  schema
+  SchemaBuilder::from(PerformanceRow::schema())
+    .partition("action", PartitionDataType::String)
+    .build()
 }
 
 fn truncate_to_time_bucket(t: Timestamp) -> Timestamp {
@@ -176,11 +112,9 @@ async fn main() -> ClientResult<()> {
     (2.0 * opt.target_rows_per_second);
   let delay = tokio::time::Duration::from_secs_f32(delay_seconds);
 
-  let mut performance_partition = HashMap::new();
-  performance_partition.insert("action".to_string(), PartitionFieldValue {
-    value: Some(partition_field_value::Value::StringVal("write".to_string())),
-    ..Default::default()
-  });
+  let performance_partition = PartitionBuilder::new()
+    .field("action", "write")
+    .build();
 
   let create_req = CreateTableRequest {
     table_name: TABLE_NAME.to_string(),
@@ -219,11 +153,9 @@ async fn main() -> ClientResult<()> {
     tokio::time::sleep_until(sleep_until).await;
     write_start_at = Instant::now();
     let timestamp = Timestamp::from(SystemTime::now());
-    let mut partition = HashMap::new();
-    partition.insert("time_bucket".to_string(), PartitionFieldValue {
-      value: Some(partition_field_value::Value::TimestampVal(truncate_to_time_bucket(timestamp.clone()))),
-      ..Default::default()
-    });
+    let partition = PartitionBuilder::new()
+      .field("time_bucket", truncate_to_time_bucket(timestamp.clone()))
+      .build();
     for _ in 0..concurrency {
       let row = generate_row(&mut rng, &words, timestamp.clone());
       let write_req = WriteToPartitionRequest {
@@ -248,7 +180,12 @@ async fn main() -> ClientResult<()> {
       .count();
 
     let duration = Instant::now() - write_start_at;
-    let performance_row = make_performance_row(duration, concurrency, errors);
+    let performance_row = PerformanceRow {
+      response_time: duration.as_secs_f32(),
+      write_start_at: SystemTime::now(),
+      concurrency: concurrency as i64,
+      errors: errors as i64,
+    }.to_row();
     let performance_write_req = WriteToPartitionRequest {
       table_name: PERFORMANCE_TABLE_NAME.to_string(),
       partition: performance_partition.clone(),
@@ -0,0 +1,42 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pancake_db_core::encoding;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_server::safe_decoding;
+
+const DTYPES: [DataType; 7] = [
+  DataType::Int64,
+  DataType::String,
+  DataType::Float32,
+  DataType::Float64,
+  DataType::Bytes,
+  DataType::Bool,
+  DataType::TimestampMicros,
+];
+const MAX_NESTED_LIST_DEPTH: u8 = 3;
+const LIMIT: usize = 1 << 16;
+
+// The first byte picks the dtype and nested list depth, and the rest is
+// decoded. Whatever decodes must survive re-encoding: encoding is not
+// canonical (anything can be escaped) and floats may be NaN, so it's the
+// re-encoded bytes that get compared.
+//
+// libFuzzer aborts on any panic, even ones safe_decoding would turn into
+// corrupt errors, so this also reports core panics worth fixing upstream.
+fuzz_target!(|data: &[u8]| {
+  if data.is_empty() {
+    return;
+  }
+  let dtype = DTYPES[data[0] as usize % DTYPES.len()];
+  let depth = (data[0] as usize / DTYPES.len()) as u8 % (MAX_NESTED_LIST_DEPTH + 1);
+  let bytes = &data[1..];
+
+  let _ = safe_decoding::decode_byte_idxs(dtype, depth, bytes, LIMIT);
+  if let Ok(values) = safe_decoding::decode_limited(dtype, depth, bytes, LIMIT) {
+    let encoder = encoding::new_encoder(dtype, depth);
+    let encoded = encoder.encode(&values).unwrap();
+    let decoded = safe_decoding::decode(dtype, depth, &encoded).unwrap();
+    assert_eq!(encoder.encode(&decoded).unwrap(), encoded);
+  }
+});
@@ -0,0 +1,38 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pancake_db_core::compression;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_server::safe_decoding;
+
+const DTYPES: [DataType; 7] = [
+  DataType::Int64,
+  DataType::String,
+  DataType::Float32,
+  DataType::Float64,
+  DataType::Bytes,
+  DataType::Bool,
+  DataType::TimestampMicros,
+];
+const MAX_NESTED_LIST_DEPTH: u8 = 3;
+// core's value codecs can't compress lists of lists
+const MAX_COMPRESSIBLE_DEPTH: u8 = 1;
+
+// Like the decode target, but for each dtype's compaction codec.
+fuzz_target!(|data: &[u8]| {
+  if data.is_empty() {
+    return;
+  }
+  let dtype = DTYPES[data[0] as usize % DTYPES.len()];
+  let depth = (data[0] as usize / DTYPES.len()) as u8 % (MAX_NESTED_LIST_DEPTH + 1);
+  let bytes = &data[1..];
+  let codec = compression::new_codec(dtype, &compression::choose_codec(dtype)).unwrap();
+
+  if let Ok(values) = safe_decoding::decompress(&*codec, bytes, depth) {
+    if depth <= MAX_COMPRESSIBLE_DEPTH {
+      let compressed = codec.compress(&values, depth).unwrap();
+      let decompressed = safe_decoding::decompress(&*codec, &compressed, depth).unwrap();
+      assert_eq!(codec.compress(&decompressed, depth).unwrap(), compressed);
+    }
+  }
+});
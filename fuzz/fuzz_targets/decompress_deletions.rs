@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pancake_db_server::safe_decoding;
+
+fuzz_target!(|data: &[u8]| {
+  let _ = safe_decoding::decompress_deletions(data);
+});
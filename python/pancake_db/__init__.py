@@ -0,0 +1,159 @@
+"""Python client for PancakeDB.
+
+Rows are written from lists of dicts or pandas DataFrames, and segments are
+read back as pyarrow Tables. Schemas use the same dict format as the
+server's REST API, e.g.
+
+    {
+        "partitioning": {"day": {"dtype": "timestampMinute"}},
+        "columns": {"user_id": {"dtype": "string"}},
+    }
+"""
+
+import json
+
+import pyarrow as pa
+
+from ._native import Client as _NativeClient
+from ._native import PancakeError
+
+__all__ = ["Client", "PancakeError"]
+
+_COLUMN_ARROW_TYPES = {
+    "bool": pa.bool_(),
+    "bytes": pa.binary(),
+    "float32": pa.float32(),
+    "float64": pa.float64(),
+    "int64": pa.int64(),
+    "string": pa.string(),
+    "timestampMicros": pa.timestamp("us", tz="UTC"),
+}
+
+_PARTITION_ARROW_TYPES = {
+    "bool": pa.bool_(),
+    "int64": pa.int64(),
+    "string": pa.string(),
+    "timestampMinute": pa.timestamp("us", tz="UTC"),
+}
+
+
+def _column_arrow_type(meta):
+    arrow_type = _COLUMN_ARROW_TYPES[meta["dtype"]]
+    for _ in range(meta.get("nestedListDepth", 0)):
+        arrow_type = pa.list_(arrow_type)
+    return arrow_type
+
+
+def _is_dataframe(rows):
+    return type(rows).__module__.startswith("pandas") and hasattr(rows, "to_dict")
+
+
+def _dataframe_records(df):
+    # NaN and NaT mark missing values in pandas; PancakeDB uses nulls
+    df = df.astype(object).where(df.notna(), None)
+    return df.to_dict("records")
+
+
+class Client:
+    """A connection to a PancakeDB server's gRPC port."""
+
+    def __init__(self, host="127.0.0.1", port=3842):
+        self._native = _NativeClient(host, port)
+
+    def list_tables(self):
+        return self._native.list_tables()
+
+    def create_table(self, table_name, schema, mode="fail_if_exists"):
+        """Creates a table.
+
+        mode is one of "fail_if_exists", "ok_if_exact", or "add_new_columns".
+        Returns a dict with "already_exists" and "columns_added" keys.
+        """
+        already_exists, columns_added = self._native.create_table(
+            table_name,
+            json.dumps(schema),
+            mode,
+        )
+        return {
+            "already_exists": already_exists,
+            "columns_added": columns_added,
+        }
+
+    def get_schema(self, table_name):
+        return json.loads(self._native.get_schema(table_name))
+
+    def drop_table(self, table_name):
+        self._native.drop_table(table_name)
+
+    def write(self, table_name, rows, partition=None, batch_size=256):
+        """Writes rows to a single partition of a table.
+
+        rows may be an iterable of dicts or a pandas DataFrame whose column
+        names match the table's. Returns the number of rows written.
+        """
+        if _is_dataframe(rows):
+            rows = _dataframe_records(rows)
+        return self._native.write_to_partition(
+            table_name,
+            rows,
+            partition,
+            batch_size,
+        )
+
+    def list_segments(self, table_name, include_metadata=False):
+        """Lists segments as dicts with "partition" and "segment_id" keys.
+
+        With include_metadata, each dict also has a "row_count".
+        """
+        return self._native.list_segments(table_name, include_metadata)
+
+    def read_segment(self, table_name, segment, columns=None):
+        """Reads one segment (as returned by list_segments) into a pyarrow Table.
+
+        Partition fields are included as constant columns. Only the named
+        columns are read if columns is given.
+        """
+        return self._read_segment(
+            table_name,
+            self.get_schema(table_name),
+            segment,
+            columns,
+        )
+
+    def read_table(self, table_name, columns=None):
+        """Reads every segment of a table into a single pyarrow Table."""
+        schema = self.get_schema(table_name)
+        tables = [
+            self._read_segment(table_name, schema, segment, columns)
+            for segment in self.list_segments(table_name)
+        ]
+        if not tables:
+            return self._arrow_schema(schema, columns).empty_table()
+        return pa.concat_tables(tables)
+
+    def _arrow_schema(self, schema, columns):
+        fields = [
+            pa.field(name, _PARTITION_ARROW_TYPES[meta["dtype"]])
+            for name, meta in sorted(schema["partitioning"].items())
+        ]
+        column_names = sorted(schema["columns"]) if columns is None else columns
+        fields.extend(
+            pa.field(name, _column_arrow_type(schema["columns"][name]))
+            for name in column_names
+        )
+        return pa.schema(fields)
+
+    def _read_segment(self, table_name, schema, segment, columns):
+        data = self._native.read_segment(
+            table_name,
+            segment["partition"],
+            segment["segment_id"],
+            columns,
+        )
+        n_rows = len(next(iter(data.values()), []))
+        for name, value in segment["partition"].items():
+            data[name] = [value] * n_rows
+        return pa.Table.from_pydict(
+            data,
+            schema=self._arrow_schema(schema, columns),
+        )
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use pancake_db_idl::dml::{FieldValue, PartitionFieldValue, RepeatedFieldValue, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use prost_types::Timestamp;
+use pyo3::IntoPyObjectExt;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+use crate::errors::new_error;
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+// Timestamps are converted through the standard library's datetime module so
+// that both datetime.datetime and pandas.Timestamp values are accepted.
+// Naive datetimes are interpreted as UTC, and returned datetimes are always
+// timezone-aware UTC.
+fn epoch(py: Python<'_>, tz_aware: bool) -> PyResult<Bound<'_, PyAny>> {
+  let datetime = py.import("datetime")?;
+  let datetime_cls = datetime.getattr("datetime")?;
+  if tz_aware {
+    let utc = datetime.getattr("timezone")?.getattr("utc")?;
+    datetime_cls.call1((1970, 1, 1, 0, 0, 0, 0, utc))
+  } else {
+    datetime_cls.call1((1970, 1, 1))
+  }
+}
+
+fn one_micro(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+  let kwargs = PyDict::new(py);
+  kwargs.set_item("microseconds", 1)?;
+  py.import("datetime")?
+    .getattr("timedelta")?
+    .call((), Some(&kwargs))
+}
+
+fn extract_timestamp(obj: &Bound<'_, PyAny>) -> PyResult<Timestamp> {
+  let py = obj.py();
+  let tz_aware = !obj.getattr("tzinfo")?.is_none();
+  let micros: i64 = obj.call_method1("__sub__", (epoch(py, tz_aware)?,))?
+    .call_method1("__floordiv__", (one_micro(py)?,))?
+    .extract()?;
+  Ok(Timestamp {
+    seconds: micros.div_euclid(MICROS_PER_SECOND),
+    nanos: (micros.rem_euclid(MICROS_PER_SECOND) * 1000) as i32,
+  })
+}
+
+fn timestamp_to_py(py: Python<'_>, t: &Timestamp) -> PyResult<PyObject> {
+  let micros = t.seconds * MICROS_PER_SECOND + (t.nanos / 1000) as i64;
+  let kwargs = PyDict::new(py);
+  kwargs.set_item("microseconds", micros)?;
+  let delta = py.import("datetime")?
+    .getattr("timedelta")?
+    .call((), Some(&kwargs))?;
+  epoch(py, true)?.call_method1("__add__", (delta,))?.into_py_any(py)
+}
+
+fn extract_atom(obj: &Bound<'_, PyAny>, dtype: DataType) -> PyResult<Value> {
+  let value = match dtype {
+    DataType::String => Value::StringVal(obj.extract()?),
+    DataType::Int64 => Value::Int64Val(obj.extract()?),
+    DataType::Bool => Value::BoolVal(obj.extract()?),
+    DataType::Bytes => Value::BytesVal(obj.downcast::<PyBytes>()?.as_bytes().to_vec()),
+    DataType::Float32 => Value::Float32Val(obj.extract::<f64>()? as f32),
+    DataType::Float64 => Value::Float64Val(obj.extract()?),
+    DataType::TimestampMicros => Value::TimestampVal(extract_timestamp(obj)?),
+  };
+  Ok(value)
+}
+
+fn extract_field_value(obj: &Bound<'_, PyAny>, dtype: DataType, nested_list_depth: u32) -> PyResult<FieldValue> {
+  if obj.is_none() {
+    return Ok(FieldValue::default());
+  }
+
+  let value = if nested_list_depth == 0 {
+    extract_atom(obj, dtype)?
+  } else {
+    let vals = obj.try_iter()?
+      .map(|item| extract_field_value(&item?, dtype, nested_list_depth - 1))
+      .collect::<PyResult<Vec<_>>>()?;
+    Value::ListVal(RepeatedFieldValue { vals })
+  };
+  Ok(FieldValue { value: Some(value) })
+}
+
+fn extract_partition_field_value(obj: &Bound<'_, PyAny>, dtype: PartitionDataType) -> PyResult<PartitionFieldValue> {
+  let value = match dtype {
+    PartitionDataType::String => PartitionValue::StringVal(obj.extract()?),
+    PartitionDataType::Int64 => PartitionValue::Int64Val(obj.extract()?),
+    PartitionDataType::Bool => PartitionValue::BoolVal(obj.extract()?),
+    PartitionDataType::TimestampMinute => PartitionValue::TimestampVal(extract_timestamp(obj)?),
+  };
+  Ok(PartitionFieldValue { value: Some(value) })
+}
+
+fn field_name_error(name: &Bound<'_, PyAny>, e: PyErr) -> PyErr {
+  new_error(format!("{}: {}", name, e))
+}
+
+pub fn extract_row(obj: &Bound<'_, PyAny>, schema: &Schema) -> PyResult<Row> {
+  let dict = obj.downcast::<PyDict>()?;
+  let mut row = Row::default();
+  for (key, value) in dict {
+    let name: String = key.extract()?;
+    let col_meta = schema.columns.get(&name)
+      .ok_or_else(|| new_error(format!("column {} does not exist", name)))?;
+    let fv = extract_field_value(&value, col_meta.dtype(), col_meta.nested_list_depth)
+      .map_err(|e| field_name_error(&key, e))?;
+    row.fields.insert(name, fv);
+  }
+  Ok(row)
+}
+
+pub fn extract_partition(obj: Option<&Bound<'_, PyDict>>, schema: &Schema) -> PyResult<HashMap<String, PartitionFieldValue>> {
+  let mut partition = HashMap::new();
+  for (key, value) in obj.into_iter().flat_map(|dict| dict.iter()) {
+    let name: String = key.extract()?;
+    let meta = schema.partitioning.get(&name)
+      .ok_or_else(|| new_error(format!("partition field {} does not exist", name)))?;
+    let pfv = extract_partition_field_value(&value, meta.dtype())
+      .map_err(|e| field_name_error(&key, e))?;
+    partition.insert(name, pfv);
+  }
+  Ok(partition)
+}
+
+pub fn field_value_to_py(py: Python<'_>, fv: &FieldValue) -> PyResult<PyObject> {
+  let obj = match &fv.value {
+    None => py.None(),
+    Some(Value::StringVal(x)) => x.into_py_any(py)?,
+    Some(Value::BoolVal(x)) => x.into_py_any(py)?,
+    Some(Value::BytesVal(x)) => PyBytes::new(py, x).into_py_any(py)?,
+    Some(Value::Int64Val(x)) => x.into_py_any(py)?,
+    Some(Value::Float32Val(x)) => x.into_py_any(py)?,
+    Some(Value::Float64Val(x)) => x.into_py_any(py)?,
+    Some(Value::TimestampVal(x)) => timestamp_to_py(py, x)?,
+    Some(Value::ListVal(x)) => {
+      let vals = x.vals.iter()
+        .map(|val| field_value_to_py(py, val))
+        .collect::<PyResult<Vec<_>>>()?;
+      PyList::new(py, vals)?.into_py_any(py)?
+    },
+  };
+  Ok(obj)
+}
+
+pub fn partition_field_value_to_py(py: Python<'_>, pfv: &PartitionFieldValue) -> PyResult<PyObject> {
+  let obj = match &pfv.value {
+    None => py.None(),
+    Some(PartitionValue::StringVal(x)) => x.into_py_any(py)?,
+    Some(PartitionValue::BoolVal(x)) => x.into_py_any(py)?,
+    Some(PartitionValue::Int64Val(x)) => x.into_py_any(py)?,
+    Some(PartitionValue::TimestampVal(x)) => timestamp_to_py(py, x)?,
+  };
+  Ok(obj)
+}
+
+pub fn partition_to_py<'py>(
+  py: Python<'py>,
+  partition: &HashMap<String, PartitionFieldValue>,
+) -> PyResult<Bound<'py, PyDict>> {
+  let dict = PyDict::new(py);
+  for (name, pfv) in partition {
+    dict.set_item(name, partition_field_value_to_py(py, pfv)?)?;
+  }
+  Ok(dict)
+}
+
+// Lays rows out column by column (a dict of lists), which is the shape
+// pyarrow.Table.from_pydict expects. Fields missing from a row become None.
+pub fn rows_to_columns<'py>(
+  py: Python<'py>,
+  rows: &[Row],
+  columns: &HashMap<String, ColumnMeta>,
+) -> PyResult<Bound<'py, PyDict>> {
+  let dict = PyDict::new(py);
+  for name in columns.keys() {
+    let vals = rows.iter()
+      .map(|row| match row.fields.get(name) {
+        Some(fv) => field_value_to_py(py, fv),
+        None => Ok(py.None()),
+      })
+      .collect::<PyResult<Vec<_>>>()?;
+    dict.set_item(name, PyList::new(py, vals)?)?;
+  }
+  Ok(dict)
+}
@@ -0,0 +1,37 @@
+use pancake_db_client::errors::ClientError;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::PyErr;
+
+create_exception!(_native, PancakeError, PyException, "Raised when a PancakeDB request fails.");
+
+pub fn new_error(message: impl Into<String>) -> PyErr {
+  PancakeError::new_err(message.into())
+}
+
+trait PyUpcastableError: std::error::Error {}
+impl PyUpcastableError for ClientError {}
+impl PyUpcastableError for serde_json::Error {}
+impl PyUpcastableError for std::io::Error {}
+
+pub struct PyClientError(PyErr);
+
+impl<E> From<E> for PyClientError where E: PyUpcastableError {
+  fn from(e: E) -> Self {
+    PyClientError(new_error(e.to_string()))
+  }
+}
+
+impl From<PyErr> for PyClientError {
+  fn from(e: PyErr) -> Self {
+    PyClientError(e)
+  }
+}
+
+impl From<PyClientError> for PyErr {
+  fn from(e: PyClientError) -> Self {
+    e.0
+  }
+}
+
+pub type PyClientResult<T> = Result<T, PyClientError>;
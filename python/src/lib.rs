@@ -0,0 +1,194 @@
+use pancake_db_client::SegmentKey;
+use pancake_db_idl::ddl::{CreateTableRequest, DropTableRequest, GetSchemaRequest, ListTablesRequest};
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{ListSegmentsRequest, WriteToPartitionRequest};
+use pancake_db_idl::schema::Schema;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use tokio::runtime::Runtime;
+
+use crate::errors::{new_error, PancakeError, PyClientResult};
+
+mod convert;
+mod errors;
+mod schema;
+
+// Native half of the `pancake_db` Python package. It wraps the Rust gRPC
+// client and converts between Python objects and PancakeDB's protobuf
+// types; pandas and pyarrow handling lives in the pure Python half.
+#[pyclass(module = "pancake_db._native")]
+struct Client {
+  runtime: Runtime,
+  client: pancake_db_client::Client,
+}
+
+fn parse_schema_mode(s: &str) -> PyClientResult<SchemaMode> {
+  match s {
+    "fail_if_exists" => Ok(SchemaMode::FailIfExists),
+    "ok_if_exact" => Ok(SchemaMode::OkIfExact),
+    "add_new_columns" => Ok(SchemaMode::AddNewColumns),
+    _ => Err(new_error(format!("unknown schema mode {}", s)).into()),
+  }
+}
+
+impl Client {
+  fn fetch_schema(&mut self, py: Python<'_>, table_name: &str) -> PyClientResult<Schema> {
+    let Client { runtime, client } = self;
+    let resp = py.allow_threads(|| runtime.block_on(client.get_schema(GetSchemaRequest {
+      table_name: table_name.to_string(),
+    })))?;
+    resp.schema.ok_or_else(|| new_error("server returned no schema").into())
+  }
+}
+
+#[pymethods]
+impl Client {
+  #[new]
+  #[pyo3(signature = (host = "127.0.0.1", port = 3842))]
+  fn new(py: Python<'_>, host: &str, port: u16) -> PyClientResult<Self> {
+    let runtime = Runtime::new()?;
+    let addr = format!("http://{}:{}", host, port);
+    let client = py.allow_threads(|| runtime.block_on(pancake_db_client::Client::connect(addr)))?;
+    Ok(Client { runtime, client })
+  }
+
+  fn list_tables(&mut self, py: Python<'_>) -> PyClientResult<Vec<String>> {
+    let Client { runtime, client } = self;
+    let resp = py.allow_threads(|| runtime.block_on(client.list_tables(ListTablesRequest {})))?;
+    Ok(resp.tables.into_iter().map(|table| table.table_name).collect())
+  }
+
+  // returns (already_exists, columns_added)
+  fn create_table(
+    &mut self,
+    py: Python<'_>,
+    table_name: &str,
+    schema_json: &str,
+    mode: &str,
+  ) -> PyClientResult<(bool, Vec<String>)> {
+    let req = CreateTableRequest {
+      table_name: table_name.to_string(),
+      schema: Some(schema::schema_from_json(schema_json)?),
+      mode: parse_schema_mode(mode)? as i32,
+    };
+    let Client { runtime, client } = self;
+    let resp = py.allow_threads(|| runtime.block_on(client.create_table(req)))?;
+    Ok((resp.already_exists, resp.columns_added))
+  }
+
+  fn get_schema(&mut self, py: Python<'_>, table_name: &str) -> PyClientResult<String> {
+    let schema = self.fetch_schema(py, table_name)?;
+    schema::schema_to_json(&schema)
+  }
+
+  fn drop_table(&mut self, py: Python<'_>, table_name: &str) -> PyClientResult<()> {
+    let Client { runtime, client } = self;
+    py.allow_threads(|| runtime.block_on(client.drop_table(DropTableRequest {
+      table_name: table_name.to_string(),
+    })))?;
+    Ok(())
+  }
+
+  // Writes an iterable of dicts, batch_size rows per request, and returns
+  // the number of rows written.
+  #[pyo3(signature = (table_name, rows, partition = None, batch_size = 256))]
+  fn write_to_partition(
+    &mut self,
+    py: Python<'_>,
+    table_name: &str,
+    rows: &Bound<'_, PyAny>,
+    partition: Option<&Bound<'_, PyDict>>,
+    batch_size: usize,
+  ) -> PyClientResult<usize> {
+    let schema = self.fetch_schema(py, table_name)?;
+    let partition = convert::extract_partition(partition, &schema)?;
+    let batch_size = batch_size.max(1);
+
+    let mut n_written = 0;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut rows_iter = rows.try_iter()?.peekable();
+    while let Some(row) = rows_iter.next() {
+      let row = convert::extract_row(&row?, &schema)
+        .map_err(|e| new_error(format!("row {}: {}", n_written + batch.len(), e)))?;
+      batch.push(row);
+      if batch.len() >= batch_size || rows_iter.peek().is_none() {
+        let req = WriteToPartitionRequest {
+          table_name: table_name.to_string(),
+          partition: partition.clone(),
+          rows: std::mem::take(&mut batch),
+        };
+        n_written += req.rows.len();
+        let Client { runtime, client } = self;
+        py.allow_threads(|| runtime.block_on(client.write_to_partition(req)))?;
+      }
+    }
+    Ok(n_written)
+  }
+
+  // Returns a list of dicts with "partition" and "segment_id" keys, plus
+  // "row_count" when include_metadata is set.
+  #[pyo3(signature = (table_name, include_metadata = false))]
+  fn list_segments<'py>(
+    &mut self,
+    py: Python<'py>,
+    table_name: &str,
+    include_metadata: bool,
+  ) -> PyClientResult<Bound<'py, PyList>> {
+    let Client { runtime, client } = self;
+    let resp = py.allow_threads(|| runtime.block_on(client.list_segments(ListSegmentsRequest {
+      table_name: table_name.to_string(),
+      include_metadata,
+      ..Default::default()
+    })))?;
+
+    let segments = PyList::empty(py);
+    for segment in &resp.segments {
+      let dict = PyDict::new(py);
+      dict.set_item("partition", convert::partition_to_py(py, &segment.partition)?)?;
+      dict.set_item("segment_id", &segment.segment_id)?;
+      if let Some(meta) = &segment.metadata {
+        dict.set_item("row_count", meta.row_count)?;
+      }
+      segments.append(dict)?;
+    }
+    Ok(segments)
+  }
+
+  // Decodes one segment into a dict mapping each requested column (all
+  // columns by default) to a list of values.
+  #[pyo3(signature = (table_name, partition, segment_id, columns = None))]
+  fn read_segment<'py>(
+    &mut self,
+    py: Python<'py>,
+    table_name: &str,
+    partition: Option<&Bound<'_, PyDict>>,
+    segment_id: &str,
+    columns: Option<Vec<String>>,
+  ) -> PyClientResult<Bound<'py, PyDict>> {
+    let mut schema = self.fetch_schema(py, table_name)?;
+    if let Some(columns) = &columns {
+      for name in columns {
+        if !schema.columns.contains_key(name) {
+          return Err(new_error(format!("column {} does not exist", name)).into());
+        }
+      }
+      schema.columns.retain(|name, _| columns.contains(name));
+    }
+    let key = SegmentKey {
+      table_name: table_name.to_string(),
+      partition: convert::extract_partition(partition, &schema)?,
+      segment_id: segment_id.to_string(),
+    };
+
+    let Client { runtime, client } = self;
+    let rows = py.allow_threads(|| runtime.block_on(client.decode_segment(&key, &schema.columns)))?;
+    Ok(convert::rows_to_columns(py, &rows, &schema.columns)?)
+  }
+}
+
+#[pymodule]
+fn _native(m: &Bound<'_, PyModule>) -> PyResult<()> {
+  m.add_class::<Client>()?;
+  m.add("PancakeError", m.py().get_type::<PancakeError>())?;
+  Ok(())
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, PartitionMeta, Schema};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{new_error, PyClientResult};
+
+// Schemas cross the Python boundary as JSON in the same format as the
+// server's REST API, e.g.
+// {"partitioning": {"day": {"dtype": "timestampMinute"}},
+//  "columns": {"user_id": {"dtype": "string"}}}
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ColumnMetaJson {
+  dtype: String,
+  #[serde(default)]
+  nested_list_depth: u32,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PartitionMetaJson {
+  dtype: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaJson {
+  #[serde(default)]
+  partitioning: HashMap<String, PartitionMetaJson>,
+  columns: HashMap<String, ColumnMetaJson>,
+}
+
+fn parse_dtype(s: &str) -> PyClientResult<DataType> {
+  match s {
+    "bool" => Ok(DataType::Bool),
+    "bytes" => Ok(DataType::Bytes),
+    "float32" => Ok(DataType::Float32),
+    "float64" => Ok(DataType::Float64),
+    "int64" => Ok(DataType::Int64),
+    "string" => Ok(DataType::String),
+    "timestampMicros" => Ok(DataType::TimestampMicros),
+    _ => Err(new_error(format!("unknown column dtype {}", s)).into()),
+  }
+}
+
+fn parse_partition_dtype(s: &str) -> PyClientResult<PartitionDataType> {
+  match s {
+    "bool" => Ok(PartitionDataType::Bool),
+    "int64" => Ok(PartitionDataType::Int64),
+    "string" => Ok(PartitionDataType::String),
+    "timestampMinute" => Ok(PartitionDataType::TimestampMinute),
+    _ => Err(new_error(format!("unknown partition dtype {}", s)).into()),
+  }
+}
+
+fn dtype_name(dtype: DataType) -> &'static str {
+  match dtype {
+    DataType::Bool => "bool",
+    DataType::Bytes => "bytes",
+    DataType::Float32 => "float32",
+    DataType::Float64 => "float64",
+    DataType::Int64 => "int64",
+    DataType::String => "string",
+    DataType::TimestampMicros => "timestampMicros",
+  }
+}
+
+fn partition_dtype_name(dtype: PartitionDataType) -> &'static str {
+  match dtype {
+    PartitionDataType::Bool => "bool",
+    PartitionDataType::Int64 => "int64",
+    PartitionDataType::String => "string",
+    PartitionDataType::TimestampMinute => "timestampMinute",
+  }
+}
+
+pub fn schema_from_json(json_str: &str) -> PyClientResult<Schema> {
+  let schema_json: SchemaJson = serde_json::from_str(json_str)?;
+  let mut schema = Schema::default();
+  for (name, meta) in &schema_json.partitioning {
+    schema.partitioning.insert(name.to_string(), PartitionMeta {
+      dtype: parse_partition_dtype(&meta.dtype)? as i32,
+    });
+  }
+  for (name, meta) in &schema_json.columns {
+    schema.columns.insert(name.to_string(), ColumnMeta {
+      dtype: parse_dtype(&meta.dtype)? as i32,
+      nested_list_depth: meta.nested_list_depth,
+    });
+  }
+  Ok(schema)
+}
+
+pub fn schema_to_json(schema: &Schema) -> PyClientResult<String> {
+  let schema_json = SchemaJson {
+    partitioning: schema.partitioning.iter()
+      .map(|(name, meta)| (
+        name.to_string(),
+        PartitionMetaJson { dtype: partition_dtype_name(meta.dtype()).to_string() },
+      ))
+      .collect(),
+    columns: schema.columns.iter()
+      .map(|(name, meta)| (
+        name.to_string(),
+        ColumnMetaJson {
+          dtype: dtype_name(meta.dtype()).to_string(),
+          nested_list_depth: meta.nested_list_depth,
+        },
+      ))
+      .collect(),
+  };
+  Ok(serde_json::to_string(&schema_json)?)
+}
@@ -0,0 +1,30 @@
+#!/bin/bash
+# Runs the criterion benchmarks, saving or comparing against a named
+# baseline so regressions show up as changes. E.g. to check a branch:
+#   git checkout main && scripts/bench.sh save main
+#   git checkout my-branch && scripts/bench.sh compare main
+# Any further args, like a benchmark name filter, go to criterion.
+# Reports are written to target/criterion/report/index.html.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+usage() {
+  echo "usage: $0 (save|compare) BASELINE [CRITERION_ARGS...]" >&2
+  exit 1
+}
+
+if [ $# -lt 2 ]; then
+  usage
+fi
+mode=$1
+baseline=$2
+shift 2
+
+case "$mode" in
+  save) flag=--save-baseline ;;
+  compare) flag=--baseline ;;
+  *) usage ;;
+esac
+
+cargo bench -p pancake-db-server --bench core_encoding --bench server -- "$flag" "$baseline" "$@"
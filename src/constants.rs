@@ -4,11 +4,74 @@ pub const MAX_NESTED_LIST_DEPTH: u32 = 3;
 pub const MAX_PARTITIONING_DEPTH: usize = 4;
 pub const MAX_NAME_LENGTH: usize = 255;
 pub const MAX_N_COLUMNS: usize = 255;
+// timestamps from 0001-01-01T00:00:00Z through 9999-12-31T23:59:59Z, the
+// range RFC 3339 can express
+pub const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+pub const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+
+// Servers refuse dirs of another major version until they are migrated.
+// Minor versions only add to the format, so servers can read dirs of any
+// minor version within their major version.
+pub const FORMAT_MAJOR_VERSION: u32 = 2;
+pub const FORMAT_MINOR_VERSION: u32 = 0;
 
 pub const TABLE_METADATA_FILENAME: &str = "table_metadata.json";
 pub const DATA_SUBDIR: &str = "data";
+pub const GARBAGE_SEGMENT_PREFIX: &str = "gc_";
+
+pub const ENCRYPTION_KEY_ENV_VAR_PREFIX: &str = "PANCAKE_DB_ENCRYPTION_KEY_";
 
 pub const ROW_ID_COLUMN_NAME: &str = "_row_id";
 pub const WRITTEN_AT_COLUMN_NAME: &str = "_written_at";
 
+pub const DEAD_LETTER_TABLE_SUFFIX: &str = "_dead_letter";
+pub const DEAD_LETTER_RAW_JSON_COLUMN_NAME: &str = "raw_json";
+pub const DEAD_LETTER_ERROR_COLUMN_NAME: &str = "error";
+
+pub const SLOW_OP_NAME_COLUMN_NAME: &str = "op";
+pub const SLOW_OP_KEY_COLUMN_NAME: &str = "key";
+pub const SLOW_OP_PRINCIPAL_COLUMN_NAME: &str = "principal";
+pub const SLOW_OP_DURATION_COLUMN_NAME: &str = "duration_millis";
+pub const SLOW_OP_LOCK_WAITS_COLUMN_NAME: &str = "lock_waits";
+pub const SLOW_OP_BYTES_COLUMN_NAME: &str = "bytes";
+
+pub const OPS_TABLE_NAME: &str = "_pancake_ops";
+pub const OPS_KIND_COLUMN_NAME: &str = "op";
+pub const OPS_TABLE_COLUMN_NAME: &str = "table_name";
+pub const OPS_PARTITION_COLUMN_NAME: &str = "partition";
+pub const OPS_SEGMENT_ID_COLUMN_NAME: &str = "segment_id";
+pub const OPS_STARTED_AT_COLUMN_NAME: &str = "started_at";
+pub const OPS_DURATION_COLUMN_NAME: &str = "duration_millis";
+pub const OPS_ROWS_COLUMN_NAME: &str = "rows";
+pub const OPS_BYTES_IN_COLUMN_NAME: &str = "bytes_in";
+pub const OPS_BYTES_OUT_COLUMN_NAME: &str = "bytes_out";
+pub const OPS_CODECS_COLUMN_NAME: &str = "codecs";
+
+pub const SYSTEM_TABLES_TABLE_NAME: &str = "_tables";
+pub const SYSTEM_COLUMNS_TABLE_NAME: &str = "_columns";
+pub const SYSTEM_SEGMENTS_TABLE_NAME: &str = "_segments";
+pub const SYSTEM_TABLE_NAME_COLUMN_NAME: &str = "table_name";
+pub const SYSTEM_SCHEMA_VERSION_COLUMN_NAME: &str = "schema_version";
+pub const SYSTEM_N_COLUMNS_COLUMN_NAME: &str = "n_columns";
+pub const SYSTEM_N_PARTITION_FIELDS_COLUMN_NAME: &str = "n_partition_fields";
+pub const SYSTEM_SORT_COLUMNS_COLUMN_NAME: &str = "sort_columns";
+pub const SYSTEM_FROZEN_COLUMN_NAME: &str = "frozen";
+pub const SYSTEM_CREATED_AT_COLUMN_NAME: &str = "created_at";
+pub const SYSTEM_LAST_ALTERED_AT_COLUMN_NAME: &str = "last_altered_at";
+pub const SYSTEM_COLUMN_NAME_COLUMN_NAME: &str = "column_name";
+pub const SYSTEM_DTYPE_COLUMN_NAME: &str = "dtype";
+pub const SYSTEM_NESTED_LIST_DEPTH_COLUMN_NAME: &str = "nested_list_depth";
+pub const SYSTEM_IS_PARTITION_COLUMN_NAME: &str = "is_partition";
+pub const SYSTEM_IS_COMPUTED_COLUMN_NAME: &str = "is_computed";
+pub const SYSTEM_IS_MASKED_COLUMN_NAME: &str = "is_masked";
+pub const SYSTEM_ADDED_AT_COLUMN_NAME: &str = "added_at";
+pub const SYSTEM_PARTITION_COLUMN_NAME: &str = "partition";
+pub const SYSTEM_SEGMENT_ID_COLUMN_NAME: &str = "segment_id";
+pub const SYSTEM_ROW_COUNT_COLUMN_NAME: &str = "row_count";
+pub const SYSTEM_STAGED_N_COLUMN_NAME: &str = "staged_n";
+pub const SYSTEM_READ_VERSION_COLUMN_NAME: &str = "read_version";
+pub const SYSTEM_UNCOMPRESSED_BYTES_COLUMN_NAME: &str = "uncompressed_bytes";
+pub const SYSTEM_IS_COLD_COLUMN_NAME: &str = "is_cold";
+pub const SYSTEM_LAST_FLUSH_AT_COLUMN_NAME: &str = "last_flush_at";
+
 pub const SHARD_ID_BYTE_LENGTH: usize = 2; // so 4 hex chars
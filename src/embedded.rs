@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use pancake_db_client::errors::{ClientError, ClientResult};
+use pancake_db_client_ext::PancakeClient;
+use pancake_db_client_ext::client::ReadSegmentColumnStream;
+use pancake_db_idl::ddl::{AlterTableRequest, AlterTableResponse, CreateTableRequest, CreateTableResponse, DropTableRequest, DropTableResponse, GetSchemaRequest, GetSchemaResponse, ListTablesRequest, ListTablesResponse};
+use pancake_db_idl::dml::{DeleteFromSegmentRequest, DeleteFromSegmentResponse, ListSegmentsRequest, ListSegmentsResponse, ReadSegmentColumnRequest, ReadSegmentDeletionsRequest, ReadSegmentDeletionsResponse, WriteToPartitionRequest, WriteToPartitionResponse};
+use pancake_db_idl::service::pancake_db_server::PancakeDb;
+use structopt::StructOpt;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status};
+use tonic::metadata::MetadataValue;
+use uuid::Uuid;
+
+use crate::errors::{Contextable, ServerError, ServerResult};
+use crate::opt::Opt;
+use crate::server::Server;
+use crate::utils::{chaos, vfs};
+
+/// A server running inside this process on a local dir, with no HTTP,
+/// GRPC, or Postgres listeners. Its clients call the server directly, but
+/// requests still go through the same authorization, limits, and error
+/// handling as GRPC requests.
+///
+/// Flushing, compaction, and other background loops run as tasks on the
+/// current tokio runtime until the server is stopped or dropped.
+pub struct Embedded {
+  server: Server,
+  background: JoinHandle<()>,
+  memory_root: Option<PathBuf>,
+}
+
+fn opts_from_args(dir: &Path, args: &[&str]) -> ServerResult<Opt> {
+  let dir_str = dir.to_str()
+    .ok_or_else(|| ServerError::invalid("dir is not a valid string"))?;
+  let mut all_args = vec!["pancake-db-server", "--dir", dir_str];
+  all_args.extend_from_slice(args);
+  Opt::from_iter_safe(all_args)
+    .map_err(|e| ServerError::invalid(&e.message))
+}
+
+impl Embedded {
+  pub async fn start(dir: impl Into<PathBuf>) -> ServerResult<Embedded> {
+    Self::start_with_args(dir, &[]).await
+  }
+
+  /// Starts with server command line flags, like
+  /// `["--target-rows-per-segment", "1000"]`. Flags for listeners are
+  /// ignored.
+  pub async fn start_with_args(dir: impl Into<PathBuf>, args: &[&str]) -> ServerResult<Embedded> {
+    let dir = dir.into();
+    std::fs::create_dir_all(&dir)?;
+    Self::start_with_opts(opts_from_args(&dir, args)?).await
+  }
+
+  /// Starts with nothing on disk: all metadata and column files live in
+  /// memory and are discarded when the server is dropped. Meant for tests,
+  /// which then need no temporary dirs and wait on no fsyncs.
+  pub async fn start_in_memory() -> ServerResult<Embedded> {
+    Self::start_in_memory_with_args(&[]).await
+  }
+
+  pub async fn start_in_memory_with_args(args: &[&str]) -> ServerResult<Embedded> {
+    let dir = PathBuf::from(format!("/pancake-db-memory/{}", Uuid::new_v4()));
+    vfs::mount_memory(&dir);
+    let res = match opts_from_args(&dir, args) {
+      Ok(opts) => Self::start_with_opts(opts).await,
+      Err(e) => Err(e),
+    };
+    match res {
+      Ok(mut embedded) => {
+        embedded.memory_root = Some(dir);
+        Ok(embedded)
+      },
+      Err(e) => {
+        vfs::unmount_memory(&dir);
+        Err(e)
+      },
+    }
+  }
+
+  pub async fn start_with_opts(opts: Opt) -> ServerResult<Embedded> {
+    opts.validate();
+    let server = Server::new(opts.clone());
+    if !opts.read_only {
+      server.recover()
+        .await
+        .with_context(|| "while recovering server state")?;
+    }
+
+    // The background loops borrow the server, so they're created inside
+    // the task that runs them, which reports back whether that worked.
+    let (init_sender, init_receiver) = oneshot::channel();
+    let background_server = server.clone();
+    let background = tokio::spawn(async move {
+      let backgrounds = match background_server.init().await {
+        Ok(backgrounds) => backgrounds,
+        Err(e) => {
+          let _ = init_sender.send(Err(e));
+          return;
+        }
+      };
+      let _ = init_sender.send(Ok(()));
+      futures::future::join3(backgrounds.0, backgrounds.1, backgrounds.2).await;
+    });
+    init_receiver.await
+      .map_err(|_| ServerError::internal("embedded server stopped while initializing"))?
+      .with_context(|| "while initializing background processes")?;
+    server.spawn_replication();
+    if opts.chaos {
+      chaos::enable();
+    }
+    log::info!("started embedded server in dir {:?}", opts.dir);
+
+    Ok(Embedded {
+      server,
+      background,
+      memory_root: None,
+    })
+  }
+
+  pub fn client(&self) -> EmbeddedClient {
+    EmbeddedClient {
+      server: self.server.clone(),
+      api_key: None,
+    }
+  }
+
+  /// Flushes staged rows now instead of on the flush loop's schedule.
+  pub async fn flush(&self) -> ServerResult<()> {
+    self.server.flush_now().await
+  }
+
+  /// Compacts the segments that are due for it now instead of on the
+  /// compaction loop's schedule. Whether a segment is due still depends on
+  /// flags like `--min-rows-for-compaction`.
+  pub async fn compact(&self) -> ServerResult<()> {
+    self.server.compact_now().await
+  }
+
+  /// Stops the background loops. Like a crash, this may interrupt a flush
+  /// or compaction, which is recovered the next time the dir is served.
+  pub async fn stop(self) {
+    self.server.stop().await;
+  }
+}
+
+impl Drop for Embedded {
+  fn drop(&mut self) {
+    self.background.abort();
+    if let Some(memory_root) = &self.memory_root {
+      vfs::unmount_memory(memory_root);
+    }
+  }
+}
+
+/// A client of an `Embedded` server, implementing the same `PancakeClient`
+/// trait as the network client.
+#[derive(Clone)]
+pub struct EmbeddedClient {
+  server: Server,
+  api_key: Option<String>,
+}
+
+fn client_result<T>(res: Result<Response<T>, Status>) -> ClientResult<T> {
+  res.map(Response::into_inner).map_err(ClientError::from)
+}
+
+impl EmbeddedClient {
+  /// Makes requests with this API key, for servers with an `--authz-file`.
+  pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+    self.api_key = Some(api_key.into());
+    self
+  }
+
+  fn request<T>(&self, req: T) -> ClientResult<Request<T>> {
+    let mut request = Request::new(req);
+    if let Some(api_key) = &self.api_key {
+      let value = MetadataValue::from_str(&format!("Bearer {}", api_key))
+        .map_err(|_| ClientError::other("API key is not valid metadata".to_string()))?;
+      request.metadata_mut().insert("authorization", value);
+    }
+    Ok(request)
+  }
+}
+
+#[async_trait]
+impl PancakeClient for EmbeddedClient {
+  async fn alter_table(&mut self, req: AlterTableRequest) -> ClientResult<AlterTableResponse> {
+    client_result(PancakeDb::alter_table(&self.server, self.request(req)?).await)
+  }
+
+  async fn create_table(&mut self, req: CreateTableRequest) -> ClientResult<CreateTableResponse> {
+    client_result(PancakeDb::create_table(&self.server, self.request(req)?).await)
+  }
+
+  async fn drop_table(&mut self, req: DropTableRequest) -> ClientResult<DropTableResponse> {
+    client_result(PancakeDb::drop_table(&self.server, self.request(req)?).await)
+  }
+
+  async fn get_schema(&mut self, req: GetSchemaRequest) -> ClientResult<GetSchemaResponse> {
+    client_result(PancakeDb::get_schema(&self.server, self.request(req)?).await)
+  }
+
+  async fn delete_from_segment(&mut self, req: DeleteFromSegmentRequest) -> ClientResult<DeleteFromSegmentResponse> {
+    client_result(PancakeDb::delete_from_segment(&self.server, self.request(req)?).await)
+  }
+
+  async fn list_tables(&mut self, req: ListTablesRequest) -> ClientResult<ListTablesResponse> {
+    client_result(PancakeDb::list_tables(&self.server, self.request(req)?).await)
+  }
+
+  async fn list_segments(&mut self, req: ListSegmentsRequest) -> ClientResult<ListSegmentsResponse> {
+    client_result(PancakeDb::list_segments(&self.server, self.request(req)?).await)
+  }
+
+  async fn read_segment_deletions(&mut self, req: ReadSegmentDeletionsRequest) -> ClientResult<ReadSegmentDeletionsResponse> {
+    client_result(PancakeDb::read_segment_deletions(&self.server, self.request(req)?).await)
+  }
+
+  async fn write_to_partition(&mut self, req: WriteToPartitionRequest) -> ClientResult<WriteToPartitionResponse> {
+    client_result(PancakeDb::write_to_partition(&self.server, self.request(req)?).await)
+  }
+
+  async fn read_segment_column(&mut self, req: ReadSegmentColumnRequest) -> ClientResult<ReadSegmentColumnStream> {
+    let stream = client_result(PancakeDb::read_segment_column(&self.server, self.request(req)?).await)?;
+    Ok(stream.map(|res| res.map_err(ClientError::from)).boxed())
+  }
+}
@@ -1,26 +1,56 @@
 use std::fmt;
 use std::fmt::{Display, Formatter, Debug};
 use std::io;
+use std::time::Duration;
 use chrono::format::ParseError;
+use hyper::body::Bytes;
 
 use warp::http::StatusCode;
+pub use pancake_db_client_ext::ErrorCode;
 use pancake_db_core::errors::{CoreError, CoreErrorKind};
 use tonic::{Code, Status};
+use tonic::metadata::{MetadataMap, MetadataValue};
+
+// at most this many of a write's invalid rows are described in its error
+// message
+const MAX_DESCRIBED_ROW_ERRORS: usize = 10;
 
 #[derive(Clone, Debug)]
 pub struct ServerError {
   message: String,
   contexts: Vec<String>,
   pub kind: ServerErrorKind,
+  // a more specific code than the kind's, if any
+  code: Option<ErrorCode>,
+  // for writes rejected because of invalid rows, why each was rejected
+  pub row_errors: Vec<RowError>,
+  // for requests rejected by rate or concurrency limits, how long clients
+  // should wait before retrying
+  pub retry_after: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RowError {
+  pub row_index: usize,
+  pub message: String,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum ServerErrorKind {
   Invalid, // 400
   DoesNotExist, // 404
+  ReadOnly, // 403
+  Unauthenticated, // 401
+  PermissionDenied, // 403
   TooManyRequests, // 429
+  TooLarge, // 413
+  QuotaExceeded, // 507
+  Cancelled, // 499
+  TimedOut, // 504
+  Aborted, // 409
   Internal, // 500
   Corrupt, // 500
+  ChecksumMismatch, // 500
 }
 
 impl ServerErrorKind {
@@ -28,9 +58,42 @@ impl ServerErrorKind {
     match &self {
       ServerErrorKind::Invalid => StatusCode::BAD_REQUEST,
       ServerErrorKind::DoesNotExist => StatusCode::NOT_FOUND,
+      ServerErrorKind::ReadOnly => StatusCode::FORBIDDEN,
+      ServerErrorKind::Unauthenticated => StatusCode::UNAUTHORIZED,
+      ServerErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
       ServerErrorKind::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+      ServerErrorKind::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+      ServerErrorKind::QuotaExceeded => StatusCode::INSUFFICIENT_STORAGE,
+      // client closed request, as nginx reports it
+      ServerErrorKind::Cancelled => StatusCode::from_u16(499).unwrap(),
+      ServerErrorKind::TimedOut => StatusCode::GATEWAY_TIMEOUT,
+      ServerErrorKind::Aborted => StatusCode::CONFLICT,
       ServerErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
       ServerErrorKind::Corrupt => StatusCode::INTERNAL_SERVER_ERROR,
+      ServerErrorKind::ChecksumMismatch => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+}
+
+// Codes are defined in the client crate so clients parse exactly the
+// codes the server sends.
+impl From<ServerErrorKind> for ErrorCode {
+  fn from(kind: ServerErrorKind) -> Self {
+    match kind {
+      ServerErrorKind::Invalid => ErrorCode::Invalid,
+      ServerErrorKind::DoesNotExist => ErrorCode::DoesNotExist,
+      ServerErrorKind::ReadOnly => ErrorCode::ReadOnly,
+      ServerErrorKind::Unauthenticated => ErrorCode::Unauthenticated,
+      ServerErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+      ServerErrorKind::TooManyRequests => ErrorCode::TooManyRequests,
+      ServerErrorKind::TooLarge => ErrorCode::TooLarge,
+      ServerErrorKind::QuotaExceeded => ErrorCode::QuotaExceeded,
+      ServerErrorKind::Cancelled => ErrorCode::Cancelled,
+      ServerErrorKind::TimedOut => ErrorCode::TimedOut,
+      ServerErrorKind::Aborted => ErrorCode::Aborted,
+      ServerErrorKind::Internal => ErrorCode::Internal,
+      ServerErrorKind::Corrupt => ErrorCode::Corrupt,
+      ServerErrorKind::ChecksumMismatch => ErrorCode::ChecksumMismatch,
     }
   }
 }
@@ -40,9 +103,18 @@ impl Display for ServerErrorKind {
     let string = match self {
       ServerErrorKind::Invalid => "invalid request",
       ServerErrorKind::DoesNotExist => "missing",
+      ServerErrorKind::ReadOnly => "read-only",
+      ServerErrorKind::Unauthenticated => "unauthenticated",
+      ServerErrorKind::PermissionDenied => "permission denied",
       ServerErrorKind::TooManyRequests => "too many requests",
+      ServerErrorKind::TooLarge => "request too large",
+      ServerErrorKind::QuotaExceeded => "quota exceeded",
+      ServerErrorKind::Cancelled => "cancelled",
+      ServerErrorKind::TimedOut => "timed out",
+      ServerErrorKind::Aborted => "aborted",
       ServerErrorKind::Internal => "internal error",
       ServerErrorKind::Corrupt => "corrupt internal data",
+      ServerErrorKind::ChecksumMismatch => "checksum mismatch",
     };
     write!(f, "{}", string)
   }
@@ -54,14 +126,24 @@ impl ServerError {
       message: message.as_ref().to_string(),
       contexts: Vec::new(),
       kind,
+      code: None,
+      row_errors: Vec::new(),
+      retry_after: None,
     }
   }
 
   pub fn does_not_exist(entity_name: &'static str, value: &impl Display) -> ServerError {
-    ServerError::new(
+    let res = ServerError::new(
       format!("{} with name {} does not exist", entity_name, value),
       ServerErrorKind::DoesNotExist,
-    )
+    );
+    match entity_name {
+      "table" | "dropped table" => res.with_code(ErrorCode::TableNotFound),
+      "column" => res.with_code(ErrorCode::ColumnNotFound),
+      "partition" | "partition segments file" => res.with_code(ErrorCode::PartitionNotFound),
+      "segment" => res.with_code(ErrorCode::SegmentNotFound),
+      _ => res,
+    }
   }
 
   pub fn invalid(explanation: impl AsRef<str>) -> ServerError {
@@ -71,6 +153,90 @@ impl ServerError {
     )
   }
 
+  // row errors must be in row order
+  pub fn invalid_rows(row_errors: Vec<RowError>, n_rows: usize) -> ServerError {
+    let mut descriptions: Vec<String> = row_errors.iter()
+      .take(MAX_DESCRIBED_ROW_ERRORS)
+      .map(|row_error| format!("row {}: {}", row_error.row_index, row_error.message))
+      .collect();
+    if row_errors.len() > MAX_DESCRIBED_ROW_ERRORS {
+      descriptions.push(format!("and {} more", row_errors.len() - MAX_DESCRIBED_ROW_ERRORS));
+    }
+    let mut res = ServerError::invalid(format!(
+      "{} of {} rows are invalid; {}",
+      row_errors.len(),
+      n_rows,
+      descriptions.join("; "),
+    ));
+    res.row_errors = row_errors;
+    res.with_code(ErrorCode::InvalidRows)
+  }
+
+  pub fn read_only(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::ReadOnly
+    )
+  }
+
+  pub fn unauthenticated(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::Unauthenticated
+    )
+  }
+
+  pub fn permission_denied(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::PermissionDenied
+    )
+  }
+
+  pub fn too_many_requests(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::TooManyRequests
+    )
+  }
+
+  pub fn too_large(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::TooLarge
+    )
+  }
+
+  pub fn quota_exceeded(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::QuotaExceeded
+    )
+  }
+
+  pub fn cancelled(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::Cancelled
+    )
+  }
+
+  pub fn timed_out(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::TimedOut
+    )
+  }
+
+  // The state a multi-page read started from changed between its pages,
+  // so the client must start the read over.
+  pub fn restart_read(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::Aborted
+    ).with_code(ErrorCode::RestartRead)
+  }
+
   pub fn internal(explanation: impl AsRef<str>) -> ServerError {
     ServerError::new(
       explanation,
@@ -85,6 +251,21 @@ impl ServerError {
     )
   }
 
+  pub fn checksum_mismatch(explanation: impl AsRef<str>) -> ServerError {
+    ServerError::new(
+      explanation,
+      ServerErrorKind::ChecksumMismatch
+    )
+  }
+
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+
+  pub fn code(&self) -> ErrorCode {
+    self.code.unwrap_or_else(|| ErrorCode::from(self.kind))
+  }
+
   pub fn to_client_string(&self) -> String {
     // we want to obscure internal errors for security or something
     match self.kind {
@@ -101,6 +282,24 @@ impl ServerError {
     self.add_context(context);
     self
   }
+
+  // only for codes more specific than the error's kind
+  pub fn with_code(mut self, code: ErrorCode) -> Self {
+    self.code = Some(code);
+    self
+  }
+
+  pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+    self.retry_after = Some(retry_after);
+    self
+  }
+
+  // rounded up, since clients retrying early would just be rejected again
+  pub fn retry_after_seconds(&self) -> Option<u64> {
+    self.retry_after.map(|retry_after| {
+      retry_after.as_secs() + if retry_after.subsec_nanos() > 0 { 1 } else { 0 }
+    })
+  }
 }
 
 impl Display for ServerError {
@@ -116,6 +315,8 @@ impl Display for ServerError {
   }
 }
 
+impl std::error::Error for ServerError {}
+
 pub trait ServerUpcastableError: Display {
   fn kind(&self) -> ServerErrorKind;
 }
@@ -126,6 +327,9 @@ impl<E> From<E> for ServerError where E: ServerUpcastableError {
       message: error.to_string(),
       contexts: Vec::new(),
       kind: error.kind(),
+      code: None,
+      row_errors: Vec::new(),
+      retry_after: None,
     }
   }
 }
@@ -200,10 +404,32 @@ impl From<ServerError> for Status {
     let code = match err.kind {
       ServerErrorKind::Invalid => Code::InvalidArgument,
       ServerErrorKind::DoesNotExist => Code::NotFound,
-      ServerErrorKind::TooManyRequests => Code::Unavailable,
+      ServerErrorKind::ReadOnly => Code::FailedPrecondition,
+      ServerErrorKind::Unauthenticated => Code::Unauthenticated,
+      ServerErrorKind::PermissionDenied => Code::PermissionDenied,
+      ServerErrorKind::TooManyRequests => Code::ResourceExhausted,
+      ServerErrorKind::TooLarge => Code::ResourceExhausted,
+      ServerErrorKind::QuotaExceeded => Code::ResourceExhausted,
+      ServerErrorKind::Cancelled => Code::Cancelled,
+      ServerErrorKind::TimedOut => Code::DeadlineExceeded,
+      ServerErrorKind::Aborted => Code::Aborted,
       ServerErrorKind::Corrupt => Code::Internal,
+      ServerErrorKind::ChecksumMismatch => Code::DataLoss,
       ServerErrorKind::Internal => Code::Internal,
     };
-    Status::new(code, err.message)
+    // The error code goes in the status details and metadata, and also
+    // prefixes the message, since some clients only keep the message.
+    let error_code = err.code().as_str();
+    let mut metadata = MetadataMap::new();
+    metadata.insert("pancake-error-code", MetadataValue::from_static(error_code));
+    if let Some(retry_after_seconds) = err.retry_after_seconds() {
+      metadata.insert("retry-after", MetadataValue::from(retry_after_seconds));
+    }
+    Status::with_details_and_metadata(
+      code,
+      format!("[{}] {}", error_code, err.message),
+      Bytes::from_static(error_code.as_bytes()),
+      metadata,
+    )
   }
 }
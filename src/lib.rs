@@ -0,0 +1,153 @@
+#![recursion_limit = "256"]
+#![allow(clippy::new_without_default)]
+#![allow(clippy::needless_range_loop)]
+
+//! The PancakeDB server. Besides the `pancake-db-server` binary, it can run
+//! inside another Rust process with [`Embedded`].
+
+use std::net::{SocketAddr, TcpListener};
+
+use futures::FutureExt;
+use pancake_db_idl::service::pancake_db_server::PancakeDbServer;
+use tower::ServiceBuilder;
+use tower_http::add_extension::AddExtensionLayer;
+use warp::{Filter, Reply};
+
+use crate::errors::Contextable;
+use crate::logging::Logger;
+use crate::server::Server;
+
+pub use crate::embedded::{Embedded, EmbeddedClient};
+pub use crate::errors::{ServerError, ServerResult};
+pub use crate::opt::Opt;
+// for the fuzz targets in fuzz/
+#[doc(hidden)]
+pub use crate::utils::safe_decoding;
+
+mod logging;
+mod opt;
+mod server;
+mod types;
+mod utils;
+mod constants;
+mod errors;
+mod ops;
+mod metadata;
+mod locks;
+mod serde_models;
+mod pgwire;
+mod embedded;
+mod listener;
+
+static LOGGER: Logger = Logger::new();
+
+pub fn init_logging(level: log::LevelFilter) {
+  log::set_max_level(level);
+  log::set_logger(&LOGGER)
+    .expect("unable to initialize logging");
+}
+
+// Runs the server with its HTTP, GRPC, and optional Postgres listeners
+// until one of them fails.
+pub async fn serve(opts: Opt) -> ServerResult<()> {
+  let server = Server::new(opts.clone());
+  if opts.upgrade_format_dry_run {
+    return server.plan_format_upgrade()
+      .await
+      .with_context(|| "while planning format upgrade");
+  }
+  if opts.read_only {
+    log::info!("serving {:?} as a read-only replica", opts.dir);
+  } else {
+    server.recover()
+      .await
+      .with_context(|| "while recovering server state")?;
+  }
+
+  let backgrounds = server.init()
+    .await
+    .with_context(|| "while initializing background processes")?;
+  log::info!("initialized server background processes in dir {:?}", opts.dir);
+  if opts.chaos {
+    utils::chaos::enable();
+  }
+
+  let filter = utils::rest::warp_filter()
+    .or(utils::console::warp_filter());
+  let filter = match utils::rest::warp_cors(&opts.cors_allowed_origins) {
+    Some(cors) => filter.with(cors).map(|reply| Box::new(reply) as Box<dyn Reply>).boxed(),
+    None => filter.map(|reply| Box::new(reply) as Box<dyn Reply>).boxed(),
+  };
+  let warp_service = warp::service(filter);
+  let tower_service = ServiceBuilder::new()
+    .layer(AddExtensionLayer::new(server.clone()))
+    .service(warp_service);
+  let grpc_web_config = utils::rest::grpc_web_config(&opts.cors_allowed_origins);
+  let grpc_service = || tonic::transport::Server::builder()
+    .add_service(grpc_web_config.enable(PancakeDbServer::new(server.clone())));
+
+  let listeners_future = match opts.port {
+    Some(port) => {
+      let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+        .expect("port busy");
+      let listener_future = listener::serve(
+        listener,
+        tower_service,
+        grpc_service().into_service(),
+        true,
+      );
+      log::info!("bound HTTP and GRPC listener to port {}", port);
+      async move {
+        listener_future.await.expect("HTTP and GRPC server crashed");
+      }.boxed()
+    },
+    None => {
+      let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], opts.http_port)))
+        .expect("port busy");
+      let hyper_future = listener::serve(
+        listener,
+        tower_service,
+        grpc_service().into_service(),
+        false,
+      );
+      log::info!("bound HTTP listener to port {}", opts.http_port);
+
+      let tonic_future = grpc_service().serve(SocketAddr::from(([0, 0, 0, 0], opts.grpc_port)));
+      log::info!("bound GRPC listener to port {}", opts.grpc_port);
+      async move {
+        let (hyper_res, tonic_res) = futures::future::join(hyper_future, tonic_future).await;
+        hyper_res.expect("HTTP server crashed");
+        tonic_res.expect("GRPC server crashed");
+      }.boxed()
+    },
+  };
+
+  if let Some(pg_port) = opts.pg_port {
+    let pg_server = server.clone();
+    tokio::spawn(async move {
+      if let Err(e) = pgwire::serve(pg_server, pg_port).await {
+        log::error!("Postgres listener failed: {}", e);
+      }
+    });
+  }
+
+  let hangup_server = server.clone();
+  tokio::spawn(async move {
+    if let Err(e) = hangup_server.reload_config_on_hangup().await {
+      log::error!("{}", e);
+    }
+  });
+
+  server.spawn_replication();
+
+  log::info!("ready to serve requests");
+
+  futures::future::join4(
+    listeners_future,
+    backgrounds.0,
+    backgrounds.1,
+    backgrounds.2,
+  )
+    .await;
+  Ok(())
+}
@@ -0,0 +1,66 @@
+use std::convert::Infallible;
+use std::net::TcpListener;
+
+use futures::future::{self, FutureExt, TryFutureExt};
+use hyper::{Body, Request, Response, Server as HyperServer};
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use tonic::body::BoxBody;
+use tonic::Status;
+use tower::{Service, ServiceExt};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// GRPC requires every request's content type to start with this, and
+// grpc-web with the latter
+const GRPC_CONTENT_TYPE: &[u8] = b"application/grpc";
+const GRPC_WEB_CONTENT_TYPE: &[u8] = b"application/grpc-web";
+
+fn content_type_starts_with(req: &Request<Body>, prefix: &[u8]) -> bool {
+  req.headers()
+    .get(CONTENT_TYPE)
+    .map(|content_type| content_type.as_bytes().starts_with(prefix))
+    .unwrap_or(false)
+}
+
+// Serves HTTP and grpc-web on a port, and GRPC too if serve_grpc, for
+// containers that would rather expose just one port. Hyper speaks HTTP/1
+// and HTTP/2 alike, so each request goes to the GRPC service if it's GRPC
+// or grpc-web and to the HTTP service otherwise. The GRPC service
+// translates grpc-web itself, while grpc-web CORS preflights are answered
+// by the HTTP service.
+pub async fn serve<H, G>(
+  listener: TcpListener,
+  http_service: H,
+  grpc_service: G,
+  serve_grpc: bool,
+) -> hyper::Result<()>
+where
+  H: Service<Request<Body>, Response=Response<Body>, Error=Infallible> + Clone + Send + 'static,
+  H::Future: Send + 'static,
+  G: Service<Request<Body>, Response=Response<BoxBody>, Error=BoxError> + Clone + Send + Sync + 'static,
+  G::Future: Send + 'static,
+{
+  let make_service = make_service_fn(move |_| {
+    let http_service = http_service.clone();
+    let grpc_service = grpc_service.clone();
+    future::ok::<_, Infallible>(service_fn(move |req| {
+      let is_grpc_web = content_type_starts_with(&req, GRPC_WEB_CONTENT_TYPE);
+      if is_grpc_web || (serve_grpc && content_type_starts_with(&req, GRPC_CONTENT_TYPE)) {
+        grpc_service.clone().oneshot(req).boxed()
+      } else {
+        http_service.clone()
+          .oneshot(req)
+          .map_ok(|resp| resp.map(|body| {
+            body.map_err(|e| Status::internal(e.to_string())).boxed_unsync()
+          }))
+          .map_err(|e: Infallible| -> BoxError { match e {} })
+          .boxed()
+      }
+    }))
+  });
+  HyperServer::from_tcp(listener)?
+    .serve(make_service)
+    .await
+}
@@ -5,6 +5,8 @@ use crate::errors::{ServerResult, ServerError};
 use crate::locks::traits::ServerOpLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
+use crate::server::slow_ops;
+use crate::server::system_tables;
 use crate::metadata::deletion::DeletionMetadata;
 use crate::metadata::table::TableMetadata;
 use crate::types::SegmentKey;
@@ -32,16 +34,18 @@ impl ServerOpLocks for DeletionWriteLocks {
     server: &Server,
     op: &Op,
   ) -> ServerResult<Op::Response> {
+    server.check_writable()?;
     let key: SegmentKey = op.get_key()?;
     let table_name = &key.table_name;
     let table_lock = server.table_metadata_cache.get_lock(table_name).await?;
-    let table_guard = table_lock.read().await;
+    let table_guard = slow_ops::wait_for_lock("table", table_lock.read()).await?;
     let table_meta = common::unwrap_metadata(table_name, &*table_guard)?;
+    table_meta.check_mutable(table_name)?;
 
     key.partition.check_against_schema(&table_meta.schema())?;
 
     let deletion_lock = server.deletion_metadata_cache.get_lock(&key).await?;
-    let deletion_guard = deletion_lock.write_owned().await;
+    let deletion_guard = slow_ops::wait_for_lock("deletion", deletion_lock.write_owned()).await?;
 
     let locks = DeletionWriteLocks {
       table_meta,
@@ -64,17 +68,24 @@ impl ServerOpLocks for DeletionReadLocks {
     let key: SegmentKey = op.get_key()?;
     let table_name = &key.table_name;
     let table_lock = server.table_metadata_cache.get_lock(table_name).await?;
-    let table_guard = table_lock.read().await;
-    let table_meta = common::unwrap_metadata(table_name, &*table_guard)?;
+    let table_guard = slow_ops::wait_for_lock("table", table_lock.read()).await?;
+    let mut maybe_table = table_guard.clone();
+    if maybe_table.is_none() && op.reads_system_tables() {
+      maybe_table = system_tables::table_meta(table_name);
+    }
+    let table_meta = common::unwrap_metadata(table_name, &maybe_table)?;
 
     key.partition.check_against_schema(&table_meta.schema())?;
 
     let deletion_lock = server.deletion_metadata_cache.get_lock(&key).await?;
-    let deletion_guard = deletion_lock.read().await;
+    let deletion_guard = slow_ops::wait_for_lock("deletion", deletion_lock.read()).await?;
 
     let segment_meta_lock = server.segment_metadata_cache.get_lock(&key).await?;
-    let segment_guard = segment_meta_lock.read().await;
-    let maybe_segment_meta = segment_guard.clone();
+    let segment_guard = slow_ops::wait_for_lock("segment", segment_meta_lock.read()).await?;
+    let mut maybe_segment_meta = segment_guard.clone();
+    if maybe_segment_meta.is_none() && op.reads_system_tables() {
+      maybe_segment_meta = system_tables::segment_meta(&key);
+    }
 
     if maybe_segment_meta.is_none() {
       return Err(ServerError::does_not_exist("segment", &key.segment_id.to_string()));
@@ -1,27 +1,32 @@
 use async_trait::async_trait;
+use rand::Rng;
 use tokio::sync::OwnedRwLockWriteGuard;
 use crate::Contextable;
 
-use crate::errors::ServerResult;
+use crate::errors::{ServerError, ServerResult};
 use crate::locks::table::GlobalTableReadLocks;
 use crate::locks::traits::ServerOpLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
+use crate::server::slow_ops;
 use crate::metadata::global::GlobalMetadata;
+use crate::metadata::manifest::MetadataBatch;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::partition::PartitionMetadata;
 use crate::metadata::segment::SegmentMetadata;
 use crate::metadata::table::TableMetadata;
 use crate::types::{PartitionKey, SegmentKey, ShardId};
-use crate::utils::{common, navigation};
+use crate::utils::navigation;
 use crate::utils::dirs;
+use crate::utils::vfs;
 
+// Locks for writing to one of a partition's active segments. The partition
+// write lock is only held while choosing the segment, so writers to
+// different active segments of the same partition proceed concurrently.
 pub struct PartitionWriteLocks {
   pub global_meta: GlobalMetadata,
   pub table_meta: TableMetadata,
-  pub definitely_partition_guard: OwnedRwLockWriteGuard<Option<PartitionMetadata>>,
   pub definitely_segment_guard: OwnedRwLockWriteGuard<Option<SegmentMetadata>>,
-  pub shard_id: ShardId,
   pub segment_key: SegmentKey,
 }
 
@@ -48,10 +53,13 @@ impl PartitionWriteLocks {
     key: &PartitionKey,
     server: &Server,
   ) -> ServerResult<Self> {
+    server.check_writable()?;
+    server.check_disk_quota(&key.table_name).await?;
     let GlobalTableReadLocks { global_meta, table_meta } = table_read_locks;
     let dir = &server.opts.dir;
 
     key.partition.check_against_schema(&table_meta.schema())?;
+    table_meta.check_mutable(&key.table_name)?;
 
     // partition lock
     // Ideally we'd check the read lock, return it if Some, and otherwise acquire drop that read
@@ -59,57 +67,127 @@ impl PartitionWriteLocks {
     // But for now just get a write lock for simplicity at the cost of some contention.
     let partition_lock = server.partition_metadata_cache.get_lock(&key)
       .await?;
-    let mut partition_guard = partition_lock.write_owned().await;
-    let partition_meta = match &mut *partition_guard {
-      Some(meta) => meta,
-      None => {
-        let partition_meta = PartitionMetadata::new(global_meta.n_shards_log);
-        common::create_if_new(&dirs::partition_dir(dir, &key)).await?;
-        partition_meta.overwrite(dir, &key).await?;
-        *partition_guard = Some(partition_meta.clone());
-        partition_guard.as_mut().unwrap()
-      },
+    let (mut partition_guard, shard_id, mut maybe_segment) = loop {
+      let mut partition_guard = slow_ops::wait_for_lock("partition", partition_lock.write()).await?;
+      let partition_meta = match &mut *partition_guard {
+        Some(meta) => meta,
+        None => {
+          let partition_meta = PartitionMetadata::new(global_meta.n_shards_log);
+          // the dirs of the partition's other fields and buckets may not exist yet
+          let partition_dir = dirs::partition_dir(dir, &key);
+          vfs::create_dir_all(&partition_dir).await
+            .map_err(|e| ServerError::from(e).with_context(format!(
+              "while creating directory {:?}",
+              partition_dir,
+            )))?;
+          partition_meta.overwrite(dir, &key).await?;
+          *partition_guard = Some(partition_meta.clone());
+          partition_guard.as_mut().unwrap()
+        },
+      };
+
+      let shard_id = ShardId::randomly_select(
+        global_meta.n_shards_log,
+        1, // TODO use table_meta
+        &key,
+        partition_meta.sharding_denominator_log,
+      );
+      let max_active_segments = server.runtime_config().await.active_segments_per_partition.max(1);
+      let active_segment_ids = partition_meta.active_segment_ids_in_shard(&shard_id);
+
+      // segment lock
+      // Prefer an active segment no other writer holds, then a new segment if
+      // the partition has room for one, and only then wait for a busy one.
+      let n_active = active_segment_ids.len();
+      let offset = if n_active > 0 { rand::thread_rng().gen_range(0..n_active) } else { 0 };
+      let mut maybe_segment = None;
+      for i in 0..n_active {
+        let segment_id = active_segment_ids[(offset + i) % n_active];
+        let segment_lock = server.segment_metadata_cache.get_lock(&key.segment_key(segment_id)).await?;
+        if let Ok(segment_guard) = segment_lock.try_write_owned() {
+          maybe_segment = Some((segment_id, segment_guard));
+          break;
+        }
+      }
+      if maybe_segment.is_some() || n_active < max_active_segments as usize {
+        break (partition_guard, shard_id, maybe_segment);
+      }
+
+      // Waiting with the partition locked would block every other writer to
+      // the partition, even those that would find a free segment. So we wait
+      // unlocked and choose again, since the active segments may have
+      // changed meanwhile.
+      let segment_lock = server.segment_metadata_cache.get_lock(&key.segment_key(active_segment_ids[offset])).await?;
+      drop(partition_guard);
+      drop(slow_ops::wait_for_lock("segment", segment_lock.write_owned()).await?);
     };
+    let partition_meta = partition_guard.as_mut().unwrap();
+
+    // writes never go to cold segments, so a cold one is replaced
+    let mut replaced_segment_id = None;
+    if let Some((segment_id, segment_guard)) = &maybe_segment {
+      if segment_guard.as_ref().map(|meta| meta.is_cold).unwrap_or(false) {
+        replaced_segment_id = Some(*segment_id);
+        maybe_segment = None;
+      }
+    }
 
-    let shard_id = ShardId::randomly_select(
-      global_meta.n_shards_log,
-      1, // TODO use table_meta
-      &key,
-      partition_meta.sharding_denominator_log,
-    );
-    let maybe_active_segment_id = partition_meta.get_active_segment_id(&shard_id);
-
-    // segment lock
-    let segment_id = match maybe_active_segment_id {
-      Some(id) => id,
+    let (segment_id, mut segment_guard, is_new_segment) = match maybe_segment {
+      Some((segment_id, segment_guard)) => (segment_id, segment_guard, false),
       None => {
         let segment_id = shard_id.generate_segment_id();
-        partition_meta.active_segment_ids.push(segment_id);
-        partition_meta.overwrite(dir, &key).await?;
-        segment_id
-      }
+        let segment_lock = server.segment_metadata_cache.get_lock(&key.segment_key(segment_id)).await?;
+        (segment_id, slow_ops::wait_for_lock("segment", segment_lock.write_owned()).await?, true)
+      },
     };
     let segment_key = key.segment_key(segment_id);
 
-    let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
-    let mut segment_guard = segment_lock.write_owned().await;
-    let maybe_segment_meta = &mut *segment_guard;
-    if maybe_segment_meta.is_none() {
+    if is_new_segment || segment_guard.is_none() {
       navigation::create_segment_dirs(&dirs::segment_dir(&server.opts.dir, &segment_key)).await
         .with_context(|| format!(
           "while creating new segment dirs for {}",
           segment_key,
         ))?;
       let segment_meta = SegmentMetadata::new_from_schema(&table_meta.schema());
-      *maybe_segment_meta = Some(segment_meta);
+
+      // the partition must never refer to a segment without metadata, and
+      // flushes must know whether to encrypt the first version
+      let mut batch = MetadataBatch::default();
+      let maybe_compaction = server.initial_compaction();
+      let compaction_key = segment_key.compaction_key(0);
+      if let Some(compaction) = &maybe_compaction {
+        batch.add(compaction, &compaction_key)?;
+      }
+      batch.add(&segment_meta, &segment_key)?;
+      if is_new_segment {
+        match replaced_segment_id {
+          Some(old_id) => partition_meta.replace_active_segment_id(old_id, segment_id),
+          None => partition_meta.active_segment_ids.push(segment_id),
+        }
+        batch.add(partition_meta, key)?;
+      }
+      let index_lock = server.partition_index_cache.get_lock(&key.table_name).await?;
+      let mut index_guard = index_lock.write().await;
+      let mut index = index_guard.clone().unwrap_or_default();
+      let is_new_to_index = index.add_segment(&key.partition, segment_id);
+      if is_new_to_index {
+        batch.add(&index, &key.table_name)?;
+      }
+      batch.commit(server).await?;
+      if is_new_to_index {
+        *index_guard = Some(index);
+      }
+      drop(index_guard);
+      if maybe_compaction.is_some() {
+        *server.compaction_cache.get_lock(&compaction_key).await?.write().await = maybe_compaction;
+      }
+      *segment_guard = Some(segment_meta);
     }
 
     Ok(PartitionWriteLocks {
       global_meta,
       table_meta,
-      definitely_partition_guard: partition_guard,
       definitely_segment_guard: segment_guard,
-      shard_id,
       segment_key,
     })
   }
@@ -1,5 +1,4 @@
 use async_trait::async_trait;
-use tokio::sync::OwnedRwLockWriteGuard;
 
 use crate::errors::{ServerError, ServerResult};
 use crate::locks::traits::ServerOpLocks;
@@ -7,58 +6,16 @@ use crate::metadata::segment::SegmentMetadata;
 use crate::metadata::table::TableMetadata;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
+use crate::server::slow_ops;
+use crate::server::system_tables;
 use crate::types::SegmentKey;
 
-pub struct SegmentWriteLocks {
-  pub table_meta: TableMetadata,
-  pub definitely_segment_guard: OwnedRwLockWriteGuard<Option<SegmentMetadata>>,
-  pub segment_key: SegmentKey,
-}
-
 pub struct SegmentReadLocks {
   pub table_meta: TableMetadata,
   pub segment_meta: SegmentMetadata,
   pub segment_key: SegmentKey,
 }
 
-#[async_trait]
-impl ServerOpLocks for SegmentWriteLocks {
-  type Key = SegmentKey;
-
-  async fn execute<Op: ServerOp<Locks=Self>>(
-    server: &Server,
-    op: &Op,
-  ) -> ServerResult<Op::Response> {
-    let key: SegmentKey = op.get_key()?;
-    let table_name = &key.table_name;
-    let table_lock = server.table_metadata_cache.get_lock(table_name).await?;
-    let table_guard = table_lock.read().await;
-    let maybe_table = table_guard.clone();
-    if maybe_table.is_none() {
-      return Err(ServerError::does_not_exist("table", table_name));
-    }
-
-    let table_meta = maybe_table.unwrap();
-    let schema = table_meta.schema();
-    key.partition.check_against_schema(&schema)?;
-
-    let segment_meta_lock = server.segment_metadata_cache.get_lock(&key).await?;
-    let mut segment_guard = segment_meta_lock.write_owned().await;
-    let maybe_segment_meta = &mut *segment_guard;
-    if maybe_segment_meta.is_none() {
-      *maybe_segment_meta = Some(SegmentMetadata::new_from_schema(&schema));
-    }
-
-    let locks = SegmentWriteLocks {
-      table_meta,
-      definitely_segment_guard: segment_guard,
-      segment_key: key,
-    };
-
-    op.execute_with_locks(server, locks).await
-  }
-}
-
 #[async_trait]
 impl ServerOpLocks for SegmentReadLocks {
   type Key = SegmentKey;
@@ -70,8 +27,11 @@ impl ServerOpLocks for SegmentReadLocks {
     let key: SegmentKey = op.get_key()?;
     let table_name = &key.table_name;
     let table_lock = server.table_metadata_cache.get_lock(table_name).await?;
-    let table_guard = table_lock.read().await;
-    let maybe_table = table_guard.clone();
+    let table_guard = slow_ops::wait_for_lock("table", table_lock.read()).await?;
+    let mut maybe_table = table_guard.clone();
+    if maybe_table.is_none() && op.reads_system_tables() {
+      maybe_table = system_tables::table_meta(table_name);
+    }
     if maybe_table.is_none() {
       return Err(ServerError::does_not_exist("table", table_name));
     }
@@ -80,8 +40,11 @@ impl ServerOpLocks for SegmentReadLocks {
     key.partition.check_against_schema(&table_meta.schema())?;
 
     let segment_meta_lock = server.segment_metadata_cache.get_lock(&key).await?;
-    let segment_guard = segment_meta_lock.read().await;
-    let maybe_segment_meta = segment_guard.clone();
+    let segment_guard = slow_ops::wait_for_lock("segment", segment_meta_lock.read()).await?;
+    let mut maybe_segment_meta = segment_guard.clone();
+    if maybe_segment_meta.is_none() && op.reads_system_tables() {
+      maybe_segment_meta = system_tables::segment_meta(&key);
+    }
 
     if maybe_segment_meta.is_none() {
       return Err(ServerError::does_not_exist("segment", &key.segment_id.to_string()));
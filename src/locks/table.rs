@@ -5,6 +5,8 @@ use crate::errors::{ServerError, ServerResult};
 use crate::locks::traits::ServerOpLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
+use crate::server::slow_ops;
+use crate::server::system_tables;
 use crate::metadata::table::TableMetadata;
 use crate::metadata::global::GlobalMetadata;
 
@@ -21,6 +23,13 @@ pub struct TableWriteLocks {
   pub maybe_table_guard: OwnedRwLockWriteGuard<Option<TableMetadata>>,
 }
 
+// Write locks on a source and destination table, obtained in name order so
+// that ops on the same two tables can't deadlock.
+pub struct TablePairWriteLocks {
+  pub maybe_src_table_guard: OwnedRwLockWriteGuard<Option<TableMetadata>>,
+  pub maybe_dst_table_guard: OwnedRwLockWriteGuard<Option<TableMetadata>>,
+}
+
 #[async_trait]
 impl ServerOpLocks for GlobalTableReadLocks {
   type Key = String;
@@ -29,18 +38,25 @@ impl ServerOpLocks for GlobalTableReadLocks {
     server: &Server,
     op: &Op,
   ) -> ServerResult<Op::Response> where Self: Sized {
-    let locks = GlobalTableReadLocks::obtain(server, &op.get_key()?).await?;
+    let locks = GlobalTableReadLocks::obtain_for(server, &op.get_key()?, op.reads_system_tables()).await?;
     op.execute_with_locks(server, locks).await
   }
 }
 
 impl GlobalTableReadLocks {
   pub async fn obtain(server: &Server, key: &String) -> ServerResult<Self> {
-    let global_guard = server.global_metadata_lock.read().await;
+    Self::obtain_for(server, key, false).await
+  }
+
+  async fn obtain_for(server: &Server, key: &String, reads_system_tables: bool) -> ServerResult<Self> {
+    let global_guard = slow_ops::wait_for_lock("global", server.global_metadata_lock.read()).await?;
 
     let lock = server.table_metadata_cache.get_lock(key).await?;
-    let guard = lock.read().await;
-    let maybe_table = guard.clone();
+    let guard = slow_ops::wait_for_lock("table", lock.read()).await?;
+    let mut maybe_table = guard.clone();
+    if maybe_table.is_none() && reads_system_tables {
+      maybe_table = system_tables::table_meta(key);
+    }
     if maybe_table.is_none() {
       return Err(ServerError::does_not_exist("table", key))
     }
@@ -62,8 +78,11 @@ impl ServerOpLocks for TableReadLocks {
   ) -> ServerResult<Op::Response> where Self: Sized {
     let table_name = op.get_key()?;
     let lock = server.table_metadata_cache.get_lock(&table_name).await?;
-    let guard = lock.read().await;
-    let maybe_table = guard.clone();
+    let guard = slow_ops::wait_for_lock("table", lock.read()).await?;
+    let mut maybe_table = guard.clone();
+    if maybe_table.is_none() && op.reads_system_tables() {
+      maybe_table = system_tables::table_meta(&table_name);
+    }
     if maybe_table.is_none() {
       return Err(ServerError::does_not_exist("table", &table_name))
     }
@@ -83,12 +102,44 @@ impl ServerOpLocks for TableWriteLocks {
     server: &Server,
     op: &Op,
   ) -> ServerResult<Op::Response> {
+    server.check_writable()?;
     let table_name = op.get_key()?;
     let lock = server.table_metadata_cache.get_lock(&table_name).await?;
-    let guard = lock.write_owned().await;
+    let guard = slow_ops::wait_for_lock("table", lock.write_owned()).await?;
     let locks = TableWriteLocks {
       maybe_table_guard: guard,
     };
     op.execute_with_locks(server, locks).await
   }
 }
+
+#[async_trait]
+impl ServerOpLocks for TablePairWriteLocks {
+  // source and destination table names
+  type Key = (String, String);
+
+  async fn execute<Op: ServerOp<Locks=Self>>(
+    server: &Server,
+    op: &Op,
+  ) -> ServerResult<Op::Response> {
+    server.check_writable()?;
+    let (src_table_name, dst_table_name) = op.get_key()?;
+    if src_table_name == dst_table_name {
+      return Err(ServerError::invalid("source and destination tables must differ"));
+    }
+    let src_lock = server.table_metadata_cache.get_lock(&src_table_name).await?;
+    let dst_lock = server.table_metadata_cache.get_lock(&dst_table_name).await?;
+    let (maybe_src_table_guard, maybe_dst_table_guard) = if src_table_name < dst_table_name {
+      let src_guard = slow_ops::wait_for_lock("table", src_lock.write_owned()).await?;
+      (src_guard, slow_ops::wait_for_lock("table", dst_lock.write_owned()).await?)
+    } else {
+      let dst_guard = slow_ops::wait_for_lock("table", dst_lock.write_owned()).await?;
+      (slow_ops::wait_for_lock("table", src_lock.write_owned()).await?, dst_guard)
+    };
+    let locks = TablePairWriteLocks {
+      maybe_src_table_guard,
+      maybe_dst_table_guard,
+    };
+    op.execute_with_locks(server, locks).await
+  }
+}
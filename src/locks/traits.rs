@@ -1,3 +1,5 @@
+use std::fmt::Debug;
+
 use async_trait::async_trait;
 
 use crate::errors::ServerResult;
@@ -7,7 +9,7 @@ use crate::server::Server;
 
 #[async_trait]
 pub trait ServerOpLocks: Send {
-  type Key;
+  type Key: Debug;
 
   async fn execute<Op: ServerOp<Locks=Self>>(
     server: &Server,
@@ -1,7 +1,34 @@
-use log::{Log, Metadata, Record};
-use chrono::{Utc, SecondsFormat};
+use std::collections::VecDeque;
+use std::sync::Mutex;
 
-pub struct Logger;
+use log::{Level, Log, Metadata, Record};
+use chrono::{DateTime, Utc, SecondsFormat};
+
+const RECENT_ERRORS_CAPACITY: usize = 100;
+
+#[derive(Clone)]
+pub struct RecentError {
+  pub at: DateTime<Utc>,
+  pub message: String,
+}
+
+pub struct Logger {
+  // a ring buffer of the most recent error logs, for introspection
+  recent_errors: Mutex<VecDeque<RecentError>>,
+}
+
+impl Logger {
+  pub const fn new() -> Self {
+    Logger {
+      recent_errors: Mutex::new(VecDeque::new()),
+    }
+  }
+
+  pub fn recent_errors(&self) -> Vec<RecentError> {
+    let recent_errors = self.recent_errors.lock().unwrap();
+    recent_errors.iter().cloned().collect()
+  }
+}
 
 impl Log for Logger {
   fn enabled(&self, _: &Metadata) -> bool {
@@ -9,14 +36,26 @@ impl Log for Logger {
   }
 
   fn log(&self, record: &Record) {
-    let time_string = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let now = Utc::now();
+    let time_string = now.to_rfc3339_opts(SecondsFormat::Millis, true);
     println!(
       "{} {}| {}",
       &time_string[2..],
       record.level(),
       record.args()
     );
+
+    if record.level() == Level::Error {
+      let mut recent_errors = self.recent_errors.lock().unwrap();
+      if recent_errors.len() >= RECENT_ERRORS_CAPACITY {
+        recent_errors.pop_front();
+      }
+      recent_errors.push_back(RecentError {
+        at: now,
+        message: record.args().to_string(),
+      });
+    }
   }
 
   fn flush(&self) {}
-}
\ No newline at end of file
+}
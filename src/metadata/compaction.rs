@@ -3,9 +3,12 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::errors::ServerResult;
 use crate::impl_metadata_serde_json;
 use crate::types::CompactionKey;
+use crate::utils::common;
 use crate::utils::dirs;
+use crate::utils::hll::HyperLogLog;
 
 use super::traits::{PersistentCacheData, PersistentMetadata, MetadataKey};
 
@@ -17,11 +20,41 @@ impl MetadataKey for CompactionKey {
 pub struct Compaction {
   // total # of rows ever written (including deleted ones that don't show up)
   // covered by this compaction
-  pub all_time_compacted_n: u32,
+  pub all_time_compacted_n: u64,
   // total # of rows deleted prior to this compaction (these don't show up in
   // the compacted data)
-  pub all_time_omitted_n: u32,
+  pub all_time_omitted_n: u64,
   pub col_codecs: HashMap<String, String>,
+  // whether compacted column files end in a checksum footer; compactions
+  // written before checksums were introduced do not
+  #[serde(default)]
+  pub checksummed: bool,
+  // approximate distinct values of each schema column's compacted data,
+  // filled in once every column has been compacted
+  #[serde(default)]
+  pub col_sketches: HashMap<String, HyperLogLog>,
+  // columns with a bloom filter file next to their compacted data
+  #[serde(default)]
+  pub bloom_filter_columns: Vec<String>,
+  // if nonempty, compacted rows are sorted by these columns instead of
+  // being in row id order
+  #[serde(default)]
+  pub sort_columns: Vec<String>,
+  // the key this version's column files and zone maps are encrypted with,
+  // or None if they are plaintext
+  #[serde(default)]
+  pub encryption_key_id: Option<String>,
+  // whether this version's compaction finished, which is recorded in the
+  // same manifest batch that switches its segment to reading it; versions
+  // that were never compacted, and those compacted before sealing was
+  // introduced, are not sealed
+  #[serde(default)]
+  pub sealed: bool,
+  // Row counts of the blocks compacted incrementally after each column's
+  // base file, oldest first. A column rewritten in full since has no file
+  // for the earlier increments, so missing increments read as empty.
+  #[serde(default)]
+  pub increment_ns: Vec<u64>,
 }
 
 impl_metadata_serde_json!(Compaction);
@@ -33,13 +66,26 @@ impl Default for Compaction {
       all_time_compacted_n: 0,
       all_time_omitted_n: 0,
       col_codecs: HashMap::new(),
+      checksummed: false,
+      col_sketches: HashMap::new(),
+      bloom_filter_columns: Vec::new(),
+      sort_columns: Vec::new(),
+      encryption_key_id: None,
+      sealed: false,
+      increment_ns: Vec::new(),
     }
   }
 }
 
-impl PersistentMetadata<CompactionKey> for Compaction {
-  const CACHE_SIZE_LIMIT: usize = 65536;
+impl Compaction {
+  // compacted rows that weren't already deleted, which is how many rows the
+  // compacted files hold
+  pub fn stored_n(&self) -> ServerResult<u64> {
+    common::checked_sub_n(self.all_time_compacted_n, self.all_time_omitted_n, "omitted rows")
+  }
+}
 
+impl PersistentMetadata<CompactionKey> for Compaction {
   fn relative_path(key: &CompactionKey) -> PathBuf {
     dirs::relative_version_dir(key).join("compaction.json")
   }
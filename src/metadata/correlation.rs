@@ -1,49 +1,204 @@
-use crate::metadata::traits::{EphemeralCacheData, EphemeralMetadata};
-use crate::types::{CompactionKey, SegmentKey};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::Mutex;
+
 use crate::errors::{ServerResult, ServerError};
+use crate::types::SegmentKey;
+use crate::utils::shared_hash_map::CacheStats;
 
+// What a correlation id has pinned for one segment. Plain reads pin only the
+// read version and see the latest deletions; snapshots also pin the deletion
+// id so that deletes made after the snapshot stay invisible.
+#[derive(Clone, Copy, Debug)]
+pub struct SegmentPin {
+  pub version: u64,
+  pub deletion_id: Option<u64>,
+}
+
+// A correlation id pins the read version of one segment, so that every
+// request in a multi-request read sees the same compaction. A snapshot is a
+// correlation id pinning every segment of a table at once. Pins expire
+// after a TTL that is refreshed each time the correlation id is used, or
+// can be released early with an EndRead.
 #[derive(Clone)]
 pub struct CorrelationMetadata {
-  pub compaction_key: CompactionKey,
+  pub pins: HashMap<SegmentKey, SegmentPin>,
+  pub is_snapshot: bool,
+  pub expires_at: DateTime<Utc>,
 }
 
-impl EphemeralMetadata for CorrelationMetadata {
-  const CACHE_SIZE_LIMIT: usize = 16384;
+impl CorrelationMetadata {
+  fn is_expired(&self, now: DateTime<Utc>) -> bool {
+    self.expires_at <= now
+  }
 }
 
-pub type CorrelationMetadataCache = EphemeralCacheData<String, CorrelationMetadata>;
+// Unlike the other metadata caches, entries here are never evicted to make
+// room: compaction relies on them to know which old versions are still
+// being read. Instead, new pins are refused while the cache is full of
+// unexpired ones.
+#[derive(Clone)]
+pub struct CorrelationMetadataCache {
+  size_limit: usize,
+  data: Arc<Mutex<HashMap<String, CorrelationMetadata>>>,
+  hits: Arc<AtomicU64>,
+  misses: Arc<AtomicU64>,
+  expirations: Arc<AtomicU64>,
+}
 
-// clippy doesn't understand that &String is needed here due to type bounds
-#[allow(clippy::ptr_arg)]
 impl CorrelationMetadataCache {
-  pub async fn get_correlated_read_version(
+  pub fn new(size_limit: usize) -> Self {
+    CorrelationMetadataCache {
+      size_limit,
+      data: Arc::new(Mutex::new(HashMap::new())),
+      hits: Arc::new(AtomicU64::new(0)),
+      misses: Arc::new(AtomicU64::new(0)),
+      expirations: Arc::new(AtomicU64::new(0)),
+    }
+  }
+
+  fn remove_expired(&self, data: &mut HashMap<String, CorrelationMetadata>, now: DateTime<Utc>) -> usize {
+    let n_before = data.len();
+    data.retain(|_, meta| !meta.is_expired(now));
+    let n_removed = n_before - data.len();
+    self.expirations.fetch_add(n_removed as u64, Ordering::Relaxed);
+    n_removed
+  }
+
+  fn make_room(&self, data: &mut HashMap<String, CorrelationMetadata>, now: DateTime<Utc>) -> ServerResult<()> {
+    if data.len() >= self.size_limit && self.remove_expired(data, now) == 0 {
+      return Err(ServerError::too_many_requests(format!(
+        "{} reads are already in progress; end some before beginning more",
+        data.len(),
+      )));
+    }
+    Ok(())
+  }
+
+  // Returns the segment's pin and when it expires. If the correlation id is
+  // new (or its pin expired), it pins the given version.
+  pub async fn get_correlated_pin(
     &self,
-    correlation_id: &String,
+    correlation_id: &str,
     segment_key: &SegmentKey,
     version: u64,
-  ) -> ServerResult<u64> {
-    let lock = self.get_lock(correlation_id).await?;
-    let mut guard = lock.write().await;
-    let compaction_key = segment_key.compaction_key(version);
-    if guard.is_some() {
-      let meta = guard.clone().unwrap();
-      let prev_segment_key = meta.compaction_key.segment_key();
-      let this_segment_key = compaction_key.segment_key();
-      if prev_segment_key == this_segment_key {
-        Ok(meta.compaction_key.version)
-      } else {
-        Err(ServerError::invalid(format!(
-          "correlation id {} originally used for segment {} now used for segment {}",
-          correlation_id,
-          prev_segment_key,
-          this_segment_key,
-        )))
+    ttl: Duration,
+  ) -> ServerResult<(SegmentPin, DateTime<Utc>)> {
+    let now = Utc::now();
+    let expires_at = now + ttl;
+    let mut data = self.data.lock().await;
+    if let Some(meta) = data.get_mut(correlation_id) {
+      if !meta.is_expired(now) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        let pin = match meta.pins.get(segment_key) {
+          Some(pin) => *pin,
+          None if meta.is_snapshot => return Err(ServerError::invalid(format!(
+            "segment {} is not part of snapshot {}",
+            segment_key,
+            correlation_id,
+          ))),
+          None => return Err(ServerError::invalid(format!(
+            "correlation id {} originally used for segment {} now used for segment {}",
+            correlation_id,
+            meta.pins.keys().next().map(|key| key.to_string()).unwrap_or_default(),
+            segment_key,
+          ))),
+        };
+        meta.expires_at = meta.expires_at.max(expires_at);
+        return Ok((pin, meta.expires_at));
       }
-    } else {
-      *guard = Some(CorrelationMetadata {
-        compaction_key,
-      });
-      Ok(version)
+    }
+
+    self.misses.fetch_add(1, Ordering::Relaxed);
+    self.make_room(&mut data, now)?;
+    let pin = SegmentPin {
+      version,
+      deletion_id: None,
+    };
+    let mut pins = HashMap::new();
+    pins.insert(segment_key.clone(), pin);
+    data.insert(correlation_id.to_string(), CorrelationMetadata {
+      pins,
+      is_snapshot: false,
+      expires_at,
+    });
+    Ok((pin, expires_at))
+  }
+
+  // returns when the snapshot expires
+  pub async fn begin_snapshot(
+    &self,
+    snapshot_id: &str,
+    pins: HashMap<SegmentKey, SegmentPin>,
+    ttl: Duration,
+  ) -> ServerResult<DateTime<Utc>> {
+    let now = Utc::now();
+    let expires_at = now + ttl;
+    let mut data = self.data.lock().await;
+    if data.get(snapshot_id).map(|meta| !meta.is_expired(now)).unwrap_or(false) {
+      return Err(ServerError::invalid(format!(
+        "correlation id {} is already in use",
+        snapshot_id,
+      )));
+    }
+
+    self.misses.fetch_add(1, Ordering::Relaxed);
+    self.make_room(&mut data, now)?;
+    data.insert(snapshot_id.to_string(), CorrelationMetadata {
+      pins,
+      is_snapshot: true,
+      expires_at,
+    });
+    Ok(expires_at)
+  }
+
+  // extends an unexpired pin without changing what it pins
+  pub async fn refresh(&self, correlation_id: &str, ttl: Duration) {
+    let now = Utc::now();
+    let mut data = self.data.lock().await;
+    if let Some(meta) = data.get_mut(correlation_id) {
+      if !meta.is_expired(now) {
+        meta.expires_at = meta.expires_at.max(now + ttl);
+      }
+    }
+  }
+
+  // returns whether the correlation id was pinning anything
+  pub async fn end_read(&self, correlation_id: &str) -> bool {
+    let mut data = self.data.lock().await;
+    match data.remove(correlation_id) {
+      Some(meta) => !meta.is_expired(Utc::now()),
+      None => false,
+    }
+  }
+
+  // versions of the segment that unexpired correlation ids are reading
+  pub async fn pinned_versions(&self, segment_key: &SegmentKey) -> HashSet<u64> {
+    let now = Utc::now();
+    let data = self.data.lock().await;
+    data.values()
+      .filter(|meta| !meta.is_expired(now))
+      .filter_map(|meta| meta.pins.get(segment_key))
+      .map(|pin| pin.version)
+      .collect()
+  }
+
+  // returns the number of expired pins removed
+  pub async fn prune_expired(&self) -> usize {
+    let mut data = self.data.lock().await;
+    self.remove_expired(&mut data, Utc::now())
+  }
+
+  pub async fn stats(&self) -> CacheStats {
+    CacheStats {
+      n_entries: self.data.lock().await.len(),
+      size_limit: self.size_limit,
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      evictions: self.expirations.load(Ordering::Relaxed),
     }
   }
 }
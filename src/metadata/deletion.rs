@@ -6,8 +6,6 @@ use crate::types::SegmentKey;
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DeletionMetadata {}
 
-impl EphemeralMetadata for DeletionMetadata {
-  const CACHE_SIZE_LIMIT: usize = 16384;
-}
+impl EphemeralMetadata for DeletionMetadata {}
 
 pub type DeletionMetadataCache = EphemeralCacheData<SegmentKey, DeletionMetadata>;
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use crate::constants::{FORMAT_MAJOR_VERSION, FORMAT_MINOR_VERSION};
 use crate::impl_metadata_serde_json;
 use crate::types::EmptyKey;
 
@@ -14,23 +15,58 @@ impl MetadataKey for EmptyKey {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GlobalMetadata {
   pub n_shards_log: u32,
+  // the partition dir fanout the dir is laid out with
+  #[serde(default)]
+  pub partition_dir_fanout: u32,
+  // The on-disk format the dir is written in. Dirs from before formats were
+  // versioned deserialize as major version 0.
+  #[serde(default)]
+  pub format_major_version: u32,
+  #[serde(default)]
+  pub format_minor_version: u32,
+  // the format upgrade in progress, if one was interrupted
+  #[serde(default)]
+  pub format_upgrade: Option<FormatUpgradeProgress>,
+  // Every feature of the format that any server writing to the dir may have
+  // used, so that older servers can tell when they'd misread it.
+  #[serde(default)]
+  pub format_features: Vec<FormatFeature>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FormatFeature {
+  pub name: String,
+  // whether servers without the feature still read the dir correctly, only
+  // unable to write it
+  #[serde(default)]
+  pub read_compatible: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FormatUpgradeProgress {
+  pub target_major_version: u32,
+  // names of the migrations already run, in order
+  pub completed_migrations: Vec<String>,
 }
 
 impl_metadata_serde_json!(GlobalMetadata);
 
 impl PersistentMetadata<EmptyKey> for GlobalMetadata {
-  const CACHE_SIZE_LIMIT: usize = 1;
-
   fn relative_path(_: &EmptyKey) -> PathBuf {
     "global_metadata.json".into()
   }
 }
 
-#[allow(clippy::derivable_impls)]
+// the metadata of a new dir, in the current format
 impl Default for GlobalMetadata {
   fn default() -> Self {
     GlobalMetadata {
       n_shards_log: 0,
+      partition_dir_fanout: 0,
+      format_major_version: FORMAT_MAJOR_VERSION,
+      format_minor_version: FORMAT_MINOR_VERSION,
+      format_upgrade: None,
+      format_features: Vec::new(),
     }
   }
 }
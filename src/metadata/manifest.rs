@@ -0,0 +1,123 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::server::Server;
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+use super::traits::{MetadataKey, PersistentMetadata};
+
+// Metadata mutations that span several files are recorded as a single
+// manifest entry before any of the files are touched. Writing the entry is
+// the commit point: once it exists, recovery replays it, so after a crash
+// either every file in the batch reflects the mutation or none do.
+// Entries are named by commit time so they replay in the order committed.
+#[derive(Serialize, Deserialize)]
+struct ManifestWrite {
+  relative_path: PathBuf,
+  contents: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+  writes: Vec<ManifestWrite>,
+}
+
+#[derive(Default)]
+pub struct MetadataBatch {
+  writes: Vec<ManifestWrite>,
+}
+
+impl MetadataBatch {
+  pub fn add<K: MetadataKey, M: PersistentMetadata<K>>(
+    &mut self,
+    metadata: &M,
+    key: &K,
+  ) -> ServerResult<()> {
+    self.writes.push(ManifestWrite {
+      relative_path: M::relative_path(key),
+      contents: metadata.to_json_string()?,
+    });
+    Ok(())
+  }
+
+  // Callers must hold write locks on every piece of metadata in the batch
+  // and update their cached values themselves.
+  pub async fn commit(self, server: &Server) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let entry = ManifestEntry { writes: self.writes };
+    let commit_nanos = Utc::now().timestamp_nanos_opt()
+      .ok_or_else(|| ServerError::internal("current time is out of range for a manifest entry"))?;
+    let entry_path = dirs::manifest_dir(dir).join(format!(
+      "{:020}_{}.json",
+      commit_nanos,
+      Uuid::new_v4(),
+    ));
+    common::overwrite_file_atomic(
+      &entry_path,
+      serde_json::to_string(&entry)?.as_bytes(),
+      dir,
+    ).await?;
+
+    // Past the commit point, the files may disagree with each other until
+    // the entry is replayed. The server stops taking writes before the
+    // caller's locks are released, so nothing builds on the partial batch
+    // before recovery finishes it at restart.
+    if let Err(e) = apply(dir, &entry_path, entry).await {
+      server.mark_unhealthy(format!(
+        "metadata manifest entry {:?} failed past its commit point; restart to replay it: {}",
+        entry_path,
+        e,
+      ));
+      return Err(e);
+    }
+    Ok(())
+  }
+}
+
+async fn apply(dir: &Path, entry_path: &Path, entry: ManifestEntry) -> ServerResult<()> {
+  for write in &entry.writes {
+    common::overwrite_file_atomic(
+      dir.join(&write.relative_path),
+      write.contents.as_bytes(),
+      dir,
+    ).await?;
+  }
+  vfs::remove_file(entry_path).await?;
+  Ok(())
+}
+
+// returns the number of entries replayed
+pub async fn replay(dir: &Path) -> ServerResult<usize> {
+  let manifest_dir = dirs::manifest_dir(dir);
+  let mut read_dir = match vfs::read_dir(&manifest_dir).await {
+    Ok(read_dir) => read_dir,
+    Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(0),
+    Err(e) => return Err(e.into()),
+  };
+
+  let mut entry_paths = Vec::new();
+  while let Some(dir_entry) = read_dir.next_entry().await? {
+    entry_paths.push(dir_entry.path());
+  }
+  entry_paths.sort();
+
+  for entry_path in &entry_paths {
+    log::debug!("replaying metadata manifest entry {:?}", entry_path);
+    let entry_str = vfs::read_to_string(entry_path).await?;
+    let entry: ManifestEntry = serde_json::from_str(&entry_str)
+      .map_err(|e| ServerError::corrupt(format!(
+        "unable to parse manifest entry {:?}: {}",
+        entry_path,
+        e,
+      )))?;
+    apply(dir, entry_path, entry).await?;
+  }
+  Ok(entry_paths.len())
+}
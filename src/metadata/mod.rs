@@ -1,10 +1,13 @@
-pub use traits::{PersistentMetadata, MetadataKey};
+pub use traits::{MetadataJson, PersistentMetadata, MetadataKey};
 
 mod traits;
 pub mod segment;
 pub mod table;
 pub mod compaction;
 pub mod partition;
+pub mod partition_index;
 pub mod global;
 pub mod deletion;
 pub mod correlation;
+pub mod manifest;
+pub mod transaction;
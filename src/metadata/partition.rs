@@ -26,8 +26,6 @@ pub struct PartitionMetadata {
 impl_metadata_serde_json!(PartitionMetadata);
 
 impl PersistentMetadata<PartitionKey> for PartitionMetadata {
-  const CACHE_SIZE_LIMIT: usize = 65536;
-
   fn relative_path(key: &PartitionKey) -> PathBuf {
     dirs::relative_partition_dir(key)
       .join("partition_metadata.json")
@@ -46,13 +44,11 @@ impl PartitionMetadata {
     }
   }
 
-  pub fn get_active_segment_id(&self, shard_id: &ShardId) -> Option<Uuid> {
-    for segment_id in &self.active_segment_ids {
-      if shard_id.contains_segment_id(*segment_id) {
-        return Some(*segment_id);
-      }
-    }
-    None
+  pub fn active_segment_ids_in_shard(&self, shard_id: &ShardId) -> Vec<Uuid> {
+    self.active_segment_ids.iter()
+      .copied()
+      .filter(|&segment_id| shard_id.contains_segment_id(segment_id))
+      .collect()
   }
 
   pub fn replace_active_segment_id(&mut self, old_id: Uuid, new_id: Uuid) {
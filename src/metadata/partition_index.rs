@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::impl_metadata_serde_json;
+use crate::types::NormalizedPartition;
+use crate::utils::dirs;
+
+use super::traits::{PersistentCacheData, PersistentMetadata};
+
+// Every partition of a table and the segments in it, so that listing them
+// reads no directories. Ops that add or remove segments keep it up to
+// date, and recovery rebuilds it from the directories in case a crash left
+// it behind them.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PartitionIndex {
+  pub partitions: Vec<IndexedPartition>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexedPartition {
+  pub partition: NormalizedPartition,
+  pub segment_ids: Vec<Uuid>,
+}
+
+impl_metadata_serde_json!(PartitionIndex);
+
+impl PersistentMetadata<String> for PartitionIndex {
+  fn relative_path(table_name: &String) -> PathBuf {
+    dirs::relative_table_dir(table_name)
+      .join("partition_index.json")
+  }
+}
+
+impl PartitionIndex {
+  // returns whether the segment is new to the index
+  pub fn add_segment(&mut self, partition: &NormalizedPartition, segment_id: Uuid) -> bool {
+    let maybe_indexed = self.partitions.iter_mut()
+      .find(|indexed| &indexed.partition == partition);
+    match maybe_indexed {
+      Some(indexed) if indexed.segment_ids.contains(&segment_id) => false,
+      Some(indexed) => {
+        indexed.segment_ids.push(segment_id);
+        true
+      },
+      None => {
+        self.partitions.push(IndexedPartition {
+          partition: partition.clone(),
+          segment_ids: vec![segment_id],
+        });
+        true
+      },
+    }
+  }
+
+  // Returns whether any of the segments were in the index. The partition
+  // stays, like its directory does.
+  pub fn remove_segments(&mut self, partition: &NormalizedPartition, segment_ids: &[Uuid]) -> bool {
+    let mut changed = false;
+    for indexed in &mut self.partitions {
+      if &indexed.partition == partition {
+        let n_segments = indexed.segment_ids.len();
+        indexed.segment_ids.retain(|id| !segment_ids.contains(id));
+        changed = indexed.segment_ids.len() != n_segments;
+      }
+    }
+    changed
+  }
+}
+
+pub type PartitionIndexCache = PersistentCacheData<String, PartitionIndex>;
@@ -4,11 +4,14 @@ use std::path::PathBuf;
 use chrono::{DateTime, Utc};
 use pancake_db_idl::schema::Schema;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::constants::{ROW_ID_COLUMN_NAME, WRITTEN_AT_COLUMN_NAME};
 use crate::impl_metadata_serde_json;
 use crate::metadata::traits::MetadataKey;
+use crate::errors::ServerResult;
 use crate::types::SegmentKey;
+use crate::utils::common;
 use crate::utils::dirs;
 
 use super::traits::{PersistentCacheData, PersistentMetadata};
@@ -19,10 +22,10 @@ impl MetadataKey for SegmentKey {
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SegmentMetadata {
-  pub all_time_n: u32, // only increases, includes deleted
-  pub all_time_deleted_n: u32, // only increases
+  pub all_time_n: u64, // only increases, includes deleted
+  pub all_time_deleted_n: u64, // only increases
   pub all_time_uncompressed_size: u64, // only increases, includes deleted
-  pub staged_n: u32, // includes deleted
+  pub staged_n: u64, // includes deleted
   pub write_versions: Vec<u64>,
   pub read_version: u64,
   pub read_version_since: DateTime<Utc>,
@@ -31,13 +34,14 @@ pub struct SegmentMetadata {
   pub explicit_columns: HashSet<String>,
   pub deletion_id: u64,
   pub is_cold: bool, // future writes will not go to cold segments
+  // segments merged or split into this one that may not be removed yet
+  #[serde(default)]
+  pub replaced_segment_ids: Vec<Uuid>,
 }
 
 impl_metadata_serde_json!(SegmentMetadata);
 
 impl PersistentMetadata<SegmentKey> for SegmentMetadata {
-  const CACHE_SIZE_LIMIT: usize = 65536;
-
   fn relative_path(key: &SegmentKey) -> PathBuf {
     dirs::relative_segment_dir(key)
       .join("segment_metadata.json")
@@ -59,6 +63,7 @@ impl SegmentMetadata {
       explicit_columns,
       deletion_id: 0,
       is_cold: false,
+      replaced_segment_ids: Vec::new(),
     }
   }
 
@@ -69,6 +74,15 @@ impl SegmentMetadata {
 
     Self::new(explicit_columns)
   }
+
+  // rows that are flushed or compacted, including deleted ones
+  pub fn flushed_n(&self) -> ServerResult<u64> {
+    common::checked_sub_n(self.all_time_n, self.staged_n, "staged rows")
+  }
+
+  pub fn live_n(&self) -> ServerResult<u64> {
+    common::checked_sub_n(self.all_time_n, self.all_time_deleted_n, "deleted rows")
+  }
 }
 
 pub type SegmentMetadataCache = PersistentCacheData<SegmentKey, SegmentMetadata>;
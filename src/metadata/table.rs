@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use chrono::{DateTime, Utc};
 use pancake_db_idl::schema::{Schema, ColumnMeta, PartitionMeta};
 use serde::{Deserialize, Serialize};
 
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::computed::ComputedColumn;
 use crate::utils::dirs;
+use crate::utils::masking::MaskingRule;
 use crate::metadata::traits::MetadataKey;
 
 use super::traits::{PersistentCacheData, PersistentMetadata};
@@ -29,10 +33,85 @@ pub struct SchemaSerde {
   pub columns: HashMap<String, ColumnMetaSerde>,
 }
 
+// Settings that take the place of the server's runtime config for a single
+// table. Unset ones follow the server's.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TableConfigOverrides {
+  #[serde(default)]
+  pub target_rows_per_segment: Option<u32>,
+  #[serde(default)]
+  pub target_uncompressed_bytes_per_segment: Option<u64>,
+  #[serde(default)]
+  pub min_rows_for_compaction: Option<u32>,
+  #[serde(default)]
+  pub min_compaction_intermission_seconds: Option<i64>,
+  #[serde(default)]
+  pub compact_as_constant_seconds: Option<i64>,
+  #[serde(default)]
+  pub max_compaction_increments: Option<u32>,
+  #[serde(default)]
+  pub read_page_byte_size: Option<usize>,
+}
+
+impl TableConfigOverrides {
+  pub fn validate(&self) -> ServerResult<()> {
+    if self.target_rows_per_segment == Some(0) {
+      return Err(ServerError::invalid("target rows per segment must be positive"));
+    }
+    if self.target_uncompressed_bytes_per_segment == Some(0) {
+      return Err(ServerError::invalid("target uncompressed bytes per segment must be positive"));
+    }
+    if self.read_page_byte_size == Some(0) {
+      return Err(ServerError::invalid("read page byte size must be positive"));
+    }
+    let is_negative = |seconds: Option<i64>| matches!(seconds, Some(x) if x < 0);
+    if is_negative(self.min_compaction_intermission_seconds) || is_negative(self.compact_as_constant_seconds) {
+      return Err(ServerError::invalid("compaction seconds must not be negative"));
+    }
+    Ok(())
+  }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TableMetadata {
   schema: SchemaSerde,
   pub dropped: bool,
+  // columns that compaction builds bloom filters for
+  #[serde(default)]
+  pub bloom_filter_columns: Vec<String>,
+  // columns that compaction sorts rows by
+  #[serde(default)]
+  pub sort_columns: Vec<String>,
+  // Renamed columns, keyed by their current name. Their files, staged
+  // values, and the schema above still use the name they are stored under,
+  // and clients only see the current name.
+  #[serde(default)]
+  pub column_aliases: HashMap<String, String>,
+  // keyed by the name each column is stored under, with sources also by
+  // stored name
+  #[serde(default)]
+  pub computed_columns: HashMap<String, ComputedColumn>,
+  // sensitive columns, keyed by the name each is stored under, whose values
+  // are masked for principals who may not unmask the table
+  #[serde(default)]
+  pub column_masks: HashMap<String, MaskingRule>,
+  // incremented whenever the table is altered, so that clients caching its
+  // schema can tell when it is stale
+  #[serde(default)]
+  pub schema_version: u64,
+  // unknown for tables created before these were recorded
+  #[serde(default)]
+  pub created_at: Option<DateTime<Utc>>,
+  #[serde(default)]
+  pub last_altered_at: Option<DateTime<Utc>>,
+  // keyed by the name each column is stored under
+  #[serde(default)]
+  pub column_added_at: HashMap<String, DateTime<Utc>>,
+  // frozen tables reject writes and deletions, but are still compacted
+  #[serde(default)]
+  pub frozen: bool,
+  #[serde(default)]
+  pub config_overrides: TableConfigOverrides,
 }
 
 impl From<&ColumnMeta> for ColumnMetaSerde {
@@ -101,9 +180,23 @@ impl MetadataKey for TableKey {
 
 impl TableMetadata {
   pub fn new(schema: &Schema) -> Self {
+    let now = Utc::now();
     TableMetadata {
       schema: SchemaSerde::from(schema),
       dropped: false,
+      bloom_filter_columns: Vec::new(),
+      sort_columns: Vec::new(),
+      column_aliases: HashMap::new(),
+      computed_columns: HashMap::new(),
+      column_masks: HashMap::new(),
+      schema_version: 0,
+      created_at: Some(now),
+      last_altered_at: None,
+      column_added_at: schema.columns.keys()
+        .map(|col_name| (col_name.clone(), now))
+        .collect(),
+      frozen: false,
+      config_overrides: TableConfigOverrides::default(),
     }
   }
 
@@ -111,20 +204,118 @@ impl TableMetadata {
     Schema::from(&self.schema)
   }
 
+  // the schema as clients see it, with renamed columns under their current
+  // names
+  pub fn visible_schema(&self) -> Schema {
+    let mut schema = self.schema();
+    schema.columns = schema.columns.into_iter()
+      .map(|(col_name, col_meta)| (self.visible_column_name(&col_name), col_meta))
+      .collect();
+    schema
+  }
+
+  // The name a column is stored under, given the name clients know it by.
+  // A renamed column's old name no longer refers to it, so it maps to None.
+  pub fn stored_column_name(&self, col_name: &str) -> Option<String> {
+    if let Some(stored_name) = self.column_aliases.get(col_name) {
+      Some(stored_name.clone())
+    } else if self.column_aliases.values().any(|stored_name| stored_name == col_name) {
+      None
+    } else {
+      Some(col_name.to_string())
+    }
+  }
+
+  pub fn visible_column_name(&self, stored_name: &str) -> String {
+    self.column_aliases.iter()
+      .find(|(_, alias_stored_name)| *alias_stored_name == stored_name)
+      .map(|(col_name, _)| col_name.clone())
+      .unwrap_or_else(|| stored_name.to_string())
+  }
+
+  // computed columns as clients see them, under their current names
+  pub fn visible_computed_columns(&self) -> HashMap<String, ComputedColumn> {
+    self.computed_columns.iter()
+      .map(|(col_name, computed)| (
+        self.visible_column_name(col_name),
+        ComputedColumn {
+          source_column: self.visible_column_name(&computed.source_column),
+          ..computed.clone()
+        },
+      ))
+      .collect()
+  }
+
+  // masking rules as clients see them, under current column names
+  pub fn visible_column_masks(&self) -> HashMap<String, MaskingRule> {
+    self.column_masks.iter()
+      .map(|(col_name, rule)| (self.visible_column_name(col_name), *rule))
+      .collect()
+  }
+
+  // renames are from current name to new name and happen simultaneously
+  pub fn rename_columns(&mut self, renames: &HashMap<String, String>) {
+    let mut new_aliases: HashMap<String, String> = self.column_aliases.iter()
+      .filter(|(col_name, _)| !renames.contains_key(*col_name))
+      .map(|(col_name, stored_name)| (col_name.clone(), stored_name.clone()))
+      .collect();
+    for (old_name, new_name) in renames {
+      let stored_name = self.stored_column_name(old_name)
+        .unwrap_or_else(|| old_name.clone());
+      if *new_name != stored_name {
+        new_aliases.insert(new_name.clone(), stored_name);
+      }
+    }
+    self.column_aliases = new_aliases;
+  }
+
   pub fn extend_columns(&mut self, columns: &HashMap<String, ColumnMeta>) {
+    let now = Utc::now();
     self.schema.columns.extend(
       columns.iter()
         .map(|(k, v)| (k.to_string(), ColumnMetaSerde::from(v)))
-    )
+    );
+    self.column_added_at.extend(
+      columns.keys()
+        .map(|k| (k.to_string(), now))
+    );
+  }
+
+  // metadata for a new table with the same schema and settings
+  pub fn copied(&self) -> Self {
+    let now = Utc::now();
+    TableMetadata {
+      dropped: false,
+      frozen: false,
+      schema_version: 0,
+      created_at: Some(now),
+      last_altered_at: None,
+      column_added_at: self.schema.columns.keys()
+        .map(|col_name| (col_name.clone(), now))
+        .collect(),
+      ..self.clone()
+    }
+  }
+
+  pub fn check_mutable(&self, table_name: &str) -> ServerResult<()> {
+    if self.frozen {
+      return Err(ServerError::read_only(format!(
+        "table {} is frozen",
+        table_name,
+      )));
+    }
+    Ok(())
+  }
+
+  pub fn mark_altered(&mut self) {
+    self.schema_version += 1;
+    self.last_altered_at = Some(Utc::now());
   }
 }
 
 crate::impl_metadata_serde_json!(TableMetadata);
 
 impl PersistentMetadata<TableKey> for TableMetadata {
-  // no one should have more tables than this anyway
-  const CACHE_SIZE_LIMIT: usize = 16384;
-
   fn relative_path(table_name: &TableKey) -> PathBuf {
     dirs::relative_table_dir(table_name)
       .join(TABLE_METADATA_FILENAME)
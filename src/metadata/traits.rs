@@ -4,13 +4,13 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use tokio::fs;
 use tokio::sync::RwLock;
 use std::io;
 
 use crate::errors::{ServerError, ServerResult};
 use crate::utils::common;
-use crate::utils::shared_hash_map::SharedHashMap;
+use crate::utils::shared_hash_map::{CacheStats, SharedHashMap};
+use crate::utils::vfs;
 
 pub trait MetadataJson: Clone + Send + Sync {
   fn to_json_string(&self) -> ServerResult<String>;
@@ -31,14 +31,10 @@ macro_rules! impl_metadata_serde_json {
   }
 }
 
-pub trait EphemeralMetadata: Clone + Send + Sync {
-  const CACHE_SIZE_LIMIT: usize;
-}
+pub trait EphemeralMetadata: Clone + Send + Sync {}
 
 #[async_trait]
 pub trait PersistentMetadata<K: MetadataKey>: MetadataJson {
-  const CACHE_SIZE_LIMIT: usize;
-
   fn relative_path(k: &K) -> PathBuf;
 
   fn path(dir: &Path, k: &K) -> PathBuf {
@@ -47,7 +43,7 @@ pub trait PersistentMetadata<K: MetadataKey>: MetadataJson {
 
   async fn load(dir: &Path, k: &K) -> ServerResult<Option<Self>> {
     let path = Self::path(dir, k);
-    return match fs::read_to_string(&path).await {
+    return match vfs::read_to_string(&path).await {
       Ok(json_string) => {
         Ok(Some(Self::from_json_str(&json_string)?))
       },
@@ -87,10 +83,10 @@ pub struct PersistentCacheData<K, M> where M: PersistentMetadata<K>, K: Metadata
 }
 
 impl<K, M> PersistentCacheData<K, M> where M: PersistentMetadata<K>, K: MetadataKey  {
-  pub fn new(dir: &Path) -> Self {
+  pub fn new(dir: &Path, size_limit: usize) -> Self {
     PersistentCacheData {
       dir: dir.to_path_buf(),
-      data: Arc::new(SharedHashMap::new(M::CACHE_SIZE_LIMIT)),
+      data: Arc::new(SharedHashMap::new(size_limit)),
     }
   }
 
@@ -104,6 +100,24 @@ impl<K, M> PersistentCacheData<K, M> where M: PersistentMetadata<K>, K: Metadata
   where F: Fn(&K) -> bool {
     self.data.prune_unsafe(f).await
   }
+
+  // reloads every cached value from disk, in case another process has
+  // changed it
+  pub async fn refresh(&self) -> ServerResult<()> {
+    for (k, lock) in self.data.entries().await {
+      let value = M::load(&self.dir, &k).await?;
+      *lock.write().await = value;
+    }
+    Ok(())
+  }
+
+  pub async fn stats(&self) -> CacheStats {
+    self.data.stats().await
+  }
+
+  pub async fn held_locks(&self) -> Vec<(K, bool)> {
+    self.data.held_locks().await
+  }
 }
 
 #[derive(Clone)]
@@ -116,9 +130,9 @@ async fn async_none<M: EphemeralMetadata>() -> ServerResult<Option<M>> {
 }
 
 impl<K, M> EphemeralCacheData<K, M> where M: EphemeralMetadata, K: MetadataKey  {
-  pub fn new() -> Self {
+  pub fn new(size_limit: usize) -> Self {
     EphemeralCacheData {
-      data: Arc::new(SharedHashMap::new(M::CACHE_SIZE_LIMIT)),
+      data: Arc::new(SharedHashMap::new(size_limit)),
     }
   }
 
@@ -130,4 +144,12 @@ impl<K, M> EphemeralCacheData<K, M> where M: EphemeralMetadata, K: MetadataKey
     where F: Fn(&K) -> bool {
     self.data.prune_unsafe(f).await
   }
+
+  pub async fn stats(&self) -> CacheStats {
+    self.data.stats().await
+  }
+
+  pub async fn held_locks(&self) -> Vec<(K, bool)> {
+    self.data.held_locks().await
+  }
 }
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use pancake_db_idl::dml::{PartitionFieldValue, Row, WriteToPartitionRequest};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::types::NormalizedPartition;
+
+// Rows written under a transaction id are validated and buffered here,
+// invisible to reads, until the transaction commits. Nothing here is
+// persisted: a transaction still open when the server stops is aborted.
+pub struct PendingTransaction {
+  pub table_name: String,
+  pub writes: HashMap<NormalizedPartition, WriteToPartitionRequest>,
+  pub n_rows: usize,
+  ttl: Duration,
+  expires_at: DateTime<Utc>,
+}
+
+impl PendingTransaction {
+  fn is_expired(&self, now: DateTime<Utc>) -> bool {
+    self.expires_at <= now
+  }
+}
+
+#[derive(Clone)]
+pub struct TransactionCache {
+  size_limit: usize,
+  data: Arc<Mutex<HashMap<String, PendingTransaction>>>,
+}
+
+impl TransactionCache {
+  pub fn new(size_limit: usize) -> Self {
+    TransactionCache {
+      size_limit,
+      data: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  fn get_open<'a>(
+    data: &'a mut HashMap<String, PendingTransaction>,
+    tx_id: &str,
+  ) -> ServerResult<&'a mut PendingTransaction> {
+    match data.get_mut(tx_id) {
+      Some(tx) if !tx.is_expired(Utc::now()) => Ok(tx),
+      _ => Err(ServerError::does_not_exist("transaction", &tx_id)),
+    }
+  }
+
+  // returns the new transaction's id and when it expires
+  pub async fn begin(&self, table_name: &str, ttl: Duration) -> ServerResult<(String, DateTime<Utc>)> {
+    let now = Utc::now();
+    let mut data = self.data.lock().await;
+    if data.len() >= self.size_limit {
+      data.retain(|_, tx| !tx.is_expired(now));
+      if data.len() >= self.size_limit {
+        return Err(ServerError::too_many_requests(format!(
+          "{} transactions are already open; commit or abort some before beginning more",
+          data.len(),
+        )));
+      }
+    }
+
+    let tx_id = Uuid::new_v4().to_string();
+    let expires_at = now + ttl;
+    data.insert(tx_id.clone(), PendingTransaction {
+      table_name: table_name.to_string(),
+      writes: HashMap::new(),
+      n_rows: 0,
+      ttl,
+      expires_at,
+    });
+    Ok((tx_id, expires_at))
+  }
+
+  // Buffers rows that have already been validated against the table's
+  // schema, and extends the transaction's TTL.
+  pub async fn add_rows(
+    &self,
+    tx_id: &str,
+    table_name: &str,
+    partition: &NormalizedPartition,
+    raw_partition: HashMap<String, PartitionFieldValue>,
+    rows: Vec<Row>,
+    max_rows: usize,
+  ) -> ServerResult<()> {
+    let mut data = self.data.lock().await;
+    let tx = Self::get_open(&mut data, tx_id)?;
+    if tx.table_name != table_name {
+      return Err(ServerError::invalid(format!(
+        "transaction {} writes to table {}, not {}",
+        tx_id,
+        tx.table_name,
+        table_name,
+      )));
+    }
+    if tx.n_rows + rows.len() > max_rows {
+      return Err(ServerError::invalid(format!(
+        "transaction {} would exceed the limit of {} rows",
+        tx_id,
+        max_rows,
+      )));
+    }
+
+    tx.n_rows += rows.len();
+    tx.expires_at = Utc::now() + tx.ttl;
+    tx.writes.entry(partition.clone())
+      .or_insert_with(|| WriteToPartitionRequest {
+        table_name: table_name.to_string(),
+        partition: raw_partition,
+        rows: Vec::new(),
+      })
+      .rows
+      .extend(rows);
+    Ok(())
+  }
+
+  // removes the transaction so that it can be committed
+  pub async fn take(&self, tx_id: &str) -> ServerResult<PendingTransaction> {
+    let mut data = self.data.lock().await;
+    Self::get_open(&mut data, tx_id)?;
+    Ok(data.remove(tx_id).unwrap())
+  }
+
+  // returns whether the transaction was open
+  pub async fn abort(&self, tx_id: &str) -> bool {
+    let mut data = self.data.lock().await;
+    match data.remove(tx_id) {
+      Some(tx) => !tx.is_expired(Utc::now()),
+      None => false,
+    }
+  }
+
+  // returns the number of expired transactions removed
+  pub async fn prune_expired(&self) -> usize {
+    let now = Utc::now();
+    let mut data = self.data.lock().await;
+    let n_before = data.len();
+    data.retain(|_, tx| !tx.is_expired(now));
+    n_before - data.len()
+  }
+}
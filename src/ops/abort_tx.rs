@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{AbortTxRequestSerde, AbortTxResponseSerde};
+use crate::server::authz::Access;
+
+// Discards a transaction's buffered rows.
+pub struct AbortTxOp {
+  pub req: AbortTxRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for AbortTxOp {
+  type Locks = TrivialLocks;
+  type Response = AbortTxResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  // the transaction id is proof enough, since beginning the transaction
+  // required write access to its table
+  fn required_access(&self) -> Vec<Access> {
+    Vec::new()
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let was_open = server.transaction_cache.abort(&self.req.tx_id).await;
+    Ok(AbortTxResponseSerde { was_open })
+  }
+}
+
+impl RestRoute for AbortTxOp {
+  type Req = AbortTxRequestSerde;
+
+  const ROUTE_NAME: &'static str = "abort_tx";
+
+  fn new_op(req: Self::Req) -> AbortTxOp {
+    AbortTxOp { req }
+  }
+}
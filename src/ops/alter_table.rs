@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use async_trait::async_trait;
 use pancake_db_idl::ddl::{AlterTableRequest, AlterTableResponse};
 
@@ -7,10 +9,17 @@ use crate::locks::table::TableWriteLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
 use crate::metadata::PersistentMetadata;
+use crate::metadata::table::TableConfigOverrides;
+use crate::server::audit::{self, AuditEvent};
+use crate::server::authz::{Access, Verb};
 use crate::utils::common;
 
 pub struct AlterTableOp {
   pub req: AlterTableRequest,
+  // from current name to new name
+  pub rename_columns: HashMap<String, String>,
+  // if given, replaces all of the table's config overrides
+  pub config_overrides: Option<TableConfigOverrides>,
 }
 
 #[async_trait]
@@ -22,6 +31,32 @@ impl ServerOp for AlterTableOp {
     Ok(self.req.table_name.clone())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    let mut parts = Vec::new();
+    if !self.req.new_columns.is_empty() {
+      parts.push(format!("new columns {}", audit::join_sorted(self.req.new_columns.keys())));
+    }
+    let mut renames = self.rename_columns.iter()
+      .map(|(old_name, new_name)| format!("{} to {}", old_name, new_name))
+      .collect::<Vec<_>>();
+    renames.sort();
+    if !renames.is_empty() {
+      parts.push(format!("renamed {}", renames.join(", ")));
+    }
+    if let Some(config_overrides) = &self.config_overrides {
+      parts.push(format!("config overrides {:?}", config_overrides));
+    }
+    Some(AuditEvent {
+      operation: "alter_table",
+      table_name: self.req.table_name.clone(),
+      summary: parts.join("; "),
+    })
+  }
+
   async fn execute_with_locks(
     &self,
     server: &Server,
@@ -37,11 +72,22 @@ impl ServerOp for AlterTableOp {
     let table_meta = common::unwrap_metadata(table_name, &*maybe_table_guard)?;
 
     common::validate_entity_name_for_write("table name", &req.table_name)?;
-    if req.new_columns.is_empty() {
+    if req.new_columns.is_empty() && self.rename_columns.is_empty() && self.config_overrides.is_none() {
       return Err(ServerError::invalid("alter table request contains 0 alterations"))
     }
-    let schema = table_meta.schema();
+    if let Some(config_overrides) = &self.config_overrides {
+      config_overrides.validate()?;
+    }
+    let schema = table_meta.visible_schema();
+    for (old_name, new_name) in &self.rename_columns {
+      if !schema.columns.contains_key(old_name) {
+        return Err(ServerError::does_not_exist("column", old_name));
+      }
+      common::validate_entity_name_for_write("column name", new_name)?;
+    }
     let all_column_names: Vec<String> = schema.columns.keys()
+      .filter(|col_name| !self.rename_columns.contains_key(*col_name))
+      .chain(self.rename_columns.values())
       .chain(req.new_columns.keys())
       .cloned()
       .collect();
@@ -69,7 +115,20 @@ impl ServerOp for AlterTableOp {
     }
 
     let mut new_table_meta = table_meta.clone();
+    new_table_meta.rename_columns(&self.rename_columns);
+    for col_name in req.new_columns.keys() {
+      if new_table_meta.stored_column_name(col_name).is_none() {
+        return Err(ServerError::invalid(format!(
+          "column name {} is still used by the files of a renamed column",
+          col_name,
+        )));
+      }
+    }
     new_table_meta.extend_columns(&req.new_columns);
+    if let Some(config_overrides) = &self.config_overrides {
+      new_table_meta.config_overrides = config_overrides.clone();
+    }
+    new_table_meta.mark_altered();
     new_table_meta.overwrite(dir, table_name).await?;
     *maybe_table_guard = Some(new_table_meta);
 
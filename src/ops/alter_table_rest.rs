@@ -0,0 +1,68 @@
+use pancake_db_idl::ddl::AlterTableRequest;
+use pancake_db_idl::schema::ColumnMeta;
+
+use crate::{Server, ServerResult};
+use crate::locks::table::TableWriteLocks;
+use crate::locks::traits::ServerOpLocks;
+use crate::metadata::table::TableConfigOverrides;
+use crate::ops::alter_table::AlterTableOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{AlterTableRequestSerde, EmptySerde};
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
+
+pub struct AlterTableRestOp {
+  pub req: AlterTableRequestSerde
+}
+
+#[async_trait::async_trait]
+impl ServerOp for AlterTableRestOp {
+  type Locks = TableWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<<Self::Locks as ServerOpLocks>::Key> {
+    Ok(self.req.table_name.to_string())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    self.pb_op().audit_event()
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: Self::Locks) -> ServerResult<Self::Response> {
+    self.pb_op().execute_with_locks(server, locks).await?;
+    Ok(EmptySerde {})
+  }
+}
+
+impl AlterTableRestOp {
+  fn pb_op(&self) -> AlterTableOp {
+    let req = &self.req;
+    AlterTableOp {
+      req: AlterTableRequest {
+        table_name: req.table_name.to_string(),
+        new_columns: req.new_columns.iter()
+          .map(|(col_name, col_meta)| (col_name.to_string(), ColumnMeta {
+            dtype: col_meta.dtype.into(),
+            nested_list_depth: col_meta.nested_list_depth,
+          }))
+          .collect(),
+      },
+      rename_columns: req.rename_columns.clone(),
+      config_overrides: req.config.as_ref().map(TableConfigOverrides::from),
+    }
+  }
+}
+
+impl RestRoute for AlterTableRestOp {
+  type Req = AlterTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "alter_table";
+
+  fn new_op(req: Self::Req) -> AlterTableRestOp {
+    AlterTableRestOp { req }
+  }
+}
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{AuditLogRequestSerde, AuditLogResponseSerde};
+use crate::server::audit::AuditFilter;
+
+const DEFAULT_MAX_RESULTS: usize = 100;
+
+pub struct AuditLogOp {
+  pub req: AuditLogRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for AuditLogOp {
+  type Locks = TrivialLocks;
+  type Response = AuditLogResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let since = match &req.since {
+      Some(since) => Some(
+        DateTime::parse_from_rfc3339(since)
+          .map_err(|_| ServerError::invalid(format!("since must be an RFC3339 timestamp but was {}", since)))?
+          .with_timezone(&Utc)
+      ),
+      None => None,
+    };
+    let filter = AuditFilter {
+      table_name: req.table_name.clone(),
+      operation: req.operation.clone(),
+      since,
+      max_results: req.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+    };
+    let entries = server.read_audit_log(&filter).await?;
+    Ok(AuditLogResponseSerde { entries })
+  }
+}
+
+impl RestRoute for AuditLogOp {
+  type Req = AuditLogRequestSerde;
+
+  const ROUTE_NAME: &'static str = "audit_log";
+
+  fn new_op(req: Self::Req) -> AuditLogOp {
+    AuditLogOp { req }
+  }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{BackgroundLoopSerde, BackgroundStatusResponseSerde, EmptySerde};
+
+pub struct BackgroundStatusOp;
+
+#[async_trait]
+impl ServerOp for BackgroundStatusOp {
+  type Locks = TrivialLocks;
+  type Response = BackgroundStatusResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let status = server.background_status().await;
+    let mut flush_candidates: Vec<_> = status.flush_candidates.iter()
+      .map(|key| key.to_string())
+      .collect();
+    flush_candidates.sort();
+    let forced_compactions = status.forced_compactions.iter()
+      .map(|key| key.to_string())
+      .collect();
+    let loops = status.loops.into_iter()
+      .map(|(name, loop_status)| BackgroundLoopSerde {
+        name: name.to_string(),
+        iterations: loop_status.iterations,
+        last_started_at: loop_status.last_started_at
+          .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        last_lag_millis: loop_status.last_lag.as_millis() as u64,
+        last_duration_millis: loop_status.last_duration.as_millis() as u64,
+        current_segment: loop_status.current_segment.map(|key| key.to_string()),
+        panics: loop_status.panics,
+        last_panic: loop_status.last_panic,
+        last_panic_at: loop_status.last_panic_at
+          .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        restarting: loop_status.is_restarting,
+      })
+      .collect();
+    Ok(BackgroundStatusResponseSerde {
+      flush_candidates,
+      flush_backlog: status.flush_queue.backlog as u64,
+      flush_candidates_added: status.flush_queue.n_added,
+      flush_candidates_coalesced: status.flush_queue.n_coalesced,
+      oldest_flush_candidate_age_millis: status.flush_queue.oldest_age.as_millis() as u64,
+      max_flush_candidate_age_millis: status.flush_queue.max_taken_age.as_millis() as u64,
+      forced_compactions,
+      loops,
+    })
+  }
+}
+
+impl RestRoute for BackgroundStatusOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "background";
+
+  fn new_op(_req: Self::Req) -> BackgroundStatusOp {
+    BackgroundStatusOp
+  }
+}
@@ -0,0 +1,90 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{Duration, SecondsFormat};
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::TableReadLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{BeginReadRequestSerde, BeginReadResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, SegmentKey};
+use crate::utils::common;
+
+// Pins a segment's current read version under a correlation id, so that
+// subsequent ReadSegmentColumn and ReadSegmentDeletions requests using it
+// read a consistent version, and compaction keeps that version's files
+// until the read ends or expires.
+pub struct BeginReadOp {
+  pub req: BeginReadRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for BeginReadOp {
+  type Locks = TableReadLocks;
+  type Response = BeginReadResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    common::validate_segment_id(&req.segment_id)?;
+    let ttl_seconds = req.ttl_seconds
+      .unwrap_or(server.runtime_config().await.correlation_ttl_seconds);
+    if ttl_seconds <= 0 {
+      return Err(ServerError::invalid("ttl seconds must be positive"));
+    }
+    let correlation_id = match &req.correlation_id {
+      Some(id) if id.is_empty() => return Err(ServerError::invalid("correlation id must not be empty")),
+      Some(id) => id.clone(),
+      None => Uuid::new_v4().to_string(),
+    };
+
+    let schema = locks.table_meta.schema();
+    let partition = write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?;
+    let segment_key = SegmentKey {
+      table_name: req.table_name.clone(),
+      partition: NormalizedPartition::from_raw_fields(&partition)?,
+      segment_id: Uuid::from_str(&req.segment_id)?,
+    };
+    segment_key.partition.check_against_schema(&schema)?;
+
+    let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+    let segment_guard = segment_lock.read().await;
+    let read_version = match &*segment_guard {
+      Some(segment_meta) => segment_meta.read_version,
+      None => return Err(ServerError::does_not_exist("segment", &req.segment_id)),
+    };
+
+    let (pin, expires_at) = server.correlation_metadata_cache.get_correlated_pin(
+      &correlation_id,
+      &segment_key,
+      read_version,
+      Duration::seconds(ttl_seconds),
+    ).await?;
+    Ok(BeginReadResponseSerde {
+      correlation_id,
+      read_version: pin.version,
+      expires_at: expires_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+    })
+  }
+}
+
+impl RestRoute for BeginReadOp {
+  type Req = BeginReadRequestSerde;
+
+  const ROUTE_NAME: &'static str = "begin_read";
+
+  fn new_op(req: Self::Req) -> BeginReadOp {
+    BeginReadOp { req }
+  }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{Duration, SecondsFormat};
+use pancake_db_idl::dml::ListSegmentsRequest;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::metadata::correlation::SegmentPin;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::list_segments_rest::partition_field_to_json;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{BeginSnapshotRequestSerde, BeginSnapshotResponseSerde, SnapshotSegmentSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+
+// Pins the read version and deletion id of every matching segment of a
+// table under one snapshot id. Using the snapshot id as the correlation id
+// for ReadSegmentColumn and ReadSegmentDeletions requests then gives a scan
+// across those segments a consistent view, even as compactions and deletes
+// proceed. Snapshots end like any other read, via EndRead or expiry.
+//
+// Rows written to a segment after the snapshot may still appear at the end
+// of its columns until the segment is compacted; clients wanting only the
+// snapshot's rows can use each segment's row count.
+pub struct BeginSnapshotOp {
+  pub req: BeginSnapshotRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for BeginSnapshotOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = BeginSnapshotResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let ttl_seconds = req.ttl_seconds
+      .unwrap_or(server.runtime_config().await.correlation_ttl_seconds);
+    if ttl_seconds <= 0 {
+      return Err(ServerError::invalid("ttl seconds must be positive"));
+    }
+    let snapshot_id = match &req.snapshot_id {
+      Some(id) if id.is_empty() => return Err(ServerError::invalid("snapshot id must not be empty")),
+      Some(id) => id.clone(),
+      None => Uuid::new_v4().to_string(),
+    };
+
+    let schema = locks.table_meta.schema();
+    let partition_filter = write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?;
+    let list_req = ListSegmentsRequest {
+      table_name: req.table_name.clone(),
+      ..Default::default()
+    };
+    let listed = ListSegmentsOp { req: list_req, partition_conditions: Vec::new() }.execute_with_locks(server, locks).await?;
+
+    // Hold every segment's read lock until the snapshot is registered, so
+    // no delete or compaction lands between pinning one segment and the next.
+    let mut guards = Vec::new();
+    let mut pins = HashMap::new();
+    let mut segments = Vec::new();
+    for segment in &listed.segments {
+      let is_match = partition_filter.iter()
+        .all(|(name, value)| segment.partition.get(name) == Some(value));
+      if !is_match {
+        continue;
+      }
+
+      let partition_key = PartitionKey {
+        table_name: req.table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      let segment_guard = server.segment_metadata_cache.get_lock(&segment_key)
+        .await?
+        .read_owned()
+        .await;
+      let segment_meta = match &*segment_guard {
+        Some(segment_meta) => segment_meta,
+        None => continue,
+      };
+
+      pins.insert(segment_key, SegmentPin {
+        version: segment_meta.read_version,
+        deletion_id: Some(segment_meta.deletion_id),
+      });
+      segments.push(SnapshotSegmentSerde {
+        partition: segment.partition.iter()
+          .map(|(name, value)| Ok((name.to_string(), partition_field_to_json(value)?)))
+          .collect::<ServerResult<_>>()?,
+        segment_id: segment.segment_id.clone(),
+        read_version: segment_meta.read_version,
+        deletion_id: segment_meta.deletion_id,
+        row_count: segment_meta.live_n()?,
+      });
+      guards.push(segment_guard);
+    }
+
+    let expires_at = server.correlation_metadata_cache.begin_snapshot(
+      &snapshot_id,
+      pins,
+      Duration::seconds(ttl_seconds),
+    ).await?;
+    drop(guards);
+    log::debug!(
+      "began snapshot {} of {} covering {} segments",
+      snapshot_id,
+      req.table_name,
+      segments.len(),
+    );
+
+    Ok(BeginSnapshotResponseSerde {
+      snapshot_id,
+      expires_at: expires_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+      segments,
+    })
+  }
+}
+
+impl RestRoute for BeginSnapshotOp {
+  type Req = BeginSnapshotRequestSerde;
+
+  const ROUTE_NAME: &'static str = "begin_snapshot";
+
+  fn new_op(req: Self::Req) -> BeginSnapshotOp {
+    BeginSnapshotOp { req }
+  }
+}
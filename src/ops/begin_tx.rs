@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use chrono::{Duration, SecondsFormat};
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::TableReadLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{BeginTxRequestSerde, BeginTxResponseSerde};
+use crate::server::authz::{Access, Verb};
+
+// Opens a transaction on a table. Rows written with its transaction id are
+// buffered until a CommitTx makes all of them visible at once, across every
+// partition they were written to.
+pub struct BeginTxOp {
+  pub req: BeginTxRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for BeginTxOp {
+  type Locks = TableReadLocks;
+  type Response = BeginTxResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Write, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TableReadLocks) -> ServerResult<Self::Response> {
+    server.check_writable()?;
+    let ttl_seconds = self.req.ttl_seconds
+      .unwrap_or(server.runtime_config().await.transaction_ttl_seconds);
+    if ttl_seconds <= 0 {
+      return Err(ServerError::invalid("ttl seconds must be positive"));
+    }
+
+    let (tx_id, expires_at) = server.transaction_cache.begin(
+      &self.req.table_name,
+      Duration::seconds(ttl_seconds),
+    ).await?;
+    Ok(BeginTxResponseSerde {
+      tx_id,
+      expires_at: expires_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+    })
+  }
+}
+
+impl RestRoute for BeginTxOp {
+  type Req = BeginTxRequestSerde;
+
+  const ROUTE_NAME: &'static str = "begin_tx";
+
+  fn new_op(req: Self::Req) -> BeginTxOp {
+    BeginTxOp { req }
+  }
+}
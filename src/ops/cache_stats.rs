@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{CacheStatsResponseSerde, CacheStatsSerde, EmptySerde};
+use crate::utils::shared_hash_map::CacheStats;
+
+pub struct CacheStatsOp;
+
+fn stats_to_serde(cache_name: &str, stats: CacheStats) -> CacheStatsSerde {
+  let n_requests = stats.hits + stats.misses;
+  let hit_rate = if n_requests == 0 {
+    0.0
+  } else {
+    stats.hits as f64 / n_requests as f64
+  };
+  CacheStatsSerde {
+    cache_name: cache_name.to_string(),
+    n_entries: stats.n_entries,
+    size_limit: stats.size_limit,
+    hits: stats.hits,
+    misses: stats.misses,
+    evictions: stats.evictions,
+    hit_rate,
+  }
+}
+
+#[async_trait]
+impl ServerOp for CacheStatsOp {
+  type Locks = TrivialLocks;
+  type Response = CacheStatsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let caches = vec![
+      stats_to_serde("table", server.table_metadata_cache.stats().await),
+      stats_to_serde("partition", server.partition_metadata_cache.stats().await),
+      stats_to_serde("partition_index", server.partition_index_cache.stats().await),
+      stats_to_serde("segment", server.segment_metadata_cache.stats().await),
+      stats_to_serde("compaction", server.compaction_cache.stats().await),
+      stats_to_serde("deletion", server.deletion_metadata_cache.stats().await),
+      stats_to_serde("correlation", server.correlation_metadata_cache.stats().await),
+    ];
+    Ok(CacheStatsResponseSerde { caches })
+  }
+}
+
+impl RestRoute for CacheStatsOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "cache_stats";
+
+  fn new_op(_req: Self::Req) -> CacheStatsOp {
+    CacheStatsOp
+  }
+}
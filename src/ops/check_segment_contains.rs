@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use pancake_db_idl::dml::ListSegmentsRequest;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::list_segments_rest::partition_field_to_json;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{CheckSegmentContainsRequestSerde, CheckSegmentContainsResponseSerde, SegmentContainsSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+use crate::utils::bloom::BloomFilter;
+use crate::utils::common;
+use crate::utils::dirs;
+
+// Checks each matching segment's bloom filter for a value, so clients can
+// skip segments that definitely don't contain it before reading columns.
+// Segments without a filter for the column, or with rows written since
+// their last compaction, may always contain the value.
+pub struct CheckSegmentContainsOp {
+  pub req: CheckSegmentContainsRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for CheckSegmentContainsOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = CheckSegmentContainsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let dir = &server.opts.dir;
+    let schema = locks.table_meta.visible_schema();
+    let col_meta = schema.columns.get(&req.column_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", &req.column_name))?;
+    let col_name = locks.table_meta.stored_column_name(&req.column_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", &req.column_name))?;
+    // probing for values would reveal what a masked column contains
+    if locks.table_meta.column_masks.contains_key(&col_name) {
+      server.authorize(&[Access::table(Verb::Unmask, &req.table_name)]).await?;
+    }
+    let value = write_to_partition_rest::parse_field_value(
+      &req.value,
+      common::unwrap_dtype(col_meta.dtype)?,
+    )?;
+    let partition_filter = write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?;
+    let list_req = ListSegmentsRequest {
+      table_name: req.table_name.clone(),
+      ..Default::default()
+    };
+    let listed = ListSegmentsOp { req: list_req, partition_conditions: Vec::new() }.execute_with_locks(server, locks).await?;
+
+    let mut segments = Vec::new();
+    for segment in &listed.segments {
+      let is_match = partition_filter.iter()
+        .all(|(name, value)| segment.partition.get(name) == Some(value));
+      if !is_match {
+        continue;
+      }
+
+      let partition_key = PartitionKey {
+        table_name: req.table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      // hold the read lock so compaction can't remove the filter's version
+      let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+      let segment_guard = segment_lock.read().await;
+      let segment_meta = match &*segment_guard {
+        Some(segment_meta) => segment_meta,
+        None => continue,
+      };
+      let compaction_key = segment_key.compaction_key(segment_meta.read_version);
+      let compaction = server.compaction_cache
+        .get_lock(&compaction_key)
+        .await?
+        .read()
+        .await
+        .clone()
+        .unwrap_or_default();
+
+      let is_covered = compaction.bloom_filter_columns.contains(&col_name) &&
+        segment_meta.all_time_n == compaction.all_time_compacted_n;
+      let may_contain = if is_covered {
+        let path = dirs::bloom_filter_file(dir, &compaction_key, &col_name);
+        let bytes = common::read_or_empty(&path).await?;
+        BloomFilter::from_bytes(&bytes, &path)?.may_contain(&value)
+      } else {
+        true
+      };
+      segments.push(SegmentContainsSerde {
+        partition: segment.partition.iter()
+          .map(|(name, value)| Ok((name.to_string(), partition_field_to_json(value)?)))
+          .collect::<ServerResult<_>>()?,
+        segment_id: segment.segment_id.clone(),
+        may_contain,
+        used_bloom_filter: is_covered,
+      });
+    }
+
+    Ok(CheckSegmentContainsResponseSerde { segments })
+  }
+}
+
+impl RestRoute for CheckSegmentContainsOp {
+  type Req = CheckSegmentContainsRequestSerde;
+
+  const ROUTE_NAME: &'static str = "check_segment_contains";
+
+  fn new_op(req: Self::Req) -> CheckSegmentContainsOp {
+    CheckSegmentContainsOp { req }
+  }
+}
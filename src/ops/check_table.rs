@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::pin_mut;
+use futures::StreamExt;
+use pancake_db_idl::schema::ColumnMeta;
+
+use crate::errors::{Contextable, ServerError, ServerResult};
+use crate::locks::table::TableWriteLocks;
+use crate::metadata::compaction::Compaction;
+use crate::metadata::PersistentMetadata;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{CheckTableIssueKindSerde, CheckTableIssueSerde, CheckTableRequestSerde, CheckTableResponseSerde};
+use crate::server::Server;
+use crate::metadata::segment::SegmentMetadata;
+use crate::server::authz::{Access, Verb};
+use crate::server::cancel;
+use crate::types::{NormalizedPartition, PartitionKey, SegmentKey};
+use crate::utils::checksum;
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::navigation;
+use crate::utils::storage;
+use crate::utils::vfs;
+
+pub struct CheckTableOp {
+  pub req: CheckTableRequestSerde,
+}
+
+#[derive(Default)]
+struct CheckReport {
+  n_segments_checked: u32,
+  issues: Vec<CheckTableIssueSerde>,
+}
+
+impl CheckReport {
+  fn add(&mut self, kind: CheckTableIssueKindSerde, path: &Path, message: impl AsRef<str>) {
+    self.add_maybe_repaired(kind, path, message, false);
+  }
+
+  fn add_maybe_repaired(
+    &mut self,
+    kind: CheckTableIssueKindSerde,
+    path: &Path,
+    message: impl AsRef<str>,
+    repaired: bool,
+  ) {
+    log::warn!("check found {:?} issue at {:?}: {}", kind, path, message.as_ref());
+    self.issues.push(CheckTableIssueSerde {
+      kind,
+      path: path.to_string_lossy().to_string(),
+      message: message.as_ref().to_string(),
+      repaired,
+    });
+  }
+}
+
+fn parse_version(fname: &str) -> Option<u64> {
+  fname.strip_prefix('v').and_then(|v| v.parse::<u64>().ok())
+}
+
+impl CheckTableOp {
+  async fn check_segment(
+    &self,
+    server: &Server,
+    augmented_cols: &HashMap<String, ColumnMeta>,
+    segment_key: &SegmentKey,
+    report: &mut CheckReport,
+  ) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let segment_dir = dirs::segment_dir(dir, segment_key);
+    let segment_lock = server.segment_metadata_cache.get_lock(segment_key).await?;
+    let segment_meta = match segment_lock.read().await.clone() {
+      Some(segment_meta) => segment_meta,
+      None => {
+        report.add(
+          CheckTableIssueKindSerde::MissingSegmentMetadata,
+          &segment_dir,
+          "segment directory has no segment metadata",
+        );
+        return Ok(());
+      }
+    };
+    report.n_segments_checked += 1;
+
+    self.check_versions(dir, segment_key, &segment_meta, report).await?;
+    let compaction_key = segment_key.compaction_key(segment_meta.read_version);
+    if !common::file_exists(dirs::version_dir(dir, &compaction_key)).await? {
+      // missing version was already reported
+      return Ok(());
+    }
+    let compaction = server.compaction_cache
+      .get_lock(&compaction_key)
+      .await?
+      .read()
+      .await
+      .clone()
+      .unwrap_or_default();
+
+    self.check_staged_rows(dir, segment_key, &segment_meta, report).await;
+    if segment_meta.all_time_n < segment_meta.staged_n + compaction.all_time_compacted_n {
+      report.add(
+        CheckTableIssueKindSerde::RowCountMismatch,
+        &segment_dir,
+        format!(
+          "segment has {} rows but {} are staged and {} are compacted",
+          segment_meta.all_time_n,
+          segment_meta.staged_n,
+          compaction.all_time_compacted_n,
+        ),
+      );
+    } else {
+      let mut col_names = segment_meta.explicit_columns.iter().collect::<Vec<_>>();
+      col_names.sort();
+      for col_name in col_names {
+        if let Some(col_meta) = augmented_cols.get(col_name) {
+          self.check_column(
+            server,
+            segment_key,
+            &segment_meta,
+            &compaction,
+            col_name,
+            col_meta,
+            report,
+          ).await;
+        }
+      }
+    }
+    self.check_deletions(server, segment_key, &segment_meta, report).await?;
+    Ok(())
+  }
+
+  // Versions newer than the read version that aren't being written to are
+  // left over from an interrupted compaction and can safely be removed.
+  // Older versions may still serve ongoing reads, so we leave them for
+  // compaction to clean up.
+  async fn check_versions(
+    &self,
+    dir: &Path,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+    report: &mut CheckReport,
+  ) -> ServerResult<()> {
+    for &version in &segment_meta.write_versions {
+      let version_dir = dirs::version_dir(dir, &segment_key.compaction_key(version));
+      if !common::file_exists(&version_dir).await? {
+        report.add(
+          CheckTableIssueKindSerde::MissingVersion,
+          &version_dir,
+          format!("segment metadata refers to version {} which does not exist", version),
+        );
+      }
+    }
+
+    let mut read_dir = vfs::read_dir(dirs::segment_dir(dir, segment_key)).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      if !entry.file_type().await?.is_dir() {
+        continue;
+      }
+      let maybe_version = entry.file_name()
+        .to_str()
+        .and_then(parse_version);
+      let version = match maybe_version {
+        Some(version) => version,
+        None => continue,
+      };
+      if version > segment_meta.read_version && !segment_meta.write_versions.contains(&version) {
+        let repaired = if self.req.repair {
+          vfs::remove_dir_all(entry.path()).await?;
+          true
+        } else {
+          false
+        };
+        report.add_maybe_repaired(
+          CheckTableIssueKindSerde::DanglingVersion,
+          &entry.path(),
+          format!(
+            "version {} is newer than read version {} but not being written",
+            version,
+            segment_meta.read_version,
+          ),
+          repaired,
+        );
+      }
+    }
+    Ok(())
+  }
+
+  async fn check_staged_rows(
+    &self,
+    dir: &Path,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+    report: &mut CheckReport,
+  ) {
+    let path = dirs::staged_rows_path(dir, segment_key);
+    let n_res = common::read_or_empty(&path)
+      .await
+      .and_then(|bytes| common::staged_bytes_to_rows(&bytes))
+      .map(|rows| rows.len());
+    match n_res {
+      Ok(n) if n == segment_meta.staged_n as usize => (),
+      Ok(n) => report.add(
+        CheckTableIssueKindSerde::RowCountMismatch,
+        &path,
+        format!("found {} staged rows but segment metadata has {}", n, segment_meta.staged_n),
+      ),
+      Err(e) => report.add(CheckTableIssueKindSerde::UnreadableFile, &path, e.to_string()),
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  async fn check_column(
+    &self,
+    server: &Server,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+    compaction: &Compaction,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    report: &mut CheckReport,
+  ) {
+    let dir = &server.opts.dir;
+    let read_version = segment_meta.read_version;
+    let compaction_key = segment_key.compaction_key(read_version);
+
+    let compact_path = dirs::compact_col_file(dir, &compaction_key, col_name);
+    if compaction.checksummed && compaction.col_codecs.contains_key(col_name) {
+      let mut paths = vec![compact_path.clone()];
+      for increment in 0..compaction.increment_ns.len() {
+        paths.push(dirs::compact_increment_col_file(dir, &compaction_key, col_name, increment));
+      }
+      for path in &paths {
+        let checksum_res = async {
+          let maybe_cipher = server.column_cipher(compaction).await?;
+          let bytes = storage::read_or_empty(path, maybe_cipher.as_deref()).await?;
+          if !bytes.is_empty() {
+            checksum::verify_and_strip_footer(&bytes, path)?;
+          }
+          ServerResult::Ok(())
+        }.await;
+        if let Err(e) = checksum_res {
+          report.add(CheckTableIssueKindSerde::ChecksumMismatch, path, e.to_string());
+          return;
+        }
+      }
+    }
+
+    let compacted_n = match compaction.stored_n() {
+      Ok(compacted_n) => compacted_n,
+      Err(e) => {
+        report.add(CheckTableIssueKindSerde::RowCountMismatch, &compact_path, e.to_string());
+        return;
+      }
+    };
+    let compact_res = server.read_compact_col(
+      segment_key,
+      col_name,
+      col_meta,
+      read_version,
+      compaction,
+      usize::MAX,
+    ).await;
+    match compact_res {
+      Ok(values) if values.len() == compacted_n as usize => (),
+      Ok(values) => report.add(
+        CheckTableIssueKindSerde::RowCountMismatch,
+        &compact_path,
+        format!("found {} compacted rows but expected {}", values.len(), compacted_n),
+      ),
+      Err(e) => report.add(CheckTableIssueKindSerde::UnreadableFile, &compact_path, e.to_string()),
+    }
+
+    let flush_path = dirs::flush_col_file(dir, &compaction_key, col_name);
+    let flushed_n = match common::flush_only_n(segment_meta, compaction) {
+      Ok(flushed_n) => flushed_n,
+      Err(e) => {
+        report.add(CheckTableIssueKindSerde::RowCountMismatch, &flush_path, e.to_string());
+        return;
+      }
+    };
+    let flush_res = server.read_flush_col(
+      segment_key,
+      col_name,
+      col_meta,
+      read_version,
+      compaction,
+      usize::MAX,
+    ).await;
+    match flush_res {
+      Ok(values) if values.len() == flushed_n as usize => (),
+      Ok(values) => report.add(
+        CheckTableIssueKindSerde::RowCountMismatch,
+        &flush_path,
+        format!("found {} flushed rows but expected {}", values.len(), flushed_n),
+      ),
+      Err(e) => report.add(CheckTableIssueKindSerde::UnreadableFile, &flush_path, e.to_string()),
+    }
+  }
+
+  async fn check_deletions(
+    &self,
+    server: &Server,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+    report: &mut CheckReport,
+  ) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let compaction_key = segment_key.compaction_key(segment_meta.read_version);
+    let pre_path = dirs::pre_compaction_deletions_path(dir, &compaction_key);
+    let post_path = dirs::post_compaction_deletions_path(
+      dir,
+      &compaction_key,
+      segment_meta.deletion_id,
+    );
+
+    let pre = match server.read_pre_compaction_deletions(&compaction_key).await {
+      Ok(pre) => pre,
+      Err(e) => {
+        report.add(CheckTableIssueKindSerde::UnreadableFile, &pre_path, e.to_string());
+        return Ok(());
+      }
+    };
+    let post = match server.read_post_compaction_deletions(
+      &compaction_key,
+      segment_meta.deletion_id,
+    ).await {
+      Ok(post) => post,
+      Err(e) => {
+        report.add(CheckTableIssueKindSerde::UnreadableFile, &post_path, e.to_string());
+        return Ok(());
+      }
+    };
+
+    let n_pre_deleted = pre.iter().filter(|&&is_deleted| is_deleted).count();
+    let n_post_deleted = post.iter().filter(|&&is_deleted| is_deleted).count();
+    let all_time_n = segment_meta.all_time_n as usize;
+    let mut is_consistent = true;
+    if pre.len() > all_time_n {
+      is_consistent = false;
+      report.add(
+        CheckTableIssueKindSerde::DeletionMismatch,
+        &pre_path,
+        format!("covers {} rows but segment only has {}", pre.len(), all_time_n),
+      );
+    }
+    if post.len() + n_pre_deleted > all_time_n {
+      is_consistent = false;
+      report.add(
+        CheckTableIssueKindSerde::DeletionMismatch,
+        &post_path,
+        format!(
+          "covers {} rows but segment only has {} rows not deleted before compaction",
+          post.len(),
+          all_time_n.saturating_sub(n_pre_deleted),
+        ),
+      );
+    }
+    let n_deleted = n_pre_deleted + n_post_deleted;
+    if n_deleted != segment_meta.all_time_deleted_n as usize {
+      // the deletion files say which rows are deleted, so when they fit the
+      // segment, the count can be taken from them
+      let repaired = if self.req.repair && is_consistent {
+        Self::repair_deleted_n(server, segment_key, n_deleted as u64).await?;
+        true
+      } else {
+        false
+      };
+      report.add_maybe_repaired(
+        CheckTableIssueKindSerde::DeletionMismatch,
+        &dirs::segment_dir(dir, segment_key),
+        format!(
+          "deletion files mark {} rows deleted but segment metadata has {}",
+          n_deleted,
+          segment_meta.all_time_deleted_n,
+        ),
+        repaired,
+      );
+    }
+    Ok(())
+  }
+
+  async fn repair_deleted_n(server: &Server, segment_key: &SegmentKey, deleted_n: u64) -> ServerResult<()> {
+    let segment_lock = server.segment_metadata_cache.get_lock(segment_key).await?;
+    let mut segment_guard = segment_lock.write().await;
+    if let Some(segment_meta) = segment_guard.as_mut() {
+      segment_meta.all_time_deleted_n = deleted_n;
+      segment_meta.overwrite(&server.opts.dir, segment_key).await?;
+    }
+    Ok(())
+  }
+
+  async fn check_tmp_files(&self, server: &Server, report: &mut CheckReport) -> ServerResult<()> {
+    let min_age = Duration::from_secs(server.opts.orphaned_tmp_file_seconds);
+    for orphan in server.orphaned_tmp_files(min_age).await? {
+      let repaired = if self.req.repair {
+        vfs::remove_file(&orphan.path).await?;
+        true
+      } else {
+        false
+      };
+      report.add_maybe_repaired(
+        CheckTableIssueKindSerde::OrphanedTmpFile,
+        &orphan.path,
+        format!("tmp file was last modified {}s ago", orphan.age.as_secs()),
+        repaired,
+      );
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl ServerOp for CheckTableOp {
+  type Locks = TableWriteLocks;
+  type Response = CheckTableResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  // We hold the table write lock throughout so that no writes, flushes,
+  // compactions, or deletions can change files as we check them.
+  // Only issues that can be fixed without losing data are repaired:
+  // dangling versions, orphaned tmp files, and deleted row counts that
+  // disagree with the deletion files.
+  async fn execute_with_locks(
+    &self,
+    server: &Server,
+    locks: TableWriteLocks,
+  ) -> ServerResult<CheckTableResponseSerde> where TableWriteLocks: 'async_trait {
+    let table_name = &self.req.table_name;
+    common::validate_entity_name_for_read("table name", table_name)?;
+    let dir = &server.opts.dir;
+    let TableWriteLocks {
+      maybe_table_guard
+    } = locks;
+    let table_meta = match &*maybe_table_guard {
+      Some(table_meta) => table_meta,
+      None => return Err(ServerError::does_not_exist("table", table_name)),
+    };
+
+    log::info!("checking table {} (repair: {})", table_name, self.req.repair);
+    let schema = table_meta.schema();
+    let augmented_cols = common::augmented_columns(&schema);
+    let mut report = CheckReport::default();
+    for partition in navigation::partitions_for_table(
+      dir,
+      table_name,
+      &schema.partitioning,
+      &Vec::new(),
+    ).await.with_context(|| "while listing partitions")? {
+      let normalized = NormalizedPartition::from_raw_fields(&partition)?;
+      let partition_key = PartitionKey {
+        table_name: table_name.clone(),
+        partition: normalized,
+      };
+      let segment_id_stream = navigation::stream_segment_ids_for_partition(
+        dir,
+        partition_key.clone(),
+      );
+      pin_mut!(segment_id_stream);
+      while let Some(segment_id_result) = segment_id_stream.next().await {
+        cancel::check()?;
+        let segment_key = partition_key.segment_key(segment_id_result?);
+        self.check_segment(server, &augmented_cols, &segment_key, &mut report)
+          .await
+          .with_context(|| format!("while checking segment {}", segment_key))?;
+      }
+    }
+    self.check_tmp_files(server, &mut report)
+      .await
+      .with_context(|| "while checking tmp files")?;
+
+    log::info!(
+      "checked {} segments of table {} and found {} issues",
+      report.n_segments_checked,
+      table_name,
+      report.issues.len(),
+    );
+    Ok(CheckTableResponseSerde {
+      n_segments_checked: report.n_segments_checked,
+      issues: report.issues,
+    })
+  }
+}
+
+impl RestRoute for CheckTableOp {
+  type Req = CheckTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "check_table";
+
+  fn new_op(req: Self::Req) -> CheckTableOp {
+    CheckTableOp { req }
+  }
+}
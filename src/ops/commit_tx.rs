@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{Server, ServerResult};
+use crate::errors::{Contextable, ServerError};
+use crate::locks::partition::PartitionWriteLocks;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition::{PreparedWrite, WriteToPartitionOp};
+use crate::serde_models::{CommitTxRequestSerde, CommitTxResponseSerde};
+use crate::server::authz::Access;
+use crate::types::PartitionKey;
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+// Once a commit record exists, recovery finishes appending every staged
+// rows file it lists, so a crash mid-commit never leaves only some of the
+// transaction's partitions with its rows.
+#[derive(Serialize, Deserialize)]
+struct CommitAppend {
+  relative_path: PathBuf,
+  // length of the staged rows file before the append
+  offset: u64,
+  // base 64
+  staged_bytes: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CommitRecord {
+  appends: Vec<CommitAppend>,
+}
+
+pub struct CommitTxOp {
+  pub req: CommitTxRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for CommitTxOp {
+  type Locks = TrivialLocks;
+  type Response = CommitTxResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  // the transaction id is proof enough, since beginning the transaction
+  // required write access to its table
+  fn required_access(&self) -> Vec<Access> {
+    Vec::new()
+  }
+
+  // 1. take the transaction out of the cache; it is consumed even if
+  //    committing fails
+  // 2. obtain write locks on every partition in a consistent order and
+  //    prepare each partition's write
+  // 3. write the commit record (the commit point)
+  // 4. append to each staged rows file and update segment metadata,
+  //    redoing failed appends before any partition's locks are released
+  // 5. remove the commit record
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let dir = &server.opts.dir;
+    let tx_id = &self.req.tx_id;
+    let tx = server.transaction_cache.take(tx_id).await?;
+    let n_partitions = tx.writes.len();
+    let n_rows = tx.n_rows;
+    let table_name = tx.table_name;
+
+    // sorting keeps concurrent commits from deadlocking on each other's
+    // partitions
+    let mut writes: Vec<_> = tx.writes.into_iter()
+      .map(|(partition, req)| {
+        let partition_key = PartitionKey {
+          table_name: table_name.clone(),
+          partition,
+        };
+        (partition_key, req)
+      })
+      .collect();
+    writes.sort_by_key(|(partition_key, _)| partition_key.to_string());
+
+    let mut prepared_writes: Vec<PreparedWrite> = Vec::with_capacity(writes.len());
+    for (partition_key, req) in writes {
+      let table_locks = GlobalTableReadLocks::obtain(server, &table_name).await?;
+      let partition_locks = PartitionWriteLocks::from_table_read(
+        table_locks,
+        &partition_key,
+        server,
+      ).await?;
+      let prepared = WriteToPartitionOp { req }.prepare(partition_locks)
+        .await
+        .with_context(|| format!("while preparing transaction write to {}", partition_key))?;
+      prepared_writes.push(prepared);
+    }
+
+    let mut appends = Vec::with_capacity(prepared_writes.len());
+    for prepared in &prepared_writes {
+      let offset = common::file_len_or_zero(
+        dirs::staged_rows_path(dir, &prepared.segment_key)
+      ).await?;
+      appends.push(CommitAppend {
+        relative_path: dirs::relative_staged_rows_path(&prepared.segment_key),
+        offset,
+        staged_bytes: base64::encode(&prepared.staged_bytes),
+      });
+    }
+    let record = CommitRecord { appends };
+    let record_path = dirs::transaction_dir(dir).join(format!("{}.json", tx_id));
+    common::overwrite_file_atomic(
+      &record_path,
+      serde_json::to_string(&record)?.as_bytes(),
+      dir,
+    ).await?;
+
+    // Past the commit point, every partition must get the rows before its
+    // locks are released. Failing that, the server stops taking writes, so
+    // nothing rewrites the staged rows files before recovery finishes the
+    // commit at restart.
+    if let Err(e) = Self::append_and_finish(server, &record, &mut prepared_writes).await {
+      server.mark_unhealthy(format!(
+        "transaction {} failed past its commit point; restart to finish it: {}",
+        tx_id,
+        e,
+      ));
+      return Err(e);
+    }
+    drop(prepared_writes);
+    vfs::remove_file(&record_path).await?;
+
+    log::debug!("committed transaction {} with {} rows", tx_id, n_rows);
+    Ok(CommitTxResponseSerde {
+      n_partitions,
+      n_rows,
+    })
+  }
+}
+
+impl CommitTxOp {
+  // A failed append may have been partial, so it's redone from the
+  // record's offset. Every partition is appended to and finished even after
+  // one fails, returning the first error.
+  async fn append_and_finish(
+    server: &Server,
+    record: &CommitRecord,
+    prepared_writes: &mut [PreparedWrite],
+  ) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let mut res = Ok(());
+    for (prepared, append) in prepared_writes.iter().zip(&record.appends) {
+      if let Err(e) = prepared.append(server).await {
+        log::warn!(
+          "redoing transaction append to {} after error: {}",
+          prepared.segment_key,
+          e,
+        );
+        if let Err(e) = Self::recover_append(dir, append).await {
+          log::error!("unable to redo transaction append to {}: {}", prepared.segment_key, e);
+          if res.is_ok() {
+            res = Err(e);
+          }
+        }
+      }
+    }
+
+    for prepared in prepared_writes.iter_mut() {
+      if let Err(e) = prepared.finish(server).await {
+        log::error!("unable to finish transaction write to {}: {}", prepared.segment_key, e);
+        if res.is_ok() {
+          res = Err(e);
+        }
+      }
+    }
+    res
+  }
+
+  // Finishes the appends of transactions that were committed but
+  // interrupted, returning the staged rows files they appended to. This must
+  // run before segments recover their writes, which then fill in segment
+  // metadata for the appended rows.
+  pub async fn recover(server: &Server) -> ServerResult<HashSet<PathBuf>> {
+    let dir = &server.opts.dir;
+    let mut staged_rows_paths = HashSet::new();
+    let mut read_dir = match vfs::read_dir(dirs::transaction_dir(dir)).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(staged_rows_paths),
+      Err(e) => return Err(e.into()),
+    };
+
+    while let Some(entry) = read_dir.next_entry().await? {
+      let record_path = entry.path();
+      log::debug!("finishing interrupted transaction commit {:?}", record_path);
+      let record_str = vfs::read_to_string(&record_path).await?;
+      let record: CommitRecord = serde_json::from_str(&record_str)
+        .map_err(|e| ServerError::corrupt(format!(
+          "unable to parse transaction commit record {:?}: {}",
+          record_path,
+          e,
+        )))?;
+      for append in &record.appends {
+        Self::recover_append(dir, append).await
+          .with_context(|| format!("while recovering transaction commit {:?}", record_path))?;
+        staged_rows_paths.insert(dir.join(&append.relative_path));
+      }
+      vfs::remove_file(&record_path).await?;
+    }
+    Ok(staged_rows_paths)
+  }
+
+  async fn recover_append(dir: &Path, append: &CommitAppend) -> ServerResult<()> {
+    let path = dir.join(&append.relative_path);
+    let staged_bytes = base64::decode(&append.staged_bytes)
+      .map_err(|e| ServerError::corrupt(format!(
+        "unable to decode staged bytes for {:?}: {}",
+        path,
+        e,
+      )))?;
+    let end = append.offset + staged_bytes.len() as u64;
+    let len = common::file_len_or_zero(&path).await?;
+    if len >= end {
+      return Ok(());
+    }
+    if len < append.offset {
+      return Err(ServerError::corrupt(format!(
+        "staged rows file {:?} is shorter ({}) than before the transaction appended to it ({})",
+        path,
+        len,
+        append.offset,
+      )));
+    }
+    if len > append.offset {
+      // a partial append; redo it from the start
+      let file = vfs::OpenOptions::new().write(true).open(&path).await?;
+      file.set_len(append.offset).await?;
+    }
+    common::append_to_file(&path, &staged_bytes).await
+  }
+}
+
+impl RestRoute for CommitTxOp {
+  type Req = CommitTxRequestSerde;
+
+  const ROUTE_NAME: &'static str = "commit_tx";
+
+  fn new_op(req: Self::Req) -> CommitTxOp {
+    CommitTxOp { req }
+  }
+}
@@ -1,47 +1,106 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::path::Path;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use chrono::{Duration, Utc};
 use pancake_db_core::{compression, deletion};
 use pancake_db_core::compression::ValueCodec;
-use pancake_db_idl::schema::{ColumnMeta, Schema};
-use tokio::fs;
-use tokio::sync::OwnedRwLockWriteGuard;
+use pancake_db_idl::dml::FieldValue;
+use pancake_db_idl::schema::ColumnMeta;
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock};
+use tokio::time::Instant;
 
-use crate::errors::{ServerError, ServerResult};
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::{ServerError, ServerErrorKind, ServerResult};
 use crate::locks::table::TableReadLocks;
 use crate::ops::traits::ServerOp;
+use crate::opt::RuntimeConfig;
 use crate::server::Server;
 use crate::metadata::compaction::Compaction;
 use crate::metadata::deletion::DeletionMetadata;
+use crate::metadata::manifest::MetadataBatch;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::segment::SegmentMetadata;
+use crate::metadata::table::TableMetadata;
+use crate::server::authz::{Access, Verb};
+use crate::server::cancel;
+use crate::server::ops_history::OpRecord;
+use crate::server::slow_ops;
 use crate::types::{CompactionKey, SegmentKey};
+use crate::utils::bloom::BloomFilter;
+use crate::utils::checksum;
 use crate::utils::common;
 use crate::utils::dirs;
+use crate::utils::encryption::Cipher;
+use crate::utils::hll::HyperLogLog;
+use crate::utils::storage;
+use crate::utils::vfs;
+
+// links an old version's compacted file into the new version's staging
+// dir, returning false if it doesn't exist
+async fn link_compacted_file(path: &Path, staged_path: &Path) -> ServerResult<bool> {
+  match vfs::hard_link(path, staged_path).await {
+    Ok(()) => Ok(true),
+    Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Ok(false),
+    Err(e) => Err(ServerError::from(e).with_context(format!(
+      "while linking compacted file {:?}",
+      path,
+    ))),
+  }
+}
+
+async fn publish_staged_file(staged_path: &Path, path: &Path) -> ServerResult<()> {
+  vfs::rename(staged_path, path).await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while publishing staged compaction file {:?}",
+      staged_path,
+    )))
+}
 
 struct CompactionAssessment {
   pub do_compaction: bool,
   pub old_version: u64,
   pub new_version: u64,
-  pub all_time_n_to_compact: u32,
+  pub all_time_n_to_compact: u64,
   pub deletion_id: u64,
   pub warrants_object_storage: bool,
+  // whether to append the new rows to the old version's compacted blocks
+  // instead of rewriting everything
+  pub incremental: bool,
+}
+
+// what a compaction did, for the ops history
+struct CompactionSummary {
+  pub n_rows: u64,
+  pub bytes_in: u64,
+  pub bytes_out: u64,
+  pub codecs: HashMap<String, String>,
+  // committed along with the new version
+  pub col_sketches: HashMap<String, HyperLogLog>,
 }
 
 pub struct CompactionOp {
   pub key: SegmentKey,
+  // compacts in full whenever there are rows to compact, ignoring the
+  // heuristics and full compaction hours
+  pub force: bool,
 }
 
 impl CompactionOp {
   async fn delete_old_versions(
     &self,
-    dir: &Path,
+    server: &Server,
     current_read_version: u64,
   ) -> ServerResult<()> {
-    let dir = dirs::segment_dir(dir, &self.key);
-    let mut read_dir = fs::read_dir(&dir).await?;
+    // ongoing reads may still need old versions they pinned
+    let pinned_versions = server.correlation_metadata_cache
+      .pinned_versions(&self.key)
+      .await;
+    let dir = dirs::segment_dir(&server.opts.dir, &self.key);
+    let mut read_dir = vfs::read_dir(&dir).await?;
     while let Ok(Some(entry)) = read_dir.next_entry().await {
       if !entry.file_type().await.unwrap().is_dir() {
         continue;
@@ -61,14 +120,20 @@ impl CompactionOp {
         continue;
       }
       let parsed: u64 = parts[1].parse::<u64>()?;
-      if parsed < current_read_version {
+      if parsed < current_read_version && pinned_versions.contains(&parsed) {
+        log::debug!(
+          "not deleting {} version {}; it is pinned by an ongoing read",
+          self.key,
+          parsed,
+        );
+      } else if parsed < current_read_version {
         log::info!(
           "deleting {} version {}",
           self.key,
           parsed,
         );
         let full_path = dir.join(fname);
-        fs::remove_dir_all(full_path).await?;
+        vfs::remove_dir_all(full_path).await?;
       }
     }
     Ok(())
@@ -77,21 +142,22 @@ impl CompactionOp {
   async fn assess_compaction(
     &self,
     server: &Server,
+    table_meta: &TableMetadata,
     segment_meta: &SegmentMetadata,
   ) -> ServerResult<CompactionAssessment> {
-    let opts = &server.opts;
+    let runtime_config = server.table_runtime_config(table_meta).await;
     let current_time = Utc::now();
-    if current_time - segment_meta.read_version_since > Duration::seconds(opts.delete_stale_compaction_seconds) {
+    if current_time - segment_meta.read_version_since > Duration::seconds(runtime_config.delete_stale_compaction_seconds) {
       log::debug!(
         "checking for old versions for {} version {} (current as of {:?}",
         self.key,
         segment_meta.read_version,
         segment_meta.read_version_since,
       );
-      self.delete_old_versions(&opts.dir, segment_meta.read_version).await?;
+      self.delete_old_versions(server, segment_meta.read_version).await?;
     }
 
-    let all_time_n_to_compact = segment_meta.all_time_n - segment_meta.staged_n as u32;
+    let all_time_n_to_compact = segment_meta.flushed_n()?;
     let new_version = segment_meta.read_version + 1;
     let mut res = CompactionAssessment {
       do_compaction: false,
@@ -100,8 +166,19 @@ impl CompactionOp {
       all_time_n_to_compact,
       deletion_id: segment_meta.deletion_id,
       warrants_object_storage: false,
+      incremental: false,
     };
-    if segment_meta.write_versions.len() > 1 || all_time_n_to_compact < opts.min_rows_for_compaction {
+    let is_compacting = segment_meta.write_versions.len() > 1;
+    if self.force && !is_compacting && all_time_n_to_compact > 0 {
+      log::debug!(
+        "will compact {}; compaction was forced",
+        self.key,
+      );
+      res.do_compaction = true;
+      res.warrants_object_storage = segment_meta.is_cold;
+      return Ok(res);
+    }
+    if is_compacting || all_time_n_to_compact < runtime_config.min_rows_for_compaction as u64 {
       log::debug!(
         "will not compact {}; already compacting or too few rows",
         self.key,
@@ -109,7 +186,7 @@ impl CompactionOp {
       return Ok(res);
     }
 
-    if current_time - segment_meta.read_version_since < Duration::seconds(opts.min_compaction_intermission_seconds) {
+    if current_time - segment_meta.read_version_since < Duration::seconds(runtime_config.min_compaction_intermission_seconds) {
       log::debug!(
         "will not compact {}; was compacted too recently",
         self.key,
@@ -128,7 +205,7 @@ impl CompactionOp {
 
     let n_rows_has_increased = all_time_n_to_compact > existing_compaction.all_time_compacted_n;
     let n_rows_has_doubled = all_time_n_to_compact >= 2 * existing_compaction.all_time_compacted_n;
-    let n_rows_has_been_constant = current_time - segment_meta.last_flush_at > Duration::seconds(opts.compact_as_constant_seconds);
+    let n_rows_has_been_constant = current_time - segment_meta.last_flush_at > Duration::seconds(runtime_config.compact_as_constant_seconds);
     if n_rows_has_doubled || (n_rows_has_increased && n_rows_has_been_constant) {
       log::debug!(
         "will compact {}",
@@ -136,6 +213,24 @@ impl CompactionOp {
       );
       res.do_compaction = true;
       res.warrants_object_storage = segment_meta.is_cold;
+      // Once the rows have doubled, rewriting them all compresses much
+      // better than another increment would, so that's when full rewrites
+      // happen, along with every max_compaction_increments-th compaction.
+      res.incremental = !n_rows_has_doubled &&
+        existing_compaction.increment_ns.len() < runtime_config.max_compaction_increments as usize &&
+        self.can_append(server, table_meta, &existing_compaction);
+      let is_full_rewrite = !res.incremental && existing_compaction.all_time_compacted_n > 0;
+      let is_off_hours = matches!(
+        runtime_config.full_compaction_hours,
+        Some(window) if !window.contains(current_time)
+      );
+      if is_full_rewrite && is_off_hours {
+        log::debug!(
+          "will not compact {} yet; full rewrites wait for the full compaction hours",
+          self.key,
+        );
+        res.do_compaction = false;
+      }
     } else {
       log::debug!(
         "will not compact {}; recently active without enough new rows",
@@ -147,49 +242,258 @@ impl CompactionOp {
 
   fn plan_compaction(
     &self,
+    table_meta: &TableMetadata,
     augmented_cols: &HashMap<String, ColumnMeta>,
     assessment: &CompactionAssessment,
-    all_time_omitted_n: u32,
-  ) -> Compaction {
+    all_time_omitted_n: u64,
+    encryption_key_id: Option<String>,
+    maybe_appended_compaction: Option<&Compaction>,
+  ) -> ServerResult<Compaction> {
     let mut col_codecs = HashMap::new();
 
     for (col_name, col_meta) in augmented_cols {
-      col_codecs.insert(
-        col_name.to_string(),
-        compression::choose_codec(col_meta.dtype())
-      );
+      // appended columns keep the codec their old blocks were compressed with
+      let codec = maybe_appended_compaction
+        .and_then(|old_compaction| old_compaction.col_codecs.get(col_name).cloned())
+        .unwrap_or_else(|| compression::choose_codec(col_meta.dtype()));
+      col_codecs.insert(col_name.to_string(), codec);
     }
+    let increment_ns = match maybe_appended_compaction {
+      Some(old_compaction) => {
+        let mut increment_ns = old_compaction.increment_ns.clone();
+        increment_ns.push(common::checked_sub_n(
+          assessment.all_time_n_to_compact,
+          old_compaction.all_time_compacted_n,
+          "previously compacted rows",
+        )?);
+        increment_ns
+      },
+      None => Vec::new(),
+    };
 
-    Compaction {
+    Ok(Compaction {
       all_time_compacted_n: assessment.all_time_n_to_compact,
       all_time_omitted_n,
       col_codecs,
+      checksummed: true,
+      col_sketches: HashMap::new(),
+      bloom_filter_columns: table_meta.bloom_filter_columns.iter()
+        .filter(|col_name| augmented_cols.contains_key(*col_name))
+        .cloned()
+        .collect(),
+      sort_columns: table_meta.sort_columns.clone(),
+      encryption_key_id,
+      sealed: false,
+      increment_ns,
+    })
+  }
+
+  // Appending links the old version's compacted blocks into the new one as
+  // they are, so it needs them to be checksummed and encrypted the same way
+  // new blocks will be, and in the same row order.
+  fn can_append(
+    &self,
+    server: &Server,
+    table_meta: &TableMetadata,
+    old_compaction: &Compaction,
+  ) -> bool {
+    old_compaction.all_time_compacted_n > 0 &&
+      old_compaction.checksummed &&
+      old_compaction.sort_columns.is_empty() &&
+      table_meta.sort_columns.is_empty() &&
+      old_compaction.encryption_key_id == server.opts.encryption_key_id
+  }
+
+  // Returns the old position of each row in sorted order, or None if the
+  // table has no sort columns. Ties keep their old order.
+  async fn plan_sort(
+    &self,
+    server: &Server,
+    table_meta: &TableMetadata,
+    assessment: &CompactionAssessment,
+    old_compaction: &Compaction,
+  ) -> ServerResult<Option<Vec<usize>>> {
+    if table_meta.sort_columns.is_empty() {
+      return Ok(None);
+    }
+
+    let schema = table_meta.schema();
+    let n = assessment.all_time_n_to_compact as usize;
+    let mut sort_values = Vec::with_capacity(table_meta.sort_columns.len());
+    for col_name in &table_meta.sort_columns {
+      let col_meta = schema.columns.get(col_name)
+        .ok_or_else(|| ServerError::internal(format!("sort column {} is not in the schema", col_name)))?;
+      sort_values.push(server.read_typed_col(
+        &self.key,
+        col_name,
+        col_meta,
+        assessment.old_version,
+        old_compaction,
+        n,
+      ).await?);
     }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| {
+      for values in &sort_values {
+        let ordering = values.compare_rows(i, j);
+        if ordering != Ordering::Equal {
+          return ordering;
+        }
+      }
+      Ordering::Equal
+    });
+    Ok(Some(order))
   }
 
-  async fn execute_col_compaction(
+  // reads the rows to compact, in sorted order if sorting
+  async fn read_col_to_compact(
     &self,
     server: &Server,
     col_name: &str,
     col_meta: &ColumnMeta,
     assessment: &CompactionAssessment,
-    old_compression_params: Option<&String>,
-    compressor: &dyn ValueCodec,
-  ) -> ServerResult<()> {
+    old_compaction: &Compaction,
+    sort_order: Option<&[usize]>,
+  ) -> ServerResult<Vec<FieldValue>> {
     let values = server.read_col(
       &self.key,
       col_name,
       col_meta,
       assessment.old_version,
-      old_compression_params,
+      old_compaction,
       assessment.all_time_n_to_compact as usize,
     ).await?;
-    let bytes = compressor.compress(&values, col_meta.nested_list_depth as u8)?;
+    // columns that were never explicit have no values to sort
+    match sort_order {
+      Some(order) if values.len() == order.len() => Ok(order.iter().map(|&i| values[i].clone()).collect()),
+      _ => Ok(values),
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  async fn execute_col_compaction(
+    &self,
+    server: &Server,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    assessment: &CompactionAssessment,
+    compressor: &dyn ValueCodec,
+    maybe_cipher: Option<&Cipher>,
+    values: &[FieldValue],
+  ) -> ServerResult<usize> {
     let compaction_key = self.key.compaction_key(assessment.new_version);
-    common::append_to_file(
-      &dirs::compact_col_file(&server.opts.dir, &compaction_key, col_name),
-      bytes.as_slice(),
+    Self::write_compacted_block(
+      &dirs::staged_compact_col_file(&server.opts.dir, &compaction_key, col_name),
+      col_meta,
+      compressor,
+      maybe_cipher,
+      values,
+    ).await
+  }
+
+  // returns the number of bytes written
+  async fn write_compacted_block(
+    staged_path: &Path,
+    col_meta: &ColumnMeta,
+    compressor: &dyn ValueCodec,
+    maybe_cipher: Option<&Cipher>,
+    values: &[FieldValue],
+  ) -> ServerResult<usize> {
+    let bytes = checksum::with_footer(
+      compressor.compress(values, col_meta.nested_list_depth as u8)?
+    );
+    storage::append(staged_path, bytes.as_slice(), maybe_cipher).await?;
+    Ok(bytes.len())
+  }
+
+  // Links the old version's compacted blocks of a column into the new
+  // version and compresses only the rows flushed since into a new
+  // increment. Returns those rows and the bytes written, or None if the
+  // column has no compacted data to append to, in which case it must be
+  // rewritten in full.
+  #[allow(clippy::too_many_arguments)]
+  async fn execute_col_append(
+    &self,
+    server: &Server,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    assessment: &CompactionAssessment,
+    old_compaction: &Compaction,
+    compressor: &dyn ValueCodec,
+    maybe_cipher: Option<&Cipher>,
+  ) -> ServerResult<Option<(Vec<FieldValue>, usize)>> {
+    let dir = &server.opts.dir;
+    let old_compaction_key = self.key.compaction_key(assessment.old_version);
+    let new_compaction_key = self.key.compaction_key(assessment.new_version);
+    if !old_compaction.col_codecs.contains_key(col_name) {
+      return Ok(None);
+    }
+    let is_linked = link_compacted_file(
+      &dirs::compact_col_file(dir, &old_compaction_key, col_name),
+      &dirs::staged_compact_col_file(dir, &new_compaction_key, col_name),
+    ).await?;
+    if !is_linked {
+      return Ok(None);
+    }
+    // columns rewritten in full since have no files for earlier increments
+    for increment in 0..old_compaction.increment_ns.len() {
+      link_compacted_file(
+        &dirs::compact_increment_col_file(dir, &old_compaction_key, col_name, increment),
+        &dirs::staged_compact_increment_col_file(dir, &new_compaction_key, col_name, increment),
+      ).await?;
+    }
+
+    let new_n = common::checked_sub_n(
+      assessment.all_time_n_to_compact,
+      old_compaction.all_time_compacted_n,
+      "previously compacted rows",
+    )?;
+    let values = server.read_flush_col(
+      &self.key,
+      col_name,
+      col_meta,
+      assessment.old_version,
+      old_compaction,
+      new_n as usize,
     ).await?;
+    let mut n_bytes = 0;
+    if !values.is_empty() {
+      n_bytes = Self::write_compacted_block(
+        &dirs::staged_compact_increment_col_file(
+          dir,
+          &new_compaction_key,
+          col_name,
+          old_compaction.increment_ns.len(),
+        ),
+        col_meta,
+        compressor,
+        maybe_cipher,
+        &values,
+      ).await?;
+    }
+    Ok(Some((values, n_bytes)))
+  }
+
+  // moves everything staged into the new version's dir
+  async fn publish_staged_files(
+    &self,
+    server: &Server,
+    new_compaction_key: &CompactionKey,
+  ) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let staging_dir = dirs::compaction_staging_dir(dir, new_compaction_key);
+    let mut staged_paths = Vec::new();
+    let mut read_dir = vfs::read_dir(&staging_dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      staged_paths.push(entry.path());
+    }
+    let version_dir = dirs::version_dir(dir, new_compaction_key);
+    for staged_path in &staged_paths {
+      let path = version_dir.join(staged_path.file_name().unwrap());
+      publish_staged_file(staged_path, &path).await?;
+    }
+    vfs::remove_dir_all(&staging_dir).await?;
     Ok(())
   }
 
@@ -200,37 +504,29 @@ impl CompactionOp {
     old_compaction_key: &CompactionKey,
     new_compaction_key: &CompactionKey,
     assessment: &CompactionAssessment,
-  ) -> ServerResult<u32> {
-    let old_pre_compaction_deletions = server.read_pre_compaction_deletions(
-      old_compaction_key
-    ).await?;
-    let old_post_compaction_deletions = server.read_post_compaction_deletions(
+    sort_order: Option<&[usize]>,
+  ) -> ServerResult<u64> {
+    let mut new_pre_compaction_deletions = server.read_combined_deletions(
       old_compaction_key,
       assessment.deletion_id,
     ).await?;
-
-    let n_pre = old_pre_compaction_deletions.len();
-    let n_post = old_post_compaction_deletions.len();
-    if n_pre == 0 && n_post == 0 {
+    if new_pre_compaction_deletions.is_empty() {
       return Ok(0);
     }
-
-    let mut new_pre_compaction_deletions = Vec::new();
-    let mut row_id = 0;
-    let mut post_i = 0;
-    let mut all_time_omitted_n = 0;
-    while row_id < n_pre || post_i < n_post {
-      let pre_deleted = row_id < n_pre && old_pre_compaction_deletions[row_id];
-      let post_deleted = post_i < n_post && old_post_compaction_deletions[post_i];
-      let is_deleted = pre_deleted || post_deleted;
-      new_pre_compaction_deletions.push(is_deleted);
-      if is_deleted && row_id < assessment.all_time_n_to_compact as usize {
-        all_time_omitted_n += 1;
+    let all_time_omitted_n = new_pre_compaction_deletions.iter()
+      .take(assessment.all_time_n_to_compact as usize)
+      .filter(|&&is_deleted| is_deleted)
+      .count() as u64;
+
+    // deletions are by position, so they move with their sorted rows
+    if let Some(order) = sort_order {
+      let mut sorted_deletions: Vec<bool> = order.iter()
+        .map(|&i| new_pre_compaction_deletions.get(i).cloned().unwrap_or(false))
+        .collect();
+      if new_pre_compaction_deletions.len() > order.len() {
+        sorted_deletions.extend(&new_pre_compaction_deletions[order.len()..]);
       }
-      if !pre_deleted {
-        post_i += 1
-      }
-      row_id += 1
+      new_pre_compaction_deletions = sorted_deletions;
     }
 
     let deletion_bytes = deletion::compress_deletions(&new_pre_compaction_deletions)?;
@@ -248,18 +544,19 @@ impl CompactionOp {
   async fn compact(
     &self,
     server: &Server,
-    schema: &Schema,
+    table_meta: &TableMetadata,
     assessment: &CompactionAssessment,
     deletion_meta_guard: OwnedRwLockWriteGuard<Option<DeletionMetadata>>,
-  ) -> ServerResult<()> {
+  ) -> ServerResult<CompactionSummary> {
     let dir = &server.opts.dir;
-    let augmented_cols = common::augmented_columns(schema);
+    let schema = table_meta.schema();
+    let augmented_cols = common::augmented_columns(&schema);
     let old_compaction_key = self.key.compaction_key(assessment.old_version);
     let old_compaction = {
       let old_compaction_lock = server.compaction_cache
         .get_lock(&old_compaction_key)
         .await?;
-      let new_compaction_guard = old_compaction_lock.read().await;
+      let new_compaction_guard = slow_ops::wait_for_lock("compaction", old_compaction_lock.read()).await?;
       new_compaction_guard.clone().unwrap_or_default()
     };
     let new_compaction_key = self.key.compaction_key(assessment.new_version);
@@ -270,6 +567,10 @@ impl CompactionOp {
       assessment.all_time_n_to_compact,
     );
 
+    // Sorting has to be planned before deletions are compacted, since
+    // deletions move with their rows.
+    let sort_order = self.plan_sort(server, table_meta, assessment, &old_compaction).await?;
+
     // First compact deletion information, since deletions are locked.
     // Along the way we'll be able to count the number of rows omitted
     // We'll compact each column later, since flushes aren't locked.
@@ -278,8 +579,14 @@ impl CompactionOp {
       &old_compaction_key,
       &new_compaction_key,
       assessment,
+      sort_order.as_deref(),
     ).await?;
-    drop(deletion_meta_guard);
+    // Deletions find sorted rows through the new version's compacted row
+    // ids, so when sorting they stay locked until those are written.
+    let mut maybe_deletion_meta_guard = Some(deletion_meta_guard);
+    if sort_order.is_none() {
+      drop(maybe_deletion_meta_guard.take());
+    }
     log::debug!(
       "wrote deletion information for {} deletion id {} all_time_omitted_n {}",
       new_compaction_key,
@@ -288,7 +595,21 @@ impl CompactionOp {
     );
 
     // Write the compaction metadata
-    let compaction = self.plan_compaction(&augmented_cols, assessment, all_time_omitted_n);
+    let is_appending = assessment.incremental;
+    if is_appending {
+      log::debug!(
+        "appending to the compacted blocks of {} incrementally",
+        old_compaction_key,
+      );
+    }
+    let compaction = self.plan_compaction(
+      table_meta,
+      &augmented_cols,
+      assessment,
+      all_time_omitted_n,
+      server.opts.encryption_key_id.clone(),
+      if is_appending { Some(&old_compaction) } else { None },
+    )?;
     {
       let new_compaction_lock = server.compaction_cache
         .get_lock(&new_compaction_key)
@@ -302,31 +623,117 @@ impl CompactionOp {
       new_compaction_key,
     );
 
-    // Now we compact each column.
-    for (col_name, col_meta) in &augmented_cols {
-      let old_compression_params = old_compaction.col_codecs
-        .get(col_name);
+    // Now we compact each column, sketching the schema's columns and
+    // building bloom filters as we go. Everything is staged until all of it
+    // has been written, so a version's compacted files are complete once
+    // they're in its dir.
+    common::create_if_new(dirs::compaction_staging_dir(dir, &new_compaction_key)).await?;
+    let maybe_cipher = server.column_cipher(&compaction).await?;
+    let mut col_sketches = HashMap::new();
+    let mut n_rows = 0;
+    let mut bytes_in = 0;
+    let mut bytes_out = 0;
+    let mut col_names: Vec<&String> = augmented_cols.keys().collect();
+    col_names.sort_by_key(|col_name| col_name.as_str() != ROW_ID_COLUMN_NAME);
+    for col_name in col_names {
+      cancel::check()?;
+      let col_meta = &augmented_cols[col_name];
       let compressor = compression::new_codec(
         common::unwrap_dtype(col_meta.dtype)?,
         compaction.col_codecs.get(col_name).unwrap()
       )?;
-      self.execute_col_compaction(
-        server,
-        col_name,
-        col_meta,
-        assessment,
-        old_compression_params,
-        &*compressor,
-      ).await?;
+      // bloom filters, and sketches without an old one to merge into, need
+      // every value, so those columns are rewritten in full
+      let is_appendable = is_appending &&
+        !compaction.bloom_filter_columns.contains(col_name) &&
+        (!schema.columns.contains_key(col_name) || old_compaction.col_sketches.contains_key(col_name));
+      let maybe_appended_values = if is_appendable {
+        self.execute_col_append(
+          server,
+          col_name,
+          col_meta,
+          assessment,
+          &old_compaction,
+          &*compressor,
+          maybe_cipher.as_deref(),
+        ).await?
+      } else {
+        None
+      };
+      let values = match maybe_appended_values {
+        Some((values, n_bytes)) => {
+          bytes_out += n_bytes;
+          if let Some(old_sketch) = old_compaction.col_sketches.get(col_name) {
+            let mut sketch = HyperLogLog::from_values(&values);
+            sketch.merge(old_sketch);
+            col_sketches.insert(col_name.clone(), sketch);
+          }
+          values
+        },
+        None => {
+          let values = self.read_col_to_compact(
+            server,
+            col_name,
+            col_meta,
+            assessment,
+            &old_compaction,
+            sort_order.as_deref(),
+          ).await?;
+          bytes_out += self.execute_col_compaction(
+            server,
+            col_name,
+            col_meta,
+            assessment,
+            &*compressor,
+            maybe_cipher.as_deref(),
+            &values,
+          ).await?;
+          if schema.columns.contains_key(col_name) {
+            col_sketches.insert(col_name.clone(), HyperLogLog::from_values(&values));
+          }
+          values
+        },
+      };
+      if col_name == ROW_ID_COLUMN_NAME {
+        // deletes made once deletions are unlocked find sorted rows through
+        // the row ids, so they can't wait to be published
+        publish_staged_file(
+          &dirs::staged_compact_col_file(dir, &new_compaction_key, col_name),
+          &dirs::compact_col_file(dir, &new_compaction_key, col_name),
+        ).await?;
+        drop(maybe_deletion_meta_guard.take());
+        n_rows = values.len();
+      }
+      let n_value_bytes = values.iter().map(common::byte_size_of_field).sum();
+      bytes_in += n_value_bytes;
+      server.throttle_compaction(n_value_bytes).await?;
+      if compaction.bloom_filter_columns.contains(col_name) {
+        let bloom_filter_bytes = BloomFilter::from_values(&values).to_bytes();
+        bytes_out += bloom_filter_bytes.len();
+        common::overwrite_file(
+          dirs::staged_bloom_filter_file(dir, &new_compaction_key, col_name),
+          bloom_filter_bytes,
+        ).await?;
+      }
       log::debug!(
         "wrote compacted column {} for {}",
         col_name,
         new_compaction_key,
       );
     }
+
+    // The new version's files are all in place, but it isn't committed
+    // until the segment starts reading it.
+    self.publish_staged_files(server, &new_compaction_key).await?;
     log::info!("finished compaction for {}", new_compaction_key);
 
-    Ok(())
+    Ok(CompactionSummary {
+      n_rows: n_rows as u64,
+      bytes_in: bytes_in as u64,
+      bytes_out: bytes_out as u64,
+      codecs: compaction.col_codecs,
+      col_sketches,
+    })
   }
 }
 
@@ -339,6 +746,14 @@ impl ServerOp for CompactionOp {
     Ok(self.key.table_name.clone())
   }
 
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.key.table_name)]
+  }
+
   // 1. obtain write lock on segment meta, check if we need compaction
   // 2. create version directory and compaction meta, update segment meta, release segment meta lock
   // 3. do compaction
@@ -346,6 +761,7 @@ impl ServerOp for CompactionOp {
   // Obtaining and releasing segment meta is safe because only 1 compaction can happen at a time.
   // Return whether to remove this segment key from the set of compaction candidates
   async fn execute_with_locks(&self, server: &Server, locks: TableReadLocks) -> ServerResult<Self::Response> {
+    server.check_writable()?;
     let TableReadLocks {
       table_meta,
     } = locks;
@@ -357,25 +773,39 @@ impl ServerOp for CompactionOp {
       .await?;
 
     // we'll manually drop the deletion lock when we don't need it
-    let deletion_meta_guard = deletion_lock.write_owned().await;
+    let deletion_meta_guard = slow_ops::wait_for_lock("deletion", deletion_lock.write_owned()).await?;
 
     // we put this code in a block to scope the first write lock on segment meta
     let assessment = {
-      let mut segment_guard = segment_lock.write().await;
+      let mut segment_guard = slow_ops::wait_for_lock("segment", segment_lock.write()).await?;
       let maybe_segment_meta = &mut *segment_guard;
       if maybe_segment_meta.is_none() {
         return Err(ServerError::does_not_exist("segment", &self.key));
       }
       let segment_meta = maybe_segment_meta.as_mut().unwrap();
 
-      let assessment = self.assess_compaction(server, segment_meta).await?;
+      let assessment = self.assess_compaction(server, &table_meta, segment_meta).await?;
 
       if assessment.do_compaction {
-        // create a new directory and start flushing to the new version as well
+        // create a new directory and start flushing to the new version as
+        // well, recording its initial compaction metadata in the same batch
         let compaction_key = self.key.compaction_key(assessment.new_version);
         common::create_if_new(dirs::version_dir(&opts.dir, &compaction_key)).await?;
-        segment_meta.write_versions = vec![segment_meta.read_version, assessment.new_version];
-        segment_meta.overwrite(&opts.dir, &self.key).await?;
+        let compaction_lock = server.compaction_cache.get_lock(&compaction_key).await?;
+        let mut compaction_guard = slow_ops::wait_for_lock("compaction", compaction_lock.write()).await?;
+        let maybe_compaction = server.initial_compaction();
+        let mut new_segment_meta = segment_meta.clone();
+        new_segment_meta.write_versions = vec![segment_meta.read_version, assessment.new_version];
+        let mut batch = MetadataBatch::default();
+        if let Some(compaction) = &maybe_compaction {
+          batch.add(compaction, &compaction_key)?;
+        }
+        batch.add(&new_segment_meta, &self.key)?;
+        batch.commit(server).await?;
+        if maybe_compaction.is_some() {
+          *compaction_guard = maybe_compaction;
+        }
+        *segment_meta = new_segment_meta;
       }
       assessment
     };
@@ -383,18 +813,59 @@ impl ServerOp for CompactionOp {
     if assessment.do_compaction {
       // important that segment meta is not locked during compaction
       // otherwise writes would be blocked
-      self.compact(server, &table_meta.schema(), &assessment, deletion_meta_guard).await?;
-
-      let mut segment_guard = segment_lock.write().await;
-      let maybe_segment_meta = &mut *segment_guard;
-      if maybe_segment_meta.is_none() {
-        return Err(ServerError::does_not_exist("segment", &self.key));
-      }
-      let segment_meta = maybe_segment_meta.as_mut().unwrap();
-      segment_meta.read_version = assessment.new_version;
-      segment_meta.write_versions = vec![assessment.new_version];
-      segment_meta.read_version_since = Utc::now();
-      segment_meta.overwrite(&opts.dir, &self.key).await?;
+      let started_at = SystemTime::now();
+      let start = Instant::now();
+      let summary = match self.compact(server, &table_meta, &assessment, deletion_meta_guard).await {
+        Ok(summary) => summary,
+        Err(e) => {
+          if matches!(e.kind, ServerErrorKind::Cancelled | ServerErrorKind::TimedOut) {
+            self.abandon(server, &segment_lock, &assessment).await?;
+          }
+          return Err(e);
+        },
+      };
+
+      // Sealing the new version and switching the segment to read it are
+      // one batch, so recovery never finds a compaction half committed.
+      // Flushes may have added codecs to the new version meanwhile, so
+      // those are kept.
+      let new_compaction_key = self.key.compaction_key(assessment.new_version);
+      let new_compaction_lock = server.compaction_cache.get_lock(&new_compaction_key).await?;
+      let mut segment_guard = slow_ops::wait_for_lock("segment", segment_lock.write()).await?;
+      let segment_meta = match &mut *segment_guard {
+        Some(segment_meta) => segment_meta,
+        None => return Err(ServerError::does_not_exist("segment", &self.key)),
+      };
+      let mut new_compaction_guard = slow_ops::wait_for_lock("compaction", new_compaction_lock.write()).await?;
+      let new_compaction = Compaction {
+        col_sketches: summary.col_sketches,
+        sealed: true,
+        ..new_compaction_guard.clone().unwrap_or_default()
+      };
+      let mut new_segment_meta = segment_meta.clone();
+      new_segment_meta.read_version = assessment.new_version;
+      new_segment_meta.write_versions = vec![assessment.new_version];
+      new_segment_meta.read_version_since = Utc::now();
+      let mut batch = MetadataBatch::default();
+      batch.add(&new_compaction, &new_compaction_key)?;
+      batch.add(&new_segment_meta, &self.key)?;
+      batch.commit(server).await?;
+      *new_compaction_guard = Some(new_compaction);
+      *segment_meta = new_segment_meta;
+      drop(new_compaction_guard);
+      drop(segment_guard);
+
+      server.record_op_history(OpRecord {
+        kind: "compaction",
+        segment_key: self.key.clone(),
+        started_at,
+        duration: start.elapsed(),
+        n_rows: summary.n_rows,
+        bytes_in: summary.bytes_in,
+        bytes_out: summary.bytes_out,
+        codecs: summary.codecs,
+      }).await;
+      server.measure_disk_usage(&self.key).await?;
     }
 
     Ok(())
@@ -402,6 +873,31 @@ impl ServerOp for CompactionOp {
 }
 
 impl CompactionOp {
+  // Undoes a cancelled compaction, so the segment goes back to writing
+  // only its current version and can be compacted again later.
+  async fn abandon(
+    &self,
+    server: &Server,
+    segment_lock: &RwLock<Option<SegmentMetadata>>,
+    assessment: &CompactionAssessment,
+  ) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let mut segment_guard = segment_lock.write().await;
+    let segment_meta = match &mut *segment_guard {
+      Some(segment_meta) => segment_meta,
+      None => return Err(ServerError::does_not_exist("segment", &self.key)),
+    };
+    log::info!("abandoning cancelled compaction of {}", self.key);
+    segment_meta.write_versions = vec![segment_meta.read_version];
+    segment_meta.overwrite(dir, &self.key).await?;
+    drop(segment_guard);
+
+    let compaction_key = self.key.compaction_key(assessment.new_version);
+    *server.compaction_cache.get_lock(&compaction_key).await?.write().await = None;
+    vfs::remove_dir_all(dirs::version_dir(dir, &compaction_key)).await?;
+    Ok(())
+  }
+
   pub async fn recover(
     server: &Server,
     segment_key: &SegmentKey,
@@ -409,14 +905,17 @@ impl CompactionOp {
   ) -> ServerResult<()> {
     let dir = &server.opts.dir;
     if segment_meta.write_versions.len() > 1 {
-      log::debug!(
-        "identified incomplete compaction for {}; removing files",
-        segment_key,
-      );
-      for version in &segment_meta.write_versions {
-        if *version != segment_meta.read_version {
-          let compaction_key = segment_key.compaction_key(*version);
-          fs::remove_dir_all(dirs::version_dir(dir, &compaction_key)).await?;
+      // A finished compaction switches the segment to its new version in
+      // the same manifest batch that seals it, which has been replayed by
+      // now, so any version still being written to was never committed.
+      for &version in &segment_meta.write_versions {
+        if version > segment_meta.read_version {
+          let compaction_key = segment_key.compaction_key(version);
+          log::debug!(
+            "identified incomplete compaction for {}; removing files",
+            compaction_key,
+          );
+          vfs::remove_dir_all(dirs::version_dir(dir, &compaction_key)).await?;
         }
       }
 
@@ -424,6 +923,59 @@ impl CompactionOp {
       segment_meta.overwrite(dir, segment_key).await?;
     }
 
+    // a compaction interrupted before recording its new version in segment
+    // metadata leaves that version's dir behind
+    let mut read_dir = vfs::read_dir(dirs::segment_dir(dir, segment_key)).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      let maybe_version = entry.file_name()
+        .to_str()
+        .and_then(|fname| fname.strip_prefix('v'))
+        .and_then(|version| version.parse::<u64>().ok());
+      if let Some(version) = maybe_version {
+        if version > segment_meta.read_version && !segment_meta.write_versions.contains(&version) {
+          log::debug!("removing unrecorded version {} of {}", version, segment_key);
+          vfs::remove_dir_all(entry.path()).await?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub async fn verify_checksums(
+    server: &Server,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+  ) -> ServerResult<()> {
+    let compaction_key = segment_key.compaction_key(segment_meta.read_version);
+    Self::verify_version_checksums(server, &compaction_key).await
+  }
+
+  async fn verify_version_checksums(
+    server: &Server,
+    compaction_key: &CompactionKey,
+  ) -> ServerResult<()> {
+    let dir = &server.opts.dir;
+    let maybe_compaction = Compaction::load(dir, compaction_key).await?;
+    let compaction = match maybe_compaction {
+      Some(compaction) if compaction.checksummed => compaction,
+      _ => return Ok(()),
+    };
+
+    let maybe_cipher = server.column_cipher(&compaction).await?;
+    for col_name in compaction.col_codecs.keys() {
+      let mut paths = vec![dirs::compact_col_file(dir, compaction_key, col_name)];
+      for increment in 0..compaction.increment_ns.len() {
+        paths.push(dirs::compact_increment_col_file(dir, compaction_key, col_name, increment));
+      }
+      for path in &paths {
+        let bytes = storage::read_or_empty(path, maybe_cipher.as_deref()).await?;
+        if bytes.is_empty() {
+          continue;
+        }
+        checksum::verify_and_strip_footer(&bytes, path)?;
+      }
+    }
     Ok(())
   }
 }
@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::locks::table::TableReadLocks;
+use crate::metadata::compaction::Compaction;
+use crate::ops::compact::CompactionOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::opt::RuntimeConfig;
+use crate::serde_models::{CompactedSegmentSerde, CompactSegmentRequestSerde, CompactSegmentResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey, SegmentKey};
+
+async fn read_version(server: &Server, segment_key: &SegmentKey) -> ServerResult<Option<u64>> {
+  let segment_lock = server.segment_metadata_cache.get_lock(segment_key).await?;
+  let segment_guard = segment_lock.read().await;
+  Ok(segment_guard.as_ref().map(|meta| meta.read_version))
+}
+
+// Runs a CompactionOp on the segment, describing the version it leaves
+// the segment reading.
+pub async fn compact_and_summarize(
+  server: &Server,
+  segment_key: &SegmentKey,
+  force: bool,
+) -> ServerResult<CompactedSegmentSerde> {
+  let start = Instant::now();
+  let version_before = read_version(server, segment_key).await?;
+  CompactionOp { key: segment_key.clone(), force }.execute(server).await?;
+  let version_after = read_version(server, segment_key).await?;
+  // compaction meta never changes, so it's fine to drop the lock immediately
+  let compaction = match version_after {
+    Some(version) => server.compaction_cache
+      .get_lock(&segment_key.compaction_key(version))
+      .await?
+      .read()
+      .await
+      .clone()
+      .unwrap_or_default(),
+    None => Compaction::default(),
+  };
+  Ok(CompactedSegmentSerde {
+    segment_id: segment_key.segment_id.to_string(),
+    compacted: version_after != version_before,
+    read_version: version_after.unwrap_or_default(),
+    all_time_compacted_n: compaction.all_time_compacted_n,
+    all_time_omitted_n: compaction.all_time_omitted_n,
+    n_increments: compaction.increment_ns.len() as u32,
+    duration_millis: start.elapsed().as_millis() as u64,
+  })
+}
+
+// Compacts one segment in full regardless of the compaction heuristics,
+// like after deleting many of its rows or before a backup.
+pub struct CompactSegmentOp {
+  pub req: CompactSegmentRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for CompactSegmentOp {
+  type Locks = TableReadLocks;
+  type Response = CompactSegmentResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableReadLocks) -> ServerResult<Self::Response> {
+    server.check_writable()?;
+    let schema = locks.table_meta.schema();
+    let partition = write_to_partition_rest::pb_partition(
+      &self.req.partition,
+      &schema.partitioning,
+    )?;
+    let partition_key = PartitionKey {
+      table_name: self.req.table_name.clone(),
+      partition: NormalizedPartition::from_raw_fields(&partition)?,
+    };
+    partition_key.partition.check_against_schema(&schema)?;
+    let segment_key = partition_key.segment_key(Uuid::from_str(&self.req.segment_id)?);
+
+    if self.req.background {
+      server.queue_forced_compaction(segment_key).await;
+      return Ok(CompactSegmentResponseSerde {
+        queued: true,
+        segment: None,
+      });
+    }
+
+    let segment = compact_and_summarize(server, &segment_key, true).await?;
+    Ok(CompactSegmentResponseSerde {
+      queued: false,
+      segment: Some(segment),
+    })
+  }
+}
+
+impl RestRoute for CompactSegmentOp {
+  type Req = CompactSegmentRequestSerde;
+
+  const ROUTE_NAME: &'static str = "compact_segment";
+
+  fn new_op(req: Self::Req) -> CompactSegmentOp {
+    CompactSegmentOp { req }
+  }
+}
@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use pancake_db_idl::dml::ListSegmentsRequest;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::compact_segment;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::opt::RuntimeConfig;
+use crate::serde_models::{CompactTableRequestSerde, CompactTableResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::server::cancel;
+use crate::types::{NormalizedPartition, PartitionKey};
+
+pub struct CompactTableOp {
+  pub req: CompactTableRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for CompactTableOp {
+  type Locks = TrivialLocks;
+  type Response = CompactTableResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  // Runs the same compaction the compaction loop would, immediately and for
+  // every segment in the table, or with force, compacts each segment in
+  // full. Each CompactionOp obtains its own locks.
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    server.check_writable()?;
+    let table_name = &self.req.table_name;
+    if self.req.background && !self.req.force {
+      return Err(ServerError::invalid("only forced compactions can be queued"));
+    }
+    let req = ListSegmentsRequest {
+      table_name: table_name.clone(),
+      ..Default::default()
+    };
+    let list_resp = ListSegmentsOp { req, partition_conditions: Vec::new() }.execute(server).await?;
+
+    let mut compacted_segments = Vec::new();
+    for segment in &list_resp.segments {
+      cancel::check()?;
+      let partition_key = PartitionKey {
+        table_name: table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      if self.req.background {
+        server.queue_forced_compaction(segment_key).await;
+        continue;
+      }
+      let compacted_segment = compact_segment::compact_and_summarize(
+        server,
+        &segment_key,
+        self.req.force,
+      ).await?;
+      if compacted_segment.compacted {
+        compacted_segments.push(compacted_segment);
+      }
+    }
+
+    if self.req.background {
+      log::info!(
+        "queued forced compactions of {} segments in {}",
+        list_resp.segments.len(),
+        table_name,
+      );
+    } else {
+      log::info!(
+        "manually compacted {} of {} segments in {}",
+        compacted_segments.len(),
+        list_resp.segments.len(),
+        table_name,
+      );
+    }
+    Ok(CompactTableResponseSerde {
+      n_segments_checked: list_resp.segments.len() as u32,
+      n_segments_compacted: compacted_segments.len() as u32,
+      queued: self.req.background,
+      compacted_segments,
+    })
+  }
+}
+
+impl RestRoute for CompactTableOp {
+  type Req = CompactTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "compact_table";
+
+  fn new_op(req: Self::Req) -> CompactTableOp {
+    CompactTableOp { req }
+  }
+}
@@ -0,0 +1,209 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
+use async_trait::async_trait;
+use futures::pin_mut;
+use futures::StreamExt;
+
+use crate::constants::TABLE_METADATA_FILENAME;
+use crate::errors::{ErrorCode, ServerError, ServerResult};
+use crate::locks::table::TablePairWriteLocks;
+use crate::metadata::{MetadataJson, PersistentMetadata};
+use crate::metadata::partition_index::PartitionIndex;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{CopyTableRequestSerde, EmptySerde};
+use crate::server::Server;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
+use crate::types::{InternalTableInfo, SegmentKey};
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::dirs::FileKind;
+use crate::utils::vfs;
+
+// Creates a table with the same schema as another, and optionally the same
+// rows, so that a table can be reprocessed into a copy and swapped in with
+// a rename. The copy is assembled in a staging dir and renamed into place,
+// so it appears all at once. Files that never change once written are hard
+// linked when the filesystem allows it, and everything else is copied.
+// Writes to the source table wait until the copy is done.
+pub struct CopyTableOp {
+  pub req: CopyTableRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for CopyTableOp {
+  type Locks = TablePairWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<(String, String)> {
+    Ok((self.req.table_name.clone(), self.req.new_table_name.clone()))
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![
+      Access::table(Verb::Read, &self.req.table_name),
+      Access::table(Verb::Create, &self.req.new_table_name),
+    ]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    let contents = if self.req.include_data {
+      "schema and data"
+    } else {
+      "schema only"
+    };
+    Some(AuditEvent {
+      operation: "copy_table",
+      table_name: self.req.table_name.clone(),
+      summary: format!("to {}, {}", self.req.new_table_name, contents),
+    })
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TablePairWriteLocks) -> ServerResult<EmptySerde> {
+    let dir = &server.opts.dir;
+    let table_name = &self.req.table_name;
+    let new_table_name = &self.req.new_table_name;
+    common::validate_entity_name_for_write("table name", table_name)?;
+    common::validate_entity_name_for_write("table name", new_table_name)?;
+
+    let TablePairWriteLocks {
+      maybe_src_table_guard,
+      mut maybe_dst_table_guard,
+    } = locks;
+    let table_meta = common::unwrap_metadata(table_name, &*maybe_src_table_guard)?;
+    if maybe_dst_table_guard.is_some() {
+      return Err(ServerError::invalid(format!(
+        "table {} already exists",
+        new_table_name,
+      )).with_code(ErrorCode::TableAlreadyExists));
+    }
+
+    log::info!(
+      "copying table {} to {}{}",
+      table_name,
+      new_table_name,
+      if self.req.include_data { " with data" } else { "" },
+    );
+    let staging_dir = dirs::table_copy_staging_dir(dir);
+    let staged_table_dir = dirs::table_dir(&staging_dir, new_table_name);
+    remove_dir_if_exists(&staged_table_dir).await?;
+    vfs::create_dir_all(dirs::table_data_dir(&staging_dir, new_table_name)).await?;
+
+    let mut segment_keys = Vec::new();
+    let mut partition_index = PartitionIndex::default();
+    if self.req.include_data {
+      let table = InternalTableInfo {
+        name: table_name.clone(),
+        meta: table_meta.clone(),
+      };
+      let segment_key_stream = server.stream_table_segment_keys(&table);
+      pin_mut!(segment_key_stream);
+      let mut last_partition_key = None;
+      while let Some(segment_key_result) = segment_key_stream.next().await {
+        let segment_key = segment_key_result?;
+        let new_segment_key = SegmentKey {
+          table_name: new_table_name.clone(),
+          ..segment_key.clone()
+        };
+
+        let partition_key = segment_key.partition_key();
+        if last_partition_key.as_ref() != Some(&partition_key) {
+          // just the partition metadata, not the segments
+          copy_dir_files(
+            &dirs::partition_dir(dir, &partition_key),
+            &dirs::partition_dir(&staging_dir, &new_segment_key.partition_key()),
+            false,
+          ).await?;
+          last_partition_key = Some(partition_key);
+        }
+
+        let deletion_lock = server.deletion_metadata_cache.get_lock(&segment_key).await?;
+        let _deletion_guard = deletion_lock.read().await;
+        let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+        let segment_guard = segment_lock.read().await;
+        if let Some(segment_meta) = &*segment_guard {
+          copy_dir_files(
+            &dirs::segment_dir(dir, &segment_key),
+            &dirs::segment_dir(&staging_dir, &new_segment_key),
+            true,
+          ).await?;
+          partition_index.add_segment(&new_segment_key.partition, new_segment_key.segment_id);
+          segment_keys.push((new_segment_key, segment_meta.staged_n > 0));
+        }
+      }
+    }
+
+    let new_table_meta = table_meta.copied();
+    vfs::write(
+      staged_table_dir.join(TABLE_METADATA_FILENAME),
+      new_table_meta.to_json_string()?,
+    ).await?;
+    vfs::write(
+      PartitionIndex::path(&staging_dir, new_table_name),
+      partition_index.to_json_string()?,
+    ).await?;
+    vfs::rename(&staged_table_dir, dirs::table_dir(dir, new_table_name)).await?;
+    *maybe_dst_table_guard = Some(new_table_meta);
+    server.prune_table_caches(new_table_name).await;
+
+    for (segment_key, has_staged_rows) in segment_keys {
+      if has_staged_rows {
+        server.add_flush_candidate(segment_key.clone()).await;
+      }
+      server.measure_disk_usage(&segment_key).await?;
+    }
+    Ok(EmptySerde {})
+  }
+}
+
+impl CopyTableOp {
+  // removes whatever interrupted copies left behind
+  pub async fn recover(server: &Server) -> ServerResult<()> {
+    remove_dir_if_exists(&dirs::table_copy_staging_dir(&server.opts.dir)).await
+  }
+}
+
+async fn remove_dir_if_exists(dir: &Path) -> ServerResult<()> {
+  match vfs::remove_dir_all(dir).await {
+    Ok(()) => Ok(()),
+    Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Ok(()),
+    Err(e) => Err(e.into()),
+  }
+}
+
+async fn copy_dir_files(src_dir: &Path, dst_dir: &Path, is_deep: bool) -> ServerResult<()> {
+  let mut pending = vec![(src_dir.to_path_buf(), dst_dir.to_path_buf())];
+  while let Some((src, dst)) = pending.pop() {
+    vfs::create_dir_all(&dst).await?;
+    let mut read_dir = vfs::read_dir(&src).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let dst_path = dst.join(&name);
+      if entry.file_type().await?.is_dir() {
+        if is_deep {
+          pending.push((entry.path(), dst_path));
+        }
+        continue;
+      }
+
+      // the copy must not see later appends to the original
+      let is_linked = matches!(FileKind::of(&name), FileKind::Immutable) &&
+        vfs::hard_link(entry.path(), &dst_path).await.is_ok();
+      if !is_linked {
+        vfs::copy(entry.path(), &dst_path).await?;
+      }
+    }
+  }
+  Ok(())
+}
+
+impl RestRoute for CopyTableOp {
+  type Req = CopyTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "copy_table";
+
+  fn new_op(req: Self::Req) -> CopyTableOp {
+    CopyTableOp { req }
+  }
+}
@@ -0,0 +1,90 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use pancake_db_idl::dml::ListSegmentsRequest;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{CountRowsRequestSerde, CountRowsResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+
+// Counts the live rows of every matching segment from segment metadata
+// alone, without reading any column files, so dashboards can poll counts
+// cheaply. Staged rows are counted, and deleted ones are not.
+pub struct CountRowsOp {
+  pub req: CountRowsRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for CountRowsOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = CountRowsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let schema = locks.table_meta.visible_schema();
+    let partition_filter = write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?;
+    let partition_conditions = write_to_partition_rest::partition_conditions(
+      &req.partition_filter,
+      &schema.partitioning,
+    )?;
+    let list_req = ListSegmentsRequest {
+      table_name: req.table_name.clone(),
+      ..Default::default()
+    };
+    let listed = ListSegmentsOp { req: list_req, partition_conditions }.execute_with_locks(server, locks).await?;
+
+    let mut row_count = 0;
+    let mut n_segments = 0;
+    for segment in &listed.segments {
+      let is_match = partition_filter.iter()
+        .all(|(name, value)| segment.partition.get(name) == Some(value));
+      if !is_match {
+        continue;
+      }
+
+      let partition_key = PartitionKey {
+        table_name: req.table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      let maybe_segment_meta = server.segment_metadata_cache.get_lock(&segment_key)
+        .await?
+        .read()
+        .await
+        .clone();
+      if let Some(segment_meta) = maybe_segment_meta {
+        row_count += segment_meta.live_n()?;
+        n_segments += 1;
+      }
+    }
+
+    Ok(CountRowsResponseSerde {
+      row_count,
+      n_segments,
+    })
+  }
+}
+
+impl RestRoute for CountRowsOp {
+  type Req = CountRowsRequestSerde;
+
+  const ROUTE_NAME: &'static str = "count_rows";
+
+  fn new_op(req: Self::Req) -> CountRowsOp {
+    CountRowsOp { req }
+  }
+}
@@ -5,16 +5,20 @@ use pancake_db_idl::ddl::{CreateTableRequest, CreateTableResponse, AlterTableReq
 use pancake_db_idl::ddl::create_table_request::SchemaMode;
 use pancake_db_idl::schema::Schema;
 
-use crate::constants::{MAX_PARTITIONING_DEPTH, MAX_NESTED_LIST_DEPTH, MAX_N_COLUMNS};
+use crate::constants::{MAX_PARTITIONING_DEPTH, MAX_NESTED_LIST_DEPTH, MAX_N_COLUMNS, OPS_TABLE_NAME};
 use crate::utils::dirs;
-use crate::errors::{ServerError, ServerResult};
+use crate::errors::{ErrorCode, ServerError, ServerResult};
 use crate::locks::table::TableWriteLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
 use crate::metadata::PersistentMetadata;
 use crate::utils::common;
-use crate::metadata::table::TableMetadata;
+use crate::utils::computed;
+use crate::utils::masking;
+use crate::metadata::table::{TableConfigOverrides, TableMetadata};
 use crate::ops::alter_table::AlterTableOp;
+use crate::server::audit::{self, AuditEvent};
+use crate::server::authz::{self, Access, Principal, Verb};
 
 fn partitioning_matches(schema0: &Schema, schema1: &Schema) -> bool {
   if schema0.partitioning.len() != schema1.partitioning.len() {
@@ -48,6 +52,13 @@ fn is_subset(sub_schema: &Schema, schema: &Schema) -> bool {
 
 pub struct CreateTableOp {
   pub req: CreateTableRequest,
+  // only settable when the table is created; gRPC requests can't set these
+  pub sort_columns: Vec<String>,
+  // expressions keyed by column name
+  pub computed_columns: HashMap<String, String>,
+  // masking rule expressions keyed by column name
+  pub column_masks: HashMap<String, String>,
+  pub config_overrides: TableConfigOverrides,
 }
 
 #[async_trait]
@@ -59,6 +70,38 @@ impl ServerOp for CreateTableOp {
     Ok(self.req.table_name.clone())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Create, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    let mut parts = Vec::new();
+    if let Some(schema) = &self.req.schema {
+      parts.push(format!("columns {}", audit::join_sorted(schema.columns.keys())));
+      if !schema.partitioning.is_empty() {
+        parts.push(format!("partitioning {}", audit::join_sorted(schema.partitioning.keys())));
+      }
+    }
+    if !self.sort_columns.is_empty() {
+      parts.push(format!("sort columns {}", self.sort_columns.join(", ")));
+    }
+    if !self.computed_columns.is_empty() {
+      parts.push(format!("computed columns {}", audit::join_sorted(self.computed_columns.keys())));
+    }
+    if !self.column_masks.is_empty() {
+      parts.push(format!("masked columns {}", audit::join_sorted(self.column_masks.keys())));
+    }
+    if self.config_overrides != TableConfigOverrides::default() {
+      parts.push(format!("config overrides {:?}", self.config_overrides));
+    }
+    parts.push(format!("mode {:?}", SchemaMode::from_i32(self.req.mode).unwrap_or_default()));
+    Some(AuditEvent {
+      operation: "create_table",
+      table_name: self.req.table_name.clone(),
+      summary: parts.join("; "),
+    })
+  }
+
   async fn execute_with_locks(
     &self,
     server: &Server,
@@ -74,7 +117,12 @@ impl ServerOp for CreateTableOp {
       None => Err(ServerError::invalid("missing table schema")),
     }?;
 
-    common::validate_entity_name_for_write("table name", &req.table_name)?;
+    // only the server itself may create its reserved ops table
+    if req.table_name == OPS_TABLE_NAME && authz::current_principal() == Principal::Internal {
+      common::validate_entity_name_for_read("table name", &req.table_name)?;
+    } else {
+      common::validate_entity_name_for_write("table name", &req.table_name)?;
+    }
     if schema.partitioning.len() > MAX_PARTITIONING_DEPTH {
       return Err(ServerError::invalid(format!(
         "number of partition fields may not exceed {} but was {}",
@@ -104,24 +152,55 @@ impl ServerOp for CreateTableOp {
       }
     }
 
+    common::check_no_duplicate_names("sort column", self.sort_columns.clone())?;
+    for col_name in &self.sort_columns {
+      match schema.columns.get(col_name) {
+        Some(col_meta) if col_meta.nested_list_depth > 0 => Err(ServerError::invalid(format!(
+          "cannot sort by nested list column {}",
+          col_name,
+        ))),
+        Some(_) => Ok(()),
+        None => Err(ServerError::does_not_exist("column", col_name)),
+      }?;
+    }
+
+    let computed_columns = computed::parse_computed_columns(&self.computed_columns, schema)?;
+    let column_masks = masking::parse_column_masks(&self.column_masks, &schema.columns)?;
+    self.config_overrides.validate()?;
+
     let maybe_table = &mut *locks.maybe_table_guard;
     let mut result = CreateTableResponse {..Default::default()};
 
     match maybe_table {
       Some(table_meta) => {
-        let meta_schema = table_meta.schema();
+        let meta_schema = table_meta.visible_schema();
         result.already_exists = true;
         if !partitioning_matches(schema, &meta_schema) {
-          return Err(ServerError::invalid("existing schema has different partitioning"))
+          return Err(ServerError::invalid("existing schema has different partitioning").with_code(ErrorCode::SchemaMismatch))
+        }
+        let meta_sort_columns: Vec<String> = table_meta.sort_columns.iter()
+          .map(|col_name| table_meta.visible_column_name(col_name))
+          .collect();
+        if !self.sort_columns.is_empty() && self.sort_columns != meta_sort_columns {
+          return Err(ServerError::invalid("existing schema has different sort columns").with_code(ErrorCode::SchemaMismatch))
+        }
+        if !computed_columns.is_empty() && computed_columns != table_meta.visible_computed_columns() {
+          return Err(ServerError::invalid("existing schema has different computed columns").with_code(ErrorCode::SchemaMismatch))
+        }
+        if !column_masks.is_empty() && column_masks != table_meta.visible_column_masks() {
+          return Err(ServerError::invalid("existing schema has different column masks").with_code(ErrorCode::SchemaMismatch))
+        }
+        if self.config_overrides != TableConfigOverrides::default() && self.config_overrides != table_meta.config_overrides {
+          return Err(ServerError::invalid("existing schema has different config overrides").with_code(ErrorCode::SchemaMismatch))
         }
 
         match schema_mode {
-          SchemaMode::FailIfExists => Err(ServerError::invalid("table already exists")),
+          SchemaMode::FailIfExists => Err(ServerError::invalid("table already exists").with_code(ErrorCode::TableAlreadyExists)),
           SchemaMode::OkIfExact => {
             if is_subset(&meta_schema, schema) && is_subset(schema, &meta_schema) {
               Ok(result)
             } else {
-              Err(ServerError::invalid("existing schema columns are not identical"))
+              Err(ServerError::invalid("existing schema columns are not identical").with_code(ErrorCode::SchemaMismatch))
             }
           },
           SchemaMode::AddNewColumns => {
@@ -139,13 +218,15 @@ impl ServerOp for CreateTableOp {
                     table_name: req.table_name.to_string(),
                     new_columns,
                     ..Default::default()
-                  }
+                  },
+                  rename_columns: HashMap::new(),
+                  config_overrides: None,
                 };
                 alter_table_op.execute_with_locks(server, locks).await?;
               }
               Ok(result)
             } else {
-              Err(ServerError::invalid("existing schema contains columns not in declared schema"))
+              Err(ServerError::invalid("existing schema contains columns not in declared schema").with_code(ErrorCode::SchemaMismatch))
             }
           }
         }
@@ -159,7 +240,11 @@ impl ServerOp for CreateTableOp {
         let table_data_dir = dirs::table_data_dir(dir, table_name);
         common::create_if_new(table_data_dir).await?;
 
-        let table_meta = TableMetadata::new(&schema.clone());
+        let mut table_meta = TableMetadata::new(&schema.clone());
+        table_meta.sort_columns = self.sort_columns.clone();
+        table_meta.computed_columns = computed_columns;
+        table_meta.column_masks = column_masks;
+        table_meta.config_overrides = self.config_overrides.clone();
         *maybe_table = Some(table_meta.clone());
         table_meta.overwrite(dir, table_name).await?;
         Ok(result)
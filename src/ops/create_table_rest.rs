@@ -4,9 +4,12 @@ use pancake_db_idl::schema::Schema;
 use crate::{Server, ServerResult};
 use crate::locks::table::TableWriteLocks;
 use crate::locks::traits::ServerOpLocks;
+use crate::metadata::table::TableConfigOverrides;
 use crate::ops::create_table::CreateTableOp;
 use crate::ops::traits::{RestRoute, ServerOp};
 use crate::serde_models::{CreateTableRequestSerde, CreateTableResponseSerde};
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
 
 pub struct CreateTableRestOp {
   pub req: CreateTableRequestSerde
@@ -21,14 +24,16 @@ impl ServerOp for CreateTableRestOp {
     Ok(self.req.table_name.to_string())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Create, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    self.pb_op().audit_event()
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: Self::Locks) -> ServerResult<Self::Response> {
-    let req = &self.req;
-    let pb_req = CreateTableRequest {
-      table_name: req.table_name.clone(),
-      schema: Some(Schema::from(&req.schema)),
-      mode: i32::from(req.mode),
-    };
-    let pb_resp = CreateTableOp { req: pb_req }.execute_with_locks(
+    let pb_resp = self.pb_op().execute_with_locks(
       server,
       locks,
     ).await?;
@@ -39,6 +44,23 @@ impl ServerOp for CreateTableRestOp {
   }
 }
 
+impl CreateTableRestOp {
+  fn pb_op(&self) -> CreateTableOp {
+    let req = &self.req;
+    CreateTableOp {
+      req: CreateTableRequest {
+        table_name: req.table_name.clone(),
+        schema: Some(Schema::from(&req.schema)),
+        mode: i32::from(req.mode),
+      },
+      sort_columns: req.schema.sort_columns.clone(),
+      computed_columns: req.schema.computed_columns.clone(),
+      column_masks: req.schema.column_masks.clone(),
+      config_overrides: TableConfigOverrides::from(&req.config),
+    }
+  }
+}
+
 impl RestRoute for CreateTableRestOp {
   type Req = CreateTableRequestSerde;
 
@@ -10,6 +10,8 @@ use crate::locks::deletion::DeletionWriteLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
 use crate::metadata::PersistentMetadata;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
 use crate::types::{SegmentKey, NormalizedPartition};
 use crate::utils::common;
 use crate::utils::dirs;
@@ -33,6 +35,26 @@ impl ServerOp for DeleteFromSegmentOp {
     })
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Delete, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    let partition = NormalizedPartition::from_raw_fields(&self.req.partition)
+      .map(|partition| partition.to_string())
+      .unwrap_or_default();
+    Some(AuditEvent {
+      operation: "delete_from_segment",
+      table_name: self.req.table_name.clone(),
+      summary: format!(
+        "{} row ids from segment {} of partition {}",
+        self.req.row_ids.len(),
+        self.req.segment_id,
+        partition,
+      ),
+    })
+  }
+
   async fn execute_with_locks(
     &self,
     server: &Server,
@@ -63,11 +85,11 @@ impl ServerOp for DeleteFromSegmentOp {
       )?
     };
 
-    if max_row_id >= segment_meta.all_time_n {
+    if max_row_id as u64 >= segment_meta.all_time_n {
       return Err(ServerError::invalid(format!(
-        "requested to deleted row id of {} when max row id in segment is {}",
+        "requested to deleted row id of {} when segment only has {} rows",
         max_row_id,
-        segment_meta.all_time_n - 1,
+        segment_meta.all_time_n,
       )))
     }
 
@@ -75,7 +97,8 @@ impl ServerOp for DeleteFromSegmentOp {
     let new_deletion_id = old_deletion_id + 1;
 
     let mut maybe_n_deleted = None;
-    for &version in &segment_meta.write_versions {
+    let mut newly_deleted_row_ids = Vec::new();
+    for (version_idx, &version) in segment_meta.write_versions.iter().enumerate() {
       let compaction_key = segment_key.compaction_key(version);
       let pre_compaction_deletions = server.read_pre_compaction_deletions(
         &compaction_key
@@ -86,18 +109,38 @@ impl ServerOp for DeleteFromSegmentOp {
         old_deletion_id,
       ).await?;
 
+      // deletions are by position, which differs from row id in sorted
+      // compactions
+      let compaction = server.compaction_cache
+        .get_lock(&compaction_key)
+        .await?
+        .read()
+        .await
+        .clone()
+        .unwrap_or_default();
+      let compacted_row_ids = server.read_compacted_row_ids(
+        &segment_key,
+        version,
+        &compaction,
+      ).await?;
+      let n_positions = (max_row_id as usize + 1).max(compacted_row_ids.len());
+
       let mut version_n_deleted = 0;
       let mut post_i = 0;
-      for row_id in 0..(max_row_id + 1) as usize {
-        let pre_deleted = row_id < n_pre && pre_compaction_deletions[row_id];
+      for position in 0..n_positions {
+        let row_id = compacted_row_ids.get(position).cloned().unwrap_or(position as u32);
+        let pre_deleted = position < n_pre && pre_compaction_deletions[position];
         if post_i >= post_compaction_deletions.len() {
           post_compaction_deletions.push(false);
         }
 
         if !pre_deleted {
-          if row_ids.contains(&(row_id as u32)) && !post_compaction_deletions[post_i]{
+          if row_ids.contains(&row_id) && !post_compaction_deletions[post_i]{
             post_compaction_deletions[post_i] = true;
             version_n_deleted += 1;
+            if version_idx == 0 {
+              newly_deleted_row_ids.push(row_id);
+            }
           }
 
           post_i += 1
@@ -143,8 +186,16 @@ impl ServerOp for DeleteFromSegmentOp {
 
       let segment_meta = segment_meta_guard.as_mut().unwrap();
       segment_meta.deletion_id = new_deletion_id;
-      segment_meta.all_time_deleted_n += n_deleted;
+      segment_meta.all_time_deleted_n += n_deleted as u64;
       segment_meta.overwrite(dir, &segment_key).await?;
+
+      if !newly_deleted_row_ids.is_empty() {
+        newly_deleted_row_ids.sort_unstable();
+        let log_bytes: Vec<u8> = newly_deleted_row_ids.iter()
+          .flat_map(|row_id| row_id.to_le_bytes())
+          .collect();
+        common::append_to_file(dirs::deletion_log_path(dir, &segment_key), &log_bytes).await?;
+      }
     }
 
     Ok(DeleteFromSegmentResponse {
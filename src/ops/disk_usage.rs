@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{DiskUsageResponseSerde, EmptySerde, TableDiskUsageSerde};
+use crate::server::is_over_limit;
+
+pub struct DiskUsageOp;
+
+#[async_trait]
+impl ServerOp for DiskUsageOp {
+  type Locks = TrivialLocks;
+  type Response = DiskUsageResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let runtime_config = server.runtime_config().await;
+    let report = server.disk_usage_report().await;
+    let tables = report.table_bytes.into_iter()
+      .map(|(table_name, bytes)| TableDiskUsageSerde {
+        table_name,
+        bytes,
+        is_over_soft_limit: is_over_limit(bytes, runtime_config.table_disk_soft_limit_bytes),
+        is_over_hard_limit: is_over_limit(bytes, runtime_config.table_disk_hard_limit_bytes),
+      })
+      .collect();
+    Ok(DiskUsageResponseSerde {
+      total_bytes: report.total_bytes,
+      is_over_soft_limit: is_over_limit(report.total_bytes, runtime_config.global_disk_soft_limit_bytes),
+      is_over_hard_limit: is_over_limit(report.total_bytes, runtime_config.global_disk_hard_limit_bytes),
+      tables,
+    })
+  }
+}
+
+impl RestRoute for DiskUsageOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "disk_usage";
+
+  fn new_op(_req: Self::Req) -> DiskUsageOp {
+    DiskUsageOp
+  }
+}
@@ -1,8 +1,5 @@
 use async_trait::async_trait;
 use pancake_db_idl::ddl::{DropTableRequest, DropTableResponse};
-use tokio::fs;
-
-use crate::utils::dirs;
 use crate::errors::{ServerResult, ServerError};
 use crate::locks::table::TableWriteLocks;
 use crate::ops::traits::ServerOp;
@@ -10,7 +7,8 @@ use crate::server::Server;
 use crate::utils::common;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::table::TableMetadata;
-use std::path::Path;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
 
 pub struct DropTableOp {
   pub req: DropTableRequest,
@@ -25,6 +23,18 @@ impl ServerOp for DropTableOp {
     Ok(self.req.table_name.clone())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Delete, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    Some(AuditEvent {
+      operation: "drop_table",
+      table_name: self.req.table_name.clone(),
+      summary: String::new(),
+    })
+  }
+
   async fn execute_with_locks(
     &self,
     server: &Server,
@@ -51,27 +61,22 @@ impl ServerOp for DropTableOp {
     table_meta.overwrite(dir, table_name).await?;
 
     *maybe_table = None;
+    server.partition_index_cache.prune(|key| key == table_name)
+      .await;
     server.partition_metadata_cache.prune(|key| &key.table_name == table_name)
       .await;
     server.segment_metadata_cache.prune(|key| &key.table_name == table_name)
       .await;
     // don't really need to prune compaction metadata because nothing will try to use it
     // without the segment metadata
-    // we need to delete data directory first, or else we might delete the `dropped`
-    // flag in the metadata, crash, and leave all the data on disk, unable to resume
-    Self::remove_data(dir, table_name).await?;
+    server.trash_table(table_name).await?;
+    server.forget_table_disk_usage(table_name).await;
 
     Ok(DropTableResponse {..Default::default()})
   }
 }
 
 impl DropTableOp {
-  async fn remove_data(dir: &Path, table_name: &str) -> ServerResult<()> {
-    fs::remove_dir_all(dirs::table_data_dir(dir, table_name)).await?;
-    fs::remove_dir_all(dirs::table_dir(dir, table_name)).await?;
-    Ok(())
-  }
-
   pub async fn recover(
     server: &Server,
     table_name: &str,
@@ -79,10 +84,10 @@ impl DropTableOp {
   ) -> ServerResult<()> {
     if table_meta.dropped {
       log::debug!(
-        "identified interrupted drop for table {}; removing files",
+        "identified interrupted drop for table {}; moving files to trash",
         table_name
       );
-      Self::remove_data(&server.opts.dir, table_name).await?;
+      server.trash_table(table_name).await?;
     }
     Ok(())
   }
@@ -6,6 +6,8 @@ use crate::locks::traits::ServerOpLocks;
 use crate::ops::drop_table::DropTableOp;
 use crate::ops::traits::{RestRoute, ServerOp};
 use crate::serde_models::{DropTableRequestSerde, EmptySerde};
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
 
 pub struct DropTableRestOp {
   pub req: DropTableRequestSerde
@@ -20,15 +22,30 @@ impl ServerOp for DropTableRestOp {
     Ok(self.req.table_name.to_string())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Delete, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    self.pb_op().audit_event()
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: Self::Locks) -> ServerResult<Self::Response> {
-    let pb_req = DropTableRequest {
-      table_name: self.req.table_name.to_string()
-    };
-    DropTableOp { req: pb_req }.execute_with_locks(server, locks).await?;
+    self.pb_op().execute_with_locks(server, locks).await?;
     Ok(EmptySerde {})
   }
 }
 
+impl DropTableRestOp {
+  fn pb_op(&self) -> DropTableOp {
+    DropTableOp {
+      req: DropTableRequest {
+        table_name: self.req.table_name.to_string()
+      },
+    }
+  }
+}
+
 impl RestRoute for DropTableRestOp {
   type Req = DropTableRequestSerde;
 
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EndReadRequestSerde, EndReadResponseSerde};
+use crate::server::authz::Access;
+
+// Releases a correlation id's pin early, so compaction may delete the
+// version it was reading.
+pub struct EndReadOp {
+  pub req: EndReadRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for EndReadOp {
+  type Locks = TrivialLocks;
+  type Response = EndReadResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    Vec::new()
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let was_pinned = server.correlation_metadata_cache
+      .end_read(&self.req.correlation_id)
+      .await;
+    Ok(EndReadResponseSerde { was_pinned })
+  }
+}
+
+impl RestRoute for EndReadOp {
+  type Req = EndReadRequestSerde;
+
+  const ROUTE_NAME: &'static str = "end_read";
+
+  fn new_op(req: Self::Req) -> EndReadOp {
+    EndReadOp { req }
+  }
+}
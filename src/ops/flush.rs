@@ -1,70 +1,144 @@
-use std::collections::HashSet;
-use std::io;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use chrono::Utc;
 use pancake_db_core::{compression, encoding};
 use pancake_db_idl::dml::FieldValue;
 use pancake_db_idl::schema::ColumnMeta;
-use tokio::fs;
-use tokio::fs::OpenOptions;
+use tokio::time::Instant;
 
 use crate::errors::{ServerError, ServerResult};
-use crate::locks::segment::SegmentWriteLocks;
+use crate::locks::table::TableReadLocks;
 use crate::metadata::compaction::Compaction;
+use crate::metadata::manifest::MetadataBatch;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::segment::SegmentMetadata;
 use crate::metadata::table::TableMetadata;
 use crate::ops::traits::ServerOp;
+use crate::opt::RuntimeConfig;
 use crate::server::Server;
+use crate::server::cancel;
+use crate::server::ops_history::OpRecord;
+use crate::server::slow_ops;
 use crate::types::{CompactionKey, SegmentKey};
+use crate::utils::checksum;
 use crate::utils::common;
+use crate::utils::computed;
 use crate::utils::decoding_seek;
 use crate::utils::dirs;
+use crate::utils::encryption::Cipher;
+use crate::utils::storage;
+use crate::utils::vfs;
+use crate::utils::zone_map;
 
 pub struct FlushOp {
   pub segment_key: SegmentKey,
 }
 
+// a version whose files for a newly explicit column are being asserted
+struct ExplicitFilesVersion<'a> {
+  compaction_key: &'a CompactionKey,
+  // gains the column's codec, which the caller commits
+  compaction: &'a mut Compaction,
+  maybe_cipher: Option<&'a Cipher>,
+}
+
 #[async_trait]
 impl ServerOp for FlushOp {
-  type Locks = SegmentWriteLocks;
+  type Locks = TableReadLocks;
   type Response = ();
 
-  fn get_key(&self) -> ServerResult<SegmentKey> {
-    Ok(self.segment_key.clone())
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.segment_key.table_name.clone())
   }
 
-  async fn execute_with_locks(&self, server: &Server, locks: SegmentWriteLocks) -> ServerResult<()> {
-    common::validate_entity_name_for_read("table name", &self.segment_key.table_name)?;
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
 
-    let dir = &server.opts.dir;
-    let SegmentWriteLocks {
-      table_meta,
-      mut definitely_segment_guard,
-      segment_key,
-    } = locks;
-    let segment_meta = definitely_segment_guard.as_mut().unwrap();
+  // 1. under a segment read lock, read the staged rows
+  // 2. encode each column's values without holding any segment lock, so
+  //    writes can continue staging rows
+  // 3. under the segment write lock, mark the segment as flushing, append
+  //    the encoded columns, and update the staged row count
+  // 4. remove the flushed rows from the front of the staged rows file, then
+  //    mark the segment as done flushing
+  async fn execute_with_locks(&self, server: &Server, locks: TableReadLocks) -> ServerResult<()> {
+    server.check_writable()?;
+    let started_at = SystemTime::now();
+    let start = Instant::now();
+    let segment_key = &self.segment_key;
+    common::validate_entity_name_for_read("table name", &segment_key.table_name)?;
 
-    let n_rows = segment_meta.staged_n;
-    if n_rows == 0 {
-      return Err(ServerError::internal(format!("tried to flush {} with 0 rows", segment_key)));
-    }
+    let dir = &server.opts.dir;
+    let schema = locks.table_meta.schema();
+    segment_key.partition.check_against_schema(&schema)?;
+    let segment_lock = server.segment_metadata_cache.get_lock(segment_key).await?;
+
+    let staged_rows_path = dirs::staged_rows_path(dir, segment_key);
+    let (staged_bytes, mut rows) = {
+      let segment_guard = slow_ops::wait_for_lock("segment", segment_lock.read()).await?;
+      let segment_meta = common::unwrap_metadata(segment_key, &segment_guard)?;
+      if segment_meta.staged_n == 0 {
+        return Err(ServerError::internal(format!("tried to flush {} with 0 rows", segment_key)));
+      }
+      let staged_bytes = vfs::read(&staged_rows_path).await?;
+      slow_ops::add_bytes(staged_bytes.len());
+      let rows = common::staged_bytes_to_rows(&staged_bytes)?;
+      if rows.len() != segment_meta.staged_n as usize {
+        return Err(ServerError::internal(format!(
+          "segment {} has {} staged rows but {} in metadata",
+          segment_key,
+          rows.len(),
+          segment_meta.staged_n,
+        )));
+      }
+      (staged_bytes, rows)
+    };
+    let n_rows = rows.len() as u64;
     log::debug!(
       "flushing {} rows for segment {}",
       n_rows,
       segment_key,
     );
 
-    let staged_rows_path = dirs::staged_rows_path(dir, &segment_key);
-    let staged_bytes = fs::read(&staged_rows_path).await?;
-    let rows = common::staged_bytes_to_rows(&staged_bytes)?;
+    computed::fill_rows(&locks.table_meta.computed_columns, &mut rows);
+    let augmented_cols = common::augmented_columns(
+      &schema
+    );
+    let mut encoded_cols = HashMap::with_capacity(augmented_cols.len());
+    for (col_name, col_meta) in &augmented_cols {
+      let field_values = rows
+        .iter()
+        .map(|row| row.fields.get(col_name).cloned().unwrap_or_default())
+        .collect::<Vec<FieldValue>>();
+      encoded_cols.insert(col_name, zone_map::encode_blocks(col_meta, &field_values)?);
+    }
+
+    // past here, the flush changes files, so it can only stop now
+    cancel::check()?;
+    let mut segment_guard = slow_ops::wait_for_lock("segment", segment_lock.write()).await?;
+    // the server may have become unhealthy while this waited, and then the
+    // staged rows must stay as they are for recovery
+    server.check_writable()?;
+    let segment_meta = match &mut *segment_guard {
+      Some(segment_meta) => segment_meta,
+      None => return Err(ServerError::does_not_exist("segment", segment_key)),
+    };
+    // only flushes remove staged rows, and writes only append them
+    let current_staged_bytes = vfs::read(&staged_rows_path).await?;
+    if segment_meta.staged_n < n_rows || !current_staged_bytes.starts_with(&staged_bytes) {
+      return Err(ServerError::internal(format!(
+        "staged rows of segment {} changed during flush",
+        segment_key,
+      )));
+    }
 
     // if any columns in the request have never been explicitly flushed to this
     // segment before, we need to initialize them
     let mut new_explicit_columns = HashSet::new();
-    let schema = table_meta.schema();
     for col_name in schema.columns.keys() {
       if !segment_meta.explicit_columns.contains(col_name) {
         new_explicit_columns.insert(col_name.to_string());
@@ -74,37 +148,62 @@ impl ServerOp for FlushOp {
     // before we do anything destructive, mark this segment as flushing
     // for recovery purposes
     segment_meta.flushing = true;
-    segment_meta.overwrite(dir, &segment_key).await?;
-
-    let augmented_cols = common::augmented_columns(
-      &schema
-    );
+    segment_meta.overwrite(dir, segment_key).await?;
 
+    // Codecs chosen for new explicit columns are committed in one batch
+    // with the segment's new counts, so the compaction locks they change
+    // are held until then.
+    let mut batch = MetadataBatch::default();
+    let mut compaction_updates = Vec::new();
+    let mut bytes_out = 0;
     for &version in &segment_meta.write_versions {
       let compaction_key = segment_key.compaction_key(version);
+      let compaction_lock = server.compaction_cache.get_lock(&compaction_key).await?;
+      let maybe_compaction_guard = if new_explicit_columns.is_empty() {
+        None
+      } else {
+        Some(slow_ops::wait_for_lock("compaction", compaction_lock.clone().write_owned()).await?)
+      };
+      let mut compaction = match &maybe_compaction_guard {
+        Some(compaction_guard) => (**compaction_guard).clone(),
+        None => compaction_lock.read().await.clone(),
+      }.unwrap_or_default();
+      let n_codecs = compaction.col_codecs.len();
+      let maybe_cipher = server.column_cipher(&compaction).await?;
       for (col_name, col_meta) in &augmented_cols {
         if new_explicit_columns.contains(col_name) {
-          self.assert_explicit_files(
+          Self::assert_explicit_files(
             col_name,
             col_meta,
-            &compaction_key,
             segment_meta,
+            ExplicitFilesVersion {
+              compaction_key: &compaction_key,
+              compaction: &mut compaction,
+              maybe_cipher: maybe_cipher.as_deref(),
+            },
             server
           ).await?;
         }
 
-        let field_values = rows
-          .iter()
-          .map(|row| row.fields.get(col_name).cloned().unwrap_or_default())
-          .collect::<Vec<FieldValue>>();
-        let dtype = common::unwrap_dtype(col_meta.dtype)?;
-        let bytes = encoding::new_encoder(dtype, col_meta.nested_list_depth as u8)
-          .encode(&field_values)?;
-        common::append_to_file(
+        let (bytes, zone_map_bytes) = &encoded_cols[col_name];
+        bytes_out += bytes.len() + zone_map_bytes.len();
+        storage::append(
           &dirs::flush_col_file(dir, &compaction_key, col_name),
-          &bytes,
+          bytes,
+          maybe_cipher.as_deref(),
+        ).await?;
+        storage::append(
+          &dirs::zone_map_file(dir, &compaction_key, col_name),
+          zone_map_bytes,
+          maybe_cipher.as_deref(),
         ).await?;
       }
+      if let Some(compaction_guard) = maybe_compaction_guard {
+        if compaction.col_codecs.len() != n_codecs {
+          batch.add(&compaction, &compaction_key)?;
+          compaction_updates.push((compaction_guard, compaction));
+        }
+      }
     }
 
     if !new_explicit_columns.is_empty() {
@@ -116,82 +215,107 @@ impl ServerOp for FlushOp {
       segment_meta.explicit_columns.extend(new_explicit_columns);
     }
     segment_meta.last_flush_at = Utc::now();
-    segment_meta.staged_n = 0;
-    segment_meta.overwrite(dir, &segment_key).await?;
+    segment_meta.staged_n -= n_rows;
+    batch.add(&*segment_meta, segment_key)?;
+    batch.commit(server).await?;
+    for (mut compaction_guard, compaction) in compaction_updates {
+      *compaction_guard = Some(compaction);
+    }
 
-    log::debug!("truncating staged rows path {:?}", staged_rows_path);
-    Self::truncate_staged_rows(staged_rows_path).await?;
+    log::debug!("removing flushed rows from staged rows path {:?}", staged_rows_path);
+    Self::overwrite_staged_rows(
+      server,
+      staged_rows_path,
+      &current_staged_bytes[staged_bytes.len()..],
+    ).await?;
 
     segment_meta.flushing = false;
-    segment_meta.overwrite(dir, &segment_key).await?;
+    segment_meta.overwrite(dir, segment_key).await?;
+    drop(segment_guard);
 
-    Ok(())
+    server.record_op_history(OpRecord {
+      kind: "flush",
+      segment_key: segment_key.clone(),
+      started_at,
+      duration: start.elapsed(),
+      n_rows,
+      bytes_in: staged_bytes.len() as u64,
+      bytes_out: bytes_out as u64,
+      codecs: HashMap::new(),
+    }).await;
+    server.measure_disk_usage(segment_key).await
   }
 }
 
 impl FlushOp {
   async fn assert_explicit_files(
-    &self,
     col_name: &str,
     col_meta: &ColumnMeta,
-    compaction_key: &CompactionKey,
     segment_meta: &SegmentMetadata,
+    version: ExplicitFilesVersion<'_>,
     server: &Server,
   ) -> ServerResult<()> {
+    let ExplicitFilesVersion {
+      compaction_key,
+      compaction,
+      maybe_cipher,
+    } = version;
     let dir = &server.opts.dir;
     let dtype = common::unwrap_dtype(col_meta.dtype)?;
     let nested_list_depth = col_meta.nested_list_depth as u8;
 
     // compacted data
-    let compaction = {
-      let compaction_lock = server.compaction_cache.get_lock(compaction_key).await?;
-      let mut compaction_guard = compaction_lock.write().await;
-      let mut compaction = compaction_guard.clone().unwrap_or_default();
-
-      let compacted_n = compaction.all_time_compacted_n - compaction.all_time_omitted_n;
-      if compacted_n > 0 {
-        let codec_name = compaction.col_codecs
-          .get(col_name)
-          .cloned()
-          .unwrap_or_else(|| compression::choose_codec(dtype));
-        let codec = compression::new_codec(dtype, &codec_name)?;
-        let mut compacted_nulls = Vec::with_capacity(compacted_n as usize);
-        for _ in 0..compacted_n {
-          compacted_nulls.push(FieldValue::default());
-        }
-        let compacted_null_bytes = codec.compress(&compacted_nulls, nested_list_depth)?;
-        compaction.col_codecs.insert(col_name.to_string(), codec_name);
-        compaction.overwrite(dir, compaction_key).await?;
-        *compaction_guard = Some(compaction.clone());
-
-        log::info!(
-          "asserting explicit compaction column file for {} column {} with {} rows",
-          compaction_key,
-          col_name,
-          compacted_n,
-        );
-        common::assert_file(
-          &dirs::compact_col_file(dir, compaction_key, col_name),
-          compacted_null_bytes
-        ).await?;
+    let compacted_n = compaction.stored_n()?;
+    if compacted_n > 0 {
+      let codec_name = compaction.col_codecs
+        .get(col_name)
+        .cloned()
+        .unwrap_or_else(|| compression::choose_codec(dtype));
+      let codec = compression::new_codec(dtype, &codec_name)?;
+      let mut compacted_nulls = Vec::with_capacity(compacted_n as usize);
+      for _ in 0..compacted_n {
+        compacted_nulls.push(FieldValue::default());
       }
-      compaction
-    };
+      let mut compacted_null_bytes = codec.compress(&compacted_nulls, nested_list_depth)?;
+      if compaction.checksummed {
+        compacted_null_bytes = checksum::with_footer(compacted_null_bytes);
+      }
+      compaction.col_codecs.insert(col_name.to_string(), codec_name);
+
+      log::info!(
+        "asserting explicit compaction column file for {} column {} with {} rows",
+        compaction_key,
+        col_name,
+        compacted_n,
+      );
+      storage::assert_file(
+        &dirs::compact_col_file(dir, compaction_key, col_name),
+        compacted_null_bytes,
+        maybe_cipher,
+      ).await?;
+    }
 
     // flushed data
-    let flushed_n = common::flush_only_n(segment_meta, &compaction);
+    let flushed_n = common::checked_u32_n(common::flush_only_n(segment_meta, compaction)?)?;
     if flushed_n > 0 {
       let encoder = encoding::new_encoder(dtype, nested_list_depth);
-      let flushed_null_bytes = encoder.encode_count(flushed_n as u32);
+      let flushed_null_bytes = encoder.encode_count(flushed_n);
       log::info!(
         "asserting explicit flush column file for {} column {} with {} rows",
         compaction_key,
         col_name,
         flushed_n,
       );
-      common::assert_file(
+      let zone_map_bytes = zone_map::null_block_bytes(flushed_n, flushed_null_bytes.len());
+      storage::assert_file(
         &dirs::flush_col_file(dir, compaction_key, col_name),
-        flushed_null_bytes
+        flushed_null_bytes,
+        maybe_cipher,
+      ).await?;
+      storage::assert_file(
+        &dirs::zone_map_file(dir, compaction_key, col_name),
+        zone_map_bytes,
+        maybe_cipher,
       ).await?;
     }
 
@@ -199,7 +323,7 @@ impl FlushOp {
   }
 
   async fn truncate_staged_rows(staged_rows_path: PathBuf) -> ServerResult<()> {
-    fs::OpenOptions::new()
+    vfs::OpenOptions::new()
       .create(true)
       .write(true)
       .truncate(true)
@@ -208,6 +332,23 @@ impl FlushOp {
     Ok(())
   }
 
+  // rows staged after a flush began stay staged
+  async fn overwrite_staged_rows(
+    server: &Server,
+    staged_rows_path: PathBuf,
+    remaining_bytes: &[u8],
+  ) -> ServerResult<()> {
+    if remaining_bytes.is_empty() {
+      Self::truncate_staged_rows(staged_rows_path).await
+    } else {
+      common::overwrite_file_atomic(
+        &staged_rows_path,
+        remaining_bytes,
+        &server.opts.dir,
+      ).await
+    }
+  }
+
   async fn trim_flush_files(
     server: &Server,
     table_meta: &TableMetadata,
@@ -221,41 +362,48 @@ impl FlushOp {
       .await?
       .unwrap_or_default();
 
-    let trim_idx = common::flush_only_n(segment_meta, &compaction) as usize;
-    for (col_name, col_meta) in &table_meta.schema().columns {
+    let trim_idx = common::flush_only_n(segment_meta, &compaction)? as usize;
+    let maybe_cipher = server.column_cipher(&compaction).await?;
+    // the flush appended to the row id and written at columns too
+    for (col_name, col_meta) in &common::augmented_columns(&table_meta.schema()) {
       let flush_file = dirs::flush_col_file(dir, compaction_key, col_name);
-      let bytes_result = fs::read(&flush_file).await;
-
-      if let Err(e) = &bytes_result {
-        if matches!(e.kind(), io::ErrorKind::NotFound) {
-          log::debug!(
-            "flush file for {} column {} does not yet exist; skipping trim",
-            compaction_key,
-            col_name,
-          );
-          continue;
-        }
+      if !common::file_exists(&flush_file).await? {
+        log::debug!(
+          "flush file for {} column {} does not yet exist; skipping trim",
+          compaction_key,
+          col_name,
+        );
+        continue;
       }
 
+      // only the block the trim falls in needs decoding
       log::debug!("determining where to truncate {:?}", flush_file);
-      let bytes = bytes_result?;
-      let trim_byte_idx = decoding_seek::byte_idx_for_row_idx(
+      let seek_index = server.read_seek_index(compaction_key, col_name, maybe_cipher.as_deref()).await?;
+      let span = seek_index.span_for_row_idx(trim_idx);
+      let span_bytes = storage::read_with_offset(&flush_file, span.start, span.byte_len(), maybe_cipher.as_deref()).await?;
+      let trim_byte_idx = span.start + decoding_seek::byte_idx_for_row_idx(
         common::unwrap_dtype(col_meta.dtype)?,
         col_meta.nested_list_depth as u8,
-        &bytes,
-        trim_idx,
-      )?;
-
-      if trim_byte_idx != bytes.len() {
-        log::debug!("trimming {:?} from {} to {} bytes", flush_file, bytes.len(), trim_byte_idx);
-        let file = OpenOptions::new()
-          .write(true)
-          .open(flush_file)
-          .await?;
-        file.set_len(trim_byte_idx as u64).await?;
+        &span_bytes,
+        trim_idx - span.row_idx,
+      )? as u64;
+      let flush_byte_len = storage::len_or_zero(&flush_file, maybe_cipher.as_deref()).await?;
+
+      if trim_byte_idx != flush_byte_len {
+        log::debug!("trimming {:?} from {} to {} bytes", flush_file, flush_byte_len, trim_byte_idx);
+        storage::truncate(&flush_file, trim_byte_idx, maybe_cipher.as_deref()).await?;
       } else {
         log::debug!("no trim needed for {:?}", flush_file);
       }
+
+      // the zone map may also have blocks from the interrupted flush
+      let zone_map_path = dirs::zone_map_file(dir, compaction_key, col_name);
+      let zone_map_bytes = storage::read_or_empty(&zone_map_path, maybe_cipher.as_deref()).await?;
+      let zone_map_len = zone_map::trimmed_len(&zone_map_bytes, trim_byte_idx);
+      if zone_map_len != zone_map_bytes.len() {
+        log::debug!("trimming {:?} from {} to {} bytes", zone_map_path, zone_map_bytes.len(), zone_map_len);
+        storage::truncate(&zone_map_path, zone_map_len as u64, maybe_cipher.as_deref()).await?;
+      }
     }
     Ok(())
   }
@@ -271,30 +419,40 @@ impl FlushOp {
       return Ok(())
     }
 
-    if segment_meta.staged_n == 0 {
-      log::debug!(
-        "identified incomplete flush in segment {}; recovering by truncating staged rows",
-        segment_key,
-      );
-      let staged_rows_path = dirs::staged_rows_path(
-        dir,
-        segment_key,
-      );
-      Self::truncate_staged_rows(staged_rows_path).await?;
-    } else {
+    // Trimming to the flushed row count in metadata undoes any appends the
+    // flush didn't record, and is a no-op if it did record them. In that
+    // case, the flushed rows may still lead the staged rows file.
+    log::debug!(
+      "identified incomplete flush in segment {}; recovering by trimming flush columns",
+      segment_key,
+    );
+    for version in &segment_meta.write_versions {
+      let compaction_key = segment_key.compaction_key(*version);
+      Self::trim_flush_files(
+        server,
+        table_meta,
+        segment_meta,
+        &compaction_key
+      ).await?;
+    }
+    let staged_rows_path = dirs::staged_rows_path(
+      dir,
+      segment_key,
+    );
+    let staged_bytes = common::read_or_empty(&staged_rows_path).await?;
+    let staged_rows = common::staged_bytes_to_rows(&staged_bytes)?;
+    if staged_rows.len() > segment_meta.staged_n as usize {
+      let n_flushed = staged_rows.len() - segment_meta.staged_n as usize;
       log::debug!(
-        "identified incomplete flush in segment {}; recovering by trimming flush columns",
+        "removing {} flushed rows from staged rows of segment {}",
+        n_flushed,
         segment_key,
       );
-      for version in &segment_meta.write_versions {
-        let compaction_key = segment_key.compaction_key(*version);
-        Self::trim_flush_files(
-          server,
-          table_meta,
-          segment_meta,
-          &compaction_key
-        ).await?;
-      }
+      Self::overwrite_staged_rows(
+        server,
+        staged_rows_path,
+        &common::rows_to_staged_bytes(&staged_rows[n_flushed..])?,
+      ).await?;
     }
 
     log::debug!("cleaning segment {} metadata", segment_key);
@@ -0,0 +1,70 @@
+use async_trait::async_trait;
+
+use crate::errors::ServerResult;
+use crate::locks::table::TableWriteLocks;
+use crate::metadata::PersistentMetadata;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, FreezeTableRequestSerde};
+use crate::server::Server;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
+use crate::utils::common;
+
+// Freezes a table so that its data can no longer change, e.g. once it is
+// archived, or unfreezes it. Compaction of a frozen table carries on.
+pub struct FreezeTableOp {
+  pub req: FreezeTableRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for FreezeTableOp {
+  type Locks = TableWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    Some(AuditEvent {
+      operation: "freeze_table",
+      table_name: self.req.table_name.clone(),
+      summary: format!("frozen {}", self.req.frozen),
+    })
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableWriteLocks) -> ServerResult<EmptySerde> {
+    let table_name = &self.req.table_name;
+    common::validate_entity_name_for_write("table name", table_name)?;
+
+    let TableWriteLocks {
+      mut maybe_table_guard,
+    } = locks;
+    let mut table_meta = common::unwrap_metadata(table_name, &*maybe_table_guard)?;
+    if table_meta.frozen != self.req.frozen {
+      log::info!(
+        "{} table {}",
+        if self.req.frozen { "freezing" } else { "unfreezing" },
+        table_name,
+      );
+      table_meta.frozen = self.req.frozen;
+      table_meta.overwrite(&server.opts.dir, table_name).await?;
+      *maybe_table_guard = Some(table_meta);
+    }
+    Ok(EmptySerde {})
+  }
+}
+
+impl RestRoute for FreezeTableOp {
+  type Req = FreezeTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "freeze_table";
+
+  fn new_op(req: Self::Req) -> FreezeTableOp {
+    FreezeTableOp { req }
+  }
+}
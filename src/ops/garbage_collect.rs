@@ -0,0 +1,129 @@
+use std::io::ErrorKind;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+
+use crate::constants::GARBAGE_SEGMENT_PREFIX;
+use crate::errors::{ServerError, ServerResult};
+use crate::locks::table::TableReadLocks;
+use crate::metadata::PersistentMetadata;
+use crate::metadata::segment::SegmentMetadata;
+use crate::ops::traits::ServerOp;
+use crate::opt::RuntimeConfig;
+use crate::server::Server;
+use crate::server::slow_ops;
+use crate::types::{PartitionKey, SegmentKey};
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+pub struct GarbageCollectOp {
+  pub key: SegmentKey,
+}
+
+impl GarbageCollectOp {
+  fn is_garbage(&self, runtime_config: &RuntimeConfig, segment_meta: &SegmentMetadata) -> bool {
+    let grace = Duration::seconds(runtime_config.gc_fully_deleted_segment_seconds);
+    segment_meta.is_cold &&
+      segment_meta.all_time_n > 0 &&
+      segment_meta.all_time_deleted_n >= segment_meta.all_time_n &&
+      segment_meta.staged_n == 0 &&
+      !segment_meta.flushing &&
+      segment_meta.write_versions.len() == 1 &&
+      Utc::now() - segment_meta.last_flush_at > grace
+  }
+}
+
+#[async_trait]
+impl ServerOp for GarbageCollectOp {
+  type Locks = TableReadLocks;
+  // whether the segment was garbage collected
+  type Response = bool;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.key.table_name.clone())
+  }
+
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  // 1. obtain partition, deletion, and segment write locks (in the same order
+  //    as writes and deletions do) and check whether every row is deleted
+  //    and no read has pinned any of its versions
+  // 2. remove the segment from the partition's active segments
+  // 3. move the segment directory out of the way, then remove it
+  // A crash after 2 just leaves an ordinary inactive segment for the next
+  // sweep, and a crash after the move leaves a directory recovery will remove.
+  async fn execute_with_locks(&self, server: &Server, _locks: TableReadLocks) -> ServerResult<bool> {
+    server.check_writable()?;
+    let dir = &server.opts.dir;
+    let runtime_config = server.runtime_config().await;
+    let partition_key = self.key.partition_key();
+
+    let partition_lock = server.partition_metadata_cache.get_lock(&partition_key)
+      .await?;
+    let mut partition_guard = slow_ops::wait_for_lock("partition", partition_lock.write()).await?;
+    let deletion_lock = server.deletion_metadata_cache.get_lock(&self.key)
+      .await?;
+    let _deletion_guard = slow_ops::wait_for_lock("deletion", deletion_lock.write()).await?;
+    let segment_lock = server.segment_metadata_cache.get_lock(&self.key)
+      .await?;
+    let mut segment_guard = slow_ops::wait_for_lock("segment", segment_lock.write()).await?;
+
+    let is_garbage = match &*segment_guard {
+      Some(segment_meta) => self.is_garbage(&runtime_config, segment_meta),
+      None => return Err(ServerError::does_not_exist("segment", &self.key)),
+    };
+    // ongoing correlated reads and snapshots may still need its files
+    let is_pinned = !server.correlation_metadata_cache.pinned_versions(&self.key).await.is_empty();
+    if !is_garbage || is_pinned {
+      return Ok(false);
+    }
+
+    log::info!("garbage collecting fully deleted segment {}", self.key);
+    server.remove_from_partition_index(&partition_key, &[self.key.segment_id]).await?;
+    if let Some(partition_meta) = &mut *partition_guard {
+      if partition_meta.active_segment_ids.contains(&self.key.segment_id) {
+        partition_meta.active_segment_ids.retain(|&id| id != self.key.segment_id);
+        partition_meta.overwrite(dir, &partition_key).await?;
+      }
+    }
+
+    let garbage_dir = dirs::garbage_segment_dir(dir, &self.key);
+    vfs::rename(dirs::segment_dir(dir, &self.key), &garbage_dir).await?;
+    *segment_guard = None;
+    server.compaction_cache.prune(|key| key.segment_key() == self.key)
+      .await;
+    vfs::remove_dir_all(&garbage_dir).await?;
+    Ok(true)
+  }
+}
+
+impl GarbageCollectOp {
+  pub async fn recover(
+    server: &Server,
+    partition_key: &PartitionKey,
+  ) -> ServerResult<()> {
+    let partition_dir = dirs::partition_dir(&server.opts.dir, partition_key);
+    let mut read_dir = match vfs::read_dir(&partition_dir).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(()),
+      Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = read_dir.next_entry().await? {
+      let fname = entry.file_name();
+      let is_garbage = fname.to_str()
+        .map(|s| s.starts_with(GARBAGE_SEGMENT_PREFIX))
+        .unwrap_or(false);
+      if is_garbage && entry.file_type().await?.is_dir() {
+        log::debug!(
+          "identified interrupted garbage collection in {}; removing {:?}",
+          partition_key,
+          fname,
+        );
+        vfs::remove_dir_all(entry.path()).await?;
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use pancake_db_idl::dml::ListSegmentsRequest;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{GetColumnSketchRequestSerde, GetColumnSketchResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+use crate::utils::common;
+use crate::utils::hll::HyperLogLog;
+
+// Merges the distinct-count sketches that compaction stores for a column
+// across every matching segment. Only compacted rows are covered; the
+// response says how many rows were left out.
+pub struct GetColumnSketchOp {
+  pub req: GetColumnSketchRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for GetColumnSketchOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = GetColumnSketchResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let schema = locks.table_meta.visible_schema();
+    if !schema.columns.contains_key(&req.column_name) {
+      return Err(ServerError::does_not_exist("column", &req.column_name));
+    }
+    let col_name = locks.table_meta.stored_column_name(&req.column_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", &req.column_name))?;
+    let partition_filter = write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?;
+    let list_req = ListSegmentsRequest {
+      table_name: req.table_name.clone(),
+      ..Default::default()
+    };
+    let listed = ListSegmentsOp { req: list_req, partition_conditions: Vec::new() }.execute_with_locks(server, locks).await?;
+
+    let mut sketch = HyperLogLog::default();
+    let mut n_segments = 0;
+    let mut n_sketched_segments = 0;
+    let mut n_unsketched_rows = 0;
+    for segment in &listed.segments {
+      let is_match = partition_filter.iter()
+        .all(|(name, value)| segment.partition.get(name) == Some(value));
+      if !is_match {
+        continue;
+      }
+
+      let partition_key = PartitionKey {
+        table_name: req.table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      let maybe_segment_meta = server.segment_metadata_cache.get_lock(&segment_key)
+        .await?
+        .read()
+        .await
+        .clone();
+      let segment_meta = match maybe_segment_meta {
+        Some(segment_meta) => segment_meta,
+        None => continue,
+      };
+      n_segments += 1;
+
+      // compaction metadata never changes once the version is readable
+      let compaction = server.compaction_cache
+        .get_lock(&segment_key.compaction_key(segment_meta.read_version))
+        .await?
+        .read()
+        .await
+        .clone()
+        .unwrap_or_default();
+      match compaction.col_sketches.get(&col_name) {
+        Some(segment_sketch) => {
+          sketch.merge(segment_sketch);
+          n_sketched_segments += 1;
+          n_unsketched_rows += common::checked_sub_n(segment_meta.all_time_n, compaction.all_time_compacted_n, "compacted rows")?;
+        },
+        None => {
+          n_unsketched_rows += segment_meta.live_n()?;
+        },
+      }
+    }
+
+    Ok(GetColumnSketchResponseSerde {
+      approx_distinct_count: sketch.estimate(),
+      sketch: sketch.into(),
+      n_segments,
+      n_sketched_segments,
+      n_unsketched_rows,
+    })
+  }
+}
+
+impl RestRoute for GetColumnSketchOp {
+  type Req = GetColumnSketchRequestSerde;
+
+  const ROUTE_NAME: &'static str = "get_column_sketch";
+
+  fn new_op(req: Self::Req) -> GetColumnSketchOp {
+    GetColumnSketchOp { req }
+  }
+}
@@ -0,0 +1,217 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use pancake_db_idl::dml::FieldValue;
+use pancake_db_idl::dml::field_value::Value;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest::{self, field_value_to_json};
+use crate::serde_models::{GetRowsByIdRequestSerde, GetRowsByIdResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+use crate::utils::common;
+use crate::utils::computed;
+use crate::utils::dirs;
+
+const MAX_ROW_IDS: usize = 4096;
+
+// Looks up rows of a segment by _row_id. A row keeps its row id for as long
+// as it's in the segment, through flushes and compactions, sorted ones
+// included, so clients can hold on to row ids to delete or re-read rows
+// later; merging or splitting segments moves rows to new segments under new
+// row ids. Compacted blocks are decompressed as far as the last row needed,
+// but flushed values are found by seeking through the flush file rather
+// than decoding every value before them.
+pub struct GetRowsByIdOp {
+  pub req: GetRowsByIdRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for GetRowsByIdOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = GetRowsByIdResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    common::validate_segment_id(&req.segment_id)?;
+    if req.row_ids.is_empty() {
+      return Err(ServerError::invalid("no row ids provided to get"));
+    }
+    if req.row_ids.len() > MAX_ROW_IDS {
+      return Err(ServerError::invalid(format!(
+        "number of row ids may not exceed {} but was {}",
+        MAX_ROW_IDS,
+        req.row_ids.len(),
+      )));
+    }
+
+    let table_meta = &locks.table_meta;
+    let schema = table_meta.visible_schema();
+    let col_names = match &req.columns {
+      Some(col_names) => {
+        common::check_no_duplicate_names("column", col_names.clone())?;
+        col_names.clone()
+      },
+      None => schema.columns.keys().cloned().collect(),
+    };
+    let mut stored_col_names = Vec::new();
+    for col_name in &col_names {
+      if !schema.columns.contains_key(col_name) {
+        return Err(ServerError::does_not_exist("column", col_name));
+      }
+      let stored_col_name = table_meta.stored_column_name(col_name)
+        .ok_or_else(|| ServerError::does_not_exist("column", col_name))?;
+      stored_col_names.push(stored_col_name);
+    }
+
+    let partition_key = PartitionKey {
+      table_name: req.table_name.clone(),
+      partition: NormalizedPartition::from_raw_fields(
+        &write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?,
+      )?,
+    };
+    let segment_key = partition_key.segment_key(Uuid::from_str(&req.segment_id)?);
+    let segment_meta = common::unwrap_metadata(
+      &segment_key,
+      &*server.segment_metadata_cache.get_lock(&segment_key).await?.read().await,
+    )?;
+    let version = segment_meta.read_version;
+    let compaction_key = segment_key.compaction_key(version);
+    let compaction = server.compaction_cache
+      .get_lock(&compaction_key)
+      .await?
+      .read()
+      .await
+      .clone()
+      .unwrap_or_default();
+    let deletions = server.read_combined_deletions(&compaction_key, segment_meta.deletion_id).await?;
+
+    // deletions are by position, which differs from row id in sorted
+    // compactions
+    let compacted_row_ids = server.read_compacted_row_ids(&segment_key, version, &compaction).await?;
+    let compacted_positions: HashMap<u32, usize> = compacted_row_ids.iter()
+      .enumerate()
+      .map(|(position, &row_id)| (row_id, position))
+      .collect();
+    let mut found = Vec::new();
+    let mut missing_row_ids = Vec::new();
+    let mut seen_row_ids = HashSet::new();
+    for &row_id in &req.row_ids {
+      if !seen_row_ids.insert(row_id) {
+        continue;
+      }
+      let maybe_position = if row_id as u64 >= segment_meta.all_time_n {
+        None
+      } else if compacted_row_ids.is_empty() {
+        Some(row_id as usize)
+      } else {
+        compacted_positions.get(&row_id).cloned()
+          .or_else(|| Some(row_id as usize).filter(|&position| position >= compacted_row_ids.len()))
+      };
+      match maybe_position {
+        Some(position) if !deletions.get(position).cloned().unwrap_or(false) => found.push((row_id, position)),
+        _ => missing_row_ids.push(row_id),
+      }
+    }
+
+    let n_compacted = compaction.all_time_compacted_n as usize;
+    let n_flushed = segment_meta.flushed_n()? as usize;
+    let mut sorted_positions: Vec<usize> = found.iter().map(|(_, position)| *position).collect();
+    sorted_positions.sort_unstable();
+    let compacted_limit = sorted_positions.iter()
+      .filter(|&&position| position < n_compacted)
+      .max()
+      .map(|&position| position + 1)
+      .unwrap_or(0);
+    let flushed_positions: Vec<usize> = sorted_positions.iter()
+      .cloned()
+      .filter(|&position| position >= n_compacted && position < n_flushed)
+      .collect();
+    let flushed_offsets: Vec<usize> = flushed_positions.iter()
+      .map(|&position| position - n_compacted)
+      .collect();
+    let staged_rows = if sorted_positions.last().map(|&position| position >= n_flushed).unwrap_or(false) {
+      let staged_bytes = common::read_or_empty(dirs::staged_rows_path(&server.opts.dir, &segment_key)).await?;
+      let mut staged_rows = common::staged_bytes_to_rows(&staged_bytes)?;
+      computed::fill_rows(&table_meta.computed_columns, &mut staged_rows);
+      staged_rows
+    } else {
+      Vec::new()
+    };
+
+    let masks = server.read_masks(&req.table_name, table_meta).await;
+    let mut rows = vec![HashMap::new(); found.len()];
+    for (col_name, stored_col_name) in col_names.iter().zip(&stored_col_names) {
+      let col_meta = &schema.columns[col_name];
+      let is_explicit = segment_meta.explicit_columns.contains(stored_col_name);
+      let compacted_values = if is_explicit && compacted_limit > 0 {
+        server.read_compact_col(&segment_key, stored_col_name, col_meta, version, &compaction, compacted_limit).await?
+      } else {
+        Vec::new()
+      };
+      let flushed_values: HashMap<usize, FieldValue> = if is_explicit {
+        let values = server.read_flush_col_at(
+          &segment_key,
+          stored_col_name,
+          col_meta,
+          version,
+          &compaction,
+          &flushed_offsets,
+        ).await?;
+        flushed_positions.iter().cloned().zip(values).collect()
+      } else {
+        HashMap::new()
+      };
+
+      for ((_, position), row) in found.iter().zip(rows.iter_mut()) {
+        let value = if *position < n_compacted {
+          compacted_values.get(*position).cloned()
+        } else if *position < n_flushed {
+          flushed_values.get(position).cloned()
+        } else {
+          staged_rows.get(*position - n_flushed)
+            .and_then(|staged_row| staged_row.fields.get(stored_col_name).cloned())
+        }.unwrap_or_default();
+        let value = match masks.get(stored_col_name) {
+          Some(rule) => rule.mask(&value),
+          None => value,
+        };
+        row.insert(col_name.clone(), field_value_to_json(&value)?);
+      }
+    }
+    for ((row_id, _), row) in found.iter().zip(rows.iter_mut()) {
+      row.insert(ROW_ID_COLUMN_NAME.to_string(), field_value_to_json(&FieldValue {
+        value: Some(Value::Int64Val(*row_id as i64)),
+      })?);
+    }
+
+    Ok(GetRowsByIdResponseSerde {
+      rows,
+      missing_row_ids,
+    })
+  }
+}
+
+impl RestRoute for GetRowsByIdOp {
+  type Req = GetRowsByIdRequestSerde;
+
+  const ROUTE_NAME: &'static str = "get_rows_by_id";
+
+  fn new_op(req: Self::Req) -> GetRowsByIdOp {
+    GetRowsByIdOp { req }
+  }
+}
@@ -6,6 +6,7 @@ use crate::locks::table::TableReadLocks;
 use crate::ops::traits::ServerOp;
 
 use crate::server::Server;
+use crate::server::authz::{Access, Verb};
 
 pub struct GetSchemaOp {
   pub req: GetSchemaRequest,
@@ -20,10 +21,18 @@ impl ServerOp for GetSchemaOp {
     Ok(self.req.table_name.clone())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
   async fn execute_with_locks(&self, _server: &Server, locks: TableReadLocks) -> ServerResult<GetSchemaResponse> {
     let TableReadLocks { table_meta } = locks;
     Ok(GetSchemaResponse {
-      schema: Some(table_meta.schema()),
+      schema: Some(table_meta.visible_schema()),
       ..Default::default()
     })
   }
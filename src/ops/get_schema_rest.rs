@@ -0,0 +1,87 @@
+use std::convert::TryFrom;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use pancake_db_idl::ddl::GetSchemaRequest;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::TableReadLocks;
+use crate::ops::get_schema::GetSchemaOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{GetSchemaRequestSerde, GetSchemaResponseSerde, SchemaSerde, TableConfigSerde};
+use crate::server::authz::{Access, Verb};
+
+pub struct GetSchemaRestOp {
+  pub req: GetSchemaRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for GetSchemaRestOp {
+  type Locks = TableReadLocks;
+  type Response = GetSchemaResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableReadLocks) -> ServerResult<Self::Response> {
+    let sort_columns = locks.table_meta.sort_columns.iter()
+      .map(|col_name| locks.table_meta.visible_column_name(col_name))
+      .collect();
+    let computed_columns = locks.table_meta.visible_computed_columns().into_iter()
+      .map(|(col_name, computed)| (col_name, computed.expression()))
+      .collect();
+    let column_masks = locks.table_meta.visible_column_masks().into_iter()
+      .map(|(col_name, rule)| (col_name, rule.expression()))
+      .collect();
+    let table_meta = &locks.table_meta;
+    let format_time = |t: &DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let schema_version = table_meta.schema_version;
+    let frozen = table_meta.frozen;
+    let config = TableConfigSerde::from(&table_meta.config_overrides);
+    let created_at = table_meta.created_at.as_ref().map(format_time);
+    let last_altered_at = table_meta.last_altered_at.as_ref().map(format_time);
+    let column_added_at = table_meta.column_added_at.iter()
+      .map(|(col_name, added_at)| (table_meta.visible_column_name(col_name), format_time(added_at)))
+      .collect();
+    let req = GetSchemaRequest {
+      table_name: self.req.table_name.clone(),
+    };
+    let pb_resp = GetSchemaOp { req }.execute_with_locks(server, locks).await?;
+    let schema = pb_resp.schema
+      .ok_or_else(|| ServerError::internal("get schema response is missing schema"))?;
+    Ok(GetSchemaResponseSerde {
+      schema: SchemaSerde {
+        sort_columns,
+        computed_columns,
+        column_masks,
+        ..SchemaSerde::try_from(&schema)?
+      },
+      schema_version,
+      created_at,
+      last_altered_at,
+      column_added_at,
+      frozen,
+      config,
+    })
+  }
+}
+
+impl RestRoute for GetSchemaRestOp {
+  type Req = GetSchemaRequestSerde;
+
+  const ROUTE_NAME: &'static str = "get_schema";
+
+  fn new_op(req: Self::Req) -> GetSchemaRestOp {
+    GetSchemaRestOp { req }
+  }
+}
@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, HeldLockSerde, HeldLocksResponseSerde};
+
+pub struct HeldLocksOp;
+
+fn extend_with_locks<K: Display>(
+  res: &mut Vec<HeldLockSerde>,
+  entity_name: &str,
+  held_locks: Vec<(K, bool)>,
+) {
+  res.extend(held_locks.into_iter().map(|(key, is_write)| HeldLockSerde {
+    entity_name: entity_name.to_string(),
+    key: key.to_string(),
+    is_write,
+  }));
+}
+
+#[async_trait]
+impl ServerOp for HeldLocksOp {
+  type Locks = TrivialLocks;
+  type Response = HeldLocksResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  // This is only a snapshot; locks may be released or obtained by the time
+  // the response is sent.
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let mut locks = Vec::new();
+    if server.global_metadata_lock.try_write().is_err() {
+      locks.push(HeldLockSerde {
+        entity_name: "global".to_string(),
+        key: "".to_string(),
+        is_write: server.global_metadata_lock.try_read().is_err(),
+      });
+    }
+    extend_with_locks(&mut locks, "table", server.table_metadata_cache.held_locks().await);
+    extend_with_locks(&mut locks, "partition", server.partition_metadata_cache.held_locks().await);
+    extend_with_locks(&mut locks, "partition_index", server.partition_index_cache.held_locks().await);
+    extend_with_locks(&mut locks, "deletion", server.deletion_metadata_cache.held_locks().await);
+    extend_with_locks(&mut locks, "segment", server.segment_metadata_cache.held_locks().await);
+    extend_with_locks(&mut locks, "compaction", server.compaction_cache.held_locks().await);
+    Ok(HeldLocksResponseSerde { locks })
+  }
+}
+
+impl RestRoute for HeldLocksOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "held_locks";
+
+  fn new_op(_req: Self::Req) -> HeldLocksOp {
+    HeldLocksOp
+  }
+}
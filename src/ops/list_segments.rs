@@ -1,7 +1,6 @@
 use std::collections::{HashSet, HashMap};
 
 use async_trait::async_trait;
-use futures::{pin_mut, StreamExt};
 use pancake_db_idl::dml::{ListSegmentsRequest, ListSegmentsResponse, PartitionFieldValue, Segment};
 use pancake_db_idl::dml::SegmentMetadata as PbSegmentMetadata;
 
@@ -10,21 +9,52 @@ use crate::locks::table::GlobalTableReadLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
 use crate::metadata::segment::SegmentMetadata;
-use crate::types::{NormalizedPartition, PartitionKey};
-use crate::utils::{common, navigation, sharding};
+use crate::server::authz::{Access, Verb};
+use crate::server::system_tables::SystemTable;
+use crate::metadata::partition_index::IndexedPartition;
+use crate::types::PartitionKey;
+use crate::utils::{common, sharding};
+use crate::utils::common::PartitionCondition;
 
 pub struct ListSegmentsOp {
   pub req: ListSegmentsRequest,
+  // applied along with the request's partition filter, for conditions it
+  // can't express
+  pub partition_conditions: Vec<PartitionCondition>,
 }
 
 impl ListSegmentsOp {
-  fn pb_segment_meta_from_option(maybe_segment_meta: Option<SegmentMetadata>) -> Option<PbSegmentMetadata> {
+  fn pb_segment_meta_from_option(maybe_segment_meta: Option<SegmentMetadata>) -> ServerResult<Option<PbSegmentMetadata>> {
     maybe_segment_meta.map(|meta| {
-      let row_count = (meta.all_time_n - meta.all_time_deleted_n) as u32;
-      PbSegmentMetadata {
+      let row_count = common::checked_u32_n(meta.live_n()?)?;
+      Ok(PbSegmentMetadata {
         row_count,
         ..Default::default()
-      }
+      })
+    }).transpose()
+  }
+
+  // system tables have a single unpartitioned segment
+  async fn list_system_table_segments(
+    &self,
+    server: &Server,
+    system_table: SystemTable,
+  ) -> ServerResult<ListSegmentsResponse> {
+    let metadata = if self.req.include_metadata {
+      Some(PbSegmentMetadata {
+        row_count: server.system_table_rows(system_table).await?.len() as u32,
+        ..Default::default()
+      })
+    } else {
+      None
+    };
+    Ok(ListSegmentsResponse {
+      segments: vec![Segment {
+        segment_id: SystemTable::segment_id().to_string(),
+        metadata,
+        ..Default::default()
+      }],
+      ..Default::default()
     })
   }
 
@@ -32,27 +62,19 @@ impl ListSegmentsOp {
     &self,
     server: &Server,
     table_name: &str,
-    partitions: &[HashMap<String, PartitionFieldValue>],
+    partitions: Vec<(HashMap<String, PartitionFieldValue>, IndexedPartition)>,
     n_shards_log: u32,
     shards: HashSet<u64>,
     include_metadata: bool,
   ) -> ServerResult<Vec<Segment>> {
     let mut segments = Vec::new();
-    for partition in partitions {
-      let normalized_partition = NormalizedPartition::from_raw_fields(partition)?;
+    for (partition, indexed) in partitions {
       let partition_key = PartitionKey {
         table_name: table_name.to_string(),
-        partition: normalized_partition,
+        partition: indexed.partition,
       };
 
-      let segment_id_stream = navigation::stream_segment_ids_for_partition(
-        &server.opts.dir,
-        partition_key.clone(),
-      );
-      pin_mut!(segment_id_stream);
-      while let Some(segment_id_result) = segment_id_stream.next().await {
-        let segment_id = segment_id_result?;
-
+      for segment_id in indexed.segment_ids {
         if !shards.contains(&sharding::segment_id_to_shard(n_shards_log, segment_id)) {
           continue;
         }
@@ -64,7 +86,7 @@ impl ListSegmentsOp {
             .await?;
           let segment_guard = segment_lock.read().await;
           let maybe_segment_meta = segment_guard.clone();
-          Self::pb_segment_meta_from_option(maybe_segment_meta)
+          Self::pb_segment_meta_from_option(maybe_segment_meta)?
         } else {
           None
         };
@@ -89,6 +111,14 @@ impl ServerOp for ListSegmentsOp {
     Ok(self.req.table_name.clone())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<ListSegmentsResponse> {
     let req = &self.req;
     let table_name = &req.table_name;
@@ -96,16 +126,16 @@ impl ServerOp for ListSegmentsOp {
 
     let GlobalTableReadLocks {
       global_meta,
-      table_meta,
+      table_meta: _,
     } = locks;
 
-    let partitioning = table_meta.schema().partitioning.clone();
-    let partitions = navigation::partitions_for_table(
-      &server.opts.dir,
-      table_name,
-      &partitioning,
-      &req.partition_filter,
-    ).await?;
+    if let Some(system_table) = SystemTable::from_name(table_name) {
+      return self.list_system_table_segments(server, system_table).await;
+    }
+
+    let mut partition_conditions = PartitionCondition::from_filters(&req.partition_filter);
+    partition_conditions.extend(self.partition_conditions.iter().cloned());
+    let partitions = server.indexed_partitions(table_name, &partition_conditions).await?;
 
     let n_shards = 1_u64 << global_meta.n_shards_log;
     let mut all_shards = HashSet::new();
@@ -116,7 +146,7 @@ impl ServerOp for ListSegmentsOp {
     let segments = self.list_shards_segments(
       server,
       table_name,
-      &partitions,
+      partitions,
       global_meta.n_shards_log,
       all_shards,
       req.include_metadata,
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use pancake_db_idl::dml::{ListSegmentsRequest, PartitionFieldValue};
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{ListSegmentsRequestSerde, ListSegmentsResponseSerde, SegmentInfoSerde, SegmentStatsSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+
+pub struct ListSegmentsRestOp {
+  pub req: ListSegmentsRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for ListSegmentsRestOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = ListSegmentsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let table_name = &self.req.table_name;
+    let partition_conditions = write_to_partition_rest::partition_conditions(
+      &self.req.partition_filter,
+      &locks.table_meta.schema().partitioning,
+    )?;
+    let req = ListSegmentsRequest {
+      table_name: table_name.clone(),
+      ..Default::default()
+    };
+    let pb_resp = ListSegmentsOp { req, partition_conditions }.execute_with_locks(server, locks).await?;
+
+    let mut segments = Vec::with_capacity(pb_resp.segments.len());
+    for segment in &pb_resp.segments {
+      let partition_key = PartitionKey {
+        table_name: table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+      let maybe_segment_meta = segment_lock.read().await.clone();
+      let stats = maybe_segment_meta.map(|meta| ServerResult::Ok(SegmentStatsSerde {
+        row_count: meta.live_n()?,
+        all_time_n: meta.all_time_n,
+        all_time_deleted_n: meta.all_time_deleted_n,
+        staged_n: meta.staged_n,
+        uncompressed_bytes: meta.all_time_uncompressed_size,
+        read_version: meta.read_version,
+        is_cold: meta.is_cold,
+        last_flush_at: meta.last_flush_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+      })).transpose()?;
+
+      segments.push(SegmentInfoSerde {
+        partition: segment.partition.iter()
+          .map(|(name, value)| Ok((name.to_string(), partition_field_to_json(value)?)))
+          .collect::<ServerResult<HashMap<_, _>>>()?,
+        segment_id: segment.segment_id.clone(),
+        stats,
+      });
+    }
+    Ok(ListSegmentsResponseSerde { segments })
+  }
+}
+
+// the inverse of the parsing done for REST writes
+pub fn partition_field_to_json(field: &PartitionFieldValue) -> ServerResult<JsonValue> {
+  let res = match &field.value {
+    Some(PartitionValue::StringVal(x)) => JsonValue::String(x.clone()),
+    Some(PartitionValue::Int64Val(x)) => JsonValue::from(*x),
+    Some(PartitionValue::BoolVal(x)) => JsonValue::Bool(*x),
+    Some(PartitionValue::TimestampVal(x)) => {
+      let t = Utc.timestamp_opt(x.seconds, x.nanos as u32)
+        .single()
+        .ok_or_else(|| ServerError::invalid(format!(
+          "partition timestamp {}s {}ns is out of range",
+          x.seconds,
+          x.nanos,
+        )))?;
+      JsonValue::String(t.to_rfc3339_opts(SecondsFormat::Secs, true))
+    },
+    None => JsonValue::Null,
+  };
+  Ok(res)
+}
+
+impl RestRoute for ListSegmentsRestOp {
+  type Req = ListSegmentsRequestSerde;
+
+  const ROUTE_NAME: &'static str = "list_segments";
+
+  fn new_op(req: Self::Req) -> ListSegmentsRestOp {
+    ListSegmentsRestOp { req }
+  }
+}
@@ -5,6 +5,7 @@ use crate::errors::ServerResult;
 use crate::locks::trivial::TrivialLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
+use crate::server::authz::{Access, Verb};
 
 pub struct ListTablesOp {
   pub req: ListTablesRequest,
@@ -19,6 +20,11 @@ impl ServerOp for ListTablesOp {
     Ok(())
   }
 
+  // anyone may list tables, but only sees the ones they may read
+  fn required_access(&self) -> Vec<Access> {
+    Vec::new()
+  }
+
   async fn execute_with_locks(
     &self,
     server: &Server,
@@ -26,6 +32,9 @@ impl ServerOp for ListTablesOp {
   ) -> ServerResult<ListTablesResponse> {
     let mut tables = Vec::new();
     for info in server.internal_list_tables().await? {
+      if !server.is_authorized(&Access::table(Verb::Read, &info.name)).await {
+        continue;
+      }
       tables.push(TableInfo {
         table_name: info.name,
         ..Default::default()
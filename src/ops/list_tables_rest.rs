@@ -1,13 +1,18 @@
 use pancake_db_idl::ddl::ListTablesRequest;
 
 use crate::{Server, ServerResult};
+use crate::errors::{ServerError, ServerErrorKind};
 use crate::locks::traits::ServerOpLocks;
 use crate::locks::trivial::TrivialLocks;
+use crate::ops::list_segments_rest::ListSegmentsRestOp;
 use crate::ops::list_tables::ListTablesOp;
 use crate::ops::traits::{RestRoute, ServerOp};
-use crate::serde_models::{EmptySerde, ListTablesResponseSerde, TableInfoSerde};
+use crate::serde_models::{ListSegmentsRequestSerde, ListTablesRequestSerde, ListTablesResponseSerde, TableInfoSerde, TableStatsSerde};
+use crate::server::authz::Access;
 
-pub struct ListTablesRestOp;
+pub struct ListTablesRestOp {
+  pub req: ListTablesRequestSerde,
+}
 
 #[async_trait::async_trait]
 impl ServerOp for ListTablesRestOp {
@@ -18,24 +23,97 @@ impl ServerOp for ListTablesRestOp {
     Ok(())
   }
 
+  // anyone may list tables, but only sees the ones they may read
+  fn required_access(&self) -> Vec<Access> {
+    Vec::new()
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: Self::Locks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    if req.max_results == Some(0) {
+      return Err(ServerError::invalid("max results must be positive"));
+    }
+
     let pb_resp = ListTablesOp { req: ListTablesRequest::default() }.execute_with_locks(server, locks).await?;
+    // the continuation token is the name of the last table on the previous
+    // page
+    let mut table_names: Vec<String> = pb_resp.tables.into_iter()
+      .map(|t| t.table_name)
+      .filter(|table_name| table_name.starts_with(&req.name_prefix))
+      .filter(|table_name| match &req.continuation_token {
+        Some(token) => table_name > token,
+        None => true,
+      })
+      .collect();
+    table_names.sort();
+
+    let mut continuation_token = None;
+    if let Some(max_results) = req.max_results {
+      if table_names.len() > max_results {
+        table_names.truncate(max_results);
+        continuation_token = table_names.last().cloned();
+      }
+    }
+
+    let mut tables = Vec::with_capacity(table_names.len());
+    for table_name in table_names {
+      let stats = if req.include_stats {
+        Self::table_stats(server, &table_name).await?
+      } else {
+        None
+      };
+      tables.push(TableInfoSerde {
+        table_name,
+        stats,
+      });
+    }
     Ok(ListTablesResponseSerde {
-      tables: pb_resp.tables.iter()
-        .map(|t| TableInfoSerde {
-          table_name: t.table_name.to_string()
-        })
-        .collect()
+      tables,
+      continuation_token,
     })
   }
 }
 
+impl ListTablesRestOp {
+  // None if the table was dropped since it was listed
+  async fn table_stats(server: &Server, table_name: &str) -> ServerResult<Option<TableStatsSerde>> {
+    let table_lock = server.table_metadata_cache.get_lock(&table_name.to_string()).await?;
+    let n_columns = match &*table_lock.read().await {
+      Some(table_meta) => table_meta.schema().columns.len(),
+      None => return Ok(None),
+    };
+    let segments = match (ListSegmentsRestOp {
+      req: ListSegmentsRequestSerde {
+        table_name: table_name.to_string(),
+        partition_filter: Vec::new(),
+      },
+    }.execute(server).await) {
+      Ok(resp) => resp.segments,
+      Err(e) if matches!(e.kind, ServerErrorKind::DoesNotExist) => return Ok(None),
+      Err(e) => return Err(e),
+    };
+
+    let mut stats = TableStatsSerde {
+      n_columns,
+      n_segments: segments.len(),
+      row_count: 0,
+      uncompressed_bytes: 0,
+      disk_bytes: server.table_disk_bytes(table_name).await,
+    };
+    for segment_stats in segments.iter().flat_map(|segment| &segment.stats) {
+      stats.row_count += segment_stats.row_count;
+      stats.uncompressed_bytes += segment_stats.uncompressed_bytes;
+    }
+    Ok(Some(stats))
+  }
+}
+
 impl RestRoute for ListTablesRestOp {
-  type Req = EmptySerde;
+  type Req = ListTablesRequestSerde;
 
   const ROUTE_NAME: &'static str = "list_tables";
 
-  fn new_op(_req: Self::Req) -> ListTablesRestOp {
-    ListTablesRestOp
+  fn new_op(req: Self::Req) -> ListTablesRestOp {
+    ListTablesRestOp { req }
   }
 }
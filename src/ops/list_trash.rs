@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use chrono::{Duration, SecondsFormat};
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, ListTrashResponseSerde, TrashedTableSerde};
+
+pub struct ListTrashOp;
+
+#[async_trait]
+impl ServerOp for ListTrashOp {
+  type Locks = TrivialLocks;
+  type Response = ListTrashResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let retention = Duration::seconds(server.runtime_config().await.trash_retention_seconds);
+    let tables = server.list_trash().await?
+      .into_iter()
+      .map(|trashed| TrashedTableSerde {
+        table_name: trashed.table_name,
+        dropped_at: trashed.dropped_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        purge_at: (trashed.dropped_at + retention).to_rfc3339_opts(SecondsFormat::Millis, true),
+      })
+      .collect();
+    Ok(ListTrashResponseSerde { tables })
+  }
+}
+
+impl RestRoute for ListTrashOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "list_trash";
+
+  fn new_op(_req: Self::Req) -> ListTrashOp {
+    ListTrashOp
+  }
+}
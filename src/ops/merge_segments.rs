@@ -0,0 +1,227 @@
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use tokio::sync::OwnedRwLockWriteGuard;
+use uuid::Uuid;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::locks::table::GlobalTableReadLocks;
+use crate::metadata::PersistentMetadata;
+use crate::metadata::segment::SegmentMetadata;
+use crate::ops::traits::ServerOp;
+use crate::opt::RuntimeConfig;
+use crate::server::Server;
+use crate::server::cancel;
+use crate::server::slow_ops;
+use crate::types::{PartitionKey, SegmentKey, ShardId};
+use crate::utils::{common, dirs, navigation, vfs};
+
+type SegmentGuard = OwnedRwLockWriteGuard<Option<SegmentMetadata>>;
+
+pub struct MergeSegmentsOp {
+  pub key: PartitionKey,
+  // merge regardless of how recently the segments were flushed
+  pub ignore_grace: bool,
+}
+
+#[derive(Default)]
+pub struct MergedSegments {
+  pub segment_id: Option<Uuid>,
+  pub merged_segment_ids: Vec<Uuid>,
+}
+
+impl MergeSegmentsOp {
+  fn is_mergeable(&self, runtime_config: &RuntimeConfig, segment_meta: &SegmentMetadata) -> ServerResult<bool> {
+    let grace = Duration::seconds(runtime_config.merge_small_segment_seconds);
+    Ok(
+      segment_meta.live_n()? < runtime_config.min_rows_for_compaction as u64 &&
+        segment_meta.staged_n == 0 &&
+        !segment_meta.flushing &&
+        segment_meta.write_versions.len() == 1 &&
+        segment_meta.replaced_segment_ids.is_empty() &&
+        (self.ignore_grace || Utc::now() - segment_meta.last_flush_at > grace)
+    )
+  }
+
+  async fn is_pinned(server: &Server, segment_key: &SegmentKey) -> bool {
+    !server.correlation_metadata_cache.pinned_versions(segment_key).await.is_empty()
+  }
+
+  // Picks small segments to merge in segment id order, stopping before the
+  // merged segment would exceed the target segment size.
+  async fn select_candidates(
+    &self,
+    server: &Server,
+    runtime_config: &RuntimeConfig,
+  ) -> ServerResult<Vec<Uuid>> {
+    let mut segment_ids = Vec::new();
+    let segment_id_stream = navigation::stream_segment_ids_for_partition(
+      &server.opts.dir,
+      self.key.clone(),
+    );
+    futures::pin_mut!(segment_id_stream);
+    while let Some(segment_id_result) = segment_id_stream.next().await {
+      segment_ids.push(segment_id_result?);
+    }
+    segment_ids.sort();
+
+    let mut res = Vec::new();
+    let mut total_live_n = 0;
+    for segment_id in segment_ids {
+      let segment_key = self.key.segment_key(segment_id);
+      let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+      let segment_guard = slow_ops::wait_for_lock("segment", segment_lock.read()).await?;
+      let segment_meta = match &*segment_guard {
+        Some(segment_meta) => segment_meta,
+        None => continue,
+      };
+      // a segment with inconsistent row counts is left for check_table
+      // instead of being merged away
+      let is_mergeable = match self.is_mergeable(runtime_config, segment_meta) {
+        Ok(is_mergeable) => is_mergeable,
+        Err(e) => {
+          log::error!("not merging segment {}: {}", segment_key, e);
+          false
+        },
+      };
+      if !is_mergeable || Self::is_pinned(server, &segment_key).await {
+        continue;
+      }
+      let live_n = segment_meta.live_n()?;
+      if total_live_n + live_n > runtime_config.target_rows_per_segment as u64 {
+        break;
+      }
+      total_live_n += live_n;
+      res.push(segment_id);
+    }
+    Ok(res)
+  }
+}
+
+#[async_trait]
+impl ServerOp for MergeSegmentsOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = MergedSegments;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.key.table_name.clone())
+  }
+
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  // 1. pick small segments without staged rows and obtain partition, then
+  //    deletion, then segment write locks (in the same order as writes and
+  //    deletions do), checking again that each is still mergeable
+  // 2. read each segment's live rows, renumbering row ids from 0
+  // 3. build the new segment with the rows staged (so the flush loop will
+  //    flush them) in a garbage directory, then move it into place with the
+  //    source segments recorded in its metadata
+  // 4. remove the sources from the partition's active segments and remove
+  //    their directories, then clear the record of them
+  // A crash before the move in 3 leaves a directory recovery will remove,
+  // and a crash after leaves the record recovery uses to finish 4.
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<MergedSegments> {
+    server.check_writable()?;
+    let dir = &server.opts.dir;
+    let runtime_config = server.table_runtime_config(&locks.table_meta).await;
+
+    let partition_lock = server.partition_metadata_cache.get_lock(&self.key)
+      .await?;
+    let mut partition_guard = slow_ops::wait_for_lock("partition", partition_lock.write()).await?;
+    let partition_meta = match &mut *partition_guard {
+      Some(partition_meta) => partition_meta,
+      None => return Err(ServerError::does_not_exist("partition", &self.key)),
+    };
+
+    let candidate_ids = self.select_candidates(server, &runtime_config).await?;
+    if candidate_ids.len() < 2 {
+      return Ok(MergedSegments::default());
+    }
+
+    let mut _deletion_guards = Vec::with_capacity(candidate_ids.len());
+    for &segment_id in &candidate_ids {
+      let deletion_lock = server.deletion_metadata_cache.get_lock(&self.key.segment_key(segment_id))
+        .await?;
+      _deletion_guards.push(slow_ops::wait_for_lock("deletion", deletion_lock.write_owned()).await?);
+    }
+    let mut sources: Vec<(SegmentKey, SegmentGuard)> = Vec::with_capacity(candidate_ids.len());
+    for &segment_id in &candidate_ids {
+      let segment_key = self.key.segment_key(segment_id);
+      let segment_lock = server.segment_metadata_cache.get_lock(&segment_key)
+        .await?;
+      let segment_guard = slow_ops::wait_for_lock("segment", segment_lock.write_owned()).await?;
+      let still_mergeable = match &*segment_guard {
+        Some(segment_meta) => self.is_mergeable(&runtime_config, segment_meta)? &&
+          !Self::is_pinned(server, &segment_key).await,
+        None => false,
+      };
+      if still_mergeable {
+        sources.push((segment_key, segment_guard));
+      }
+    }
+    if sources.len() < 2 {
+      return Ok(MergedSegments::default());
+    }
+
+    let columns = common::augmented_columns(&locks.table_meta.schema());
+    let mut rows = Vec::new();
+    for (segment_key, segment_guard) in &sources {
+      let segment_meta = segment_guard.as_ref().unwrap();
+      rows.extend(server.read_live_rows(segment_key, segment_meta, &columns).await?);
+      cancel::check()?;
+    }
+
+    let shard_id = ShardId::randomly_select(
+      locks.global_meta.n_shards_log,
+      1, // TODO use table_meta
+      &self.key,
+      partition_meta.sharding_denominator_log,
+    );
+    let segment_key = self.key.segment_key(shard_id.generate_segment_id());
+    let merged_keys: Vec<_> = sources.iter()
+      .map(|(source_key, _)| source_key.clone())
+      .collect();
+    let merged_segment_ids: Vec<_> = merged_keys.iter()
+      .map(|source_key| source_key.segment_id)
+      .collect();
+    log::info!(
+      "merging {} segments with {} live rows in {} into {}",
+      sources.len(),
+      rows.len(),
+      self.key,
+      segment_key.segment_id,
+    );
+    let mut segment_meta = server.build_rewritten_segment(
+      &segment_key,
+      &locks.table_meta.schema(),
+      &mut rows,
+    ).await?;
+    segment_meta.replaced_segment_ids = merged_segment_ids.clone();
+    let building_dir = dirs::garbage_segment_dir(dir, &segment_key);
+    common::overwrite_file(
+      building_dir.join("segment_metadata.json"),
+      serde_json::to_string(&segment_meta)?.as_bytes(),
+    ).await?;
+    vfs::rename(&building_dir, dirs::segment_dir(dir, &segment_key)).await?;
+    server.add_to_partition_index(&self.key, &[segment_key.segment_id]).await?;
+
+    server.remove_replaced_segments(partition_meta, &merged_keys).await?;
+    for (_, segment_guard) in &mut sources {
+      **segment_guard = None;
+    }
+    segment_meta.replaced_segment_ids = Vec::new();
+    segment_meta.overwrite(dir, &segment_key).await?;
+    let new_segment_lock = server.segment_metadata_cache.get_lock(&segment_key)
+      .await?;
+    *new_segment_lock.write().await = Some(segment_meta);
+    server.add_flush_candidate(segment_key.clone()).await;
+
+    Ok(MergedSegments {
+      segment_id: Some(segment_key.segment_id),
+      merged_segment_ids,
+    })
+  }
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::merge_segments::MergeSegmentsOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::opt::RuntimeConfig;
+use crate::serde_models::{MergeSegmentsRequestSerde, MergeSegmentsResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, PartitionKey};
+
+// Merges a partition's small segments immediately, without waiting for
+// them to go unflushed as long as the compaction loop would.
+pub struct MergeSegmentsRestOp {
+  pub req: MergeSegmentsRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for MergeSegmentsRestOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = MergeSegmentsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let partition = write_to_partition_rest::pb_partition(
+      &self.req.partition,
+      &locks.table_meta.schema().partitioning,
+    )?;
+    let key = PartitionKey {
+      table_name: self.req.table_name.clone(),
+      partition: NormalizedPartition::from_raw_fields(&partition)?,
+    };
+    key.partition.check_against_schema(&locks.table_meta.schema())?;
+    let merged = MergeSegmentsOp {
+      key,
+      ignore_grace: true,
+    }.execute_with_locks(server, locks).await?;
+
+    Ok(MergeSegmentsResponseSerde {
+      segment_id: merged.segment_id.map(|id| id.to_string()),
+      merged_segment_ids: merged.merged_segment_ids.iter()
+        .map(|id| id.to_string())
+        .collect(),
+    })
+  }
+}
+
+impl RestRoute for MergeSegmentsRestOp {
+  type Req = MergeSegmentsRequestSerde;
+
+  const ROUTE_NAME: &'static str = "merge_segments";
+
+  fn new_op(req: Self::Req) -> MergeSegmentsRestOp {
+    MergeSegmentsRestOp { req }
+  }
+}
@@ -2,17 +2,55 @@ pub mod traits;
 pub mod alter_table;
 pub mod create_table;
 pub mod drop_table;
+pub mod undrop_table;
+pub mod list_trash;
+pub mod rename_table;
+pub mod copy_table;
+pub mod freeze_table;
 pub mod get_schema;
 pub mod list_segments;
 pub mod write_to_partition;
 pub mod flush;
 pub mod read_segment_column;
+pub mod read_segment_column_rest;
+pub mod read_live_rows;
 pub mod compact;
 pub mod list_tables;
 pub mod delete_from_segment;
 pub mod read_segment_deletions;
+pub mod garbage_collect;
+pub mod check_table;
+pub mod cache_stats;
+pub mod reload_config;
+pub mod held_locks;
+pub mod background_status;
+pub mod staged_segments;
+pub mod recent_errors;
+pub mod compact_segment;
+pub mod compact_table;
+pub mod begin_read;
+pub mod begin_snapshot;
+pub mod end_read;
+pub mod begin_tx;
+pub mod commit_tx;
+pub mod abort_tx;
+pub mod read_changes;
+pub mod get_column_sketch;
+pub mod count_rows;
+pub mod set_bloom_filter_columns;
+pub mod set_column_masks;
+pub mod check_segment_contains;
+pub mod get_rows_by_id;
+pub mod merge_segments;
+pub mod split_segment;
+pub mod disk_usage;
+pub mod audit_log;
 
+pub mod alter_table_rest;
 pub mod create_table_rest;
 pub mod drop_table_rest;
+pub mod get_schema_rest;
 pub mod list_tables_rest;
+pub mod list_segments_rest;
 pub mod write_to_partition_rest;
+pub mod merge_segments_rest;
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use pancake_db_idl::dml::{FieldValue, ListSegmentsRequest};
+use pancake_db_idl::dml::field_value::Value;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::metadata::table::TableMetadata;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::list_segments_rest::partition_field_to_json;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest::field_value_to_json;
+use crate::serde_models::{ChangeEventSerde, ReadChangesRequestSerde, ReadChangesResponseSerde};
+use crate::server::authz::{Access, Verb};
+use crate::server::cancel;
+use crate::types::{NormalizedPartition, PartitionKey, SegmentKey};
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::masking::MaskingRule;
+
+const DEFAULT_MAX_ROWS: usize = 4096;
+const DELETION_LOG_ENTRY_SIZE: u64 = 4;
+
+// How far a reader has consumed each segment's changes, keyed by segment id.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+struct SegmentCursor {
+  next_row_id: u32,
+  deletion_log_offset: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ChangeCursor {
+  segments: HashMap<String, SegmentCursor>,
+}
+
+impl ChangeCursor {
+  fn decode(s: &str) -> ServerResult<Self> {
+    let invalid = || ServerError::invalid("change cursor is malformed");
+    let bytes = base64::decode(s).map_err(|_| invalid())?;
+    serde_json::from_slice(&bytes).map_err(|_| invalid())
+  }
+
+  fn encode(&self) -> ServerResult<String> {
+    Ok(base64::encode(serde_json::to_vec(self)?))
+  }
+}
+
+// Returns the changes committed to a table since a cursor: batches of rows
+// once they are flushed, followed by the row ids later deleted from them.
+// Within each segment, events are in write order, and a deletion is only
+// returned after the row it deletes. Clients mirror a table by polling with
+// the cursor from each response.
+pub struct ReadChangesOp {
+  pub req: ReadChangesRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for ReadChangesOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = ReadChangesResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let mut cursor = match &req.cursor {
+      Some(s) => ChangeCursor::decode(s)?,
+      None => ChangeCursor::default(),
+    };
+    let mut rows_remaining = req.max_rows.unwrap_or(DEFAULT_MAX_ROWS);
+    if rows_remaining == 0 {
+      return Err(ServerError::invalid("max rows must be positive"));
+    }
+
+    let table_meta = locks.table_meta.clone();
+    let masks = server.read_masks(&req.table_name, &table_meta).await;
+    let list_req = ListSegmentsRequest {
+      table_name: req.table_name.clone(),
+      ..Default::default()
+    };
+    let mut segments = ListSegmentsOp { req: list_req, partition_conditions: Vec::new() }.execute_with_locks(server, locks)
+      .await?
+      .segments;
+    segments.sort_by(|a, b| a.segment_id.cmp(&b.segment_id));
+
+    let mut events = Vec::new();
+    for segment in &segments {
+      if rows_remaining == 0 {
+        break;
+      }
+      cancel::check()?;
+      let partition_key = PartitionKey {
+        table_name: req.table_name.clone(),
+        partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+      };
+      let segment_key = partition_key.segment_key(Uuid::from_str(&segment.segment_id)?);
+      let segment_cursor = cursor.segments.entry(segment.segment_id.clone())
+        .or_default();
+      let json_partition = segment.partition.iter()
+        .map(|(name, value)| Ok((name.to_string(), partition_field_to_json(value)?)))
+        .collect::<ServerResult<HashMap<_, _>>>()?;
+
+      let rows = Self::read_new_rows(
+        server,
+        &segment_key,
+        &table_meta,
+        &masks,
+        segment_cursor,
+        rows_remaining,
+      ).await?;
+      if !rows.is_empty() {
+        rows_remaining -= rows.len();
+        events.push(ChangeEventSerde::Rows {
+          partition: json_partition.clone(),
+          segment_id: segment.segment_id.clone(),
+          rows,
+        });
+      }
+
+      let row_ids = Self::read_new_deletions(server, &segment_key, segment_cursor).await?;
+      if !row_ids.is_empty() {
+        events.push(ChangeEventSerde::Deletions {
+          partition: json_partition,
+          segment_id: segment.segment_id.clone(),
+          row_ids,
+        });
+      }
+    }
+
+    Ok(ReadChangesResponseSerde {
+      events,
+      cursor: cursor.encode()?,
+    })
+  }
+}
+
+impl ReadChangesOp {
+  // Reads flushed rows with row ids at or after the cursor. Staged rows are
+  // left for a later call, once they are flushed.
+  async fn read_new_rows(
+    server: &Server,
+    segment_key: &SegmentKey,
+    table_meta: &TableMetadata,
+    masks: &HashMap<String, MaskingRule>,
+    segment_cursor: &mut SegmentCursor,
+    max_rows: usize,
+  ) -> ServerResult<Vec<HashMap<String, serde_json::Value>>> {
+    let columns = common::augmented_columns(&table_meta.schema());
+    // holding the read lock keeps flushes and compactions from changing the
+    // files underneath us
+    let segment_lock = server.segment_metadata_cache.get_lock(segment_key).await?;
+    let segment_guard = segment_lock.read().await;
+    let segment_meta = match &*segment_guard {
+      Some(segment_meta) => segment_meta,
+      None => return Ok(Vec::new()),
+    };
+    let flushed_n = segment_meta.flushed_n()?;
+    if flushed_n <= segment_cursor.next_row_id as u64 {
+      return Ok(Vec::new());
+    }
+
+    let read_version = segment_meta.read_version;
+    let compaction = server.compaction_cache
+      .get_lock(&segment_key.compaction_key(read_version))
+      .await?
+      .read()
+      .await
+      .clone()
+      .unwrap_or_default();
+
+    let row_ids = server.read_col(
+      segment_key,
+      ROW_ID_COLUMN_NAME,
+      &columns[ROW_ID_COLUMN_NAME],
+      read_version,
+      &compaction,
+      usize::MAX,
+    ).await?
+      .iter()
+      .map(Self::row_id_of)
+      .collect::<ServerResult<Vec<_>>>()?;
+    // sorted compactions store rows out of row id order, so we take the
+    // lowest new row ids wherever they are
+    let mut new_positions: Vec<usize> = (0..row_ids.len())
+      .filter(|&position| row_ids[position] >= segment_cursor.next_row_id)
+      .collect();
+    if new_positions.is_empty() {
+      return Ok(Vec::new());
+    }
+    new_positions.sort_by_key(|&position| row_ids[position]);
+    new_positions.truncate(max_rows);
+    // unsorted, the new rows are usually all flushed, after the rows we've
+    // already read
+    let start = *new_positions.iter().min().unwrap();
+    let end = new_positions.iter().max().unwrap() + 1;
+
+    let mut rows = vec![HashMap::new(); new_positions.len()];
+    for (col_name, col_meta) in &columns {
+      let values = if segment_meta.explicit_columns.contains(col_name) {
+        server.read_col_range(
+          segment_key,
+          col_name,
+          col_meta,
+          read_version,
+          &compaction,
+          start,
+          end - start,
+        ).await?
+      } else {
+        Vec::new()
+      };
+      for (&position, row) in new_positions.iter().zip(rows.iter_mut()) {
+        let value = values.get(position - start)
+          .map(|value| match masks.get(col_name) {
+            Some(rule) => field_value_to_json(&rule.mask(value)),
+            None => field_value_to_json(value),
+          })
+          .transpose()?
+          .unwrap_or(serde_json::Value::Null);
+        row.insert(table_meta.visible_column_name(col_name), value);
+      }
+    }
+
+    segment_cursor.next_row_id = row_ids[*new_positions.last().unwrap()] + 1;
+    Ok(rows)
+  }
+
+  async fn read_new_deletions(
+    server: &Server,
+    segment_key: &SegmentKey,
+    segment_cursor: &mut SegmentCursor,
+  ) -> ServerResult<Vec<u32>> {
+    let path = dirs::deletion_log_path(&server.opts.dir, segment_key);
+    let len = common::file_len_or_zero(&path).await?;
+    if len <= segment_cursor.deletion_log_offset {
+      return Ok(Vec::new());
+    }
+    let bytes = common::read_with_offset(
+      &path,
+      segment_cursor.deletion_log_offset,
+      (len - segment_cursor.deletion_log_offset) as usize,
+    ).await?;
+
+    let mut row_ids = Vec::new();
+    for chunk in bytes.chunks_exact(DELETION_LOG_ENTRY_SIZE as usize) {
+      let row_id = u32::from_le_bytes(chunk.try_into().unwrap());
+      // the row itself hasn't been returned yet
+      if row_id >= segment_cursor.next_row_id {
+        break;
+      }
+      row_ids.push(row_id);
+      segment_cursor.deletion_log_offset += DELETION_LOG_ENTRY_SIZE;
+    }
+    Ok(row_ids)
+  }
+
+  fn row_id_of(value: &FieldValue) -> ServerResult<u32> {
+    match &value.value {
+      Some(Value::Int64Val(row_id)) => Ok(*row_id as u32),
+      _ => Err(ServerError::corrupt("row id column contains a non-integer")),
+    }
+  }
+}
+
+impl RestRoute for ReadChangesOp {
+  type Req = ReadChangesRequestSerde;
+
+  const ROUTE_NAME: &'static str = "read_changes";
+
+  fn new_op(req: Self::Req) -> ReadChangesOp {
+    ReadChangesOp { req }
+  }
+}
@@ -0,0 +1,239 @@
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::Duration;
+use pancake_db_core::encoding;
+use pancake_db_idl::dml::{FieldValue, ReadSegmentColumnRequest, Row};
+use pancake_db_idl::schema::ColumnMeta;
+use twox_hash::XxHash64;
+use uuid::Uuid;
+
+use crate::constants::WRITTEN_AT_COLUMN_NAME;
+use crate::errors::{ServerError, ServerResult};
+use crate::locks::deletion::DeletionReadLocks;
+use crate::metadata::compaction::Compaction;
+use crate::metadata::segment::SegmentMetadata;
+use crate::ops::traits::ServerOp;
+use crate::server::Server;
+use crate::server::authz::{Access, Verb};
+use crate::types::{NormalizedPartition, SegmentKey};
+use crate::utils::common;
+use crate::utils::computed;
+use crate::utils::dirs;
+use crate::utils::zone_map::ZoneMapPredicate;
+
+pub struct LiveRowsResponse {
+  // encoded but uncompressed
+  pub data: Vec<u8>,
+  pub row_count: u32,
+}
+
+// Reads the live rows of a segment column, optionally only those whose
+// _written_at falls in a range, a sample of them, or the first few, so
+// that incremental consumers only fetch rows written since their last
+// checkpoint and exploratory reads don't fetch a whole segment. Unlike
+// zone map predicates, the range is evaluated on every row, including
+// compacted and staged ones, and deleted rows are left out, so the
+// matching values come back re-encoded in the order they are stored.
+// Reads of different columns under the same correlation id return the
+// same rows: each row's place in the sample is decided by hashing its
+// position with the correlation id, and the limit is applied last, to the
+// newest rows in reverse.
+pub struct ReadLiveRowsOp {
+  pub req: ReadSegmentColumnRequest,
+  pub range: Option<ZoneMapPredicate>,
+  pub limit: Option<u32>,
+  // the fraction of rows to return, in (0, 1]
+  pub sample_rate: Option<f64>,
+  pub reverse: bool,
+}
+
+#[async_trait]
+impl ServerOp for ReadLiveRowsOp {
+  type Locks = DeletionReadLocks;
+  type Response = LiveRowsResponse;
+
+  fn get_key(&self) -> ServerResult<SegmentKey> {
+    let partition = NormalizedPartition::from_raw_fields(&self.req.partition)?;
+    Ok(SegmentKey {
+      table_name: self.req.table_name.clone(),
+      partition,
+      segment_id: Uuid::from_str(&self.req.segment_id)?,
+    })
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: DeletionReadLocks) -> ServerResult<LiveRowsResponse> {
+    let req = &self.req;
+    common::validate_entity_name_for_read("table name", &req.table_name)?;
+    common::validate_segment_id(&req.segment_id)?;
+    common::validate_entity_name_for_read("column name", &req.column_name)?;
+
+    let DeletionReadLocks {
+      table_meta,
+      maybe_deletion_meta: _,
+      segment_meta,
+      segment_key,
+    } = locks;
+    let columns = common::augmented_columns(&table_meta.schema());
+    let col_name = table_meta.stored_column_name(&req.column_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", &req.column_name))?;
+    let col_meta = columns.get(&col_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", &req.column_name))?;
+
+    let runtime_config = server.runtime_config().await;
+    let (pin, _) = server.correlation_metadata_cache.get_correlated_pin(
+      &req.correlation_id,
+      &segment_key,
+      segment_meta.read_version,
+      Duration::seconds(runtime_config.correlation_ttl_seconds),
+    ).await?;
+    let compaction_key = segment_key.compaction_key(pin.version);
+    let compaction = server.compaction_cache
+      .get_lock(&compaction_key)
+      .await?
+      .read()
+      .await
+      .clone()
+      .unwrap_or_default();
+    let deletions = server.read_combined_deletions(
+      &compaction_key,
+      pin.deletion_id.unwrap_or(segment_meta.deletion_id),
+    ).await?;
+    let staged_bytes = common::read_or_empty(dirs::staged_rows_path(&server.opts.dir, &segment_key)).await?;
+    let mut staged_rows = common::staged_bytes_to_rows(&staged_bytes)?;
+    computed::fill_rows(&table_meta.computed_columns, &mut staged_rows);
+
+    // Without a range or sample, the first rows are the ones returned, so
+    // the limit bounds how many we need to read, deleted ones included.
+    let read_limit = match (&self.range, self.sample_rate, self.limit) {
+      (None, None, Some(limit)) if !self.reverse => {
+        let n_deleted = deletions.iter().filter(|is_deleted| **is_deleted).count();
+        (limit as usize).saturating_add(n_deleted)
+      },
+      _ => usize::MAX,
+    };
+    let values = Self::read_values(
+      server,
+      &segment_key,
+      &segment_meta,
+      &compaction,
+      pin.version,
+      &col_name,
+      col_meta,
+      &staged_rows,
+      read_limit,
+    ).await?;
+    let written_ats = match &self.range {
+      Some(_) => {
+        let written_ats = Self::read_values(
+          server,
+          &segment_key,
+          &segment_meta,
+          &compaction,
+          pin.version,
+          WRITTEN_AT_COLUMN_NAME,
+          &columns[WRITTEN_AT_COLUMN_NAME],
+          &staged_rows,
+          read_limit,
+        ).await?;
+        if values.len() != written_ats.len() {
+          return Err(ServerError::corrupt(format!(
+            "segment {} has {} rows in column {} but {} in {}",
+            segment_key,
+            values.len(),
+            req.column_name,
+            written_ats.len(),
+            WRITTEN_AT_COLUMN_NAME,
+          )));
+        }
+        written_ats
+      },
+      None => Vec::new(),
+    };
+
+    let maybe_mask = server.read_masks(&req.table_name, &table_meta).await
+      .remove(&col_name);
+    let limit = self.limit.map(|limit| limit as usize).unwrap_or(usize::MAX);
+    let mut matching_values = values.into_iter()
+      .enumerate()
+      .filter(|(position, _)| {
+        let is_deleted = deletions.get(*position).cloned().unwrap_or(false);
+        let is_in_range = match &self.range {
+          Some(range) => range.matches(&written_ats[*position]),
+          None => true,
+        };
+        let is_sampled = match self.sample_rate {
+          Some(sample_rate) => is_sampled(&req.correlation_id, *position, sample_rate),
+          None => true,
+        };
+        !is_deleted && is_in_range && is_sampled
+      })
+      .map(|(_, value)| match &maybe_mask {
+        Some(rule) => rule.mask(&value),
+        None => value,
+      })
+      .collect::<Vec<FieldValue>>();
+    if self.reverse {
+      matching_values.reverse();
+    }
+    matching_values.truncate(limit);
+    let encoder = encoding::new_encoder(
+      common::unwrap_dtype(col_meta.dtype)?,
+      col_meta.nested_list_depth as u8,
+    );
+    Ok(LiveRowsResponse {
+      data: encoder.encode(&matching_values)?,
+      row_count: matching_values.len() as u32,
+    })
+  }
+}
+
+// whether the row at this position is in the correlation's sample
+fn is_sampled(correlation_id: &str, position: usize, sample_rate: f64) -> bool {
+  let mut hasher = XxHash64::with_seed(0);
+  hasher.write(correlation_id.as_bytes());
+  hasher.write_u64(position as u64);
+  (hasher.finish() as f64) < sample_rate * (u64::MAX as f64)
+}
+
+impl ReadLiveRowsOp {
+  // the first limit values of the column in row order, including staged
+  // rows and implicit nulls
+  #[allow(clippy::too_many_arguments)]
+  async fn read_values(
+    server: &Server,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+    compaction: &Compaction,
+    version: u64,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    staged_rows: &[Row],
+    limit: usize,
+  ) -> ServerResult<Vec<FieldValue>> {
+    let mut res = if segment_meta.explicit_columns.contains(col_name) {
+      server.read_col(
+        segment_key,
+        col_name,
+        col_meta,
+        version,
+        compaction,
+        limit,
+      ).await?
+    } else {
+      let n_flushed = segment_meta.flushed_n()? as usize;
+      vec![FieldValue::default(); n_flushed.min(limit)]
+    };
+    // staged rows come after every flushed one, so only once those are all read
+    let n_staged = limit.saturating_sub(res.len());
+    res.extend(staged_rows.iter()
+      .take(n_staged)
+      .map(|row| row.fields.get(col_name).cloned().unwrap_or_default()));
+    Ok(res)
+  }
+}
@@ -1,51 +1,127 @@
+use std::path::Path;
 use std::str::FromStr;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
-use pancake_db_core::encoding;
+use chrono::Duration;
+use pancake_db_core::{compression, encoding};
 use pancake_db_idl::dml::{FieldValue, ReadSegmentColumnRequest, ReadSegmentColumnResponse};
+use pancake_db_idl::dml::field_value::Value;
 use pancake_db_idl::dtype::DataType;
-use tokio::fs;
+use pancake_db_idl::schema::ColumnMeta;
+use prost_types::Timestamp;
 use uuid::Uuid;
 
+use crate::constants::{ROW_ID_COLUMN_NAME, WRITTEN_AT_COLUMN_NAME};
 use crate::errors::{ServerError, ServerResult};
 use crate::locks::segment::SegmentReadLocks;
+use crate::metadata::compaction::Compaction;
+use crate::metadata::segment::SegmentMetadata;
+use crate::metadata::table::TableMetadata;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
-use crate::types::{NormalizedPartition, SegmentKey};
+use crate::server::authz::{Access, Verb};
+use crate::server::system_tables::SystemTable;
+use crate::types::{CompactionKey, NormalizedPartition, SegmentKey};
+use crate::utils::checksum;
 use crate::utils::common;
+use crate::utils::computed;
 use crate::utils::dirs;
+use crate::utils::encryption::Cipher;
+use crate::utils::safe_decoding;
+use crate::utils::storage;
+use crate::utils::zone_map;
+use crate::utils::zone_map::{SkippedRows, ZoneMapBlock, ZoneMapPredicate};
 
 #[derive(Clone, Copy, Debug)]
 enum FileType {
   Flush,
   Compact,
+  // blocks compacted incrementally after the compacted base file, whose
+  // offset is the increment's index
+  Increment,
+  // only read on its own in reverse, since forward reads append staged rows
+  // to the last page of flushed data
+  Staged,
 }
 
+// Where the next page of a read starts. It never leaves the server: GRPC
+// streams every page of a read and REST reads them all before responding,
+// so clients have no token to forge or hold onto past its segment's
+// compaction, and its fields stay private to this op.
+// The pin keeps the read version the same from page to page, but the
+// table can still be altered and rows deleted in between, which would give
+// pages inconsistent row and deletion counts. So the continuation also
+// records the schema version and deletion id the read started with, and
+// later pages fail with a restart read error if either has changed.
 #[derive(Clone, Debug)]
 pub struct SegmentColumnContinuation {
   version: u64,
   file_type: FileType,
   offset: u64,
+  schema_version: u64,
+  // None when the read's snapshot pins its deletions anyway
+  deletion_id: Option<u64>,
 }
 
 impl SegmentColumnContinuation {
-  fn new(file_type: FileType, version: u64) -> Self {
+  fn new(file_type: FileType, version: u64, schema_version: u64, deletion_id: Option<u64>) -> Self {
     SegmentColumnContinuation {
       version,
       file_type,
       offset: 0,
+      schema_version,
+      deletion_id,
     }
   }
+
+  // the same read, continuing from another file or offset
+  fn at(&self, file_type: FileType, offset: u64) -> Self {
+    SegmentColumnContinuation {
+      file_type,
+      offset,
+      ..self.clone()
+    }
+  }
+
+  fn validate(&self, table_meta: &TableMetadata, segment_meta: &SegmentMetadata) -> ServerResult<()> {
+    if table_meta.schema_version != self.schema_version {
+      return Err(ServerError::restart_read("the table was altered during the read"));
+    }
+    if matches!(self.deletion_id, Some(deletion_id) if deletion_id != segment_meta.deletion_id) {
+      return Err(ServerError::restart_read("rows were deleted from the segment during the read"));
+    }
+    Ok(())
+  }
 }
 
 pub struct ContinuedReadSegmentColumnResponse {
   pub resp: ReadSegmentColumnResponse,
   pub continuation: Option<SegmentColumnContinuation>,
+  // flushed rows left out of the data because of the predicate
+  pub skipped_rows: Vec<SkippedRows>,
 }
 
+// With a predicate, flushed blocks whose zone maps show they have no
+// matching rows are left out of the data. Compacted and staged rows are
+// always returned in full, so clients must still apply the predicate.
+// Masked columns come back masked in a single page of uncompressed data,
+// and predicates on them don't prune anything.
+// In reverse, for reading the latest rows without reading the oldest ones
+// first, pages go from the newest rows to the oldest: staged rows, then
+// flushed data from its last block back, then compacted data. Each page
+// of uncompressed data decodes on its own to rows newest first, but
+// compacted data can only be decompressed whole, so its pages still go in
+// stored order, and its rows decode oldest first.
+// Clients decompress all compacted data as one block, so the server
+// decompresses each incremental compaction block itself and serves it
+// like flushed data, between the compacted base and the flushed data (or
+// before the base, newest first, in reverse).
 pub struct ReadSegmentColumnOp {
   pub req: ReadSegmentColumnRequest,
   pub continuation: Option<SegmentColumnContinuation>,
+  pub predicate: Option<ZoneMapPredicate>,
+  pub reverse: bool,
 }
 
 #[async_trait]
@@ -62,6 +138,14 @@ impl ServerOp for ReadSegmentColumnOp {
     })
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: SegmentReadLocks) -> ServerResult<Self::Response> {
     let req = &self.req;
     common::validate_entity_name_for_read("table name", &req.table_name)?;
@@ -70,40 +154,68 @@ impl ServerOp for ReadSegmentColumnOp {
     if self.req.correlation_id.is_empty() && self.continuation.is_none() {
       return Err(ServerError::invalid("must provide either a correlation id or a continuation token"))
     }
+    if self.reverse && self.predicate.is_some() {
+      return Err(ServerError::invalid("a predicate can't be combined with a reverse read"))
+    }
 
     let SegmentReadLocks {
       table_meta,
       segment_meta,
       segment_key,
     } = locks;
-    let col_name = req.column_name.clone();
+    let col_name = table_meta.stored_column_name(&req.column_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", &req.column_name))?;
 
     let augmented_columns = common::augmented_columns(&table_meta.schema());
     let maybe_col_meta = augmented_columns
       .get(&col_name);
     if maybe_col_meta.is_none() {
-      return Err(ServerError::does_not_exist("column", &col_name));
+      return Err(ServerError::does_not_exist("column", &req.column_name));
     }
     let col_meta = maybe_col_meta.unwrap();
 
+    if let Some(system_table) = SystemTable::from_name(&req.table_name) {
+      return self.read_system_table_column(server, system_table, &col_name, col_meta).await;
+    }
+
+    let runtime_config = server.table_runtime_config(&table_meta).await;
     let is_explicit_column = segment_meta.explicit_columns.contains(&col_name);
     let continuation = if self.continuation.is_none() {
-      let version = server.correlation_metadata_cache.get_correlated_read_version(
+      let (pin, _) = server.correlation_metadata_cache.get_correlated_pin(
         &req.correlation_id,
         &segment_key,
-        segment_meta.read_version
+        segment_meta.read_version,
+        Duration::seconds(runtime_config.correlation_ttl_seconds),
       ).await?;
+      let version = pin.version;
+      let schema_version = table_meta.schema_version;
+      let deletion_id = match pin.deletion_id {
+        Some(_) => None,
+        None => Some(segment_meta.deletion_id),
+      };
 
       // If the segment explicitly contains this column and version > 0,
       // it probably has compacted data.
       // Otherwise it definitely doesn't, and we can skip to flushed+staged data.
-      if is_explicit_column && version > 0 {
-        SegmentColumnContinuation::new(FileType::Compact, version)
+      let file_type = if self.reverse {
+        FileType::Staged
+      } else if is_explicit_column && version > 0 {
+        FileType::Compact
       } else {
-        SegmentColumnContinuation::new(FileType::Flush, version)
-      }
+        FileType::Flush
+      };
+      SegmentColumnContinuation::new(file_type, version, schema_version, deletion_id)
     } else {
-      self.continuation.clone().unwrap()
+      // keep the pin alive for reads that take many pages
+      if !req.correlation_id.is_empty() {
+        server.correlation_metadata_cache.refresh(
+          &req.correlation_id,
+          Duration::seconds(runtime_config.correlation_ttl_seconds),
+        ).await;
+      }
+      let continuation = self.continuation.clone().unwrap();
+      continuation.validate(&table_meta, &segment_meta)?;
+      continuation
     };
 
     let compaction_key = segment_key.compaction_key(continuation.version);
@@ -115,15 +227,20 @@ impl ServerOp for ReadSegmentColumnOp {
       .await
       .clone()
       .unwrap_or_default();
+    let maybe_cipher_arc = server.column_cipher(&compaction).await?;
+    let maybe_cipher = maybe_cipher_arc.as_deref();
 
-    let opts = &server.opts;
-    let dir = &opts.dir;
-    let row_count = (segment_meta.all_time_n - segment_meta.all_time_deleted_n) as u32;
-    let deletion_count = segment_meta.all_time_deleted_n - compaction.all_time_omitted_n;
+    let dir = &server.opts.dir;
+    let row_count = common::checked_u32_n(segment_meta.live_n()?)?;
+    let deletion_count = common::checked_u32_n(common::checked_sub_n(
+      segment_meta.all_time_deleted_n,
+      compaction.all_time_omitted_n,
+      "omitted rows",
+    )?)?;
     let implicit_nulls_count = if is_explicit_column {
       0
     } else {
-      segment_meta.all_time_n - segment_meta.staged_n as u32
+      common::checked_u32_n(segment_meta.flushed_n()?)?
     };
     let mut resp = ReadSegmentColumnResponse {
       row_count,
@@ -131,7 +248,46 @@ impl ServerOp for ReadSegmentColumnOp {
       implicit_nulls_count,
       ..Default::default()
     };
+    let masks = server.read_masks(&req.table_name, &table_meta).await;
+    if let Some(rule) = masks.get(&col_name) {
+      if self.continuation.is_some() {
+        return Err(ServerError::invalid(format!(
+          "column {} is masked, so it is read in a single page",
+          req.column_name,
+        )));
+      }
+      let mut values = server.read_col(
+        &segment_key,
+        &col_name,
+        col_meta,
+        continuation.version,
+        &compaction,
+        usize::MAX,
+      ).await?;
+      values.extend(Self::read_staged_values(dir, &segment_key, &table_meta, &col_name).await?);
+      if self.reverse {
+        values.reverse();
+      }
+      let masked_values = values.iter()
+        .map(|value| rule.mask(value))
+        .collect::<Vec<FieldValue>>();
+      let encoder = encoding::new_encoder(
+        common::unwrap_dtype(col_meta.dtype)?,
+        col_meta.nested_list_depth as u8,
+      );
+      resp.data = encoder.encode(&masked_values)?;
+      return Ok(ContinuedReadSegmentColumnResponse {
+        resp,
+        continuation: None,
+        skipped_rows: Vec::new(),
+      });
+    }
+    // zone maps would reveal the range of a masked predicate column's values
+    let predicate = self.predicate.as_ref()
+      .filter(|predicate| !masks.contains_key(&predicate.column_name));
+
     let mut new_continuation = None;
+    let mut skipped_rows = Vec::new();
     match continuation.file_type {
       FileType::Compact => {
         let compressed_filename = dirs::compact_col_file(
@@ -144,74 +300,169 @@ impl ServerOp for ReadSegmentColumnOp {
           .map(|c| c.clone() as String)
           .unwrap_or_default();
 
-        let compressed_data = common::read_with_offset(
-          compressed_filename,
+        // checksummed files end in a footer that clients must not receive
+        let file_len = storage::len_or_zero(&compressed_filename, maybe_cipher).await?;
+        let data_len = if compaction.checksummed {
+          checksum::data_len(file_len)
+        } else {
+          file_len
+        };
+        if compaction.checksummed && runtime_config.verify_checksums_on_read &&
+          continuation.offset == 0 && file_len > 0 {
+          let bytes = storage::read_or_empty(&compressed_filename, maybe_cipher).await?;
+          checksum::verify_and_strip_footer(&bytes, &compressed_filename)?;
+        }
+
+        let page_byte_size = data_len.saturating_sub(continuation.offset)
+          .min(runtime_config.read_page_byte_size as u64) as usize;
+        let compressed_data = server.read_page(
+          &compressed_filename,
           continuation.offset,
-          opts.read_page_byte_size,
+          page_byte_size,
+          maybe_cipher,
         ).await?;
 
-        if compressed_data.len() < opts.read_page_byte_size {
-          let has_flushed_data_future = common::file_exists(dirs::flush_col_file(
-            dir,
-            &compaction_key,
-            &col_name
-          ));
-          let has_staged_data_future = common::file_nonempty(dirs::staged_rows_path(
-            dir,
-            &segment_key,
-          ));
-          let (has_flushed_data, has_staged_data) = tokio::join!(has_flushed_data_future, has_staged_data_future);
-          if has_flushed_data? || has_staged_data? {
-            new_continuation = Some(SegmentColumnContinuation {
-              version: continuation.version,
-              file_type: FileType::Flush,
-              offset: 0,
-            });
-          }
+        if continuation.offset + compressed_data.len() as u64 >= data_len {
+          // in reverse, compacted data comes last
+          new_continuation = if self.reverse {
+            None
+          } else if !compaction.increment_ns.is_empty() {
+            Some(continuation.at(FileType::Increment, 0))
+          } else {
+            Self::flush_continuation(dir, &segment_key, &compaction_key, &col_name, &continuation).await?
+          };
         } else {
-          new_continuation = Some(SegmentColumnContinuation {
-            version: continuation.version,
-            file_type: FileType::Compact,
-            offset: continuation.offset + compressed_data.len() as u64,
-          });
+          let next_offset = continuation.offset + compressed_data.len() as u64;
+          server.prefetch_page(
+            compressed_filename,
+            next_offset,
+            (data_len - next_offset).min(runtime_config.read_page_byte_size as u64) as usize,
+            maybe_cipher_arc.clone(),
+          ).await;
+          new_continuation = Some(continuation.at(FileType::Compact, next_offset));
         };
 
         resp.codec = codec;
         resp.data = compressed_data;
       },
-      FileType::Flush => {
-        let uncompressed_filename = dirs::flush_col_file(
+      FileType::Increment => {
+        let increment = continuation.offset as usize;
+        let mut values = Self::read_increment_values(
+          server,
+          &compaction_key,
+          &compaction,
+          &col_name,
+          col_meta,
+          increment,
+        ).await?;
+        if self.reverse {
+          values.reverse();
+        }
+        resp.data = Self::encode(col_meta, &values)?;
+        new_continuation = if self.reverse && increment > 0 {
+          Some(continuation.at(FileType::Increment, increment as u64 - 1))
+        } else if self.reverse {
+          Some(continuation.at(FileType::Compact, 0))
+        } else if increment + 1 < compaction.increment_ns.len() {
+          Some(continuation.at(FileType::Increment, increment as u64 + 1))
+        } else {
+          Self::flush_continuation(dir, &segment_key, &compaction_key, &col_name, &continuation).await?
+        };
+      },
+      FileType::Staged => {
+        let mut staged_values = Self::read_staged_values(dir, &segment_key, &table_meta, &col_name).await?;
+        staged_values.reverse();
+        resp.data = Self::encode(col_meta, &staged_values)?;
+        let flush_len = storage::len_or_zero(
+          &dirs::flush_col_file(dir, &compaction_key, &col_name),
+          maybe_cipher,
+        ).await?;
+        new_continuation = if flush_len > 0 {
+          Some(continuation.at(FileType::Flush, flush_len))
+        } else {
+          Self::reverse_compact_continuation(is_explicit_column, &compaction, &continuation)
+        };
+      },
+      // in reverse, the offset is where the flushed data left to read ends
+      FileType::Flush if self.reverse => {
+        let (data, start) = Self::read_flush_page_reverse(
           dir,
           &compaction_key,
           &col_name,
-        );
-        resp.data = common::read_with_offset(
-          uncompressed_filename,
+          col_meta,
+          maybe_cipher,
           continuation.offset,
-          opts.read_page_byte_size,
+          runtime_config.read_page_byte_size,
         ).await?;
+        resp.data = data;
+        new_continuation = if start > 0 {
+          Some(continuation.at(FileType::Flush, start))
+        } else {
+          Self::reverse_compact_continuation(is_explicit_column, &compaction, &continuation)
+        };
+      },
+      FileType::Flush => {
+        let maybe_blocks = match predicate {
+          Some(predicate) => Self::prunable_blocks(
+            dir,
+            &compaction_key,
+            &col_name,
+            predicate,
+            maybe_cipher,
+          ).await?,
+          None => None,
+        };
+        let reached_end = match maybe_blocks {
+          Some(blocks) => {
+            let compacted_n = common::checked_u32_n(compaction.stored_n()?)?;
+            Self::read_flush_blocks(
+              dir,
+              &compaction_key,
+              &col_name,
+              &blocks,
+              compacted_n,
+              maybe_cipher,
+              &continuation,
+              runtime_config.read_page_byte_size,
+              &mut resp,
+              &mut skipped_rows,
+              &mut new_continuation,
+            ).await?
+          },
+          None => {
+            let uncompressed_filename = dirs::flush_col_file(
+              dir,
+              &compaction_key,
+              &col_name,
+            );
+            resp.data = server.read_page(
+              &uncompressed_filename,
+              continuation.offset,
+              runtime_config.read_page_byte_size,
+              maybe_cipher,
+            ).await?;
 
-        if resp.data.len() < opts.read_page_byte_size {
+            if resp.data.len() < runtime_config.read_page_byte_size {
+              true
+            } else {
+              let next_offset = continuation.offset + resp.data.len() as u64;
+              server.prefetch_page(
+                uncompressed_filename,
+                next_offset,
+                runtime_config.read_page_byte_size,
+                maybe_cipher_arc.clone(),
+              ).await;
+              new_continuation = Some(continuation.at(FileType::Flush, next_offset));
+              false
+            }
+          },
+        };
+
+        if reached_end {
           // we have reached the end of flushed data
           // encode staged data on the fly and append it
-          let staged_rows_path = dirs::staged_rows_path(dir, &segment_key);
-          let staged_bytes = fs::read(&staged_rows_path).await?;
-          let staged_rows = common::staged_bytes_to_rows(&staged_bytes)?;
-          let staged_values = staged_rows.iter()
-            .map(|row| row.fields.get(&col_name).cloned().unwrap_or_default())
-            .collect::<Vec<FieldValue>>();
-          let encoder = encoding::new_encoder(
-            DataType::from_i32(col_meta.dtype).ok_or(ServerError::internal("unknown dtype"))?,
-            col_meta.nested_list_depth as u8
-          );
-          let staged_bytes = encoder.encode(&staged_values)?;
-          resp.data.extend(staged_bytes);
-        } else {
-          new_continuation = Some(SegmentColumnContinuation {
-            version: continuation.version,
-            file_type: FileType::Flush,
-            offset: continuation.offset + resp.data.len() as u64
-          });
+          let staged_values = Self::read_staged_values(dir, &segment_key, &table_meta, &col_name).await?;
+          resp.data.extend(Self::encode(col_meta, &staged_values)?);
         }
       }
     }
@@ -219,6 +470,284 @@ impl ServerOp for ReadSegmentColumnOp {
     Ok(ContinuedReadSegmentColumnResponse {
       resp,
       continuation: new_continuation,
+      skipped_rows,
     })
   }
 }
+
+impl ReadSegmentColumnOp {
+  // System tables are materialized whole on every read, so each column
+  // comes back in a single page of uncompressed data.
+  async fn read_system_table_column(
+    &self,
+    server: &Server,
+    system_table: SystemTable,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+  ) -> ServerResult<ContinuedReadSegmentColumnResponse> {
+    let rows = server.system_table_rows(system_table).await?;
+    let written_at = FieldValue {
+      value: Some(Value::TimestampVal(Timestamp::from(SystemTime::now()))),
+    };
+    let mut values = rows.iter()
+      .enumerate()
+      .map(|(row_idx, row)| match col_name {
+        ROW_ID_COLUMN_NAME => FieldValue {
+          value: Some(Value::Int64Val(row_idx as i64)),
+        },
+        WRITTEN_AT_COLUMN_NAME => written_at.clone(),
+        _ => row.fields.get(col_name).cloned().unwrap_or_default(),
+      })
+      .collect::<Vec<_>>();
+    if self.reverse {
+      values.reverse();
+    }
+    Ok(ContinuedReadSegmentColumnResponse {
+      resp: ReadSegmentColumnResponse {
+        row_count: values.len() as u32,
+        data: Self::encode(col_meta, &values)?,
+        ..Default::default()
+      },
+      continuation: None,
+      skipped_rows: Vec::new(),
+    })
+  }
+
+  fn encode(col_meta: &ColumnMeta, values: &[FieldValue]) -> ServerResult<Vec<u8>> {
+    let encoder = encoding::new_encoder(
+      DataType::from_i32(col_meta.dtype).ok_or(ServerError::internal("unknown dtype"))?,
+      col_meta.nested_list_depth as u8
+    );
+    Ok(encoder.encode(values)?)
+  }
+
+  // in reverse, what comes after the flushed and staged data
+  fn reverse_compact_continuation(
+    is_explicit_column: bool,
+    compaction: &Compaction,
+    continuation: &SegmentColumnContinuation,
+  ) -> Option<SegmentColumnContinuation> {
+    let n_increments = compaction.increment_ns.len();
+    if !is_explicit_column || continuation.version == 0 {
+      None
+    } else if n_increments > 0 {
+      Some(continuation.at(FileType::Increment, n_increments as u64 - 1))
+    } else {
+      Some(continuation.at(FileType::Compact, 0))
+    }
+  }
+
+  // forward, what comes after the compacted data
+  async fn flush_continuation(
+    dir: &Path,
+    segment_key: &SegmentKey,
+    compaction_key: &CompactionKey,
+    col_name: &str,
+    continuation: &SegmentColumnContinuation,
+  ) -> ServerResult<Option<SegmentColumnContinuation>> {
+    let has_flushed_data_future = common::file_exists(dirs::flush_col_file(
+      dir,
+      compaction_key,
+      col_name
+    ));
+    let has_staged_data_future = common::file_nonempty(dirs::staged_rows_path(
+      dir,
+      segment_key,
+    ));
+    let (has_flushed_data, has_staged_data) = tokio::join!(has_flushed_data_future, has_staged_data_future);
+    if has_flushed_data? || has_staged_data? {
+      Ok(Some(continuation.at(FileType::Flush, 0)))
+    } else {
+      Ok(None)
+    }
+  }
+
+  // the rows of an incremental compaction block, or none if the column was
+  // rewritten in full since it was compacted
+  async fn read_increment_values(
+    server: &Server,
+    compaction_key: &CompactionKey,
+    compaction: &Compaction,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    increment: usize,
+  ) -> ServerResult<Vec<FieldValue>> {
+    let path = dirs::compact_increment_col_file(&server.opts.dir, compaction_key, col_name, increment);
+    let bytes = server.read_compact_file_bytes(&path, compaction).await?;
+    let codec = match compaction.col_codecs.get(col_name) {
+      Some(codec) if !bytes.is_empty() => codec,
+      _ => return Ok(Vec::new()),
+    };
+    let decompressor = compression::new_codec(common::unwrap_dtype(col_meta.dtype)?, codec)?;
+    Ok(safe_decoding::decompress(&*decompressor, &bytes, col_meta.nested_list_depth as u8)?)
+  }
+
+  // Reads whole flushed blocks back from end until the page is full,
+  // returning their rows newest first and the offset they start at.
+  // Without a zone map, rows can't be told apart until they're decoded, so
+  // everything before end is one page.
+  #[allow(clippy::too_many_arguments)]
+  async fn read_flush_page_reverse(
+    dir: &Path,
+    compaction_key: &CompactionKey,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    maybe_cipher: Option<&Cipher>,
+    end: u64,
+    page_byte_size: usize,
+  ) -> ServerResult<(Vec<u8>, u64)> {
+    let mut start = end;
+    match Self::load_zone_map(dir, compaction_key, col_name, maybe_cipher).await? {
+      Some(blocks) => {
+        let mut block_bounds = Vec::with_capacity(blocks.len());
+        let mut byte_offset = 0;
+        for block in &blocks {
+          block_bounds.push((byte_offset, byte_offset + block.byte_len as u64));
+          byte_offset += block.byte_len as u64;
+        }
+        for (block_start, block_end) in block_bounds.into_iter().rev() {
+          if block_end > end {
+            continue;
+          }
+          if end - start >= page_byte_size as u64 {
+            break;
+          }
+          start = block_start;
+        }
+      },
+      None => {
+        start = 0;
+      },
+    }
+
+    let bytes = storage::read_with_offset(
+      &dirs::flush_col_file(dir, compaction_key, col_name),
+      start,
+      (end - start) as usize,
+      maybe_cipher,
+    ).await?;
+    let dtype = common::unwrap_dtype(col_meta.dtype)?;
+    let mut values = safe_decoding::decode_limited(dtype, col_meta.nested_list_depth as u8, &bytes, usize::MAX)?;
+    values.reverse();
+    Ok((Self::encode(col_meta, &values)?, start))
+  }
+
+  async fn read_staged_values(
+    dir: &Path,
+    segment_key: &SegmentKey,
+    table_meta: &TableMetadata,
+    col_name: &str,
+  ) -> ServerResult<Vec<FieldValue>> {
+    // a new segment has no staged rows file until its first write appends one
+    let staged_bytes = common::read_or_empty(dirs::staged_rows_path(dir, segment_key)).await?;
+    let mut staged_rows = common::staged_bytes_to_rows(&staged_bytes)?;
+    computed::fill_rows(&table_meta.computed_columns, &mut staged_rows);
+    Ok(
+      staged_rows.iter()
+        .map(|row| row.fields.get(col_name).cloned().unwrap_or_default())
+        .collect()
+    )
+  }
+
+  // Returns the column's flushed blocks and whether each may match the
+  // predicate, or None if either zone map is missing or out of date.
+  async fn prunable_blocks(
+    dir: &Path,
+    compaction_key: &CompactionKey,
+    col_name: &str,
+    predicate: &ZoneMapPredicate,
+    maybe_cipher: Option<&Cipher>,
+  ) -> ServerResult<Option<Vec<(ZoneMapBlock, bool)>>> {
+    let col_blocks = match Self::load_zone_map(dir, compaction_key, col_name, maybe_cipher).await? {
+      Some(blocks) => blocks,
+      None => return Ok(None),
+    };
+    let predicate_blocks = if predicate.column_name == col_name {
+      col_blocks.clone()
+    } else {
+      match Self::load_zone_map(dir, compaction_key, &predicate.column_name, maybe_cipher).await? {
+        Some(blocks) => blocks,
+        None => return Ok(None),
+      }
+    };
+
+    // every column is flushed in the same blocks of rows
+    let is_aligned = col_blocks.len() == predicate_blocks.len() &&
+      col_blocks.iter().zip(&predicate_blocks)
+        .all(|(col_block, predicate_block)| col_block.n_rows == predicate_block.n_rows);
+    if !is_aligned {
+      return Ok(None);
+    }
+
+    Ok(Some(
+      col_blocks.into_iter()
+        .zip(&predicate_blocks)
+        .map(|(col_block, predicate_block)| (col_block, predicate.may_match(predicate_block)))
+        .collect()
+    ))
+  }
+
+  async fn load_zone_map(
+    dir: &Path,
+    compaction_key: &CompactionKey,
+    col_name: &str,
+    maybe_cipher: Option<&Cipher>,
+  ) -> ServerResult<Option<Vec<ZoneMapBlock>>> {
+    let flush_byte_len = storage::len_or_zero(
+      &dirs::flush_col_file(dir, compaction_key, col_name),
+      maybe_cipher,
+    ).await?;
+    let bytes = storage::read_or_empty(&dirs::zone_map_file(dir, compaction_key, col_name), maybe_cipher).await?;
+    Ok(zone_map::parse(&bytes, flush_byte_len))
+  }
+
+  // Reads a page of whole flushed blocks, skipping those that can't match.
+  // Returns whether it reached the end of the flushed data.
+  #[allow(clippy::too_many_arguments)]
+  async fn read_flush_blocks(
+    dir: &Path,
+    compaction_key: &CompactionKey,
+    col_name: &str,
+    blocks: &[(ZoneMapBlock, bool)],
+    compacted_n: u32,
+    maybe_cipher: Option<&Cipher>,
+    continuation: &SegmentColumnContinuation,
+    page_byte_size: usize,
+    resp: &mut ReadSegmentColumnResponse,
+    skipped_rows: &mut Vec<SkippedRows>,
+    new_continuation: &mut Option<SegmentColumnContinuation>,
+  ) -> ServerResult<bool> {
+    let path = dirs::flush_col_file(dir, compaction_key, col_name);
+    let mut byte_offset = 0;
+    let mut row_offset = compacted_n;
+    for (block, may_match) in blocks {
+      let block_byte_offset = byte_offset;
+      let block_row_offset = row_offset;
+      byte_offset += block.byte_len as u64;
+      row_offset += block.n_rows;
+      if block_byte_offset < continuation.offset {
+        continue;
+      }
+
+      if resp.data.len() >= page_byte_size {
+        *new_continuation = Some(continuation.at(FileType::Flush, block_byte_offset));
+        return Ok(false);
+      }
+
+      if *may_match {
+        resp.data.extend(storage::read_with_offset(
+          &path,
+          block_byte_offset,
+          block.byte_len as usize,
+          maybe_cipher,
+        ).await?);
+      } else {
+        skipped_rows.push(SkippedRows {
+          row_offset: block_row_offset,
+          n_rows: block.n_rows,
+        });
+      }
+    }
+    Ok(true)
+  }
+}
@@ -0,0 +1,252 @@
+use async_trait::async_trait;
+use pancake_db_idl::dml::ReadSegmentColumnRequest;
+use pancake_db_idl::dtype::DataType;
+use prost::Message;
+use uuid::Uuid;
+
+use crate::{Server, ServerResult};
+use crate::constants::WRITTEN_AT_COLUMN_NAME;
+use crate::errors::ServerError;
+use crate::locks::table::GlobalTableReadLocks;
+use crate::ops::read_live_rows::ReadLiveRowsOp;
+use crate::ops::read_segment_column::ReadSegmentColumnOp;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest;
+use crate::serde_models::{ReadSegmentColumnRequestSerde, ReadSegmentColumnResponseSerde, SkippedRowsSerde};
+use crate::server::authz::{Access, Verb};
+use crate::utils::common;
+use crate::utils::zone_map::ZoneMapPredicate;
+
+// ReadSegmentColumnResponseSerde as protobuf, with the same field meanings
+#[derive(Clone, PartialEq, Message)]
+pub struct ReadSegmentColumnResponsePb {
+  #[prost(uint32, tag = "1")]
+  pub row_count: u32,
+  #[prost(uint32, tag = "2")]
+  pub deletion_count: u32,
+  #[prost(uint32, tag = "3")]
+  pub implicit_nulls_count: u32,
+  #[prost(string, tag = "4")]
+  pub codec: String,
+  #[prost(bytes = "vec", tag = "5")]
+  pub compacted_data: Vec<u8>,
+  #[prost(bytes = "vec", tag = "6")]
+  pub flushed_data: Vec<u8>,
+  #[prost(message, repeated, tag = "7")]
+  pub skipped_rows: Vec<SkippedRowsPb>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SkippedRowsPb {
+  #[prost(uint32, tag = "1")]
+  pub row_offset: u32,
+  #[prost(uint32, tag = "2")]
+  pub n_rows: u32,
+}
+
+// Reads a whole segment column in one response, optionally skipping
+// flushed blocks that can't match a range predicate, or only returning the
+// live rows, optionally just those written within a range, sampled, or up
+// to a limit.
+pub struct ReadSegmentColumnRestOp {
+  pub req: ReadSegmentColumnRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for ReadSegmentColumnRestOp {
+  type Locks = GlobalTableReadLocks;
+  type Response = ReadSegmentColumnResponseSerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
+    let req = &self.req;
+    let schema = locks.table_meta.visible_schema();
+    let predicate = match &req.predicate {
+      Some(predicate) => {
+        let col_meta = schema.columns.get(&predicate.column_name)
+          .ok_or_else(|| ServerError::does_not_exist("column", &predicate.column_name))?;
+        if col_meta.nested_list_depth > 0 {
+          return Err(ServerError::invalid("predicates on nested list columns are not supported"));
+        }
+        let dtype = common::unwrap_dtype(col_meta.dtype)?;
+        let parse_bound = |bound: &Option<serde_json::Value>| {
+          bound.as_ref()
+            .map(|value| write_to_partition_rest::parse_field_value(value, dtype))
+            .transpose()
+        };
+        let stored_col_name = locks.table_meta.stored_column_name(&predicate.column_name)
+          .ok_or_else(|| ServerError::does_not_exist("column", &predicate.column_name))?;
+        Some(ZoneMapPredicate {
+          column_name: stored_col_name,
+          min: parse_bound(&predicate.min)?,
+          max: parse_bound(&predicate.max)?,
+        })
+      },
+      None => None,
+    };
+
+    let written_at_range = if req.min_written_at.is_some() || req.max_written_at.is_some() {
+      let parse_bound = |bound: &Option<serde_json::Value>| {
+        bound.as_ref()
+          .map(|value| write_to_partition_rest::parse_field_value(value, DataType::TimestampMicros))
+          .transpose()
+      };
+      Some(ZoneMapPredicate {
+        column_name: WRITTEN_AT_COLUMN_NAME.to_string(),
+        min: parse_bound(&req.min_written_at)?,
+        max: parse_bound(&req.max_written_at)?,
+      })
+    } else {
+      None
+    };
+    if let Some(sample_rate) = req.sample_rate {
+      if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+        return Err(ServerError::invalid("sample rate must be in (0, 1]"));
+      }
+    }
+    let is_live_rows_read = req.apply_deletions ||
+      written_at_range.is_some() ||
+      req.limit.is_some() ||
+      req.sample_rate.is_some();
+    if predicate.is_some() && is_live_rows_read {
+      return Err(ServerError::invalid(
+        "a predicate can't be combined with applying deletions, a written_at range, limit, or sample rate"
+      ));
+    }
+
+    let (correlation_id, is_own_correlation) = match &req.correlation_id {
+      Some(correlation_id) => (correlation_id.clone(), false),
+      None => (Uuid::new_v4().to_string(), true),
+    };
+    let pb_req = ReadSegmentColumnRequest {
+      table_name: req.table_name.clone(),
+      partition: write_to_partition_rest::pb_partition(&req.partition, &schema.partitioning)?,
+      segment_id: req.segment_id.clone(),
+      column_name: req.column_name.clone(),
+      correlation_id: correlation_id.clone(),
+    };
+    let res = if is_live_rows_read {
+      let op = ReadLiveRowsOp {
+        req: pb_req,
+        range: written_at_range,
+        limit: req.limit,
+        sample_rate: req.sample_rate,
+        reverse: req.reverse,
+      };
+      Self::read_live_rows(server, op).await
+    } else {
+      Self::read_all(server, pb_req, predicate, req.reverse).await
+    };
+    if is_own_correlation {
+      server.correlation_metadata_cache.end_read(&correlation_id).await;
+    }
+    res
+  }
+}
+
+impl ReadSegmentColumnRestOp {
+  async fn read_live_rows(
+    server: &Server,
+    op: ReadLiveRowsOp,
+  ) -> ServerResult<ReadSegmentColumnResponseSerde> {
+    let resp = op.execute(server).await?;
+    Ok(ReadSegmentColumnResponseSerde {
+      row_count: resp.row_count,
+      deletion_count: 0,
+      implicit_nulls_count: 0,
+      codec: String::new(),
+      compacted_data: Vec::new(),
+      flushed_data: resp.data,
+      skipped_rows: Vec::new(),
+    })
+  }
+
+  async fn read_all(
+    server: &Server,
+    req: ReadSegmentColumnRequest,
+    predicate: Option<ZoneMapPredicate>,
+    reverse: bool,
+  ) -> ServerResult<ReadSegmentColumnResponseSerde> {
+    let mut compacted_bytes = Vec::new();
+    let mut flushed_bytes = Vec::new();
+    let mut skipped_rows = Vec::new();
+    let mut continuation = None;
+    let mut first_resp = None;
+    loop {
+      let continued = ReadSegmentColumnOp {
+        req: req.clone(),
+        continuation,
+        predicate: predicate.clone(),
+        reverse,
+      }.execute(server).await?;
+      let resp = continued.resp;
+      if resp.codec.is_empty() {
+        flushed_bytes.extend(&resp.data);
+      } else {
+        compacted_bytes.extend(&resp.data);
+      }
+      skipped_rows.extend(continued.skipped_rows.into_iter().map(|skipped| SkippedRowsSerde {
+        row_offset: skipped.row_offset,
+        n_rows: skipped.n_rows,
+      }));
+      let first_resp = first_resp.get_or_insert_with(|| resp.clone());
+      if !resp.codec.is_empty() {
+        first_resp.codec = resp.codec;
+      }
+      continuation = continued.continuation;
+      if continuation.is_none() {
+        break;
+      }
+    }
+
+    let first_resp = first_resp.unwrap_or_default();
+    Ok(ReadSegmentColumnResponseSerde {
+      row_count: first_resp.row_count,
+      deletion_count: first_resp.deletion_count,
+      implicit_nulls_count: first_resp.implicit_nulls_count,
+      codec: first_resp.codec,
+      compacted_data: compacted_bytes,
+      flushed_data: flushed_bytes,
+      skipped_rows,
+    })
+  }
+}
+
+impl RestRoute for ReadSegmentColumnRestOp {
+  type Req = ReadSegmentColumnRequestSerde;
+
+  const ROUTE_NAME: &'static str = "read_segment_column";
+
+  fn new_op(req: Self::Req) -> ReadSegmentColumnRestOp {
+    ReadSegmentColumnRestOp { req }
+  }
+
+  fn into_protobuf(resp: ReadSegmentColumnResponseSerde) -> Result<Vec<u8>, ReadSegmentColumnResponseSerde> {
+    let pb = ReadSegmentColumnResponsePb {
+      row_count: resp.row_count,
+      deletion_count: resp.deletion_count,
+      implicit_nulls_count: resp.implicit_nulls_count,
+      codec: resp.codec,
+      compacted_data: resp.compacted_data,
+      flushed_data: resp.flushed_data,
+      skipped_rows: resp.skipped_rows.into_iter()
+        .map(|skipped| SkippedRowsPb {
+          row_offset: skipped.row_offset,
+          n_rows: skipped.n_rows,
+        })
+        .collect(),
+    };
+    Ok(pb.encode_to_vec())
+  }
+}
@@ -1,6 +1,7 @@
 use std::str::FromStr;
 
 use async_trait::async_trait;
+use chrono::Duration;
 use pancake_db_idl::dml::{ReadSegmentDeletionsRequest, ReadSegmentDeletionsResponse};
 use uuid::Uuid;
 
@@ -8,6 +9,8 @@ use crate::errors::{ServerResult, ServerError};
 use crate::locks::deletion::DeletionReadLocks;
 use crate::ops::traits::ServerOp;
 use crate::server::Server;
+use crate::server::authz::{Access, Verb};
+use crate::server::system_tables::SystemTable;
 use crate::types::{NormalizedPartition, SegmentKey};
 use crate::utils::common;
 use crate::utils::dirs;
@@ -30,6 +33,14 @@ impl ServerOp for ReadSegmentDeletionsOp {
     })
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Read, &self.req.table_name)]
+  }
+
+  fn reads_system_tables(&self) -> bool {
+    true
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: DeletionReadLocks) -> ServerResult<ReadSegmentDeletionsResponse> {
     let req = &self.req;
     common::validate_entity_name_for_read("table name", &req.table_name)?;
@@ -37,6 +48,10 @@ impl ServerOp for ReadSegmentDeletionsOp {
     if req.correlation_id.is_empty() {
       return Err(ServerError::invalid("must provide correlation id"))
     }
+    // system tables are materialized without deletions
+    if SystemTable::from_name(&req.table_name).is_some() {
+      return Ok(ReadSegmentDeletionsResponse::default());
+    }
 
     let DeletionReadLocks {
       table_meta: _,
@@ -44,17 +59,19 @@ impl ServerOp for ReadSegmentDeletionsOp {
       segment_meta,
       segment_key,
     } = locks;
-    let version = server.correlation_metadata_cache.get_correlated_read_version(
+    let runtime_config = server.runtime_config().await;
+    let (pin, _) = server.correlation_metadata_cache.get_correlated_pin(
       &req.correlation_id,
       &segment_key,
-      segment_meta.read_version
+      segment_meta.read_version,
+      Duration::seconds(runtime_config.correlation_ttl_seconds),
     ).await?;
-    let compaction_key = segment_key.compaction_key(version);
+    let compaction_key = segment_key.compaction_key(pin.version);
 
     let post_deletions_path = dirs::post_compaction_deletions_path(
       &server.opts.dir,
       &compaction_key,
-      segment_meta.deletion_id,
+      pin.deletion_id.unwrap_or(segment_meta.deletion_id),
     );
     let data = common::read_or_empty(post_deletions_path).await?;
 
@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+
+use crate::{LOGGER, Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, RecentErrorSerde, RecentErrorsResponseSerde};
+
+pub struct RecentErrorsOp;
+
+#[async_trait]
+impl ServerOp for RecentErrorsOp {
+  type Locks = TrivialLocks;
+  type Response = RecentErrorsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, _server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let errors = LOGGER.recent_errors()
+      .into_iter()
+      .map(|recent_error| RecentErrorSerde {
+        at: recent_error.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        message: recent_error.message,
+      })
+      .collect();
+    Ok(RecentErrorsResponseSerde { errors })
+  }
+}
+
+impl RestRoute for RecentErrorsOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "recent_errors";
+
+  fn new_op(_req: Self::Req) -> RecentErrorsOp {
+    RecentErrorsOp
+  }
+}
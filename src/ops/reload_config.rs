@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, ReloadConfigResponseSerde};
+
+pub struct ReloadConfigOp;
+
+#[async_trait]
+impl ServerOp for ReloadConfigOp {
+  type Locks = TrivialLocks;
+  type Response = ReloadConfigResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let changed = server.reload_config().await?;
+    Ok(ReloadConfigResponseSerde { changed })
+  }
+}
+
+impl RestRoute for ReloadConfigOp {
+  type Req = EmptySerde;
+
+  const ROUTE_NAME: &'static str = "reload_config";
+
+  fn new_op(_req: Self::Req) -> ReloadConfigOp {
+    ReloadConfigOp
+  }
+}
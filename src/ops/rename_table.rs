@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+
+use crate::errors::{ErrorCode, ServerError, ServerResult};
+use crate::locks::table::TablePairWriteLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, RenameTableRequestSerde};
+use crate::server::Server;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+// Moves a table to a new name by renaming its dir, which holds its metadata
+// along with all its data, so the rename is atomic. Writes that already
+// chose a segment of the table before the rename fail.
+pub struct RenameTableOp {
+  pub req: RenameTableRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for RenameTableOp {
+  type Locks = TablePairWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<(String, String)> {
+    Ok((self.req.table_name.clone(), self.req.new_table_name.clone()))
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![
+      Access::table(Verb::Delete, &self.req.table_name),
+      Access::table(Verb::Create, &self.req.new_table_name),
+    ]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    Some(AuditEvent {
+      operation: "rename_table",
+      table_name: self.req.table_name.clone(),
+      summary: format!("to {}", self.req.new_table_name),
+    })
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TablePairWriteLocks) -> ServerResult<EmptySerde> {
+    let dir = &server.opts.dir;
+    let table_name = &self.req.table_name;
+    let new_table_name = &self.req.new_table_name;
+    common::validate_entity_name_for_write("table name", table_name)?;
+    common::validate_entity_name_for_write("table name", new_table_name)?;
+
+    let TablePairWriteLocks {
+      mut maybe_src_table_guard,
+      mut maybe_dst_table_guard,
+    } = locks;
+    let table_meta = common::unwrap_metadata(table_name, &*maybe_src_table_guard)?;
+    if maybe_dst_table_guard.is_some() {
+      return Err(ServerError::invalid(format!(
+        "table {} already exists",
+        new_table_name,
+      )).with_code(ErrorCode::TableAlreadyExists));
+    }
+
+    log::info!("renaming table {} to {}", table_name, new_table_name);
+    vfs::rename(
+      dirs::table_dir(dir, table_name),
+      dirs::table_dir(dir, new_table_name),
+    ).await?;
+
+    *maybe_src_table_guard = None;
+    *maybe_dst_table_guard = Some(table_meta);
+    server.prune_table_caches(table_name).await;
+    server.prune_table_caches(new_table_name).await;
+    server.rename_flush_candidates(table_name, new_table_name).await;
+    // the compaction loop measures it again under its new name
+    server.forget_table_disk_usage(table_name).await;
+
+    Ok(EmptySerde {})
+  }
+}
+
+impl RestRoute for RenameTableOp {
+  type Req = RenameTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "rename_table";
+
+  fn new_op(req: Self::Req) -> RenameTableOp {
+    RenameTableOp { req }
+  }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::TableWriteLocks;
+use crate::metadata::PersistentMetadata;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, SetBloomFilterColumnsRequestSerde};
+use crate::server::authz::{Access, Verb};
+use crate::utils::common;
+
+// Chooses which columns compaction builds bloom filters for. Segments pick
+// up the change the next time they are compacted.
+pub struct SetBloomFilterColumnsOp {
+  pub req: SetBloomFilterColumnsRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for SetBloomFilterColumnsOp {
+  type Locks = TableWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableWriteLocks) -> ServerResult<Self::Response> {
+    let table_name = &self.req.table_name;
+    let TableWriteLocks {
+      mut maybe_table_guard
+    } = locks;
+    let table_meta = common::unwrap_metadata(table_name, &*maybe_table_guard)?;
+
+    common::validate_entity_name_for_write("table name", table_name)?;
+    common::check_no_duplicate_names("column", self.req.columns.clone())?;
+    let schema = table_meta.visible_schema();
+    let mut stored_col_names = Vec::with_capacity(self.req.columns.len());
+    for col_name in &self.req.columns {
+      match table_meta.stored_column_name(col_name) {
+        Some(stored_col_name) if schema.columns.contains_key(col_name) => stored_col_names.push(stored_col_name),
+        _ => return Err(ServerError::does_not_exist("column", col_name)),
+      }
+    }
+
+    let mut new_table_meta = table_meta.clone();
+    new_table_meta.bloom_filter_columns = stored_col_names;
+    new_table_meta.bloom_filter_columns.sort();
+    new_table_meta.overwrite(&server.opts.dir, table_name).await?;
+    *maybe_table_guard = Some(new_table_meta);
+    Ok(EmptySerde {})
+  }
+}
+
+impl RestRoute for SetBloomFilterColumnsOp {
+  type Req = SetBloomFilterColumnsRequestSerde;
+
+  const ROUTE_NAME: &'static str = "set_bloom_filter_columns";
+
+  fn new_op(req: Self::Req) -> SetBloomFilterColumnsOp {
+    SetBloomFilterColumnsOp { req }
+  }
+}
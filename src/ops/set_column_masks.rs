@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::locks::table::TableWriteLocks;
+use crate::metadata::PersistentMetadata;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, SetColumnMasksRequestSerde};
+use crate::server::audit::{self, AuditEvent};
+use crate::server::authz::{Access, Verb};
+use crate::utils::common;
+use crate::utils::masking;
+
+// Chooses which columns are masked, and how, for principals who may read
+// the table but not unmask it. Stored data is unchanged, so this applies
+// to every read from then on.
+pub struct SetColumnMasksOp {
+  pub req: SetColumnMasksRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for SetColumnMasksOp {
+  type Locks = TableWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Admin, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    let summary = if self.req.column_masks.is_empty() {
+      "no masked columns".to_string()
+    } else {
+      format!("masked columns {}", audit::join_sorted(self.req.column_masks.keys()))
+    };
+    Some(AuditEvent {
+      operation: "set_column_masks",
+      table_name: self.req.table_name.clone(),
+      summary,
+    })
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableWriteLocks) -> ServerResult<Self::Response> {
+    let table_name = &self.req.table_name;
+    let TableWriteLocks {
+      mut maybe_table_guard
+    } = locks;
+    let table_meta = common::unwrap_metadata(table_name, &*maybe_table_guard)?;
+
+    common::validate_entity_name_for_write("table name", table_name)?;
+    let visible_masks = masking::parse_column_masks(
+      &self.req.column_masks,
+      &table_meta.visible_schema().columns,
+    )?;
+    let mut column_masks = HashMap::with_capacity(visible_masks.len());
+    for (col_name, rule) in visible_masks {
+      let stored_col_name = table_meta.stored_column_name(&col_name)
+        .ok_or_else(|| ServerError::does_not_exist("column", &col_name))?;
+      column_masks.insert(stored_col_name, rule);
+    }
+
+    let mut new_table_meta = table_meta.clone();
+    new_table_meta.column_masks = column_masks;
+    new_table_meta.mark_altered();
+    new_table_meta.overwrite(&server.opts.dir, table_name).await?;
+    *maybe_table_guard = Some(new_table_meta);
+    Ok(EmptySerde {})
+  }
+}
+
+impl RestRoute for SetColumnMasksOp {
+  type Req = SetColumnMasksRequestSerde;
+
+  const ROUTE_NAME: &'static str = "set_column_masks";
+
+  fn new_op(req: Self::Req) -> SetColumnMasksOp {
+    SetColumnMasksOp { req }
+  }
+}
@@ -0,0 +1,159 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::locks::table::GlobalTableReadLocks;
+use crate::metadata::manifest::MetadataBatch;
+use crate::metadata::PersistentMetadata;
+use crate::metadata::segment::SegmentMetadata;
+use crate::ops::traits::ServerOp;
+use crate::opt::RuntimeConfig;
+use crate::server::Server;
+use crate::server::cancel;
+use crate::server::slow_ops;
+use crate::types::{SegmentKey, ShardId};
+use crate::utils::{common, dirs, vfs};
+
+// segments are split once their live rows exceed this multiple of
+// target_rows_per_segment
+const MIN_SPLIT_MULTIPLE: u64 = 2;
+
+pub struct SplitSegmentOp {
+  pub key: SegmentKey,
+}
+
+impl SplitSegmentOp {
+  fn is_splittable(runtime_config: &RuntimeConfig, segment_meta: &SegmentMetadata) -> ServerResult<bool> {
+    Ok(
+      segment_meta.is_cold &&
+        segment_meta.live_n()? > MIN_SPLIT_MULTIPLE * runtime_config.target_rows_per_segment as u64 &&
+        segment_meta.staged_n == 0 &&
+        !segment_meta.flushing &&
+        segment_meta.write_versions.len() == 1 &&
+        segment_meta.replaced_segment_ids.is_empty()
+    )
+  }
+}
+
+#[async_trait]
+impl ServerOp for SplitSegmentOp {
+  type Locks = GlobalTableReadLocks;
+  // the ids of the segments it was split into, if any
+  type Response = Vec<Uuid>;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.key.table_name.clone())
+  }
+
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.background_op_timeout_seconds
+  }
+
+  // 1. obtain partition, deletion, and segment write locks (in the same order
+  //    as writes and deletions do) and check whether the segment is far
+  //    larger than the target
+  // 2. read its live rows and divide them evenly among as few new segments
+  //    as keep each within the target, renumbering row ids from 0
+  // 3. build each new segment with its rows staged in a garbage directory
+  //    and move it into place
+  // 4. commit every new segment's metadata, recording the segment it
+  //    replaces, along with the partition's active segments in one batch
+  // 5. remove the original segment's directory, then clear the record of it
+  // A crash before 4 leaves directories recovery will remove, and a crash
+  // after leaves the record recovery uses to finish 5.
+  async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Vec<Uuid>> {
+    server.check_writable()?;
+    let dir = &server.opts.dir;
+    let runtime_config = server.table_runtime_config(&locks.table_meta).await;
+    let partition_key = self.key.partition_key();
+
+    let partition_lock = server.partition_metadata_cache.get_lock(&partition_key)
+      .await?;
+    let mut partition_guard = slow_ops::wait_for_lock("partition", partition_lock.write()).await?;
+    let deletion_lock = server.deletion_metadata_cache.get_lock(&self.key)
+      .await?;
+    let _deletion_guard = slow_ops::wait_for_lock("deletion", deletion_lock.write()).await?;
+    let segment_lock = server.segment_metadata_cache.get_lock(&self.key)
+      .await?;
+    let mut segment_guard = slow_ops::wait_for_lock("segment", segment_lock.write()).await?;
+
+    let segment_meta = match &*segment_guard {
+      Some(segment_meta) => segment_meta,
+      None => return Err(ServerError::does_not_exist("segment", &self.key)),
+    };
+    let partition_meta = match &mut *partition_guard {
+      Some(partition_meta) => partition_meta,
+      None => return Err(ServerError::does_not_exist("partition", &partition_key)),
+    };
+    let is_pinned = !server.correlation_metadata_cache.pinned_versions(&self.key).await.is_empty();
+    if !Self::is_splittable(&runtime_config, segment_meta)? || is_pinned {
+      return Ok(Vec::new());
+    }
+
+    let schema = locks.table_meta.schema();
+    let columns = common::augmented_columns(&schema);
+    let mut rows = server.read_live_rows(&self.key, segment_meta, &columns).await?;
+    cancel::check()?;
+    let target_n = runtime_config.target_rows_per_segment.max(1) as usize;
+    let n_splits = rows.len().div_ceil(target_n);
+    let split_n = rows.len().div_ceil(n_splits);
+    log::info!(
+      "splitting segment {} with {} live rows into {} segments",
+      self.key,
+      rows.len(),
+      n_splits,
+    );
+
+    let mut new_segments = Vec::with_capacity(n_splits);
+    for split_rows in rows.chunks_mut(split_n) {
+      let shard_id = ShardId::randomly_select(
+        locks.global_meta.n_shards_log,
+        1, // TODO use table_meta
+        &partition_key,
+        partition_meta.sharding_denominator_log,
+      );
+      let new_key = partition_key.segment_key(shard_id.generate_segment_id());
+      let mut new_meta = server.build_rewritten_segment(&new_key, &schema, split_rows).await?;
+      new_meta.replaced_segment_ids = vec![self.key.segment_id];
+      vfs::rename(
+        dirs::garbage_segment_dir(dir, &new_key),
+        dirs::segment_dir(dir, &new_key),
+      ).await?;
+      new_segments.push((new_key, new_meta));
+    }
+
+    let mut batch = MetadataBatch::default();
+    for (new_key, new_meta) in &new_segments {
+      batch.add(new_meta, new_key)?;
+    }
+    if partition_meta.active_segment_ids.contains(&self.key.segment_id) {
+      partition_meta.active_segment_ids.retain(|&id| id != self.key.segment_id);
+      batch.add(partition_meta, &partition_key)?;
+    }
+    let index_lock = server.partition_index_cache.get_lock(&partition_key.table_name).await?;
+    let mut index_guard = index_lock.write().await;
+    let mut index = index_guard.clone().unwrap_or_default();
+    for (new_key, _) in &new_segments {
+      index.add_segment(&partition_key.partition, new_key.segment_id);
+    }
+    batch.add(&index, &partition_key.table_name)?;
+    batch.commit(server).await?;
+    *index_guard = Some(index);
+    drop(index_guard);
+
+    server.remove_replaced_segments(partition_meta, std::slice::from_ref(&self.key)).await?;
+    *segment_guard = None;
+
+    let mut new_segment_ids = Vec::with_capacity(new_segments.len());
+    for (new_key, mut new_meta) in new_segments {
+      new_meta.replaced_segment_ids = Vec::new();
+      new_meta.overwrite(dir, &new_key).await?;
+      let new_segment_lock = server.segment_metadata_cache.get_lock(&new_key)
+        .await?;
+      *new_segment_lock.write().await = Some(new_meta);
+      server.add_flush_candidate(new_key.clone()).await;
+      new_segment_ids.push(new_key.segment_id);
+    }
+    Ok(new_segment_ids)
+  }
+}
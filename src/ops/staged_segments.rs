@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use futures::pin_mut;
+use futures::StreamExt;
+
+use crate::{Server, ServerResult};
+use crate::locks::trivial::TrivialLocks;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{StagedSegmentSerde, StagedSegmentsRequestSerde, StagedSegmentsResponseSerde};
+
+pub struct StagedSegmentsOp {
+  pub req: StagedSegmentsRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for StagedSegmentsOp {
+  type Locks = TrivialLocks;
+  type Response = StagedSegmentsResponseSerde;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  // lists every segment with staged (unflushed) rows
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<Self::Response> {
+    let mut segments = Vec::new();
+    let segment_key_stream = server.stream_all_segment_keys();
+    pin_mut!(segment_key_stream);
+    while let Some(segment_key_result) = segment_key_stream.next().await {
+      let segment_key = segment_key_result?;
+      if let Some(table_name) = &self.req.table_name {
+        if &segment_key.table_name != table_name {
+          continue;
+        }
+      }
+
+      let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+      let maybe_segment_meta = segment_lock.read().await.clone();
+      if let Some(segment_meta) = maybe_segment_meta {
+        if segment_meta.staged_n > 0 {
+          segments.push(StagedSegmentSerde {
+            segment: segment_key.to_string(),
+            staged_n: segment_meta.staged_n,
+            flushing: segment_meta.flushing,
+          });
+        }
+      }
+    }
+    Ok(StagedSegmentsResponseSerde { segments })
+  }
+}
+
+impl RestRoute for StagedSegmentsOp {
+  type Req = StagedSegmentsRequestSerde;
+
+  const ROUTE_NAME: &'static str = "staged_segments";
+
+  fn new_op(req: Self::Req) -> StagedSegmentsOp {
+    StagedSegmentsOp { req }
+  }
+}
@@ -1,15 +1,54 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use futures::pin_mut;
 use serde::de::DeserializeOwned;
+use tokio::time::{Duration, Instant};
 
 use crate::{Server, ServerResult};
 use crate::locks::traits::ServerOpLocks;
+use crate::opt::RuntimeConfig;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
+use crate::server::cancel;
+use crate::server::slow_ops::{self, OpProfile};
+use crate::utils::names::NormalizeNames;
+
+// the op's type name without its module path, like FlushOp
+fn op_name<Op>() -> &'static str {
+  let type_name = std::any::type_name::<Op>();
+  type_name.rsplit("::").next().unwrap_or(type_name)
+}
 
 #[async_trait]
 pub trait ServerOp: Sync {
   type Locks: ServerOpLocks;
-  type Response;
+  type Response: Send;
 
   fn get_key(&self) -> ServerResult<<Self::Locks as ServerOpLocks>::Key>;
+
+  // what the requesting principal must be allowed to do; by default, only
+  // server-wide admins may run an op
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::server(Verb::Admin)]
+  }
+
+  // how long the op may run before it's cancelled; 0 means no limit
+  fn timeout_seconds(&self, config: &RuntimeConfig) -> u64 {
+    config.op_timeout_seconds
+  }
+
+  // DDL and deletion ops describe themselves for the audit log
+  fn audit_event(&self) -> Option<AuditEvent> {
+    None
+  }
+
+  // read ops that also serve the virtual system tables, whose metadata the
+  // locks make up instead of reporting them missing
+  fn reads_system_tables(&self) -> bool {
+    false
+  }
+
   async fn execute_with_locks(
     &self,
     server: &Server,
@@ -17,7 +56,53 @@ pub trait ServerOp: Sync {
   ) -> ServerResult<Self::Response> where Self::Locks: 'async_trait;
 
   async fn execute(&self, server: &Server) -> ServerResult<Self::Response> where Self: Sized {
-    <Self::Locks as ServerOpLocks>::execute(server, self).await
+    // ops run by other ops are profiled as part of the outer op
+    if slow_ops::is_profiling() {
+      return self.execute_authorized(server).await;
+    }
+
+    let profile = Arc::new(OpProfile::default());
+    let cancellation = cancel::current()
+      .map(|request_cancellation| request_cancellation.child())
+      .unwrap_or_default();
+    let timeout_seconds = self.timeout_seconds(&server.runtime_config().await);
+    let start = Instant::now();
+    let op_future = cancel::scope(
+      cancellation.clone(),
+      slow_ops::scope(profile.clone(), self.execute_authorized(server)),
+    );
+    pin_mut!(op_future);
+    // a timed out op keeps running until its next safe point, so it never
+    // leaves a write half done
+    let res = if timeout_seconds == 0 {
+      op_future.await
+    } else {
+      let timeout = Duration::from_secs(timeout_seconds);
+      tokio::select! {
+        res = &mut op_future => res,
+        _ = tokio::time::sleep(timeout) => {
+          cancellation.time_out(timeout);
+          op_future.await
+        },
+      }
+    };
+    let key = match self.get_key() {
+      Ok(key) => format!("{:?}", key),
+      Err(_) => "<invalid key>".to_string(),
+    };
+    server.record_op_profile(op_name::<Self>(), key, start.elapsed(), &profile).await;
+    res
+  }
+
+  async fn execute_authorized(&self, server: &Server) -> ServerResult<Self::Response> where Self: Sized {
+    let res = match server.authorize(&self.required_access()).await {
+      Ok(()) => <Self::Locks as ServerOpLocks>::execute(server, self).await,
+      Err(e) => Err(e),
+    };
+    if let Some(event) = self.audit_event() {
+      server.record_audit_event(event, res.as_ref().err()).await;
+    }
+    res
   }
 }
 
@@ -29,10 +114,22 @@ pub trait ServerWriteOp: ServerOp<Locks=<Self as ServerWriteOp>::Locks> {
 }
 
 #[async_trait::async_trait]
-pub trait RestRoute: ServerOp + Send + Sync {
-  type Req: DeserializeOwned + Send + Sync;
+pub trait RestRoute: ServerOp + Send + Sync + 'static {
+  type Req: DeserializeOwned + NormalizeNames + Send + Sync;
 
   const ROUTE_NAME: &'static str;
 
   fn new_op(req: Self::Req) -> Self;
+
+  // how many rows the request writes, for the per-request row limit
+  fn request_row_count(_req: &Self::Req) -> usize {
+    0
+  }
+
+  // For routes that can also reply with protobuf, to clients that accept
+  // application/x-protobuf, saving the JSON and base 64 overhead. Routes
+  // that can't give the response back to be sent as JSON.
+  fn into_protobuf(resp: Self::Response) -> Result<Vec<u8>, Self::Response> {
+    Err(resp)
+  }
 }
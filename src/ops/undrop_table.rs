@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use futures::pin_mut;
+use futures::StreamExt;
+
+use crate::errors::{ErrorCode, ServerError, ServerResult};
+use crate::locks::table::TableWriteLocks;
+use crate::metadata::PersistentMetadata;
+use crate::metadata::table::TableMetadata;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::serde_models::{EmptySerde, UndropTableRequestSerde};
+use crate::server::Server;
+use crate::server::audit::AuditEvent;
+use crate::server::authz::{Access, Verb};
+use crate::types::InternalTableInfo;
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+// Restores the most recently dropped table of a name from the trash.
+pub struct UndropTableOp {
+  pub req: UndropTableRequestSerde,
+}
+
+#[async_trait]
+impl ServerOp for UndropTableOp {
+  type Locks = TableWriteLocks;
+  type Response = EmptySerde;
+
+  fn get_key(&self) -> ServerResult<String> {
+    Ok(self.req.table_name.clone())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Create, &self.req.table_name)]
+  }
+
+  fn audit_event(&self) -> Option<AuditEvent> {
+    Some(AuditEvent {
+      operation: "undrop_table",
+      table_name: self.req.table_name.clone(),
+      summary: String::new(),
+    })
+  }
+
+  async fn execute_with_locks(&self, server: &Server, locks: TableWriteLocks) -> ServerResult<EmptySerde> {
+    let dir = &server.opts.dir;
+    let table_name = &self.req.table_name;
+    common::validate_entity_name_for_write("table name", table_name)?;
+
+    let TableWriteLocks {
+      mut maybe_table_guard,
+    } = locks;
+    if maybe_table_guard.is_some() {
+      return Err(ServerError::invalid(format!(
+        "table {} already exists",
+        table_name,
+      )).with_code(ErrorCode::TableAlreadyExists));
+    }
+    let trashed = server.list_trash().await?
+      .into_iter()
+      .rfind(|trashed| &trashed.table_name == table_name)
+      .ok_or_else(|| ServerError::does_not_exist("dropped table", table_name))?;
+
+    log::info!("undropping table {} dropped at {}", table_name, trashed.dropped_at);
+    vfs::rename(&trashed.path, dirs::table_dir(dir, table_name)).await?;
+    // if we crash before clearing the `dropped` flag, recovery puts the
+    // table back in the trash
+    let mut table_meta = TableMetadata::load(dir, table_name).await?
+      .ok_or_else(|| ServerError::corrupt(format!(
+        "dropped table {} has no metadata",
+        table_name,
+      )))?;
+    table_meta.dropped = false;
+    table_meta.overwrite(dir, table_name).await?;
+    *maybe_table_guard = Some(table_meta.clone());
+    server.prune_table_caches(table_name).await;
+
+    // flush whatever rows were still staged when it was dropped
+    let table = InternalTableInfo {
+      name: table_name.clone(),
+      meta: table_meta,
+    };
+    let segment_key_stream = server.stream_table_segment_keys(&table);
+    pin_mut!(segment_key_stream);
+    while let Some(segment_key_result) = segment_key_stream.next().await {
+      let segment_key = segment_key_result?;
+      let segment_lock = server.segment_metadata_cache.get_lock(&segment_key).await?;
+      let has_staged_rows = segment_lock.read().await
+        .as_ref()
+        .map(|segment_meta| segment_meta.staged_n > 0)
+        .unwrap_or(false);
+      if has_staged_rows {
+        server.add_flush_candidate(segment_key.clone()).await;
+      }
+      server.measure_disk_usage(&segment_key).await?;
+    }
+    Ok(EmptySerde {})
+  }
+}
+
+impl RestRoute for UndropTableOp {
+  type Req = UndropTableRequestSerde;
+
+  const ROUTE_NAME: &'static str = "undrop_table";
+
+  fn new_op(req: Self::Req) -> UndropTableOp {
+    UndropTableOp { req }
+  }
+}
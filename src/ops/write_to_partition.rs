@@ -3,17 +3,22 @@ use async_trait::async_trait;
 use pancake_db_idl::dml::{FieldValue, Row, WriteToPartitionRequest, WriteToPartitionResponse};
 use pancake_db_idl::dml::field_value::Value;
 use prost_types::Timestamp;
-use tokio::fs;
+use tokio::sync::OwnedRwLockWriteGuard;
 
 use crate::constants::{ROW_ID_COLUMN_NAME, WRITTEN_AT_COLUMN_NAME};
 use crate::errors::{Contextable, ServerError, ServerResult};
 use crate::locks::partition::PartitionWriteLocks;
+use crate::locks::trivial::TrivialLocks;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::segment::SegmentMetadata;
+use crate::metadata::table::{TableConfigOverrides, TableMetadata};
 use crate::ops::traits::ServerOp;
+use crate::opt::RuntimeConfig;
 use crate::server::Server;
+use crate::server::authz::{Access, Verb};
+use crate::server::slow_ops;
 use crate::types::{NormalizedPartition, PartitionKey, SegmentKey};
-use crate::utils::{common, navigation};
+use crate::utils::common;
 use crate::utils::dirs;
 
 pub struct WriteToPartitionOp {
@@ -33,60 +38,152 @@ impl ServerOp for WriteToPartitionOp {
     })
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Write, &self.req.table_name)]
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: PartitionWriteLocks) -> ServerResult<WriteToPartitionResponse> {
+    self.write(server, locks).await?;
+    Ok(WriteToPartitionResponse {..Default::default()})
+  }
+}
+
+// Writes through the server's group commit, if it has a window. Each
+// request is authorized on its own before joining a group, since the group
+// is written as whichever request leads it.
+pub struct GroupedWriteToPartitionOp {
+  pub req: WriteToPartitionRequest,
+}
+
+#[async_trait]
+impl ServerOp for GroupedWriteToPartitionOp {
+  type Locks = TrivialLocks;
+  type Response = WriteToPartitionResponse;
+
+  fn get_key(&self) -> ServerResult<()> {
+    Ok(())
+  }
+
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Write, &self.req.table_name)]
+  }
+
+  async fn execute_with_locks(&self, server: &Server, _locks: TrivialLocks) -> ServerResult<WriteToPartitionResponse> {
+    server.write_grouped(&self.req).await
+  }
+}
+
+// The row ids assigned to a write, which writers can later use to refer to
+// the rows they wrote, e.g. to delete them.
+pub struct WrittenRows {
+  pub segment_key: SegmentKey,
+  // inclusive; None if no rows were written
+  pub row_id_range: Option<(u64, u64)>,
+}
+
+// A write whose rows have been assigned row ids and serialized, still
+// holding its segment write lock. Transactions prepare the writes for every
+// partition before appending any of them.
+pub struct PreparedWrite {
+  segment_guard: OwnedRwLockWriteGuard<Option<SegmentMetadata>>,
+  pub segment_key: SegmentKey,
+  full_rows: Vec<Row>,
+  pub staged_bytes: Vec<u8>,
+  config_overrides: TableConfigOverrides,
+}
+
+impl PreparedWrite {
+  pub fn written_rows(&self) -> WrittenRows {
+    // row ids are assigned consecutively
+    let row_id = |row: Option<&Row>| match row?.fields.get(ROW_ID_COLUMN_NAME)?.value {
+      Some(Value::Int64Val(row_id)) => Some(row_id as u64),
+      _ => None,
+    };
+    let row_id_range = row_id(self.full_rows.first()).zip(row_id(self.full_rows.last()));
+    WrittenRows {
+      segment_key: self.segment_key.clone(),
+      row_id_range,
+    }
+  }
+
+  pub async fn append(&self, server: &Server) -> ServerResult<()> {
+    slow_ops::add_bytes(self.staged_bytes.len());
+    common::append_to_file(
+      dirs::staged_rows_path(&server.opts.dir, &self.segment_key),
+      &self.staged_bytes,
+    ).await
+  }
+
+  // the segment stays locked until the prepared write is dropped
+  pub async fn finish(&mut self, server: &Server) -> ServerResult<()> {
+    let runtime_config = server.runtime_config().await.with_overrides(&self.config_overrides);
+    WriteToPartitionOp::increment_segment_size(
+      &self.full_rows,
+      self.segment_guard.as_mut().unwrap(),
+      server,
+      &runtime_config,
+      &self.segment_key,
+    ).await
+  }
+}
+
+impl WriteToPartitionOp {
+  pub async fn write(&self, server: &Server, locks: PartitionWriteLocks) -> ServerResult<WrittenRows> {
+    let mut prepared = self.prepare(locks).await?;
+    let written_rows = prepared.written_rows();
+    prepared.append(server).await?;
+    prepared.finish(server).await?;
+    Ok(written_rows)
+  }
+
+  pub async fn prepare(&self, locks: PartitionWriteLocks) -> ServerResult<PreparedWrite> {
     common::validate_entity_name_for_read("table name", &self.req.table_name)?;
 
-    let dir = &server.opts.dir;
     let PartitionWriteLocks {
       global_meta: _,
       table_meta,
-      mut definitely_partition_guard,
-      mut definitely_segment_guard,
-      shard_id,
-      mut segment_key,
+      definitely_segment_guard,
+      segment_key,
     } = locks;
-    let partition_key = segment_key.partition_key();
-    let partition_meta = definitely_partition_guard.as_mut().unwrap();
-    let mut segment_meta = definitely_segment_guard.as_mut().unwrap();
 
-    let schema = table_meta.schema();
+    let schema = table_meta.visible_schema();
     common::validate_rows(&schema, &self.req.rows)?;
-
-    let mut default_segment_meta = SegmentMetadata::new_from_schema(&schema);
-    if segment_meta.is_cold {
-      let new_segment_id = shard_id.generate_segment_id();
-      let key = SegmentKey {
-        table_name: segment_key.table_name.clone(),
-        partition: segment_key.partition.clone(),
-        segment_id: new_segment_id,
-      };
-      navigation::create_segment_dirs(&dirs::segment_dir(dir, &key)).await?;
-      partition_meta.replace_active_segment_id(segment_key.segment_id, new_segment_id);
-      partition_meta.overwrite(dir, &partition_key).await?;
-
-      segment_key = key;
-      segment_meta = &mut default_segment_meta;
+    if !table_meta.computed_columns.is_empty() {
+      for col_name in self.req.rows.iter().flat_map(|row| row.fields.keys()) {
+        let is_computed = table_meta.stored_column_name(col_name)
+          .map(|stored_name| table_meta.computed_columns.contains_key(&stored_name))
+          .unwrap_or(false);
+        if is_computed {
+          return Err(ServerError::invalid(format!(
+            "column {} is computed and can't be written",
+            col_name,
+          )));
+        }
+      }
     }
 
+    // the new rows' ids have to fit in the segment's row id space
+    let segment_meta = definitely_segment_guard.as_ref().unwrap();
+    common::checked_u32_n(segment_meta.all_time_n + self.req.rows.len() as u64)?;
+
     // add DB columns to rows
-    let full_rows = self.full_db_columns(segment_meta);
+    let full_rows = self.full_db_columns(&table_meta, segment_meta);
 
     let staged_bytes = common::rows_to_staged_bytes(&full_rows)
       .with_context(|| "while writing staged rows to bytes")?;
-    common::append_to_file(
-      dirs::staged_rows_path(&server.opts.dir, &segment_key),
-      &staged_bytes,
-    ).await?;
 
-    Self::increment_segment_size(&full_rows, segment_meta, server, &segment_key).await?;
-
-    Ok(WriteToPartitionResponse {..Default::default()})
+    Ok(PreparedWrite {
+      segment_guard: definitely_segment_guard,
+      segment_key,
+      full_rows,
+      staged_bytes,
+      config_overrides: table_meta.config_overrides.clone(),
+    })
   }
-}
 
-impl WriteToPartitionOp {
   fn full_db_columns(
     &self,
+    table_meta: &TableMetadata,
     segment_meta: &SegmentMetadata,
   ) -> Vec<Row> {
     let written_at = FieldValue {
@@ -95,7 +192,19 @@ impl WriteToPartitionOp {
     let mut res = Vec::with_capacity(self.req.rows.len());
     let mut row_id = segment_meta.all_time_n;
     for row in &self.req.rows {
-      let mut full = row.clone();
+      // renamed columns are staged under the name they are stored under
+      let mut full = if table_meta.column_aliases.is_empty() {
+        row.clone()
+      } else {
+        Row {
+          fields: row.fields.iter()
+            .map(|(col_name, value)| (
+              table_meta.stored_column_name(col_name).unwrap_or_else(|| col_name.clone()),
+              value.clone(),
+            ))
+            .collect(),
+        }
+      };
       let row_id_fv = FieldValue {
         value: Some(Value::Int64Val(row_id as i64)),
         ..Default::default()
@@ -113,6 +222,7 @@ impl WriteToPartitionOp {
     full_rows: &[Row],
     segment_meta: &mut SegmentMetadata,
     server: &Server,
+    runtime_config: &RuntimeConfig,
     segment_key: &SegmentKey
   ) -> ServerResult<()> {
     let opts = &server.opts;
@@ -123,11 +233,11 @@ impl WriteToPartitionOp {
           .map(common::byte_size_of_field)
           .sum::<usize>())
         .sum::<usize>() as u64;
-      segment_meta.all_time_n += n_rows as u32;
-      segment_meta.staged_n += n_rows as u32;
+      segment_meta.all_time_n += n_rows as u64;
+      segment_meta.staged_n += n_rows as u64;
       segment_meta.all_time_uncompressed_size += uncompressed_size;
-      if segment_meta.all_time_n >= opts.target_rows_per_segment + segment_meta.all_time_deleted_n ||
-        segment_meta.all_time_uncompressed_size >= opts.target_uncompressed_bytes_per_segment {
+      if segment_meta.all_time_n >= runtime_config.target_rows_per_segment as u64 + segment_meta.all_time_deleted_n ||
+        segment_meta.all_time_uncompressed_size >= runtime_config.target_uncompressed_bytes_per_segment {
         segment_meta.is_cold = true;
       }
       segment_meta.overwrite(&opts.dir, segment_key).await?;
@@ -136,14 +246,23 @@ impl WriteToPartitionOp {
     Ok(())
   }
 
+  // Writes append under the segment lock and record their rows in segment
+  // metadata before the next write appends, so any unrecorded rows belong
+  // to a single write that was interrupted. It was never acknowledged, and
+  // may have been cut off anywhere, even between rows, so its rows are
+  // removed. Rows appended by a committed transaction are recorded instead.
   pub async fn recover(
     server: &Server,
+    table_meta: &TableMetadata,
     segment_key: &SegmentKey,
     segment_meta: &mut SegmentMetadata,
+    committed: bool,
   ) -> ServerResult<()> {
     let dir = &server.opts.dir;
     let staged_rows_path = dirs::staged_rows_path(dir, segment_key);
-    let staged_rows = common::staged_bytes_to_rows(&fs::read(&staged_rows_path).await?)?;
+    // the file doesn't exist until the segment's first write appends to it
+    let staged_bytes = common::read_or_empty(&staged_rows_path).await?;
+    let (staged_rows, n_complete_bytes) = common::staged_bytes_to_complete_rows(&staged_bytes)?;
     if staged_rows.len() < segment_meta.staged_n as usize {
       return Err(ServerError::internal(format!(
         "segment {} is in an impossible state with fewer rows ({}) in staged file than in metadata ({})",
@@ -152,13 +271,35 @@ impl WriteToPartitionOp {
         segment_meta.staged_n,
       )))
     }
-    let n_rows = staged_rows.len() - segment_meta.staged_n as usize;
-    if n_rows > 0 {
+    let recorded_rows = &staged_rows[..segment_meta.staged_n as usize];
+    let unrecorded_rows = &staged_rows[segment_meta.staged_n as usize..];
+    if committed && n_complete_bytes == staged_bytes.len() {
+      if !unrecorded_rows.is_empty() {
+        log::debug!(
+          "identified committed staged rows missing from segment {} metadata; filling them in",
+          segment_key,
+        );
+      }
+      return Self::increment_segment_size(
+        unrecorded_rows,
+        segment_meta,
+        server,
+        &server.table_runtime_config(table_meta).await,
+        segment_key,
+      ).await;
+    }
+
+    if !unrecorded_rows.is_empty() || n_complete_bytes < staged_bytes.len() {
       log::debug!(
-        "identified missing staged rows in segment {} metadata; filling them in",
+        "identified interrupted write to segment {}; removing its staged rows",
         segment_key,
-      )
+      );
+      common::overwrite_file_atomic(
+        &staged_rows_path,
+        common::rows_to_staged_bytes(recorded_rows)?,
+        dir,
+      ).await?;
     }
-    Self::increment_segment_size(&staged_rows, segment_meta, server, segment_key).await
+    Ok(())
   }
 }
\ No newline at end of file
@@ -1,25 +1,29 @@
 use std::collections::HashMap;
-use std::time::SystemTime;
 
 use async_trait::async_trait;
-use chrono::DateTime;
-use pancake_db_idl::dml::{FieldValue, PartitionFieldValue, RepeatedFieldValue, Row, WriteToPartitionRequest};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use pancake_db_idl::dml::{FieldValue, PartitionFieldComparison, PartitionFieldValue, RepeatedFieldValue, Row, WriteToPartitionRequest};
 use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dml::partition_field_comparison::Operator;
 use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
 use pancake_db_idl::dtype::DataType;
 use pancake_db_idl::partition_dtype::PartitionDataType;
 use pancake_db_idl::schema::{ColumnMeta, PartitionMeta};
-use prost_types::Timestamp;
 use serde_json::{Number, Value as JsonValue};
 
 use crate::{Server, ServerResult};
-use crate::errors::ServerError;
+use crate::errors::{ServerError, ServerErrorKind};
 use crate::locks::partition::PartitionWriteLocks;
 use crate::locks::table::GlobalTableReadLocks;
+use crate::metadata::table::TableMetadata;
 use crate::ops::traits::{RestRoute, ServerOp};
 use crate::ops::write_to_partition::WriteToPartitionOp;
-use crate::serde_models::{EmptySerde, WriteToPartitionRequestSerde};
+use crate::server::DeadLetter;
+use crate::serde_models::{DroppedFieldSerde, PartitionFilterSerde, PartitionOperatorSerde, WriteToPartitionRequestSerde, WriteToPartitionResponseSerde};
+use crate::server::authz::{Access, Verb};
 use crate::types::{NormalizedPartition, PartitionKey};
+use crate::utils::common;
+use crate::utils::common::{InvalidField, PartitionCondition};
 
 pub struct WriteToPartitionRestOp {
   pub req: WriteToPartitionRequestSerde,
@@ -28,22 +32,92 @@ pub struct WriteToPartitionRestOp {
 #[async_trait]
 impl ServerOp for WriteToPartitionRestOp {
   type Locks = GlobalTableReadLocks;
-  type Response = EmptySerde;
+  type Response = WriteToPartitionResponseSerde;
 
   fn get_key(&self) -> ServerResult<String> {
     Ok(self.req.table_name.to_string())
   }
 
+  fn required_access(&self) -> Vec<Access> {
+    vec![Access::table(Verb::Write, &self.req.table_name)]
+  }
+
   async fn execute_with_locks(&self, server: &Server, locks: GlobalTableReadLocks) -> ServerResult<Self::Response> {
-    let schema = locks.table_meta.schema();
-    let partition = self.pb_partition(&schema.partitioning)?;
-    let rows = self.pb_rows(&schema.columns)?;
+    locks.table_meta.check_mutable(&self.req.table_name)?;
+    let schema = locks.table_meta.visible_schema();
+    let partition = pb_partition(&self.req.partition, &schema.partitioning)?;
+    let (mut rows, mut invalid_fields) = self.pb_rows(&schema.columns)?;
+    let dead_letter = server.runtime_config().await.dead_letter_invalid_rows;
+    if self.req.lenient || dead_letter {
+      invalid_fields.extend(Self::drop_computed_fields(&locks.table_meta, &mut rows));
+      invalid_fields.extend(common::drop_invalid_fields(&schema, &mut rows)?);
+    }
+    invalid_fields.sort_by(|a, b| (a.row_index, &a.col_name).cmp(&(b.row_index, &b.col_name)));
+    if !self.req.lenient && !dead_letter && !invalid_fields.is_empty() {
+      return Err(common::invalid_rows_error(&invalid_fields, rows.len()));
+    }
+
+    let mut dead_lettered_rows = Vec::new();
+    if dead_letter && !invalid_fields.is_empty() {
+      let row_errors = common::row_errors(&invalid_fields);
+      dead_lettered_rows = row_errors.iter().map(|row_error| row_error.row_index).collect();
+      let dead_letters = row_errors.into_iter()
+        .map(|row_error| Ok(DeadLetter {
+          raw_json: serde_json::to_string(&self.req.rows[row_error.row_index])?,
+          error: row_error.message,
+        }))
+        .collect::<ServerResult<Vec<_>>>()?;
+      server.write_dead_letters(&self.req.table_name, dead_letters).await?;
+      if !self.req.lenient {
+        // the rows went to the dead-letter table instead
+        let mut row_index = 0;
+        rows.retain(|_| {
+          let keep = dead_lettered_rows.binary_search(&row_index).is_err();
+          row_index += 1;
+          keep
+        });
+      }
+    }
+
+    let dropped_fields = if self.req.lenient {
+      invalid_fields
+    } else {
+      Vec::new()
+    };
+    let mut response = WriteToPartitionResponseSerde {
+      dropped_fields: dropped_fields.into_iter()
+        .map(|dropped| DroppedFieldSerde {
+          row_index: dropped.row_index,
+          column_name: dropped.col_name,
+          message: dropped.message,
+        })
+        .collect(),
+      dead_lettered_rows,
+      segment_id: None,
+      min_row_id: None,
+      max_row_id: None,
+    };
 
     let partition_key = PartitionKey {
       table_name: self.req.table_name.to_string(),
       partition: NormalizedPartition::from_raw_fields(&partition)?,
     };
 
+    if let Some(tx_id) = &self.req.tx_id {
+      partition_key.partition.check_against_schema(&schema)?;
+      common::validate_rows(&schema, &rows)?;
+      let max_rows = server.runtime_config().await.max_transaction_rows;
+      server.transaction_cache.add_rows(
+        tx_id,
+        &self.req.table_name,
+        &partition_key.partition,
+        partition,
+        rows,
+        max_rows,
+      ).await?;
+      return Ok(response);
+    }
+
     let partition_write_locks = PartitionWriteLocks::from_table_read(
       locks,
       &partition_key,
@@ -55,56 +129,150 @@ impl ServerOp for WriteToPartitionRestOp {
       partition,
       rows,
     };
-    WriteToPartitionOp { req }.execute_with_locks(server, partition_write_locks).await?;
-    Ok(EmptySerde {})
+    let written_rows = WriteToPartitionOp { req }.write(server, partition_write_locks).await?;
+    response.segment_id = Some(written_rows.segment_key.segment_id.to_string());
+    if let Some((min_row_id, max_row_id)) = written_rows.row_id_range {
+      response.min_row_id = Some(min_row_id);
+      response.max_row_id = Some(max_row_id);
+    }
+    Ok(response)
   }
 }
 
 impl WriteToPartitionRestOp {
-  fn pb_partition(&self, partitioning: &HashMap<String, PartitionMeta>) -> ServerResult<HashMap<String, PartitionFieldValue>> {
-    let mut partition = HashMap::new();
-
-    for (partition_name, partition_field) in &self.req.partition {
-      let dtype = match partitioning.get(partition_name) {
-        Some(meta) => PartitionDataType::from_i32(meta.dtype)
-          .ok_or(ServerError::internal("unknown dtype")),
-        None => Err(ServerError::invalid(format!(
-          "partition column {} does not exist",
-          partition_name,
-        ))),
-      }?;
-      partition.insert(partition_name.to_string(), parse_partition_field_value(partition_field, dtype)?);
-    }
-    Ok(partition)
-
-  }
-
-  fn pb_rows(&self, col_metas: &HashMap<String, ColumnMeta>) -> ServerResult<Vec<Row>> {
+  // Fields that can't be parsed are left out and returned along with the
+  // rows, which are otherwise in request order.
+  fn pb_rows(&self, col_metas: &HashMap<String, ColumnMeta>) -> ServerResult<(Vec<Row>, Vec<InvalidField>)> {
     let mut rows = Vec::new();
-    for row in &self.req.rows {
+    let mut invalid_fields = Vec::new();
+    for (row_index, row) in self.req.rows.iter().enumerate() {
       let mut pb_row: Row = Row::default();
       for (col_name, value) in row {
-        let col_meta = col_metas.get(col_name)
-          .ok_or(ServerError::invalid(format!(
+        let parsed = col_metas.get(col_name)
+          .ok_or_else(|| ServerError::invalid(format!(
             "column {} does not exist",
             col_name,
-          )))?;
+          )))
+          .and_then(|col_meta| {
+            let dtype = DataType::from_i32(col_meta.dtype)
+              .ok_or(ServerError::internal("unknown dtype"))?;
+            parse_field_value(value, dtype)
+          });
 
-        let dtype = DataType::from_i32(col_meta.dtype)
-          .ok_or(ServerError::internal("unknown dtype"))?;
-
-        let field_val = parse_field_value(value, dtype)?;
-        pb_row.fields.insert(col_name.to_string(), field_val);
+        match parsed {
+          Ok(field_val) => {
+            pb_row.fields.insert(col_name.to_string(), field_val);
+          },
+          Err(e) if matches!(e.kind, ServerErrorKind::Invalid) => invalid_fields.push(InvalidField {
+            row_index,
+            col_name: col_name.to_string(),
+            message: e.message().to_string(),
+          }),
+          Err(e) => return Err(e),
+        }
       }
       rows.push(pb_row);
     }
-    Ok(rows)
+    Ok((rows, invalid_fields))
+  }
+
+  fn drop_computed_fields(table_meta: &TableMetadata, rows: &mut [Row]) -> Vec<InvalidField> {
+    let mut res = Vec::new();
+    if table_meta.computed_columns.is_empty() {
+      return res;
+    }
+    for (row_index, row) in rows.iter_mut().enumerate() {
+      row.fields.retain(|col_name, _| {
+        let is_computed = table_meta.stored_column_name(col_name)
+          .map(|stored_name| table_meta.computed_columns.contains_key(&stored_name))
+          .unwrap_or(false);
+        if is_computed {
+          res.push(InvalidField {
+            row_index,
+            col_name: col_name.clone(),
+            message: format!("column {} is computed and can't be written", col_name),
+          });
+        }
+        !is_computed
+      });
+    }
+    res
   }
 }
 
-fn parse_timestamp(s: &str) -> ServerResult<Timestamp> {
-  let chrono_t = DateTime::parse_from_rfc3339(s)?;
-  Ok(Timestamp::from(SystemTime::from(chrono_t)))
+pub fn pb_partition(
+  json_partition: &HashMap<String, JsonValue>,
+  partitioning: &HashMap<String, PartitionMeta>,
+) -> ServerResult<HashMap<String, PartitionFieldValue>> {
+  let mut partition = HashMap::new();
+
+  for (partition_name, partition_field) in json_partition {
+    let dtype = match partitioning.get(partition_name) {
+      Some(meta) => PartitionDataType::from_i32(meta.dtype)
+        .ok_or(ServerError::internal("unknown dtype")),
+      None => Err(ServerError::invalid(format!(
+        "partition column {} does not exist",
+        partition_name,
+      ))),
+    }?;
+    partition.insert(partition_name.to_string(), parse_partition_field_value(partition_field, dtype)?);
+  }
+  Ok(partition)
+}
+
+pub fn partition_conditions(
+  filters: &[PartitionFilterSerde],
+  partitioning: &HashMap<String, PartitionMeta>,
+) -> ServerResult<Vec<PartitionCondition>> {
+  let mut conditions = Vec::with_capacity(filters.len());
+  for filter in filters {
+    let dtype = match partitioning.get(&filter.name) {
+      Some(meta) => common::unwrap_partition_dtype(meta.dtype)?,
+      None => return Err(ServerError::invalid(format!(
+        "partition column {} does not exist",
+        filter.name,
+      ))),
+    };
+    let mut values = Vec::with_capacity(filter.values.len());
+    for value in &filter.values {
+      values.push(parse_partition_field_value(value, dtype)?.value.unwrap());
+    }
+    let n_values = match filter.operator {
+      PartitionOperatorSerde::In => values.len(),
+      PartitionOperatorSerde::Between => 2,
+      _ => 1,
+    };
+    if values.len() != n_values {
+      return Err(ServerError::invalid(format!(
+        "{:?} filter on {} must have {} values",
+        filter.operator,
+        filter.name,
+        n_values,
+      )));
+    }
+    let operator = match filter.operator {
+      PartitionOperatorSerde::Eq => Operator::EqTo,
+      PartitionOperatorSerde::Lt => Operator::Less,
+      PartitionOperatorSerde::Le => Operator::LessOrEqTo,
+      PartitionOperatorSerde::Gt => Operator::Greater,
+      PartitionOperatorSerde::Ge => Operator::GreaterOrEqTo,
+      PartitionOperatorSerde::In => {
+        conditions.push(PartitionCondition::In { name: filter.name.clone(), values });
+        continue;
+      },
+      PartitionOperatorSerde::Between => {
+        let (min, max) = (values[0].clone(), values[1].clone());
+        conditions.push(PartitionCondition::Between { name: filter.name.clone(), min, max });
+        continue;
+      },
+    };
+    conditions.push(PartitionCondition::Comparison(PartitionFieldComparison {
+      name: filter.name.clone(),
+      operator: operator as i32,
+      value: Some(PartitionFieldValue { value: Some(values[0].clone()) }),
+    }));
+  }
+  Ok(conditions)
 }
 
 fn number_to_i64(n: &Number) -> ServerResult<i64> {
@@ -117,7 +285,7 @@ fn number_to_i64(n: &Number) -> ServerResult<i64> {
 fn parse_partition_field_value(json_value: &JsonValue, dtype: PartitionDataType) -> ServerResult<PartitionFieldValue> {
   let value = match (json_value, dtype) {
     (JsonValue::String(s), PartitionDataType::String) => Ok(PartitionValue::StringVal(s.to_string())),
-    (JsonValue::String(s), PartitionDataType::TimestampMinute) => Ok(PartitionValue::TimestampVal(parse_timestamp(s)?)),
+    (JsonValue::String(s), PartitionDataType::TimestampMinute) => Ok(PartitionValue::TimestampVal(common::parse_timestamp(s)?)),
     (JsonValue::Number(n), PartitionDataType::Int64) => Ok(PartitionValue::Int64Val(number_to_i64(n)?)),
     (JsonValue::Bool(b), PartitionDataType::Bool) => Ok(PartitionValue::BoolVal(*b)),
     _ => Err(ServerError::invalid(format!(
@@ -130,14 +298,14 @@ fn parse_partition_field_value(json_value: &JsonValue, dtype: PartitionDataType)
   Ok(PartitionFieldValue { value: Some(value) })
 }
 
-fn parse_field_value(json_value: &JsonValue, dtype: DataType) -> ServerResult<FieldValue> {
+pub fn parse_field_value(json_value: &JsonValue, dtype: DataType) -> ServerResult<FieldValue> {
   if json_value.is_null() {
     return Ok(FieldValue::default());
   }
 
   let value = match (json_value, dtype) {
     (JsonValue::String(s), DataType::String) => Ok(Value::StringVal(s.to_string())),
-    (JsonValue::String(s), DataType::TimestampMicros) => Ok(Value::TimestampVal(parse_timestamp(s)?)),
+    (JsonValue::String(s), DataType::TimestampMicros) => Ok(Value::TimestampVal(common::parse_timestamp(s)?)),
     (JsonValue::String(s), DataType::Bytes) => {
       let bytes = base64::decode(s).map_err(|_|
         ServerError::invalid(format!(
@@ -168,6 +336,33 @@ fn parse_field_value(json_value: &JsonValue, dtype: DataType) -> ServerResult<Fi
   Ok(FieldValue { value: Some(value) })
 }
 
+// the inverse of parse_field_value
+pub fn field_value_to_json(field_value: &FieldValue) -> ServerResult<JsonValue> {
+  let res = match &field_value.value {
+    None => JsonValue::Null,
+    Some(Value::StringVal(x)) => JsonValue::String(x.clone()),
+    Some(Value::Int64Val(x)) => JsonValue::from(*x),
+    Some(Value::BoolVal(x)) => JsonValue::Bool(*x),
+    Some(Value::BytesVal(x)) => JsonValue::String(base64::encode(x)),
+    Some(Value::Float32Val(x)) => JsonValue::from(*x),
+    Some(Value::Float64Val(x)) => JsonValue::from(*x),
+    Some(Value::TimestampVal(x)) => {
+      let t = Utc.timestamp_opt(x.seconds, x.nanos as u32)
+        .single()
+        .ok_or_else(|| ServerError::invalid(format!(
+          "timestamp {}s {}ns is out of range",
+          x.seconds,
+          x.nanos,
+        )))?;
+      JsonValue::String(t.to_rfc3339_opts(SecondsFormat::Micros, true))
+    },
+    Some(Value::ListVal(x)) => JsonValue::Array(
+      x.vals.iter().map(field_value_to_json).collect::<ServerResult<_>>()?
+    ),
+  };
+  Ok(res)
+}
+
 impl RestRoute for WriteToPartitionRestOp {
   type Req = WriteToPartitionRequestSerde;
 
@@ -176,4 +371,8 @@ impl RestRoute for WriteToPartitionRestOp {
   fn new_op(req: Self::Req) -> WriteToPartitionRestOp {
     WriteToPartitionRestOp { req }
   }
+
+  fn request_row_count(req: &Self::Req) -> usize {
+    req.rows.len()
+  }
 }
@@ -4,10 +4,24 @@ use std::str::FromStr;
 use log::LevelFilter;
 use structopt::StructOpt;
 
+use crate::constants::ENCRYPTION_KEY_ENV_VAR_PREFIX;
 use crate::errors::ServerError;
+use crate::metadata::table::TableConfigOverrides;
 use crate::ServerResult;
+use crate::utils::durability::FsyncPolicy;
+use crate::utils::names::NameNormalization;
+use crate::utils::schedule::HourWindow;
+use crate::utils::vfs;
 
 const MIN_DIR_LEN: usize = 5;
+const ENV_VAR_PREFIX: &str = "PANCAKE_";
+const CONFIG_ENV_VAR: &str = "PANCAKE_CONFIG";
+
+// the name of a --name or --name=value arg
+fn arg_name(arg_str: &str) -> Option<&str> {
+  arg_str.strip_prefix("--")
+    .and_then(|name_and_value| name_and_value.split('=').next())
+}
 
 #[derive(Clone, Debug, StructOpt)]
 #[structopt(name = "PancakeDB Server")]
@@ -22,12 +36,90 @@ pub struct Opt {
   #[structopt(long, default_value = "3842")]
   pub grpc_port: u16,
 
+  // if set, serve both HTTP and GRPC on this one port instead of
+  // http_port and grpc_port, telling GRPC requests apart by content type
+  #[structopt(long)]
+  pub port: Option<u16>,
+
+  // Origins whose browser pages may call the HTTP and grpc-web APIs, comma
+  // separated, or * for any. Browsers send their origin with grpc-web calls
+  // even to the same origin, so grpc-web pages' origins must be listed.
+  // Once any are listed, browser requests from unlisted origins are
+  // refused, so list the console's own origin too if it's used.
+  #[structopt(long, use_delimiter = true)]
+  pub cors_allowed_origins: Vec<String>,
+
+  // if set, also serve a read-only Postgres wire protocol frontend on this
+  // port, so that SQL clients can run simple SELECTs
+  #[structopt(long)]
+  pub pg_port: Option<u16>,
+
   #[structopt(long, default_value = "INFO")]
   pub log_level: LevelFilter,
 
+  // If set, a TOML file of API keys and the roles they grant, and every
+  // request is checked against it. Without it, every request may do
+  // anything. The file is re-read whenever the config reloads.
+  #[structopt(long)]
+  pub authz_file: Option<PathBuf>,
+
+  // If set, the flush and compacted column files and zone maps of new
+  // segment versions are encrypted with AES-256-GCM under the key of this
+  // id. Older versions keep the key they were written with until they are
+  // compacted again, so rotating keys means keeping old keys available.
+  #[structopt(long)]
+  pub encryption_key_id: Option<String>,
+
+  // Keys are looked up by id, first in the PANCAKE_DB_ENCRYPTION_KEY_<ID>
+  // environment variable, then in this TOML file of ids to keys, then by
+  // running the KMS command with the id as its argument. Keys are 32
+  // base64-encoded bytes.
+  #[structopt(long)]
+  pub encryption_keys_file: Option<PathBuf>,
+
+  #[structopt(long)]
+  pub encryption_kms_command: Option<PathBuf>,
+
   #[structopt(flatten)]
   pub cloud_opts: CloudOpt,
 
+  // Serve reads from a dir that another server writes to, rejecting writes
+  // and running no recovery, flushes, or compactions of its own. Reads may
+  // lag the writer by up to replica_refresh_seconds.
+  #[structopt(long, parse(try_from_str), default_value = "false")]
+  pub read_only: bool,
+
+  // how often a read-only server reloads the metadata it has cached
+  #[structopt(long, default_value = "10")]
+  pub replica_refresh_seconds: u64,
+
+  // if set, continually copy dir to this warm standby dir (which may be a
+  // network mount), so that a server can take over on it
+  #[structopt(long)]
+  pub standby_dir: Option<PathBuf>,
+
+  // how often to copy changes to the standby dir
+  #[structopt(long, default_value = "10")]
+  pub standby_ship_seconds: u64,
+
+  // If above 0, each partition field's directory is nested under one of
+  // this many hashed bucket directories, so that tables with many partitions
+  // don't put them all under one parent. Changing it moves existing
+  // partitions into the new layout at startup.
+  #[structopt(long, default_value = "0")]
+  pub partition_dir_fanout: u32,
+
+  // If false, the server refuses to start on a dir written in an older
+  // format major version instead of migrating it.
+  #[structopt(long, parse(try_from_str), default_value = "true")]
+  pub upgrade_format: bool,
+
+  // Check whether the dir needs a format upgrade and whether its
+  // migrations' pre-checks pass, then exit without changing or serving
+  // anything.
+  #[structopt(long, parse(try_from_str), default_value = "false")]
+  pub upgrade_format_dry_run: bool,
+
   // Segments should complete shortly after reaching either the target
   // number of rows or the target uncompressed size (whichever comes first).
   #[structopt(long, default_value = "5000000")]
@@ -36,11 +128,20 @@ pub struct Opt {
   #[structopt(long, default_value = "130000000")] // just under 128MB
   pub target_uncompressed_bytes_per_segment: u64,
 
+  // how many segments each partition writes to concurrently; raising this
+  // scales write throughput to a single partition
+  #[structopt(long, default_value = "1")]
+  pub active_segments_per_partition: u32,
+
   // the fewest number of rows in a segment before compaction
   // will be considered
   #[structopt(long, default_value = "30000")]
   pub min_rows_for_compaction: u32,
 
+  // how often the background loop will flush segments with staged rows
+  #[structopt(long, default_value = "10")]
+  pub flush_loop_seconds: u64,
+
   // how often the background loop will check each partition and
   // see if it needs compaction
   #[structopt(long, default_value = "10")]
@@ -62,8 +163,194 @@ pub struct Opt {
   #[structopt(long, default_value = "1800")]
   pub compact_as_constant_seconds: i64,
 
+  // how many times a segment version may be compacted incrementally,
+  // compressing only its new rows into another block, before the next
+  // compaction rewrites it in full; 0 always rewrites in full
+  #[structopt(long, default_value = "0")]
+  pub max_compaction_increments: u32,
+
+  // If set, the UTC hours (like 2-6) during which compactions may rewrite
+  // a segment's compacted data in full. Outside them, only a segment's
+  // first compaction and incremental ones run.
+  #[structopt(long)]
+  pub full_compaction_hours: Option<HourWindow>,
+
+  // If set, the most bytes of column data (measured uncompressed) that
+  // compactions may process per second, leaving disk bandwidth for reads
+  // and writes
+  #[structopt(long)]
+  pub compaction_bytes_per_second: Option<u64>,
+
+  // how long a cold segment with every row deleted must go without
+  // flushes before we garbage collect its files and metadata
+  #[structopt(long, default_value = "7200")]
+  pub gc_fully_deleted_segment_seconds: i64,
+
+  // how long a segment with fewer live rows than min_rows_for_compaction
+  // must go without flushes before we merge it with other such segments
+  // in its partition
+  #[structopt(long, default_value = "3600")]
+  pub merge_small_segment_seconds: i64,
+
+  // Disk usage limits, in bytes, for each table and for all tables together.
+  // Going over a soft limit logs a warning, and going over a hard limit
+  // rejects writes until usage drops below it.
+  #[structopt(long)]
+  pub table_disk_soft_limit_bytes: Option<u64>,
+
+  #[structopt(long)]
+  pub table_disk_hard_limit_bytes: Option<u64>,
+
+  #[structopt(long)]
+  pub global_disk_soft_limit_bytes: Option<u64>,
+
+  #[structopt(long)]
+  pub global_disk_hard_limit_bytes: Option<u64>,
+
+  // how long dropped tables are kept in the trash, where they can be
+  // undropped, before being removed; 0 removes them right away
+  #[structopt(long, default_value = "86400")]
+  pub trash_retention_seconds: i64,
+
+  // how old a file in the tmp dir must be before we consider it orphaned
+  // by an interrupted atomic overwrite and remove it
+  #[structopt(long, default_value = "600")]
+  pub orphaned_tmp_file_seconds: u64,
+
+  // how often the background loop will look for orphaned tmp files
+  #[structopt(long, default_value = "600")]
+  pub janitor_loop_seconds: u64,
+
+  // how long, in microseconds, the first write to a partition waits for
+  // others to write along with it; 0 writes each request on its own
+  #[structopt(long, default_value = "0")]
+  pub group_commit_window_micros: u64,
+
+  // When to fsync writes and metadata overwrites: never, leaving it to the
+  // OS; always, before each returns; or every fsync_interval_millis.
+  #[structopt(long, default_value = "never")]
+  pub fsync_policy: FsyncPolicy,
+
+  #[structopt(long, default_value = "1000")]
+  pub fsync_interval_millis: u64,
+
+  // How table, column, and partition names in requests are normalized:
+  // none, matching them exactly, or lowercase, matching them regardless of
+  // case. The server refuses to start with lowercase if any existing names
+  // aren't lowercase.
+  #[structopt(long, default_value = "none")]
+  pub name_normalization: NameNormalization,
+
   #[structopt(long, default_value = "2097152")]
   pub read_page_byte_size: usize,
+
+  // the most bytes of read pages to hold in memory, read ahead of readers
+  // asking for them; 0 disables prefetching
+  #[structopt(long, default_value = "67108864")]
+  pub read_prefetch_bytes: usize,
+
+  // the most entries each metadata cache will hold before evicting the least
+  // recently used ones; entries in use are never evicted
+  #[structopt(long, default_value = "16384")]
+  pub table_cache_size: usize,
+
+  #[structopt(long, default_value = "65536")]
+  pub partition_cache_size: usize,
+
+  #[structopt(long, default_value = "65536")]
+  pub segment_cache_size: usize,
+
+  #[structopt(long, default_value = "65536")]
+  pub compaction_cache_size: usize,
+
+  #[structopt(long, default_value = "16384")]
+  pub deletion_cache_size: usize,
+
+  // the most reads (correlation ids) that may pin a segment's read version
+  // at once
+  #[structopt(long, default_value = "16384")]
+  pub correlation_cache_size: usize,
+
+  // how long a read pins its segment's read version after its most recent
+  // request, unless it ends sooner
+  #[structopt(long, default_value = "600")]
+  pub correlation_ttl_seconds: i64,
+
+  // the most transactions that may be open at once
+  #[structopt(long, default_value = "1024")]
+  pub transaction_cache_size: usize,
+
+  // how long an open transaction lives after its most recent write before
+  // it is aborted
+  #[structopt(long, default_value = "300")]
+  pub transaction_ttl_seconds: i64,
+
+  // the most rows a single transaction may buffer before committing
+  #[structopt(long, default_value = "65536")]
+  pub max_transaction_rows: usize,
+
+  // the most bytes a REST request body or GRPC message may have
+  #[structopt(long, default_value = "67108864")]
+  pub max_request_bytes: usize,
+
+  // the most rows a single write request may contain
+  #[structopt(long, default_value = "65536")]
+  pub max_request_rows: usize,
+
+  // the most REST and GRPC requests handled at once; more are rejected
+  // with a hint of when to retry
+  #[structopt(long, default_value = "1024")]
+  pub max_concurrent_requests: usize,
+
+  // If set, how many requests each principal may make per second, in
+  // bursts of up to that many. Anonymous requests share one budget.
+  #[structopt(long)]
+  pub max_requests_per_second: Option<u32>,
+
+  // ops taking at least this long are logged with their lock waits and
+  // bytes processed; 0 disables the slow op log
+  #[structopt(long, default_value = "1000")]
+  pub slow_op_millis: u64,
+
+  // if set, slow ops are also written to this table, created if needed
+  #[structopt(long)]
+  pub slow_op_table: Option<String>,
+
+  // whether to write each flush and compaction to the _pancake_ops table,
+  // created if needed
+  #[structopt(long, parse(try_from_str), default_value = "false")]
+  pub record_ops_history: bool,
+
+  // how long ops run for clients may take before they're cancelled at
+  // their next safe point; 0 means no limit
+  #[structopt(long, default_value = "300")]
+  pub op_timeout_seconds: u64,
+
+  // the same for flushes, compactions, and other background maintenance
+  #[structopt(long, default_value = "3600")]
+  pub background_op_timeout_seconds: u64,
+
+  // Instead of rejecting REST writes with invalid rows, write the valid rows
+  // and copy each invalid row's raw JSON and errors into the table's
+  // dead-letter table, creating it if needed. Rows of lenient writes that
+  // lost fields are copied there too.
+  #[structopt(long, parse(try_from_str), default_value = "false")]
+  pub dead_letter_invalid_rows: bool,
+
+  // whether to verify the checksum of a whole compacted column file
+  // before serving or recompacting it
+  #[structopt(long, parse(try_from_str), default_value = "false")]
+  pub verify_checksums_on_read: bool,
+
+  // whether to verify the checksums of every segment's compacted column
+  // files during startup recovery
+  #[structopt(long, parse(try_from_str), default_value = "false")]
+  pub verify_checksums_on_recovery: bool,
+
+  // For soak tests only: randomly delay lock waits, fail file IO, and kill
+  // background loop iterations once the server has started.
+  #[structopt(long, hidden = true, parse(try_from_str), default_value = "false")]
+  pub chaos: bool,
 }
 
 #[derive(Clone, Copy, Debug, StructOpt)]
@@ -98,28 +385,47 @@ impl FromStr for CloudProvider {
 
 impl Opt {
   pub fn validate(&self) {
-    let dir_maybe_str = self.dir
-      .canonicalize()
-      .expect("unable to canonicalize dir - make sure it exists");
+    // an in-memory dir exists only to the server, not the OS
+    let dir_maybe_str = if vfs::is_in_memory(&self.dir) {
+      self.dir.clone()
+    } else {
+      self.dir
+        .canonicalize()
+        .expect("unable to canonicalize dir - make sure it exists")
+    };
     let dir_str = dir_maybe_str
       .to_str()
       .expect("dir was not a valid string");
     if dir_str.len() < MIN_DIR_LEN {
       panic!("suspiciously short length for dir; please choose a more specific path")
     }
+
+    if let Some(standby_dir) = &self.standby_dir {
+      if self.read_only {
+        panic!("a read-only server can't ship to a standby dir");
+      }
+      std::fs::create_dir_all(standby_dir)
+        .expect("unable to create standby dir");
+      let standby_dir = standby_dir
+        .canonicalize()
+        .expect("unable to canonicalize standby dir");
+      if standby_dir.starts_with(&dir_maybe_str) || dir_maybe_str.starts_with(&standby_dir) {
+        panic!("standby dir must not overlap with dir");
+      }
+    }
   }
 
-  pub fn from_waterfall() -> Self {
-    let maybe_config_path = std::env::var("PANCAKE_CONFIG");
+  fn config_arg_strs() -> ServerResult<Vec<String>> {
+    let maybe_config_path = std::env::var(CONFIG_ENV_VAR);
     let conf_arg_strs = match maybe_config_path {
       Ok(config_path) => {
         log::info!("loading some args from {}", config_path);
-        let toml_bytes = std::fs::read(config_path)
-          .expect("could not read config file");
+        let toml_bytes = std::fs::read(&config_path)
+          .map_err(|e| ServerError::invalid(format!("could not read config file {}: {}", config_path, e)))?;
         let toml_str = String::from_utf8(toml_bytes)
-          .expect("non-utf8 config file");
+          .map_err(|_| ServerError::invalid("non-utf8 config file"))?;
         let config = toml_str.parse::<toml::Value>()
-          .expect("config file contains invalid TOML");
+          .map_err(|e| ServerError::invalid(format!("config file contains invalid TOML: {}", e)))?;
         match config {
           toml::Value::Table(table) => {
             table.iter()
@@ -129,24 +435,254 @@ impl Opt {
                   toml::Value::Boolean(b) => b.to_string(),
                   toml::Value::Integer(i) => i.to_string(),
                   toml::Value::Float(f) => f.to_string(),
-                  _ => panic!("no nested TOML config values allowed")
+                  _ => return Err(ServerError::invalid("no nested TOML config values allowed"))
                 };
-                format!(
+                Ok(format!(
                   "--{}={}",
                   key,
                   val_str,
-                )
+                ))
               })
-              .collect::<Vec<_>>()
+              .collect::<ServerResult<Vec<_>>>()?
           },
-          _ => panic!("config file TOML must be a table")
+          _ => return Err(ServerError::invalid("config file TOML must be a table"))
         }
       },
       Err(_) => vec![]
     };
-    let cmd_arg_strs: Vec<_> = std::env::args().collect();
-    let arg_strs = cmd_arg_strs.iter().chain(&conf_arg_strs).collect::<Vec<_>>();
+    Ok(conf_arg_strs)
+  }
 
+  // PANCAKE_HTTP_PORT=3000 becomes --http-port=3000, and so on for every
+  // arg, skipping the PANCAKE_* variables that mean something else
+  fn env_arg_strs() -> Vec<String> {
+    std::env::vars()
+      .filter(|(key, _)| key != CONFIG_ENV_VAR && !key.starts_with(ENCRYPTION_KEY_ENV_VAR_PREFIX))
+      .filter_map(|(key, val)| {
+        let arg_name = key.strip_prefix(ENV_VAR_PREFIX)?
+          .to_lowercase()
+          .replace('_', "-");
+        Some(format!("--{}={}", arg_name, val))
+      })
+      .collect()
+  }
+
+  // Args come from the command line, then PANCAKE_* environment variables,
+  // then the PANCAKE_CONFIG file, each taking precedence over the next.
+  fn waterfall_arg_strs() -> ServerResult<Vec<String>> {
+    let mut arg_strs: Vec<_> = std::env::args().collect();
+    for arg_str in Self::env_arg_strs().into_iter().chain(Self::config_arg_strs()?) {
+      let is_set = arg_strs.iter()
+        .any(|existing| arg_name(existing).is_some() && arg_name(existing) == arg_name(&arg_str));
+      if !is_set {
+        arg_strs.push(arg_str);
+      }
+    }
+    Ok(arg_strs)
+  }
+
+  pub fn from_waterfall() -> Self {
+    let arg_strs = Self::waterfall_arg_strs()
+      .unwrap_or_else(|e| panic!("{}", e));
     Self::from_iter(arg_strs)
   }
+
+  pub fn apply_runtime_config(&mut self, config: &RuntimeConfig) {
+    self.log_level = config.log_level;
+    self.target_rows_per_segment = config.target_rows_per_segment;
+    self.target_uncompressed_bytes_per_segment = config.target_uncompressed_bytes_per_segment;
+    self.active_segments_per_partition = config.active_segments_per_partition;
+    self.min_rows_for_compaction = config.min_rows_for_compaction;
+    self.flush_loop_seconds = config.flush_loop_seconds;
+    self.compaction_loop_seconds = config.compaction_loop_seconds;
+    self.delete_stale_compaction_seconds = config.delete_stale_compaction_seconds;
+    self.min_compaction_intermission_seconds = config.min_compaction_intermission_seconds;
+    self.compact_as_constant_seconds = config.compact_as_constant_seconds;
+    self.max_compaction_increments = config.max_compaction_increments;
+    self.full_compaction_hours = config.full_compaction_hours;
+    self.compaction_bytes_per_second = config.compaction_bytes_per_second;
+    self.gc_fully_deleted_segment_seconds = config.gc_fully_deleted_segment_seconds;
+    self.merge_small_segment_seconds = config.merge_small_segment_seconds;
+    self.table_disk_soft_limit_bytes = config.table_disk_soft_limit_bytes;
+    self.table_disk_hard_limit_bytes = config.table_disk_hard_limit_bytes;
+    self.global_disk_soft_limit_bytes = config.global_disk_soft_limit_bytes;
+    self.global_disk_hard_limit_bytes = config.global_disk_hard_limit_bytes;
+    self.trash_retention_seconds = config.trash_retention_seconds;
+    self.max_request_bytes = config.max_request_bytes;
+    self.max_request_rows = config.max_request_rows;
+    self.max_concurrent_requests = config.max_concurrent_requests;
+    self.max_requests_per_second = config.max_requests_per_second;
+    self.slow_op_millis = config.slow_op_millis;
+    self.slow_op_table = config.slow_op_table.clone();
+    self.record_ops_history = config.record_ops_history;
+    self.op_timeout_seconds = config.op_timeout_seconds;
+    self.background_op_timeout_seconds = config.background_op_timeout_seconds;
+    self.dead_letter_invalid_rows = config.dead_letter_invalid_rows;
+    self.read_page_byte_size = config.read_page_byte_size;
+    self.verify_checksums_on_read = config.verify_checksums_on_read;
+  }
+
+  // like from_waterfall, but returns an error instead of exiting the
+  // process on invalid args, so the config can be reloaded while running
+  pub fn try_from_waterfall() -> ServerResult<Self> {
+    let arg_strs = Self::waterfall_arg_strs()?;
+    Self::from_iter_safe(arg_strs)
+      .map_err(|e| ServerError::invalid(e.message))
+  }
+}
+
+// The subset of options that can change while the server is running.
+// Everything else in Opt requires a restart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeConfig {
+  pub log_level: LevelFilter,
+  pub target_rows_per_segment: u32,
+  pub target_uncompressed_bytes_per_segment: u64,
+  pub active_segments_per_partition: u32,
+  pub min_rows_for_compaction: u32,
+  pub flush_loop_seconds: u64,
+  pub compaction_loop_seconds: u64,
+  pub delete_stale_compaction_seconds: i64,
+  pub min_compaction_intermission_seconds: i64,
+  pub compact_as_constant_seconds: i64,
+  pub max_compaction_increments: u32,
+  pub full_compaction_hours: Option<HourWindow>,
+  pub compaction_bytes_per_second: Option<u64>,
+  pub gc_fully_deleted_segment_seconds: i64,
+  pub merge_small_segment_seconds: i64,
+  pub table_disk_soft_limit_bytes: Option<u64>,
+  pub table_disk_hard_limit_bytes: Option<u64>,
+  pub global_disk_soft_limit_bytes: Option<u64>,
+  pub global_disk_hard_limit_bytes: Option<u64>,
+  pub trash_retention_seconds: i64,
+  pub correlation_ttl_seconds: i64,
+  pub transaction_ttl_seconds: i64,
+  pub max_transaction_rows: usize,
+  pub max_request_bytes: usize,
+  pub max_request_rows: usize,
+  pub max_concurrent_requests: usize,
+  pub max_requests_per_second: Option<u32>,
+  pub slow_op_millis: u64,
+  pub slow_op_table: Option<String>,
+  pub record_ops_history: bool,
+  pub op_timeout_seconds: u64,
+  pub background_op_timeout_seconds: u64,
+  pub dead_letter_invalid_rows: bool,
+  pub read_page_byte_size: usize,
+  pub verify_checksums_on_read: bool,
+}
+
+impl From<&Opt> for RuntimeConfig {
+  fn from(opts: &Opt) -> Self {
+    RuntimeConfig {
+      log_level: opts.log_level,
+      target_rows_per_segment: opts.target_rows_per_segment,
+      target_uncompressed_bytes_per_segment: opts.target_uncompressed_bytes_per_segment,
+      active_segments_per_partition: opts.active_segments_per_partition,
+      min_rows_for_compaction: opts.min_rows_for_compaction,
+      flush_loop_seconds: opts.flush_loop_seconds,
+      compaction_loop_seconds: opts.compaction_loop_seconds,
+      delete_stale_compaction_seconds: opts.delete_stale_compaction_seconds,
+      min_compaction_intermission_seconds: opts.min_compaction_intermission_seconds,
+      compact_as_constant_seconds: opts.compact_as_constant_seconds,
+      max_compaction_increments: opts.max_compaction_increments,
+      full_compaction_hours: opts.full_compaction_hours,
+      compaction_bytes_per_second: opts.compaction_bytes_per_second,
+      gc_fully_deleted_segment_seconds: opts.gc_fully_deleted_segment_seconds,
+      merge_small_segment_seconds: opts.merge_small_segment_seconds,
+      table_disk_soft_limit_bytes: opts.table_disk_soft_limit_bytes,
+      table_disk_hard_limit_bytes: opts.table_disk_hard_limit_bytes,
+      global_disk_soft_limit_bytes: opts.global_disk_soft_limit_bytes,
+      global_disk_hard_limit_bytes: opts.global_disk_hard_limit_bytes,
+      trash_retention_seconds: opts.trash_retention_seconds,
+      correlation_ttl_seconds: opts.correlation_ttl_seconds,
+      transaction_ttl_seconds: opts.transaction_ttl_seconds,
+      max_transaction_rows: opts.max_transaction_rows,
+      max_request_bytes: opts.max_request_bytes,
+      max_request_rows: opts.max_request_rows,
+      max_concurrent_requests: opts.max_concurrent_requests,
+      max_requests_per_second: opts.max_requests_per_second,
+      slow_op_millis: opts.slow_op_millis,
+      slow_op_table: opts.slow_op_table.clone(),
+      record_ops_history: opts.record_ops_history,
+      op_timeout_seconds: opts.op_timeout_seconds,
+      background_op_timeout_seconds: opts.background_op_timeout_seconds,
+      dead_letter_invalid_rows: opts.dead_letter_invalid_rows,
+      read_page_byte_size: opts.read_page_byte_size,
+      verify_checksums_on_read: opts.verify_checksums_on_read,
+    }
+  }
+}
+
+impl RuntimeConfig {
+  // the config as it applies to one table
+  pub fn with_overrides(self, overrides: &TableConfigOverrides) -> Self {
+    RuntimeConfig {
+      target_rows_per_segment: overrides.target_rows_per_segment
+        .unwrap_or(self.target_rows_per_segment),
+      target_uncompressed_bytes_per_segment: overrides.target_uncompressed_bytes_per_segment
+        .unwrap_or(self.target_uncompressed_bytes_per_segment),
+      min_rows_for_compaction: overrides.min_rows_for_compaction
+        .unwrap_or(self.min_rows_for_compaction),
+      min_compaction_intermission_seconds: overrides.min_compaction_intermission_seconds
+        .unwrap_or(self.min_compaction_intermission_seconds),
+      compact_as_constant_seconds: overrides.compact_as_constant_seconds
+        .unwrap_or(self.compact_as_constant_seconds),
+      max_compaction_increments: overrides.max_compaction_increments
+        .unwrap_or(self.max_compaction_increments),
+      read_page_byte_size: overrides.read_page_byte_size
+        .unwrap_or(self.read_page_byte_size),
+      ..self
+    }
+  }
+
+  pub fn changed_fields(&self, other: &RuntimeConfig) -> Vec<&'static str> {
+    let mut res = Vec::new();
+    macro_rules! check_fields {
+      ($($field: ident),*) => {
+        $(
+          if self.$field != other.$field {
+            res.push(stringify!($field));
+          }
+        )*
+      }
+    }
+    check_fields!(
+      log_level,
+      target_rows_per_segment,
+      target_uncompressed_bytes_per_segment,
+      active_segments_per_partition,
+      min_rows_for_compaction,
+      compaction_loop_seconds,
+      delete_stale_compaction_seconds,
+      min_compaction_intermission_seconds,
+      compact_as_constant_seconds,
+      max_compaction_increments,
+      full_compaction_hours,
+      compaction_bytes_per_second,
+      gc_fully_deleted_segment_seconds,
+      merge_small_segment_seconds,
+      table_disk_soft_limit_bytes,
+      table_disk_hard_limit_bytes,
+      global_disk_soft_limit_bytes,
+      global_disk_hard_limit_bytes,
+      trash_retention_seconds,
+      correlation_ttl_seconds,
+      transaction_ttl_seconds,
+      max_transaction_rows,
+      max_request_bytes,
+      max_request_rows,
+      max_concurrent_requests,
+      max_requests_per_second,
+      slow_op_millis,
+      slow_op_table,
+      record_ops_history,
+      op_timeout_seconds,
+      background_op_timeout_seconds,
+      dead_letter_invalid_rows,
+      read_page_byte_size,
+      verify_checksums_on_read
+    );
+    res
+  }
 }
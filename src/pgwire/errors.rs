@@ -0,0 +1,75 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::errors::{ServerError, ServerErrorKind};
+
+// SQLSTATE codes
+pub const SYNTAX_ERROR: &str = "42601";
+pub const UNDEFINED_TABLE: &str = "42P01";
+pub const UNDEFINED_COLUMN: &str = "42703";
+pub const UNDEFINED_FUNCTION: &str = "42883";
+pub const UNDEFINED_OBJECT: &str = "42704";
+pub const DATATYPE_MISMATCH: &str = "42804";
+pub const FEATURE_NOT_SUPPORTED: &str = "0A000";
+pub const PROTOCOL_VIOLATION: &str = "08P01";
+pub const INVALID_PARAMETER_VALUE: &str = "22023";
+pub const DATETIME_FIELD_OVERFLOW: &str = "22008";
+pub const READ_ONLY_SQL_TRANSACTION: &str = "25006";
+pub const INVALID_PASSWORD: &str = "28P01";
+pub const INSUFFICIENT_PRIVILEGE: &str = "42501";
+pub const QUERY_CANCELED: &str = "57014";
+pub const SERIALIZATION_FAILURE: &str = "40001";
+pub const INSUFFICIENT_RESOURCES: &str = "53000";
+pub const DISK_FULL: &str = "53100";
+pub const INTERNAL_ERROR: &str = "XX000";
+pub const DATA_CORRUPTED: &str = "XX001";
+
+#[derive(Clone, Debug)]
+pub struct PgError {
+  pub code: &'static str,
+  pub message: String,
+}
+
+impl PgError {
+  pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+    PgError {
+      code,
+      message: message.into(),
+    }
+  }
+
+  pub fn syntax(message: impl Into<String>) -> Self {
+    Self::new(SYNTAX_ERROR, message)
+  }
+
+  pub fn unsupported(message: impl Into<String>) -> Self {
+    Self::new(FEATURE_NOT_SUPPORTED, message)
+  }
+}
+
+impl Display for PgError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({})", self.message, self.code)
+  }
+}
+
+impl From<ServerError> for PgError {
+  fn from(e: ServerError) -> Self {
+    let code = match e.kind {
+      ServerErrorKind::DoesNotExist => UNDEFINED_TABLE,
+      ServerErrorKind::Invalid => INVALID_PARAMETER_VALUE,
+      ServerErrorKind::ReadOnly => READ_ONLY_SQL_TRANSACTION,
+      ServerErrorKind::Unauthenticated => INVALID_PASSWORD,
+      ServerErrorKind::PermissionDenied => INSUFFICIENT_PRIVILEGE,
+      ServerErrorKind::TooManyRequests | ServerErrorKind::TooLarge => INSUFFICIENT_RESOURCES,
+      ServerErrorKind::QuotaExceeded => DISK_FULL,
+      ServerErrorKind::Cancelled | ServerErrorKind::TimedOut => QUERY_CANCELED,
+      ServerErrorKind::Aborted => SERIALIZATION_FAILURE,
+      ServerErrorKind::Corrupt | ServerErrorKind::ChecksumMismatch => DATA_CORRUPTED,
+      ServerErrorKind::Internal => INTERNAL_ERROR,
+    };
+    PgError::new(code, e.to_client_string())
+  }
+}
+
+pub type PgResult<T> = Result<T, PgError>;
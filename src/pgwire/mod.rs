@@ -0,0 +1,174 @@
+use std::net::SocketAddr;
+
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::errors::ServerResult;
+use crate::Server;
+use crate::server::authz::{self, Principal};
+
+use self::errors::{INVALID_PASSWORD, PROTOCOL_VIOLATION};
+use self::protocol::{Connection, Startup};
+use self::query::QueryResponse;
+
+mod errors;
+mod protocol;
+mod query;
+mod sql;
+mod types;
+
+// A read-only Postgres wire protocol frontend. Only the simple query
+// protocol is supported, with no TLS, and queries are limited to what the
+// sql module parses. When authz is enabled, the password is an API key.
+pub async fn serve(server: Server, port: u16) -> ServerResult<()> {
+  let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port))).await?;
+  log::info!("bound Postgres listener to port {}", port);
+  loop {
+    let (stream, addr) = listener.accept().await?;
+    let server = server.clone();
+    tokio::spawn(async move {
+      if let Err(e) = handle_connection(&server, stream).await {
+        log::debug!("Postgres connection from {} ended with error: {}", addr, e);
+      }
+    });
+  }
+}
+
+async fn handle_connection(server: &Server, stream: TcpStream) -> std::io::Result<()> {
+  let mut conn = Connection::new(stream);
+  let params = match conn.read_startup().await? {
+    Startup::Params(params) => params,
+    // there's nothing to cancel, since we process queries to completion
+    Startup::Cancel => return Ok(()),
+  };
+  log::debug!(
+    "accepted Postgres connection for user {:?} to database {:?}",
+    params.get("user"),
+    params.get("database"),
+  );
+
+  let principal = match authenticate(server, &mut conn).await? {
+    Some(principal) => principal,
+    None => return Ok(()),
+  };
+  conn.write_authentication_ok().await?;
+  for (name, value) in &[
+    ("server_version", "14.0"),
+    ("server_encoding", "UTF8"),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, MDY"),
+    ("TimeZone", "UTC"),
+    ("integer_datetimes", "on"),
+    ("standard_conforming_strings", "on"),
+  ] {
+    conn.write_parameter_status(name, value).await?;
+  }
+  conn.write_backend_key_data(std::process::id() as i32, rand::random()).await?;
+  conn.write_ready_for_query().await?;
+  conn.flush().await?;
+
+  authz::scope(principal, serve_queries(server, &mut conn)).await
+}
+
+// Asks for a password when authz is enabled, treating an empty one as
+// anonymous. Returns None if authentication failed.
+async fn authenticate(server: &Server, conn: &mut Connection) -> std::io::Result<Option<Principal>> {
+  if !server.is_authz_enabled() {
+    return Ok(Some(Principal::Anonymous));
+  }
+
+  conn.write_authentication_cleartext_password().await?;
+  conn.flush().await?;
+  let password = match conn.read_message().await? {
+    Some(message) if message.tag == b'p' => protocol::body_string(&message.body),
+    Some(_) => {
+      conn.write_fatal(PROTOCOL_VIOLATION, "expected a password message").await?;
+      conn.flush().await?;
+      return Ok(None);
+    },
+    None => return Ok(None),
+  };
+  let maybe_api_key = if password.is_empty() {
+    None
+  } else {
+    Some(password.as_str())
+  };
+  match server.authenticate(maybe_api_key).await {
+    Ok(principal) => Ok(Some(principal)),
+    Err(e) => {
+      conn.write_fatal(INVALID_PASSWORD, e.message()).await?;
+      conn.flush().await?;
+      Ok(None)
+    },
+  }
+}
+
+async fn serve_queries(server: &Server, conn: &mut Connection) -> std::io::Result<()> {
+  // after an extended protocol message fails, the client expects everything
+  // up to the next Sync to be ignored
+  let mut awaiting_sync = false;
+  while let Some(message) = conn.read_message().await? {
+    match message.tag {
+      b'Q' => {
+        let query = protocol::body_string(&message.body);
+        log::debug!("received Postgres query: {}", query);
+        simple_query(server, conn, &query).await?;
+        conn.write_ready_for_query().await?;
+      },
+      b'X' => return Ok(()),
+      b'S' => {
+        awaiting_sync = false;
+        conn.write_ready_for_query().await?;
+      },
+      b'H' => (),
+      b'P' | b'B' | b'D' | b'E' | b'C' => {
+        if !awaiting_sync {
+          conn.write_error(
+            errors::FEATURE_NOT_SUPPORTED,
+            "the extended query protocol is not supported; use simple queries",
+          ).await?;
+          awaiting_sync = true;
+        }
+      },
+      tag => {
+        conn.write_error(
+          PROTOCOL_VIOLATION,
+          &format!("unsupported message type {}", tag as char),
+        ).await?;
+        conn.write_ready_for_query().await?;
+      },
+    }
+    conn.flush().await?;
+  }
+  Ok(())
+}
+
+async fn simple_query(server: &Server, conn: &mut Connection, query: &str) -> std::io::Result<()> {
+  let statements = sql::split_statements(query);
+  if statements.is_empty() {
+    return conn.write_empty_query_response().await;
+  }
+
+  for statement_str in &statements {
+    let result = match sql::parse(statement_str) {
+      Ok(statement) => query::execute(server, &statement).await,
+      Err(e) => Err(e),
+    };
+    match result {
+      Ok(QueryResponse::Empty) => conn.write_empty_query_response().await?,
+      Ok(QueryResponse::Rows { columns, rows }) => {
+        conn.write_row_description(&columns).await?;
+        for row in &rows {
+          conn.write_data_row(row).await?;
+        }
+        conn.write_command_complete(&format!("SELECT {}", rows.len())).await?;
+      },
+      Ok(QueryResponse::Command(tag)) => conn.write_command_complete(&tag).await?,
+      Err(e) => {
+        log::debug!("Postgres query failed: {}", e);
+        // like Postgres, we abandon the rest of the query string on error
+        return conn.write_error(e.code, &e.message).await;
+      },
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+
+use super::types::{Cell, PgType};
+
+// Frontend/backend protocol version 3.0 message framing.
+// See https://www.postgresql.org/docs/current/protocol-message-formats.html
+
+const PROTOCOL_VERSION_3: i32 = 196608;
+const SSL_REQUEST_CODE: i32 = 80877103;
+const GSSENC_REQUEST_CODE: i32 = 80877104;
+const CANCEL_REQUEST_CODE: i32 = 80877102;
+// guards against allocating absurd buffers for malformed lengths
+const MAX_MESSAGE_LEN: usize = 1 << 24;
+
+pub enum Startup {
+  Params(HashMap<String, String>),
+  Cancel,
+}
+
+pub struct FrontendMessage {
+  pub tag: u8,
+  pub body: Vec<u8>,
+}
+
+pub struct Connection {
+  stream: BufStream<TcpStream>,
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+  std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn put_cstr(buf: &mut Vec<u8>, s: &str) {
+  buf.extend(s.as_bytes());
+  buf.push(0);
+}
+
+fn parse_cstrs(bytes: &[u8]) -> Vec<String> {
+  bytes.split(|&b| b == 0)
+    .map(|s| String::from_utf8_lossy(s).to_string())
+    .collect()
+}
+
+impl Connection {
+  pub fn new(stream: TcpStream) -> Self {
+    Connection {
+      stream: BufStream::new(stream),
+    }
+  }
+
+  async fn read_len(&mut self) -> std::io::Result<usize> {
+    let len = self.stream.read_i32().await?;
+    if len < 4 || len as usize > MAX_MESSAGE_LEN {
+      return Err(invalid_data("invalid message length"));
+    }
+    Ok(len as usize - 4)
+  }
+
+  // Reads the untagged startup packet, declining any encryption requests.
+  pub async fn read_startup(&mut self) -> std::io::Result<Startup> {
+    loop {
+      let len = self.read_len().await?;
+      let mut body = vec![0_u8; len];
+      self.stream.read_exact(&mut body).await?;
+      if body.len() < 4 {
+        return Err(invalid_data("startup packet too short"));
+      }
+      let code = i32::from_be_bytes([body[0], body[1], body[2], body[3]]);
+      match code {
+        SSL_REQUEST_CODE | GSSENC_REQUEST_CODE => {
+          self.stream.write_all(b"N").await?;
+          self.stream.flush().await?;
+        },
+        CANCEL_REQUEST_CODE => return Ok(Startup::Cancel),
+        PROTOCOL_VERSION_3 => {
+          let strs = parse_cstrs(&body[4..]);
+          let params = strs.chunks(2)
+            .filter(|pair| pair.len() == 2 && !pair[0].is_empty())
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+          return Ok(Startup::Params(params));
+        },
+        _ => return Err(invalid_data("unsupported protocol version")),
+      }
+    }
+  }
+
+  // Returns None when the client disconnects.
+  pub async fn read_message(&mut self) -> std::io::Result<Option<FrontendMessage>> {
+    let tag = match self.stream.read_u8().await {
+      Ok(tag) => tag,
+      Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+      Err(e) => return Err(e),
+    };
+    let len = self.read_len().await?;
+    let mut body = vec![0_u8; len];
+    self.stream.read_exact(&mut body).await?;
+    Ok(Some(FrontendMessage { tag, body }))
+  }
+
+  async fn write_message(&mut self, tag: u8, body: &[u8]) -> std::io::Result<()> {
+    self.stream.write_u8(tag).await?;
+    self.stream.write_i32(body.len() as i32 + 4).await?;
+    self.stream.write_all(body).await
+  }
+
+  pub async fn flush(&mut self) -> std::io::Result<()> {
+    self.stream.flush().await
+  }
+
+  pub async fn write_authentication_ok(&mut self) -> std::io::Result<()> {
+    self.write_message(b'R', &0_i32.to_be_bytes()).await
+  }
+
+  pub async fn write_authentication_cleartext_password(&mut self) -> std::io::Result<()> {
+    self.write_message(b'R', &3_i32.to_be_bytes()).await
+  }
+
+  pub async fn write_parameter_status(&mut self, name: &str, value: &str) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    put_cstr(&mut body, name);
+    put_cstr(&mut body, value);
+    self.write_message(b'S', &body).await
+  }
+
+  pub async fn write_backend_key_data(&mut self, process_id: i32, secret_key: i32) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    body.extend(process_id.to_be_bytes());
+    body.extend(secret_key.to_be_bytes());
+    self.write_message(b'K', &body).await
+  }
+
+  pub async fn write_ready_for_query(&mut self) -> std::io::Result<()> {
+    // always idle, since we don't track transactions
+    self.write_message(b'Z', b"I").await
+  }
+
+  pub async fn write_row_description(&mut self, columns: &[(String, PgType)]) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    body.extend((columns.len() as i16).to_be_bytes());
+    for (name, pg_type) in columns {
+      put_cstr(&mut body, name);
+      body.extend(0_i32.to_be_bytes()); // table OID
+      body.extend(0_i16.to_be_bytes()); // column attribute number
+      body.extend(pg_type.oid().to_be_bytes());
+      body.extend(pg_type.type_len().to_be_bytes());
+      body.extend((-1_i32).to_be_bytes()); // type modifier
+      body.extend(0_i16.to_be_bytes()); // text format
+    }
+    self.write_message(b'T', &body).await
+  }
+
+  pub async fn write_data_row(&mut self, row: &[Cell]) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    body.extend((row.len() as i16).to_be_bytes());
+    for cell in row {
+      match cell.to_text() {
+        Some(text) => {
+          body.extend((text.len() as i32).to_be_bytes());
+          body.extend(text.as_bytes());
+        },
+        None => body.extend((-1_i32).to_be_bytes()),
+      }
+    }
+    self.write_message(b'D', &body).await
+  }
+
+  pub async fn write_command_complete(&mut self, tag: &str) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    put_cstr(&mut body, tag);
+    self.write_message(b'C', &body).await
+  }
+
+  pub async fn write_empty_query_response(&mut self) -> std::io::Result<()> {
+    self.write_message(b'I', &[]).await
+  }
+
+  pub async fn write_error(&mut self, code: &str, message: &str) -> std::io::Result<()> {
+    self.write_error_response("ERROR", code, message).await
+  }
+
+  // an error that ends the connection
+  pub async fn write_fatal(&mut self, code: &str, message: &str) -> std::io::Result<()> {
+    self.write_error_response("FATAL", code, message).await
+  }
+
+  async fn write_error_response(&mut self, severity: &str, code: &str, message: &str) -> std::io::Result<()> {
+    let mut body = Vec::new();
+    for (field, value) in &[(b'S', severity), (b'V', severity), (b'C', code), (b'M', message)] {
+      body.push(*field);
+      put_cstr(&mut body, value);
+    }
+    body.push(0);
+    self.write_message(b'E', &body).await
+  }
+}
+
+// the string of a simple Query or PasswordMessage message
+pub fn body_string(body: &[u8]) -> String {
+  let end = body.iter().position(|&b| b == 0).unwrap_or(body.len());
+  String::from_utf8_lossy(&body[..end]).to_string()
+}
@@ -0,0 +1,486 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use pancake_db_idl::ddl::GetSchemaRequest;
+use pancake_db_idl::dml::{ListSegmentsRequest, PartitionFieldComparison, PartitionFieldValue, PartitionFilter};
+use pancake_db_idl::dml::partition_field_comparison::Operator;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use pancake_db_idl::dml::partition_filter::Value as PartitionFilterValue;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use prost_types::Timestamp;
+
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::{ServerError, ServerErrorKind};
+use crate::ops::get_schema::GetSchemaOp;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::traits::ServerOp;
+use crate::Server;
+use crate::server::authz::{Access, Verb};
+use crate::utils::common;
+
+use super::errors::{DATATYPE_MISMATCH, PgError, PgResult, UNDEFINED_COLUMN, UNDEFINED_FUNCTION, UNDEFINED_OBJECT, UNDEFINED_TABLE};
+use super::sql::{CompareOp, Comparison, Literal, Select, SelectExpr, Statement, TableRef};
+use super::types::{self, Cell, PgType};
+
+const CATALOG_NAME: &str = "pancake";
+const PUBLIC_SCHEMA: &str = "public";
+const PG_CATALOG_SCHEMA: &str = "pg_catalog";
+const INFORMATION_SCHEMA: &str = "information_schema";
+const SERVER_VERSION: &str = "14.0";
+
+pub enum QueryResponse {
+  Empty,
+  Rows {
+    columns: Vec<(String, PgType)>,
+    rows: Vec<Vec<Cell>>,
+  },
+  Command(String),
+}
+
+type Columns = Vec<(String, PgType)>;
+type NamedRow = HashMap<String, Cell>;
+
+fn literal_cell(literal: &Literal) -> (Cell, PgType) {
+  match literal {
+    Literal::Null => (Cell::Null, PgType::Text),
+    Literal::Bool(x) => (Cell::Bool(*x), PgType::Bool),
+    Literal::Int(x) => (Cell::Int(*x), PgType::Int8),
+    Literal::Float(x) => (Cell::Float(*x), PgType::Float8),
+    Literal::Str(x) => (Cell::Text(x.clone()), PgType::Text),
+  }
+}
+
+fn function_cell(name: &str) -> PgResult<(Cell, PgType)> {
+  let value = match name {
+    "version" => format!("PostgreSQL {} (PancakeDB)", SERVER_VERSION),
+    "current_database" => CATALOG_NAME.to_string(),
+    "current_schema" => PUBLIC_SCHEMA.to_string(),
+    "current_user" | "session_user" => CATALOG_NAME.to_string(),
+    _ => return Err(PgError::new(
+      UNDEFINED_FUNCTION,
+      format!("function {}() does not exist", name),
+    )),
+  };
+  Ok((Cell::Text(value), PgType::Text))
+}
+
+fn show_value(name: &str) -> PgResult<&'static str> {
+  let value = match name {
+    "server_version" => SERVER_VERSION,
+    "server_encoding" | "client_encoding" => "UTF8",
+    "datestyle" => "ISO, MDY",
+    "timezone" => "UTC",
+    "transaction_isolation" => "read committed",
+    "standard_conforming_strings" | "integer_datetimes" => "on",
+    "search_path" => PUBLIC_SCHEMA,
+    _ => return Err(PgError::new(
+      UNDEFINED_OBJECT,
+      format!("unrecognized configuration parameter \"{}\"", name),
+    )),
+  };
+  Ok(value)
+}
+
+fn compare_matches(ordering: Option<Ordering>, op: CompareOp) -> bool {
+  match ordering {
+    None => false,
+    Some(ordering) => match op {
+      CompareOp::Eq => ordering == Ordering::Equal,
+      CompareOp::NotEq => ordering != Ordering::Equal,
+      CompareOp::Less => ordering == Ordering::Less,
+      CompareOp::LessOrEq => ordering != Ordering::Greater,
+      CompareOp::Greater => ordering == Ordering::Greater,
+      CompareOp::GreaterOrEq => ordering != Ordering::Less,
+    }
+  }
+}
+
+fn check_comparable(comparison: &Comparison, pg_type: &PgType) -> PgResult<()> {
+  let ok = match (pg_type, &comparison.value) {
+    (_, Literal::Null) => true,
+    (PgType::Bool, Literal::Bool(_)) => true,
+    (PgType::Int8, Literal::Int(_) | Literal::Float(_)) => true,
+    (PgType::Float4 | PgType::Float8, Literal::Int(_) | Literal::Float(_)) => true,
+    (PgType::Text, Literal::Str(_)) => true,
+    (PgType::Timestamptz, Literal::Str(s)) => types::parse_timestamp(s).is_some(),
+    _ => false,
+  };
+  if ok {
+    Ok(())
+  } else {
+    Err(PgError::new(
+      DATATYPE_MISMATCH,
+      format!("cannot compare column {} of type {} to {}", comparison.column, pg_type.sql_name(), comparison.value),
+    ))
+  }
+}
+
+// Output column descriptions and a function from source rows to output rows
+// for a SELECT over a source with the given (ordered) columns.
+struct Projection {
+  columns: Columns,
+  exprs: Vec<SelectExpr>,
+}
+
+impl Projection {
+  // `star_columns` are the columns * expands to, a subset of `available`
+  fn new(select: &Select, star_columns: &[(String, PgType)], available: &[(String, PgType)]) -> PgResult<Self> {
+    let find = |name: &str| available.iter()
+      .find(|(col_name, _)| col_name == name)
+      .map(|(_, pg_type)| pg_type.clone())
+      .ok_or_else(|| PgError::new(UNDEFINED_COLUMN, format!("column \"{}\" does not exist", name)));
+
+    let mut columns = Vec::new();
+    let mut exprs = Vec::new();
+    for item in &select.items {
+      match &item.expr {
+        SelectExpr::Star => {
+          if select.from.is_none() {
+            return Err(PgError::syntax("SELECT * with no tables specified is not valid"));
+          }
+          for (name, pg_type) in star_columns {
+            columns.push((name.clone(), pg_type.clone()));
+            exprs.push(SelectExpr::Column(name.clone()));
+          }
+        },
+        SelectExpr::Column(name) => {
+          let pg_type = find(name)?;
+          columns.push((item.alias.clone().unwrap_or_else(|| name.clone()), pg_type));
+          exprs.push(item.expr.clone());
+        },
+        SelectExpr::Function(name) => {
+          let (_, pg_type) = function_cell(name)?;
+          columns.push((item.alias.clone().unwrap_or_else(|| name.clone()), pg_type));
+          exprs.push(item.expr.clone());
+        },
+        SelectExpr::Literal(literal) => {
+          let (_, pg_type) = literal_cell(literal);
+          columns.push((item.alias.clone().unwrap_or_else(|| "?column?".to_string()), pg_type));
+          exprs.push(item.expr.clone());
+        },
+      }
+    }
+
+    for comparison in &select.filters {
+      check_comparable(comparison, &find(&comparison.column)?)?;
+    }
+    Ok(Projection { columns, exprs })
+  }
+
+  // the source columns the projection reads
+  fn referenced_columns(&self) -> Vec<&str> {
+    self.exprs.iter()
+      .filter_map(|expr| match expr {
+        SelectExpr::Column(name) => Some(name.as_str()),
+        _ => None,
+      })
+      .collect()
+  }
+
+  fn project(&self, row: &NamedRow) -> PgResult<Vec<Cell>> {
+    self.exprs.iter()
+      .map(|expr| Ok(match expr {
+        SelectExpr::Column(name) => row.get(name).cloned().unwrap_or(Cell::Null),
+        SelectExpr::Function(name) => function_cell(name)?.0,
+        SelectExpr::Literal(literal) => literal_cell(literal).0,
+        SelectExpr::Star => Cell::Null,
+      }))
+      .collect()
+  }
+}
+
+fn matches_filters(row: &NamedRow, filters: &[Comparison]) -> bool {
+  filters.iter().all(|comparison| {
+    let cell = row.get(&comparison.column).unwrap_or(&Cell::Null);
+    compare_matches(cell.compare(&comparison.value), comparison.op)
+  })
+}
+
+fn select_from_rows(select: &Select, available: &[(String, PgType)], rows: Vec<NamedRow>) -> PgResult<QueryResponse> {
+  let projection = Projection::new(select, available, available)?;
+  let limit = select.limit.unwrap_or(usize::MAX);
+  let mut res = Vec::new();
+  for row in rows.iter().filter(|row| matches_filters(row, &select.filters)).take(limit) {
+    res.push(projection.project(row)?);
+  }
+  Ok(QueryResponse::Rows {
+    columns: projection.columns,
+    rows: res,
+  })
+}
+
+fn text(s: &str) -> Cell {
+  Cell::Text(s.to_string())
+}
+
+fn user_columns(schema: &Schema) -> Columns {
+  let mut partition_names: Vec<_> = schema.partitioning.keys().collect();
+  partition_names.sort();
+  let mut column_names: Vec<_> = schema.columns.keys().collect();
+  column_names.sort();
+
+  let mut columns = Vec::new();
+  for name in partition_names {
+    let dtype = PartitionDataType::from_i32(schema.partitioning[name].dtype)
+      .unwrap_or(PartitionDataType::String);
+    columns.push((name.clone(), PgType::from_partition_dtype(dtype)));
+  }
+  for name in column_names {
+    let meta = &schema.columns[name];
+    let dtype = common::unwrap_dtype(meta.dtype).unwrap_or(pancake_db_idl::dtype::DataType::String);
+    columns.push((name.clone(), PgType::from_dtype(dtype, meta.nested_list_depth)));
+  }
+  columns
+}
+
+// pg_catalog and information_schema shims so that tools can discover tables
+async fn catalog_table(server: &Server, table_ref: &TableRef) -> PgResult<Option<(Columns, Vec<NamedRow>)>> {
+  let schema = table_ref.schema.as_deref();
+  let name = table_ref.name.as_str();
+  let is_pg_catalog = matches!(schema, None | Some(PG_CATALOG_SCHEMA));
+  let is_information_schema = schema == Some(INFORMATION_SCHEMA);
+  if !(is_pg_catalog && (name == "pg_tables" || name == "pg_namespace")) &&
+    !(is_information_schema && (name == "tables" || name == "columns")) {
+    return Ok(None);
+  }
+
+  let mut tables = Vec::new();
+  for table in server.internal_list_tables().await? {
+    if server.is_authorized(&Access::table(Verb::Read, &table.name)).await {
+      tables.push(table);
+    }
+  }
+  tables.sort_by(|a, b| a.name.cmp(&b.name));
+
+  let make_rows = |columns: &Columns, values: Vec<Vec<Cell>>| -> Vec<NamedRow> {
+    values.into_iter()
+      .map(|row| columns.iter().map(|(name, _)| name.clone()).zip(row).collect())
+      .collect()
+  };
+
+  let (columns, values) = match name {
+    "pg_tables" => {
+      let columns = vec![
+        ("schemaname".to_string(), PgType::Text),
+        ("tablename".to_string(), PgType::Text),
+        ("tableowner".to_string(), PgType::Text),
+        ("tablespace".to_string(), PgType::Text),
+        ("hasindexes".to_string(), PgType::Bool),
+        ("hasrules".to_string(), PgType::Bool),
+        ("hastriggers".to_string(), PgType::Bool),
+        ("rowsecurity".to_string(), PgType::Bool),
+      ];
+      let values = tables.iter()
+        .map(|table| vec![
+          text(PUBLIC_SCHEMA),
+          text(&table.name),
+          text(CATALOG_NAME),
+          Cell::Null,
+          Cell::Bool(false),
+          Cell::Bool(false),
+          Cell::Bool(false),
+          Cell::Bool(false),
+        ])
+        .collect();
+      (columns, values)
+    },
+    "pg_namespace" => {
+      let columns = vec![
+        ("oid".to_string(), PgType::Int8),
+        ("nspname".to_string(), PgType::Text),
+      ];
+      let values = vec![
+        vec![Cell::Int(11), text(PG_CATALOG_SCHEMA)],
+        vec![Cell::Int(2200), text(PUBLIC_SCHEMA)],
+        vec![Cell::Int(13000), text(INFORMATION_SCHEMA)],
+      ];
+      (columns, values)
+    },
+    "tables" => {
+      let columns = vec![
+        ("table_catalog".to_string(), PgType::Text),
+        ("table_schema".to_string(), PgType::Text),
+        ("table_name".to_string(), PgType::Text),
+        ("table_type".to_string(), PgType::Text),
+      ];
+      let values = tables.iter()
+        .map(|table| vec![
+          text(CATALOG_NAME),
+          text(PUBLIC_SCHEMA),
+          text(&table.name),
+          text("BASE TABLE"),
+        ])
+        .collect();
+      (columns, values)
+    },
+    _ => {
+      let columns = vec![
+        ("table_catalog".to_string(), PgType::Text),
+        ("table_schema".to_string(), PgType::Text),
+        ("table_name".to_string(), PgType::Text),
+        ("column_name".to_string(), PgType::Text),
+        ("ordinal_position".to_string(), PgType::Int8),
+        ("is_nullable".to_string(), PgType::Text),
+        ("data_type".to_string(), PgType::Text),
+      ];
+      let mut values = Vec::new();
+      for table in &tables {
+        for (idx, (col_name, pg_type)) in user_columns(&table.meta.visible_schema()).iter().enumerate() {
+          values.push(vec![
+            text(CATALOG_NAME),
+            text(PUBLIC_SCHEMA),
+            text(&table.name),
+            text(col_name),
+            Cell::Int(idx as i64 + 1),
+            text("YES"),
+            text(pg_type.sql_name()),
+          ]);
+        }
+      }
+      (columns, values)
+    },
+  };
+  let rows = make_rows(&columns, values);
+  Ok(Some((columns, rows)))
+}
+
+fn partition_filter(comparison: &Comparison, dtype: PartitionDataType) -> Option<PartitionFilter> {
+  let operator = match comparison.op {
+    CompareOp::Eq => Operator::EqTo,
+    CompareOp::Less => Operator::Less,
+    CompareOp::LessOrEq => Operator::LessOrEqTo,
+    CompareOp::Greater => Operator::Greater,
+    CompareOp::GreaterOrEq => Operator::GreaterOrEqTo,
+    CompareOp::NotEq => return None,
+  };
+  let value = match (&comparison.value, dtype) {
+    (Literal::Str(s), PartitionDataType::String) => PartitionValue::StringVal(s.clone()),
+    (Literal::Int(x), PartitionDataType::Int64) => PartitionValue::Int64Val(*x),
+    (Literal::Bool(x), PartitionDataType::Bool) if operator == Operator::EqTo => PartitionValue::BoolVal(*x),
+    (Literal::Str(s), PartitionDataType::TimestampMinute) => PartitionValue::TimestampVal(
+      Timestamp::from(SystemTime::from(types::parse_timestamp(s)?))
+    ),
+    _ => return None,
+  };
+  Some(PartitionFilter {
+    value: Some(PartitionFilterValue::Comparison(PartitionFieldComparison {
+      name: comparison.column.clone(),
+      operator: operator as i32,
+      value: Some(PartitionFieldValue { value: Some(value) }),
+    })),
+  })
+}
+
+async fn select_from_table(server: &Server, select: &Select, table_name: &str) -> PgResult<QueryResponse> {
+  let schema_resp = GetSchemaOp {
+    req: GetSchemaRequest { table_name: table_name.to_string() },
+  }.execute(server).await
+    .map_err(|e| match e.kind {
+      ServerErrorKind::DoesNotExist => PgError::new(
+        UNDEFINED_TABLE,
+        format!("relation \"{}\" does not exist", table_name),
+      ),
+      _ => PgError::from(e),
+    })?;
+  let schema = schema_resp.schema
+    .ok_or_else(|| ServerError::internal("get schema response is missing schema"))?;
+
+  // hidden columns can be selected explicitly but are not part of *
+  let star_columns = user_columns(&schema);
+  let mut available = star_columns.clone();
+  let augmented_columns = common::augmented_columns(&schema);
+  for (name, meta) in &augmented_columns {
+    if !schema.columns.contains_key(name) {
+      available.push((name.clone(), PgType::from_dtype(common::unwrap_dtype(meta.dtype)?, 0)));
+    }
+  }
+  let projection = Projection::new(select, &star_columns, &available)?;
+
+  // only read the data columns we need
+  let mut read_columns: HashMap<String, ColumnMeta> = projection.referenced_columns().into_iter()
+    .chain(select.filters.iter().map(|comparison| comparison.column.as_str()))
+    .filter_map(|name| augmented_columns.get(name).map(|meta| (name.to_string(), meta.clone())))
+    .collect();
+  if read_columns.is_empty() {
+    read_columns.insert(ROW_ID_COLUMN_NAME.to_string(), augmented_columns[ROW_ID_COLUMN_NAME].clone());
+  }
+
+  let partition_filter = select.filters.iter()
+    .filter_map(|comparison| {
+      let dtype = PartitionDataType::from_i32(schema.partitioning.get(&comparison.column)?.dtype)?;
+      partition_filter(comparison, dtype)
+    })
+    .collect();
+  let segments = ListSegmentsOp {
+    req: ListSegmentsRequest {
+      table_name: table_name.to_string(),
+      partition_filter,
+      ..Default::default()
+    },
+    partition_conditions: Vec::new(),
+  }.execute(server).await?.segments;
+
+  let limit = select.limit.unwrap_or(usize::MAX);
+  let mut rows = Vec::new();
+  for segment in &segments {
+    if rows.len() >= limit {
+      break;
+    }
+    let partition_cells = segment.partition.iter()
+      .map(|(name, value)| Ok((name.clone(), Cell::from_partition_field_value(value)?)))
+      .collect::<PgResult<NamedRow>>()?;
+    for row in server.decode_segment(table_name, segment, &read_columns).await? {
+      let mut named_row = partition_cells.clone();
+      for (name, value) in &row.fields {
+        named_row.insert(name.clone(), Cell::from_field_value(value)?);
+      }
+      if matches_filters(&named_row, &select.filters) {
+        rows.push(projection.project(&named_row)?);
+        if rows.len() >= limit {
+          break;
+        }
+      }
+    }
+  }
+
+  Ok(QueryResponse::Rows {
+    columns: projection.columns,
+    rows,
+  })
+}
+
+async fn select(server: &Server, select: &Select) -> PgResult<QueryResponse> {
+  let table_ref = match &select.from {
+    None => return select_from_rows(select, &[], vec![NamedRow::new()]),
+    Some(table_ref) => table_ref,
+  };
+
+  if let Some((columns, rows)) = catalog_table(server, table_ref).await? {
+    return select_from_rows(select, &columns, rows);
+  }
+
+  match table_ref.schema.as_deref() {
+    None | Some(PUBLIC_SCHEMA) => select_from_table(server, select, &table_ref.name).await,
+    Some(schema) => Err(PgError::new(
+      UNDEFINED_TABLE,
+      format!("relation \"{}.{}\" does not exist", schema, table_ref.name),
+    )),
+  }
+}
+
+pub async fn execute(server: &Server, statement: &Statement) -> PgResult<QueryResponse> {
+  match statement {
+    Statement::Empty => Ok(QueryResponse::Empty),
+    Statement::Select(s) => select(server, s).await,
+    Statement::Show(name) => {
+      let value = show_value(name)?;
+      Ok(QueryResponse::Rows {
+        columns: vec![(name.clone(), PgType::Text)],
+        rows: vec![vec![text(value)]],
+      })
+    },
+    Statement::NoOp(tag) => Ok(QueryResponse::Command(tag.to_string())),
+  }
+}
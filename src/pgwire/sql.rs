@@ -0,0 +1,438 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use super::errors::{PgError, PgResult};
+
+// A deliberately small SQL dialect: enough for simple SELECTs with
+// conjunctive comparisons, plus the housekeeping statements clients send on
+// connect. Anything else is rejected as unsupported.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+  Null,
+  Bool(bool),
+  Int(i64),
+  Float(f64),
+  Str(String),
+}
+
+impl Display for Literal {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Literal::Null => write!(f, "NULL"),
+      Literal::Bool(x) => write!(f, "{}", x),
+      Literal::Int(x) => write!(f, "{}", x),
+      Literal::Float(x) => write!(f, "{}", x),
+      Literal::Str(x) => write!(f, "'{}'", x.replace('\'', "''")),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+  Eq,
+  NotEq,
+  Less,
+  LessOrEq,
+  Greater,
+  GreaterOrEq,
+}
+
+impl CompareOp {
+  // the equivalent operator with operands swapped
+  fn flipped(self) -> CompareOp {
+    match self {
+      CompareOp::Less => CompareOp::Greater,
+      CompareOp::LessOrEq => CompareOp::GreaterOrEq,
+      CompareOp::Greater => CompareOp::Less,
+      CompareOp::GreaterOrEq => CompareOp::LessOrEq,
+      other => other,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+  pub column: String,
+  pub op: CompareOp,
+  pub value: Literal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectExpr {
+  Star,
+  Column(String),
+  Function(String),
+  Literal(Literal),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectItem {
+  pub expr: SelectExpr,
+  pub alias: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableRef {
+  pub schema: Option<String>,
+  pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Select {
+  pub items: Vec<SelectItem>,
+  pub from: Option<TableRef>,
+  pub filters: Vec<Comparison>,
+  pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+  Empty,
+  Select(Select),
+  Show(String),
+  // statements we accept but ignore, with the command tag to reply with
+  NoOp(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Word(String),
+  QuotedIdent(String),
+  Str(String),
+  Number(String),
+  Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &["<=", ">=", "<>", "!=", "=", "<", ">", "*", ",", ".", "(", ")", "-"];
+
+const RESERVED: &[&str] = &["from", "where", "limit", "and", "or", "as", "order", "group"];
+
+fn tokenize(sql: &str) -> PgResult<Vec<Token>> {
+  let chars: Vec<char> = sql.chars().collect();
+  let mut tokens = Vec::new();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    if c.is_whitespace() {
+      i += 1;
+    } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+      while i < chars.len() && chars[i] != '\n' {
+        i += 1;
+      }
+    } else if c == '\'' || c == '"' {
+      // both kinds of quote escape themselves by doubling
+      let mut s = String::new();
+      i += 1;
+      loop {
+        match chars.get(i) {
+          None => return Err(PgError::syntax("unterminated quoted string")),
+          Some(&x) if x == c => {
+            if chars.get(i + 1) == Some(&c) {
+              s.push(c);
+              i += 2;
+            } else {
+              i += 1;
+              break;
+            }
+          },
+          Some(&x) => {
+            s.push(x);
+            i += 1;
+          },
+        }
+      }
+      tokens.push(if c == '\'' { Token::Str(s) } else { Token::QuotedIdent(s) });
+    } else if c.is_ascii_digit() {
+      let start = i;
+      while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == 'e' || chars[i] == 'E') {
+        i += 1;
+      }
+      tokens.push(Token::Number(chars[start..i].iter().collect()));
+    } else if c.is_alphabetic() || c == '_' {
+      let start = i;
+      while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+        i += 1;
+      }
+      let word: String = chars[start..i].iter().collect();
+      tokens.push(Token::Word(word.to_lowercase()));
+    } else {
+      let rest: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+      match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+        Some(s) => {
+          tokens.push(Token::Symbol(s));
+          i += s.len();
+        },
+        None => return Err(PgError::syntax(format!("unexpected character {}", c))),
+      }
+    }
+  }
+  Ok(tokens)
+}
+
+// Splits a query string on semicolons that are not inside quotes.
+pub fn split_statements(query: &str) -> Vec<String> {
+  let mut res = Vec::new();
+  let mut current = String::new();
+  let mut quote = None;
+  for c in query.chars() {
+    match (quote, c) {
+      (None, ';') => {
+        res.push(std::mem::take(&mut current));
+        continue;
+      },
+      (None, '\'') | (None, '"') => quote = Some(c),
+      (Some(q), _) if q == c => quote = None,
+      _ => (),
+    }
+    current.push(c);
+  }
+  res.push(current);
+  res.into_iter()
+    .filter(|s| !s.trim().is_empty())
+    .collect()
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let res = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    res
+  }
+
+  fn peek_word(&self, word: &str) -> bool {
+    matches!(self.peek(), Some(Token::Word(w)) if w == word)
+  }
+
+  fn peek_symbol(&self, symbol: &str) -> bool {
+    matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+  }
+
+  fn accept_word(&mut self, word: &str) -> bool {
+    let res = self.peek_word(word);
+    if res {
+      self.pos += 1;
+    }
+    res
+  }
+
+  fn accept_symbol(&mut self, symbol: &str) -> bool {
+    let res = self.peek_symbol(symbol);
+    if res {
+      self.pos += 1;
+    }
+    res
+  }
+
+  fn identifier(&mut self) -> PgResult<String> {
+    match self.next() {
+      Some(Token::Word(w)) if !RESERVED.contains(&w.as_str()) => Ok(w),
+      Some(Token::QuotedIdent(s)) => Ok(s),
+      other => Err(PgError::syntax(format!("expected identifier but found {:?}", other))),
+    }
+  }
+
+  fn literal(&mut self) -> PgResult<Literal> {
+    let negative = self.accept_symbol("-");
+    let literal = match self.next() {
+      Some(Token::Str(s)) if !negative => Literal::Str(s),
+      Some(Token::Word(w)) if w == "null" && !negative => Literal::Null,
+      Some(Token::Word(w)) if w == "true" && !negative => Literal::Bool(true),
+      Some(Token::Word(w)) if w == "false" && !negative => Literal::Bool(false),
+      Some(Token::Number(n)) => {
+        let n = if negative { format!("-{}", n) } else { n };
+        match n.parse::<i64>() {
+          Ok(x) => Literal::Int(x),
+          Err(_) => Literal::Float(
+            n.parse::<f64>().map_err(|_| PgError::syntax(format!("invalid number {}", n)))?
+          ),
+        }
+      },
+      other => return Err(PgError::syntax(format!("expected literal but found {:?}", other))),
+    };
+    Ok(literal)
+  }
+
+  fn is_literal_start(&self) -> bool {
+    match self.peek() {
+      Some(Token::Str(_)) | Some(Token::Number(_)) | Some(Token::Symbol("-")) => true,
+      Some(Token::Word(w)) => w == "null" || w == "true" || w == "false",
+      _ => false,
+    }
+  }
+
+  // a possibly qualified column name; qualifiers are ignored since we
+  // only ever select from one table
+  fn column(&mut self) -> PgResult<String> {
+    let mut name = self.identifier()?;
+    while self.accept_symbol(".") {
+      name = self.identifier()?;
+    }
+    Ok(name)
+  }
+
+  fn select_item(&mut self) -> PgResult<SelectItem> {
+    let expr = if self.accept_symbol("*") {
+      SelectExpr::Star
+    } else if self.is_literal_start() {
+      SelectExpr::Literal(self.literal()?)
+    } else {
+      let name = self.column()?;
+      if self.accept_symbol("(") {
+        if !self.accept_symbol(")") {
+          return Err(PgError::unsupported("function arguments and aggregates are not supported"));
+        }
+        SelectExpr::Function(name)
+      } else {
+        SelectExpr::Column(name)
+      }
+    };
+
+    let alias = if self.accept_word("as") {
+      Some(self.identifier()?)
+    } else {
+      match self.peek() {
+        Some(Token::Word(w)) if !RESERVED.contains(&w.as_str()) => Some(self.identifier()?),
+        Some(Token::QuotedIdent(_)) => Some(self.identifier()?),
+        _ => None,
+      }
+    };
+    Ok(SelectItem { expr, alias })
+  }
+
+  fn compare_op(&mut self) -> PgResult<CompareOp> {
+    let op = match self.next() {
+      Some(Token::Symbol("=")) => CompareOp::Eq,
+      Some(Token::Symbol("<>")) | Some(Token::Symbol("!=")) => CompareOp::NotEq,
+      Some(Token::Symbol("<")) => CompareOp::Less,
+      Some(Token::Symbol("<=")) => CompareOp::LessOrEq,
+      Some(Token::Symbol(">")) => CompareOp::Greater,
+      Some(Token::Symbol(">=")) => CompareOp::GreaterOrEq,
+      other => return Err(PgError::syntax(format!("expected comparison operator but found {:?}", other))),
+    };
+    Ok(op)
+  }
+
+  fn comparison(&mut self) -> PgResult<Comparison> {
+    if self.is_literal_start() {
+      let value = self.literal()?;
+      let op = self.compare_op()?.flipped();
+      let column = self.column()?;
+      Ok(Comparison { column, op, value })
+    } else {
+      let column = self.column()?;
+      let op = self.compare_op()?;
+      let value = self.literal()?;
+      Ok(Comparison { column, op, value })
+    }
+  }
+
+  fn select(&mut self) -> PgResult<Select> {
+    let mut items = vec![self.select_item()?];
+    while self.accept_symbol(",") {
+      items.push(self.select_item()?);
+    }
+
+    let from = if self.accept_word("from") {
+      let first = self.identifier()?;
+      if self.accept_symbol(".") {
+        Some(TableRef { schema: Some(first), name: self.identifier()? })
+      } else {
+        Some(TableRef { schema: None, name: first })
+      }
+    } else {
+      None
+    };
+
+    let mut filters = Vec::new();
+    if self.accept_word("where") {
+      if from.is_none() {
+        return Err(PgError::syntax("WHERE requires FROM"));
+      }
+      filters.push(self.comparison()?);
+      while self.accept_word("and") {
+        filters.push(self.comparison()?);
+      }
+      if self.peek_word("or") {
+        return Err(PgError::unsupported("only conjunctions (AND) of comparisons are supported"));
+      }
+    }
+
+    let limit = if self.accept_word("limit") {
+      match self.next() {
+        Some(Token::Number(n)) => Some(
+          n.parse::<usize>().map_err(|_| PgError::syntax(format!("invalid limit {}", n)))?
+        ),
+        Some(Token::Word(w)) if w == "all" => None,
+        other => return Err(PgError::syntax(format!("expected limit but found {:?}", other))),
+      }
+    } else {
+      None
+    };
+
+    Ok(Select { items, from, filters, limit })
+  }
+
+  fn statement(&mut self) -> PgResult<Statement> {
+    let first = match self.next() {
+      None => return Ok(Statement::Empty),
+      Some(Token::Word(w)) => w,
+      Some(other) => return Err(PgError::syntax(format!("unexpected {:?}", other))),
+    };
+    let statement = match first.as_str() {
+      "select" => Statement::Select(self.select()?),
+      "show" => {
+        let mut name = self.identifier()?;
+        while self.accept_symbol(".") {
+          name = format!("{}.{}", name, self.identifier()?);
+        }
+        Statement::Show(name)
+      },
+      // we are read-only, so session settings and transactions are no-ops
+      "set" | "reset" | "discard" => {
+        self.pos = self.tokens.len();
+        Statement::NoOp(if first == "set" { "SET" } else if first == "reset" { "RESET" } else { "DISCARD ALL" })
+      },
+      "begin" | "start" => {
+        self.pos = self.tokens.len();
+        Statement::NoOp("BEGIN")
+      },
+      "commit" | "end" => {
+        self.pos = self.tokens.len();
+        Statement::NoOp("COMMIT")
+      },
+      "rollback" | "abort" => {
+        self.pos = self.tokens.len();
+        Statement::NoOp("ROLLBACK")
+      },
+      other => return Err(PgError::unsupported(format!("{} statements are not supported", other.to_uppercase()))),
+    };
+
+    if let Some(token) = self.peek() {
+      let message = format!("unsupported or unexpected syntax at {:?}", token);
+      return Err(if matches!(token, Token::Word(_)) {
+        PgError::unsupported(message)
+      } else {
+        PgError::syntax(message)
+      });
+    }
+    Ok(statement)
+  }
+}
+
+pub fn parse(sql: &str) -> PgResult<Statement> {
+  let mut parser = Parser {
+    tokens: tokenize(sql)?,
+    pos: 0,
+  };
+  parser.statement()
+}
@@ -0,0 +1,224 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use pancake_db_idl::dml::{FieldValue, PartitionFieldValue};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use prost_types::Timestamp;
+
+use super::errors::{DATETIME_FIELD_OVERFLOW, PgError, PgResult};
+use super::sql::Literal;
+
+// Postgres types we present columns as. Everything is sent in text format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgType {
+  Bool,
+  Bytea,
+  Int8,
+  Float4,
+  Float8,
+  Text,
+  Timestamptz,
+  Array(Box<PgType>),
+}
+
+impl PgType {
+  pub fn from_dtype(dtype: DataType, nested_list_depth: u32) -> PgType {
+    let scalar = match dtype {
+      DataType::Bool => PgType::Bool,
+      DataType::Bytes => PgType::Bytea,
+      DataType::Int64 => PgType::Int8,
+      DataType::Float32 => PgType::Float4,
+      DataType::Float64 => PgType::Float8,
+      DataType::String => PgType::Text,
+      DataType::TimestampMicros => PgType::Timestamptz,
+    };
+    // Postgres arrays are multidimensional rather than nested, so one array
+    // type covers every list depth
+    if nested_list_depth > 0 {
+      PgType::Array(Box::new(scalar))
+    } else {
+      scalar
+    }
+  }
+
+  pub fn from_partition_dtype(dtype: PartitionDataType) -> PgType {
+    match dtype {
+      PartitionDataType::Bool => PgType::Bool,
+      PartitionDataType::Int64 => PgType::Int8,
+      PartitionDataType::String => PgType::Text,
+      PartitionDataType::TimestampMinute => PgType::Timestamptz,
+    }
+  }
+
+  pub fn oid(&self) -> i32 {
+    match self {
+      PgType::Bool => 16,
+      PgType::Bytea => 17,
+      PgType::Int8 => 20,
+      PgType::Float4 => 700,
+      PgType::Float8 => 701,
+      PgType::Text => 25,
+      PgType::Timestamptz => 1184,
+      PgType::Array(inner) => match **inner {
+        PgType::Bool => 1000,
+        PgType::Bytea => 1001,
+        PgType::Int8 => 1016,
+        PgType::Float4 => 1021,
+        PgType::Float8 => 1022,
+        PgType::Timestamptz => 1185,
+        _ => 1009,
+      },
+    }
+  }
+
+  pub fn type_len(&self) -> i16 {
+    match self {
+      PgType::Bool => 1,
+      PgType::Float4 => 4,
+      PgType::Int8 | PgType::Float8 | PgType::Timestamptz => 8,
+      _ => -1,
+    }
+  }
+
+  // as shown in information_schema.columns
+  pub fn sql_name(&self) -> &'static str {
+    match self {
+      PgType::Bool => "boolean",
+      PgType::Bytea => "bytea",
+      PgType::Int8 => "bigint",
+      PgType::Float4 => "real",
+      PgType::Float8 => "double precision",
+      PgType::Text => "text",
+      PgType::Timestamptz => "timestamp with time zone",
+      PgType::Array(_) => "ARRAY",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+  Null,
+  Bool(bool),
+  Bytes(Vec<u8>),
+  Int(i64),
+  Float(f64),
+  Text(String),
+  Timestamp(DateTime<Utc>),
+  List(Vec<Cell>),
+}
+
+fn timestamp_to_datetime(t: &Timestamp) -> PgResult<DateTime<Utc>> {
+  Utc.timestamp_opt(t.seconds, t.nanos as u32)
+    .single()
+    .ok_or_else(|| PgError::new(
+      DATETIME_FIELD_OVERFLOW,
+      format!("timestamp {}s {}ns is out of range", t.seconds, t.nanos),
+    ))
+}
+
+fn format_float(x: f64) -> String {
+  if x.is_nan() {
+    "NaN".to_string()
+  } else if x.is_infinite() {
+    if x > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+  } else {
+    x.to_string()
+  }
+}
+
+fn quote_array_element(s: &str) -> String {
+  format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Cell {
+  pub fn from_field_value(fv: &FieldValue) -> PgResult<Cell> {
+    let res = match &fv.value {
+      None => Cell::Null,
+      Some(Value::BoolVal(x)) => Cell::Bool(*x),
+      Some(Value::BytesVal(x)) => Cell::Bytes(x.clone()),
+      Some(Value::Int64Val(x)) => Cell::Int(*x),
+      Some(Value::Float32Val(x)) => Cell::Float(*x as f64),
+      Some(Value::Float64Val(x)) => Cell::Float(*x),
+      Some(Value::StringVal(x)) => Cell::Text(x.clone()),
+      Some(Value::TimestampVal(x)) => Cell::Timestamp(timestamp_to_datetime(x)?),
+      Some(Value::ListVal(x)) => Cell::List(
+        x.vals.iter().map(Cell::from_field_value).collect::<PgResult<_>>()?
+      ),
+    };
+    Ok(res)
+  }
+
+  pub fn from_partition_field_value(pfv: &PartitionFieldValue) -> PgResult<Cell> {
+    let res = match &pfv.value {
+      None => Cell::Null,
+      Some(PartitionValue::BoolVal(x)) => Cell::Bool(*x),
+      Some(PartitionValue::Int64Val(x)) => Cell::Int(*x),
+      Some(PartitionValue::StringVal(x)) => Cell::Text(x.clone()),
+      Some(PartitionValue::TimestampVal(x)) => Cell::Timestamp(timestamp_to_datetime(x)?),
+    };
+    Ok(res)
+  }
+
+  // the text format representation, or None for NULL
+  pub fn to_text(&self) -> Option<String> {
+    match self {
+      Cell::Null => None,
+      Cell::Bool(x) => Some(if *x { "t" } else { "f" }.to_string()),
+      Cell::Bytes(x) => {
+        let hex: String = x.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(format!("\\x{}", hex))
+      },
+      Cell::Int(x) => Some(x.to_string()),
+      Cell::Float(x) => Some(format_float(*x)),
+      Cell::Text(x) => Some(x.clone()),
+      Cell::Timestamp(x) => Some(x.format("%Y-%m-%d %H:%M:%S%.f+00").to_string()),
+      Cell::List(x) => {
+        let elements: Vec<String> = x.iter()
+          .map(|cell| match cell {
+            Cell::List(_) => cell.to_text().unwrap_or_default(),
+            _ => cell.to_text()
+              .map(|s| quote_array_element(&s))
+              .unwrap_or_else(|| "NULL".to_string()),
+          })
+          .collect();
+        Some(format!("{{{}}}", elements.join(",")))
+      },
+    }
+  }
+
+  // SQL comparison semantics: anything compared to NULL is unknown (None)
+  pub fn compare(&self, literal: &Literal) -> Option<Ordering> {
+    match (self, literal) {
+      (Cell::Null, _) | (_, Literal::Null) => None,
+      (Cell::Bool(x), Literal::Bool(y)) => Some(x.cmp(y)),
+      (Cell::Int(x), Literal::Int(y)) => Some(x.cmp(y)),
+      (Cell::Int(x), Literal::Float(y)) => (*x as f64).partial_cmp(y),
+      (Cell::Float(x), Literal::Int(y)) => x.partial_cmp(&(*y as f64)),
+      (Cell::Float(x), Literal::Float(y)) => x.partial_cmp(y),
+      (Cell::Text(x), Literal::Str(y)) => Some(x.as_str().cmp(y.as_str())),
+      (Cell::Timestamp(x), Literal::Str(y)) => parse_timestamp(y).map(|y| x.cmp(&y)),
+      _ => None,
+    }
+  }
+}
+
+// accepts RFC 3339 as well as Postgres' usual "2022-01-01 00:00:00" format,
+// which we interpret as UTC
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+  if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+    return Some(t.with_timezone(&Utc));
+  }
+  let s = s.trim_end_matches("+00");
+  for fmt in &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M:%S"] {
+    if let Ok(t) = NaiveDateTime::parse_from_str(s, fmt) {
+      return Some(Utc.from_utc_datetime(&t));
+    }
+  }
+  chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+    .ok()
+    .and_then(|d| d.and_hms_opt(0, 0, 0))
+    .map(|t| Utc.from_utc_datetime(&t))
+}
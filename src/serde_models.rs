@@ -5,15 +5,18 @@ use pancake_db_idl::ddl::create_table_request::SchemaMode;
 use pancake_db_idl::dtype::DataType;
 use pancake_db_idl::partition_dtype::PartitionDataType;
 use pancake_db_idl::schema::{ColumnMeta, PartitionMeta, Schema};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::errors::ServerError;
+use crate::metadata::table::TableConfigOverrides;
+use crate::utils::rest::ErrorResponse;
 use crate::ServerResult;
 
 macro_rules! impl_serde_enum {
   ($t: ident, $orig: ty, {$($vars: ident),*}) => {
-    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
     #[serde(rename_all = "camelCase")]
     pub enum $t {
       $($vars),*
@@ -53,17 +56,94 @@ macro_rules! impl_serde_enum {
   }
 }
 
-#[derive(Serialize, Deserialize)]
+// (de)serializes bytes as base 64 strings, for fields that are also sent
+// raw in other formats
+mod base64_bytes {
+  use serde::{Deserialize, Deserializer, Serializer};
+  use serde::de::Error;
+
+  pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(bytes))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    base64::decode(&s).map_err(D::Error::custom)
+  }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct EmptySerde {}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct WriteToPartitionRequestSerde {
   pub table_name: String,
   #[serde(default)]
   pub partition: HashMap<String, Value>,
   pub rows: Vec<HashMap<String, Value>>,
+  // if provided, the rows are buffered until the transaction commits
+  #[serde(default)]
+  pub tx_id: Option<String>,
+  // instead of rejecting the request, leave invalid fields null and report
+  // them in the response
+  #[serde(default)]
+  pub lenient: bool,
+}
+
+// a frame sent over the /ws write socket
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteFrameSerde {
+  // echoed in the frame's ack, so clients can match them up
+  #[serde(default)]
+  pub id: Option<Value>,
+  #[serde(flatten)]
+  pub req: WriteToPartitionRequestSerde,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteAckSerde {
+  // the frame's position among those sent on the socket, from 0
+  pub seq: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<Value>,
+  // exactly one of response and error is present
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub response: Option<WriteToPartitionResponseSerde>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<ErrorResponse>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DroppedFieldSerde {
+  pub row_index: usize,
+  pub column_name: String,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteToPartitionResponseSerde {
+  // fields left null by a lenient write, in row order
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub dropped_fields: Vec<DroppedFieldSerde>,
+  // indices of the rows copied to the dead-letter table, in order; unless
+  // the write was lenient, these rows were not written to the table itself
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub dead_lettered_rows: Vec<usize>,
+  // the segment the rows went to; absent for transactional writes, which
+  // only get row ids when they commit
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub segment_id: Option<String>,
+  // the inclusive range of _row_id values assigned to the rows, in order
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_row_id: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_row_id: Option<u64>,
 }
 
 impl_serde_enum!(
@@ -90,7 +170,7 @@ impl Default for SchemaModeSerde {
   }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ColumnMetaSerde {
   pub dtype: DataTypeSerde,
@@ -98,18 +178,28 @@ pub struct ColumnMetaSerde {
   pub nested_list_depth: u32,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PartitionMetaSerde {
   pub dtype: PartitionDataTypeSerde,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SchemaSerde {
   #[serde(default)]
   pub partitioning: HashMap<String, PartitionMetaSerde>,
   pub columns: HashMap<String, ColumnMetaSerde>,
+  // compaction sorts each segment's rows by these columns, in order
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub sort_columns: Vec<String>,
+  // expressions like lowercase(s) for columns that flushes derive from
+  // another column instead of clients writing them
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub computed_columns: HashMap<String, String>,
+  // masking rules like hash or redact(0, 4) for sensitive columns
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub column_masks: HashMap<String, String>,
 }
 
 impl TryFrom<&Schema> for SchemaSerde {
@@ -129,7 +219,13 @@ impl TryFrom<&Schema> for SchemaSerde {
         nested_list_depth: v.nested_list_depth,
       });
     }
-    Ok(SchemaSerde { partitioning, columns })
+    Ok(SchemaSerde {
+      partitioning,
+      columns,
+      sort_columns: Vec::new(),
+      computed_columns: HashMap::new(),
+      column_masks: HashMap::new(),
+    })
   }
 }
 
@@ -151,36 +247,883 @@ impl From<&SchemaSerde> for Schema {
   }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateTableRequestSerde {
   pub table_name: String,
   pub schema: SchemaSerde,
   #[serde(default)]
   pub mode: SchemaModeSerde,
+  #[serde(default)]
+  pub config: TableConfigSerde,
 }
 
-#[derive(Serialize, Deserialize)]
+// Overrides of the server's runtime config for one table. Unset settings
+// follow the server's.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TableConfigSerde {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub target_rows_per_segment: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub target_uncompressed_bytes_per_segment: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_rows_for_compaction: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_compaction_intermission_seconds: Option<i64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub compact_as_constant_seconds: Option<i64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_compaction_increments: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub read_page_byte_size: Option<usize>,
+}
+
+impl From<&TableConfigSerde> for TableConfigOverrides {
+  fn from(config: &TableConfigSerde) -> Self {
+    TableConfigOverrides {
+      target_rows_per_segment: config.target_rows_per_segment,
+      target_uncompressed_bytes_per_segment: config.target_uncompressed_bytes_per_segment,
+      min_rows_for_compaction: config.min_rows_for_compaction,
+      min_compaction_intermission_seconds: config.min_compaction_intermission_seconds,
+      compact_as_constant_seconds: config.compact_as_constant_seconds,
+      max_compaction_increments: config.max_compaction_increments,
+      read_page_byte_size: config.read_page_byte_size,
+    }
+  }
+}
+
+impl From<&TableConfigOverrides> for TableConfigSerde {
+  fn from(overrides: &TableConfigOverrides) -> Self {
+    TableConfigSerde {
+      target_rows_per_segment: overrides.target_rows_per_segment,
+      target_uncompressed_bytes_per_segment: overrides.target_uncompressed_bytes_per_segment,
+      min_rows_for_compaction: overrides.min_rows_for_compaction,
+      min_compaction_intermission_seconds: overrides.min_compaction_intermission_seconds,
+      compact_as_constant_seconds: overrides.compact_as_constant_seconds,
+      max_compaction_increments: overrides.max_compaction_increments,
+      read_page_byte_size: overrides.read_page_byte_size,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateTableResponseSerde {
   pub already_exists: bool,
   pub columns_added: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DropTableRequestSerde {
   pub table_name: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FreezeTableRequestSerde {
+  pub table_name: String,
+  // false to unfreeze
+  #[serde(default = "default_frozen")]
+  pub frozen: bool,
+}
+
+fn default_frozen() -> bool {
+  true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UndropTableRequestSerde {
+  pub table_name: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedTableSerde {
+  pub table_name: String,
+  pub dropped_at: String,
+  pub purge_at: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListTrashResponseSerde {
+  // sorted by table name, then by when they were dropped
+  pub tables: Vec<TrashedTableSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogRequestSerde {
+  pub table_name: Option<String>,
+  pub operation: Option<String>,
+  // an RFC3339 timestamp
+  pub since: Option<String>,
+  pub max_results: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntrySerde {
+  pub at: String,
+  pub principal: String,
+  pub operation: String,
+  pub table_name: String,
+  #[serde(default, skip_serializing_if = "String::is_empty")]
+  pub summary: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogResponseSerde {
+  // newest first
+  pub entries: Vec<AuditEntrySerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameTableRequestSerde {
+  pub table_name: String,
+  pub new_table_name: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyTableRequestSerde {
+  pub table_name: String,
+  pub new_table_name: String,
+  // whether to copy the rows too, or only the schema
+  #[serde(default)]
+  pub include_data: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AlterTableRequestSerde {
+  pub table_name: String,
+  #[serde(default)]
+  pub new_columns: HashMap<String, ColumnMetaSerde>,
+  // from current name to new name
+  #[serde(default)]
+  pub rename_columns: HashMap<String, String>,
+  // if given, replaces all of the table's config overrides
+  #[serde(default)]
+  pub config: Option<TableConfigSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct TableInfoSerde {
   pub table_name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub stats: Option<TableStatsSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TableStatsSerde {
+  pub n_columns: usize,
+  pub n_segments: usize,
+  pub row_count: u64,
+  pub uncompressed_bytes: u64,
+  // as of the last time its segments were measured
+  pub disk_bytes: u64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListTablesRequestSerde {
+  #[serde(default)]
+  pub name_prefix: String,
+  // if set, return at most this many tables along with a continuation
+  // token for the next page
+  #[serde(default)]
+  pub max_results: Option<usize>,
+  #[serde(default)]
+  pub continuation_token: Option<String>,
+  // whether to look up every segment of each table for its stats
+  #[serde(default)]
+  pub include_stats: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ListTablesResponseSerde {
+  // sorted by name
   pub tables: Vec<TableInfoSerde>,
+  // absent on the last page
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub continuation_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSchemaRequestSerde {
+  pub table_name: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSchemaResponseSerde {
+  pub schema: SchemaSerde,
+  // incremented whenever the table is altered
+  pub schema_version: u64,
+  // absent for tables created before these were recorded
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub created_at: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub last_altered_at: Option<String>,
+  // by column name
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub column_added_at: HashMap<String, String>,
+  #[serde(default)]
+  pub frozen: bool,
+  #[serde(default)]
+  pub config: TableConfigSerde,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PartitionOperatorSerde {
+  Eq,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  In,
+  Between,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionFilterSerde {
+  pub name: String,
+  pub operator: PartitionOperatorSerde,
+  // one value to compare to, except any number for in and an inclusive
+  // [min, max] for between
+  pub values: Vec<Value>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSegmentsRequestSerde {
+  pub table_name: String,
+  // only segments whose partitions satisfy every filter are listed
+  #[serde(default)]
+  pub partition_filter: Vec<PartitionFilterSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentStatsSerde {
+  pub row_count: u64,
+  pub all_time_n: u64,
+  pub all_time_deleted_n: u64,
+  pub staged_n: u64,
+  pub uncompressed_bytes: u64,
+  pub read_version: u64,
+  pub is_cold: bool,
+  pub last_flush_at: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentInfoSerde {
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  pub stats: Option<SegmentStatsSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSegmentsResponseSerde {
+  pub segments: Vec<SegmentInfoSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginReadRequestSerde {
+  pub table_name: String,
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  // generated by the server if not provided
+  pub correlation_id: Option<String>,
+  // defaults to the server's correlation TTL
+  pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginReadResponseSerde {
+  pub correlation_id: String,
+  pub read_version: u64,
+  pub expires_at: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EndReadRequestSerde {
+  pub correlation_id: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EndReadResponseSerde {
+  pub was_pinned: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginSnapshotRequestSerde {
+  pub table_name: String,
+  // if given, only segments whose partitions have these values are included
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+  // generated by the server if not provided
+  pub snapshot_id: Option<String>,
+  // defaults to the server's correlation TTL
+  pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSegmentSerde {
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  pub read_version: u64,
+  pub deletion_id: u64,
+  pub row_count: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginSnapshotResponseSerde {
+  pub snapshot_id: String,
+  pub expires_at: String,
+  pub segments: Vec<SnapshotSegmentSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactTableRequestSerde {
+  pub table_name: String,
+  // compact every segment with rows to compact, ignoring the heuristics
+  #[serde(default)]
+  pub force: bool,
+  // queue forced compactions for the compaction loop instead of waiting
+  // for them
+  #[serde(default)]
+  pub background: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactTableResponseSerde {
+  pub n_segments_checked: u32,
+  pub n_segments_compacted: u32,
+  // whether the compactions were queued rather than run
+  pub queued: bool,
+  pub compacted_segments: Vec<CompactedSegmentSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactSegmentRequestSerde {
+  pub table_name: String,
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  // queue the compaction for the compaction loop instead of waiting for it
+  #[serde(default)]
+  pub background: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactSegmentResponseSerde {
+  pub queued: bool,
+  // None if queued
+  pub segment: Option<CompactedSegmentSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactedSegmentSerde {
+  pub segment_id: String,
+  // false if there was nothing to compact
+  pub compacted: bool,
+  // the version reads use once the compaction finished
+  pub read_version: u64,
+  pub all_time_compacted_n: u64,
+  pub all_time_omitted_n: u64,
+  pub n_increments: u32,
+  pub duration_millis: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckTableRequestSerde {
+  pub table_name: String,
+  #[serde(default)]
+  pub repair: bool,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckTableIssueKindSerde {
+  MissingSegmentMetadata,
+  MissingVersion,
+  DanglingVersion,
+  RowCountMismatch,
+  ChecksumMismatch,
+  DeletionMismatch,
+  UnreadableFile,
+  OrphanedTmpFile,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckTableIssueSerde {
+  pub kind: CheckTableIssueKindSerde,
+  pub path: String,
+  pub message: String,
+  pub repaired: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckTableResponseSerde {
+  pub n_segments_checked: u32,
+  pub issues: Vec<CheckTableIssueSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStatsSerde {
+  pub cache_name: String,
+  pub n_entries: usize,
+  pub size_limit: usize,
+  pub hits: u64,
+  pub misses: u64,
+  pub evictions: u64,
+  pub hit_rate: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStatsResponseSerde {
+  pub caches: Vec<CacheStatsSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TableDiskUsageSerde {
+  pub table_name: String,
+  pub bytes: u64,
+  pub is_over_soft_limit: bool,
+  pub is_over_hard_limit: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskUsageResponseSerde {
+  pub total_bytes: u64,
+  pub is_over_soft_limit: bool,
+  pub is_over_hard_limit: bool,
+  pub tables: Vec<TableDiskUsageSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReloadConfigResponseSerde {
+  pub changed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HeldLockSerde {
+  pub entity_name: String,
+  pub key: String,
+  pub is_write: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HeldLocksResponseSerde {
+  pub locks: Vec<HeldLockSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackgroundLoopSerde {
+  pub name: String,
+  pub iterations: u64,
+  pub last_started_at: Option<String>,
+  pub last_lag_millis: u64,
+  pub last_duration_millis: u64,
+  pub current_segment: Option<String>,
+  pub panics: u64,
+  pub last_panic: Option<String>,
+  pub last_panic_at: Option<String>,
+  pub restarting: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackgroundStatusResponseSerde {
+  pub flush_candidates: Vec<String>,
+  // segments waiting to be flushed, and how many have been queued and
+  // coalesced with one already queued since startup
+  pub flush_backlog: u64,
+  pub flush_candidates_added: u64,
+  pub flush_candidates_coalesced: u64,
+  // how long the longest-waiting segment has waited to be flushed, and the
+  // longest any has waited before the flush loop took it, since startup
+  pub oldest_flush_candidate_age_millis: u64,
+  pub max_flush_candidate_age_millis: u64,
+  // segments queued to be compacted regardless of heuristics, in order
+  pub forced_compactions: Vec<String>,
+  pub loops: Vec<BackgroundLoopSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopHealthSerde {
+  pub name: String,
+  pub healthy: bool,
+  pub panics: u64,
+  pub last_panic_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessResponseSerde {
+  pub ready: bool,
+  pub loops: Vec<LoopHealthSerde>,
+  // set when the server takes no writes until restarted
+  pub unhealthy_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StagedSegmentsRequestSerde {
+  #[serde(default)]
+  pub table_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StagedSegmentSerde {
+  pub segment: String,
+  pub staged_n: u64,
+  pub flushing: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StagedSegmentsResponseSerde {
+  pub segments: Vec<StagedSegmentSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentErrorSerde {
+  pub at: String,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentErrorsResponseSerde {
+  pub errors: Vec<RecentErrorSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginTxRequestSerde {
+  pub table_name: String,
+  // defaults to the server's transaction TTL
+  pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BeginTxResponseSerde {
+  pub tx_id: String,
+  pub expires_at: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitTxRequestSerde {
+  pub tx_id: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitTxResponseSerde {
+  pub n_partitions: usize,
+  pub n_rows: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortTxRequestSerde {
+  pub tx_id: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortTxResponseSerde {
+  pub was_open: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadChangesRequestSerde {
+  pub table_name: String,
+  // from a previous response; omit to start from the beginning of the table
+  #[serde(default)]
+  pub cursor: Option<String>,
+  // defaults to the server's limit
+  #[serde(default)]
+  pub max_rows: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ChangeEventSerde {
+  #[serde(rename_all = "camelCase")]
+  Rows {
+    partition: HashMap<String, Value>,
+    segment_id: String,
+    rows: Vec<HashMap<String, Value>>,
+  },
+  #[serde(rename_all = "camelCase")]
+  Deletions {
+    partition: HashMap<String, Value>,
+    segment_id: String,
+    row_ids: Vec<u32>,
+  },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadChangesResponseSerde {
+  pub events: Vec<ChangeEventSerde>,
+  // resumes after the last event returned
+  pub cursor: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscribeChangesRequestSerde {
+  pub table_name: String,
+  #[serde(default)]
+  pub cursor: Option<String>,
+  // how long to wait between polls once caught up; defaults to 1000
+  #[serde(default)]
+  pub poll_interval_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetColumnSketchRequestSerde {
+  pub table_name: String,
+  pub column_name: String,
+  // if given, only segments whose partitions have these values are included
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetColumnSketchResponseSerde {
+  pub approx_distinct_count: u64,
+  // the merged sketch, for clients to merge with others
+  pub sketch: String,
+  pub n_segments: usize,
+  pub n_sketched_segments: usize,
+  // rows not yet compacted, and therefore missing from the sketch
+  pub n_unsketched_rows: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CountRowsRequestSerde {
+  pub table_name: String,
+  // if given, only segments whose partitions have these values are counted
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+  // and satisfy every one of these filters
+  #[serde(default)]
+  pub partition_filter: Vec<PartitionFilterSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CountRowsResponseSerde {
+  // live rows, staged ones included
+  pub row_count: u64,
+  pub n_segments: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetBloomFilterColumnsRequestSerde {
+  pub table_name: String,
+  // replaces the table's previous bloom filter columns
+  pub columns: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetColumnMasksRequestSerde {
+  pub table_name: String,
+  // masking rules by column name, replacing the table's previous ones
+  pub column_masks: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckSegmentContainsRequestSerde {
+  pub table_name: String,
+  pub column_name: String,
+  pub value: Value,
+  // if given, only segments whose partitions have these values are checked
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentContainsSerde {
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  // false only if the segment definitely does not contain the value
+  pub may_contain: bool,
+  pub used_bloom_filter: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckSegmentContainsResponseSerde {
+  pub segments: Vec<SegmentContainsSerde>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRowsByIdRequestSerde {
+  pub table_name: String,
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  pub row_ids: Vec<u32>,
+  // defaults to every column
+  #[serde(default)]
+  pub columns: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRowsByIdResponseSerde {
+  // in the order requested, each with its _row_id
+  pub rows: Vec<HashMap<String, Value>>,
+  // requested row ids that were deleted or never written
+  pub missing_row_ids: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSegmentsRequestSerde {
+  pub table_name: String,
+  pub partition: HashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSegmentsResponseSerde {
+  // the new segment, if any segments were merged
+  pub segment_id: Option<String>,
+  pub merged_segment_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneMapPredicateSerde {
+  pub column_name: String,
+  // inclusive bounds; omit either for a one-sided range
+  #[serde(default)]
+  pub min: Option<Value>,
+  #[serde(default)]
+  pub max: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadSegmentColumnRequestSerde {
+  pub table_name: String,
+  #[serde(default)]
+  pub partition: HashMap<String, Value>,
+  pub segment_id: String,
+  pub column_name: String,
+  // if omitted, the read uses its own correlation id
+  #[serde(default)]
+  pub correlation_id: Option<String>,
+  #[serde(default)]
+  pub predicate: Option<ZoneMapPredicateSerde>,
+  // inclusive bounds on _written_at; with either, only the live rows
+  // written within them are returned, all in flushed_data
+  #[serde(default)]
+  pub min_written_at: Option<Value>,
+  #[serde(default)]
+  pub max_written_at: Option<Value>,
+  // With either, only the live rows are returned, all in flushed_data: at
+  // most limit of them, and each with probability sample_rate. Reads of
+  // different columns under the same correlation id get the same rows.
+  #[serde(default)]
+  pub limit: Option<u32>,
+  #[serde(default)]
+  pub sample_rate: Option<f64>,
+  // Leaves deleted rows out, so clients needn't read_segment_deletions and
+  // zip them with the values. The live rows all come back in flushed_data,
+  // implicit nulls included, so deletion_count and implicit_nulls_count
+  // are 0. Implied by a written_at range, limit, or sample rate.
+  #[serde(default)]
+  pub apply_deletions: bool,
+  // Newest rows first: flushed_data starts with the newest staged row,
+  // though compacted_data is still oldest first. With a written_at range,
+  // limit, or sample rate, the limit keeps the newest rows.
+  #[serde(default)]
+  pub reverse: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedRowsSerde {
+  pub row_offset: u32,
+  pub n_rows: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadSegmentColumnResponseSerde {
+  pub row_count: u32,
+  pub deletion_count: u32,
+  pub implicit_nulls_count: u32,
+  pub codec: String,
+  // compressed with the codec
+  #[serde(with = "base64_bytes")]
+  #[schemars(with = "String")]
+  pub compacted_data: Vec<u8>,
+  // encoded but uncompressed
+  #[serde(with = "base64_bytes")]
+  #[schemars(with = "String")]
+  pub flushed_data: Vec<u8>,
+  // flushed rows left out of flushed_data because of the predicate
+  pub skipped_rows: Vec<SkippedRowsSerde>,
 }
@@ -0,0 +1,128 @@
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::sync::Mutex;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::serde_models::AuditEntrySerde;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+use super::authz;
+use super::Server;
+
+// a DDL or deletion op worth recording in the audit log
+pub struct AuditEvent {
+  pub operation: &'static str,
+  pub table_name: String,
+  pub summary: String,
+}
+
+pub fn join_sorted<'a>(names: impl Iterator<Item=&'a String>) -> String {
+  let mut names = names.map(|name| name.as_str()).collect::<Vec<_>>();
+  names.sort_unstable();
+  names.join(", ")
+}
+
+// serializes appends to the audit log
+#[derive(Clone, Default)]
+pub struct AuditLog {
+  mutex: Arc<Mutex<()>>,
+}
+
+pub struct AuditFilter {
+  pub table_name: Option<String>,
+  pub operation: Option<String>,
+  pub since: Option<DateTime<Utc>>,
+  pub max_results: usize,
+}
+
+impl AuditFilter {
+  fn matches(&self, entry: &AuditEntrySerde) -> ServerResult<bool> {
+    if self.table_name.as_ref().map(|name| name != &entry.table_name).unwrap_or(false) ||
+      self.operation.as_ref().map(|op| op != &entry.operation).unwrap_or(false) {
+      return Ok(false);
+    }
+    if let Some(since) = &self.since {
+      let at = DateTime::parse_from_rfc3339(&entry.at)
+        .map_err(|_| ServerError::corrupt(format!("audit log entry has invalid time {}", entry.at)))?;
+      if at < *since {
+        return Ok(false);
+      }
+    }
+    Ok(true)
+  }
+}
+
+impl Server {
+  // Appends an op to the audit log, whether it succeeded or not. The op has
+  // already happened, so failing to record it is only logged.
+  pub async fn record_audit_event(&self, event: AuditEvent, maybe_error: Option<&ServerError>) {
+    if self.opts.read_only {
+      return;
+    }
+
+    let entry = AuditEntrySerde {
+      at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+      principal: authz::current_principal().id().to_string(),
+      operation: event.operation.to_string(),
+      table_name: event.table_name,
+      summary: event.summary,
+      error: maybe_error.map(|e| e.to_client_string()),
+    };
+    if let Err(e) = self.append_audit_entry(&entry).await {
+      log::error!(
+        "failed to record {} of table {} in the audit log: {}",
+        entry.operation,
+        entry.table_name,
+        e,
+      );
+    }
+  }
+
+  async fn append_audit_entry(&self, entry: &AuditEntrySerde) -> ServerResult<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let _guard = self.audit_log.mutex.lock().await;
+    let mut file = vfs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(dirs::audit_log_path(&self.opts.dir))
+      .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.sync_data().await?;
+    Ok(())
+  }
+
+  // the newest matching entries, newest first
+  pub async fn read_audit_log(&self, filter: &AuditFilter) -> ServerResult<Vec<AuditEntrySerde>> {
+    let contents = {
+      let _guard = self.audit_log.mutex.lock().await;
+      match vfs::read_to_string(dirs::audit_log_path(&self.opts.dir)).await {
+        Ok(contents) => contents,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+      }
+    };
+
+    let mut res = Vec::new();
+    for line in contents.lines().rev() {
+      if res.len() >= filter.max_results {
+        break;
+      }
+      // a crash during an append can leave a partial line
+      let entry: AuditEntrySerde = match serde_json::from_str(line) {
+        Ok(entry) => entry,
+        Err(e) => {
+          log::warn!("skipping invalid audit log entry: {}", e);
+          continue;
+        },
+      };
+      if filter.matches(&entry)? {
+        res.push(entry);
+      }
+    }
+    Ok(res)
+  }
+}
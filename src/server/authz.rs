@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::Future;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::table::TableMetadata;
+use crate::utils::masking::MaskingRule;
+
+use super::Server;
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+tokio::task_local! {
+  static PRINCIPAL: Principal;
+}
+
+// Who a request is running on behalf of. Work outside any request, like
+// recovery and the background loops, runs as Internal and may do anything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Principal {
+  Internal,
+  Anonymous,
+  Named(String),
+}
+
+impl Display for Principal {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Principal::Internal => write!(f, "the server"),
+      Principal::Anonymous => write!(f, "an anonymous request"),
+      Principal::Named(name) => write!(f, "principal {}", name),
+    }
+  }
+}
+
+impl Principal {
+  // a short identifier, for records like the audit log
+  pub fn id(&self) -> &str {
+    match self {
+      Principal::Internal => "<server>",
+      Principal::Anonymous => "<anonymous>",
+      Principal::Named(name) => name,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verb {
+  Create,
+  Read,
+  Write,
+  Delete,
+  // sees the values of masked columns as written
+  Unmask,
+  // alters a table's settings; an admin grant also allows every other verb
+  Admin,
+}
+
+// A verb an op needs, either on one table or on the server as a whole.
+// Server-wide access is only granted by grants whose patterns include *.
+#[derive(Clone, Debug)]
+pub struct Access {
+  pub verb: Verb,
+  pub table_name: Option<String>,
+}
+
+impl Access {
+  pub fn table(verb: Verb, table_name: &str) -> Self {
+    Access {
+      verb,
+      table_name: Some(table_name.to_string()),
+    }
+  }
+
+  pub fn server(verb: Verb) -> Self {
+    Access {
+      verb,
+      table_name: None,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthzConfig {
+  // roles of requests that don't present an API key
+  #[serde(default)]
+  anonymous_roles: Vec<String>,
+  #[serde(default)]
+  roles: HashMap<String, Role>,
+  #[serde(default)]
+  principals: HashMap<String, PrincipalConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Role {
+  grants: Vec<Grant>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Grant {
+  verbs: HashSet<Verb>,
+  // table name patterns, where * matches any run of characters
+  tables: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrincipalConfig {
+  api_key: String,
+  roles: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct Authz {
+  config: Arc<RwLock<Option<AuthzConfig>>>,
+}
+
+fn pattern_matches(pattern: &str, name: &str) -> bool {
+  let mut parts = pattern.split('*');
+  let first = parts.next().unwrap_or("");
+  let mut rest = match name.strip_prefix(first) {
+    Some(rest) => rest,
+    None => return false,
+  };
+  let mut parts = parts.collect::<Vec<_>>();
+  let last = match parts.pop() {
+    Some(last) => last,
+    // no wildcards
+    None => return rest.is_empty(),
+  };
+  for part in parts {
+    match rest.find(part) {
+      Some(idx) => rest = &rest[idx + part.len()..],
+      None => return false,
+    }
+  }
+  rest.ends_with(last)
+}
+
+impl Grant {
+  fn allows(&self, access: &Access) -> bool {
+    if !self.verbs.contains(&access.verb) && !self.verbs.contains(&Verb::Admin) {
+      return false;
+    }
+    match &access.table_name {
+      Some(table_name) => self.tables.iter().any(|pattern| pattern_matches(pattern, table_name)),
+      None => self.tables.iter().any(|pattern| pattern == "*"),
+    }
+  }
+}
+
+impl AuthzConfig {
+  async fn load(path: &Path) -> ServerResult<Self> {
+    let bytes = tokio::fs::read(path).await
+      .map_err(|e| ServerError::invalid(format!("could not read authz file {:?}: {}", path, e)))?;
+    let toml_str = String::from_utf8(bytes)
+      .map_err(|_| ServerError::invalid("non-utf8 authz file"))?;
+    let config: AuthzConfig = toml::from_str(&toml_str)
+      .map_err(|e| ServerError::invalid(format!("invalid authz file {:?}: {}", path, e)))?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  fn validate(&self) -> ServerResult<()> {
+    let role_names = self.principals.values()
+      .flat_map(|principal| principal.roles.iter())
+      .chain(self.anonymous_roles.iter());
+    for role_name in role_names {
+      if !self.roles.contains_key(role_name) {
+        return Err(ServerError::invalid(format!("authz file refers to unknown role {}", role_name)));
+      }
+    }
+    let mut api_keys = HashSet::new();
+    for (name, principal) in &self.principals {
+      if principal.api_key.is_empty() || !api_keys.insert(&principal.api_key) {
+        return Err(ServerError::invalid(format!("principal {} must have a nonempty, unique API key", name)));
+      }
+    }
+    Ok(())
+  }
+
+  fn principal_for_key(&self, api_key: &str) -> Option<Principal> {
+    self.principals.iter()
+      .find(|(_, principal)| principal.api_key == api_key)
+      .map(|(name, _)| Principal::Named(name.clone()))
+  }
+
+  fn is_allowed(&self, principal: &Principal, access: &Access) -> bool {
+    let role_names = match principal {
+      Principal::Internal => return true,
+      Principal::Anonymous => &self.anonymous_roles,
+      Principal::Named(name) => match self.principals.get(name) {
+        Some(principal) => &principal.roles,
+        // removed by a config reload
+        None => return false,
+      },
+    };
+    role_names.iter()
+      .filter_map(|role_name| self.roles.get(role_name))
+      .flat_map(|role| role.grants.iter())
+      .any(|grant| grant.allows(access))
+  }
+}
+
+// the principal of the request this task is serving
+pub fn current_principal() -> Principal {
+  PRINCIPAL.try_with(|principal| principal.clone())
+    .unwrap_or(Principal::Internal)
+}
+
+// runs a future on behalf of a principal
+pub async fn scope<F: Future>(principal: Principal, future: F) -> F::Output {
+  PRINCIPAL.scope(principal, future).await
+}
+
+impl Server {
+  pub fn is_authz_enabled(&self) -> bool {
+    self.opts.authz_file.is_some()
+  }
+
+  pub async fn load_authz_config(&self) -> ServerResult<()> {
+    if let Some(path) = &self.opts.authz_file {
+      let config = AuthzConfig::load(path).await?;
+      log::info!(
+        "loaded authz config with {} roles and {} principals",
+        config.roles.len(),
+        config.principals.len(),
+      );
+      *self.authz.config.write().await = Some(config);
+    }
+    Ok(())
+  }
+
+  // Requests without an API key are anonymous. When authz is disabled,
+  // API keys are ignored.
+  pub async fn authenticate(&self, maybe_api_key: Option<&str>) -> ServerResult<Principal> {
+    let config_guard = self.authz.config.read().await;
+    let (config, api_key) = match (config_guard.as_ref(), maybe_api_key) {
+      (Some(config), Some(api_key)) => (config, api_key),
+      _ => return Ok(Principal::Anonymous),
+    };
+    config.principal_for_key(api_key)
+      .ok_or_else(|| ServerError::unauthenticated("unrecognized API key"))
+  }
+
+  // authenticates the value of an HTTP Authorization header or its GRPC
+  // metadata equivalent
+  pub async fn authenticate_bearer(&self, maybe_authorization: Option<&str>) -> ServerResult<Principal> {
+    let maybe_api_key = match maybe_authorization {
+      Some(authorization) => Some(
+        authorization.strip_prefix(BEARER_PREFIX)
+          .map(|api_key| api_key.trim())
+          .ok_or_else(|| ServerError::unauthenticated("authorization must be of the form \"Bearer <API key>\""))?
+      ),
+      None => None,
+    };
+    self.authenticate(maybe_api_key).await
+  }
+
+  pub async fn authorize(&self, accesses: &[Access]) -> ServerResult<()> {
+    let config_guard = self.authz.config.read().await;
+    let config = match config_guard.as_ref() {
+      Some(config) => config,
+      None => return Ok(()),
+    };
+    let principal = current_principal();
+    for access in accesses {
+      if !config.is_allowed(&principal, access) {
+        let target = match &access.table_name {
+          Some(table_name) => format!("table {}", table_name),
+          None => "the server".to_string(),
+        };
+        return Err(ServerError::permission_denied(format!(
+          "{} may not {} {}",
+          principal,
+          format!("{:?}", access.verb).to_lowercase(),
+          target,
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  pub async fn is_authorized(&self, access: &Access) -> bool {
+    self.authorize(std::slice::from_ref(access)).await.is_ok()
+  }
+
+  // The masks the current principal's reads of a table get, keyed by
+  // stored column name. Without authz, nothing is masked.
+  pub async fn read_masks(&self, table_name: &str, table_meta: &TableMetadata) -> HashMap<String, MaskingRule> {
+    if table_meta.column_masks.is_empty() || self.is_authorized(&Access::table(Verb::Unmask, table_name)).await {
+      HashMap::new()
+    } else {
+      table_meta.column_masks.clone()
+    }
+  }
+}
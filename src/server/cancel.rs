@@ -0,0 +1,115 @@
+use std::sync::{Arc, Mutex};
+
+use futures::Future;
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::errors::{ServerError, ServerResult};
+
+tokio::task_local! {
+  static CANCELLATION: Cancellation;
+}
+
+// Lets a request or op be stopped early, when its client goes away or it
+// runs past its timeout. Ops only stop at points where stopping leaves
+// nothing half done, and release their locks as they return the error.
+#[derive(Clone, Default)]
+pub struct Cancellation {
+  token: CancellationToken,
+  timeout: Arc<Mutex<Option<Duration>>>,
+}
+
+impl Cancellation {
+  // cancelled along with this one, but can also be cancelled on its own
+  pub fn child(&self) -> Cancellation {
+    Cancellation {
+      token: self.token.child_token(),
+      timeout: Arc::default(),
+    }
+  }
+
+  pub fn cancel(&self) {
+    self.token.cancel();
+  }
+
+  pub fn time_out(&self, timeout: Duration) {
+    *self.timeout.lock().unwrap() = Some(timeout);
+    self.token.cancel();
+  }
+
+  pub fn error(&self) -> ServerError {
+    match *self.timeout.lock().unwrap() {
+      Some(timeout) => ServerError::timed_out(format!(
+        "op did not finish within its {}s timeout",
+        timeout.as_secs(),
+      )),
+      None => ServerError::cancelled("request was cancelled by the client"),
+    }
+  }
+
+  pub fn check(&self) -> ServerResult<()> {
+    if self.token.is_cancelled() {
+      Err(self.error())
+    } else {
+      Ok(())
+    }
+  }
+
+  pub async fn cancelled(&self) {
+    self.token.cancelled().await
+  }
+}
+
+// cancels a request's work when the request's future is dropped
+struct CancelOnDrop(Cancellation);
+
+impl Drop for CancelOnDrop {
+  fn drop(&mut self) {
+    self.0.cancel();
+  }
+}
+
+pub fn current() -> Option<Cancellation> {
+  CANCELLATION.try_with(|cancellation| cancellation.clone()).ok()
+}
+
+// returns an error if the current request or op has been cancelled
+pub fn check() -> ServerResult<()> {
+  match current() {
+    Some(cancellation) => cancellation.check(),
+    None => Ok(()),
+  }
+}
+
+// runs a future that can be cancelled
+pub async fn scope<F: Future>(cancellation: Cancellation, future: F) -> F::Output {
+  CANCELLATION.scope(cancellation, future).await
+}
+
+// Runs a future, but gives up with an error if the current request or op
+// is cancelled first. Only for futures that are safe to drop midway, like
+// waiting for a lock.
+pub async fn unless_cancelled<F: Future>(future: F) -> ServerResult<F::Output> {
+  let cancellation = match current() {
+    Some(cancellation) => cancellation,
+    None => return Ok(future.await),
+  };
+  tokio::select! {
+    biased;
+    res = future => Ok(res),
+    _ = cancellation.cancelled() => Err(cancellation.error()),
+  }
+}
+
+// Runs a request's work in its own task. Servers drop the futures of
+// requests whose clients hang up, which could stop a write halfway, so
+// instead the work is cancelled and stops at its next safe point.
+pub async fn spawn_request<F, T>(future: F) -> ServerResult<T>
+where F: Future<Output=ServerResult<T>> + Send + 'static, T: Send + 'static {
+  let cancellation = Cancellation::default();
+  let _cancel_on_drop = CancelOnDrop(cancellation.clone());
+  match tokio::spawn(scope(cancellation, future)).await {
+    Ok(res) => res,
+    Err(e) => Err(ServerError::internal(format!("request task failed: {}", e))),
+  }
+}
@@ -0,0 +1,57 @@
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::table::TableMetadata;
+use crate::opt::{Opt, RuntimeConfig};
+
+use super::Server;
+
+impl Server {
+  pub async fn runtime_config(&self) -> RuntimeConfig {
+    self.runtime_config.read().await.clone()
+  }
+
+  // the runtime config with the table's overrides applied
+  pub async fn table_runtime_config(&self, table_meta: &TableMetadata) -> RuntimeConfig {
+    self.runtime_config().await.with_overrides(&table_meta.config_overrides)
+  }
+
+  // Re-reads the command line and config file and applies any dynamic
+  // settings. Returns the names of the settings that changed.
+  pub async fn reload_config(&self) -> ServerResult<Vec<String>> {
+    let new_opts = Opt::try_from_waterfall()?;
+    let new_config = RuntimeConfig::from(&new_opts);
+
+    // options outside RuntimeConfig only take effect on restart
+    let startup_config = RuntimeConfig::from(&self.opts);
+    let mut new_static_opts = new_opts.clone();
+    new_static_opts.apply_runtime_config(&startup_config);
+    if format!("{:?}", new_static_opts) != format!("{:?}", self.opts) {
+      log::warn!("ignoring changes to options that require a restart");
+    }
+
+    self.load_authz_config().await?;
+
+    let mut config_guard = self.runtime_config.write().await;
+    let changed = config_guard.changed_fields(&new_config)
+      .iter()
+      .map(|field| field.to_string())
+      .collect::<Vec<_>>();
+    log::set_max_level(new_config.log_level);
+    *config_guard = new_config;
+    log::info!("reloaded config; changed {:?}", changed);
+    Ok(changed)
+  }
+
+  pub async fn reload_config_on_hangup(&self) -> ServerResult<()> {
+    let mut hangups = signal(SignalKind::hangup())
+      .map_err(|e| ServerError::internal(format!("unable to listen for SIGHUP: {}", e)))?;
+    while hangups.recv().await.is_some() {
+      log::info!("received SIGHUP; reloading config");
+      if let Err(e) = self.reload_config().await {
+        log::error!("reloading config failed: {}", e);
+      }
+    }
+    Ok(())
+  }
+}
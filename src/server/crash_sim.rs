@@ -0,0 +1,385 @@
+// Deterministic crash simulations: each scenario is a seeded script of
+// writes, flushes, compactions, and deletions, run against a server on an
+// in-memory dir. A clean run counts the script's filesystem mutations, then
+// the script is rerun once per mutation with a fault injected there, after
+// which a fresh server recovers the dir and we check that
+// * check_table finds no issues,
+// * every acknowledged write is readable exactly once and every
+//   acknowledged deletion is gone,
+// * the interrupted op took effect entirely or not at all, and
+// * the recovered server can write, flush, and compact again.
+// Background loops never run, so each run makes the same mutations in the
+// same order.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use pancake_db_idl::ddl::CreateTableRequest;
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{DeleteFromSegmentRequest, FieldValue, ListSegmentsRequest, Row, Segment, WriteToPartitionRequest};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::table::TableConfigOverrides;
+use crate::ops::check_table::CheckTableOp;
+use crate::ops::compact::CompactionOp;
+use crate::ops::create_table::CreateTableOp;
+use crate::ops::delete_from_segment::DeleteFromSegmentOp;
+use crate::ops::flush::FlushOp;
+use crate::ops::list_segments::ListSegmentsOp;
+use crate::ops::traits::ServerOp;
+use crate::ops::write_to_partition::WriteToPartitionOp;
+use crate::opt::Opt;
+use crate::serde_models::CheckTableRequestSerde;
+use crate::types::{NormalizedPartition, SegmentKey};
+use crate::utils::common;
+use crate::utils::memory_fs::{Fault, MemoryFs};
+use crate::utils::vfs;
+
+use super::Server;
+
+const TABLE_NAME: &str = "sim";
+const COLUMN_NAME: &str = "value";
+
+#[derive(Clone, Debug)]
+enum Step {
+  Write(Vec<i64>),
+  Flush,
+  Compact,
+  Delete(i64),
+}
+
+fn script(seed: u64, n_steps: usize) -> Vec<Step> {
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut next_value = 0;
+  let mut written = Vec::new();
+  let mut steps = Vec::new();
+  for _ in 0..n_steps {
+    let step = match rng.gen_range(0..10) {
+      0..=3 => {
+        let n_rows = rng.gen_range(1..4);
+        let values = (next_value..next_value + n_rows).collect::<Vec<_>>();
+        next_value += n_rows;
+        written.extend(values.iter().cloned());
+        Step::Write(values)
+      },
+      4..=5 => Step::Flush,
+      6..=7 => Step::Compact,
+      _ if !written.is_empty() => {
+        let value = written.remove(rng.gen_range(0..written.len()));
+        Step::Delete(value)
+      },
+      _ => Step::Flush,
+    };
+    steps.push(step);
+  }
+  steps
+}
+
+struct Sim {
+  dir: PathBuf,
+  memory_fs: Arc<MemoryFs>,
+}
+
+impl Sim {
+  fn new() -> Self {
+    let dir = PathBuf::from(format!("/pancake-db-crash-sim/{}", Uuid::new_v4()));
+    vfs::mount_memory(&dir);
+    let memory_fs = vfs::memory_fs(&dir).expect("just mounted");
+    Sim {
+      dir,
+      memory_fs,
+    }
+  }
+
+  // like starting the server process, minus its listeners and loops
+  async fn start(&self) -> ServerResult<Server> {
+    let opts = sim_opts(&self.dir);
+    opts.validate();
+    let server = Server::new(opts);
+    server.recover().await?;
+    // creates the dirs and metadata the loops would need, without
+    // starting them
+    drop(server.init().await?);
+    Ok(server)
+  }
+}
+
+impl Drop for Sim {
+  fn drop(&mut self) {
+    vfs::unmount_memory(&self.dir);
+  }
+}
+
+fn sim_opts(dir: &Path) -> Opt {
+  Opt::from_iter_safe(vec![
+    "pancake-db-server",
+    "--dir",
+    dir.to_str().unwrap(),
+    "--min-rows-for-compaction",
+    "1",
+    "--min-compaction-intermission-seconds",
+    "0",
+  ]).unwrap()
+}
+
+fn value_row(value: i64) -> Row {
+  let mut row = Row::default();
+  row.fields.insert(COLUMN_NAME.to_string(), FieldValue {
+    value: Some(Value::Int64Val(value)),
+  });
+  row
+}
+
+async fn create_table(server: &Server) -> ServerResult<()> {
+  let mut columns = HashMap::new();
+  columns.insert(COLUMN_NAME.to_string(), ColumnMeta {
+    dtype: DataType::Int64 as i32,
+    ..Default::default()
+  });
+  CreateTableOp {
+    req: CreateTableRequest {
+      table_name: TABLE_NAME.to_string(),
+      schema: Some(Schema {
+        columns,
+        ..Default::default()
+      }),
+      mode: SchemaMode::FailIfExists as i32,
+    },
+    sort_columns: Vec::new(),
+    computed_columns: HashMap::new(),
+    column_masks: HashMap::new(),
+    config_overrides: TableConfigOverrides::default(),
+  }.execute(server).await?;
+  Ok(())
+}
+
+async fn segments(server: &Server) -> ServerResult<Vec<Segment>> {
+  let resp = ListSegmentsOp {
+    req: ListSegmentsRequest {
+      table_name: TABLE_NAME.to_string(),
+      ..Default::default()
+    },
+    partition_conditions: Vec::new(),
+  }.execute(server).await?;
+  Ok(resp.segments)
+}
+
+fn segment_key(segment: &Segment) -> ServerResult<SegmentKey> {
+  Ok(SegmentKey {
+    table_name: TABLE_NAME.to_string(),
+    partition: NormalizedPartition::from_raw_fields(&segment.partition)?,
+    segment_id: Uuid::from_str(&segment.segment_id)?,
+  })
+}
+
+fn int_field(row: &Row, column_name: &str) -> ServerResult<i64> {
+  match row.fields.get(column_name).and_then(|field| field.value.as_ref()) {
+    Some(Value::Int64Val(x)) => Ok(*x),
+    other => Err(ServerError::internal(format!("unexpected {} value {:?}", column_name, other))),
+  }
+}
+
+// every value with the segment and row id it was read from
+async fn read_values(server: &Server) -> ServerResult<Vec<(i64, Segment, u32)>> {
+  let table_meta = server.table_metadata_cache.get_lock(&TABLE_NAME.to_string())
+    .await?
+    .read()
+    .await
+    .clone()
+    .ok_or_else(|| ServerError::does_not_exist("table", &TABLE_NAME))?;
+  let augmented_cols = common::augmented_columns(&table_meta.schema());
+  let mut columns = HashMap::new();
+  for column_name in [COLUMN_NAME, ROW_ID_COLUMN_NAME] {
+    columns.insert(column_name.to_string(), augmented_cols[column_name].clone());
+  }
+
+  let mut res = Vec::new();
+  for segment in segments(server).await? {
+    for row in server.decode_segment(TABLE_NAME, &segment, &columns).await? {
+      let row_id = int_field(&row, ROW_ID_COLUMN_NAME)? as u32;
+      res.push((int_field(&row, COLUMN_NAME)?, segment.clone(), row_id));
+    }
+  }
+  Ok(res)
+}
+
+async fn run_step(server: &Server, step: &Step) -> ServerResult<()> {
+  match step {
+    Step::Write(values) => {
+      WriteToPartitionOp {
+        req: WriteToPartitionRequest {
+          table_name: TABLE_NAME.to_string(),
+          rows: values.iter().map(|value| value_row(*value)).collect(),
+          ..Default::default()
+        },
+      }.execute(server).await?;
+    },
+    Step::Flush => {
+      for segment in segments(server).await? {
+        let key = segment_key(&segment)?;
+        // like the flush loop, only flush segments with staged rows
+        let segment_meta = server.segment_metadata_cache.get_lock(&key)
+          .await?
+          .read()
+          .await
+          .clone();
+        if segment_meta.map(|meta| meta.staged_n > 0).unwrap_or(false) {
+          FlushOp { segment_key: key }.execute(server).await?;
+        }
+      }
+    },
+    Step::Compact => {
+      for segment in segments(server).await? {
+        CompactionOp { key: segment_key(&segment)?, force: false }.execute(server).await?;
+      }
+    },
+    Step::Delete(value) => {
+      let located = read_values(server).await?
+        .into_iter()
+        .find(|(read_value, _, _)| read_value == value);
+      if let Some((_, segment, row_id)) = located {
+        DeleteFromSegmentOp {
+          req: DeleteFromSegmentRequest {
+            table_name: TABLE_NAME.to_string(),
+            partition: segment.partition.clone(),
+            segment_id: segment.segment_id.clone(),
+            row_ids: vec![row_id],
+          },
+        }.execute(server).await?;
+      }
+    },
+  }
+  Ok(())
+}
+
+// what the clients were told before the crash
+#[derive(Default)]
+struct Acked {
+  values: BTreeSet<i64>,
+  interrupted: Option<Step>,
+}
+
+async fn run_until_crash(sim: &Sim, steps: &[Step]) -> Acked {
+  let mut acked = Acked::default();
+  let server = match sim.start().await {
+    Ok(server) => server,
+    Err(_) => return acked,
+  };
+  if create_table(&server).await.is_err() || sim.memory_fs.has_crashed() {
+    return acked;
+  }
+  for step in steps {
+    let res = run_step(&server, step).await;
+    // an op that finished after the crash never got to reply
+    if res.is_err() || sim.memory_fs.has_crashed() {
+      acked.interrupted = Some(step.clone());
+      break;
+    }
+    match step {
+      Step::Write(values) => acked.values.extend(values.iter().cloned()),
+      Step::Delete(value) => {
+        acked.values.remove(value);
+      },
+      _ => (),
+    }
+  }
+  acked
+}
+
+async fn check_recovered(sim: &Sim, acked: &Acked, context: &str) {
+  let server = sim.start().await
+    .unwrap_or_else(|e| panic!("{}: recovery failed: {}", context, e));
+  if segments(&server).await.is_err() {
+    // the crash came before the table existed
+    assert!(acked.values.is_empty(), "{}: lost the table", context);
+    return;
+  }
+
+  let report = CheckTableOp {
+    req: CheckTableRequestSerde {
+      table_name: TABLE_NAME.to_string(),
+      repair: false,
+    },
+  }.execute(&server).await
+    .unwrap_or_else(|e| panic!("{}: check failed: {}", context, e));
+  assert!(
+    report.issues.is_empty(),
+    "{}: check found issues {}",
+    context,
+    serde_json::to_string(&report.issues).unwrap(),
+  );
+
+  let read = read_values(&server).await
+    .unwrap_or_else(|e| panic!("{}: read failed: {}", context, e))
+    .into_iter()
+    .map(|(value, _, _)| value)
+    .collect::<Vec<_>>();
+  let read_set = read.iter().cloned().collect::<BTreeSet<_>>();
+  assert_eq!(read.len(), read_set.len(), "{}: read duplicate values {:?}", context, read);
+  let mut expected = acked.values.clone();
+  match &acked.interrupted {
+    Some(Step::Write(values)) => {
+      let n_present = values.iter().filter(|value| read_set.contains(value)).count();
+      assert!(
+        n_present == 0 || n_present == values.len(),
+        "{}: interrupted write of {:?} partly survived as {:?}",
+        context,
+        values,
+        read_set,
+      );
+      if n_present > 0 {
+        expected.extend(values.iter().cloned());
+      }
+    },
+    Some(Step::Delete(value)) if !read_set.contains(value) => {
+      expected.remove(value);
+    },
+    _ => (),
+  }
+  assert_eq!(read_set, expected, "{}: wrong values after recovery", context);
+
+  let next_value = read_set.iter().max().map(|value| value + 1).unwrap_or(0);
+  for step in &[Step::Write(vec![next_value]), Step::Flush, Step::Compact] {
+    run_step(&server, step).await
+      .unwrap_or_else(|e| panic!("{}: {:?} after recovery failed: {}", context, step, e));
+  }
+}
+
+async fn simulate(seed: u64, n_steps: usize) {
+  let steps = script(seed, n_steps);
+  let n_mutations = {
+    let sim = Sim::new();
+    let acked = run_until_crash(&sim, &steps).await;
+    assert!(acked.interrupted.is_none(), "seed {} failed without faults", seed);
+    check_recovered(&sim, &acked, &format!("seed {} without faults", seed)).await;
+    sim.memory_fs.n_mutations()
+  };
+
+  for fault in [Fault::Fail, Fault::Tear] {
+    for n in 1..=n_mutations {
+      let sim = Sim::new();
+      sim.memory_fs.inject_fault(n, fault);
+      let acked = run_until_crash(&sim, &steps).await;
+      sim.memory_fs.restart();
+      let context = format!("seed {} with {:?} at mutation {}/{}", seed, fault, n, n_mutations);
+      check_recovered(&sim, &acked, &context).await;
+    }
+  }
+}
+
+#[tokio::test]
+async fn recovers_from_crash_at_any_mutation() {
+  for seed in 0..3 {
+    simulate(seed, 12).await;
+  }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use pancake_db_idl::ddl::CreateTableRequest;
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{FieldValue, Row, WriteToPartitionRequest};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+
+use crate::constants::{DEAD_LETTER_ERROR_COLUMN_NAME, DEAD_LETTER_RAW_JSON_COLUMN_NAME, DEAD_LETTER_TABLE_SUFFIX, MAX_NAME_LENGTH};
+use crate::errors::ServerResult;
+use crate::metadata::table::TableConfigOverrides;
+use crate::ops::create_table::CreateTableOp;
+use crate::ops::traits::ServerOp;
+use crate::ops::write_to_partition::WriteToPartitionOp;
+use crate::utils::checksum;
+
+use super::Server;
+use super::authz::{self, Principal};
+
+// A row that failed validation, as the client sent it.
+pub struct DeadLetter {
+  pub raw_json: String,
+  pub error: String,
+}
+
+// Table names near the length limit are truncated and suffixed with a hash
+// of the full name, so the dead-letter table name is always valid and
+// distinct tables still get distinct dead-letter tables. Table names are
+// ASCII, so truncating by bytes is safe.
+pub fn dead_letter_table_name(table_name: &str) -> String {
+  let name = format!("{}{}", table_name, DEAD_LETTER_TABLE_SUFFIX);
+  if name.len() <= MAX_NAME_LENGTH {
+    return name;
+  }
+
+  let hash = format!("_{:016x}", checksum::xxhash64(table_name.as_bytes()));
+  let prefix_len = MAX_NAME_LENGTH - hash.len() - DEAD_LETTER_TABLE_SUFFIX.len();
+  format!("{}{}{}", &table_name[..prefix_len], hash, DEAD_LETTER_TABLE_SUFFIX)
+}
+
+fn dead_letter_schema() -> Schema {
+  let string_column = ColumnMeta {
+    dtype: DataType::String as i32,
+    nested_list_depth: 0,
+  };
+  Schema {
+    columns: [DEAD_LETTER_RAW_JSON_COLUMN_NAME, DEAD_LETTER_ERROR_COLUMN_NAME].iter()
+      .map(|col_name| (col_name.to_string(), string_column.clone()))
+      .collect(),
+    ..Default::default()
+  }
+}
+
+impl Server {
+  // Writes rejected rows of a table to its unpartitioned dead-letter table,
+  // creating it if needed, so that lossy ingestion can be debugged.
+  pub async fn write_dead_letters(&self, table_name: &str, dead_letters: Vec<DeadLetter>) -> ServerResult<()> {
+    if dead_letters.is_empty() {
+      return Ok(());
+    }
+
+    // the writer may not be allowed to create or write the dead-letter
+    // table, but its rows belong there regardless
+    authz::scope(Principal::Internal, self.write_dead_letters_internal(table_name, dead_letters)).await
+  }
+
+  async fn write_dead_letters_internal(&self, table_name: &str, dead_letters: Vec<DeadLetter>) -> ServerResult<()> {
+    let dead_letter_table_name = dead_letter_table_name(table_name);
+    // only try creating it when it's missing, so that the audit log isn't
+    // flooded with no-op creations
+    let table_lock = self.table_metadata_cache.get_lock(&dead_letter_table_name).await?;
+    let exists = table_lock.read().await.is_some();
+    if !exists {
+      CreateTableOp {
+        req: CreateTableRequest {
+          table_name: dead_letter_table_name.clone(),
+          schema: Some(dead_letter_schema()),
+          mode: SchemaMode::OkIfExact as i32,
+        },
+        sort_columns: Vec::new(),
+        computed_columns: HashMap::new(),
+        column_masks: HashMap::new(),
+        config_overrides: TableConfigOverrides::default(),
+      }.execute(self).await?;
+    }
+
+    let string_value = |s: String| FieldValue {
+      value: Some(Value::StringVal(s)),
+    };
+    let rows = dead_letters.into_iter()
+      .map(|dead_letter| Row {
+        fields: vec![
+          (DEAD_LETTER_RAW_JSON_COLUMN_NAME.to_string(), string_value(dead_letter.raw_json)),
+          (DEAD_LETTER_ERROR_COLUMN_NAME.to_string(), string_value(dead_letter.error)),
+        ].into_iter().collect(),
+      })
+      .collect::<Vec<_>>();
+    log::info!(
+      "writing {} invalid rows of table {} to {}",
+      rows.len(),
+      table_name,
+      dead_letter_table_name,
+    );
+    WriteToPartitionOp {
+      req: WriteToPartitionRequest {
+        table_name: dead_letter_table_name,
+        partition: HashMap::new(),
+        rows,
+      },
+    }.execute(self).await?;
+    Ok(())
+  }
+}
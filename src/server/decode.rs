@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use pancake_db_core::compression;
+use pancake_db_idl::dml::{FieldValue, ReadSegmentColumnRequest, ReadSegmentDeletionsRequest, Row, Segment};
+use pancake_db_idl::schema::ColumnMeta;
+use uuid::Uuid;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::ops::read_segment_column::ReadSegmentColumnOp;
+use crate::ops::read_segment_deletions::ReadSegmentDeletionsOp;
+use crate::ops::traits::ServerOp;
+use crate::utils::common;
+use crate::utils::safe_decoding;
+
+use super::Server;
+
+// Decodes whole segments into rows for server-side consumers, going through
+// the same read ops (and correlation-based versioning) that clients use.
+impl Server {
+  async fn decode_segment_column(
+    &self,
+    table_name: &str,
+    segment: &Segment,
+    column_name: &str,
+    column_meta: &ColumnMeta,
+    is_deleted: &[bool],
+    correlation_id: &str,
+  ) -> ServerResult<Vec<FieldValue>> {
+    let req = ReadSegmentColumnRequest {
+      table_name: table_name.to_string(),
+      partition: segment.partition.clone(),
+      segment_id: segment.segment_id.clone(),
+      column_name: column_name.to_string(),
+      correlation_id: correlation_id.to_string(),
+    };
+    let mut compressed_bytes = Vec::new();
+    let mut uncompressed_bytes = Vec::new();
+    let mut codec = String::new();
+    let mut implicit_nulls_count;
+    let mut continuation = None;
+    loop {
+      let continued = ReadSegmentColumnOp {
+        req: req.clone(),
+        continuation,
+        predicate: None,
+        reverse: false,
+      }.execute(self).await?;
+      let resp = continued.resp;
+      if resp.codec.is_empty() {
+        uncompressed_bytes.extend(resp.data);
+      } else {
+        compressed_bytes.extend(resp.data);
+        codec = resp.codec;
+      }
+      implicit_nulls_count = resp.implicit_nulls_count;
+      continuation = continued.continuation;
+      if continuation.is_none() {
+        break;
+      }
+    }
+
+    let dtype = common::unwrap_dtype(column_meta.dtype)?;
+    let nested_list_depth = column_meta.nested_list_depth as u8;
+    let mut values = Vec::new();
+    if !compressed_bytes.is_empty() {
+      if implicit_nulls_count > 0 {
+        return Err(ServerError::internal(
+          "read contained both compacted data and implicit nulls"
+        ));
+      }
+      let decompressor = compression::new_codec(dtype, &codec)?;
+      values.extend(safe_decoding::decompress(&*decompressor, &compressed_bytes, nested_list_depth)?);
+    }
+    values.extend((0..implicit_nulls_count).map(|_| FieldValue::default()));
+    if !uncompressed_bytes.is_empty() {
+      values.extend(safe_decoding::decode(dtype, nested_list_depth, &uncompressed_bytes)?);
+    }
+
+    Ok(
+      values.into_iter()
+        .enumerate()
+        .filter(|(row_idx, _)| !is_deleted.get(*row_idx).cloned().unwrap_or(false))
+        .map(|(_, value)| value)
+        .collect()
+    )
+  }
+
+  // Reads the given columns of a segment, omitting deleted rows.
+  // At least one column must be given, since that determines the row count.
+  pub async fn decode_segment(
+    &self,
+    table_name: &str,
+    segment: &Segment,
+    columns: &HashMap<String, ColumnMeta>,
+  ) -> ServerResult<Vec<Row>> {
+    if columns.is_empty() {
+      return Err(ServerError::internal("unable to decode segment with no columns"));
+    }
+
+    let correlation_id = Uuid::new_v4().to_string();
+    let res = self.decode_correlated_segment(table_name, segment, columns, &correlation_id).await;
+    self.correlation_metadata_cache.end_read(&correlation_id).await;
+    res
+  }
+
+  async fn decode_correlated_segment(
+    &self,
+    table_name: &str,
+    segment: &Segment,
+    columns: &HashMap<String, ColumnMeta>,
+    correlation_id: &str,
+  ) -> ServerResult<Vec<Row>> {
+    let deletions_resp = ReadSegmentDeletionsOp {
+      req: ReadSegmentDeletionsRequest {
+        table_name: table_name.to_string(),
+        partition: segment.partition.clone(),
+        segment_id: segment.segment_id.clone(),
+        correlation_id: correlation_id.to_string(),
+      }
+    }.execute(self).await?;
+    let is_deleted = safe_decoding::decompress_deletions(&deletions_resp.data)?;
+
+    // columns can differ in length if rows are written during the read, so
+    // we only keep rows present in every column
+    let mut rows: Option<Vec<Row>> = None;
+    for (column_name, column_meta) in columns {
+      let values = self.decode_segment_column(
+        table_name,
+        segment,
+        column_name,
+        column_meta,
+        &is_deleted,
+        correlation_id,
+      ).await?;
+      let rows = rows.get_or_insert_with(|| vec![Row::default(); values.len()]);
+      rows.truncate(values.len());
+      for (row, value) in rows.iter_mut().zip(values) {
+        row.fields.insert(column_name.clone(), value);
+      }
+    }
+    Ok(rows.unwrap_or_default())
+  }
+}
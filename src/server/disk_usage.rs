@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::opt::RuntimeConfig;
+use crate::types::SegmentKey;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+use super::Server;
+
+// Bytes on disk per segment, measured whenever a segment is flushed or
+// compacted and on every pass of the compaction loop. Staged rows count
+// once they are flushed.
+#[derive(Default, Clone)]
+pub struct DiskUsage {
+  mutex: Arc<Mutex<DiskUsageState>>,
+}
+
+#[derive(Default)]
+struct DiskUsageState {
+  segment_bytes: HashMap<SegmentKey, u64>,
+  table_bytes: HashMap<String, u64>,
+  total_bytes: u64,
+  // what is over its soft limit, so that we only warn once per crossing
+  tables_over_soft_limit: HashSet<String>,
+  is_total_over_soft_limit: bool,
+}
+
+pub struct DiskUsageReport {
+  pub total_bytes: u64,
+  // sorted by table name
+  pub table_bytes: Vec<(String, u64)>,
+}
+
+pub fn is_over_limit(bytes: u64, limit: Option<u64>) -> bool {
+  limit.map(|limit| bytes > limit).unwrap_or(false)
+}
+
+impl DiskUsageState {
+  fn set_segment_bytes(&mut self, segment_key: &SegmentKey, maybe_bytes: Option<u64>) {
+    let old_bytes = match maybe_bytes {
+      Some(bytes) => self.segment_bytes.insert(segment_key.clone(), bytes),
+      None => self.segment_bytes.remove(segment_key),
+    }.unwrap_or(0);
+    let new_bytes = maybe_bytes.unwrap_or(0);
+    let table_bytes = self.table_bytes.entry(segment_key.table_name.clone())
+      .or_default();
+    *table_bytes = *table_bytes + new_bytes - old_bytes;
+    self.total_bytes = self.total_bytes + new_bytes - old_bytes;
+  }
+
+  fn warn_on_soft_limits(&mut self, table_name: &str, runtime_config: &RuntimeConfig) {
+    let table_bytes = self.table_bytes.get(table_name).cloned().unwrap_or(0);
+    if is_over_limit(table_bytes, runtime_config.table_disk_soft_limit_bytes) {
+      if self.tables_over_soft_limit.insert(table_name.to_string()) {
+        log::warn!(
+          "table {} uses {} bytes of disk, over its soft limit of {}",
+          table_name,
+          table_bytes,
+          runtime_config.table_disk_soft_limit_bytes.unwrap(),
+        );
+      }
+    } else {
+      self.tables_over_soft_limit.remove(table_name);
+    }
+
+    let is_total_over = is_over_limit(self.total_bytes, runtime_config.global_disk_soft_limit_bytes);
+    if is_total_over && !self.is_total_over_soft_limit {
+      log::warn!(
+        "all tables use {} bytes of disk, over the global soft limit of {}",
+        self.total_bytes,
+        runtime_config.global_disk_soft_limit_bytes.unwrap(),
+      );
+    }
+    self.is_total_over_soft_limit = is_total_over;
+  }
+}
+
+async fn dir_byte_size(dir: &Path) -> ServerResult<Option<u64>> {
+  let mut res = 0;
+  let mut pending = vec![dir.to_path_buf()];
+  while let Some(dir) = pending.pop() {
+    let mut read_dir = match vfs::read_dir(&dir).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => continue,
+      Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = read_dir.next_entry().await? {
+      let metadata = match entry.metadata().await {
+        Ok(metadata) => metadata,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => continue,
+        Err(e) => return Err(e.into()),
+      };
+      if metadata.is_dir() {
+        pending.push(entry.path());
+      } else {
+        res += metadata.len();
+      }
+    }
+  }
+
+  if vfs::metadata(dir).await.is_ok() {
+    Ok(Some(res))
+  } else {
+    Ok(None)
+  }
+}
+
+impl Server {
+  pub async fn measure_disk_usage(&self, segment_key: &SegmentKey) -> ServerResult<()> {
+    let maybe_bytes = dir_byte_size(&dirs::segment_dir(&self.opts.dir, segment_key)).await?;
+    let runtime_config = self.runtime_config().await;
+    let mut state = self.disk_usage.mutex.lock().await;
+    state.set_segment_bytes(segment_key, maybe_bytes);
+    state.warn_on_soft_limits(&segment_key.table_name, &runtime_config);
+    Ok(())
+  }
+
+  // Stops counting segments that weren't seen during a full pass over every
+  // segment, because they have since been garbage collected, merged, split,
+  // or dropped.
+  pub async fn forget_disk_usage_except(&self, seen_segment_keys: &HashSet<SegmentKey>) {
+    let mut state = self.disk_usage.mutex.lock().await;
+    let unseen_keys: Vec<_> = state.segment_bytes.keys()
+      .filter(|key| !seen_segment_keys.contains(key))
+      .cloned()
+      .collect();
+    for segment_key in &unseen_keys {
+      // it may have been created during the pass
+      if vfs::metadata(dirs::segment_dir(&self.opts.dir, segment_key)).await.is_err() {
+        state.set_segment_bytes(segment_key, None);
+      }
+    }
+    state.table_bytes.retain(|_, bytes| *bytes > 0);
+  }
+
+  pub async fn forget_table_disk_usage(&self, table_name: &str) {
+    let mut state = self.disk_usage.mutex.lock().await;
+    let table_keys: Vec<_> = state.segment_bytes.keys()
+      .filter(|key| key.table_name == table_name)
+      .cloned()
+      .collect();
+    for segment_key in &table_keys {
+      state.set_segment_bytes(segment_key, None);
+    }
+    state.table_bytes.remove(table_name);
+    state.tables_over_soft_limit.remove(table_name);
+  }
+
+  // rejects writes to a table over its hard limit, or to any table when all
+  // tables together are over the global hard limit
+  pub async fn check_disk_quota(&self, table_name: &str) -> ServerResult<()> {
+    let runtime_config = self.runtime_config().await;
+    let state = self.disk_usage.mutex.lock().await;
+    let table_bytes = state.table_bytes.get(table_name).cloned().unwrap_or(0);
+    if is_over_limit(table_bytes, runtime_config.table_disk_hard_limit_bytes) {
+      return Err(ServerError::quota_exceeded(format!(
+        "table {} uses {} bytes of disk, over its hard limit of {}",
+        table_name,
+        table_bytes,
+        runtime_config.table_disk_hard_limit_bytes.unwrap(),
+      )));
+    }
+    if is_over_limit(state.total_bytes, runtime_config.global_disk_hard_limit_bytes) {
+      return Err(ServerError::quota_exceeded(format!(
+        "all tables use {} bytes of disk, over the global hard limit of {}",
+        state.total_bytes,
+        runtime_config.global_disk_hard_limit_bytes.unwrap(),
+      )));
+    }
+    Ok(())
+  }
+
+  pub async fn table_disk_bytes(&self, table_name: &str) -> u64 {
+    let state = self.disk_usage.mutex.lock().await;
+    state.table_bytes.get(table_name).cloned().unwrap_or(0)
+  }
+
+  pub async fn disk_usage_report(&self) -> DiskUsageReport {
+    let state = self.disk_usage.mutex.lock().await;
+    let mut table_bytes: Vec<_> = state.table_bytes.iter()
+      .map(|(table_name, &bytes)| (table_name.clone(), bytes))
+      .collect();
+    table_bytes.sort();
+    DiskUsageReport {
+      total_bytes: state.total_bytes,
+      table_bytes,
+    }
+  }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::process::Command;
+use tokio::sync::RwLock;
+
+use crate::constants::ENCRYPTION_KEY_ENV_VAR_PREFIX;
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::compaction::Compaction;
+use crate::utils::encryption;
+use crate::utils::encryption::Cipher;
+
+use super::Server;
+
+// column file ciphers by key id, loaded the first time each key is needed
+#[derive(Clone, Default)]
+pub struct Keyring {
+  ciphers: Arc<RwLock<HashMap<String, Arc<Cipher>>>>,
+}
+
+impl Server {
+  // Compaction metadata for a version before anything is written to it, so
+  // that flushes into the version know which key to use. Without encryption,
+  // versions don't need metadata until they are compacted.
+  pub fn initial_compaction(&self) -> Option<Compaction> {
+    self.opts.encryption_key_id.as_ref().map(|key_id| Compaction {
+      encryption_key_id: Some(key_id.clone()),
+      ..Default::default()
+    })
+  }
+
+  // fails fast on startup if the active key can't be loaded
+  pub async fn check_encryption_key(&self) -> ServerResult<()> {
+    if let Some(key_id) = &self.opts.encryption_key_id {
+      self.cipher(key_id).await?;
+      log::info!("encrypting new column files with key {}", key_id);
+    }
+    Ok(())
+  }
+
+  // the cipher for a version's column files, if they are encrypted
+  pub async fn column_cipher(&self, compaction: &Compaction) -> ServerResult<Option<Arc<Cipher>>> {
+    match &compaction.encryption_key_id {
+      Some(key_id) => Ok(Some(self.cipher(key_id).await?)),
+      None => Ok(None),
+    }
+  }
+
+  async fn cipher(&self, key_id: &str) -> ServerResult<Arc<Cipher>> {
+    if let Some(cipher) = self.keyring.ciphers.read().await.get(key_id) {
+      return Ok(cipher.clone());
+    }
+
+    encryption::validate_key_id(key_id)?;
+    let key_b64 = self.load_key(key_id).await?;
+    let key_bytes = base64::decode(key_b64.trim())
+      .map_err(|_| ServerError::invalid(format!("encryption key {} is not valid base64", key_id)))?;
+    let cipher = Arc::new(Cipher::new(key_id, &key_bytes)?);
+    self.keyring.ciphers.write().await.insert(key_id.to_string(), cipher.clone());
+    Ok(cipher)
+  }
+
+  async fn load_key(&self, key_id: &str) -> ServerResult<String> {
+    if let Ok(key_b64) = std::env::var(format!("{}{}", ENCRYPTION_KEY_ENV_VAR_PREFIX, key_id.to_uppercase())) {
+      return Ok(key_b64);
+    }
+
+    if let Some(path) = &self.opts.encryption_keys_file {
+      let bytes = tokio::fs::read(path).await
+        .map_err(|e| ServerError::invalid(format!("could not read encryption keys file {:?}: {}", path, e)))?;
+      let toml_str = String::from_utf8(bytes)
+        .map_err(|_| ServerError::invalid("non-utf8 encryption keys file"))?;
+      let keys: HashMap<String, String> = toml::from_str(&toml_str)
+        .map_err(|e| ServerError::invalid(format!("invalid encryption keys file {:?}: {}", path, e)))?;
+      if let Some(key_b64) = keys.get(key_id) {
+        return Ok(key_b64.clone());
+      }
+    }
+
+    if let Some(command) = &self.opts.encryption_kms_command {
+      let output = Command::new(command)
+        .arg(key_id)
+        .output()
+        .await
+        .map_err(|e| ServerError::internal(format!("could not run KMS command {:?}: {}", command, e)))?;
+      if !output.status.success() {
+        return Err(ServerError::internal(format!(
+          "KMS command {:?} failed for key {} with {}",
+          command,
+          key_id,
+          output.status,
+        )));
+      }
+      return String::from_utf8(output.stdout)
+        .map_err(|_| ServerError::internal("KMS command output non-utf8 key"));
+    }
+
+    Err(ServerError::internal(format!("encryption key {} not found", key_id)))
+  }
+}
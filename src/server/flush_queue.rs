@@ -0,0 +1,155 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::types::SegmentKey;
+
+// Segments with staged rows wait here for the flush loop. Every write adds
+// its segment, so the queue is sharded by segment: writers only lock the
+// shard their segment hashes to, coalescing repeat writes to a segment
+// already queued, and the flush loop takes each shard's segments in turn,
+// moving them out rather than copying them.
+//
+// Each segment remembers when it was first queued since its last flush, and
+// the flush loop takes the longest-waiting first, so a segment written to
+// constantly can't hold up the others.
+
+const N_SHARDS: usize = 16;
+
+#[derive(Clone)]
+pub struct FlushQueue {
+  inner: Arc<FlushQueueInner>,
+}
+
+struct FlushQueueInner {
+  // when each queued segment was first queued
+  shards: Vec<Mutex<HashMap<SegmentKey, Instant>>>,
+  backlog: AtomicUsize,
+  n_added: AtomicU64,
+  n_coalesced: AtomicU64,
+  max_taken_age_millis: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlushQueueStats {
+  // segments waiting to be flushed
+  pub backlog: usize,
+  // segments queued since startup
+  pub n_added: u64,
+  // writes to segments that were already queued
+  pub n_coalesced: u64,
+  // how long the longest-waiting segment has been waiting
+  pub oldest_age: Duration,
+  // the longest any segment has waited before being taken to flush, since
+  // startup
+  pub max_taken_age: Duration,
+}
+
+impl Default for FlushQueue {
+  fn default() -> Self {
+    FlushQueue {
+      inner: Arc::new(FlushQueueInner {
+        shards: (0..N_SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        backlog: AtomicUsize::new(0),
+        n_added: AtomicU64::new(0),
+        n_coalesced: AtomicU64::new(0),
+        max_taken_age_millis: AtomicU64::new(0),
+      }),
+    }
+  }
+}
+
+impl FlushQueue {
+  fn shard(&self, key: &SegmentKey) -> &Mutex<HashMap<SegmentKey, Instant>> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    &self.inner.shards[hasher.finish() as usize % N_SHARDS]
+  }
+
+  pub async fn add(&self, key: SegmentKey) {
+    self.add_queued_at(key, Instant::now()).await;
+  }
+
+  // Puts back a segment taken to flush, as if it had never been taken. If
+  // it's been queued again since, it keeps the earlier time.
+  pub async fn requeue(&self, key: SegmentKey, queued_at: Instant) {
+    self.add_queued_at(key, queued_at).await;
+  }
+
+  async fn add_queued_at(&self, key: SegmentKey, queued_at: Instant) {
+    let inner = &self.inner;
+    // counted under the shard lock so the backlog never dips below zero
+    let mut shard_guard = self.shard(&key).lock().await;
+    match shard_guard.get_mut(&key) {
+      Some(existing_queued_at) => {
+        *existing_queued_at = queued_at.min(*existing_queued_at);
+        inner.n_coalesced.fetch_add(1, Ordering::Relaxed);
+      },
+      None => {
+        shard_guard.insert(key, queued_at);
+        inner.backlog.fetch_add(1, Ordering::Relaxed);
+        inner.n_added.fetch_add(1, Ordering::Relaxed);
+      },
+    }
+  }
+
+  // Takes everything queued, longest-waiting first, along with when each
+  // was queued. A segment written to after this is queued again, even
+  // while it's being flushed.
+  pub async fn take_all(&self) -> Vec<(SegmentKey, Instant)> {
+    let mut res = Vec::new();
+    for shard in &self.inner.shards {
+      let mut shard_guard = shard.lock().await;
+      self.inner.backlog.fetch_sub(shard_guard.len(), Ordering::Relaxed);
+      res.extend(shard_guard.drain());
+    }
+    res.sort_by_key(|(_, queued_at)| *queued_at);
+    if let Some((_, oldest_queued_at)) = res.first() {
+      self.inner.max_taken_age_millis.fetch_max(
+        oldest_queued_at.elapsed().as_millis() as u64,
+        Ordering::Relaxed,
+      );
+    }
+    res
+  }
+
+  // so that a renamed table's staged rows still get flushed
+  pub async fn rename_table(&self, table_name: &str, new_table_name: &str) {
+    for (mut key, queued_at) in self.take_all().await {
+      if key.table_name == table_name {
+        key.table_name = new_table_name.to_string();
+      }
+      self.add_queued_at(key, queued_at).await;
+    }
+  }
+
+  pub async fn queued(&self) -> Vec<SegmentKey> {
+    let mut res = Vec::new();
+    for shard in &self.inner.shards {
+      res.extend(shard.lock().await.keys().cloned());
+    }
+    res
+  }
+
+  pub async fn stats(&self) -> FlushQueueStats {
+    let inner = &self.inner;
+    let mut oldest_age = Duration::ZERO;
+    for shard in &inner.shards {
+      if let Some(queued_at) = shard.lock().await.values().min() {
+        oldest_age = oldest_age.max(queued_at.elapsed());
+      }
+    }
+    FlushQueueStats {
+      backlog: inner.backlog.load(Ordering::Relaxed),
+      n_added: inner.n_added.load(Ordering::Relaxed),
+      n_coalesced: inner.n_coalesced.load(Ordering::Relaxed),
+      oldest_age,
+      max_taken_age: Duration::from_millis(inner.max_taken_age_millis.load(Ordering::Relaxed)),
+    }
+  }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use pancake_db_idl::dml::{Row, WriteToPartitionRequest, WriteToPartitionResponse};
+use tokio::sync::oneshot;
+use tokio::time::Duration;
+
+use crate::errors::ServerResult;
+use crate::ops::traits::ServerOp;
+use crate::ops::write_to_partition::WriteToPartitionOp;
+use crate::types::{NormalizedPartition, PartitionKey};
+
+use super::Server;
+
+// With a group commit window, the first write to a partition waits out the
+// window, collecting the rows of any other writes to the partition that
+// arrive meanwhile, then writes them all at once: one append and one
+// segment metadata update instead of one per request. The others wait for
+// it and succeed along with it. If the combined write fails, each request
+// is written on its own instead, so that each gets its own error, and rows
+// that failed together never land.
+
+#[derive(Default)]
+struct PendingGroup {
+  rows: Vec<Row>,
+  // told whether the group's write included their rows
+  followers: Vec<oneshot::Sender<bool>>,
+}
+
+#[derive(Clone, Default)]
+pub struct GroupCommits {
+  // only locked briefly, and never across an await
+  pending: Arc<Mutex<HashMap<PartitionKey, PendingGroup>>>,
+}
+
+// Closes the group if its leader goes away before writing it, so later
+// writes don't join a group nobody will write. Its followers then write on
+// their own.
+struct Leading<'a> {
+  group_commits: &'a GroupCommits,
+  key: PartitionKey,
+}
+
+impl<'a> Leading<'a> {
+  fn take_group(self) -> PendingGroup {
+    let group = self.group_commits.pending.lock().unwrap()
+      .remove(&self.key)
+      .unwrap_or_default();
+    std::mem::forget(self);
+    group
+  }
+}
+
+impl<'a> Drop for Leading<'a> {
+  fn drop(&mut self) {
+    if let Ok(mut pending) = self.group_commits.pending.lock() {
+      pending.remove(&self.key);
+    }
+  }
+}
+
+impl Server {
+  // Writes the request's rows, in a group with other writes to its
+  // partition if the server has a group commit window.
+  pub async fn write_grouped(&self, req: &WriteToPartitionRequest) -> ServerResult<WriteToPartitionResponse> {
+    let window = Duration::from_micros(self.opts.group_commit_window_micros);
+    if window.is_zero() || req.rows.is_empty() {
+      return WriteToPartitionOp { req: req.clone() }.execute(self).await;
+    }
+
+    let key = PartitionKey {
+      table_name: req.table_name.clone(),
+      partition: NormalizedPartition::from_raw_fields(&req.partition)?,
+    };
+    let maybe_follower_receiver = {
+      let mut pending = self.group_commits.pending.lock().unwrap();
+      match pending.get_mut(&key) {
+        Some(group) => {
+          let (sender, receiver) = oneshot::channel();
+          group.rows.extend(req.rows.iter().cloned());
+          group.followers.push(sender);
+          Some(receiver)
+        },
+        None => {
+          pending.insert(key.clone(), PendingGroup::default());
+          None
+        },
+      }
+    };
+
+    if let Some(receiver) = maybe_follower_receiver {
+      return if receiver.await.unwrap_or(false) {
+        Ok(WriteToPartitionResponse::default())
+      } else {
+        WriteToPartitionOp { req: req.clone() }.execute(self).await
+      };
+    }
+
+    let leading = Leading {
+      group_commits: &self.group_commits,
+      key,
+    };
+    tokio::time::sleep(window).await;
+    let group = leading.take_group();
+    if group.followers.is_empty() {
+      return WriteToPartitionOp { req: req.clone() }.execute(self).await;
+    }
+
+    let n_requests = group.followers.len() + 1;
+    let mut combined_req = req.clone();
+    combined_req.rows.extend(group.rows);
+    let n_rows = combined_req.rows.len();
+    let combined_res = WriteToPartitionOp { req: combined_req }.execute(self).await;
+    let is_written = combined_res.is_ok();
+    for follower in group.followers {
+      let _ = follower.send(is_written);
+    }
+    match combined_res {
+      Ok(resp) => {
+        log::debug!(
+          "group committed {} rows from {} writes to {}",
+          n_rows,
+          n_requests,
+          req.table_name,
+        );
+        Ok(resp)
+      },
+      Err(e) => {
+        log::debug!("group commit to {} failed; writing requests separately: {}", req.table_name, e);
+        WriteToPartitionOp { req: req.clone() }.execute(self).await
+      },
+    }
+  }
+}
@@ -1,9 +1,14 @@
+use std::collections::HashMap;
+
 use pancake_db_idl::ddl::{AlterTableRequest, AlterTableResponse, CreateTableRequest, CreateTableResponse, DropTableRequest, DropTableResponse, GetSchemaRequest, GetSchemaResponse, ListTablesRequest, ListTablesResponse};
 use pancake_db_idl::dml::{DeleteFromSegmentRequest, DeleteFromSegmentResponse, ListSegmentsRequest, ListSegmentsResponse, ReadSegmentColumnRequest, ReadSegmentDeletionsRequest, ReadSegmentDeletionsResponse, WriteToPartitionRequest, WriteToPartitionResponse};
 use pancake_db_idl::service::pancake_db_server::PancakeDb;
+use prost::Message;
 use tonic::{Request, Response, Status};
 
 use crate::Server;
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::table::TableConfigOverrides;
 use crate::ops::alter_table::AlterTableOp;
 use crate::ops::create_table::CreateTableOp;
 use crate::ops::delete_from_segment::DeleteFromSegmentOp;
@@ -13,52 +18,136 @@ use crate::ops::list_segments::ListSegmentsOp;
 use crate::ops::list_tables::ListTablesOp;
 use crate::ops::read_segment_deletions::ReadSegmentDeletionsOp;
 use crate::ops::traits::ServerOp;
-use crate::ops::write_to_partition::WriteToPartitionOp;
+use crate::ops::write_to_partition::GroupedWriteToPartitionOp;
+use crate::server::authz::{self, Access, Principal, Verb};
+use crate::server::cancel;
+use crate::server::limits::RequestPermit;
 use crate::utils::common::grpc_result;
+use crate::utils::names::NormalizeNames;
 use crate::utils::read_segment_column_stream;
 use crate::utils::read_segment_column_stream::ReadSegmentColumnStream;
 
+// The request protobufs have no room for a read direction, so reverse
+// reads ask for it in metadata instead.
+const READ_DIRECTION_METADATA: &str = "pancake-read-direction";
+
+fn is_reverse_read<T>(request: &Request<T>) -> ServerResult<bool> {
+  match request.metadata().get(READ_DIRECTION_METADATA).map(|value| value.to_str()) {
+    None | Some(Ok("forward")) => Ok(false),
+    Some(Ok("reverse")) => Ok(true),
+    _ => Err(ServerError::invalid(format!("{} must be forward or reverse", READ_DIRECTION_METADATA))),
+  }
+}
+
+fn normalized<T: NormalizeNames>(request: Request<T>) -> ServerResult<T> {
+  let mut req = request.into_inner();
+  req.normalize_names()?;
+  Ok(req)
+}
+
 #[async_trait::async_trait]
 impl PancakeDb for Server {
   async fn alter_table(&self, request: Request<AlterTableRequest>) -> Result<Response<AlterTableResponse>, Status> {
-    grpc_result(AlterTableOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = AlterTableOp { req: normalized(request)?, rename_columns: HashMap::new(), config_overrides: None };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn create_table(&self, request: Request<CreateTableRequest>) -> Result<Response<CreateTableResponse>, Status> {
-    grpc_result(CreateTableOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = CreateTableOp { req: normalized(request)?, sort_columns: Vec::new(), computed_columns: HashMap::new(), column_masks: HashMap::new(), config_overrides: TableConfigOverrides::default() };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn drop_table(&self, request: Request<DropTableRequest>) -> Result<Response<DropTableResponse>, Status> {
-    grpc_result(DropTableOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = DropTableOp { req: normalized(request)? };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn get_schema(&self, request: Request<GetSchemaRequest>) -> Result<Response<GetSchemaResponse>, Status> {
-    grpc_result(GetSchemaOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = GetSchemaOp { req: normalized(request)? };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn list_tables(&self, request: Request<ListTablesRequest>) -> Result<Response<ListTablesResponse>, Status> {
-    grpc_result(ListTablesOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = ListTablesOp { req: normalized(request)? };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn delete_from_segment(&self, request: Request<DeleteFromSegmentRequest>) -> Result<Response<DeleteFromSegmentResponse>, Status> {
-    grpc_result(DeleteFromSegmentOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = DeleteFromSegmentOp { req: normalized(request)? };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn list_segments(&self, request: Request<ListSegmentsRequest>) -> Result<Response<ListSegmentsResponse>, Status> {
-    grpc_result(ListSegmentsOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = ListSegmentsOp { req: normalized(request)?, partition_conditions: Vec::new() };
+    self.grpc_execute(principal, permit, op).await
   }
 
   type ReadSegmentColumnStream = ReadSegmentColumnStream;
 
   async fn read_segment_column(&self, request: Request<ReadSegmentColumnRequest>) -> Result<Response<Self::ReadSegmentColumnStream>, Status> {
-    Ok(Response::new(read_segment_column_stream::create_stream(request.into_inner(), self.clone())))
+    let (principal, _permit) = self.grpc_admit(&request).await?;
+    let reverse = is_reverse_read(&request)?;
+    let req = normalized(request)?;
+    // check up front, since errors in the stream can only end it
+    authz::scope(principal.clone(), self.authorize(&[Access::table(Verb::Read, &req.table_name)])).await?;
+    Ok(Response::new(read_segment_column_stream::create_stream(req, reverse, self.clone(), principal)))
   }
 
   async fn read_segment_deletions(&self, request: Request<ReadSegmentDeletionsRequest>) -> Result<Response<ReadSegmentDeletionsResponse>, Status> {
-    grpc_result(ReadSegmentDeletionsOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    let op = ReadSegmentDeletionsOp { req: normalized(request)? };
+    self.grpc_execute(principal, permit, op).await
   }
 
   async fn write_to_partition(&self, request: Request<WriteToPartitionRequest>) -> Result<Response<WriteToPartitionResponse>, Status> {
-    grpc_result(WriteToPartitionOp { req: request.into_inner() }.execute(&self).await)
+    let (principal, permit) = self.grpc_admit(&request).await?;
+    self.check_request_rows(request.get_ref().rows.len()).await?;
+    let op = GroupedWriteToPartitionOp { req: normalized(request)? };
+    self.grpc_execute(principal, permit, op).await
+  }
+}
+impl Server {
+  // Runs an op in its own task, so that a client hanging up cancels it at
+  // its next safe point rather than dropping it halfway through.
+  async fn grpc_execute<Op>(
+    &self,
+    principal: Principal,
+    permit: Option<RequestPermit>,
+    op: Op,
+  ) -> Result<Response<Op::Response>, Status> where Op: ServerOp + Send + 'static, Op::Response: 'static {
+    let server = self.clone();
+    grpc_result(cancel::spawn_request(async move {
+      let _permit = permit;
+      authz::scope(principal, op.execute(&server)).await
+    }).await)
+  }
+
+  // Authenticates and admits a request under the server's limits. Tonic has
+  // already decoded the message by now, but rejecting it still keeps it
+  // from being processed.
+  async fn grpc_admit<T: Message>(&self, request: &Request<T>) -> Result<(Principal, Option<RequestPermit>), Status> {
+    let principal = self.grpc_principal(request).await?;
+    let permit = self.admit_request(&principal).await?;
+    self.check_request_bytes(request.get_ref().encoded_len()).await?;
+    Ok((principal, permit))
+  }
+
+  // the principal whose API key is in the request's authorization metadata
+  async fn grpc_principal<T>(&self, request: &Request<T>) -> Result<Principal, Status> {
+    let maybe_authorization = match request.metadata().get("authorization") {
+      Some(value) => Some(
+        value.to_str()
+          .map_err(|_| ServerError::unauthenticated("authorization metadata is not ASCII"))?
+      ),
+      None => None,
+    };
+    Ok(self.authenticate_bearer(maybe_authorization).await?)
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,64 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+use super::Server;
+
+pub struct OrphanedTmpFile {
+  pub path: PathBuf,
+  pub age: Duration,
+}
+
+impl Server {
+  // Files left in the tmp dir by an atomic overwrite that crashed between
+  // writing and renaming. Anything younger than min_age may still belong to
+  // an overwrite in progress.
+  pub async fn orphaned_tmp_files(&self, min_age: Duration) -> ServerResult<Vec<OrphanedTmpFile>> {
+    let tmp_dir = dirs::tmp_dir(&self.opts.dir);
+    let mut read_dir = match vfs::read_dir(&tmp_dir).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(Vec::new()),
+      Err(e) => return Err(ServerError::from(e).with_context(format!(
+        "while listing {:?}",
+        tmp_dir,
+      ))),
+    };
+
+    let now = SystemTime::now();
+    let mut res = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+      let modified = entry.metadata().await?.modified()?;
+      let age = now.duration_since(modified).unwrap_or_default();
+      if age >= min_age {
+        res.push(OrphanedTmpFile {
+          path: entry.path(),
+          age,
+        });
+      }
+    }
+    Ok(res)
+  }
+
+  // returns the number of files removed
+  pub async fn remove_orphaned_tmp_files(&self, min_age: Duration) -> ServerResult<usize> {
+    let orphans = self.orphaned_tmp_files(min_age).await?;
+    for orphan in &orphans {
+      log::debug!(
+        "removing orphaned tmp file {:?} last modified {}s ago",
+        orphan.path,
+        orphan.age.as_secs(),
+      );
+      match vfs::remove_file(&orphan.path).await {
+        Ok(()) => (),
+        // it may have been renamed into place after all
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => (),
+        Err(e) => return Err(e.into()),
+      }
+    }
+    Ok(orphans.len())
+  }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::errors::{ServerError, ServerResult};
+
+use super::authz::Principal;
+use super::Server;
+
+// how long clients are told to wait when too many requests are in flight
+const BUSY_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+// Tracks the requests in flight and each principal's request rate, so that
+// abusive clients are turned away before their requests use any memory.
+#[derive(Clone, Default)]
+pub struct RequestLimiter {
+  in_flight: Arc<AtomicUsize>,
+  // token buckets by principal id
+  buckets: Arc<Mutex<HashMap<String, TokenBucket>>>,
+}
+
+struct TokenBucket {
+  tokens: f64,
+  updated_at: Instant,
+}
+
+// counts a request as in flight until dropped
+pub struct RequestPermit {
+  in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for RequestPermit {
+  fn drop(&mut self) {
+    self.in_flight.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl Server {
+  // Rejects requests over the principal's rate or the concurrency limit,
+  // with a hint of when to retry. Requests the server makes itself are
+  // never limited.
+  pub async fn admit_request(&self, principal: &Principal) -> ServerResult<Option<RequestPermit>> {
+    if *principal == Principal::Internal {
+      return Ok(None);
+    }
+
+    let config = self.runtime_config().await;
+    if let Some(requests_per_second) = config.max_requests_per_second {
+      self.take_rate_token(principal, requests_per_second).await?;
+    }
+
+    let n_in_flight = self.limiter.in_flight.fetch_add(1, Ordering::SeqCst);
+    let permit = RequestPermit {
+      in_flight: self.limiter.in_flight.clone(),
+    };
+    if n_in_flight >= config.max_concurrent_requests {
+      return Err(ServerError::too_many_requests(format!(
+        "server may handle at most {} requests at once",
+        config.max_concurrent_requests,
+      )).with_retry_after(BUSY_RETRY_AFTER));
+    }
+    Ok(Some(permit))
+  }
+
+  async fn take_rate_token(&self, principal: &Principal, requests_per_second: u32) -> ServerResult<()> {
+    let rate = requests_per_second.max(1) as f64;
+    let now = Instant::now();
+    let mut buckets = self.limiter.buckets.lock().await;
+    let bucket = buckets.entry(principal.id().to_string())
+      .or_insert(TokenBucket {
+        tokens: rate,
+        updated_at: now,
+      });
+    let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+    bucket.updated_at = now;
+    if bucket.tokens < 1.0 {
+      return Err(ServerError::too_many_requests(format!(
+        "{} may make at most {} requests per second",
+        principal,
+        requests_per_second,
+      )).with_retry_after(Duration::from_secs_f64((1.0 - bucket.tokens) / rate)));
+    }
+    bucket.tokens -= 1.0;
+    Ok(())
+  }
+
+  pub async fn check_request_bytes(&self, n_bytes: usize) -> ServerResult<()> {
+    let max_bytes = self.runtime_config().await.max_request_bytes;
+    if n_bytes > max_bytes {
+      return Err(ServerError::too_large(format!(
+        "request may have at most {} bytes",
+        max_bytes,
+      )));
+    }
+    Ok(())
+  }
+
+  pub async fn check_request_rows(&self, n_rows: usize) -> ServerResult<()> {
+    let max_rows = self.runtime_config().await.max_request_rows;
+    if n_rows > max_rows {
+      return Err(ServerError::too_large(format!(
+        "request may have at most {} rows but had {}",
+        max_rows,
+        n_rows,
+      )));
+    }
+    Ok(())
+  }
+}
@@ -1,17 +1,20 @@
+use std::collections::HashMap;
+
 use async_std::stream::Stream;
 use async_stream::try_stream;
 use futures::pin_mut;
 use futures::StreamExt;
-use tokio::fs;
 
 use crate::{Server, ServerResult};
-use crate::types::{InternalTableInfo, NormalizedPartition, PartitionKey, SegmentKey};
-use crate::utils::navigation;
+use crate::errors::ServerError;
+use crate::types::{InternalTableInfo, PartitionKey, SegmentKey};
+use crate::utils::names::{self, NameNormalization};
+use crate::utils::vfs;
 
 impl Server {
   pub async fn internal_list_tables(&self) -> ServerResult<Vec<InternalTableInfo>> {
     let mut tables = Vec::new();
-    let mut read_dir = fs::read_dir(&self.opts.dir).await?;
+    let mut read_dir = vfs::read_dir(&self.opts.dir).await?;
     while let Some(entry) = read_dir.next_entry().await? {
       if !entry.file_type().await?.is_dir() {
         continue;
@@ -37,34 +40,74 @@ impl Server {
     Ok(tables)
   }
 
+  // Tables and columns with names normalization changes would be
+  // unreachable, and ones normalizing to the same name would be ambiguous,
+  // so the server refuses to start until they're renamed.
+  pub async fn check_name_normalization(&self) -> ServerResult<()> {
+    if names::normalization() == NameNormalization::None {
+      return Ok(());
+    }
+
+    let tables = self.internal_list_tables().await?;
+    let mut problems = Vec::new();
+    let mut check_names = |entity: &str, entity_names: Vec<String>| {
+      let mut seen = HashMap::new();
+      for name in entity_names {
+        let normalized = names::normalize(&name);
+        if let Some(other_name) = seen.insert(normalized.clone(), name.clone()) {
+          problems.push(format!("{} names {} and {} both normalize to {}", entity, other_name, name, normalized));
+        } else if normalized != name {
+          problems.push(format!("{} name {} normalizes to {}", entity, name, normalized));
+        }
+      }
+    };
+    check_names("table", tables.iter().map(|table| table.name.clone()).collect());
+    for table in &tables {
+      let schema = table.meta.visible_schema();
+      check_names(
+        &format!("table {} column", table.name),
+        schema.columns.keys().chain(schema.partitioning.keys()).cloned().collect(),
+      );
+    }
+
+    if !problems.is_empty() {
+      return Err(ServerError::invalid(format!(
+        "existing names conflict with --name-normalization {:?}; rename these with \
+        normalization off and restart: {}",
+        names::normalization(),
+        problems.join("; "),
+      )));
+    }
+    Ok(())
+  }
+
   pub fn stream_all_segment_keys(&self) -> impl Stream<Item=ServerResult<SegmentKey>> + '_ {
     try_stream! {
       let tables = self.internal_list_tables().await?;
       for table in &tables {
-        let schema = table.meta.schema();
-        let partitions = navigation::partitions_for_table(
-          &self.opts.dir,
-          &table.name,
-          &schema.partitioning,
-          &Vec::new(),
-        ).await?;
-        for partition in &partitions {
-          let partition_key = PartitionKey {
-            table_name: table.name.clone(),
-            partition: NormalizedPartition::from_raw_fields(partition)?
-          };
+        let segment_key_stream = self.stream_table_segment_keys(table);
+        pin_mut!(segment_key_stream);
+        while let Some(segment_key_result) = segment_key_stream.next().await {
+          let segment_key = segment_key_result?;
+          yield segment_key;
+        }
+      }
+    }
+  }
 
-          let segment_id_stream = navigation::stream_segment_ids_for_partition(
-            &self.opts.dir,
-            partition_key.clone(),
-          );
-          pin_mut!(segment_id_stream);
-          while let Some(segment_id_result) = segment_id_stream.next().await {
-            let segment_id = segment_id_result?;
-            yield partition_key.segment_key(segment_id);
-          }
+  // segments are listed partition by partition
+  pub fn stream_table_segment_keys<'a>(&'a self, table: &'a InternalTableInfo) -> impl Stream<Item=ServerResult<SegmentKey>> + 'a {
+    try_stream! {
+      let partitions = self.indexed_partitions(&table.name, &[]).await?;
+      for (_, indexed) in partitions {
+        let partition_key = PartitionKey {
+          table_name: table.name.clone(),
+          partition: indexed.partition,
+        };
+        for segment_id in indexed.segment_ids {
+          yield partition_key.segment_key(segment_id);
         }
       }
     }
   }
-}
\ No newline at end of file
+}
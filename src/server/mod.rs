@@ -1,6 +1,7 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use futures::{Future, pin_mut};
 use futures::StreamExt;
 use tokio::sync::{Mutex, RwLock};
@@ -12,22 +13,74 @@ use crate::metadata::correlation::CorrelationMetadataCache;
 use crate::metadata::deletion::DeletionMetadataCache;
 use crate::metadata::global::GlobalMetadata;
 use crate::metadata::partition::PartitionMetadataCache;
+use crate::metadata::partition_index::PartitionIndexCache;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::segment::SegmentMetadataCache;
 use crate::metadata::table::TableMetadataCache;
+use crate::metadata::transaction::TransactionCache;
 use crate::ops::compact::CompactionOp;
 use crate::ops::flush::FlushOp;
+use crate::ops::garbage_collect::GarbageCollectOp;
+use crate::ops::merge_segments::MergeSegmentsOp;
+use crate::ops::split_segment::SplitSegmentOp;
 use crate::ops::traits::ServerOp;
-use crate::opt::Opt;
+use crate::opt::{Opt, RuntimeConfig};
 use crate::types::{EmptyKey, SegmentKey};
+use crate::utils::chaos;
 use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::durability::{self, FsyncPolicy};
+use crate::utils::names;
 
+pub mod audit;
+pub mod authz;
+pub mod cancel;
+mod config;
+#[cfg(test)]
+mod crash_sim;
+mod dead_letter;
+mod decode;
+mod disk_usage;
+mod encryption;
+mod flush_queue;
+mod group_commit;
+mod janitor;
+mod limits;
+pub mod ops_history;
+mod partition_index;
+mod partition_layout;
+mod prefetch;
 mod read;
 mod recovery;
+mod replica;
+mod rewrite;
+pub mod slow_ops;
+mod standby;
+mod supervisor;
+pub mod system_tables;
+mod throttle;
+mod trash;
+mod upgrade;
+
+pub use dead_letter::DeadLetter;
+pub use disk_usage::is_over_limit;
+use audit::AuditLog;
+use authz::Authz;
+use flush_queue::{FlushQueue, FlushQueueStats};
+use group_commit::GroupCommits;
+use disk_usage::DiskUsage;
+use encryption::Keyring;
+use limits::RequestLimiter;
+use prefetch::Prefetcher;
+use throttle::CompactionThrottle;
 mod misc;
 mod grpc;
 
-const FLUSH_SECONDS: u64 = 10;
+const FLUSH_LOOP: &str = "flush";
+const COMPACTION_LOOP: &str = "compaction";
+const JANITOR_LOOP: &str = "janitor";
+const REPLICA_REFRESH_LOOP: &str = "replica_refresh";
+const STANDBY_SHIPPER_LOOP: &str = "standby_shipper";
 
 #[derive(Default, Clone)]
 pub struct Activity {
@@ -55,123 +108,450 @@ impl Activity {
 
 #[derive(Default, Clone)]
 pub struct Background {
-  mutex: Arc<Mutex<BackgroundState>>
+  mutex: Arc<Mutex<BackgroundState>>,
+  flush_queue: FlushQueue,
 }
 
 #[derive(Default)]
 pub struct BackgroundState {
-  flush_candidates: HashSet<SegmentKey>,
+  loops: HashMap<&'static str, LoopStatus>,
+  // segments to compact regardless of heuristics in the next compaction
+  // loop iteration, in the order they were queued
+  forced_compactions: Vec<SegmentKey>,
+}
+
+#[derive(Clone, Default)]
+pub struct LoopStatus {
+  pub iterations: u64,
+  pub last_started_at: Option<DateTime<Utc>>,
+  // how far behind schedule the last iteration started
+  pub last_lag: Duration,
+  pub last_duration: Duration,
+  // the segment the loop is working on right now, if any
+  pub current_segment: Option<SegmentKey>,
+  pub panics: u64,
+  pub last_panic: Option<String>,
+  pub last_panic_at: Option<DateTime<Utc>>,
+  // whether the loop panicked and is waiting to be restarted
+  pub is_restarting: bool,
+}
+
+pub struct BackgroundStatus {
+  pub flush_candidates: Vec<SegmentKey>,
+  pub forced_compactions: Vec<SegmentKey>,
+  pub flush_queue: FlushQueueStats,
+  pub loops: Vec<(&'static str, LoopStatus)>,
 }
 
 impl Background {
   pub async fn add_flush_candidate(&self, key: SegmentKey) {
+    self.flush_queue.add(key).await;
+  }
+
+  pub async fn rename_flush_candidates(&self, table_name: &str, new_table_name: &str) {
+    self.flush_queue.rename_table(table_name, new_table_name).await;
+  }
+
+  // longest-waiting first, with when each was queued
+  pub async fn pop_flush_candidates(&self) -> Vec<(SegmentKey, std::time::Instant)> {
+    self.flush_queue.take_all().await
+  }
+
+  pub async fn requeue_flush_candidate(&self, key: SegmentKey, queued_at: std::time::Instant) {
+    self.flush_queue.requeue(key, queued_at).await;
+  }
+
+  pub async fn queue_forced_compaction(&self, key: SegmentKey) {
     let mut mux_guard = self.mutex.lock().await;
-    let state = &mut *mux_guard;
-    state.flush_candidates.insert(key);
+    if !mux_guard.forced_compactions.contains(&key) {
+      mux_guard.forced_compactions.push(key);
+    }
   }
 
-  pub async fn pop_flush_candidates(&self) -> HashSet<SegmentKey> {
+  pub async fn pop_forced_compactions(&self) -> Vec<SegmentKey> {
     let mut mux_guard = self.mutex.lock().await;
-    let state = &mut *mux_guard;
-    let res = state.flush_candidates.clone();
-    state.flush_candidates = HashSet::new();
-    res
+    std::mem::take(&mut mux_guard.forced_compactions)
+  }
+
+  pub async fn start_loop_iteration(&self, loop_name: &'static str, lag: Duration) {
+    let mut mux_guard = self.mutex.lock().await;
+    let status = mux_guard.loops.entry(loop_name).or_default();
+    status.iterations += 1;
+    status.last_started_at = Some(Utc::now());
+    status.last_lag = lag;
+  }
+
+  pub async fn finish_loop_iteration(&self, loop_name: &'static str, duration: Duration) {
+    let mut mux_guard = self.mutex.lock().await;
+    let status = mux_guard.loops.entry(loop_name).or_default();
+    status.last_duration = duration;
+    status.current_segment = None;
+  }
+
+  pub async fn record_loop_panic(&self, loop_name: &'static str, message: String) {
+    let mut mux_guard = self.mutex.lock().await;
+    let status = mux_guard.loops.entry(loop_name).or_default();
+    status.panics += 1;
+    status.last_panic = Some(message);
+    status.last_panic_at = Some(Utc::now());
+    status.is_restarting = true;
+    status.current_segment = None;
+  }
+
+  pub async fn record_loop_restart(&self, loop_name: &'static str) {
+    let mut mux_guard = self.mutex.lock().await;
+    let status = mux_guard.loops.entry(loop_name).or_default();
+    status.is_restarting = false;
+  }
+
+  pub async fn set_current_segment(&self, loop_name: &'static str, key: &SegmentKey) {
+    let mut mux_guard = self.mutex.lock().await;
+    let status = mux_guard.loops.entry(loop_name).or_default();
+    status.current_segment = Some(key.clone());
+  }
+
+  pub async fn status(&self) -> BackgroundStatus {
+    let (mut loops, forced_compactions): (Vec<_>, _) = {
+      let mux_guard = self.mutex.lock().await;
+      let loops = mux_guard.loops.iter()
+        .map(|(&name, status)| (name, status.clone()))
+        .collect();
+      (loops, mux_guard.forced_compactions.clone())
+    };
+    loops.sort_by_key(|(name, _)| *name);
+    BackgroundStatus {
+      flush_candidates: self.flush_queue.queued().await,
+      forced_compactions,
+      flush_queue: self.flush_queue.stats().await,
+      loops,
+    }
   }
 }
 
 #[derive(Clone)]
 pub struct Server {
   pub opts: Opt,
+  runtime_config: Arc<RwLock<RuntimeConfig>>,
   background: Background,
   activity: Activity,
+  disk_usage: DiskUsage,
+  authz: Authz,
+  audit_log: AuditLog,
+  keyring: Keyring,
+  limiter: RequestLimiter,
+  prefetcher: Prefetcher,
+  compaction_throttle: CompactionThrottle,
+  group_commits: GroupCommits,
+  // why the server stopped taking writes until it restarts, if it has
+  unhealthy_reason: Arc<std::sync::Mutex<Option<String>>>,
   pub global_metadata_lock: Arc<RwLock<GlobalMetadata>>,
   pub table_metadata_cache: TableMetadataCache,
   pub partition_metadata_cache: PartitionMetadataCache,
+  pub partition_index_cache: PartitionIndexCache,
   pub deletion_metadata_cache: DeletionMetadataCache,
   pub correlation_metadata_cache: CorrelationMetadataCache,
   pub segment_metadata_cache: SegmentMetadataCache,
   pub compaction_cache: CompactionCache,
+  pub transaction_cache: TransactionCache,
 }
 
 impl Server {
   async fn bootstrap(&self) -> ServerResult<()> {
     let maybe_existing_global = GlobalMetadata::load(&self.opts.dir, &EmptyKey).await?;
     if let Some(existing_global) = maybe_existing_global {
+      // writing servers upgrade the format during recovery, but read-only
+      // ones can't
+      upgrade::check_format_readable(&existing_global)?;
       let mut global_meta_guard = self.global_metadata_lock.write().await;
-      existing_global.overwrite(&self.opts.dir, &EmptyKey).await?;
+      if !self.opts.read_only {
+        existing_global.overwrite(&self.opts.dir, &EmptyKey).await?;
+      }
+      // read-only servers skip recovery, so this is where they learn the layout
+      dirs::set_partition_dir_fanout(existing_global.partition_dir_fanout);
       *global_meta_guard = existing_global;
     }
     Ok(())
   }
 
-  pub async fn init(&self) -> ServerResult<(impl Future<Output=()> + '_, impl Future<Output=()> + '_)> {
+  pub async fn init(&self) -> ServerResult<(
+    impl Future<Output=()> + '_,
+    impl Future<Output=()> + '_,
+    impl Future<Output=()> + '_,
+  )> {
+    durability::set_policy(
+      self.opts.fsync_policy,
+      Duration::from_millis(self.opts.fsync_interval_millis),
+    );
+    if self.opts.fsync_policy == FsyncPolicy::Interval {
+      tokio::spawn(durability::sync_forever());
+    }
     self.bootstrap().await?;
+    names::set_normalization(self.opts.name_normalization);
+    self.check_name_normalization().await?;
+    self.load_authz_config().await?;
+    self.check_encryption_key().await?;
+
+    if !self.opts.read_only {
+      common::create_if_new(dirs::tmp_dir(&self.opts.dir)).await?;
+      common::create_if_new(dirs::manifest_dir(&self.opts.dir)).await?;
+      common::create_if_new(dirs::transaction_dir(&self.opts.dir)).await?;
+    }
 
-    common::create_if_new(self.opts.dir.join("tmp")).await?;
+    Ok((
+      self.supervise(FLUSH_LOOP, move |is_restart| self.flush_forever(is_restart)),
+      self.supervise(COMPACTION_LOOP, move |_| self.compact_forever()),
+      self.supervise(JANITOR_LOOP, move |_| self.janitor_forever()),
+    ))
+  }
 
-    let flush_forever_future = async move {
-      let mut last_t = Instant::now();
-      let flush_interval = Duration::from_secs(FLUSH_SECONDS);
-      loop {
-        let cur_t = Instant::now();
-        let planned_t = last_t + flush_interval;
-        if cur_t < planned_t {
-          tokio::time::sleep_until(planned_t).await;
-        }
-        last_t = cur_t;
-        let candidates = self.background.pop_flush_candidates().await;
-        for candidate in &candidates {
-          let flush_result = FlushOp { segment_key: candidate.clone() }
-            .execute(self)
-            .await;
-          if let Err(err) = flush_result {
-            log::error!("flushing {} failed: {}", candidate, err);
-          }
+  // Starts shipping to the standby dir, or refreshing a read-only server's
+  // metadata, if the opts ask for it. These stop along with the server.
+  pub fn spawn_replication(&self) {
+    if let Some(standby_dir) = self.opts.standby_dir.clone() {
+      let shipper_server = self.clone();
+      tokio::spawn(async move {
+        shipper_server.ship_to_standby_forever(standby_dir).await;
+      });
+    }
+
+    if self.opts.read_only {
+      let refresh_server = self.clone();
+      tokio::spawn(async move {
+        refresh_server.refresh_metadata_forever().await;
+      });
+    }
+  }
+
+  async fn flush_forever(&self, is_restart: bool) {
+    // a read-only server leaves flushing and compaction to the server that
+    // owns its dir
+    if self.opts.read_only {
+      return;
+    }
+    if is_restart {
+      if let Err(e) = self.recover_flushes().await {
+        log::error!("recovering flushes after a panic failed: {}", e);
+      }
+    }
+    let mut last_t = Instant::now();
+    loop {
+      let flush_interval = Duration::from_secs(self.runtime_config().await.flush_loop_seconds);
+      let cur_t = Instant::now();
+      let planned_t = last_t + flush_interval;
+      if cur_t < planned_t {
+        tokio::time::sleep_until(planned_t).await;
+      }
+      last_t = cur_t;
+      let iteration_t = Instant::now();
+      self.background.start_loop_iteration(FLUSH_LOOP, iteration_t.saturating_duration_since(planned_t)).await;
+      let candidates = self.background.pop_flush_candidates().await;
+      for (candidate, _) in &candidates {
+        chaos::kill(FLUSH_LOOP);
+        self.background.set_current_segment(FLUSH_LOOP, candidate).await;
+        let flush_result = FlushOp { segment_key: candidate.clone() }
+          .execute(self)
+          .await;
+        if let Err(err) = flush_result {
+          log::error!("flushing {} failed: {}", candidate, err);
         }
+      }
+      self.background.finish_loop_iteration(FLUSH_LOOP, iteration_t.elapsed()).await;
+
+      let is_active = self.activity.is_active().await;
+      if !is_active {
+        return;
+      }
+    }
+  }
 
-        let is_active = self.activity.is_active().await;
-        if !is_active {
-          return;
+  // A flush loop that panicked may have left a segment marked as flushing
+  // and dropped the flush candidates it had popped, so we recover like we
+  // do on startup: undo incomplete flushes and requeue any segment with
+  // staged rows.
+  async fn recover_flushes(&self) -> ServerResult<()> {
+    let segment_key_stream = self.stream_all_segment_keys();
+    pin_mut!(segment_key_stream);
+    while let Some(segment_key_result) = segment_key_stream.next().await {
+      let segment_key = segment_key_result?;
+      let table_lock = self.table_metadata_cache.get_lock(&segment_key.table_name).await?;
+      let table_guard = table_lock.read().await;
+      let table_meta = match &*table_guard {
+        Some(table_meta) => table_meta,
+        None => continue,
+      };
+      let segment_lock = self.segment_metadata_cache.get_lock(&segment_key).await?;
+      let mut segment_guard = segment_lock.write().await;
+      if let Some(segment_meta) = &mut *segment_guard {
+        FlushOp::recover(self, table_meta, &segment_key, segment_meta).await?;
+        if segment_meta.staged_n > 0 {
+          self.background.add_flush_candidate(segment_key.clone()).await;
         }
       }
-    };
+    }
+    Ok(())
+  }
+
+  // Flushes every segment with staged rows without waiting for the flush
+  // loop, returning the first error after trying them all. Segments that
+  // fail stay flush candidates.
+  pub async fn flush_now(&self) -> ServerResult<()> {
+    let mut first_err = None;
+    for (candidate, queued_at) in self.background.pop_flush_candidates().await {
+      let flush_result = FlushOp { segment_key: candidate.clone() }
+        .execute(self)
+        .await;
+      if let Err(err) = flush_result {
+        self.background.requeue_flush_candidate(candidate, queued_at).await;
+        first_err.get_or_insert(err);
+      }
+    }
+    first_err.map_or(Ok(()), Err)
+  }
 
-    let compact_forever_future = async move {
-      let mut last_t = Instant::now();
-      let compact_interval = Duration::from_secs(self.opts.compaction_loop_seconds);
-      loop {
-        let cur_t = Instant::now();
-        let planned_t = last_t + compact_interval;
-        if cur_t < planned_t {
-          tokio::time::sleep_until(planned_t).await;
+  // Compacts every segment the compaction heuristics say is due without
+  // waiting for the compaction loop, returning the first error after trying
+  // them all.
+  pub async fn compact_now(&self) -> ServerResult<()> {
+    let mut first_err = None;
+    let segment_key_stream = self.stream_all_segment_keys();
+    pin_mut!(segment_key_stream);
+    while let Some(segment_key_result) = segment_key_stream.next().await {
+      let compact_result = match segment_key_result {
+        Ok(segment_key) => CompactionOp { key: segment_key, force: false }.execute(self).await,
+        Err(err) => Err(err),
+      };
+      if let Err(err) = compact_result {
+        first_err.get_or_insert(err);
+      }
+    }
+    first_err.map_or(Ok(()), Err)
+  }
+
+  async fn compact_forever(&self) {
+    if self.opts.read_only {
+      return;
+    }
+    let mut last_t = Instant::now();
+    loop {
+      let compact_interval = Duration::from_secs(self.runtime_config().await.compaction_loop_seconds);
+      let cur_t = Instant::now();
+      let planned_t = last_t + compact_interval;
+      if cur_t < planned_t {
+        tokio::time::sleep_until(planned_t).await;
+      }
+      last_t = cur_t;
+      let iteration_t = Instant::now();
+      self.background.start_loop_iteration(COMPACTION_LOOP, iteration_t.saturating_duration_since(planned_t)).await;
+      for segment_key in self.background.pop_forced_compactions().await {
+        chaos::kill(COMPACTION_LOOP);
+        self.background.set_current_segment(COMPACTION_LOOP, &segment_key).await;
+        let compact_result = CompactionOp { key: segment_key.clone(), force: true }.execute(self).await;
+        if let Err(e) = compact_result {
+          log::error!("forced compaction of {} failed: {}", segment_key, e);
         }
-        last_t = cur_t;
-        let segment_key_stream = self.stream_all_segment_keys();
-        pin_mut!(segment_key_stream);
-        while let Some(segment_key_result) = segment_key_stream.next().await {
-          // The CompactionOp uses heuristics to determine whether a compaction
-          // is needed, so we don't do any of those heuristics here.
-          match segment_key_result {
-            Ok(segment_key) => {
-              let compact_result = CompactionOp { key: segment_key.clone() }.execute(self).await;
-              if let Err(e) = compact_result {
-                log::error!("compaction failed: {}", e);
+      }
+      let segment_key_stream = self.stream_all_segment_keys();
+      pin_mut!(segment_key_stream);
+      let mut partition_keys = Vec::new();
+      let mut seen_segment_keys = HashSet::new();
+      while let Some(segment_key_result) = segment_key_stream.next().await {
+        // The CompactionOp uses heuristics to determine whether a compaction
+        // is needed, so we don't do any of those heuristics here.
+        match segment_key_result {
+          Ok(segment_key) => {
+            chaos::kill(COMPACTION_LOOP);
+            self.background.set_current_segment(COMPACTION_LOOP, &segment_key).await;
+            let compact_result = CompactionOp { key: segment_key.clone(), force: false }.execute(self).await;
+            if let Err(e) = compact_result {
+              log::error!("compaction failed: {}", e);
+            }
+            let split_result = SplitSegmentOp { key: segment_key.clone() }.execute(self).await;
+            let is_split = match split_result {
+              Ok(new_segment_ids) => !new_segment_ids.is_empty(),
+              Err(e) => {
+                log::error!("splitting segment failed: {}", e);
+                false
+              },
+            };
+            if !is_split {
+              let gc_result = GarbageCollectOp { key: segment_key.clone() }.execute(self).await;
+              if let Err(e) = gc_result {
+                log::error!("garbage collection failed: {}", e);
               }
-            },
-            Err(e) => {
-              log::error!("compaction loop failed: {}", e);
-            },
-          }
+            }
+            if let Err(e) = self.measure_disk_usage(&segment_key).await {
+              log::error!("measuring disk usage failed: {}", e);
+            }
+            // segments are listed partition by partition
+            let partition_key = segment_key.partition_key();
+            if partition_keys.last() != Some(&partition_key) {
+              partition_keys.push(partition_key);
+            }
+            seen_segment_keys.insert(segment_key);
+          },
+          Err(e) => {
+            log::error!("compaction loop failed: {}", e);
+          },
+        }
+      }
+      self.forget_disk_usage_except(&seen_segment_keys).await;
+      for partition_key in partition_keys {
+        let merge_result = MergeSegmentsOp { key: partition_key, ignore_grace: false }
+          .execute(self)
+          .await;
+        if let Err(e) = merge_result {
+          log::error!("merging segments failed: {}", e);
         }
+      }
+      self.background.finish_loop_iteration(COMPACTION_LOOP, iteration_t.elapsed()).await;
+
+      let is_active = self.activity.is_active().await;
+      if !is_active {
+        return;
+      }
+    }
+  }
 
-        let is_active = self.activity.is_active().await;
-        if !is_active {
-          return;
+  async fn janitor_forever(&self) {
+    let mut last_t = Instant::now();
+    let janitor_interval = Duration::from_secs(self.opts.janitor_loop_seconds);
+    let min_tmp_file_age = Duration::from_secs(self.opts.orphaned_tmp_file_seconds);
+    loop {
+      let cur_t = Instant::now();
+      let planned_t = last_t + janitor_interval;
+      if cur_t < planned_t {
+        tokio::time::sleep_until(planned_t).await;
+      }
+      last_t = cur_t;
+      let iteration_t = Instant::now();
+      self.background.start_loop_iteration(JANITOR_LOOP, iteration_t.saturating_duration_since(planned_t)).await;
+      if !self.opts.read_only {
+        match self.remove_orphaned_tmp_files(min_tmp_file_age).await {
+          Ok(0) => (),
+          Ok(n) => log::info!("removed {} orphaned tmp files", n),
+          Err(e) => log::error!("removing orphaned tmp files failed: {}", e),
+        }
+        match self.purge_expired_trash().await {
+          Ok(0) => (),
+          Ok(n) => log::info!("purged {} dropped tables from the trash", n),
+          Err(e) => log::error!("purging the trash failed: {}", e),
         }
       }
-    };
+      let n_expired = self.correlation_metadata_cache.prune_expired().await;
+      if n_expired > 0 {
+        log::info!("expired {} correlation ids", n_expired);
+      }
+      let n_expired = self.transaction_cache.prune_expired().await;
+      if n_expired > 0 {
+        log::info!("aborted {} expired transactions", n_expired);
+      }
+      self.background.finish_loop_iteration(JANITOR_LOOP, iteration_t.elapsed()).await;
 
-    Ok((flush_forever_future, compact_forever_future))
+      let is_active = self.activity.is_active().await;
+      if !is_active {
+        return;
+      }
+    }
   }
 
   pub async fn stop(&self) {
@@ -181,27 +561,66 @@ impl Server {
   pub fn new(opts: Opt) -> Server {
     let dir = &opts.dir;
     let global_metadata_lock = Arc::new(RwLock::new(GlobalMetadata::default()));
-    let table_metadata_cache = TableMetadataCache::new(dir);
-    let partition_metadata_cache = PartitionMetadataCache::new(dir);
-    let deletion_metadata_cache = DeletionMetadataCache::new();
-    let correlation_metadata_cache = CorrelationMetadataCache::new();
-    let segment_metadata_cache = SegmentMetadataCache::new(dir);
-    let compaction_cache = CompactionCache::new(dir);
+    let table_metadata_cache = TableMetadataCache::new(dir, opts.table_cache_size);
+    let partition_metadata_cache = PartitionMetadataCache::new(dir, opts.partition_cache_size);
+    let partition_index_cache = PartitionIndexCache::new(dir, opts.table_cache_size);
+    let deletion_metadata_cache = DeletionMetadataCache::new(opts.deletion_cache_size);
+    let correlation_metadata_cache = CorrelationMetadataCache::new(opts.correlation_cache_size);
+    let segment_metadata_cache = SegmentMetadataCache::new(dir, opts.segment_cache_size);
+    let compaction_cache = CompactionCache::new(dir, opts.compaction_cache_size);
+    let transaction_cache = TransactionCache::new(opts.transaction_cache_size);
+    let runtime_config = Arc::new(RwLock::new(RuntimeConfig::from(&opts)));
     Server {
       opts,
+      runtime_config,
       global_metadata_lock,
       table_metadata_cache,
       partition_metadata_cache,
+      partition_index_cache,
       deletion_metadata_cache,
       correlation_metadata_cache,
       segment_metadata_cache,
       compaction_cache,
+      transaction_cache,
       background: Background::default(),
       activity: Activity::default(),
+      disk_usage: DiskUsage::default(),
+      authz: Authz::default(),
+      audit_log: AuditLog::default(),
+      keyring: Keyring::default(),
+      limiter: RequestLimiter::default(),
+      prefetcher: Prefetcher::default(),
+      compaction_throttle: CompactionThrottle::default(),
+      group_commits: GroupCommits::default(),
+      unhealthy_reason: Arc::default(),
     }
   }
 
   pub async fn add_flush_candidate(&self, key: SegmentKey) {
     self.background.add_flush_candidate(key).await;
   }
+
+  // has the compaction loop compact a segment regardless of heuristics at
+  // the start of its next iteration
+  pub async fn queue_forced_compaction(&self, key: SegmentKey) {
+    self.background.queue_forced_compaction(key).await;
+  }
+
+  pub async fn rename_flush_candidates(&self, table_name: &str, new_table_name: &str) {
+    self.background.rename_flush_candidates(table_name, new_table_name).await;
+  }
+
+  // forgets cached metadata for a table's partitions and segments, e.g.
+  // once its files have moved
+  pub async fn prune_table_caches(&self, table_name: &str) {
+    self.partition_index_cache.prune(|key| key == table_name).await;
+    self.partition_metadata_cache.prune(|key| key.table_name == table_name).await;
+    self.segment_metadata_cache.prune(|key| key.table_name == table_name).await;
+    self.compaction_cache.prune(|key| key.table_name == table_name).await;
+    self.deletion_metadata_cache.prune(|key| key.table_name == table_name).await;
+  }
+
+  pub async fn background_status(&self) -> BackgroundStatus {
+    self.background.status().await
+  }
 }
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use pancake_db_idl::ddl::CreateTableRequest;
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{FieldValue, Row, WriteToPartitionRequest};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use prost_types::Timestamp;
+use tokio::time::Duration;
+
+use crate::constants::{OPS_BYTES_IN_COLUMN_NAME, OPS_BYTES_OUT_COLUMN_NAME, OPS_CODECS_COLUMN_NAME, OPS_DURATION_COLUMN_NAME, OPS_KIND_COLUMN_NAME, OPS_PARTITION_COLUMN_NAME, OPS_ROWS_COLUMN_NAME, OPS_SEGMENT_ID_COLUMN_NAME, OPS_STARTED_AT_COLUMN_NAME, OPS_TABLE_COLUMN_NAME, OPS_TABLE_NAME};
+use crate::errors::ServerResult;
+use crate::metadata::table::TableConfigOverrides;
+use crate::ops::create_table::CreateTableOp;
+use crate::ops::traits::ServerOp;
+use crate::ops::write_to_partition::WriteToPartitionOp;
+use crate::types::SegmentKey;
+
+use super::authz::{self, Principal};
+use super::slow_ops::{self, OpProfile};
+use super::Server;
+
+// A finished flush or compaction of a segment, as written to the ops table.
+pub struct OpRecord {
+  pub kind: &'static str,
+  pub segment_key: SegmentKey,
+  pub started_at: SystemTime,
+  pub duration: Duration,
+  // rows flushed or compacted
+  pub n_rows: u64,
+  // bytes of staged rows or uncompressed values processed
+  pub bytes_in: u64,
+  // bytes of column files written
+  pub bytes_out: u64,
+  // codec chosen for each compacted column; empty for flushes
+  pub codecs: HashMap<String, String>,
+}
+
+impl OpRecord {
+  fn partition_string(&self) -> String {
+    self.segment_key.partition.field_dir_names().join("/")
+  }
+
+  fn codecs_string(&self) -> String {
+    if self.codecs.is_empty() {
+      return "none".to_string();
+    }
+    let mut codecs = self.codecs.iter()
+      .map(|(col_name, codec)| format!("{}={}", col_name, codec))
+      .collect::<Vec<_>>();
+    codecs.sort();
+    codecs.join(", ")
+  }
+}
+
+fn ops_schema() -> Schema {
+  let column = |dtype: DataType| ColumnMeta {
+    dtype: dtype as i32,
+    nested_list_depth: 0,
+  };
+  let mut columns = HashMap::new();
+  for col_name in [OPS_KIND_COLUMN_NAME, OPS_TABLE_COLUMN_NAME, OPS_PARTITION_COLUMN_NAME, OPS_SEGMENT_ID_COLUMN_NAME, OPS_CODECS_COLUMN_NAME] {
+    columns.insert(col_name.to_string(), column(DataType::String));
+  }
+  for col_name in [OPS_DURATION_COLUMN_NAME, OPS_ROWS_COLUMN_NAME, OPS_BYTES_IN_COLUMN_NAME, OPS_BYTES_OUT_COLUMN_NAME] {
+    columns.insert(col_name.to_string(), column(DataType::Int64));
+  }
+  columns.insert(OPS_STARTED_AT_COLUMN_NAME.to_string(), column(DataType::TimestampMicros));
+  Schema {
+    columns,
+    ..Default::default()
+  }
+}
+
+impl Server {
+  // Writes a finished flush or compaction to the ops table if recording
+  // ops history, so compaction behavior can be read back like any table.
+  pub async fn record_op_history(&self, record: OpRecord) {
+    // the ops table's own flushes and compactions would record forever
+    if !self.runtime_config().await.record_ops_history || record.segment_key.table_name == OPS_TABLE_NAME {
+      return;
+    }
+
+    // in the background, so the op isn't delayed by the write
+    let server = self.clone();
+    tokio::spawn(async move {
+      if let Err(e) = server.write_op_record(record).await {
+        log::error!("writing op to table {} failed: {}", OPS_TABLE_NAME, e);
+      }
+    });
+  }
+
+  async fn write_op_record(&self, record: OpRecord) -> ServerResult<()> {
+    // like slow ops, profiled as part of a throwaway op so that the write
+    // isn't recorded as slow in turn
+    let future = authz::scope(Principal::Internal, self.write_op_record_internal(record));
+    slow_ops::scope(Arc::new(OpProfile::default()), future).await
+  }
+
+  async fn write_op_record_internal(&self, record: OpRecord) -> ServerResult<()> {
+    let table_lock = self.table_metadata_cache.get_lock(&OPS_TABLE_NAME.to_string()).await?;
+    let exists = table_lock.read().await.is_some();
+    if !exists {
+      CreateTableOp {
+        req: CreateTableRequest {
+          table_name: OPS_TABLE_NAME.to_string(),
+          schema: Some(ops_schema()),
+          mode: SchemaMode::OkIfExact as i32,
+        },
+        sort_columns: Vec::new(),
+        computed_columns: HashMap::new(),
+        column_masks: HashMap::new(),
+        config_overrides: TableConfigOverrides::default(),
+      }.execute(self).await?;
+    }
+
+    let string_value = |s: String| FieldValue {
+      value: Some(Value::StringVal(s)),
+    };
+    let int64_value = |x: u64| FieldValue {
+      value: Some(Value::Int64Val(x as i64)),
+    };
+    let row = Row {
+      fields: vec![
+        (OPS_KIND_COLUMN_NAME.to_string(), string_value(record.kind.to_string())),
+        (OPS_TABLE_COLUMN_NAME.to_string(), string_value(record.segment_key.table_name.clone())),
+        (OPS_PARTITION_COLUMN_NAME.to_string(), string_value(record.partition_string())),
+        (OPS_SEGMENT_ID_COLUMN_NAME.to_string(), string_value(record.segment_key.segment_id.to_string())),
+        (OPS_STARTED_AT_COLUMN_NAME.to_string(), FieldValue {
+          value: Some(Value::TimestampVal(Timestamp::from(record.started_at))),
+        }),
+        (OPS_DURATION_COLUMN_NAME.to_string(), int64_value(record.duration.as_millis() as u64)),
+        (OPS_ROWS_COLUMN_NAME.to_string(), int64_value(record.n_rows)),
+        (OPS_BYTES_IN_COLUMN_NAME.to_string(), int64_value(record.bytes_in)),
+        (OPS_BYTES_OUT_COLUMN_NAME.to_string(), int64_value(record.bytes_out)),
+        (OPS_CODECS_COLUMN_NAME.to_string(), string_value(record.codecs_string())),
+      ].into_iter().collect(),
+    };
+    WriteToPartitionOp {
+      req: WriteToPartitionRequest {
+        table_name: OPS_TABLE_NAME.to_string(),
+        partition: HashMap::new(),
+        rows: vec![row],
+      },
+    }.execute(self).await?;
+    Ok(())
+  }
+}
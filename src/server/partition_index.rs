@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use futures::{pin_mut, StreamExt};
+use pancake_db_idl::dml::PartitionFieldValue;
+use uuid::Uuid;
+
+use crate::errors::{Contextable, ServerResult};
+use crate::metadata::PersistentMetadata;
+use crate::metadata::partition_index::{IndexedPartition, PartitionIndex};
+use crate::metadata::table::TableMetadata;
+use crate::types::{NormalizedPartition, PartitionKey};
+use crate::utils::{common, navigation};
+use crate::utils::common::PartitionCondition;
+
+use super::Server;
+
+impl Server {
+  // the indexed partitions of a table that satisfy the conditions, along
+  // with their fields
+  pub async fn indexed_partitions(
+    &self,
+    table_name: &str,
+    conditions: &[PartitionCondition],
+  ) -> ServerResult<Vec<(HashMap<String, PartitionFieldValue>, IndexedPartition)>> {
+    let index_lock = self.partition_index_cache.get_lock(&table_name.to_string()).await?;
+    let index_guard = index_lock.read().await;
+    let mut res = Vec::new();
+    if let Some(index) = &*index_guard {
+      for indexed in &index.partitions {
+        let partition = indexed.partition.to_raw_fields();
+        if common::satisfies_filters(&partition, conditions)? {
+          res.push((partition, indexed.clone()));
+        }
+      }
+    }
+    Ok(res)
+  }
+
+  // The caller must hold the partition write lock, which is always taken
+  // before the index's.
+  pub async fn add_to_partition_index(
+    &self,
+    partition_key: &PartitionKey,
+    segment_ids: &[Uuid],
+  ) -> ServerResult<()> {
+    let index_lock = self.partition_index_cache.get_lock(&partition_key.table_name).await?;
+    let mut index_guard = index_lock.write().await;
+    let mut index = index_guard.clone().unwrap_or_default();
+    let mut changed = false;
+    for &segment_id in segment_ids {
+      changed |= index.add_segment(&partition_key.partition, segment_id);
+    }
+    if changed {
+      index.overwrite(&self.opts.dir, &partition_key.table_name).await?;
+      *index_guard = Some(index);
+    }
+    Ok(())
+  }
+
+  // The caller must hold the partition write lock, and should remove
+  // segments from the index before removing their directories, so they
+  // are never listed without them.
+  pub async fn remove_from_partition_index(
+    &self,
+    partition_key: &PartitionKey,
+    segment_ids: &[Uuid],
+  ) -> ServerResult<()> {
+    let index_lock = self.partition_index_cache.get_lock(&partition_key.table_name).await?;
+    let mut index_guard = index_lock.write().await;
+    if let Some(index) = &mut *index_guard {
+      if index.remove_segments(&partition_key.partition, segment_ids) {
+        index.overwrite(&self.opts.dir, &partition_key.table_name).await?;
+      }
+    }
+    Ok(())
+  }
+
+  // Replaces a table's partition index with what its directories hold,
+  // once recovery has brought them to a consistent state.
+  pub async fn rebuild_partition_index(
+    &self,
+    table_name: &str,
+    table_meta: &TableMetadata,
+  ) -> ServerResult<()> {
+    let dir = &self.opts.dir;
+    let mut index = PartitionIndex::default();
+    for partition in navigation::partitions_for_table(
+      dir,
+      table_name,
+      &table_meta.schema().partitioning,
+      &Vec::new(),
+    ).await.with_context(|| "while listing partitions")? {
+      let partition_key = PartitionKey {
+        table_name: table_name.to_string(),
+        partition: NormalizedPartition::from_raw_fields(&partition)?,
+      };
+      let mut segment_ids = Vec::new();
+      let segment_id_stream = navigation::stream_segment_ids_for_partition(
+        dir,
+        partition_key.clone(),
+      );
+      pin_mut!(segment_id_stream);
+      while let Some(segment_id_result) = segment_id_stream.next().await {
+        segment_ids.push(segment_id_result?);
+      }
+      index.partitions.push(IndexedPartition {
+        partition: partition_key.partition,
+        segment_ids,
+      });
+    }
+
+    let index_lock = self.partition_index_cache.get_lock(&table_name.to_string()).await?;
+    let mut index_guard = index_lock.write().await;
+    index.overwrite(dir, &table_name.to_string()).await?;
+    *index_guard = Some(index);
+    Ok(())
+  }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use pancake_db_idl::partition_dtype::PartitionDataType;
+
+use crate::constants::DATA_SUBDIR;
+use crate::errors::{Contextable, ServerError, ServerResult};
+use crate::metadata::global::GlobalMetadata;
+use crate::metadata::PersistentMetadata;
+use crate::metadata::table::TableMetadata;
+use crate::types::{EmptyKey, PartitionMinute};
+use crate::utils::{common, dirs};
+use crate::utils::vfs;
+
+use super::Server;
+
+// a partition dir, and the names of its field dirs
+struct PartitionDir {
+  path: PathBuf,
+  field_dir_names: Vec<String>,
+}
+
+impl Server {
+  // Recovery runs against the layout the dir was last served with.
+  pub async fn load_partition_dir_fanout(&self) -> ServerResult<()> {
+    let maybe_global_meta = GlobalMetadata::load(&self.opts.dir, &EmptyKey).await?;
+    let fanout = maybe_global_meta.map(|meta| meta.partition_dir_fanout).unwrap_or(0);
+    dirs::set_partition_dir_fanout(fanout);
+    Ok(())
+  }
+
+  // Moves every table's partitions, including those of dropped tables in
+  // the trash, into the layout of the configured fanout, then records it.
+  // Each partition moves in a single rename, and walking the old layout
+  // never finds a partition already moved, so an interrupted migration
+  // picks up where it left off.
+  pub async fn migrate_partition_layout(&self) -> ServerResult<()> {
+    let dir = &self.opts.dir;
+    let mut global_meta = GlobalMetadata::load(dir, &EmptyKey).await?.unwrap_or_default();
+    let old_fanout = global_meta.partition_dir_fanout;
+    let new_fanout = self.opts.partition_dir_fanout;
+    if old_fanout != new_fanout {
+      log::info!(
+        "migrating partition dirs from fanout {} to fanout {}",
+        old_fanout,
+        new_fanout,
+      );
+      let mut n_moved = 0;
+      for table_dir in table_dirs(dir).await? {
+        n_moved += migrate_table(&table_dir, old_fanout, new_fanout)
+          .await
+          .with_context(|| format!("while migrating partition dirs in {:?}", table_dir))?;
+      }
+      log::info!("moved {} partition dirs", n_moved);
+      global_meta.partition_dir_fanout = new_fanout;
+      // the overwrite is staged in tmp, which a new dir doesn't have yet
+      common::create_if_new(dirs::tmp_dir(dir)).await?;
+      global_meta.overwrite(dir, &EmptyKey).await?;
+    }
+    dirs::set_partition_dir_fanout(new_fanout);
+    Ok(())
+  }
+}
+
+// the dirs of live tables and of tables in the trash
+pub(super) async fn table_dirs(dir: &Path) -> ServerResult<Vec<PathBuf>> {
+  let mut res = Vec::new();
+  for parent in &[dir.to_path_buf(), dirs::trash_dir(dir)] {
+    let mut read_dir = match vfs::read_dir(parent).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if e.kind() == ErrorKind::NotFound => continue,
+      Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = read_dir.next_entry().await? {
+      if !entry.file_type().await?.is_dir() {
+        continue;
+      }
+      if let Some(name) = entry.file_name().to_str() {
+        if TableMetadata::load(parent, &name.to_string()).await?.is_some() {
+          res.push(entry.path());
+        }
+      }
+    }
+  }
+  Ok(res)
+}
+
+async fn load_table_meta(table_dir: &Path) -> ServerResult<TableMetadata> {
+  let table_name = table_dir.file_name()
+    .and_then(|name| name.to_str())
+    .ok_or_else(|| ServerError::internal(format!("unexpected table dir {:?}", table_dir)))?;
+  TableMetadata::load(table_dir.parent().unwrap(), &table_name.to_string())
+    .await?
+    .ok_or_else(|| ServerError::internal(format!("missing table metadata in {:?}", table_dir)))
+}
+
+// returns the number of partition dirs moved
+async fn migrate_table(table_dir: &Path, old_fanout: u32, new_fanout: u32) -> ServerResult<usize> {
+  let table_meta = load_table_meta(table_dir).await?;
+  let n_fields = table_meta.schema().partitioning.len();
+  if n_fields == 0 {
+    return Ok(0);
+  }
+
+  let data_dir = table_dir.join(DATA_SUBDIR);
+  let (partition_dirs, intermediate_dirs) = walk_layout(&data_dir, n_fields, old_fanout).await?;
+  let mut n_moved = 0;
+  for partition_dir in &partition_dirs {
+    let new_path = data_dir.join(dirs::bucketed_partition_path(
+      &partition_dir.field_dir_names,
+      new_fanout,
+    ));
+    if move_partition_dir(&partition_dir.path, &new_path).await? {
+      n_moved += 1;
+    }
+  }
+  remove_empty_dirs(&intermediate_dirs).await?;
+  Ok(n_moved)
+}
+
+// returns whether the partition dir needed moving
+async fn move_partition_dir(path: &Path, new_path: &Path) -> ServerResult<bool> {
+  // with two fanouts, the layouts can share paths
+  if new_path == path {
+    return Ok(false);
+  }
+  vfs::create_dir_all(new_path.parent().unwrap()).await?;
+  vfs::rename(path, new_path).await?;
+  Ok(true)
+}
+
+// children come after their parents, so this removes them first
+async fn remove_empty_dirs(intermediate_dirs: &[PathBuf]) -> ServerResult<()> {
+  for intermediate_dir in intermediate_dirs.iter().rev() {
+    if vfs::read_dir(intermediate_dir).await?.next_entry().await?.is_none() {
+      vfs::remove_dir_all(intermediate_dir).await?;
+    }
+  }
+  Ok(())
+}
+
+// Format 1 named minute partition field dirs by their minutes since the
+// epoch. Returns the name format 2 gives the dir, or None if it's not a
+// format 1 minute field dir.
+fn iso_minute_field_dir_name(
+  field_dir_name: &str,
+  minute_field_names: &HashSet<String>,
+) -> Option<ServerResult<String>> {
+  let (name, value) = field_dir_name.split_once('=')?;
+  if !minute_field_names.contains(name) {
+    return None;
+  }
+  let minutes = value.parse::<i64>().ok()?;
+  let minute = PartitionMinute { minutes };
+  let is_valid = minutes.checked_mul(60).is_some() && common::is_valid_timestamp(&minute.to_timestamp());
+  let res = if !is_valid {
+    Err(ServerError::invalid(format!(
+      "minute partition dir {} is out of the range of times partition dirs can name",
+      field_dir_name,
+    )))
+  } else {
+    minute.to_dir_value().map(|value| format!("{}={}", name, value))
+  };
+  Some(res)
+}
+
+// the table's partition dirs and what format 2 names each one's field
+// dirs; the minute fields' names are None for tables without any
+async fn minute_partition_dirs(
+  table_dir: &Path,
+  fanout: u32,
+) -> ServerResult<Option<(Vec<PartitionDir>, Vec<PathBuf>, HashSet<String>)>> {
+  let table_meta = load_table_meta(table_dir).await?;
+  let schema = table_meta.schema();
+  let minute_field_names: HashSet<String> = schema.partitioning.iter()
+    .filter(|(_, meta)| meta.dtype == PartitionDataType::TimestampMinute as i32)
+    .map(|(name, _)| name.clone())
+    .collect();
+  if minute_field_names.is_empty() {
+    return Ok(None);
+  }
+  let data_dir = table_dir.join(DATA_SUBDIR);
+  let (partition_dirs, intermediate_dirs) = walk_layout(&data_dir, schema.partitioning.len(), fanout).await?;
+  Ok(Some((partition_dirs, intermediate_dirs, minute_field_names)))
+}
+
+// minute partition dirs of the table that format 2 can't name
+pub(super) async fn unrenamable_minute_dirs(table_dir: &Path, fanout: u32) -> ServerResult<Vec<String>> {
+  let mut res = Vec::new();
+  if let Some((partition_dirs, _, minute_field_names)) = minute_partition_dirs(table_dir, fanout).await? {
+    for partition_dir in &partition_dirs {
+      for field_dir_name in &partition_dir.field_dir_names {
+        if let Some(Err(e)) = iso_minute_field_dir_name(field_dir_name, &minute_field_names) {
+          res.push(format!("{:?}: {}", partition_dir.path, e));
+        }
+      }
+    }
+  }
+  Ok(res)
+}
+
+// Renames the table's minute partition field dirs from format 1's minutes
+// since the epoch to ISO 8601 times, moving their partitions in a single
+// rename each, like fanout migrations. Dirs already renamed are left
+// alone, so an interrupted rename picks up where it left off. Returns the
+// number of partition dirs moved.
+pub(super) async fn rename_minute_dirs(table_dir: &Path, fanout: u32) -> ServerResult<usize> {
+  let (partition_dirs, intermediate_dirs, minute_field_names) = match minute_partition_dirs(table_dir, fanout).await? {
+    Some(res) => res,
+    None => return Ok(0),
+  };
+  let data_dir = table_dir.join(DATA_SUBDIR);
+  let mut n_moved = 0;
+  for partition_dir in &partition_dirs {
+    let mut field_dir_names = Vec::with_capacity(partition_dir.field_dir_names.len());
+    for field_dir_name in &partition_dir.field_dir_names {
+      field_dir_names.push(match iso_minute_field_dir_name(field_dir_name, &minute_field_names) {
+        Some(renamed) => renamed?,
+        None => field_dir_name.clone(),
+      });
+    }
+    let new_path = data_dir.join(dirs::bucketed_partition_path(&field_dir_names, fanout));
+    if move_partition_dir(&partition_dir.path, &new_path).await? {
+      n_moved += 1;
+    }
+  }
+  remove_empty_dirs(&intermediate_dirs).await?;
+  Ok(n_moved)
+}
+
+// The partition dirs of a table laid out with this fanout, and the bucket
+// and field dirs above them.
+async fn walk_layout(
+  data_dir: &Path,
+  n_fields: usize,
+  fanout: u32,
+) -> ServerResult<(Vec<PartitionDir>, Vec<PathBuf>)> {
+  let mut partition_dirs = vec![PartitionDir {
+    path: data_dir.to_path_buf(),
+    field_dir_names: Vec::new(),
+  }];
+  let mut intermediate_dirs = Vec::new();
+  for field_idx in 0..n_fields {
+    let mut subpartition_dirs = Vec::new();
+    for partition_dir in &partition_dirs {
+      let field_parent_dirs = if fanout > 0 {
+        let bucket_dirs = child_dirs(&partition_dir.path, dirs::is_partition_bucket_name).await?;
+        intermediate_dirs.extend(bucket_dirs.iter().cloned());
+        bucket_dirs
+      } else {
+        vec![partition_dir.path.clone()]
+      };
+
+      for field_parent_dir in &field_parent_dirs {
+        for field_dir in child_dirs(field_parent_dir, |name| name.contains('=')).await? {
+          if field_idx + 1 < n_fields {
+            intermediate_dirs.push(field_dir.clone());
+          }
+          let mut field_dir_names = partition_dir.field_dir_names.clone();
+          field_dir_names.push(field_dir.file_name().unwrap().to_string_lossy().to_string());
+          subpartition_dirs.push(PartitionDir {
+            path: field_dir,
+            field_dir_names,
+          });
+        }
+      }
+    }
+    partition_dirs = subpartition_dirs;
+  }
+  Ok((partition_dirs, intermediate_dirs))
+}
+
+async fn child_dirs(dir: &Path, name_filter: impl Fn(&str) -> bool) -> ServerResult<Vec<PathBuf>> {
+  let mut res = Vec::new();
+  let mut read_dir = match vfs::read_dir(dir).await {
+    Ok(read_dir) => read_dir,
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(res),
+    Err(e) => return Err(e.into()),
+  };
+  while let Some(entry) = read_dir.next_entry().await? {
+    let is_match = entry.file_name().to_str().map(&name_filter).unwrap_or(false);
+    if is_match && entry.file_type().await?.is_dir() {
+      res.push(entry.path());
+    }
+  }
+  Ok(res)
+}
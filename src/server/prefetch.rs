@@ -0,0 +1,107 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::encryption::Cipher;
+use crate::utils::storage;
+
+use super::Server;
+use super::slow_ops;
+
+// Serving a page of a column read starts reading the page after it in the
+// background, so that readers asking for pages one after another, as
+// nearly all do, find each one already read. Pages are keyed by where they
+// start, like continuations, and taken out once served. Compacted files
+// never change, and flush files only grow, so a prefetched page is only
+// served if it came back as long as asked for; a short one may have been
+// read before a flush appended to it.
+type PageKey = (PathBuf, u64, usize);
+
+#[derive(Clone, Default)]
+pub struct Prefetcher {
+  pages: Arc<Mutex<PrefetchedPages>>,
+}
+
+#[derive(Default)]
+struct PrefetchedPages {
+  reads: HashMap<PageKey, JoinHandle<ServerResult<Vec<u8>>>>,
+  // oldest first, for evicting pages readers never came back for
+  order: VecDeque<PageKey>,
+  n_bytes: usize,
+}
+
+impl PrefetchedPages {
+  fn take(&mut self, key: &PageKey) -> Option<JoinHandle<ServerResult<Vec<u8>>>> {
+    let read = self.reads.remove(key)?;
+    self.order.retain(|ordered_key| ordered_key != key);
+    self.n_bytes -= key.2;
+    Some(read)
+  }
+}
+
+impl Server {
+  // The page is held in memory until read_page takes it, or until newer
+  // prefetches push it out.
+  pub async fn prefetch_page(
+    &self,
+    path: PathBuf,
+    offset: u64,
+    len: usize,
+    maybe_cipher: Option<Arc<Cipher>>,
+  ) {
+    let max_bytes = self.opts.read_prefetch_bytes;
+    if len == 0 || len > max_bytes {
+      return;
+    }
+
+    let key = (path, offset, len);
+    let mut pages = self.prefetcher.pages.lock().await;
+    if pages.reads.contains_key(&key) {
+      return;
+    }
+    while pages.n_bytes + len > max_bytes {
+      let oldest_key = pages.order.front().cloned().unwrap();
+      if let Some(read) = pages.take(&oldest_key) {
+        read.abort();
+      }
+    }
+
+    let path = key.0.clone();
+    let read = tokio::spawn(async move {
+      storage::read_with_offset(&path, offset, len, maybe_cipher.as_deref()).await
+    });
+    pages.reads.insert(key.clone(), read);
+    pages.order.push_back(key);
+    pages.n_bytes += len;
+  }
+
+  // reads up to len bytes from the offset, taking a prefetched page if
+  // there is a full one
+  pub async fn read_page(
+    &self,
+    path: &Path,
+    offset: u64,
+    len: usize,
+    maybe_cipher: Option<&Cipher>,
+  ) -> ServerResult<Vec<u8>> {
+    let key = (path.to_path_buf(), offset, len);
+    let maybe_read = self.prefetcher.pages.lock().await.take(&key);
+    if let Some(read) = maybe_read {
+      let prefetched = read.await
+        .map_err(|e| ServerError::internal(format!("page prefetch failed: {}", e)));
+      match prefetched {
+        Ok(Ok(bytes)) if bytes.len() == len => {
+          slow_ops::add_bytes(len);
+          return Ok(bytes);
+        },
+        Ok(Ok(_)) => (),
+        Ok(Err(e)) | Err(e) => log::debug!("ignoring failed prefetch of {:?}: {}", path, e),
+      }
+    }
+    storage::read_with_offset(path, offset, len, maybe_cipher).await
+  }
+}
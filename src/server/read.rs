@@ -1,68 +1,223 @@
 use std::io::ErrorKind;
 use std::path::Path;
 
-use pancake_db_core::{compression, deletion, encoding};
+use pancake_db_core::compression;
 use pancake_db_idl::dml::FieldValue;
+use pancake_db_idl::dml::field_value::Value;
 use pancake_db_idl::schema::ColumnMeta;
-use tokio::fs;
 
-use crate::errors::ServerResult;
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::compaction::Compaction;
 use crate::types::{CompactionKey, SegmentKey};
+use crate::utils::checksum;
+use crate::utils::columnar::{self, TypedColumn};
 use crate::utils::common;
+use crate::utils::decoding_seek::{self, SeekIndex};
 use crate::utils::dirs;
+use crate::utils::encryption::Cipher;
+use crate::utils::safe_decoding;
+use crate::utils::storage;
+use crate::utils::vfs;
+use crate::utils::zone_map;
 
 use super::Server;
 
 impl Server {
+  // the bytes of one of a column's compacted files, without any checksum
+  // footer, or empty if it doesn't exist
+  pub async fn read_compact_file_bytes(
+    &self,
+    path: &Path,
+    compaction: &Compaction,
+  ) -> ServerResult<Vec<u8>> {
+    let maybe_cipher = self.column_cipher(compaction).await?;
+    let mut file_bytes = storage::read_or_empty(path, maybe_cipher.as_deref()).await?;
+    if compaction.checksummed && !file_bytes.is_empty() {
+      let len = if self.runtime_config().await.verify_checksums_on_read {
+        checksum::verify_and_strip_footer(&file_bytes, path)?.len()
+      } else {
+        checksum::strip_footer(&file_bytes).len()
+      };
+      file_bytes.truncate(len);
+    }
+    Ok(file_bytes)
+  }
+
+  // the compacted blocks of a column (its base file, then each increment
+  // it has) and their codec, if any
+  async fn read_compact_col_blocks(
+    &self,
+    segment_key: &SegmentKey,
+    col_name: &str,
+    read_version: u64,
+    compaction: &Compaction,
+  ) -> ServerResult<Option<(String, Vec<Vec<u8>>)>> {
+    let codec = match compaction.col_codecs.get(col_name) {
+      Some(codec) => codec,
+      None => return Ok(None),
+    };
+    let dir = &self.opts.dir;
+    let compaction_key = segment_key.compaction_key(read_version);
+    let mut paths = vec![dirs::compact_col_file(dir, &compaction_key, col_name)];
+    for increment in 0..compaction.increment_ns.len() {
+      paths.push(dirs::compact_increment_col_file(dir, &compaction_key, col_name, increment));
+    }
+    let mut blocks = Vec::new();
+    for path in &paths {
+      let bytes = self.read_compact_file_bytes(path, compaction).await?;
+      if !bytes.is_empty() {
+        blocks.push(bytes);
+      }
+    }
+    if blocks.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some((codec.clone(), blocks)))
+    }
+  }
+
   pub async fn read_compact_col(
     &self,
     segment_key: &SegmentKey,
     col_name: &str,
     col_meta: &ColumnMeta,
     read_version: u64,
-    codec: &str,
+    compaction: &Compaction,
+    limit: usize,
+  ) -> ServerResult<Vec<FieldValue>> {
+    let (codec, blocks) = match self.read_compact_col_blocks(segment_key, col_name, read_version, compaction).await? {
+      Some(codec_and_blocks) => codec_and_blocks,
+      None => return Ok(Vec::new()),
+    };
+    let decompressor = compression::new_codec(
+      common::unwrap_dtype(col_meta.dtype)?,
+      &codec,
+    )?;
+    let mut decoded = Vec::new();
+    for bytes in &blocks {
+      if decoded.len() >= limit {
+        break;
+      }
+      decoded.extend(safe_decoding::decompress(&*decompressor, bytes, col_meta.nested_list_depth as u8)?);
+    }
+    let limited= if limit < decoded.len() {
+      Vec::from(&decoded[0..limit])
+    } else {
+      decoded
+    };
+    Ok(limited)
+  }
+
+  pub async fn read_flush_col(
+    &self,
+    segment_key: &SegmentKey,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    read_version: u64,
+    compaction: &Compaction,
     limit: usize,
   ) -> ServerResult<Vec<FieldValue>> {
     let compaction_key = segment_key.compaction_key(read_version);
-    let path = dirs::compact_col_file(&self.opts.dir, &compaction_key, col_name);
-    let bytes = common::read_or_empty(&path).await?;
+    let path = dirs::flush_col_file(&self.opts.dir, &compaction_key, col_name);
+    let maybe_cipher = self.column_cipher(compaction).await?;
+    let bytes = storage::read_or_empty(&path, maybe_cipher.as_deref()).await?;
     if bytes.is_empty() {
       Ok(Vec::new())
     } else {
-      let decompressor = compression::new_codec(
-        common::unwrap_dtype(col_meta.dtype)?,
-        codec,
-      )?;
-      let decoded = decompressor.decompress(&bytes, col_meta.nested_list_depth as u8)?;
-      let limited= if limit < decoded.len() {
-        Vec::from(&decoded[0..limit])
-      } else {
-        decoded
-      };
-      Ok(limited)
+      let dtype = common::unwrap_dtype(col_meta.dtype)?;
+      Ok(safe_decoding::decode_limited(dtype, col_meta.nested_list_depth as u8, &bytes, limit)?)
     }
   }
 
-  pub async fn read_flush_col(
+  // Like read_flush_col, but starting at the flushed row start. Reading
+  // begins at the block containing it, so rows in earlier blocks are
+  // neither read nor decoded.
+  #[allow(clippy::too_many_arguments)]
+  pub async fn read_flush_col_range(
     &self,
     segment_key: &SegmentKey,
     col_name: &str,
     col_meta: &ColumnMeta,
     read_version: u64,
+    compaction: &Compaction,
+    start: usize,
     limit: usize,
   ) -> ServerResult<Vec<FieldValue>> {
     let compaction_key = segment_key.compaction_key(read_version);
     let path = dirs::flush_col_file(&self.opts.dir, &compaction_key, col_name);
-    let bytes = common::read_or_empty(&path).await?;
+    let maybe_cipher = self.column_cipher(compaction).await?;
+    let seek_index = self.read_seek_index(&compaction_key, col_name, maybe_cipher.as_deref()).await?;
+    let span = seek_index.span_for_row_idx(start);
+    let flush_byte_len = seek_index.flush_byte_len();
+    let bytes = storage::read_with_offset(
+      &path,
+      span.start,
+      (flush_byte_len - span.start) as usize,
+      maybe_cipher.as_deref(),
+    ).await?;
     if bytes.is_empty() {
       Ok(Vec::new())
     } else {
       let dtype = common::unwrap_dtype(col_meta.dtype)?;
-      let decoder = encoding::new_field_value_decoder(dtype, col_meta.nested_list_depth as u8);
-      Ok(decoder.decode_limited(&bytes, limit)?)
+      Ok(safe_decoding::decode_range(dtype, col_meta.nested_list_depth as u8, &bytes, start - span.row_idx, limit)?)
     }
   }
 
+  // The flush file's seek index, taken from its zone map's blocks. A zone
+  // map is behind its flush file for unrecorded writes and until recovery
+  // trims it, so only the blocks within the file are used.
+  pub async fn read_seek_index(
+    &self,
+    compaction_key: &CompactionKey,
+    col_name: &str,
+    maybe_cipher: Option<&Cipher>,
+  ) -> ServerResult<SeekIndex> {
+    let dir = &self.opts.dir;
+    let flush_byte_len = storage::len_or_zero(&dirs::flush_col_file(dir, compaction_key, col_name), maybe_cipher).await?;
+    let zone_map_bytes = storage::read_or_empty(&dirs::zone_map_file(dir, compaction_key, col_name), maybe_cipher).await?;
+    let blocks = zone_map::parse_prefix(&zone_map_bytes, flush_byte_len);
+    Ok(SeekIndex::new(&blocks, flush_byte_len))
+  }
+
+  // The flushed values at each of the given offsets into the flush file,
+  // which must be strictly increasing. Only the blocks containing them are
+  // read, seeking within each instead of decoding the values between them.
+  #[allow(clippy::too_many_arguments)]
+  pub async fn read_flush_col_at(
+    &self,
+    segment_key: &SegmentKey,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    read_version: u64,
+    compaction: &Compaction,
+    offsets: &[usize],
+  ) -> ServerResult<Vec<FieldValue>> {
+    if offsets.is_empty() {
+      return Ok(Vec::new());
+    }
+    let compaction_key = segment_key.compaction_key(read_version);
+    let path = dirs::flush_col_file(&self.opts.dir, &compaction_key, col_name);
+    let maybe_cipher = self.column_cipher(compaction).await?;
+    let seek_index = self.read_seek_index(&compaction_key, col_name, maybe_cipher.as_deref()).await?;
+    let dtype = common::unwrap_dtype(col_meta.dtype)?;
+    let nested_list_depth = col_meta.nested_list_depth as u8;
+
+    let mut res = Vec::with_capacity(offsets.len());
+    let mut i = 0;
+    while i < offsets.len() {
+      let span = seek_index.span_for_row_idx(offsets[i]);
+      let mut span_row_idxs = Vec::new();
+      while i < offsets.len() && seek_index.span_for_row_idx(offsets[i]) == span {
+        span_row_idxs.push(offsets[i] - span.row_idx);
+        i += 1;
+      }
+      let bytes = storage::read_with_offset(&path, span.start, span.byte_len(), maybe_cipher.as_deref()).await?;
+      res.extend(decoding_seek::decode_at_row_idxs(dtype, nested_list_depth, &bytes, &span_row_idxs)?);
+    }
+    Ok(res)
+  }
+
   // Includes only compacted and flushed data, omitting staged rows
   pub async fn read_col(
     &self,
@@ -70,34 +225,132 @@ impl Server {
     col_name: &str,
     col_meta: &ColumnMeta,
     read_version: u64,
-    maybe_compression_params: Option<&String>,
+    compaction: &Compaction,
     limit: usize,
   ) -> ServerResult<Vec<FieldValue>> {
-    let mut values = Vec::new();
-    if let Some(compression_params) = maybe_compression_params {
-      values.extend(
-        self.read_compact_col(
-          segment_key,
-          col_name,
-          col_meta,
-          read_version,
-          compression_params,
-          limit
-        ).await?
-      );
-    }
+    let mut values = self.read_compact_col(
+      segment_key,
+      col_name,
+      col_meta,
+      read_version,
+      compaction,
+      limit
+    ).await?;
     if values.len() < limit {
       values.extend(self.read_flush_col(
         segment_key,
         col_name,
         col_meta,
         read_version,
+        compaction,
         limit - values.len()
       ).await?);
     }
     Ok(values)
   }
 
+  // Like read_col, but only the values at positions start onward. Flushed
+  // values before start aren't decoded.
+  #[allow(clippy::too_many_arguments)]
+  pub async fn read_col_range(
+    &self,
+    segment_key: &SegmentKey,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    read_version: u64,
+    compaction: &Compaction,
+    start: usize,
+    limit: usize,
+  ) -> ServerResult<Vec<FieldValue>> {
+    let n_compacted = compaction.all_time_compacted_n as usize;
+    let mut values = Vec::new();
+    if start < n_compacted {
+      let compacted_values = self.read_compact_col(
+        segment_key,
+        col_name,
+        col_meta,
+        read_version,
+        compaction,
+        start.saturating_add(limit),
+      ).await?;
+      values.extend(compacted_values.into_iter().skip(start));
+    }
+    if values.len() < limit {
+      values.extend(self.read_flush_col_range(
+        segment_key,
+        col_name,
+        col_meta,
+        read_version,
+        compaction,
+        start.saturating_sub(n_compacted),
+        limit - values.len(),
+      ).await?);
+    }
+    Ok(values)
+  }
+
+  // Like read_col, but decodes into a typed column without building a
+  // FieldValue for each value.
+  pub async fn read_typed_col(
+    &self,
+    segment_key: &SegmentKey,
+    col_name: &str,
+    col_meta: &ColumnMeta,
+    read_version: u64,
+    compaction: &Compaction,
+    limit: usize,
+  ) -> ServerResult<TypedColumn> {
+    let dtype = common::unwrap_dtype(col_meta.dtype)?;
+    let nested_list_depth = col_meta.nested_list_depth as u8;
+    let mut column = TypedColumn::empty(dtype, nested_list_depth);
+    if let Some((codec, blocks)) = self.read_compact_col_blocks(segment_key, col_name, read_version, compaction).await? {
+      for bytes in &blocks {
+        if column.n_rows() >= limit {
+          break;
+        }
+        column.extend(columnar::decompress_columnar(dtype, &codec, bytes, nested_list_depth)?)?;
+      }
+      column.truncate_rows(limit);
+    }
+    let n_rows = column.n_rows();
+    if n_rows < limit {
+      let compaction_key = segment_key.compaction_key(read_version);
+      let path = dirs::flush_col_file(&self.opts.dir, &compaction_key, col_name);
+      let maybe_cipher = self.column_cipher(compaction).await?;
+      let bytes = storage::read_or_empty(&path, maybe_cipher.as_deref()).await?;
+      column.extend(columnar::decode_columnar(dtype, nested_list_depth, &bytes, limit - n_rows)?)?;
+    }
+    Ok(column)
+  }
+
+  // Sorted compactions store rows out of row id order. This returns the row
+  // id at each compacted position, or nothing if they are in order.
+  pub async fn read_compacted_row_ids(
+    &self,
+    segment_key: &SegmentKey,
+    read_version: u64,
+    compaction: &Compaction,
+  ) -> ServerResult<Vec<u32>> {
+    if compaction.sort_columns.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    self.read_compact_col(
+      segment_key,
+      ROW_ID_COLUMN_NAME,
+      &common::row_id_column_meta(),
+      read_version,
+      compaction,
+      usize::MAX,
+    ).await?
+      .iter()
+      .map(|value| match &value.value {
+        Some(Value::Int64Val(row_id)) => Ok(*row_id as u32),
+        _ => Err(ServerError::corrupt("row id column contains a non-integer")),
+      })
+      .collect()
+  }
+
   pub async fn read_pre_compaction_deletions(
     &self,
     compaction_key: &CompactionKey,
@@ -124,13 +377,46 @@ impl Server {
     ).await
   }
 
+  // Combines a compaction's pre- and post-compaction deletions into one
+  // bitmap by position. Post-compaction deletions skip over positions
+  // that were already deleted before the compaction.
+  pub async fn read_combined_deletions(
+    &self,
+    compaction_key: &CompactionKey,
+    deletion_id: u64,
+  ) -> ServerResult<Vec<bool>> {
+    let pre_compaction_deletions = self.read_pre_compaction_deletions(
+      compaction_key
+    ).await?;
+    let post_compaction_deletions = self.read_post_compaction_deletions(
+      compaction_key,
+      deletion_id,
+    ).await?;
+
+    let n_pre = pre_compaction_deletions.len();
+    let n_post = post_compaction_deletions.len();
+    let mut res = Vec::new();
+    let mut position = 0;
+    let mut post_i = 0;
+    while position < n_pre || post_i < n_post {
+      let pre_deleted = position < n_pre && pre_compaction_deletions[position];
+      let post_deleted = post_i < n_post && post_compaction_deletions[post_i];
+      res.push(pre_deleted || post_deleted);
+      if !pre_deleted {
+        post_i += 1
+      }
+      position += 1
+    }
+    Ok(res)
+  }
+
   async fn read_deletions(
     &self,
     path: &Path,
   ) -> ServerResult<Vec<bool>> {
-    match fs::read(path).await {
+    match vfs::read(path).await {
       Ok(bytes) => {
-        Ok(deletion::decompress_deletions(&bytes)?)
+        Ok(safe_decoding::decompress_deletions(&bytes)?)
       },
       Err(e) if matches!(e.kind(), ErrorKind::NotFound) => {
         Ok(Vec::new())
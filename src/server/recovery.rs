@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
 use futures::pin_mut;
 
 use uuid::Uuid;
@@ -6,33 +8,87 @@ use futures::StreamExt;
 
 use crate::errors::Contextable;
 use crate::errors::ServerResult;
+use crate::ops::commit_tx::CommitTxOp;
 use crate::ops::compact::CompactionOp;
+use crate::ops::copy_table::CopyTableOp;
 use crate::ops::drop_table::DropTableOp;
 use crate::ops::flush::FlushOp;
+use crate::ops::garbage_collect::GarbageCollectOp;
 use crate::ops::write_to_partition::WriteToPartitionOp;
 use crate::server::Server;
+use crate::metadata::manifest;
 use crate::metadata::PersistentMetadata;
 use crate::metadata::partition::PartitionMetadata;
 use crate::metadata::segment::SegmentMetadata;
 use crate::types::{InternalTableInfo, NormalizedPartition, PartitionKey};
+use crate::utils::dirs;
 use crate::utils::navigation;
+use crate::utils::vfs;
 
 impl Server {
   pub async fn recover(&self) -> ServerResult<()> {
     log::info!("recovering to clean state");
+    self.upgrade_format()
+      .await
+      .with_context(|| "while upgrading the dir's format")?;
+    self.load_partition_dir_fanout()
+      .await
+      .with_context(|| "while loading partition dir fanout")?;
+
+    // no atomic overwrites can be in progress yet, so every tmp file is orphaned
+    let n_tmp_files = self.remove_orphaned_tmp_files(Duration::ZERO)
+      .await
+      .with_context(|| "while removing orphaned tmp files")?;
+    if n_tmp_files > 0 {
+      log::info!("removed {} orphaned tmp files", n_tmp_files);
+    }
+
+    // bring multi-file metadata mutations to a consistent state before
+    // anything reads metadata
+    let n_manifest_entries = manifest::replay(&self.opts.dir)
+      .await
+      .with_context(|| "while replaying metadata manifest")?;
+    if n_manifest_entries > 0 {
+      log::info!("replayed {} metadata manifest entries", n_manifest_entries);
+    }
+
+    // committed transactions must be fully appended before segments recover
+    // their staged rows
+    let committed_staged_rows_paths = CommitTxOp::recover(self)
+      .await
+      .with_context(|| "while finishing committed transactions")?;
+    if !committed_staged_rows_paths.is_empty() {
+      log::info!(
+        "finished interrupted transaction commits to {} staged rows files",
+        committed_staged_rows_paths.len(),
+      );
+    }
+
+    CopyTableOp::recover(self)
+      .await
+      .with_context(|| "while removing interrupted table copies")?;
 
     let table_infos = self.internal_list_tables()
       .await
       .with_context(|| "while listing tables")?;
     for table_info in &table_infos {
-      self.recover_table(table_info.clone())
+      self.recover_table(table_info.clone(), &committed_staged_rows_paths)
         .await
         .with_context(|| format!("while recovering table {}", table_info.name))?;
     }
+
+    // only once everything is consistent in the old layout
+    self.migrate_partition_layout()
+      .await
+      .with_context(|| "while migrating partition dirs")?;
     Ok(())
   }
 
-  async fn recover_table(&self, table_info: InternalTableInfo) -> ServerResult<()> {
+  async fn recover_table(
+    &self,
+    table_info: InternalTableInfo,
+    committed_staged_rows_paths: &HashSet<PathBuf>,
+  ) -> ServerResult<()> {
     log::debug!("recovering table {}", table_info.name);
     // 1. Dropped tables
     let InternalTableInfo {
@@ -60,6 +116,10 @@ impl Server {
         table_name: table_name.clone(),
         partition: normalized.clone(),
       };
+      GarbageCollectOp::recover(self, &partition_key)
+        .await
+        .with_context(|| format!("while removing garbage segments in {}", normalized))?;
+
       let maybe_partition_meta = PartitionMetadata::load(dir, &partition_key)
         .await
         .with_context(|| format!("while loading partition meta {}", normalized))?;
@@ -85,23 +145,36 @@ impl Server {
           .with_context(|| format!("while loading segment metadata for {}", segment_key))?;
 
         if maybe_segment_meta.is_none() {
+          // nothing refers to a segment whose metadata was never committed,
+          // as when a split is interrupted
+          log::debug!("identified segment {} without metadata; removing it", segment_key);
+          vfs::remove_dir_all(dirs::segment_dir(dir, &segment_key)).await?;
           continue;
         }
         let segment_meta = maybe_segment_meta.as_mut().unwrap();
 
-        // 2. Compactions
+        // 2. Merges and splits
+        self.recover_rewrite(&segment_key, segment_meta).await?;
+
+        // 3. Compactions
         CompactionOp::recover(self, &segment_key, segment_meta).await?;
+        if self.opts.verify_checksums_on_recovery {
+          if let Err(e) = CompactionOp::verify_checksums(self, &segment_key, segment_meta).await {
+            log::error!("corrupt compacted data in {}: {}", segment_key, e);
+          }
+        }
 
-        // 3. Flushes
+        // 4. Flushes
         FlushOp::recover(self, &table_meta, &segment_key, segment_meta).await?;
 
         if active_segment_ids.contains(&segment_id) {
-          // 4. Writes
-          WriteToPartitionOp::recover(self, &segment_key, segment_meta).await?;
+          // 5. Writes
+          let committed = committed_staged_rows_paths.contains(&dirs::staged_rows_path(dir, &segment_key));
+          WriteToPartitionOp::recover(self, &table_meta, &segment_key, segment_meta, committed).await?;
         }
 
-        // 5. background state
-        // 5a. flush candidates
+        // 6. background state
+        // 6a. flush candidates
         if segment_meta.staged_n > 0 {
           log::debug!("adding segment {} as flush candidate", segment_key);
           self.background.add_flush_candidate(segment_key.clone()).await;
@@ -109,6 +182,10 @@ impl Server {
       }
     }
 
+    // 7. partition index
+    self.rebuild_partition_index(&table_name, &table_meta)
+      .await
+      .with_context(|| "while rebuilding partition index")?;
     Ok(())
   }
 }
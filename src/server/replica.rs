@@ -0,0 +1,80 @@
+use tokio::time::{Duration, Instant};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::global::GlobalMetadata;
+use crate::metadata::PersistentMetadata;
+use crate::types::EmptyKey;
+
+use super::{REPLICA_REFRESH_LOOP, Server};
+
+// A read-only server serves reads from a dir that another server writes to.
+// It never modifies the dir, so it only learns of writes, flushes, and
+// compactions by periodically reloading the metadata it has cached.
+impl Server {
+  pub fn check_writable(&self) -> ServerResult<()> {
+    if self.opts.read_only {
+      return Err(ServerError::read_only("writes must go to the server that owns this dir"));
+    }
+    match self.unhealthy_reason() {
+      Some(reason) => Err(ServerError::internal(format!(
+        "server is unhealthy and takes no writes until restarted: {}",
+        reason,
+      ))),
+      None => Ok(()),
+    }
+  }
+
+  // For failures that leave the dir in a state only recovery at startup
+  // can fix. Until then, the server stops changing the dir and reports
+  // itself as not ready.
+  pub fn mark_unhealthy(&self, reason: impl Into<String>) {
+    let reason = reason.into();
+    log::error!("marking server unhealthy: {}", reason);
+    *self.unhealthy_reason.lock().unwrap() = Some(reason);
+  }
+
+  pub fn unhealthy_reason(&self) -> Option<String> {
+    self.unhealthy_reason.lock().unwrap().clone()
+  }
+
+  pub async fn refresh_metadata(&self) -> ServerResult<()> {
+    let dir = &self.opts.dir;
+    if let Some(global_meta) = GlobalMetadata::load(dir, &EmptyKey).await? {
+      *self.global_metadata_lock.write().await = global_meta;
+    }
+    self.table_metadata_cache.refresh().await?;
+    self.partition_index_cache.refresh().await?;
+    self.partition_metadata_cache.refresh().await?;
+    self.segment_metadata_cache.refresh().await?;
+    self.compaction_cache.refresh().await?;
+    Ok(())
+  }
+
+  pub async fn refresh_metadata_forever(&self) {
+    self.supervise(REPLICA_REFRESH_LOOP, |_| self.refresh_metadata_loop()).await
+  }
+
+  async fn refresh_metadata_loop(&self) {
+    let mut last_t = Instant::now();
+    let refresh_interval = Duration::from_secs(self.opts.replica_refresh_seconds);
+    loop {
+      let cur_t = Instant::now();
+      let planned_t = last_t + refresh_interval;
+      if cur_t < planned_t {
+        tokio::time::sleep_until(planned_t).await;
+      }
+      last_t = cur_t;
+      let iteration_t = Instant::now();
+      self.background.start_loop_iteration(REPLICA_REFRESH_LOOP, iteration_t.saturating_duration_since(planned_t)).await;
+      if let Err(e) = self.refresh_metadata().await {
+        log::error!("refreshing replica metadata failed: {}", e);
+      }
+      self.background.finish_loop_iteration(REPLICA_REFRESH_LOOP, iteration_t.elapsed()).await;
+
+      let is_active = self.activity.is_active().await;
+      if !is_active {
+        return;
+      }
+    }
+  }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+
+use pancake_db_idl::dml::{FieldValue, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+
+use crate::constants::ROW_ID_COLUMN_NAME;
+use crate::errors::{ServerError, ServerResult};
+use crate::metadata::{MetadataJson, PersistentMetadata};
+use crate::metadata::compaction::Compaction;
+use crate::metadata::partition::PartitionMetadata;
+use crate::metadata::segment::SegmentMetadata;
+use crate::types::SegmentKey;
+use crate::utils::common;
+use crate::utils::dirs;
+use crate::utils::navigation;
+use crate::utils::vfs;
+
+use super::Server;
+
+// Merges and splits rewrite the live rows of some segments into new
+// segments, then remove the originals. Each new segment records the
+// segments it replaces until they are gone, so recovery can finish an
+// interrupted rewrite.
+impl Server {
+  // Reads a segment's rows that haven't been deleted, in position order.
+  // The caller must hold the segment's deletion and segment locks.
+  pub async fn read_live_rows(
+    &self,
+    segment_key: &SegmentKey,
+    segment_meta: &SegmentMetadata,
+    columns: &HashMap<String, ColumnMeta>,
+  ) -> ServerResult<Vec<Row>> {
+    let compaction_key = segment_key.compaction_key(segment_meta.read_version);
+    let compaction = self.compaction_cache
+      .get_lock(&compaction_key)
+      .await?
+      .read()
+      .await
+      .clone()
+      .unwrap_or_default();
+    let deletions = self.read_combined_deletions(
+      &compaction_key,
+      segment_meta.deletion_id,
+    ).await?;
+
+    let n = segment_meta.all_time_n as usize;
+    let mut rows = vec![Row::default(); n];
+    for (col_name, col_meta) in columns {
+      if !segment_meta.explicit_columns.contains(col_name) {
+        continue;
+      }
+      let values = self.read_col(
+        segment_key,
+        col_name,
+        col_meta,
+        segment_meta.read_version,
+        &compaction,
+        usize::MAX,
+      ).await?;
+      if values.len() != n {
+        return Err(ServerError::corrupt(format!(
+          "segment {} has {} rows in column {} but {} in metadata",
+          segment_key,
+          values.len(),
+          col_name,
+          n,
+        )));
+      }
+      for (row, value) in rows.iter_mut().zip(values) {
+        if value.value.is_some() {
+          row.fields.insert(col_name.clone(), value);
+        }
+      }
+    }
+
+    Ok(rows.into_iter()
+      .enumerate()
+      .filter(|(position, _)| !deletions.get(*position).cloned().unwrap_or(false))
+      .map(|(_, row)| row)
+      .collect())
+  }
+
+  // Builds a cold segment with the rows staged (so the flush loop will
+  // flush them) and row ids renumbered from 0. It is built in the
+  // segment's garbage directory so that it isn't listed until the caller
+  // moves it into place.
+  pub async fn build_rewritten_segment(
+    &self,
+    segment_key: &SegmentKey,
+    schema: &Schema,
+    rows: &mut [Row],
+  ) -> ServerResult<SegmentMetadata> {
+    for (row_id, row) in rows.iter_mut().enumerate() {
+      row.fields.insert(ROW_ID_COLUMN_NAME.to_string(), FieldValue {
+        value: Some(Value::Int64Val(row_id as i64)),
+      });
+    }
+
+    let mut segment_meta = SegmentMetadata::new_from_schema(schema);
+    segment_meta.all_time_n = rows.len() as u64;
+    segment_meta.staged_n = rows.len() as u64;
+    segment_meta.all_time_uncompressed_size = rows.iter()
+      .map(|row| row.fields.values()
+        .map(common::byte_size_of_field)
+        .sum::<usize>())
+      .sum::<usize>() as u64;
+    segment_meta.is_cold = true;
+
+    let building_dir = dirs::garbage_segment_dir(&self.opts.dir, segment_key);
+    navigation::create_segment_dirs(&building_dir).await?;
+    if let Some(compaction) = self.initial_compaction() {
+      let relative_path = Compaction::relative_path(&segment_key.compaction_key(0));
+      let segment_relative_dir = dirs::relative_segment_dir(segment_key);
+      common::overwrite_file(
+        building_dir.join(relative_path.strip_prefix(&segment_relative_dir).unwrap()),
+        compaction.to_json_string()?,
+      ).await?;
+    }
+    common::overwrite_file(
+      building_dir.join("staged_rows"),
+      &common::rows_to_staged_bytes(rows)?,
+    ).await?;
+    Ok(segment_meta)
+  }
+
+  // Removes segments that have been rewritten from the partition's active
+  // segments, then moves each directory out of the way and removes it.
+  // The caller must hold the partition write lock.
+  pub async fn remove_replaced_segments(
+    &self,
+    partition_meta: &mut PartitionMetadata,
+    segment_keys: &[SegmentKey],
+  ) -> ServerResult<()> {
+    let dir = &self.opts.dir;
+    if let Some(first_key) = segment_keys.first() {
+      let segment_ids: Vec<_> = segment_keys.iter()
+        .map(|key| key.segment_id)
+        .collect();
+      self.remove_from_partition_index(&first_key.partition_key(), &segment_ids).await?;
+      let n_active = partition_meta.active_segment_ids.len();
+      partition_meta.active_segment_ids.retain(|id| segment_keys.iter().all(|key| key.segment_id != *id));
+      if partition_meta.active_segment_ids.len() != n_active {
+        partition_meta.overwrite(dir, &first_key.partition_key()).await?;
+      }
+    }
+
+    for segment_key in segment_keys {
+      let garbage_dir = dirs::garbage_segment_dir(dir, segment_key);
+      match vfs::rename(dirs::segment_dir(dir, segment_key), &garbage_dir).await {
+        Ok(()) => (),
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound) => continue,
+        Err(e) => return Err(e.into()),
+      }
+      self.compaction_cache.prune(|key| &key.segment_key() == segment_key)
+        .await;
+      vfs::remove_dir_all(&garbage_dir).await?;
+    }
+    Ok(())
+  }
+
+  // finishes removing the segments a segment replaced if a merge or split
+  // was interrupted
+  pub async fn recover_rewrite(
+    &self,
+    segment_key: &SegmentKey,
+    segment_meta: &mut SegmentMetadata,
+  ) -> ServerResult<()> {
+    if segment_meta.replaced_segment_ids.is_empty() {
+      return Ok(());
+    }
+
+    let partition_key = segment_key.partition_key();
+    log::debug!(
+      "identified interrupted rewrite into {}; removing {} replaced segments",
+      segment_key,
+      segment_meta.replaced_segment_ids.len(),
+    );
+    let replaced_keys: Vec<_> = segment_meta.replaced_segment_ids.iter()
+      .map(|&segment_id| partition_key.segment_key(segment_id))
+      .collect();
+    let partition_lock = self.partition_metadata_cache.get_lock(&partition_key)
+      .await?;
+    let mut partition_guard = partition_lock.write().await;
+    if let Some(partition_meta) = &mut *partition_guard {
+      self.remove_replaced_segments(partition_meta, &replaced_keys).await?;
+    }
+    segment_meta.replaced_segment_ids = Vec::new();
+    segment_meta.overwrite(&self.opts.dir, segment_key).await
+  }
+}
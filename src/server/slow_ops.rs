@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use futures::Future;
+use pancake_db_idl::ddl::CreateTableRequest;
+use pancake_db_idl::ddl::create_table_request::SchemaMode;
+use pancake_db_idl::dml::{FieldValue, Row, WriteToPartitionRequest};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use tokio::time::{Duration, Instant};
+
+use crate::constants::{SLOW_OP_BYTES_COLUMN_NAME, SLOW_OP_DURATION_COLUMN_NAME, SLOW_OP_KEY_COLUMN_NAME, SLOW_OP_LOCK_WAITS_COLUMN_NAME, SLOW_OP_NAME_COLUMN_NAME, SLOW_OP_PRINCIPAL_COLUMN_NAME};
+use crate::errors::ServerResult;
+use crate::metadata::table::TableConfigOverrides;
+use crate::ops::create_table::CreateTableOp;
+use crate::ops::traits::ServerOp;
+use crate::ops::write_to_partition::WriteToPartitionOp;
+use crate::utils::chaos;
+
+use super::authz::{self, Principal};
+use super::cancel;
+use super::Server;
+
+tokio::task_local! {
+  static PROFILE: Arc<OpProfile>;
+}
+
+// What an op spent its time on, gathered while it runs. Ops an op runs
+// itself, like writing dead letters, count toward the outer op.
+#[derive(Default)]
+pub struct OpProfile {
+  // time spent waiting for locks, by kind of lock
+  lock_waits: Mutex<HashMap<&'static str, Duration>>,
+  // bytes of column files and staged rows read or written
+  n_bytes: AtomicU64,
+}
+
+// a finished op that took longer than the slow op threshold
+pub struct SlowOp {
+  pub name: &'static str,
+  pub key: String,
+  pub principal: Principal,
+  pub duration: Duration,
+  pub lock_waits: Vec<(&'static str, Duration)>,
+  pub n_bytes: u64,
+}
+
+impl SlowOp {
+  fn lock_waits_string(&self) -> String {
+    if self.lock_waits.is_empty() {
+      return "none".to_string();
+    }
+    self.lock_waits.iter()
+      .map(|(kind, wait)| format!("{}={}ms", kind, wait.as_millis()))
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
+}
+
+pub fn is_profiling() -> bool {
+  PROFILE.try_with(|_| ()).is_ok()
+}
+
+// runs a future, gathering what it does into the profile
+pub async fn scope<F: Future>(profile: Arc<OpProfile>, future: F) -> F::Output {
+  PROFILE.scope(profile, future).await
+}
+
+// Waits for a lock, counting the wait toward the current op. Gives up if
+// the op is cancelled while waiting. Chaos mode sometimes adds to the wait.
+pub async fn wait_for_lock<F: Future>(lock_kind: &'static str, future: F) -> ServerResult<F::Output> {
+  let start = Instant::now();
+  chaos::delay_lock().await;
+  let res = cancel::unless_cancelled(future).await;
+  let wait = start.elapsed();
+  let _ = PROFILE.try_with(|profile| {
+    *profile.lock_waits.lock().unwrap().entry(lock_kind).or_default() += wait;
+  });
+  res
+}
+
+// counts bytes read or written toward the current op
+pub fn add_bytes(n_bytes: usize) {
+  let _ = PROFILE.try_with(|profile| {
+    profile.n_bytes.fetch_add(n_bytes as u64, Ordering::Relaxed);
+  });
+}
+
+fn slow_op_schema() -> Schema {
+  let column = |dtype: DataType| ColumnMeta {
+    dtype: dtype as i32,
+    nested_list_depth: 0,
+  };
+  let mut columns = HashMap::new();
+  for col_name in [SLOW_OP_NAME_COLUMN_NAME, SLOW_OP_KEY_COLUMN_NAME, SLOW_OP_PRINCIPAL_COLUMN_NAME, SLOW_OP_LOCK_WAITS_COLUMN_NAME] {
+    columns.insert(col_name.to_string(), column(DataType::String));
+  }
+  for col_name in [SLOW_OP_DURATION_COLUMN_NAME, SLOW_OP_BYTES_COLUMN_NAME] {
+    columns.insert(col_name.to_string(), column(DataType::Int64));
+  }
+  Schema {
+    columns,
+    ..Default::default()
+  }
+}
+
+impl Server {
+  // Logs an op that took at least the slow op threshold, and writes it to
+  // the slow op table if there is one.
+  pub async fn record_op_profile(
+    &self,
+    name: &'static str,
+    key: String,
+    duration: Duration,
+    profile: &OpProfile,
+  ) {
+    let config = self.runtime_config().await;
+    if config.slow_op_millis == 0 || duration < Duration::from_millis(config.slow_op_millis) {
+      return;
+    }
+
+    let mut lock_waits = profile.lock_waits.lock().unwrap()
+      .iter()
+      .map(|(&kind, &wait)| (kind, wait))
+      .collect::<Vec<_>>();
+    lock_waits.sort_by_key(|(kind, _)| *kind);
+    let slow_op = SlowOp {
+      name,
+      key,
+      principal: authz::current_principal(),
+      duration,
+      lock_waits,
+      n_bytes: profile.n_bytes.load(Ordering::Relaxed),
+    };
+    log::warn!(
+      "slow op {} on {} for {} took {}ms (lock waits: {}) and processed {} bytes",
+      slow_op.name,
+      slow_op.key,
+      slow_op.principal,
+      slow_op.duration.as_millis(),
+      slow_op.lock_waits_string(),
+      slow_op.n_bytes,
+    );
+
+    if let Some(table_name) = config.slow_op_table {
+      // in the background, so the slow op's response isn't delayed further
+      let server = self.clone();
+      tokio::spawn(async move {
+        if let Err(e) = server.write_slow_op(&table_name, slow_op).await {
+          log::error!("writing slow op to table {} failed: {}", table_name, e);
+        }
+      });
+    }
+  }
+
+  async fn write_slow_op(&self, table_name: &str, slow_op: SlowOp) -> ServerResult<()> {
+    // Writing the slow op is an op itself; profiling it as part of a
+    // throwaway op keeps slow writes here from being recorded in turn.
+    let future = authz::scope(Principal::Internal, self.write_slow_op_internal(table_name, slow_op));
+    scope(Arc::new(OpProfile::default()), future).await
+  }
+
+  async fn write_slow_op_internal(&self, table_name: &str, slow_op: SlowOp) -> ServerResult<()> {
+    let table_lock = self.table_metadata_cache.get_lock(&table_name.to_string()).await?;
+    let exists = table_lock.read().await.is_some();
+    if !exists {
+      CreateTableOp {
+        req: CreateTableRequest {
+          table_name: table_name.to_string(),
+          schema: Some(slow_op_schema()),
+          mode: SchemaMode::OkIfExact as i32,
+        },
+        sort_columns: Vec::new(),
+        computed_columns: HashMap::new(),
+        column_masks: HashMap::new(),
+        config_overrides: TableConfigOverrides::default(),
+      }.execute(self).await?;
+    }
+
+    let string_value = |s: String| FieldValue {
+      value: Some(Value::StringVal(s)),
+    };
+    let int64_value = |x: u64| FieldValue {
+      value: Some(Value::Int64Val(x as i64)),
+    };
+    let row = Row {
+      fields: vec![
+        (SLOW_OP_NAME_COLUMN_NAME.to_string(), string_value(slow_op.name.to_string())),
+        (SLOW_OP_KEY_COLUMN_NAME.to_string(), string_value(slow_op.key.clone())),
+        (SLOW_OP_PRINCIPAL_COLUMN_NAME.to_string(), string_value(slow_op.principal.id().to_string())),
+        (SLOW_OP_DURATION_COLUMN_NAME.to_string(), int64_value(slow_op.duration.as_millis() as u64)),
+        (SLOW_OP_LOCK_WAITS_COLUMN_NAME.to_string(), string_value(slow_op.lock_waits_string())),
+        (SLOW_OP_BYTES_COLUMN_NAME.to_string(), int64_value(slow_op.n_bytes)),
+      ].into_iter().collect(),
+    };
+    WriteToPartitionOp {
+      req: WriteToPartitionRequest {
+        table_name: table_name.to_string(),
+        partition: HashMap::new(),
+        rows: vec![row],
+      },
+    }.execute(self).await?;
+    Ok(())
+  }
+}
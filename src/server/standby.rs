@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use futures::pin_mut;
+use futures::StreamExt;
+use tokio::time::{Duration, Instant};
+
+use crate::constants::GARBAGE_SEGMENT_PREFIX;
+use crate::errors::ServerResult;
+use crate::utils::{common, dirs, vfs};
+use crate::utils::dirs::FileKind;
+
+use super::{STANDBY_SHIPPER_LOOP, Server};
+
+// how much of the end of a standby's copy of an append-only file must match
+// the original before we only copy what was appended since
+const TAIL_CHECK_BYTE_SIZE: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShipStats {
+  pub n_files_copied: u64,
+  pub n_bytes_copied: u64,
+  pub n_entries_removed: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Depth {
+  // copy the dir's files, but only create its subdirs
+  Shallow,
+  Deep,
+}
+
+// A warm standby is a copy of this server's dir that another server can be
+// started on if this one is lost. The shipper brings the copy up to date by
+// copying whatever has changed since its last pass and removing whatever is
+// gone, so its first pass also catches up a standby that is stale or empty.
+// It copies each segment under that segment's deletion and read locks, so
+// every segment in the standby is consistent, and recovery on the standby
+// reconciles anything that spans segments.
+impl Server {
+  pub async fn ship_to_standby(&self, standby_dir: &Path) -> ServerResult<ShipStats> {
+    let dir = &self.opts.dir;
+    let mut stats = ShipStats::default();
+    vfs::create_dir_all(dirs::tmp_dir(standby_dir)).await?;
+
+    // global metadata, and removing the copies of dropped tables
+    let tmp_dir = dirs::tmp_dir(dir);
+    let is_tmp_dir = |name: &str| tmp_dir.ends_with(name);
+    ship_dir(dir, standby_dir, Depth::Shallow, &is_tmp_dir, standby_dir, &mut stats).await?;
+    for dir_fn in [dirs::manifest_dir, dirs::transaction_dir] {
+      ship_dir(&dir_fn(dir), &dir_fn(standby_dir), Depth::Deep, &|_| false, standby_dir, &mut stats).await?;
+    }
+
+    for table in self.internal_list_tables().await? {
+      ship_dir(
+        &dirs::table_dir(dir, &table.name),
+        &dirs::table_dir(standby_dir, &table.name),
+        Depth::Shallow,
+        &|_| false,
+        standby_dir,
+        &mut stats,
+      ).await?;
+    }
+
+    let is_garbage = |name: &str| name.starts_with(GARBAGE_SEGMENT_PREFIX);
+    let segment_key_stream = self.stream_all_segment_keys();
+    pin_mut!(segment_key_stream);
+    let mut last_partition_key = None;
+    while let Some(segment_key_result) = segment_key_stream.next().await {
+      let segment_key = segment_key_result?;
+
+      // segments are listed partition by partition
+      let partition_key = segment_key.partition_key();
+      if last_partition_key.as_ref() != Some(&partition_key) {
+        ship_dir(
+          &dirs::partition_dir(dir, &partition_key),
+          &dirs::partition_dir(standby_dir, &partition_key),
+          Depth::Shallow,
+          &is_garbage,
+          standby_dir,
+          &mut stats,
+        ).await?;
+        last_partition_key = Some(partition_key);
+      }
+
+      let deletion_lock = self.deletion_metadata_cache.get_lock(&segment_key).await?;
+      let _deletion_guard = deletion_lock.read().await;
+      let segment_lock = self.segment_metadata_cache.get_lock(&segment_key).await?;
+      let _segment_guard = segment_lock.read().await;
+      ship_dir(
+        &dirs::segment_dir(dir, &segment_key),
+        &dirs::segment_dir(standby_dir, &segment_key),
+        Depth::Deep,
+        &|_| false,
+        standby_dir,
+        &mut stats,
+      ).await?;
+    }
+    Ok(stats)
+  }
+
+  pub async fn ship_to_standby_forever(&self, standby_dir: PathBuf) {
+    self.supervise(STANDBY_SHIPPER_LOOP, |_| self.ship_to_standby_loop(&standby_dir)).await
+  }
+
+  async fn ship_to_standby_loop(&self, standby_dir: &Path) {
+    let ship_interval = Duration::from_secs(self.opts.standby_ship_seconds);
+    // catch the standby up right away
+    let mut last_t = Instant::now().checked_sub(ship_interval).unwrap_or_else(Instant::now);
+    let mut is_caught_up = false;
+    loop {
+      let cur_t = Instant::now();
+      let planned_t = last_t + ship_interval;
+      if cur_t < planned_t {
+        tokio::time::sleep_until(planned_t).await;
+      }
+      last_t = cur_t;
+      let iteration_t = Instant::now();
+      self.background.start_loop_iteration(STANDBY_SHIPPER_LOOP, iteration_t.saturating_duration_since(planned_t)).await;
+      match self.ship_to_standby(standby_dir).await {
+        Ok(stats) if !is_caught_up => {
+          log::info!(
+            "caught up standby dir {:?} by copying {} files ({} bytes) and removing {} entries",
+            standby_dir,
+            stats.n_files_copied,
+            stats.n_bytes_copied,
+            stats.n_entries_removed,
+          );
+          is_caught_up = true;
+        },
+        Ok(stats) => log::debug!("shipped to standby dir: {:?}", stats),
+        Err(e) => log::error!("shipping to standby dir failed: {}", e),
+      }
+      self.background.finish_loop_iteration(STANDBY_SHIPPER_LOOP, iteration_t.elapsed()).await;
+
+      let is_active = self.activity.is_active().await;
+      if !is_active {
+        return;
+      }
+    }
+  }
+}
+
+// Makes dst_dir match src_dir, except for entries with names matching skip.
+// Anything removed from src_dir while we're shipping it gets removed from
+// dst_dir on the next pass.
+async fn ship_dir(
+  src_dir: &Path,
+  dst_dir: &Path,
+  depth: Depth,
+  skip: &(dyn Fn(&str) -> bool + Sync),
+  standby_dir: &Path,
+  stats: &mut ShipStats,
+) -> ServerResult<()> {
+  let mut pending = vec![(src_dir.to_path_buf(), dst_dir.to_path_buf())];
+  while let Some((src, dst)) = pending.pop() {
+    let mut read_dir = match vfs::read_dir(&src).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => continue,
+      Err(e) => return Err(e.into()),
+    };
+    vfs::create_dir_all(&dst).await?;
+
+    let mut names = HashSet::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+      let name = entry.file_name().to_string_lossy().to_string();
+      if skip(&name) {
+        continue;
+      }
+      let dst_path = dst.join(&name);
+      if entry.file_type().await?.is_dir() {
+        match depth {
+          Depth::Deep => pending.push((entry.path(), dst_path)),
+          Depth::Shallow => vfs::create_dir_all(&dst_path).await?,
+        }
+      } else {
+        ship_file(&entry.path(), &dst_path, &name, standby_dir, stats).await?;
+      }
+      names.insert(name);
+    }
+
+    let mut dst_read_dir = vfs::read_dir(&dst).await?;
+    while let Some(entry) = dst_read_dir.next_entry().await? {
+      let name = entry.file_name().to_string_lossy().to_string();
+      if skip(&name) || names.contains(&name) {
+        continue;
+      }
+      if entry.file_type().await?.is_dir() {
+        vfs::remove_dir_all(entry.path()).await?;
+      } else {
+        vfs::remove_file(entry.path()).await?;
+      }
+      stats.n_entries_removed += 1;
+    }
+  }
+  Ok(())
+}
+
+async fn file_len(path: &Path) -> ServerResult<Option<u64>> {
+  match vfs::metadata(path).await {
+    Ok(metadata) => Ok(Some(metadata.len())),
+    Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Ok(None),
+    Err(e) => Err(e.into()),
+  }
+}
+
+async fn ship_file(
+  src: &Path,
+  dst: &Path,
+  file_name: &str,
+  standby_dir: &Path,
+  stats: &mut ShipStats,
+) -> ServerResult<()> {
+  let src_len = match file_len(src).await? {
+    Some(src_len) => src_len,
+    None => return Ok(()),
+  };
+  let needs_copy = match (FileKind::of(file_name), file_len(dst).await?) {
+    (_, None) => true,
+    (FileKind::Immutable, Some(dst_len)) => dst_len != src_len,
+    (FileKind::AppendOnly, Some(dst_len)) => {
+      // recovery can trim an append-only file and append to it again, so
+      // make sure the copy is still a prefix of it
+      let check_len = dst_len.min(TAIL_CHECK_BYTE_SIZE);
+      let check_offset = dst_len - check_len;
+      let is_prefix = dst_len <= src_len &&
+        common::read_with_offset(src, check_offset, check_len as usize).await? ==
+          common::read_with_offset(dst, check_offset, check_len as usize).await?;
+      if is_prefix && dst_len < src_len {
+        let bytes = common::read_with_offset(src, dst_len, (src_len - dst_len) as usize).await?;
+        common::append_to_file(dst, &bytes).await?;
+        stats.n_files_copied += 1;
+        stats.n_bytes_copied += bytes.len() as u64;
+      }
+      !is_prefix
+    },
+    (FileKind::Mutable, Some(_)) => common::read_or_empty(src).await? != common::read_or_empty(dst).await?,
+  };
+
+  if needs_copy {
+    let bytes = match vfs::read(src).await {
+      Ok(bytes) => bytes,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(()),
+      Err(e) => return Err(e.into()),
+    };
+    common::overwrite_file_atomic(dst, &bytes, standby_dir).await?;
+    stats.n_files_copied += 1;
+    stats.n_bytes_copied += bytes.len() as u64;
+  }
+  Ok(())
+}
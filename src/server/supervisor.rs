@@ -0,0 +1,48 @@
+use std::panic::AssertUnwindSafe;
+
+use futures::{Future, FutureExt};
+use tokio::time::{Duration, Instant};
+
+use crate::utils::common;
+
+use super::Server;
+
+const MIN_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+impl Server {
+  // Runs a background loop, restarting it whenever it panics so that one bad
+  // segment can't stop flushes or compactions for good. Restarts back off
+  // exponentially, starting over once a loop has run for a while without
+  // panicking. make_loop is told whether it's restarting after a panic, so
+  // the loop can clean up what the panic left behind. Returns when the loop
+  // itself returns.
+  pub async fn supervise<F, Fut>(&self, loop_name: &'static str, make_loop: F)
+  where F: Fn(bool) -> Fut, Fut: Future<Output=()> {
+    let mut backoff = MIN_RESTART_BACKOFF;
+    let mut is_restart = false;
+    loop {
+      let started_at = Instant::now();
+      let payload = match AssertUnwindSafe(make_loop(is_restart)).catch_unwind().await {
+        Ok(()) => return,
+        Err(payload) => payload,
+      };
+
+      if started_at.elapsed() >= MAX_RESTART_BACKOFF {
+        backoff = MIN_RESTART_BACKOFF;
+      }
+      let message = common::panic_message(&*payload);
+      log::error!(
+        "{} loop panicked: {}; restarting it in {:?}",
+        loop_name,
+        message,
+        backoff,
+      );
+      self.background.record_loop_panic(loop_name, message).await;
+      tokio::time::sleep(backoff).await;
+      self.background.record_loop_restart(loop_name).await;
+      backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+      is_restart = true;
+    }
+  }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use futures::{pin_mut, StreamExt};
+use pancake_db_idl::dml::{FieldValue, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use prost_types::Timestamp;
+use uuid::Uuid;
+
+use crate::constants::*;
+use crate::errors::ServerResult;
+use crate::metadata::segment::SegmentMetadata;
+use crate::metadata::table::TableMetadata;
+use crate::types::{InternalTableInfo, SegmentKey};
+
+use super::authz::{Access, Verb};
+use super::Server;
+
+// Virtual tables describing the server's other tables, materialized from
+// the metadata caches whenever they're read. Each has a single
+// unpartitioned segment with the nil segment id and no files, so clients
+// read them the same way they read any table.
+#[derive(Clone, Copy, Debug)]
+pub enum SystemTable {
+  Tables,
+  Columns,
+  Segments,
+}
+
+impl SystemTable {
+  pub fn from_name(table_name: &str) -> Option<Self> {
+    match table_name {
+      SYSTEM_TABLES_TABLE_NAME => Some(SystemTable::Tables),
+      SYSTEM_COLUMNS_TABLE_NAME => Some(SystemTable::Columns),
+      SYSTEM_SEGMENTS_TABLE_NAME => Some(SystemTable::Segments),
+      _ => None,
+    }
+  }
+
+  pub fn segment_id() -> Uuid {
+    Uuid::nil()
+  }
+
+  fn schema(&self) -> Schema {
+    let (string_cols, int64_cols, bool_cols, timestamp_cols) = match self {
+      SystemTable::Tables => (
+        vec![SYSTEM_TABLE_NAME_COLUMN_NAME, SYSTEM_SORT_COLUMNS_COLUMN_NAME],
+        vec![SYSTEM_SCHEMA_VERSION_COLUMN_NAME, SYSTEM_N_COLUMNS_COLUMN_NAME, SYSTEM_N_PARTITION_FIELDS_COLUMN_NAME],
+        vec![SYSTEM_FROZEN_COLUMN_NAME],
+        vec![SYSTEM_CREATED_AT_COLUMN_NAME, SYSTEM_LAST_ALTERED_AT_COLUMN_NAME],
+      ),
+      SystemTable::Columns => (
+        vec![SYSTEM_TABLE_NAME_COLUMN_NAME, SYSTEM_COLUMN_NAME_COLUMN_NAME, SYSTEM_DTYPE_COLUMN_NAME],
+        vec![SYSTEM_NESTED_LIST_DEPTH_COLUMN_NAME],
+        vec![SYSTEM_IS_PARTITION_COLUMN_NAME, SYSTEM_IS_COMPUTED_COLUMN_NAME, SYSTEM_IS_MASKED_COLUMN_NAME],
+        vec![SYSTEM_ADDED_AT_COLUMN_NAME],
+      ),
+      SystemTable::Segments => (
+        vec![SYSTEM_TABLE_NAME_COLUMN_NAME, SYSTEM_PARTITION_COLUMN_NAME, SYSTEM_SEGMENT_ID_COLUMN_NAME],
+        vec![SYSTEM_ROW_COUNT_COLUMN_NAME, SYSTEM_STAGED_N_COLUMN_NAME, SYSTEM_READ_VERSION_COLUMN_NAME, SYSTEM_UNCOMPRESSED_BYTES_COLUMN_NAME],
+        vec![SYSTEM_IS_COLD_COLUMN_NAME],
+        vec![SYSTEM_LAST_FLUSH_AT_COLUMN_NAME],
+      ),
+    };
+    let column = |dtype: DataType| ColumnMeta {
+      dtype: dtype as i32,
+      nested_list_depth: 0,
+    };
+    let mut columns = HashMap::new();
+    for (col_names, dtype) in [
+      (string_cols, DataType::String),
+      (int64_cols, DataType::Int64),
+      (bool_cols, DataType::Bool),
+      (timestamp_cols, DataType::TimestampMicros),
+    ] {
+      for col_name in col_names {
+        columns.insert(col_name.to_string(), column(dtype));
+      }
+    }
+    Schema {
+      columns,
+      ..Default::default()
+    }
+  }
+}
+
+// Metadata for a system table, for the read ops that serve them. Since
+// underscored names can't be created, these never shadow a real table.
+pub fn table_meta(table_name: &str) -> Option<TableMetadata> {
+  SystemTable::from_name(table_name)
+    .map(|system_table| TableMetadata::new(&system_table.schema()))
+}
+
+// metadata for a system table's only segment
+pub fn segment_meta(segment_key: &SegmentKey) -> Option<SegmentMetadata> {
+  SystemTable::from_name(&segment_key.table_name)
+    .filter(|_| segment_key.segment_id == SystemTable::segment_id())
+    .map(|system_table| SegmentMetadata::new_from_schema(&system_table.schema()))
+}
+
+fn string_value(s: String) -> FieldValue {
+  FieldValue {
+    value: Some(Value::StringVal(s)),
+  }
+}
+
+fn int64_value(x: u64) -> FieldValue {
+  FieldValue {
+    value: Some(Value::Int64Val(x as i64)),
+  }
+}
+
+fn bool_value(x: bool) -> FieldValue {
+  FieldValue {
+    value: Some(Value::BoolVal(x)),
+  }
+}
+
+fn timestamp_value(maybe_t: Option<DateTime<Utc>>) -> FieldValue {
+  FieldValue {
+    value: maybe_t.map(|t| Value::TimestampVal(Timestamp::from(SystemTime::from(t)))),
+  }
+}
+
+fn row(fields: Vec<(&str, FieldValue)>) -> Row {
+  Row {
+    fields: fields.into_iter()
+      .map(|(col_name, value)| (col_name.to_string(), value))
+      .collect(),
+  }
+}
+
+fn table_row(table: &InternalTableInfo) -> Row {
+  let schema = table.meta.visible_schema();
+  row(vec![
+    (SYSTEM_TABLE_NAME_COLUMN_NAME, string_value(table.name.clone())),
+    (SYSTEM_SCHEMA_VERSION_COLUMN_NAME, int64_value(table.meta.schema_version)),
+    (SYSTEM_N_COLUMNS_COLUMN_NAME, int64_value(schema.columns.len() as u64)),
+    (SYSTEM_N_PARTITION_FIELDS_COLUMN_NAME, int64_value(schema.partitioning.len() as u64)),
+    (SYSTEM_SORT_COLUMNS_COLUMN_NAME, string_value(table.meta.sort_columns.iter()
+      .map(|col_name| table.meta.visible_column_name(col_name))
+      .collect::<Vec<_>>()
+      .join(","))),
+    (SYSTEM_FROZEN_COLUMN_NAME, bool_value(table.meta.frozen)),
+    (SYSTEM_CREATED_AT_COLUMN_NAME, timestamp_value(table.meta.created_at)),
+    (SYSTEM_LAST_ALTERED_AT_COLUMN_NAME, timestamp_value(table.meta.last_altered_at)),
+  ])
+}
+
+// partition fields first, then columns, each in name order
+fn column_rows(table: &InternalTableInfo) -> Vec<Row> {
+  let schema = table.meta.schema();
+  let mut rows = Vec::new();
+  let mut partition_names: Vec<_> = schema.partitioning.keys().collect();
+  partition_names.sort();
+  for partition_name in partition_names {
+    let dtype = PartitionDataType::from_i32(schema.partitioning[partition_name].dtype)
+      .unwrap_or(PartitionDataType::String);
+    rows.push(row(vec![
+      (SYSTEM_TABLE_NAME_COLUMN_NAME, string_value(table.name.clone())),
+      (SYSTEM_COLUMN_NAME_COLUMN_NAME, string_value(partition_name.clone())),
+      (SYSTEM_DTYPE_COLUMN_NAME, string_value(format!("{:?}", dtype))),
+      (SYSTEM_NESTED_LIST_DEPTH_COLUMN_NAME, int64_value(0)),
+      (SYSTEM_IS_PARTITION_COLUMN_NAME, bool_value(true)),
+      (SYSTEM_IS_COMPUTED_COLUMN_NAME, bool_value(false)),
+      (SYSTEM_IS_MASKED_COLUMN_NAME, bool_value(false)),
+      (SYSTEM_ADDED_AT_COLUMN_NAME, timestamp_value(table.meta.created_at)),
+    ]));
+  }
+  let mut stored_names: Vec<_> = schema.columns.keys().collect();
+  stored_names.sort_by_key(|stored_name| table.meta.visible_column_name(stored_name));
+  for stored_name in stored_names {
+    let col_meta = &schema.columns[stored_name];
+    let dtype = DataType::from_i32(col_meta.dtype).unwrap_or(DataType::String);
+    rows.push(row(vec![
+      (SYSTEM_TABLE_NAME_COLUMN_NAME, string_value(table.name.clone())),
+      (SYSTEM_COLUMN_NAME_COLUMN_NAME, string_value(table.meta.visible_column_name(stored_name))),
+      (SYSTEM_DTYPE_COLUMN_NAME, string_value(format!("{:?}", dtype))),
+      (SYSTEM_NESTED_LIST_DEPTH_COLUMN_NAME, int64_value(col_meta.nested_list_depth as u64)),
+      (SYSTEM_IS_PARTITION_COLUMN_NAME, bool_value(false)),
+      (SYSTEM_IS_COMPUTED_COLUMN_NAME, bool_value(table.meta.computed_columns.contains_key(stored_name))),
+      (SYSTEM_IS_MASKED_COLUMN_NAME, bool_value(table.meta.column_masks.contains_key(stored_name))),
+      (SYSTEM_ADDED_AT_COLUMN_NAME, timestamp_value(table.meta.column_added_at.get(stored_name).cloned())),
+    ]));
+  }
+  rows
+}
+
+fn segment_row(segment_key: &SegmentKey, segment_meta: &SegmentMetadata) -> ServerResult<Row> {
+  Ok(row(vec![
+    (SYSTEM_TABLE_NAME_COLUMN_NAME, string_value(segment_key.table_name.clone())),
+    (SYSTEM_PARTITION_COLUMN_NAME, string_value(segment_key.partition.field_dir_names().join("/"))),
+    (SYSTEM_SEGMENT_ID_COLUMN_NAME, string_value(segment_key.segment_id.to_string())),
+    (SYSTEM_ROW_COUNT_COLUMN_NAME, int64_value(segment_meta.live_n()?)),
+    (SYSTEM_STAGED_N_COLUMN_NAME, int64_value(segment_meta.staged_n)),
+    (SYSTEM_READ_VERSION_COLUMN_NAME, int64_value(segment_meta.read_version)),
+    (SYSTEM_UNCOMPRESSED_BYTES_COLUMN_NAME, int64_value(segment_meta.all_time_uncompressed_size)),
+    (SYSTEM_IS_COLD_COLUMN_NAME, bool_value(segment_meta.is_cold)),
+    (SYSTEM_LAST_FLUSH_AT_COLUMN_NAME, timestamp_value(Some(segment_meta.last_flush_at))),
+  ]))
+}
+
+impl Server {
+  // The current rows of a system table, describing only the tables the
+  // requesting principal may read, in table name order.
+  pub async fn system_table_rows(&self, system_table: SystemTable) -> ServerResult<Vec<Row>> {
+    let mut tables = Vec::new();
+    for table in self.internal_list_tables().await? {
+      if self.is_authorized(&Access::table(Verb::Read, &table.name)).await {
+        tables.push(table);
+      }
+    }
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut rows = Vec::new();
+    for table in &tables {
+      match system_table {
+        SystemTable::Tables => rows.push(table_row(table)),
+        SystemTable::Columns => rows.extend(column_rows(table)),
+        SystemTable::Segments => {
+          let segment_key_stream = self.stream_table_segment_keys(table);
+          pin_mut!(segment_key_stream);
+          while let Some(segment_key_result) = segment_key_stream.next().await {
+            let segment_key = segment_key_result?;
+            let segment_lock = self.segment_metadata_cache.get_lock(&segment_key).await?;
+            let maybe_segment_meta = segment_lock.read().await.clone();
+            if let Some(segment_meta) = maybe_segment_meta {
+              rows.push(segment_row(&segment_key, &segment_meta)?);
+            }
+          }
+        },
+      }
+    }
+    Ok(rows)
+  }
+}
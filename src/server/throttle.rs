@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+use crate::errors::ServerResult;
+
+use super::cancel;
+use super::Server;
+
+// Limits how fast compactions process column data, so that they leave disk
+// bandwidth for reads and writes. Compactions draw from one shared bucket
+// of bytes that refills at the configured rate, waiting out any shortfall
+// instead of failing.
+#[derive(Clone, Default)]
+pub struct CompactionThrottle {
+  bucket: Arc<Mutex<Option<ByteBucket>>>,
+}
+
+struct ByteBucket {
+  // negative when compactions have drawn more than has refilled
+  bytes: f64,
+  updated_at: Instant,
+}
+
+impl Server {
+  // Counts bytes of column data a compaction has processed, waiting until
+  // they fit under compaction_bytes_per_second. Gives up if the compaction
+  // is cancelled while waiting.
+  pub async fn throttle_compaction(&self, n_bytes: usize) -> ServerResult<()> {
+    let rate = match self.runtime_config().await.compaction_bytes_per_second {
+      Some(bytes_per_second) => bytes_per_second.max(1) as f64,
+      None => return Ok(()),
+    };
+    let wait = {
+      let now = Instant::now();
+      let mut bucket_guard = self.compaction_throttle.bucket.lock().await;
+      let bucket = bucket_guard.get_or_insert(ByteBucket {
+        bytes: rate,
+        updated_at: now,
+      });
+      let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+      bucket.bytes = (bucket.bytes + elapsed * rate).min(rate);
+      bucket.updated_at = now;
+      bucket.bytes -= n_bytes as f64;
+      if bucket.bytes < 0.0 {
+        Duration::from_secs_f64(-bucket.bytes / rate)
+      } else {
+        Duration::ZERO
+      }
+    };
+    if !wait.is_zero() {
+      cancel::unless_cancelled(tokio::time::sleep(wait)).await?;
+    }
+    Ok(())
+  }
+}
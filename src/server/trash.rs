@@ -0,0 +1,91 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+use crate::errors::ServerResult;
+use crate::utils::dirs;
+use crate::utils::vfs;
+
+use super::Server;
+
+// A dropped table whose files are kept so that it can be undropped until
+// its retention window passes.
+pub struct TrashedTable {
+  pub table_name: String,
+  pub dropped_at: DateTime<Utc>,
+  pub path: PathBuf,
+}
+
+impl Server {
+  // Moves a table's dir to the trash in a single rename, or removes it if
+  // trash is disabled.
+  pub async fn trash_table(&self, table_name: &str) -> ServerResult<()> {
+    let dir = &self.opts.dir;
+    let table_dir = dirs::table_dir(dir, table_name);
+    if self.runtime_config().await.trash_retention_seconds <= 0 {
+      // remove the data first, or else we might remove the `dropped` flag
+      // in the metadata, crash, and leave all the data on disk
+      vfs::remove_dir_all(dirs::table_data_dir(dir, table_name)).await?;
+      vfs::remove_dir_all(table_dir).await?;
+      return Ok(());
+    }
+
+    vfs::create_dir_all(dirs::trash_dir(dir)).await?;
+    vfs::rename(
+      table_dir,
+      dirs::trashed_table_dir(dir, table_name, Utc::now().timestamp_millis()),
+    ).await?;
+    Ok(())
+  }
+
+  // sorted by table name, then by when they were dropped
+  pub async fn list_trash(&self) -> ServerResult<Vec<TrashedTable>> {
+    let trash_dir = dirs::trash_dir(&self.opts.dir);
+    let mut read_dir = match vfs::read_dir(&trash_dir).await {
+      Ok(read_dir) => read_dir,
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => return Ok(Vec::new()),
+      Err(e) => return Err(e.into()),
+    };
+
+    let mut res = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let parsed = name.rsplit_once('.')
+        .and_then(|(table_name, millis_str)| {
+          let dropped_at_millis = millis_str.parse::<i64>().ok()?;
+          Some((table_name, Utc.timestamp_millis_opt(dropped_at_millis).single()?))
+        });
+      match parsed {
+        Some((table_name, dropped_at)) => res.push(TrashedTable {
+          table_name: table_name.to_string(),
+          dropped_at,
+          path: entry.path(),
+        }),
+        None => log::warn!("ignoring unexpected entry {:?} in trash", entry.path()),
+      }
+    }
+    res.sort_by(|a, b| (&a.table_name, a.dropped_at).cmp(&(&b.table_name, b.dropped_at)));
+    Ok(res)
+  }
+
+  // returns the number of dropped tables removed for good
+  pub async fn purge_expired_trash(&self) -> ServerResult<usize> {
+    let retention = Duration::seconds(self.runtime_config().await.trash_retention_seconds);
+    let now = Utc::now();
+    let mut n_purged = 0;
+    for trashed in self.list_trash().await? {
+      if trashed.dropped_at + retention > now {
+        continue;
+      }
+      log::debug!(
+        "purging table {} dropped at {} from the trash",
+        trashed.table_name,
+        trashed.dropped_at,
+      );
+      vfs::remove_dir_all(&trashed.path).await?;
+      n_purged += 1;
+    }
+    Ok(n_purged)
+  }
+}
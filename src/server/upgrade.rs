@@ -0,0 +1,379 @@
+use async_trait::async_trait;
+
+use crate::constants::{FORMAT_MAJOR_VERSION, FORMAT_MINOR_VERSION};
+use crate::errors::{Contextable, ServerError, ServerResult};
+use crate::metadata::global::{FormatFeature, FormatUpgradeProgress, GlobalMetadata};
+use crate::metadata::PersistentMetadata;
+use crate::types::EmptyKey;
+use crate::utils::{common, dirs};
+
+use super::partition_layout;
+use super::Server;
+
+// A step that rewrites a dir from one format major version to the next.
+// Migrations run in registration order, and each is recorded in the global
+// metadata once it finishes, so an interrupted upgrade resumes at the first
+// unfinished migration. That one reruns from the start, so it must be safe
+// to rerun.
+#[async_trait]
+trait Migration: Send + Sync {
+  fn name(&self) -> &'static str;
+
+  // the major version this migrates from, to the one after it
+  fn source_major_version(&self) -> u32;
+
+  // Problems that would keep the migration from succeeding. Every pending
+  // migration's pre-check passes before any of them run.
+  async fn pre_check(&self, server: &Server) -> ServerResult<Vec<String>>;
+
+  async fn run(&self, server: &Server) -> ServerResult<()>;
+}
+
+// Dirs from before formats were versioned are already laid out in format
+// 1, so upgrading them only records the version.
+struct RecordFormatVersion;
+
+#[async_trait]
+impl Migration for RecordFormatVersion {
+  fn name(&self) -> &'static str {
+    "record_format_version"
+  }
+
+  fn source_major_version(&self) -> u32 {
+    0
+  }
+
+  async fn pre_check(&self, _server: &Server) -> ServerResult<Vec<String>> {
+    Ok(Vec::new())
+  }
+
+  async fn run(&self, _server: &Server) -> ServerResult<()> {
+    Ok(())
+  }
+}
+
+// Format 2 names minute partition dirs by ISO 8601 times rather than
+// minutes since the epoch.
+struct IsoMinutePartitionDirs;
+
+// the fanout the dir was last served with, which recovery hasn't loaded yet
+async fn recorded_fanout(server: &Server) -> ServerResult<u32> {
+  let maybe_global_meta = GlobalMetadata::load(&server.opts.dir, &EmptyKey).await?;
+  Ok(maybe_global_meta.map(|meta| meta.partition_dir_fanout).unwrap_or(0))
+}
+
+#[async_trait]
+impl Migration for IsoMinutePartitionDirs {
+  fn name(&self) -> &'static str {
+    "iso_minute_partition_dirs"
+  }
+
+  fn source_major_version(&self) -> u32 {
+    1
+  }
+
+  async fn pre_check(&self, server: &Server) -> ServerResult<Vec<String>> {
+    let fanout = recorded_fanout(server).await?;
+    let mut problems = Vec::new();
+    for table_dir in partition_layout::table_dirs(&server.opts.dir).await? {
+      problems.extend(partition_layout::unrenamable_minute_dirs(&table_dir, fanout).await?);
+    }
+    Ok(problems)
+  }
+
+  async fn run(&self, server: &Server) -> ServerResult<()> {
+    let fanout = recorded_fanout(server).await?;
+    let mut n_moved = 0;
+    for table_dir in partition_layout::table_dirs(&server.opts.dir).await? {
+      n_moved += partition_layout::rename_minute_dirs(&table_dir, fanout)
+        .await
+        .with_context(|| format!("while renaming minute partition dirs of {:?}", table_dir))?;
+    }
+    log::info!("renamed {} minute partition dirs", n_moved);
+    Ok(())
+  }
+}
+
+fn registered_migrations() -> Vec<Box<dyn Migration>> {
+  vec![
+    Box::new(RecordFormatVersion),
+    Box::new(IsoMinutePartitionDirs),
+  ]
+}
+
+// The features of the format this server may write, and whether servers
+// without each can still read dirs using it. New features go at the end;
+// ones that older servers would misread must not be read compatible.
+const FORMAT_FEATURES: &[(&str, bool)] = &[
+  ("zone_maps", true),
+  ("bloom_filters", true),
+  ("checksums", true),
+  ("encryption", false),
+  ("compaction_increments", false),
+  ("partition_dir_buckets", false),
+  ("column_renames", false),
+  ("escaped_partition_strings", false),
+];
+
+fn version_string(global_meta: &GlobalMetadata) -> String {
+  format!("{}.{}", global_meta.format_major_version, global_meta.format_minor_version)
+}
+
+// Refuses dirs using format features this server doesn't know, since it
+// would misread or corrupt them, unless they are all read compatible and
+// the server is read-only.
+fn check_format_features(global_meta: &GlobalMetadata, is_read_only: bool) -> ServerResult<()> {
+  let unknown_features: Vec<&FormatFeature> = global_meta.format_features.iter()
+    .filter(|feature| !FORMAT_FEATURES.iter().any(|(name, _)| *name == feature.name))
+    .collect();
+  if unknown_features.is_empty() {
+    return Ok(());
+  }
+
+  let names = unknown_features.iter()
+    .map(|feature| feature.name.as_str())
+    .collect::<Vec<_>>()
+    .join(", ");
+  if unknown_features.iter().all(|feature| feature.read_compatible) {
+    if is_read_only {
+      log::warn!("serving dir using unknown read compatible format features {}", names);
+      return Ok(());
+    }
+    return Err(ServerError::invalid(format!(
+      "dir uses format features {} written by a newer server version; \
+      run a server version that supports them, or serve the dir with --read-only true",
+      names,
+    )));
+  }
+  Err(ServerError::invalid(format!(
+    "dir uses format features {} written by a newer server version; \
+    run a server version that supports them",
+    names,
+  )))
+}
+
+// Records this server's format features in the dir, returning whether any
+// were new to it.
+fn add_format_features(global_meta: &mut GlobalMetadata) -> bool {
+  let mut is_changed = false;
+  for &(name, read_compatible) in FORMAT_FEATURES {
+    if !global_meta.format_features.iter().any(|feature| feature.name == name) {
+      global_meta.format_features.push(FormatFeature {
+        name: name.to_string(),
+        read_compatible,
+      });
+      is_changed = true;
+    }
+  }
+  is_changed
+}
+
+// Whether this server can serve the dir as it is, without migrating it or
+// writing to it.
+pub fn check_format_readable(global_meta: &GlobalMetadata) -> ServerResult<()> {
+  if global_meta.format_major_version != FORMAT_MAJOR_VERSION || global_meta.format_upgrade.is_some() {
+    return Err(ServerError::invalid(format!(
+      "dir is in format version {} but this server reads format major version {}; \
+      start a writing server of this version on the dir to upgrade it first",
+      version_string(global_meta),
+      FORMAT_MAJOR_VERSION,
+    )));
+  }
+  check_format_features(global_meta, true)
+}
+
+// The migrations left to bring the dir to the current major version, in
+// the order they run.
+fn pending_migrations(global_meta: &GlobalMetadata) -> ServerResult<Vec<Box<dyn Migration>>> {
+  let major_version = global_meta.format_major_version;
+  if major_version > FORMAT_MAJOR_VERSION {
+    return Err(ServerError::invalid(format!(
+      "dir is in format version {}, newer than this server's {}.{}; \
+      run a server version that supports format major version {}",
+      version_string(global_meta),
+      FORMAT_MAJOR_VERSION,
+      FORMAT_MINOR_VERSION,
+      major_version,
+    )));
+  }
+
+  let completed_migrations = match &global_meta.format_upgrade {
+    Some(progress) if progress.target_major_version != FORMAT_MAJOR_VERSION => {
+      return Err(ServerError::invalid(format!(
+        "an upgrade of the dir to format major version {} was interrupted; \
+        finish it with a server of that version",
+        progress.target_major_version,
+      )));
+    },
+    Some(progress) => progress.completed_migrations.clone(),
+    None => Vec::new(),
+  };
+
+  let mut res = Vec::new();
+  let mut migrations = registered_migrations();
+  for from_major_version in major_version..FORMAT_MAJOR_VERSION {
+    let n_before = res.len();
+    let mut remaining = Vec::new();
+    for migration in migrations {
+      if migration.source_major_version() == from_major_version {
+        res.push(migration);
+      } else {
+        remaining.push(migration);
+      }
+    }
+    if res.len() == n_before {
+      return Err(ServerError::internal(format!(
+        "no migration is registered from format major version {}",
+        from_major_version,
+      )));
+    }
+    migrations = remaining;
+  }
+  res.retain(|migration| !completed_migrations.iter().any(|name| name == migration.name()));
+  Ok(res)
+}
+
+impl Server {
+  // The dir's global metadata, and whether it's been written yet. A dir
+  // without any is either new, and so in the current format, or from before
+  // formats were versioned.
+  async fn load_format_global_meta(&self) -> ServerResult<(GlobalMetadata, bool)> {
+    let dir = &self.opts.dir;
+    if let Some(global_meta) = GlobalMetadata::load(dir, &EmptyKey).await? {
+      return Ok((global_meta, true));
+    }
+
+    let mut global_meta = GlobalMetadata::default();
+    if !partition_layout::table_dirs(dir).await?.is_empty() {
+      global_meta.format_major_version = 0;
+      global_meta.format_minor_version = 0;
+    }
+    Ok((global_meta, false))
+  }
+
+  async fn pre_check_migrations(&self, migrations: &[Box<dyn Migration>]) -> ServerResult<()> {
+    let mut problems = Vec::new();
+    for migration in migrations {
+      let migration_problems = migration.pre_check(self)
+        .await
+        .with_context(|| format!("while pre-checking migration {}", migration.name()))?;
+      problems.extend(migration_problems.into_iter()
+        .map(|problem| format!("{}: {}", migration.name(), problem)));
+    }
+    if !problems.is_empty() {
+      return Err(ServerError::invalid(format!(
+        "format upgrade pre-checks failed; resolve these and restart: {}",
+        problems.join("; "),
+      )));
+    }
+    Ok(())
+  }
+
+  // Logs the migrations an upgrade would run and checks that they can,
+  // without changing anything.
+  pub async fn plan_format_upgrade(&self) -> ServerResult<()> {
+    let (global_meta, _) = self.load_format_global_meta().await?;
+    let migrations = pending_migrations(&global_meta)?;
+    check_format_features(&global_meta, self.opts.read_only)?;
+    if global_meta.format_major_version == FORMAT_MAJOR_VERSION {
+      log::info!(
+        "dir is in format version {}; no migrations needed",
+        version_string(&global_meta),
+      );
+      return Ok(());
+    }
+
+    log::info!(
+      "dir is in format version {}; upgrading to {}.{} would run migrations {}",
+      version_string(&global_meta),
+      FORMAT_MAJOR_VERSION,
+      FORMAT_MINOR_VERSION,
+      migrations.iter().map(|migration| migration.name()).collect::<Vec<_>>().join(", "),
+    );
+    self.pre_check_migrations(&migrations).await?;
+    log::info!("format upgrade pre-checks passed");
+    Ok(())
+  }
+
+  // Refuses dirs this server can't serve, and brings dirs of older versions
+  // to the current format. Runs before recovery, so migrations see the dir
+  // exactly as the last server left it.
+  pub async fn upgrade_format(&self) -> ServerResult<()> {
+    let dir = &self.opts.dir;
+    let (mut global_meta, is_recorded) = self.load_format_global_meta().await?;
+    let migrations = pending_migrations(&global_meta)?;
+    check_format_features(&global_meta, false)?;
+    // the overwrites are staged in tmp, which a new dir doesn't have yet
+    common::create_if_new(dirs::tmp_dir(dir)).await?;
+
+    // an interrupted upgrade may have no migrations left, but still needs
+    // its version recorded
+    if global_meta.format_major_version < FORMAT_MAJOR_VERSION {
+      if !self.opts.upgrade_format {
+        return Err(ServerError::invalid(format!(
+          "dir is in format version {} but this server requires format major version {}; \
+          restart with --upgrade-format true to migrate it, optionally checking first with \
+          --upgrade-format-dry-run true",
+          version_string(&global_meta),
+          FORMAT_MAJOR_VERSION,
+        )));
+      }
+      self.pre_check_migrations(&migrations).await?;
+
+      log::info!(
+        "upgrading dir from format version {} to {}.{}",
+        version_string(&global_meta),
+        FORMAT_MAJOR_VERSION,
+        FORMAT_MINOR_VERSION,
+      );
+      let mut progress = global_meta.format_upgrade.clone().unwrap_or(FormatUpgradeProgress {
+        target_major_version: FORMAT_MAJOR_VERSION,
+        completed_migrations: Vec::new(),
+      });
+      global_meta.format_upgrade = Some(progress.clone());
+      global_meta.overwrite(dir, &EmptyKey).await?;
+      for migration in &migrations {
+        log::info!("running format migration {}", migration.name());
+        migration.run(self)
+          .await
+          .with_context(|| format!("while running migration {}", migration.name()))?;
+        progress.completed_migrations.push(migration.name().to_string());
+        global_meta.format_upgrade = Some(progress.clone());
+        global_meta.overwrite(dir, &EmptyKey).await?;
+      }
+      global_meta.format_major_version = FORMAT_MAJOR_VERSION;
+      global_meta.format_minor_version = FORMAT_MINOR_VERSION;
+      global_meta.format_upgrade = None;
+      add_format_features(&mut global_meta);
+      global_meta.overwrite(dir, &EmptyKey).await?;
+      log::info!("upgraded dir to format version {}", version_string(&global_meta));
+      return Ok(());
+    }
+
+    let is_minor_newer = global_meta.format_minor_version > FORMAT_MINOR_VERSION;
+    if is_minor_newer {
+      log::warn!(
+        "dir is in format version {}, newer than this server's {}.{}; \
+        features of the newer minor version are unavailable",
+        version_string(&global_meta),
+        FORMAT_MAJOR_VERSION,
+        FORMAT_MINOR_VERSION,
+      );
+    }
+    // Minor versions only add to the format, so upgrading to one is just a
+    // record of it, and a newer one stays recorded. New dirs need theirs
+    // recorded so later servers don't take them for unversioned ones.
+    let minor_version = if is_minor_newer {
+      global_meta.format_minor_version
+    } else {
+      FORMAT_MINOR_VERSION
+    };
+    let is_minor_outdated = global_meta.format_minor_version != minor_version;
+    let is_features_outdated = add_format_features(&mut global_meta);
+    if is_minor_outdated || is_features_outdated || !is_recorded {
+      global_meta.format_minor_version = minor_version;
+      global_meta.overwrite(dir, &EmptyKey).await?;
+    }
+    Ok(())
+  }
+}
@@ -4,8 +4,8 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::{Debug, Formatter, Display};
 use std::hash::{Hash, Hasher};
-use std::path::PathBuf;
 
+use chrono::{DateTime, NaiveDateTime};
 use pancake_db_idl::dml::partition_field_value::Value;
 use pancake_db_idl::dml::PartitionFieldValue;
 use pancake_db_idl::schema::Schema;
@@ -15,7 +15,7 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::constants::SHARD_ID_BYTE_LENGTH;
-use crate::errors::{ServerError, ServerResult};
+use crate::errors::{ErrorCode, ServerError, ServerResult};
 use crate::metadata::table::TableMetadata;
 use crate::utils::{common, sharding};
 
@@ -36,11 +36,51 @@ pub struct PartitionMinute {
   pub minutes: i64,
 }
 
+// the format of minutes in partition dir names
+const PARTITION_MINUTE_FORMAT: &str = "%Y-%m-%dT%H:%MZ";
+
+impl PartitionMinute {
+  // Parses a minute as in partition dir names, or as any RFC 3339 time
+  // that falls on a whole minute.
+  pub fn parse(s: &str) -> ServerResult<PartitionMinute> {
+    match NaiveDateTime::parse_from_str(s, PARTITION_MINUTE_FORMAT) {
+      Ok(t) => PartitionMinute::try_from(&Timestamp {
+        seconds: t.and_utc().timestamp(),
+        nanos: 0,
+      }),
+      Err(_) => PartitionMinute::try_from(&common::parse_timestamp(s)?),
+    }
+  }
+
+  pub fn to_timestamp(&self) -> Timestamp {
+    Timestamp {
+      seconds: self.minutes * 60,
+      nanos: 0,
+    }
+  }
+
+  // ISO 8601 in UTC, like 2022-01-31T23:59Z, which sorts chronologically
+  pub fn to_dir_value(&self) -> ServerResult<String> {
+    let t = self.minutes.checked_mul(60)
+      .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+      .ok_or_else(|| ServerError::invalid(format!(
+        "partition minute {} is out of range",
+        self.minutes,
+      )))?;
+    Ok(t.format(PARTITION_MINUTE_FORMAT).to_string())
+  }
+}
+
 impl TryFrom<&Timestamp> for PartitionMinute {
   type Error = ServerError;
 
   fn try_from(t: &Timestamp) -> ServerResult<PartitionMinute> {
-    if t.nanos != 0 {
+    if !common::is_valid_timestamp(t) {
+      Err(ServerError::invalid(format!(
+        "timestamp of {} seconds is out of range",
+        t.seconds,
+      )))
+    } else if t.nanos != 0 {
       Err(ServerError::invalid(format!(
         "whole minute expected but {} nanoseconds found in timestamp",
         t.nanos,
@@ -77,10 +117,12 @@ pub struct NormalizedPartitionField {
 impl Display for NormalizedPartitionField {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     let value_str = match &self.value {
-      NormalizedPartitionValue::String(x) => x.clone(),
+      NormalizedPartitionValue::String(x) => common::escape_partition_string(x),
       NormalizedPartitionValue::Int64(x) => x.to_string(),
       NormalizedPartitionValue::Bool(x) => if *x {"true"} else {"false"}.to_string(),
-      NormalizedPartitionValue::Minute(x) => x.minutes.to_string(), // TODO
+      // minutes from PartitionMinute::try_from are always in range
+      NormalizedPartitionValue::Minute(x) => x.to_dir_value()
+        .unwrap_or_else(|_| x.minutes.to_string()),
     };
     write!(
       f,
@@ -92,14 +134,22 @@ impl Display for NormalizedPartitionField {
 }
 
 impl NormalizedPartitionField {
-  pub fn to_path_buf(&self) -> PathBuf {
-    PathBuf::from(self.to_string())
+  pub fn to_raw(&self) -> PartitionFieldValue {
+    let value = match &self.value {
+      NormalizedPartitionValue::String(x) => Value::StringVal(x.clone()),
+      NormalizedPartitionValue::Int64(x) => Value::Int64Val(*x),
+      NormalizedPartitionValue::Bool(x) => Value::BoolVal(*x),
+      NormalizedPartitionValue::Minute(x) => Value::TimestampVal(x.to_timestamp()),
+    };
+    PartitionFieldValue {
+      value: Some(value),
+    }
   }
 
   pub fn try_from_raw(name: &str, raw_field: &PartitionFieldValue) -> ServerResult<NormalizedPartitionField> {
     let value_result: ServerResult<NormalizedPartitionValue> = match raw_field.value.as_ref() {
       Some(Value::StringVal(x)) => {
-        common::validate_partition_string(x)?;
+        common::validate_partition_string(name, x)?;
         Ok(NormalizedPartitionValue::String(x.clone()))
       },
       Some(Value::Int64Val(x)) => Ok(NormalizedPartitionValue::Int64(*x)),
@@ -137,13 +187,20 @@ impl Display for NormalizedPartition {
 }
 
 impl NormalizedPartition {
-  pub fn to_path_buf(&self) -> PathBuf {
-    self.fields.iter().map(|f| f.to_path_buf()).collect()
+  // the names of the dirs it's stored in, from outermost to innermost
+  pub fn field_dir_names(&self) -> Vec<String> {
+    self.fields.iter().map(|f| f.to_string()).collect()
+  }
+
+  pub fn to_raw_fields(&self) -> HashMap<String, PartitionFieldValue> {
+    self.fields.iter()
+      .map(|field| (field.name.clone(), field.to_raw()))
+      .collect()
   }
 
   pub fn check_against_schema(&self, schema: &Schema) -> ServerResult<()> {
     if schema.partitioning.len() != self.fields.len() {
-      return Err(ServerError::invalid("number of partition fields does not match schema"));
+      return Err(ServerError::invalid("number of partition fields does not match schema").with_code(ErrorCode::SchemaMismatch));
     }
     let mut field_map = HashMap::new();
     for field in &self.fields {
@@ -159,7 +216,7 @@ impl NormalizedPartition {
         &common::unwrap_partition_dtype(partition_meta.dtype)?,
         field
       ) {
-        return Err(ServerError::invalid("partition field dtype does not match schema"));
+        return Err(ServerError::invalid("partition field dtype does not match schema").with_code(ErrorCode::SchemaMismatch));
       }
     }
     Ok(())
@@ -0,0 +1,96 @@
+use std::convert::TryInto;
+use std::path::Path;
+
+use pancake_db_idl::dml::FieldValue;
+use prost::Message;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::checksum;
+
+// about a 1% false positive rate
+const BITS_PER_VALUE: u64 = 10;
+const N_HASHES: u32 = 7;
+const HEADER_BYTE_SIZE: usize = 9;
+
+// A bloom filter over the non-null values of a column. It serializes as a
+// header (the number of hashes, then the number of bits as a big-endian
+// u64) followed by the bits, and is stored with a checksum footer like
+// compacted column files.
+pub struct BloomFilter {
+  n_hashes: u32,
+  n_bits: u64,
+  bits: Vec<u8>,
+}
+
+impl BloomFilter {
+  pub fn from_values(values: &[FieldValue]) -> Self {
+    let n_values = values.iter().filter(|value| value.value.is_some()).count() as u64;
+    let n_bits = (n_values * BITS_PER_VALUE).max(64);
+    let mut res = BloomFilter {
+      n_hashes: N_HASHES,
+      n_bits,
+      bits: vec![0; n_bits.div_ceil(8) as usize],
+    };
+    for value in values {
+      if value.value.is_some() {
+        for bit_idx in res.bit_idxs(value) {
+          res.bits[(bit_idx / 8) as usize] |= 1 << (bit_idx % 8);
+        }
+      }
+    }
+    res
+  }
+
+  // double hashing from the two halves of one 64 bit hash
+  fn bit_idxs(&self, value: &FieldValue) -> Vec<u64> {
+    let hash = checksum::xxhash64(&value.encode_to_vec());
+    let h1 = hash & 0xffff_ffff;
+    let h2 = hash >> 32;
+    (0..self.n_hashes as u64)
+      .map(|i| h1.wrapping_add(i.wrapping_mul(h2)) % self.n_bits)
+      .collect()
+  }
+
+  // false means the value is definitely absent; null is never contained
+  pub fn may_contain(&self, value: &FieldValue) -> bool {
+    if value.value.is_none() {
+      return false;
+    }
+    self.bit_idxs(value).into_iter()
+      .all(|bit_idx| self.bits[(bit_idx / 8) as usize] & (1 << (bit_idx % 8)) != 0)
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut res = Vec::with_capacity(HEADER_BYTE_SIZE + self.bits.len());
+    res.push(self.n_hashes as u8);
+    res.extend(self.n_bits.to_be_bytes());
+    res.extend(&self.bits);
+    checksum::with_footer(res)
+  }
+
+  pub fn from_bytes(bytes: &[u8], path: &Path) -> ServerResult<Self> {
+    let bytes = checksum::verify_and_strip_footer(bytes, path)?;
+    if bytes.len() < HEADER_BYTE_SIZE {
+      return Err(ServerError::corrupt(format!(
+        "bloom filter {:?} is too short to contain a header",
+        path,
+      )));
+    }
+    let n_hashes = bytes[0] as u32;
+    let n_bits = u64::from_be_bytes(bytes[1..HEADER_BYTE_SIZE].try_into().unwrap());
+    let bits = bytes[HEADER_BYTE_SIZE..].to_vec();
+    if n_bits == 0 || (bits.len() as u64) * 8 < n_bits {
+      return Err(ServerError::corrupt(format!(
+        "bloom filter {:?} has {} bytes of bits but claims {} bits",
+        path,
+        bits.len(),
+        n_bits,
+      )));
+    }
+    Ok(BloomFilter {
+      n_hashes,
+      n_bits,
+      bits,
+    })
+  }
+}
@@ -0,0 +1,124 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use hyper::{Body, Response};
+use hyper::body::{Buf, Bytes};
+use serde::Serialize;
+use warp::{Filter, Rejection, Reply};
+
+use crate::{Server, ServerResult};
+use crate::ops::read_changes::ReadChangesOp;
+use crate::ops::traits::ServerOp;
+use crate::serde_models::{ReadChangesRequestSerde, SubscribeChangesRequestSerde};
+use crate::server::authz::{self, Principal};
+use crate::utils::names::NormalizeNames;
+use crate::utils::rest::{self, ErrorResponse};
+
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
+
+// POST /rest/subscribe_changes streams a table's changes as newline-delimited
+// JSON, one ReadChanges response per line, polling until the client
+// disconnects. Lines are only sent when there are new events, and each
+// carries the cursor to resume from. An error ends the stream with a line
+// containing its message.
+pub fn warp_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::post()
+    .and(warp::path("subscribe_changes"))
+    .and(warp::filters::ext::get::<Server>())
+    .and(warp::header::optional::<String>("authorization"))
+    .and(warp::filters::body::stream())
+    .and_then(subscribe)
+}
+
+async fn subscribe<S, B>(
+  server: Server,
+  maybe_authorization: Option<String>,
+  body: S,
+) -> Result<Box<dyn Reply>, Infallible>
+  where S: Stream<Item=Result<B, warp::Error>>, B: Buf {
+  let (principal, req) = match parse_subscription(&server, maybe_authorization.as_deref(), body).await {
+    Ok(parsed) => parsed,
+    Err(e) => return Ok(rest::error_reply(&e)),
+  };
+  let state = SubscriptionState {
+    server,
+    principal,
+    poll_interval: Duration::from_millis(req.poll_interval_ms.unwrap_or(DEFAULT_POLL_INTERVAL_MS)),
+    table_name: req.table_name,
+    cursor: req.cursor,
+    done: false,
+  };
+
+  let stream = futures::stream::unfold(state, |mut state| async move {
+    if state.done {
+      return None;
+    }
+
+    let line = loop {
+      match state.poll().await {
+        Ok(Some(line)) => break line,
+        Ok(None) => tokio::time::sleep(state.poll_interval).await,
+        Err(e) => {
+          log::info!("ending subscribe_changes stream for {}: {}", state.table_name, e);
+          state.done = true;
+          break json_line(&ErrorResponse::from(&e));
+        }
+      }
+    };
+    Some((Ok::<_, Infallible>(line), state))
+  });
+  Ok(Box::new(Response::new(Body::wrap_stream(stream.boxed()))))
+}
+
+// Subscriptions count against their principal's request rate when they
+// start, but not as in flight, since they last until the client disconnects.
+async fn parse_subscription<S, B>(
+  server: &Server,
+  maybe_authorization: Option<&str>,
+  body: S,
+) -> ServerResult<(Principal, SubscribeChangesRequestSerde)>
+  where S: Stream<Item=Result<B, warp::Error>>, B: Buf {
+  let principal = server.authenticate_bearer(maybe_authorization).await?;
+  server.admit_request(&principal).await?;
+  let body = rest::read_limited_body(server, body).await?;
+  log::info!("received REST request for subscribe_changes containing {} bytes", body.len());
+  let mut req: SubscribeChangesRequestSerde = rest::parse_rest_req(body, "")?;
+  req.normalize_names()?;
+  Ok((principal, req))
+}
+
+struct SubscriptionState {
+  server: Server,
+  principal: Principal,
+  poll_interval: Duration,
+  table_name: String,
+  cursor: Option<String>,
+  done: bool,
+}
+
+impl SubscriptionState {
+  // returns a line to send if there are new events
+  async fn poll(&mut self) -> ServerResult<Option<Bytes>> {
+    let op = ReadChangesOp {
+      req: ReadChangesRequestSerde {
+        table_name: self.table_name.clone(),
+        cursor: self.cursor.clone(),
+        max_rows: None,
+      }
+    };
+    let resp = authz::scope(self.principal.clone(), op.execute(&self.server)).await?;
+    self.cursor = Some(resp.cursor.clone());
+    if resp.events.is_empty() {
+      Ok(None)
+    } else {
+      Ok(Some(json_line(&resp)))
+    }
+  }
+}
+
+fn json_line<T: Serialize>(value: &T) -> Bytes {
+  let mut line = serde_json::to_vec(value).unwrap_or_default();
+  line.push(b'\n');
+  Bytes::from(line)
+}
@@ -0,0 +1,56 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rand::Rng;
+use tokio::time::Duration;
+
+// With --chaos, the server misbehaves on purpose so soak tests can shake out
+// races between flushes, compactions, and reads: lock waits are randomly
+// delayed, file IO randomly fails, and the background loops are randomly
+// killed between segments, leaving their supervisor to restart them. Like
+// the memory mounts, it applies to the whole process, and it's only turned
+// on once the server has recovered and started, so startup stays reliable.
+
+const LOCK_DELAY_PROBABILITY: f64 = 0.1;
+const MAX_LOCK_DELAY: Duration = Duration::from_millis(50);
+const IO_ERROR_PROBABILITY: f64 = 0.002;
+const KILL_PROBABILITY: f64 = 0.01;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+  ENABLED.store(true, Ordering::Relaxed);
+  log::warn!("chaos mode is on; expect delays, IO errors, and background loop panics");
+}
+
+pub fn is_enabled() -> bool {
+  ENABLED.load(Ordering::Relaxed)
+}
+
+fn happens(probability: f64) -> bool {
+  is_enabled() && rand::thread_rng().gen_bool(probability)
+}
+
+pub async fn delay_lock() {
+  if happens(LOCK_DELAY_PROBABILITY) {
+    let delay = rand::thread_rng().gen_range(Duration::ZERO..MAX_LOCK_DELAY);
+    tokio::time::sleep(delay).await;
+  }
+}
+
+// fails IO on path before it happens
+pub fn io_error(path: &Path) -> io::Result<()> {
+  if happens(IO_ERROR_PROBABILITY) {
+    Err(io::Error::other(format!("chaos IO error on {:?}", path)))
+  } else {
+    Ok(())
+  }
+}
+
+// panics partway through an iteration of a background loop
+pub fn kill(loop_name: &str) {
+  if happens(KILL_PROBABILITY) {
+    panic!("chaos killed the {} loop", loop_name);
+  }
+}
@@ -0,0 +1,56 @@
+use std::hash::Hasher;
+use std::path::Path;
+
+use twox_hash::XxHash64;
+
+use crate::errors::{ServerError, ServerResult};
+
+// Compacted column files written with checksums end in a footer holding the
+// xxhash64 of all preceding bytes, stored big-endian.
+pub const FOOTER_BYTE_SIZE: usize = 8;
+
+pub fn xxhash64(bytes: &[u8]) -> u64 {
+  let mut hasher = XxHash64::with_seed(0);
+  hasher.write(bytes);
+  hasher.finish()
+}
+
+pub fn with_footer(mut bytes: Vec<u8>) -> Vec<u8> {
+  let checksum = xxhash64(&bytes);
+  bytes.extend(checksum.to_be_bytes());
+  bytes
+}
+
+// the length of the file's contents, excluding the footer
+pub fn data_len(file_len: u64) -> u64 {
+  file_len.saturating_sub(FOOTER_BYTE_SIZE as u64)
+}
+
+pub fn strip_footer(bytes: &[u8]) -> &[u8] {
+  &bytes[..bytes.len().saturating_sub(FOOTER_BYTE_SIZE)]
+}
+
+pub fn verify_and_strip_footer<'a>(bytes: &'a [u8], path: &Path) -> ServerResult<&'a [u8]> {
+  if bytes.len() < FOOTER_BYTE_SIZE {
+    return Err(ServerError::checksum_mismatch(format!(
+      "file {:?} is too short ({} bytes) to contain a checksum footer",
+      path,
+      bytes.len(),
+    )));
+  }
+
+  let (data, footer) = bytes.split_at(bytes.len() - FOOTER_BYTE_SIZE);
+  let mut footer_bytes = [0_u8; FOOTER_BYTE_SIZE];
+  footer_bytes.copy_from_slice(footer);
+  let expected = u64::from_be_bytes(footer_bytes);
+  let actual = xxhash64(data);
+  if actual != expected {
+    return Err(ServerError::checksum_mismatch(format!(
+      "file {:?} has checksum {:016x} but its footer records {:016x}",
+      path,
+      actual,
+      expected,
+    )));
+  }
+  Ok(data)
+}
@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+
+use pancake_db_core::compression::{Codec, ValueCodec};
+use pancake_db_core::errors::{CoreError, CoreResult};
+use pancake_db_core::primitives::{Atom, Primitive};
+use pancake_db_core::RepLevelsAndBytes;
+use pancake_db_idl::dtype::DataType;
+use q_compress::data_types::TimestampMicros;
+
+use crate::utils::safe_decoding;
+
+// Core's decoders build a protobuf FieldValue for every value (and a
+// RepeatedFieldValue for every list), which dominates the cost of reading
+// wide columns on the server. These decode the same flush encoding and
+// compressed files straight into typed columns instead.
+//
+// A column's repetition levels are like core's, but per value rather than
+// per atom: 0 is a null row, nested_list_depth + 1 is a value, and the
+// levels in between end lists at that depth. Values line up with the levels
+// that are 0 or nested_list_depth + 1, so for unnested columns there's
+// exactly one of each per row.
+
+// reserved bytes of the core encoding
+const ESCAPE_BYTE: u8 = 255;
+const COUNT_BYTE: u8 = 254;
+const NULL_BYTE: u8 = 253;
+
+#[derive(Clone, Debug)]
+pub struct Column<P: Primitive> {
+  pub nested_list_depth: u8,
+  pub values: Vec<Option<P>>,
+  pub rep_levels: Vec<u8>,
+}
+
+impl<P: Primitive> Column<P> {
+  pub fn new(nested_list_depth: u8) -> Self {
+    Column {
+      nested_list_depth,
+      values: Vec::new(),
+      rep_levels: Vec::new(),
+    }
+  }
+
+  fn push_null(&mut self) {
+    self.values.push(None);
+    self.rep_levels.push(0);
+  }
+
+  fn has_value(&self, level: u8) -> bool {
+    level == 0 || level == self.nested_list_depth + 1
+  }
+
+  pub fn n_rows(&self) -> usize {
+    self.rep_levels.iter().filter(|&&level| level <= 1).count()
+  }
+
+  pub fn truncate_rows(&mut self, n_rows: usize) {
+    let mut rows_seen = 0;
+    let mut values_seen = 0;
+    for (level_idx, &level) in self.rep_levels.iter().enumerate() {
+      if rows_seen == n_rows {
+        self.rep_levels.truncate(level_idx);
+        self.values.truncate(values_seen);
+        return;
+      }
+      if self.has_value(level) {
+        values_seen += 1;
+      }
+      if level <= 1 {
+        rows_seen += 1;
+      }
+    }
+  }
+
+  pub fn extend(&mut self, other: Column<P>) {
+    self.values.extend(other.values);
+    self.rep_levels.extend(other.rep_levels);
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  i: usize,
+  scratch: Vec<u8>,
+}
+
+impl<'a> Reader<'a> {
+  fn complete(&self) -> bool {
+    self.i >= self.bytes.len()
+  }
+
+  fn read_one(&mut self) -> CoreResult<u8> {
+    let b = *self.bytes.get(self.i)
+      .ok_or_else(|| CoreError::corrupt("flush bytes ended mid-value"))?;
+    self.i += 1;
+    Ok(b)
+  }
+
+  fn unescaped_read_one(&mut self) -> CoreResult<u8> {
+    match self.read_one()? {
+      ESCAPE_BYTE => Ok(!self.read_one()?),
+      b if b >= NULL_BYTE => Err(CoreError::corrupt(&format!("unexpected unescaped byte at {}", self.i))),
+      b => Ok(b),
+    }
+  }
+
+  fn unescaped_read_u16(&mut self) -> CoreResult<u16> {
+    Ok(u16::from_be_bytes([self.unescaped_read_one()?, self.unescaped_read_one()?]))
+  }
+
+  fn read_atom<A: Atom>(&mut self) -> CoreResult<A> {
+    let mut scratch = std::mem::take(&mut self.scratch);
+    scratch.clear();
+    for _ in 0..A::BYTE_SIZE {
+      scratch.push(self.unescaped_read_one()?);
+    }
+    let res = A::try_from_bytes(&scratch);
+    self.scratch = scratch;
+    res
+  }
+}
+
+fn decode_value<P: Primitive>(
+  reader: &mut Reader,
+  nested_list_depth: u8,
+  current_depth: u8,
+  atoms: &mut Vec<P::A>,
+  column: &mut Column<P>,
+) -> CoreResult<()> {
+  if current_depth == nested_list_depth {
+    atoms.clear();
+    let len = if P::IS_ATOMIC {
+      1
+    } else {
+      reader.unescaped_read_u16()? as usize
+    };
+    for _ in 0..len {
+      atoms.push(reader.read_atom::<P::A>()?);
+    }
+    column.values.push(Some(P::try_from_atoms(atoms)?));
+  } else {
+    let len = reader.unescaped_read_u16()?;
+    for _ in 0..len {
+      decode_value(reader, nested_list_depth, current_depth + 1, atoms, column)?;
+    }
+  }
+  column.rep_levels.push(current_depth + 1);
+  Ok(())
+}
+
+// decodes up to limit rows of the flush encoding
+fn decode_limited<P: Primitive>(
+  nested_list_depth: u8,
+  bytes: &[u8],
+  limit: usize,
+) -> CoreResult<Column<P>> {
+  let mut column = Column::new(nested_list_depth);
+  let mut atoms = Vec::new();
+  let mut reader = Reader {
+    bytes,
+    i: 0,
+    scratch: Vec::new(),
+  };
+  let mut n_rows = 0;
+  while !reader.complete() && n_rows < limit {
+    let b0 = reader.read_one()?;
+    if b0 == NULL_BYTE {
+      column.push_null();
+    } else if b0 == COUNT_BYTE {
+      let mut count_bytes = [0_u8; 4];
+      for count_byte in &mut count_bytes {
+        *count_byte = reader.unescaped_read_one()?;
+      }
+      let count = u32::from_be_bytes(count_bytes) as usize;
+      if n_rows == 0 {
+        // only the nulls within the limit, however many are asked for
+        n_rows = count.min(limit);
+        for _ in 0..n_rows {
+          column.push_null();
+        }
+      } else if n_rows != count {
+        return Err(CoreError::corrupt("in-file count did not match number of decoded entries"));
+      }
+      continue;
+    } else {
+      reader.i -= 1;
+      decode_value(&mut reader, nested_list_depth, 0, &mut atoms, &mut column)?;
+    }
+    n_rows += 1;
+  }
+  Ok(column)
+}
+
+// regroups core's per-atom levels into values
+fn decompress<P: Primitive>(
+  codec: &str,
+  bytes: &[u8],
+  nested_list_depth: u8,
+) -> CoreResult<Column<P>> {
+  let codec: Box<dyn Codec<P=P>> = P::new_codec(codec)
+    .ok_or_else(|| CoreError::invalid(&format!("unknown codec {} for {:?}", codec, P::DTYPE)))?;
+  let RepLevelsAndBytes { levels, remaining_bytes } = codec.decompress_rep_levels(bytes)?;
+  let atoms = codec.decompress_atoms(&remaining_bytes)?;
+
+  let value_level = nested_list_depth + 1;
+  let mut column = Column::new(nested_list_depth);
+  let mut atom_idx = 0;
+  let mut value_start = 0;
+  for level in levels {
+    if level == 0 {
+      column.push_null();
+      continue;
+    } else if level == value_level + 1 && !P::IS_ATOMIC {
+      atom_idx += 1;
+      continue;
+    } else if level == value_level {
+      if P::IS_ATOMIC {
+        atom_idx += 1;
+      }
+      let value_atoms = atoms.get(value_start..atom_idx)
+        .ok_or_else(|| CoreError::corrupt("compressed column ran out of atoms"))?;
+      column.values.push(Some(P::try_from_atoms(value_atoms)?));
+      value_start = atom_idx;
+    } else if level > value_level {
+      return Err(CoreError::corrupt("invalid repetition level found"));
+    }
+    column.rep_levels.push(level);
+  }
+  Ok(column)
+}
+
+#[derive(Clone, Debug)]
+pub enum TypedColumn {
+  Int64(Column<i64>),
+  String(Column<String>),
+  Float32(Column<f32>),
+  Float64(Column<f64>),
+  Bytes(Column<Vec<u8>>),
+  Bool(Column<bool>),
+  TimestampMicros(Column<TimestampMicros>),
+}
+
+macro_rules! with_column {
+  ($typed:expr, $column:ident => $body:expr) => {
+    match $typed {
+      TypedColumn::Int64($column) => $body,
+      TypedColumn::String($column) => $body,
+      TypedColumn::Float32($column) => $body,
+      TypedColumn::Float64($column) => $body,
+      TypedColumn::Bytes($column) => $body,
+      TypedColumn::Bool($column) => $body,
+      TypedColumn::TimestampMicros($column) => $body,
+    }
+  };
+}
+
+macro_rules! for_dtype {
+  ($dtype:expr, $f:ident($($arg:expr),*)) => {
+    match $dtype {
+      DataType::Int64 => TypedColumn::Int64($f::<i64>($($arg),*)?),
+      DataType::String => TypedColumn::String($f::<String>($($arg),*)?),
+      DataType::Float32 => TypedColumn::Float32($f::<f32>($($arg),*)?),
+      DataType::Float64 => TypedColumn::Float64($f::<f64>($($arg),*)?),
+      DataType::Bytes => TypedColumn::Bytes($f::<Vec<u8>>($($arg),*)?),
+      DataType::Bool => TypedColumn::Bool($f::<bool>($($arg),*)?),
+      DataType::TimestampMicros => TypedColumn::TimestampMicros($f::<TimestampMicros>($($arg),*)?),
+    }
+  };
+}
+
+fn compare_options<T>(a: Option<&Option<T>>, b: Option<&Option<T>>, cmp: impl Fn(&T, &T) -> Ordering) -> Ordering {
+  // nulls (including values missing from columns that were never explicit)
+  // sort first
+  match (a.and_then(Option::as_ref), b.and_then(Option::as_ref)) {
+    (None, None) => Ordering::Equal,
+    (None, Some(_)) => Ordering::Less,
+    (Some(_), None) => Ordering::Greater,
+    (Some(a), Some(b)) => cmp(a, b),
+  }
+}
+
+impl TypedColumn {
+  pub fn empty(dtype: DataType, nested_list_depth: u8) -> TypedColumn {
+    match dtype {
+      DataType::Int64 => TypedColumn::Int64(Column::new(nested_list_depth)),
+      DataType::String => TypedColumn::String(Column::new(nested_list_depth)),
+      DataType::Float32 => TypedColumn::Float32(Column::new(nested_list_depth)),
+      DataType::Float64 => TypedColumn::Float64(Column::new(nested_list_depth)),
+      DataType::Bytes => TypedColumn::Bytes(Column::new(nested_list_depth)),
+      DataType::Bool => TypedColumn::Bool(Column::new(nested_list_depth)),
+      DataType::TimestampMicros => TypedColumn::TimestampMicros(Column::new(nested_list_depth)),
+    }
+  }
+
+  pub fn n_rows(&self) -> usize {
+    with_column!(self, column => column.n_rows())
+  }
+
+  pub fn truncate_rows(&mut self, n_rows: usize) {
+    with_column!(self, column => column.truncate_rows(n_rows))
+  }
+
+  pub fn extend(&mut self, other: TypedColumn) -> CoreResult<()> {
+    match (self, other) {
+      (TypedColumn::Int64(a), TypedColumn::Int64(b)) => a.extend(b),
+      (TypedColumn::String(a), TypedColumn::String(b)) => a.extend(b),
+      (TypedColumn::Float32(a), TypedColumn::Float32(b)) => a.extend(b),
+      (TypedColumn::Float64(a), TypedColumn::Float64(b)) => a.extend(b),
+      (TypedColumn::Bytes(a), TypedColumn::Bytes(b)) => a.extend(b),
+      (TypedColumn::Bool(a), TypedColumn::Bool(b)) => a.extend(b),
+      (TypedColumn::TimestampMicros(a), TypedColumn::TimestampMicros(b)) => a.extend(b),
+      _ => return Err(CoreError::invalid("cannot extend a column with one of another dtype")),
+    }
+    Ok(())
+  }
+
+  // Compares the rows at i and j of an unnested column, with rows past the
+  // end counting as nulls.
+  pub fn compare_rows(&self, i: usize, j: usize) -> Ordering {
+    match self {
+      TypedColumn::Int64(c) => compare_options(c.values.get(i), c.values.get(j), Ord::cmp),
+      TypedColumn::String(c) => compare_options(c.values.get(i), c.values.get(j), Ord::cmp),
+      TypedColumn::Float32(c) => compare_options(
+        c.values.get(i),
+        c.values.get(j),
+        |a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal),
+      ),
+      TypedColumn::Float64(c) => compare_options(
+        c.values.get(i),
+        c.values.get(j),
+        |a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal),
+      ),
+      TypedColumn::Bytes(c) => compare_options(c.values.get(i), c.values.get(j), Ord::cmp),
+      TypedColumn::Bool(c) => compare_options(c.values.get(i), c.values.get(j), Ord::cmp),
+      TypedColumn::TimestampMicros(c) => compare_options(
+        c.values.get(i),
+        c.values.get(j),
+        |a, b| a.to_total_parts().cmp(&b.to_total_parts()),
+      ),
+    }
+  }
+}
+
+pub fn decode_columnar(
+  dtype: DataType,
+  nested_list_depth: u8,
+  bytes: &[u8],
+  limit: usize,
+) -> CoreResult<TypedColumn> {
+  safe_decoding::guarded(|| Ok(for_dtype!(dtype, decode_limited(nested_list_depth, bytes, limit))))
+}
+
+pub fn decompress_columnar(
+  dtype: DataType,
+  codec: &str,
+  bytes: &[u8],
+  nested_list_depth: u8,
+) -> CoreResult<TypedColumn> {
+  safe_decoding::guarded(|| Ok(for_dtype!(dtype, decompress(codec, bytes, nested_list_depth))))
+}
+
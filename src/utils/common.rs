@@ -1,9 +1,11 @@
+use std::any::Any;
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::io::{Cursor, ErrorKind, SeekFrom};
 use std::path::Path;
 use std::str::FromStr;
+use chrono::DateTime;
 use prost::Message;
 
 use pancake_db_idl::dml::{FieldValue, partition_filter, PartitionFieldComparison, PartitionFieldValue, PartitionFilter, RepeatedFieldValue, Row};
@@ -13,21 +15,23 @@ use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
 use pancake_db_idl::dtype::DataType;
 use pancake_db_idl::partition_dtype::PartitionDataType;
 use pancake_db_idl::schema::{ColumnMeta, Schema};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
 use prost_types::Timestamp;
-use tokio::fs;
 use tokio::io;
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use uuid::Uuid;
 
 use crate::constants::*;
-use crate::errors::{ServerError, ServerResult};
+use crate::errors::{RowError, ServerError, ServerResult};
 use crate::metadata::{MetadataKey, PersistentMetadata};
 use crate::metadata::compaction::Compaction;
 use crate::metadata::segment::SegmentMetadata;
-use crate::types::{NormalizedPartitionField, NormalizedPartitionValue};
+use crate::types::{NormalizedPartitionField, NormalizedPartitionValue, PartitionMinute};
+use crate::utils::dirs;
+use crate::utils::durability;
+use crate::utils::vfs;
 
 pub async fn file_exists(fname: impl AsRef<Path>) -> ServerResult<bool> {
-  match fs::File::open(fname.as_ref()).await {
+  match vfs::File::open(fname.as_ref()).await {
     Ok(_) => Ok(true),
     Err(e) => {
       match e.kind() {
@@ -42,7 +46,7 @@ pub async fn file_exists(fname: impl AsRef<Path>) -> ServerResult<bool> {
 }
 
 pub async fn file_nonempty(fname: impl AsRef<Path>) -> ServerResult<bool> {
-  match fs::File::open(fname.as_ref()).await {
+  match vfs::File::open(fname.as_ref()).await {
     Ok(mut f) => {
       let mut buf = vec![0_u8];
       let bytes_read = f.read(&mut buf).await?;
@@ -58,7 +62,7 @@ pub async fn file_nonempty(fname: impl AsRef<Path>) -> ServerResult<bool> {
 
 pub async fn read_with_offset(fname: impl AsRef<Path>, offset: u64, bytes: usize) -> ServerResult<Vec<u8>> {
   // return completed: bool, and bytes if any
-  let mut maybe_file = fs::File::open(fname.as_ref()).await
+  let mut maybe_file = vfs::File::open(fname.as_ref()).await
     .map(Some)
     .or_else(|e| match e.kind() {
       io::ErrorKind::NotFound => Ok(None),
@@ -109,7 +113,7 @@ pub async fn read_with_offset(fname: impl AsRef<Path>, offset: u64, bytes: usize
 
 // returns whether it already exists
 pub async fn create_if_new(dir: impl AsRef<Path>) -> ServerResult<bool> {
-  match fs::create_dir(dir.as_ref()).await {
+  match vfs::create_dir(dir.as_ref()).await {
     Ok(_) => Ok(false),
     Err(e) => match e.kind() {
       ErrorKind::AlreadyExists => Ok(true),
@@ -130,13 +134,13 @@ pub async fn overwrite_file_atomic(
   dir: &Path,
 ) -> ServerResult<()> {
   let path = path.as_ref();
-  let initial_write_path = dir.join(format!("tmp/{}", Uuid::new_v4()));
+  let initial_write_path = dirs::tmp_dir(dir).join(Uuid::new_v4().to_string());
   log::debug!(
     "atomically overwriting {:?} by first writing to {:?}",
     path,
     initial_write_path,
   );
-  fs::write(
+  vfs::write(
     &initial_write_path,
     contents,
   ).await
@@ -145,8 +149,14 @@ pub async fn overwrite_file_atomic(
       initial_write_path,
       path,
     )))?;
+  durability::written(&initial_write_path).await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while syncing {:?} for atomic overwrite of {:?}",
+      initial_write_path,
+      path,
+    )))?;
 
-  fs::rename(
+  vfs::rename(
     &initial_write_path,
     path,
   ).await
@@ -155,6 +165,14 @@ pub async fn overwrite_file_atomic(
       initial_write_path,
       path,
     )))?;
+  if let Some(parent) = path.parent() {
+    durability::written(parent).await
+      .map_err(|e| ServerError::from(e).with_context(format!(
+        "while syncing {:?} after atomic overwrite of {:?}",
+        parent,
+        path,
+      )))?;
+  }
 
   Ok(())
 }
@@ -163,18 +181,23 @@ pub async fn overwrite_file(
   path: impl AsRef<Path>,
   contents: impl AsRef<[u8]>,
 ) -> ServerResult<()> {
-  fs::write(
+  vfs::write(
     path.as_ref(),
     contents,
   ).await
     .map_err(|e| ServerError::from(e).with_context(format!(
       "during non-atomic overwrite of {:?}",
       path.as_ref(),
+    )))?;
+  durability::written(path.as_ref()).await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while syncing non-atomic overwrite of {:?}",
+      path.as_ref(),
     )))
 }
 
 pub async fn append_to_file(path: impl AsRef<Path>, contents: &[u8]) -> ServerResult<()> {
-  let mut file = fs::OpenOptions::new()
+  let mut file = vfs::OpenOptions::new()
     .append(true)
     .create(true)
     .open(path.as_ref())
@@ -187,11 +210,16 @@ pub async fn append_to_file(path: impl AsRef<Path>, contents: &[u8]) -> ServerRe
     .map_err(|e| ServerError::from(e).with_context(format!(
       "while writing to append to {:?}",
       path.as_ref(),
+    )))?;
+  durability::written(path.as_ref()).await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while syncing append to {:?}",
+      path.as_ref(),
     )))
 }
 
 pub async fn read_or_empty(path: impl AsRef<Path>) -> ServerResult<Vec<u8>> {
-  match fs::read(path.as_ref()).await {
+  match vfs::read(path.as_ref()).await {
     Ok(bytes) => Ok(bytes),
     Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Ok(Vec::new()),
     Err(e) => Err(ServerError::from(e).with_context(format!(
@@ -201,6 +229,17 @@ pub async fn read_or_empty(path: impl AsRef<Path>) -> ServerResult<Vec<u8>> {
   }
 }
 
+pub async fn file_len_or_zero(path: impl AsRef<Path>) -> ServerResult<u64> {
+  match vfs::metadata(path.as_ref()).await {
+    Ok(metadata) => Ok(metadata.len()),
+    Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Ok(0),
+    Err(e) => Err(ServerError::from(e).with_context(format!(
+      "while reading length of {:?} (if it exists)",
+      path.as_ref()
+    )))
+  }
+}
+
 pub fn field_matches_meta(fv: &FieldValue, dtype: DataType, nested_list_depth: u32) -> bool {
   if fv.value.is_none() {
     return true;
@@ -212,12 +251,31 @@ pub fn field_matches_meta(fv: &FieldValue, dtype: DataType, nested_list_depth: u
     (DataType::Bytes, Value::BytesVal(_)) => true,
     (DataType::Float32, Value::Float32Val(_)) => true,
     (DataType::Float64, Value::Float64Val(_)) => true,
-    (DataType::TimestampMicros, Value::TimestampVal(_)) => true,
+    (DataType::TimestampMicros, Value::TimestampVal(t)) => is_valid_timestamp(t),
     _ => false,
   };
   traverse_check_field(fv, &checker, nested_list_depth)
 }
 
+pub fn is_valid_timestamp(t: &Timestamp) -> bool {
+  (MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&t.seconds) &&
+    (0..1_000_000_000).contains(&t.nanos)
+}
+
+// Parses an RFC 3339 time, which may have any offset, into UTC.
+pub fn parse_timestamp(s: &str) -> ServerResult<Timestamp> {
+  let t = DateTime::parse_from_rfc3339(s)
+    .map_err(|e| ServerError::invalid(format!("invalid RFC 3339 timestamp {}: {}", s, e)))?;
+  let res = Timestamp {
+    seconds: t.timestamp(),
+    nanos: t.timestamp_subsec_nanos() as i32,
+  };
+  if !is_valid_timestamp(&res) {
+    return Err(ServerError::invalid(format!("timestamp {} is out of range", s)));
+  }
+  Ok(res)
+}
+
 fn traverse_check_field(
   fv: &FieldValue,
   f: &dyn Fn(&Value) -> bool,
@@ -240,6 +298,20 @@ fn traverse_check_field(
   }
 }
 
+// None for values that have no ordering, like lists and NaNs
+pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+  match (a, b) {
+    (Value::Int64Val(a), Value::Int64Val(b)) => Some(a.cmp(b)),
+    (Value::Float32Val(a), Value::Float32Val(b)) => a.partial_cmp(b),
+    (Value::Float64Val(a), Value::Float64Val(b)) => a.partial_cmp(b),
+    (Value::StringVal(a), Value::StringVal(b)) => Some(a.cmp(b)),
+    (Value::BytesVal(a), Value::BytesVal(b)) => Some(a.cmp(b)),
+    (Value::BoolVal(a), Value::BoolVal(b)) => Some(a.cmp(b)),
+    (Value::TimestampVal(a), Value::TimestampVal(b)) => Some((a.seconds, a.nanos).cmp(&(b.seconds, b.nanos))),
+    _ => None,
+  }
+}
+
 pub fn partition_dtype_matches_field(dtype: &PartitionDataType, field: &NormalizedPartitionField) -> bool {
   let value = field.value.clone();
   match dtype {
@@ -262,7 +334,7 @@ pub fn partition_field_value_from_string(
         Err(_) => None
       }
     },
-    PartitionDataType::String => Some(PartitionValue::StringVal(value_str.to_string())),
+    PartitionDataType::String => unescape_partition_string(value_str).map(PartitionValue::StringVal),
     PartitionDataType::Bool => {
       if value_str == "true" {
         Some(PartitionValue::BoolVal(true))
@@ -272,17 +344,9 @@ pub fn partition_field_value_from_string(
         None
       }
     },
-    PartitionDataType::TimestampMinute => {
-      // TODO use formatted UTC times
-      match value_str.parse::<i64>() {
-        Ok(x) => {
-          let mut t: Timestamp = Timestamp::default();
-          t.seconds = x * 60;
-          Some(PartitionValue::TimestampVal(t))
-        },
-        Err(_) => None,
-      }
-    },
+    PartitionDataType::TimestampMinute => PartitionMinute::parse(value_str)
+      .ok()
+      .map(|minute| PartitionValue::TimestampVal(minute.to_timestamp())),
   };
   if value.is_none() {
     return Err(ServerError::internal("failed to parse partition field value"));
@@ -338,18 +402,67 @@ fn field_satisfies_comparison_filter(name: &str, field: &PartitionFieldValue, co
   })
 }
 
-pub fn satisfies_filters(partition: &HashMap<String, PartitionFieldValue>, filters: &[PartitionFilter]) -> ServerResult<bool> {
-  for (name, pfv) in partition {
+// A condition on a partition field. The IDL's filters only compare a field
+// to one value, so IN lists and inclusive BETWEEN ranges, which REST
+// requests can use to avoid listing segments once per value, only exist
+// here.
+#[derive(Clone, Debug)]
+pub enum PartitionCondition {
+  Comparison(PartitionFieldComparison),
+  In {
+    name: String,
+    values: Vec<PartitionValue>,
+  },
+  Between {
+    name: String,
+    min: PartitionValue,
+    max: PartitionValue,
+  },
+}
+
+impl PartitionCondition {
+  pub fn from_filters(filters: &[PartitionFilter]) -> Vec<PartitionCondition> {
+    let mut conditions = Vec::with_capacity(filters.len());
     for filter in filters {
-      let satisfies = match &filter.value {
-        Some(partition_filter::Value::Comparison(comparison)) => field_satisfies_comparison_filter(
-          name,
-          pfv,
-          comparison,
-        ),
-        None => Ok(true),
-      }?;
-      if !satisfies {
+      if let Some(partition_filter::Value::Comparison(comparison)) = &filter.value {
+        conditions.push(PartitionCondition::Comparison(comparison.clone()));
+      }
+    }
+    conditions
+  }
+
+  fn is_satisfied_by(&self, name: &str, field: &PartitionFieldValue) -> ServerResult<bool> {
+    let value = field.value.as_ref().unwrap();
+    match self {
+      PartitionCondition::Comparison(comparison) => field_satisfies_comparison_filter(name, field, comparison),
+      PartitionCondition::In { name: condition_name, values } => {
+        if name != condition_name {
+          return Ok(true);
+        }
+        for condition_value in values {
+          if matches!(cmp_partition_field_values(value, condition_value)?, Ordering::Equal) {
+            return Ok(true);
+          }
+        }
+        Ok(false)
+      },
+      PartitionCondition::Between { name: condition_name, min, max } => {
+        if name != condition_name {
+          return Ok(true);
+        }
+        Ok(
+          !matches!(cmp_partition_field_values(value, min)?, Ordering::Less) &&
+            !matches!(cmp_partition_field_values(value, max)?, Ordering::Greater)
+        )
+      },
+    }
+  }
+}
+
+pub fn satisfies_filters(partition: &HashMap<String, PartitionFieldValue>, conditions: &[PartitionCondition]) -> ServerResult<bool> {
+  for (name, pfv) in partition {
+    for condition in conditions {
+      if !condition.is_satisfied_by(name, pfv)? {
         return Ok(false);
       }
     }
@@ -400,18 +513,39 @@ pub fn validate_segment_id(segment_id: &str) -> ServerResult<()> {
   }
 }
 
-pub fn validate_partition_string(value: &str) -> ServerResult<()> {
-  let mut allowable_special_chars = HashSet::new();
-  allowable_special_chars.extend(vec!['-', '_', '!', '*', '(', ')']);
-  if value.chars().any(|c| !c.is_ascii_alphanumeric() && !allowable_special_chars.contains(&c)) {
-    let allowable_string = allowable_special_chars.iter()
-      .map(|c| c.to_string())
-      .collect::<Vec<String>>()
-      .join("");
+// Characters escaped in partition string dir names. Strings of only the
+// others, which used to be the only ones allowed, keep the same dir names.
+const PARTITION_STRING_ESCAPES: &AsciiSet = &NON_ALPHANUMERIC
+  .remove(b'-')
+  .remove(b'_')
+  .remove(b'!')
+  .remove(b'*')
+  .remove(b'(')
+  .remove(b')');
+
+// percent-encodes the string's UTF-8 bytes for a partition dir name
+pub fn escape_partition_string(value: &str) -> String {
+  utf8_percent_encode(value, PARTITION_STRING_ESCAPES).to_string()
+}
+
+pub fn unescape_partition_string(escaped: &str) -> Option<String> {
+  percent_decode_str(escaped)
+    .decode_utf8()
+    .ok()
+    .map(|value| value.to_string())
+}
+
+// Any string may be a partition value, as long as its escaped dir name
+// fits in a file name.
+pub fn validate_partition_string(name: &str, value: &str) -> ServerResult<()> {
+  let dir_name_len = name.len() + 1 + escape_partition_string(value).len();
+  if dir_name_len > MAX_NAME_LENGTH {
     return Err(ServerError::invalid(format!(
-      "partition string \"{}\" must contain only alphanumeric characters and characters from {}",
-      value,
-      allowable_string,
+      "partition string for {} is too long; its escaped dir name {}=... would have {} bytes, over the limit of {}",
+      name,
+      name,
+      dir_name_len,
+      MAX_NAME_LENGTH,
     )))
   }
   Ok(())
@@ -458,41 +592,108 @@ fn validate_entity_name(entity: &str, name: &str, is_write: bool) -> ServerResul
   Ok(())
 }
 
-pub fn validate_rows(schema: &Schema, rows: &[Row]) -> ServerResult<()> {
-  for row in rows {
-    for (col_name, fv) in &row.fields {
-      let mut err_msgs = Vec::new();
-      match schema.columns.get(col_name) {
-        Some(col) => {
-          if !field_matches_meta(fv, unwrap_dtype(col.dtype)?, col.nested_list_depth) {
-            err_msgs.push(format!(
-              "invalid field value for column {} with dtype {:?} and depth {}: {:?}",
-              col_name,
-              col.dtype,
-              col.nested_list_depth,
-              fv,
-            ));
-          }
-        },
-        _ => {
-          err_msgs.push(format!("unknown column: {}", col_name));
-        },
-      };
+// a field that a lenient write left out of its row
+#[derive(Clone, Debug)]
+pub struct InvalidField {
+  pub row_index: usize,
+  pub col_name: String,
+  pub message: String,
+}
 
-      if byte_size_of_field(fv) > MAX_FIELD_BYTE_SIZE {
+fn field_error(schema: &Schema, col_name: &str, fv: &FieldValue) -> ServerResult<Option<String>> {
+  let mut err_msgs = Vec::new();
+  match schema.columns.get(col_name) {
+    Some(col) => {
+      if !field_matches_meta(fv, unwrap_dtype(col.dtype)?, col.nested_list_depth) {
         err_msgs.push(format!(
-          "field for {} exceeds max byte size of {}",
+          "invalid field value for column {} with dtype {:?} and depth {}: {:?}",
           col_name,
-          MAX_FIELD_BYTE_SIZE
-        ))
+          col.dtype,
+          col.nested_list_depth,
+          fv,
+        ));
       }
+    },
+    _ => {
+      err_msgs.push(format!("unknown column: {}", col_name));
+    },
+  };
+
+  if byte_size_of_field(fv) > MAX_FIELD_BYTE_SIZE {
+    err_msgs.push(format!(
+      "field for {} exceeds max byte size of {}",
+      col_name,
+      MAX_FIELD_BYTE_SIZE
+    ))
+  }
+
+  if err_msgs.is_empty() {
+    Ok(None)
+  } else {
+    Ok(Some(err_msgs.join("; ")))
+  }
+}
 
-      if !err_msgs.is_empty() {
-        return Err(ServerError::invalid(&err_msgs.join("; ")));
+// every field that fails validation, in row order
+fn invalid_fields(schema: &Schema, rows: &[Row]) -> ServerResult<Vec<InvalidField>> {
+  let mut res = Vec::new();
+  for (row_index, row) in rows.iter().enumerate() {
+    let mut col_names: Vec<&String> = row.fields.keys().collect();
+    col_names.sort();
+    for col_name in col_names {
+      if let Some(message) = field_error(schema, col_name, &row.fields[col_name])? {
+        res.push(InvalidField {
+          row_index,
+          col_name: col_name.clone(),
+          message,
+        });
       }
     }
   }
-  Ok(())
+  Ok(res)
+}
+
+// Rejects the rows if any field is invalid, describing every invalid row.
+pub fn validate_rows(schema: &Schema, rows: &[Row]) -> ServerResult<()> {
+  let invalid = invalid_fields(schema, rows)?;
+  if invalid.is_empty() {
+    Ok(())
+  } else {
+    Err(invalid_rows_error(&invalid, rows.len()))
+  }
+}
+
+// invalid fields must be in row order
+pub fn invalid_rows_error(invalid_fields: &[InvalidField], n_rows: usize) -> ServerError {
+  ServerError::invalid_rows(row_errors(invalid_fields), n_rows)
+}
+
+// groups invalid fields, which must be in row order, into one error per row
+pub fn row_errors(invalid_fields: &[InvalidField]) -> Vec<RowError> {
+  let mut row_errors: Vec<RowError> = Vec::new();
+  for invalid_field in invalid_fields {
+    match row_errors.last_mut() {
+      Some(row_error) if row_error.row_index == invalid_field.row_index => {
+        row_error.message.push_str(", ");
+        row_error.message.push_str(&invalid_field.message);
+      },
+      _ => row_errors.push(RowError {
+        row_index: invalid_field.row_index,
+        message: invalid_field.message.clone(),
+      }),
+    }
+  }
+  row_errors
+}
+
+// Removes the fields validate_rows would reject, leaving them null, so
+// that the rest of each row can still be written.
+pub fn drop_invalid_fields(schema: &Schema, rows: &mut [Row]) -> ServerResult<Vec<InvalidField>> {
+  let res = invalid_fields(schema, rows)?;
+  for invalid_field in &res {
+    rows[invalid_field.row_index].fields.remove(&invalid_field.col_name);
+  }
+  Ok(res)
 }
 
 pub fn rows_to_staged_bytes(rows: &[Row]) -> ServerResult<Vec<u8>> {
@@ -510,31 +711,58 @@ pub fn rows_to_staged_bytes(rows: &[Row]) -> ServerResult<Vec<u8>> {
 }
 
 pub fn staged_bytes_to_rows(bytes: &[u8]) -> ServerResult<Vec<Row>> {
+  let (rows, n_complete_bytes) = staged_bytes_to_complete_rows(bytes)?;
+  if n_complete_bytes < bytes.len() {
+    return Err(ServerError::internal("corrupt staged bytes; last row is incomplete"));
+  }
+  Ok(rows)
+}
+
+// For staged rows files that a crash may have left with part of a row at
+// the end, returns the complete rows and how many bytes they take up.
+pub fn staged_bytes_to_complete_rows(bytes: &[u8]) -> ServerResult<(Vec<Row>, usize)> {
   let mut res = Vec::new();
   let mut i = 0;
-  while i < bytes.len() {
-    if bytes.len() < i + 4 {
-      return Err(ServerError::internal("corrupt staged bytes; cannot read byte count"));
-    }
-
+  while i + 4 <= bytes.len() {
     let len = u32::from_be_bytes((&bytes[i..i+4]).try_into().unwrap()) as usize;
-    i += 4;
-
-    if bytes.len() < i + len {
-      return Err(ServerError::internal("corrupt staged bytes; cannot read proto bytes"));
+    if bytes.len() < i + 4 + len {
+      break;
     }
-    let mut cursor = Cursor::new(&bytes[i..i + len]);
+
+    let mut cursor = Cursor::new(&bytes[i + 4..i + 4 + len]);
     let row = Row::decode(&mut cursor)
       .map_err(|e| ServerError::internal(e.to_string()).with_context("while parsing staged row bytes"))?;
     res.push(row);
-    i += len;
+    i += 4 + len;
   }
-  Ok(res)
+  Ok((res, i))
 }
 
 // number of rows (deleted or otherwise) in flush files (not compaction or staged)
-pub fn flush_only_n(segment_meta: &SegmentMetadata, compaction: &Compaction) -> u32 {
-  segment_meta.all_time_n - segment_meta.staged_n - compaction.all_time_compacted_n
+pub fn flush_only_n(segment_meta: &SegmentMetadata, compaction: &Compaction) -> ServerResult<u64> {
+  checked_sub_n(segment_meta.flushed_n()?, compaction.all_time_compacted_n, "compacted rows")
+}
+
+// Subtracts a part of a row count from the whole. The counters only
+// increase, so a part exceeding the whole means the metadata is
+// inconsistent, and wrapping around would silently corrupt it further.
+pub fn checked_sub_n(n: u64, part_n: u64, part_description: &str) -> ServerResult<u64> {
+  n.checked_sub(part_n).ok_or_else(|| ServerError::internal(format!(
+    "inconsistent row counts: {} ({}) exceed the total ({}); check_table may diagnose this",
+    part_description,
+    part_n,
+    n,
+  )))
+}
+
+// Row ids are u32s, in deletions and requests alike, as are the row counts
+// of protobuf responses, so a segment's counts must fit in one.
+pub fn checked_u32_n(n: u64) -> ServerResult<u32> {
+  n.try_into().map_err(|_| ServerError::internal(format!(
+    "row count {} exceeds the maximum of {}",
+    n,
+    u32::MAX,
+  )))
 }
 
 pub fn unwrap_metadata<K: MetadataKey, M: PersistentMetadata<K>>(
@@ -549,7 +777,7 @@ pub fn unwrap_metadata<K: MetadataKey, M: PersistentMetadata<K>>(
 
 // returns true if it creates a new file, false if the correct file already exists
 pub async fn assert_file(path: &Path, content: Vec<u8>) -> ServerResult<bool> {
-  match fs::read(path).await {
+  match vfs::read(path).await {
     Ok(bytes) => {
       if bytes == content {
         Ok(false)
@@ -567,7 +795,7 @@ pub async fn assert_file(path: &Path, content: Vec<u8>) -> ServerResult<bool> {
       );
       match e.kind() {
         io::ErrorKind::NotFound => {
-          fs::write(path, &content).await
+          vfs::write(path, &content).await
             .map_err(|e| ServerError::from(e).with_context(context))?;
           Ok(true)
         },
@@ -598,6 +826,13 @@ pub fn check_no_duplicate_names(entity_name: &str, names: Vec<String>) -> Server
   }
 }
 
+pub fn row_id_column_meta() -> ColumnMeta {
+  ColumnMeta {
+    dtype: DataType::Int64 as i32,
+    ..Default::default()
+  }
+}
+
 // return a schema including "DB" columns like _row_id
 pub fn augmented_columns(schema: &Schema) -> HashMap<String, ColumnMeta> {
   let mut res = schema.columns.clone();
@@ -605,10 +840,7 @@ pub fn augmented_columns(schema: &Schema) -> HashMap<String, ColumnMeta> {
   // more appropriate.
   res.insert(
     ROW_ID_COLUMN_NAME.to_string(),
-    ColumnMeta {
-      dtype: DataType::Int64 as i32,
-      ..Default::default()
-    }
+    row_id_column_meta(),
   );
   res.insert(
     WRITTEN_AT_COLUMN_NAME.to_string(),
@@ -627,3 +859,12 @@ pub fn grpc_result<T>(pancake_result: ServerResult<T>) -> Result<tonic::Response
   }
 }
 
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+  if let Some(message) = payload.downcast_ref::<&str>() {
+    message.to_string()
+  } else if let Some(message) = payload.downcast_ref::<String>() {
+    message.clone()
+  } else {
+    "unknown panic".to_string()
+  }
+}
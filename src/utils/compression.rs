@@ -0,0 +1,78 @@
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::errors::{ServerError, ServerResult};
+
+// smaller bodies aren't worth the CPU or the headers
+pub const MIN_COMPRESSED_BYTES: usize = 1024;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentEncoding {
+  Zstd,
+  Gzip,
+}
+
+impl ContentEncoding {
+  pub fn header_value(&self) -> &'static str {
+    match self {
+      ContentEncoding::Zstd => "zstd",
+      ContentEncoding::Gzip => "gzip",
+    }
+  }
+
+  // Picks the encoding an Accept-Encoding header rates highest, preferring
+  // zstd on ties, or none if the client accepts neither.
+  pub fn negotiate(accept_encoding: Option<&str>) -> Option<ContentEncoding> {
+    let mut zstd_quality = None;
+    let mut gzip_quality = None;
+    let mut wildcard_quality = None;
+    for coding in accept_encoding.unwrap_or_default().split(',') {
+      let mut parts = coding.split(';');
+      let name = parts.next().unwrap_or_default().trim().to_lowercase();
+      let quality = parts
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .filter_map(|q| q.trim().parse::<f32>().ok())
+        .next()
+        .unwrap_or(1.0);
+      match name.as_str() {
+        "zstd" => zstd_quality = Some(quality),
+        "gzip" | "x-gzip" => gzip_quality = Some(quality),
+        "*" => wildcard_quality = Some(quality),
+        _ => (),
+      }
+    }
+
+    // q=0 means the client refuses the encoding
+    let zstd_quality = zstd_quality.or(wildcard_quality).unwrap_or(0.0);
+    let gzip_quality = gzip_quality.or(wildcard_quality).unwrap_or(0.0);
+    if zstd_quality <= 0.0 && gzip_quality <= 0.0 {
+      None
+    } else if zstd_quality >= gzip_quality {
+      Some(ContentEncoding::Zstd)
+    } else {
+      Some(ContentEncoding::Gzip)
+    }
+  }
+
+  fn encode(&self, bytes: &[u8]) -> ServerResult<Vec<u8>> {
+    match self {
+      ContentEncoding::Zstd => Ok(zstd::encode_all(bytes, ZSTD_LEVEL)?),
+      ContentEncoding::Gzip => {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes)?;
+        Ok(encoder.finish()?)
+      },
+    }
+  }
+
+  // on a blocking thread, since large bodies take a while
+  pub async fn compress(self, bytes: Vec<u8>) -> ServerResult<Vec<u8>> {
+    tokio::task::spawn_blocking(move || self.encode(&bytes))
+      .await
+      .map_err(|e| ServerError::internal(format!("compression task failed: {}", e)))?
+  }
+}
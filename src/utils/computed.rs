@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use pancake_db_idl::dml::{FieldValue, Row};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::{ColumnMeta, Schema};
+use prost_types::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::common;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputedFunction {
+  Lowercase,
+  Uppercase,
+  TruncateMinute,
+  TruncateHour,
+  TruncateDay,
+}
+
+impl ComputedFunction {
+  const ALL: [ComputedFunction; 5] = [
+    ComputedFunction::Lowercase,
+    ComputedFunction::Uppercase,
+    ComputedFunction::TruncateMinute,
+    ComputedFunction::TruncateHour,
+    ComputedFunction::TruncateDay,
+  ];
+
+  fn name(&self) -> &'static str {
+    match self {
+      ComputedFunction::Lowercase => "lowercase",
+      ComputedFunction::Uppercase => "uppercase",
+      ComputedFunction::TruncateMinute => "truncate_minute",
+      ComputedFunction::TruncateHour => "truncate_hour",
+      ComputedFunction::TruncateDay => "truncate_day",
+    }
+  }
+
+  fn dtype(&self) -> DataType {
+    match self {
+      ComputedFunction::Lowercase | ComputedFunction::Uppercase => DataType::String,
+      _ => DataType::TimestampMicros,
+    }
+  }
+
+  fn apply(&self, value: &Value) -> Value {
+    match (self, value) {
+      (ComputedFunction::Lowercase, Value::StringVal(s)) => Value::StringVal(s.to_lowercase()),
+      (ComputedFunction::Uppercase, Value::StringVal(s)) => Value::StringVal(s.to_uppercase()),
+      (ComputedFunction::TruncateMinute, Value::TimestampVal(t)) => truncate(t, 60),
+      (ComputedFunction::TruncateHour, Value::TimestampVal(t)) => truncate(t, 60 * 60),
+      (ComputedFunction::TruncateDay, Value::TimestampVal(t)) => truncate(t, 24 * 60 * 60),
+      _ => value.clone(),
+    }
+  }
+}
+
+fn truncate(timestamp: &Timestamp, seconds: i64) -> Value {
+  Value::TimestampVal(Timestamp {
+    seconds: timestamp.seconds - timestamp.seconds.rem_euclid(seconds),
+    nanos: 0,
+  })
+}
+
+// A column whose values are derived from another column of the same row
+// rather than written by clients. Flushes materialize its values from the
+// staged rows, and reads of staged rows compute them on the fly.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComputedColumn {
+  pub function: ComputedFunction,
+  // the name the source column is stored under
+  pub source_column: String,
+}
+
+impl ComputedColumn {
+  // parses an expression like lowercase(s) or truncate_hour(ts)
+  pub fn parse(expression: &str) -> ServerResult<Self> {
+    let invalid = || ServerError::invalid(format!(
+      "computed column expression \"{}\" must look like function(column) with a function in {}",
+      expression,
+      ComputedFunction::ALL.iter().map(|f| f.name()).collect::<Vec<_>>().join(", "),
+    ));
+    let expression = expression.trim();
+    let (function_name, rest) = expression.split_once('(').ok_or_else(invalid)?;
+    let source_column = rest.strip_suffix(')').ok_or_else(invalid)?.trim();
+    let function = ComputedFunction::ALL.iter()
+      .find(|f| f.name() == function_name.trim().to_lowercase())
+      .ok_or_else(invalid)?;
+    if source_column.is_empty() {
+      return Err(invalid());
+    }
+    Ok(ComputedColumn {
+      function: *function,
+      source_column: source_column.to_string(),
+    })
+  }
+
+  pub fn expression(&self) -> String {
+    format!("{}({})", self.function.name(), self.source_column)
+  }
+
+  pub fn validate(&self, col_name: &str, col_meta: &ColumnMeta, source_meta: &ColumnMeta) -> ServerResult<()> {
+    let dtype = self.function.dtype();
+    let is_valid = |meta: &ColumnMeta| meta.dtype == dtype as i32 && meta.nested_list_depth == 0;
+    if !is_valid(col_meta) || !is_valid(source_meta) {
+      return Err(ServerError::invalid(format!(
+        "computed column {} and its source column {} must both be non-nested {:?} columns",
+        col_name,
+        self.source_column,
+        dtype,
+      )));
+    }
+    Ok(())
+  }
+
+  pub fn compute(&self, source_value: &FieldValue) -> FieldValue {
+    FieldValue {
+      value: source_value.value.as_ref().map(|value| self.function.apply(value)),
+    }
+  }
+}
+
+// Parses and validates computed columns declared by name and expression
+// against a schema, returning them keyed by column name.
+pub fn parse_computed_columns(
+  expressions: &HashMap<String, String>,
+  schema: &Schema,
+) -> ServerResult<HashMap<String, ComputedColumn>> {
+  let columns = &schema.columns;
+  let augmented_columns = common::augmented_columns(schema);
+  let mut res = HashMap::with_capacity(expressions.len());
+  for (col_name, expression) in expressions {
+    let col_meta = columns.get(col_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", col_name))?;
+    let computed = ComputedColumn::parse(expression)?;
+    if expressions.contains_key(&computed.source_column) {
+      return Err(ServerError::invalid(format!(
+        "computed column {} can't be computed from another computed column",
+        col_name,
+      )));
+    }
+    let source_meta = augmented_columns.get(&computed.source_column)
+      .ok_or_else(|| ServerError::does_not_exist("column", &computed.source_column))?;
+    computed.validate(col_name, col_meta, source_meta)?;
+    res.insert(col_name.clone(), computed);
+  }
+  Ok(res)
+}
+
+// overwrites every computed column's value with one computed from its source
+pub fn fill_rows(computed_columns: &HashMap<String, ComputedColumn>, rows: &mut [Row]) {
+  if computed_columns.is_empty() {
+    return;
+  }
+  for row in rows {
+    for (col_name, computed) in computed_columns {
+      let value = computed.compute(&row.fields.get(&computed.source_column).cloned().unwrap_or_default());
+      if value.value.is_some() {
+        row.fields.insert(col_name.clone(), value);
+      } else {
+        row.fields.remove(col_name);
+      }
+    }
+  }
+}
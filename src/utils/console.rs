@@ -0,0 +1,23 @@
+use warp::{Filter, Rejection, Reply};
+use warp::http::header::CONTENT_TYPE;
+
+// A minimal operator console, compiled into the binary so that it is
+// always available alongside the REST API it calls.
+const INDEX_HTML: &str = include_str!("../../assets/console/index.html");
+const CONSOLE_JS: &str = include_str!("../../assets/console/console.js");
+const CONSOLE_CSS: &str = include_str!("../../assets/console/console.css");
+
+pub fn warp_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  let index = warp::path::end()
+    .map(|| warp::reply::html(INDEX_HTML));
+  let js = warp::path("console.js")
+    .and(warp::path::end())
+    .map(|| warp::reply::with_header(CONSOLE_JS, CONTENT_TYPE, "application/javascript"));
+  let css = warp::path("console.css")
+    .and(warp::path::end())
+    .map(|| warp::reply::with_header(CONSOLE_CSS, CONTENT_TYPE, "text/css"));
+
+  warp::get()
+    .and(warp::path("console"))
+    .and(index.or(js).or(css))
+}
@@ -1,8 +1,69 @@
+use pancake_db_idl::dml::FieldValue;
 use pancake_db_idl::dtype::DataType;
 
-use pancake_db_core::encoding;
-
 use crate::errors::{ServerError, ServerResult};
+use crate::utils::safe_decoding;
+use crate::utils::zone_map::ZoneMapBlock;
+
+// A sparse index from row index to byte index in a flush file, with an
+// entry where each block its zone map lists starts. Rows past the last
+// block are found by decoding from its end to the end of the file.
+#[derive(Clone, Debug, Default)]
+pub struct SeekIndex {
+  // row index and byte index of each entry, ascending
+  entries: Vec<(usize, u64)>,
+  flush_byte_len: u64,
+}
+
+// A byte range of a flush file and the row index it starts at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeekSpan {
+  pub row_idx: usize,
+  pub start: u64,
+  pub end: u64,
+}
+
+impl SeekSpan {
+  pub fn byte_len(&self) -> usize {
+    (self.end - self.start) as usize
+  }
+}
+
+impl SeekIndex {
+  // blocks must all end within the flush file, as from zone_map::parse_prefix
+  pub fn new(blocks: &[ZoneMapBlock], flush_byte_len: u64) -> Self {
+    let mut entries = Vec::with_capacity(blocks.len() + 1);
+    let mut row_idx = 0;
+    let mut byte_idx = 0;
+    entries.push((row_idx, byte_idx));
+    for block in blocks {
+      row_idx += block.n_rows as usize;
+      byte_idx += block.byte_len as u64;
+      entries.push((row_idx, byte_idx));
+    }
+    SeekIndex {
+      entries,
+      flush_byte_len,
+    }
+  }
+
+  pub fn flush_byte_len(&self) -> u64 {
+    self.flush_byte_len
+  }
+
+  // the span from the last entry at or before the row to the next one
+  pub fn span_for_row_idx(&self, row_idx: usize) -> SeekSpan {
+    let entry_idx = self.entries.partition_point(|&(entry_row_idx, _)| entry_row_idx <= row_idx);
+    let (span_row_idx, start) = self.entries[entry_idx - 1];
+    SeekSpan {
+      row_idx: span_row_idx,
+      start,
+      end: self.entries.get(entry_idx)
+        .map(|&(_, end)| end)
+        .unwrap_or(self.flush_byte_len),
+    }
+  }
+}
 
 pub fn byte_idx_for_row_idx(
   dtype: DataType,
@@ -10,8 +71,7 @@ pub fn byte_idx_for_row_idx(
   bytes: &[u8],
   idx: usize,
 ) -> ServerResult<usize> {
-  let decoder = encoding::new_byte_idx_decoder(dtype, nested_list_depth);
-  let byte_idxs = decoder.decode_limited(bytes, idx)?;
+  let byte_idxs = safe_decoding::decode_byte_idxs(dtype, nested_list_depth, bytes, idx)?;
   if byte_idxs.len() != idx {
     return Err(ServerError::internal(format!(
       "expected at least {} rows in flush file but found {}",
@@ -22,3 +82,32 @@ pub fn byte_idx_for_row_idx(
 
   Ok(byte_idxs.last().copied().unwrap_or(0))
 }
+
+// Decodes the values at strictly increasing row indices, seeking past the
+// rows between them without decoding their values.
+pub fn decode_at_row_idxs(
+  dtype: DataType,
+  nested_list_depth: u8,
+  bytes: &[u8],
+  row_idxs: &[usize],
+) -> ServerResult<Vec<FieldValue>> {
+  let mut res = Vec::with_capacity(row_idxs.len());
+  let mut byte_idx = 0;
+  let mut next_row_idx = 0;
+  for &row_idx in row_idxs {
+    if row_idx < next_row_idx {
+      return Err(ServerError::internal("row indices to decode must be strictly increasing"));
+    }
+    byte_idx += byte_idx_for_row_idx(dtype, nested_list_depth, &bytes[byte_idx..], row_idx - next_row_idx)?;
+    let value = safe_decoding::decode_limited(dtype, nested_list_depth, &bytes[byte_idx..], 1)?
+      .pop()
+      .ok_or_else(|| ServerError::internal(format!(
+        "expected row {} in flush file but found none",
+        row_idx,
+      )))?;
+    byte_idx += byte_idx_for_row_idx(dtype, nested_list_depth, &bytes[byte_idx..], 1)?;
+    next_row_idx = row_idx + 1;
+    res.push(value);
+  }
+  Ok(res)
+}
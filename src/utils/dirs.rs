@@ -1,7 +1,112 @@
+use std::hash::Hasher;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use twox_hash::XxHash64;
 
 use crate::types::{CompactionKey, PartitionKey, SegmentKey};
-use crate::constants::DATA_SUBDIR;
+use crate::constants::{DATA_SUBDIR, GARBAGE_SEGMENT_PREFIX};
+
+// how a file in a segment dir can change once written
+pub enum FileKind {
+  // flush column files, zone maps, and deletion logs
+  AppendOnly,
+  // compacted column files and increments, bloom filters, and deletion files
+  Immutable,
+  // metadata and staged rows
+  Mutable,
+}
+
+impl FileKind {
+  pub fn of(file_name: &str) -> Self {
+    if file_name.starts_with("f_") || file_name.starts_with("z_") || file_name == "deletion_log" {
+      FileKind::AppendOnly
+    } else if file_name.starts_with("c_") || file_name.starts_with('i') ||
+      file_name.starts_with("b_") || file_name.ends_with(".qco") {
+      FileKind::Immutable
+    } else {
+      FileKind::Mutable
+    }
+  }
+}
+
+// With a fanout above 0, each partition field's dir sits in a bucket dir
+// picked by hashing its name, so no dir holds many more than fanout of
+// them. Like the memory mounts, it applies to the whole process; recovery
+// sets it once the dir has been migrated to it.
+static PARTITION_DIR_FANOUT: AtomicU32 = AtomicU32::new(0);
+
+const PARTITION_BUCKET_PREFIX: &str = "_p";
+
+pub fn set_partition_dir_fanout(fanout: u32) {
+  PARTITION_DIR_FANOUT.store(fanout, Ordering::Relaxed);
+}
+
+pub fn partition_dir_fanout() -> u32 {
+  PARTITION_DIR_FANOUT.load(Ordering::Relaxed)
+}
+
+// field dir names always contain an =, so can't collide with these
+pub fn partition_bucket_name(field_dir_name: &str, fanout: u32) -> String {
+  let mut hasher = XxHash64::with_seed(0);
+  hasher.write(field_dir_name.as_bytes());
+  format!("{}{:x}", PARTITION_BUCKET_PREFIX, hasher.finish() % fanout as u64)
+}
+
+pub fn is_partition_bucket_name(name: &str) -> bool {
+  name.starts_with(PARTITION_BUCKET_PREFIX)
+}
+
+// the path of a partition below its table's data dir, from the names of
+// its field dirs
+pub fn bucketed_partition_path<S: AsRef<str>>(field_dir_names: &[S], fanout: u32) -> PathBuf {
+  let mut res = PathBuf::new();
+  for field_dir_name in field_dir_names {
+    let field_dir_name = field_dir_name.as_ref();
+    if fanout > 0 {
+      res.push(partition_bucket_name(field_dir_name, fanout));
+    }
+    res.push(field_dir_name);
+  }
+  res
+}
+
+// where overwrite_file_atomic stages files before renaming them into place
+pub fn tmp_dir(dir: &Path) -> PathBuf {
+  dir.join("tmp")
+}
+
+// table names can't start with an underscore, so this can't collide with a table
+pub fn manifest_dir(dir: &Path) -> PathBuf {
+  dir.join("_manifest")
+}
+
+// committed transactions whose rows may not all be appended yet
+pub fn transaction_dir(dir: &Path) -> PathBuf {
+  dir.join("_transactions")
+}
+
+// JSON lines of DDL and deletion ops, appended as they happen
+pub fn audit_log_path(dir: &Path) -> PathBuf {
+  dir.join("_audit_log")
+}
+
+// where copies of tables are assembled before being renamed into place,
+// laid out like dir itself
+pub fn table_copy_staging_dir(dir: &Path) -> PathBuf {
+  dir.join("_copying")
+}
+
+// dropped tables, until their retention window passes
+pub fn trash_dir(dir: &Path) -> PathBuf {
+  dir.join("_trash")
+}
+
+// table names can't contain a dot, so the name and drop time can be told
+// apart
+pub fn trashed_table_dir(dir: &Path, table_name: &str, dropped_at_millis: i64) -> PathBuf {
+  trash_dir(dir).join(format!("{}.{}", table_name, dropped_at_millis))
+}
 
 pub fn relative_table_dir(table_name: &str) -> PathBuf {
   PathBuf::from(table_name)
@@ -23,17 +128,62 @@ pub fn flush_col_file(dir: &Path, compaction_key: &CompactionKey, col_name: &str
   version_dir(dir, compaction_key).join(format!("f_{}", col_name))
 }
 
+pub fn zone_map_file(dir: &Path, compaction_key: &CompactionKey, col_name: &str) -> PathBuf {
+  version_dir(dir, compaction_key).join(format!("z_{}", col_name))
+}
+
 pub fn compact_col_file(dir: &Path, compaction_key: &CompactionKey, col_name: &str) -> PathBuf {
   version_dir(dir, compaction_key).join(format!("c_{}", col_name))
 }
 
+// the rows of a column compacted by its version's increment-th incremental
+// compaction, following the base compact_col_file
+pub fn compact_increment_col_file(
+  dir: &Path,
+  compaction_key: &CompactionKey,
+  col_name: &str,
+  increment: usize,
+) -> PathBuf {
+  version_dir(dir, compaction_key).join(format!("i{}_{}", increment, col_name))
+}
+
+pub fn bloom_filter_file(dir: &Path, compaction_key: &CompactionKey, col_name: &str) -> PathBuf {
+  version_dir(dir, compaction_key).join(format!("b_{}", col_name))
+}
+
+// where a compaction writes its column files and bloom filters, moving them
+// into the version dir only once all are written
+pub fn compaction_staging_dir(dir: &Path, compaction_key: &CompactionKey) -> PathBuf {
+  version_dir(dir, compaction_key).join("_staging")
+}
+
+pub fn staged_compact_col_file(dir: &Path, compaction_key: &CompactionKey, col_name: &str) -> PathBuf {
+  compaction_staging_dir(dir, compaction_key).join(format!("c_{}", col_name))
+}
+
+pub fn staged_compact_increment_col_file(
+  dir: &Path,
+  compaction_key: &CompactionKey,
+  col_name: &str,
+  increment: usize,
+) -> PathBuf {
+  compaction_staging_dir(dir, compaction_key).join(format!("i{}_{}", increment, col_name))
+}
+
+pub fn staged_bloom_filter_file(dir: &Path, compaction_key: &CompactionKey, col_name: &str) -> PathBuf {
+  compaction_staging_dir(dir, compaction_key).join(format!("b_{}", col_name))
+}
+
 pub fn partition_dir(dir: &Path, table_partition: &PartitionKey) -> PathBuf {
   dir.join(relative_partition_dir(table_partition))
 }
 
 pub fn relative_partition_dir(table_partition: &PartitionKey) -> PathBuf {
   relative_table_data_dir(&table_partition.table_name)
-    .join(&table_partition.partition.to_path_buf())
+    .join(bucketed_partition_path(
+      &table_partition.partition.field_dir_names(),
+      partition_dir_fanout(),
+    ))
 }
 
 pub fn relative_segment_dir(segment_key: &SegmentKey) -> PathBuf {
@@ -45,6 +195,13 @@ pub fn segment_dir(dir: &Path, segment_key: &SegmentKey) -> PathBuf {
   dir.join(relative_segment_dir(segment_key))
 }
 
+// where a segment directory gets moved right before being garbage collected,
+// so that it is no longer listed as a segment even if removal is interrupted
+pub fn garbage_segment_dir(dir: &Path, segment_key: &SegmentKey) -> PathBuf {
+  partition_dir(dir, &segment_key.partition_key())
+    .join(format!("{}{}", GARBAGE_SEGMENT_PREFIX, &segment_key.segment_id))
+}
+
 pub fn relative_version_dir(compaction_key: &CompactionKey) -> PathBuf {
   relative_segment_dir(&compaction_key.segment_key())
     .join(format!("v{}", compaction_key.version))
@@ -54,8 +211,17 @@ pub fn version_dir(dir: &Path, compaction_key: &CompactionKey) -> PathBuf {
   dir.join(relative_version_dir(compaction_key))
 }
 
+pub fn relative_staged_rows_path(segment_key: &SegmentKey) -> PathBuf {
+  relative_segment_dir(segment_key).join("staged_rows")
+}
+
 pub fn staged_rows_path(dir: &Path, segment_key: &SegmentKey) -> PathBuf {
-  segment_dir(dir, segment_key).join("staged_rows")
+  dir.join(relative_staged_rows_path(segment_key))
+}
+
+// row ids in the order they were deleted, for change data capture
+pub fn deletion_log_path(dir: &Path, segment_key: &SegmentKey) -> PathBuf {
+  segment_dir(dir, segment_key).join("deletion_log")
 }
 
 pub fn pre_compaction_deletions_path(
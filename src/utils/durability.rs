@@ -0,0 +1,100 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use tokio::time::Duration;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::vfs;
+
+// How hard the common file helpers try to get writes onto disk before
+// returning. By default they leave that to the OS, so a write the server
+// acknowledged can be lost if the machine (not just the server) goes down
+// before the page cache is written back. With --fsync-policy=always, every
+// write is fsynced before it returns, and so is the dir of every atomic
+// overwrite's rename. With --fsync-policy=interval, the files and dirs
+// written are instead fsynced in batches every --fsync-interval-millis,
+// bounding how much can be lost to that interval. Like the partition dir
+// fanout, it applies to the whole process.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsyncPolicy {
+  Never,
+  Interval,
+  Always,
+}
+
+impl FromStr for FsyncPolicy {
+  type Err = ServerError;
+
+  fn from_str(s: &str) -> ServerResult<Self> {
+    match s.to_lowercase().as_str() {
+      "never" => Ok(FsyncPolicy::Never),
+      "interval" => Ok(FsyncPolicy::Interval),
+      "always" => Ok(FsyncPolicy::Always),
+      invalid => Err(ServerError::invalid(format!(
+        "invalid fsync policy {}",
+        invalid,
+      ))),
+    }
+  }
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(0);
+static INTERVAL_MILLIS: AtomicU64 = AtomicU64::new(1000);
+// files and dirs written since the last interval sync
+static UNSYNCED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+pub fn set_policy(policy: FsyncPolicy, interval: Duration) {
+  POLICY.store(policy as u8, Ordering::Relaxed);
+  INTERVAL_MILLIS.store(interval.as_millis().max(1) as u64, Ordering::Relaxed);
+}
+
+pub fn policy() -> FsyncPolicy {
+  match POLICY.load(Ordering::Relaxed) {
+    1 => FsyncPolicy::Interval,
+    2 => FsyncPolicy::Always,
+    _ => FsyncPolicy::Never,
+  }
+}
+
+// a file or dir that was written to, or a dir whose entries changed
+pub async fn written(path: &Path) -> io::Result<()> {
+  match policy() {
+    FsyncPolicy::Never => Ok(()),
+    FsyncPolicy::Interval => {
+      UNSYNCED.lock().unwrap().insert(path.to_path_buf());
+      Ok(())
+    },
+    FsyncPolicy::Always => vfs::sync(path).await,
+  }
+}
+
+// Fsyncs everything written since the last call. Paths removed since
+// being written have nothing left to sync.
+pub async fn sync_unsynced() -> io::Result<usize> {
+  let paths = std::mem::take(&mut *UNSYNCED.lock().unwrap());
+  for path in &paths {
+    match vfs::sync(path).await {
+      Ok(()) => (),
+      Err(e) if matches!(e.kind(), ErrorKind::NotFound) => (),
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(paths.len())
+}
+
+// runs for as long as the policy stays interval
+pub async fn sync_forever() {
+  while policy() == FsyncPolicy::Interval {
+    tokio::time::sleep(Duration::from_millis(INTERVAL_MILLIS.load(Ordering::Relaxed))).await;
+    match sync_unsynced().await {
+      Ok(n_synced) if n_synced > 0 => log::debug!("fsynced {} written paths", n_synced),
+      Ok(_) => (),
+      Err(e) => log::error!("fsyncing written paths failed: {}", e),
+    }
+  }
+}
@@ -0,0 +1,144 @@
+use std::convert::TryInto;
+use std::path::Path;
+
+use ring::aead::{Aad, AES_256_GCM, LessSafeKey, Nonce, NONCE_LEN, UnboundKey};
+
+use crate::errors::{ServerError, ServerResult};
+
+pub const KEY_LEN: usize = 32;
+const MAGIC: &[u8] = b"PANCAKE_ENC_V1";
+const TAG_LEN: usize = 16;
+// each frame starts with its plaintext length and nonce
+pub const FRAME_HEADER_LEN: usize = 4 + NONCE_LEN;
+const MAX_FRAME_PLAINTEXT_LEN: usize = 1 << 16;
+
+// An encrypted file is a header naming its key, then frames of
+// [plaintext len: u32][nonce][ciphertext][tag]. Each frame authenticates
+// its plaintext offset, so frames can't be reordered or moved between
+// positions without failing to decrypt.
+pub struct Cipher {
+  key_id: String,
+  key: LessSafeKey,
+}
+
+pub fn validate_key_id(key_id: &str) -> ServerResult<()> {
+  let is_valid = !key_id.is_empty() &&
+    key_id.len() <= 64 &&
+    key_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+  if is_valid {
+    Ok(())
+  } else {
+    Err(ServerError::invalid(format!(
+      "encryption key id {} must be 1-64 ASCII letters, digits, or underscores",
+      key_id,
+    )))
+  }
+}
+
+fn offset_aad(plaintext_offset: u64) -> Aad<[u8; 8]> {
+  Aad::from(plaintext_offset.to_be_bytes())
+}
+
+impl Cipher {
+  pub fn new(key_id: &str, key_bytes: &[u8]) -> ServerResult<Self> {
+    if key_bytes.len() != KEY_LEN {
+      return Err(ServerError::invalid(format!(
+        "encryption key {} has {} bytes but must have {}",
+        key_id,
+        key_bytes.len(),
+        KEY_LEN,
+      )));
+    }
+    let unbound = UnboundKey::new(&AES_256_GCM, key_bytes)
+      .map_err(|_| ServerError::internal("could not create encryption key"))?;
+    Ok(Cipher {
+      key_id: key_id.to_string(),
+      key: LessSafeKey::new(unbound),
+    })
+  }
+
+  pub fn file_header(&self) -> Vec<u8> {
+    let mut res = MAGIC.to_vec();
+    res.push(self.key_id.len() as u8);
+    res.extend(self.key_id.as_bytes());
+    res
+  }
+
+  pub fn file_header_len(&self) -> usize {
+    MAGIC.len() + 1 + self.key_id.len()
+  }
+
+  pub fn check_file_header(&self, bytes: &[u8], path: &Path) -> ServerResult<()> {
+    if bytes == self.file_header() {
+      return Ok(());
+    }
+    let file_key_id = bytes.strip_prefix(MAGIC)
+      .and_then(|rest| rest.get(1..))
+      .map(String::from_utf8_lossy);
+    match file_key_id {
+      Some(file_key_id) => Err(ServerError::corrupt(format!(
+        "{:?} is encrypted with key {} but its version's metadata says {}",
+        path,
+        file_key_id,
+        self.key_id,
+      ))),
+      None => Err(ServerError::corrupt(format!(
+        "{:?} is not encrypted but its version's metadata says it uses key {}",
+        path,
+        self.key_id,
+      ))),
+    }
+  }
+
+  // the bytes of a frame after its header
+  pub fn frame_body_len(plaintext_len: usize) -> usize {
+    plaintext_len + TAG_LEN
+  }
+
+  pub fn frame_plaintext_len(frame_header: &[u8]) -> usize {
+    u32::from_be_bytes(frame_header[..4].try_into().unwrap()) as usize
+  }
+
+  // encrypts plaintext starting at a plaintext offset into whole frames
+  pub fn encrypt(&self, plaintext: &[u8], plaintext_offset: u64) -> ServerResult<Vec<u8>> {
+    let n_frames = plaintext.len().div_ceil(MAX_FRAME_PLAINTEXT_LEN);
+    let mut res = Vec::with_capacity(plaintext.len() + n_frames * (FRAME_HEADER_LEN + TAG_LEN));
+    let mut offset = plaintext_offset;
+    for chunk in plaintext.chunks(MAX_FRAME_PLAINTEXT_LEN) {
+      let nonce_bytes = rand::random::<[u8; NONCE_LEN]>();
+      let mut in_out = chunk.to_vec();
+      self.key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce_bytes),
+        offset_aad(offset),
+        &mut in_out,
+      ).map_err(|_| ServerError::internal("could not encrypt column data"))?;
+      res.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+      res.extend_from_slice(&nonce_bytes);
+      res.extend(in_out);
+      offset += chunk.len() as u64;
+    }
+    Ok(res)
+  }
+
+  // decrypts one frame, given its header and body
+  pub fn decrypt_frame(
+    &self,
+    frame_header: &[u8],
+    mut body: Vec<u8>,
+    plaintext_offset: u64,
+    path: &Path,
+  ) -> ServerResult<Vec<u8>> {
+    let nonce = Nonce::try_assume_unique_for_key(&frame_header[4..FRAME_HEADER_LEN])
+      .map_err(|_| ServerError::internal("invalid nonce length"))?;
+    let plaintext_len = self.key.open_in_place(nonce, offset_aad(plaintext_offset), &mut body)
+      .map_err(|_| ServerError::corrupt(format!(
+        "could not decrypt {:?} at offset {} with key {}",
+        path,
+        plaintext_offset,
+        self.key_id,
+      )))?
+      .len();
+    body.truncate(plaintext_len);
+    Ok(body)
+  }
+}
@@ -0,0 +1,100 @@
+use std::convert::TryFrom;
+
+use pancake_db_idl::dml::FieldValue;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::checksum;
+
+// 2^11 registers gives a standard error of about 2.3% while keeping each
+// sketch small enough to store in compaction metadata.
+const PRECISION: u32 = 11;
+const N_REGISTERS: usize = 1 << PRECISION;
+
+// A HyperLogLog sketch of the distinct non-null values in a column. Sketches
+// serialize as base 64 strings of their registers, and merging two sketches
+// gives the sketch of the union of their values.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct HyperLogLog {
+  registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+  fn default() -> Self {
+    HyperLogLog {
+      registers: vec![0; N_REGISTERS],
+    }
+  }
+}
+
+impl HyperLogLog {
+  pub fn from_values(values: &[FieldValue]) -> Self {
+    let mut res = Self::default();
+    for value in values {
+      res.insert(value);
+    }
+    res
+  }
+
+  pub fn insert(&mut self, value: &FieldValue) {
+    if value.value.is_none() {
+      return;
+    }
+
+    let hash = checksum::xxhash64(&value.encode_to_vec());
+    let idx = (hash >> (64 - PRECISION)) as usize;
+    let rest = hash << PRECISION;
+    let rank = (rest.leading_zeros() + 1).min(64 - PRECISION + 1) as u8;
+    if rank > self.registers[idx] {
+      self.registers[idx] = rank;
+    }
+  }
+
+  pub fn merge(&mut self, other: &HyperLogLog) {
+    for (register, &other_register) in self.registers.iter_mut().zip(&other.registers) {
+      *register = (*register).max(other_register);
+    }
+  }
+
+  pub fn estimate(&self) -> u64 {
+    let m = N_REGISTERS as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let harmonic_sum: f64 = self.registers.iter()
+      .map(|&register| 2.0_f64.powi(-(register as i32)))
+      .sum();
+    let raw = alpha * m * m / harmonic_sum;
+
+    // small cardinalities are more accurate by linear counting
+    let n_zeros = self.registers.iter().filter(|&&register| register == 0).count();
+    if raw <= 2.5 * m && n_zeros > 0 {
+      (m * (m / n_zeros as f64).ln()).round() as u64
+    } else {
+      raw.round() as u64
+    }
+  }
+}
+
+impl From<HyperLogLog> for String {
+  fn from(hll: HyperLogLog) -> String {
+    base64::encode(&hll.registers)
+  }
+}
+
+impl TryFrom<String> for HyperLogLog {
+  type Error = ServerError;
+
+  fn try_from(s: String) -> ServerResult<HyperLogLog> {
+    let registers = base64::decode(&s)
+      .map_err(|e| ServerError::corrupt(format!("unable to decode sketch: {}", e)))?;
+    if registers.len() != N_REGISTERS {
+      return Err(ServerError::corrupt(format!(
+        "sketch has {} registers instead of {}",
+        registers.len(),
+        N_REGISTERS,
+      )));
+    }
+    Ok(HyperLogLog { registers })
+  }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use pancake_db_idl::dml::{FieldValue, RepeatedFieldValue};
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::schema::ColumnMeta;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::utils::common;
+
+const REDACTED_CHAR: char = '*';
+
+// How a sensitive column's values are shown to principals who may read the
+// table but not unmask it. Masks apply to each element of nested lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum MaskingRule {
+  // Hex SHA-256 of strings, or the raw digest of bytes. Unsalted, so equal
+  // values still mask to equal hashes and can be joined or counted.
+  Hash,
+  // replaces every character but the first and last few with *
+  Redact {
+    keep_first: usize,
+    keep_last: usize,
+  },
+  Null,
+}
+
+impl MaskingRule {
+  // parses an expression like hash, null, or redact(0, 4)
+  pub fn parse(expression: &str) -> ServerResult<Self> {
+    let invalid = || ServerError::invalid(format!(
+      "masking rule \"{}\" must be hash, null, redact, or redact(keep_first, keep_last)",
+      expression,
+    ));
+    let expression = expression.trim().to_lowercase();
+    match expression.as_str() {
+      "hash" => return Ok(MaskingRule::Hash),
+      "null" => return Ok(MaskingRule::Null),
+      "redact" => return Ok(MaskingRule::Redact { keep_first: 0, keep_last: 0 }),
+      _ => (),
+    }
+    let args = expression.strip_prefix("redact")
+      .map(|rest| rest.trim_start())
+      .and_then(|rest| rest.strip_prefix('('))
+      .and_then(|rest| rest.strip_suffix(')'))
+      .ok_or_else(invalid)?;
+    let (keep_first, keep_last) = args.split_once(',').ok_or_else(invalid)?;
+    Ok(MaskingRule::Redact {
+      keep_first: keep_first.trim().parse().map_err(|_| invalid())?,
+      keep_last: keep_last.trim().parse().map_err(|_| invalid())?,
+    })
+  }
+
+  pub fn expression(&self) -> String {
+    match self {
+      MaskingRule::Hash => "hash".to_string(),
+      MaskingRule::Redact { keep_first, keep_last } => format!("redact({}, {})", keep_first, keep_last),
+      MaskingRule::Null => "null".to_string(),
+    }
+  }
+
+  pub fn validate(&self, col_name: &str, col_meta: &ColumnMeta) -> ServerResult<()> {
+    let dtype = common::unwrap_dtype(col_meta.dtype)?;
+    let is_valid = match self {
+      MaskingRule::Hash => matches!(dtype, DataType::String | DataType::Bytes),
+      MaskingRule::Redact { .. } => matches!(dtype, DataType::String),
+      MaskingRule::Null => true,
+    };
+    if !is_valid {
+      return Err(ServerError::invalid(format!(
+        "masking rule {} does not apply to column {} of type {:?}",
+        self.expression(),
+        col_name,
+        dtype,
+      )));
+    }
+    Ok(())
+  }
+
+  fn apply(&self, value: &Value) -> Option<Value> {
+    match (self, value) {
+      (MaskingRule::Null, _) => None,
+      (_, Value::ListVal(RepeatedFieldValue { vals })) => Some(Value::ListVal(RepeatedFieldValue {
+        vals: vals.iter().map(|val| self.mask(val)).collect(),
+      })),
+      (MaskingRule::Hash, Value::StringVal(s)) => Some(Value::StringVal(
+        digest::digest(&digest::SHA256, s.as_bytes()).as_ref().iter()
+          .map(|b| format!("{:02x}", b))
+          .collect()
+      )),
+      (MaskingRule::Hash, Value::BytesVal(b)) => Some(Value::BytesVal(
+        digest::digest(&digest::SHA256, b).as_ref().to_vec()
+      )),
+      (MaskingRule::Redact { keep_first, keep_last }, Value::StringVal(s)) => Some(Value::StringVal(
+        redact(s, *keep_first, *keep_last)
+      )),
+      // validation keeps rules off columns they don't apply to
+      _ => None,
+    }
+  }
+
+  pub fn mask(&self, value: &FieldValue) -> FieldValue {
+    FieldValue {
+      value: value.value.as_ref().and_then(|value| self.apply(value)),
+    }
+  }
+}
+
+// Strings too short to keep both ends are redacted entirely, so the kept
+// characters never add up to the whole value.
+fn redact(s: &str, keep_first: usize, keep_last: usize) -> String {
+  let n_chars = s.chars().count();
+  if n_chars <= keep_first + keep_last {
+    return REDACTED_CHAR.to_string().repeat(n_chars);
+  }
+  s.chars()
+    .enumerate()
+    .map(|(i, c)| if i < keep_first || i >= n_chars - keep_last { c } else { REDACTED_CHAR })
+    .collect()
+}
+
+// Parses and validates masking rules declared by column name and expression
+// against a table's columns, returning them keyed by column name.
+pub fn parse_column_masks(
+  expressions: &HashMap<String, String>,
+  columns: &HashMap<String, ColumnMeta>,
+) -> ServerResult<HashMap<String, MaskingRule>> {
+  let mut res = HashMap::with_capacity(expressions.len());
+  for (col_name, expression) in expressions {
+    let col_meta = columns.get(col_name)
+      .ok_or_else(|| ServerError::does_not_exist("column", col_name))?;
+    let rule = MaskingRule::parse(expression)?;
+    rule.validate(col_name, col_meta)?;
+    res.insert(col_name.clone(), rule);
+  }
+  Ok(res)
+}
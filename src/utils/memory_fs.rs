@@ -0,0 +1,427 @@
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// An in-memory tree of dirs and files standing in for everything under one
+// root dir, for servers that shouldn't touch disk, like those in tests.
+// Errors use the same kinds as the real filesystem's, since callers match
+// on NotFound and AlreadyExists.
+pub struct MemoryFs {
+  nodes: Mutex<BTreeMap<PathBuf, Node>>,
+  mutations: Arc<Mutex<Mutations>>,
+}
+
+// What happens to the mutation a fault is injected into. Either way, the
+// process is considered crashed from then on.
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+  // the mutation fails without changing anything
+  Fail,
+  // writes leave only the first half of their bytes, then fail; other
+  // mutations fail without changing anything
+  Tear,
+}
+
+#[derive(Default)]
+struct Mutations {
+  n: u64,
+  fault: Option<(u64, Fault)>,
+  crashed: bool,
+}
+
+impl Mutations {
+  // Counts a mutation, returning whether to tear it, or failing if it or
+  // an earlier one crashed.
+  fn begin(mutations: &Mutex<Mutations>, path: &Path) -> io::Result<bool> {
+    let mut mutations = mutations.lock().unwrap();
+    if mutations.crashed {
+      return Err(other(format!("crashed by an earlier injected fault before mutating {:?}", path)));
+    }
+    mutations.n += 1;
+    match mutations.fault {
+      Some((n, fault)) if n == mutations.n => {
+        mutations.crashed = true;
+        match fault {
+          Fault::Fail => Err(other(format!("injected fault into mutation {} of {:?}", n, path))),
+          Fault::Tear => Ok(true),
+        }
+      },
+      _ => Ok(false),
+    }
+  }
+}
+
+fn torn(contents: &[u8]) -> &[u8] {
+  &contents[..contents.len() / 2]
+}
+
+fn tear_error(path: &Path) -> io::Error {
+  other(format!("injected fault tore a write to {:?}", path))
+}
+
+#[derive(Clone)]
+enum Node {
+  Dir(SystemTime),
+  File(Arc<Mutex<FileData>>),
+}
+
+struct FileData {
+  bytes: Vec<u8>,
+  modified: SystemTime,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct NodeMetadata {
+  pub is_dir: bool,
+  pub len: u64,
+  pub modified: SystemTime,
+}
+
+fn not_found(path: &Path) -> io::Error {
+  io::Error::new(ErrorKind::NotFound, format!("{:?} does not exist in memory", path))
+}
+
+fn already_exists(path: &Path) -> io::Error {
+  io::Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists in memory", path))
+}
+
+fn other(message: String) -> io::Error {
+  io::Error::other(message)
+}
+
+// "." and repeated or trailing separators don't change which node a path is
+pub fn normalize(path: &Path) -> PathBuf {
+  path.components().collect()
+}
+
+fn descendants<'a>(
+  nodes: &'a BTreeMap<PathBuf, Node>,
+  path: &'a Path,
+) -> impl Iterator<Item=(&'a PathBuf, &'a Node)> + 'a {
+  // paths order by component, so a dir's descendants directly follow it
+  nodes.range(path.to_path_buf()..)
+    .skip(1)
+    .take_while(move |(key, _)| key.starts_with(path))
+}
+
+impl MemoryFs {
+  pub fn new(root: &Path) -> Self {
+    let mut nodes = BTreeMap::new();
+    nodes.insert(normalize(root), Node::Dir(SystemTime::now()));
+    MemoryFs {
+      nodes: Mutex::new(nodes),
+      mutations: Arc::default(),
+    }
+  }
+
+  // only for mutations without contents to tear
+  fn begin_mutation(&self, path: &Path) -> io::Result<()> {
+    if Mutations::begin(&self.mutations, path)? {
+      return Err(tear_error(path));
+    }
+    Ok(())
+  }
+
+  fn check_parent_dir(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<()> {
+    match path.parent().and_then(|parent| nodes.get(parent)) {
+      Some(Node::Dir(_)) => Ok(()),
+      Some(Node::File(_)) => Err(other(format!("parent of {:?} is a file", path))),
+      None => Err(not_found(path)),
+    }
+  }
+
+  fn file(&self, path: &Path) -> io::Result<Arc<Mutex<FileData>>> {
+    match self.nodes.lock().unwrap().get(&normalize(path)) {
+      Some(Node::File(data)) => Ok(data.clone()),
+      Some(Node::Dir(_)) => Err(other(format!("{:?} is a dir", path))),
+      None => Err(not_found(path)),
+    }
+  }
+
+  pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    let data = self.file(path)?;
+    let bytes = data.lock().unwrap().bytes.clone();
+    Ok(bytes)
+  }
+
+  pub fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+    self.open(path, true, false)?.replace(contents)
+  }
+
+  // the file at the path, creating it if asked to, and emptying it if asked to
+  pub fn open(&self, path: &Path, create: bool, truncate: bool) -> io::Result<MemoryFileData> {
+    let path = normalize(path);
+    let mut nodes = self.nodes.lock().unwrap();
+    let data = match nodes.get(&path) {
+      Some(Node::File(data)) => data.clone(),
+      Some(Node::Dir(_)) => return Err(other(format!("{:?} is a dir", path))),
+      None if create => {
+        Self::check_parent_dir(&nodes, &path)?;
+        self.begin_mutation(&path)?;
+        let data = Arc::new(Mutex::new(FileData {
+          bytes: Vec::new(),
+          modified: SystemTime::now(),
+        }));
+        nodes.insert(path.clone(), Node::File(data.clone()));
+        data
+      },
+      None => return Err(not_found(&path)),
+    };
+    let file = MemoryFileData {
+      path,
+      data,
+      mutations: self.mutations.clone(),
+    };
+    if truncate {
+      file.set_len(0)?;
+    }
+    Ok(file)
+  }
+
+  pub fn create_dir(&self, path: &Path) -> io::Result<()> {
+    let path = normalize(path);
+    let mut nodes = self.nodes.lock().unwrap();
+    if nodes.contains_key(&path) {
+      return Err(already_exists(&path));
+    }
+    Self::check_parent_dir(&nodes, &path)?;
+    self.begin_mutation(&path)?;
+    nodes.insert(path, Node::Dir(SystemTime::now()));
+    Ok(())
+  }
+
+  pub fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+    let path = normalize(path);
+    let mut nodes = self.nodes.lock().unwrap();
+    for ancestor in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+      match nodes.get(ancestor) {
+        Some(Node::Dir(_)) => (),
+        Some(Node::File(_)) => return Err(other(format!("{:?} is a file", ancestor))),
+        None => {
+          self.begin_mutation(ancestor)?;
+          nodes.insert(ancestor.to_path_buf(), Node::Dir(SystemTime::now()));
+        },
+      }
+    }
+    Ok(())
+  }
+
+  pub fn remove_file(&self, path: &Path) -> io::Result<()> {
+    let path = normalize(path);
+    let mut nodes = self.nodes.lock().unwrap();
+    match nodes.get(&path) {
+      Some(Node::File(_)) => {
+        self.begin_mutation(&path)?;
+        nodes.remove(&path);
+        Ok(())
+      },
+      Some(Node::Dir(_)) => Err(other(format!("{:?} is a dir", path))),
+      None => Err(not_found(&path)),
+    }
+  }
+
+  pub fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+    let path = normalize(path);
+    let mut nodes = self.nodes.lock().unwrap();
+    match nodes.get(&path) {
+      Some(Node::Dir(_)) => (),
+      Some(Node::File(_)) => return Err(other(format!("{:?} is a file", path))),
+      None => return Err(not_found(&path)),
+    }
+    self.begin_mutation(&path)?;
+    let removed = descendants(&nodes, &path)
+      .map(|(key, _)| key.clone())
+      .collect::<Vec<_>>();
+    for key in removed {
+      nodes.remove(&key);
+    }
+    nodes.remove(&path);
+    Ok(())
+  }
+
+  // Like the real thing, replaces a file or an empty dir at the
+  // destination, and moves a dir along with everything in it.
+  pub fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+    let from = normalize(from);
+    let to = normalize(to);
+    let mut nodes = self.nodes.lock().unwrap();
+    let node = nodes.get(&from).cloned().ok_or_else(|| not_found(&from))?;
+    if from == to {
+      return Ok(());
+    }
+    Self::check_parent_dir(&nodes, &to)?;
+    match (&node, nodes.get(&to)) {
+      (Node::File(_), Some(Node::Dir(_))) =>
+        return Err(other(format!("can't rename file {:?} over dir {:?}", from, to))),
+      (Node::Dir(_), Some(Node::File(_))) =>
+        return Err(other(format!("can't rename dir {:?} over file {:?}", from, to))),
+      (Node::Dir(_), Some(Node::Dir(_))) if descendants(&nodes, &to).next().is_some() =>
+        return Err(other(format!("can't rename {:?} over non-empty dir {:?}", from, to))),
+      (Node::Dir(_), _) if to.starts_with(&from) =>
+        return Err(other(format!("can't move {:?} into itself", from))),
+      _ => (),
+    }
+    self.begin_mutation(&from)?;
+
+    let moved = descendants(&nodes, &from)
+      .map(|(key, node)| (key.clone(), node.clone()))
+      .collect::<Vec<_>>();
+    nodes.remove(&from);
+    nodes.insert(to.clone(), node);
+    for (key, node) in moved {
+      nodes.remove(&key);
+      let relative = key.strip_prefix(&from).expect("descendants are under the dir");
+      nodes.insert(to.join(relative), node);
+    }
+    Ok(())
+  }
+
+  // a second name for the same file, which sees writes made through either
+  pub fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+    let link = normalize(link);
+    let data = self.file(original)?;
+    let mut nodes = self.nodes.lock().unwrap();
+    if nodes.contains_key(&link) {
+      return Err(already_exists(&link));
+    }
+    Self::check_parent_dir(&nodes, &link)?;
+    self.begin_mutation(&link)?;
+    nodes.insert(link, Node::File(data));
+    Ok(())
+  }
+
+  pub fn metadata(&self, path: &Path) -> io::Result<NodeMetadata> {
+    match self.nodes.lock().unwrap().get(&normalize(path)) {
+      Some(Node::Dir(modified)) => Ok(NodeMetadata {
+        is_dir: true,
+        len: 0,
+        modified: *modified,
+      }),
+      Some(Node::File(data)) => {
+        let data = data.lock().unwrap();
+        Ok(NodeMetadata {
+          is_dir: false,
+          len: data.bytes.len() as u64,
+          modified: data.modified,
+        })
+      },
+      None => Err(not_found(path)),
+    }
+  }
+
+  // the dir's children, in order, as of now
+  pub fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+    let path = normalize(path);
+    let nodes = self.nodes.lock().unwrap();
+    match nodes.get(&path) {
+      Some(Node::Dir(_)) => (),
+      Some(Node::File(_)) => return Err(other(format!("{:?} is a file", path))),
+      None => return Err(not_found(&path)),
+    }
+    let res = descendants(&nodes, &path)
+      .filter(|(key, _)| key.parent() == Some(path.as_path()))
+      .map(|(key, _)| key.clone())
+      .collect();
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+impl MemoryFs {
+  // how many mutations have been made so far, which a later run can use
+  // to inject a fault at each one in turn
+  pub fn n_mutations(&self) -> u64 {
+    self.mutations.lock().unwrap().n
+  }
+
+  // crashes at the nth mutation from now, counting from 1
+  pub fn inject_fault(&self, n: u64, fault: Fault) {
+    let mut mutations = self.mutations.lock().unwrap();
+    let fault_n = mutations.n + n;
+    mutations.fault = Some((fault_n, fault));
+  }
+
+  pub fn has_crashed(&self) -> bool {
+    self.mutations.lock().unwrap().crashed
+  }
+
+  // Lets mutations succeed again, as for a restarted process. Everything
+  // written before the crash stays as it was, torn or not.
+  pub fn restart(&self) {
+    let mut mutations = self.mutations.lock().unwrap();
+    mutations.fault = None;
+    mutations.crashed = false;
+  }
+}
+
+// the contents of an open file, shared with any other handles to it
+pub struct MemoryFileData {
+  path: PathBuf,
+  data: Arc<Mutex<FileData>>,
+  mutations: Arc<Mutex<Mutations>>,
+}
+
+impl MemoryFileData {
+  pub fn len(&self) -> u64 {
+    self.data.lock().unwrap().bytes.len() as u64
+  }
+
+  pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> usize {
+    let data = self.data.lock().unwrap();
+    let start = (offset as usize).min(data.bytes.len());
+    let n = buf.len().min(data.bytes.len() - start);
+    buf[..n].copy_from_slice(&data.bytes[start..start + n]);
+    n
+  }
+
+  // writing past the end fills the gap with zeros
+  pub fn write_at(&self, offset: u64, contents: &[u8]) -> io::Result<()> {
+    let tear = Mutations::begin(&self.mutations, &self.path)?;
+    let contents = if tear { torn(contents) } else { contents };
+    let mut data = self.data.lock().unwrap();
+    let start = offset as usize;
+    let end = start + contents.len();
+    if data.bytes.len() < end {
+      data.bytes.resize(end, 0);
+    }
+    data.bytes[start..end].copy_from_slice(contents);
+    data.modified = SystemTime::now();
+    if tear {
+      return Err(tear_error(&self.path));
+    }
+    Ok(())
+  }
+
+  pub fn replace(&self, contents: &[u8]) -> io::Result<()> {
+    let tear = Mutations::begin(&self.mutations, &self.path)?;
+    let mut data = self.data.lock().unwrap();
+    data.bytes = if tear { torn(contents) } else { contents }.to_vec();
+    data.modified = SystemTime::now();
+    if tear {
+      return Err(tear_error(&self.path));
+    }
+    Ok(())
+  }
+
+  pub fn set_len(&self, len: u64) -> io::Result<()> {
+    if Mutations::begin(&self.mutations, &self.path)? {
+      return Err(tear_error(&self.path));
+    }
+    let mut data = self.data.lock().unwrap();
+    data.bytes.resize(len as usize, 0);
+    data.modified = SystemTime::now();
+    Ok(())
+  }
+
+  pub fn seek_position(&self, position: u64, seek: SeekFrom) -> io::Result<u64> {
+    let new_position = match seek {
+      SeekFrom::Start(offset) => Some(offset),
+      SeekFrom::End(delta) => (self.len() as i64).checked_add(delta).filter(|p| *p >= 0).map(|p| p as u64),
+      SeekFrom::Current(delta) => (position as i64).checked_add(delta).filter(|p| *p >= 0).map(|p| p as u64),
+    };
+    new_position.ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "seek to a negative position"))
+  }
+}
@@ -1,8 +1,29 @@
+pub mod bloom;
+pub mod chaos;
+pub mod checksum;
+pub mod hll;
+pub mod zone_map;
 pub mod common;
+pub mod memory_fs;
+pub mod compression;
+pub mod computed;
+pub mod masking;
 pub mod dirs;
+pub mod durability;
+pub mod schedule;
+pub mod encryption;
 pub mod decoding_seek;
+pub mod safe_decoding;
+pub mod columnar;
 pub mod shared_hash_map;
+pub mod storage;
+pub mod vfs;
 pub mod sharding;
 pub mod navigation;
+pub mod names;
+pub mod openapi;
 pub mod rest;
+pub mod console;
 pub mod read_segment_column_stream;
+pub mod change_stream;
+pub mod write_socket;
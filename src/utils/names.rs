@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use pancake_db_idl::ddl::{AlterTableRequest, CreateTableRequest, DropTableRequest, GetSchemaRequest, ListTablesRequest};
+use pancake_db_idl::dml::{DeleteFromSegmentRequest, ListSegmentsRequest, PartitionFilter, ReadSegmentColumnRequest, ReadSegmentDeletionsRequest, Row, WriteToPartitionRequest};
+use pancake_db_idl::dml::partition_filter;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::serde_models::*;
+
+// How table, column, and partition names are normalized on the way in.
+// With lowercase, names are lowercased when tables are created or altered
+// and whenever requests refer to them, so clients used to SQL's case
+// insensitivity can write MyTable or mytable alike. Names are limited to
+// ASCII alphanumerics and underscores, so there is no Unicode for NFC or
+// case folding beyond that to normalize. Like the fsync policy, it applies
+// to the whole process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameNormalization {
+  None,
+  Lowercase,
+}
+
+impl FromStr for NameNormalization {
+  type Err = ServerError;
+
+  fn from_str(s: &str) -> ServerResult<Self> {
+    match s.to_lowercase().as_str() {
+      "none" => Ok(NameNormalization::None),
+      "lowercase" => Ok(NameNormalization::Lowercase),
+      invalid => Err(ServerError::invalid(format!(
+        "invalid name normalization {}",
+        invalid,
+      ))),
+    }
+  }
+}
+
+static NORMALIZATION: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_normalization(normalization: NameNormalization) {
+  NORMALIZATION.store(normalization as u8, Ordering::Relaxed);
+}
+
+pub fn normalization() -> NameNormalization {
+  match NORMALIZATION.load(Ordering::Relaxed) {
+    1 => NameNormalization::Lowercase,
+    _ => NameNormalization::None,
+  }
+}
+
+pub fn normalize(name: &str) -> String {
+  match normalization() {
+    NameNormalization::None => name.to_string(),
+    NameNormalization::Lowercase => name.to_lowercase(),
+  }
+}
+
+fn normalize_in_place(name: &mut String) {
+  if normalization() != NameNormalization::None {
+    *name = normalize(name);
+  }
+}
+
+// names that normalize to the same one would silently overwrite each other
+fn normalize_keys<V>(map: &mut HashMap<String, V>) -> ServerResult<()> {
+  if normalization() == NameNormalization::None {
+    return Ok(());
+  }
+  let mut res = HashMap::with_capacity(map.len());
+  let mut original_names: HashMap<String, String> = HashMap::with_capacity(map.len());
+  for (name, value) in map.drain() {
+    let normalized = normalize(&name);
+    if let Some(other_name) = original_names.get(&normalized) {
+      return Err(ServerError::invalid(format!(
+        "names {} and {} both normalize to {}",
+        other_name,
+        name,
+        normalized,
+      )));
+    }
+    original_names.insert(normalized.clone(), name);
+    res.insert(normalized, value);
+  }
+  *map = res;
+  Ok(())
+}
+
+fn normalize_all(names: &mut [String]) {
+  names.iter_mut().for_each(normalize_in_place);
+}
+
+// Requests normalize the names they refer to before they become ops.
+// Anything else in them, like partition values or computed column
+// expressions, is left as is.
+pub trait NormalizeNames {
+  fn normalize_names(&mut self) -> ServerResult<()>;
+}
+
+impl NormalizeNames for CreateTableRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    if let Some(schema) = &mut self.schema {
+      normalize_keys(&mut schema.partitioning)?;
+      normalize_keys(&mut schema.columns)?;
+    }
+    Ok(())
+  }
+}
+
+impl NormalizeNames for AlterTableRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.new_columns)?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for DropTableRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for GetSchemaRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for ListTablesRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    Ok(())
+  }
+}
+
+impl NormalizeNames for DeleteFromSegmentRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    Ok(())
+  }
+}
+
+fn normalize_partition_filters(filters: &mut [PartitionFilter]) {
+  for filter in filters {
+    if let Some(partition_filter::Value::Comparison(comparison)) = &mut filter.value {
+      normalize_in_place(&mut comparison.name);
+    }
+  }
+}
+
+impl NormalizeNames for ListSegmentsRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_partition_filters(&mut self.partition_filter);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for ReadSegmentColumnRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    normalize_in_place(&mut self.column_name);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for ReadSegmentDeletionsRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    Ok(())
+  }
+}
+
+fn normalize_rows(rows: &mut [Row]) -> ServerResult<()> {
+  for row in rows {
+    normalize_keys(&mut row.fields)?;
+  }
+  Ok(())
+}
+
+impl NormalizeNames for WriteToPartitionRequest {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    normalize_rows(&mut self.rows)?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for WriteToPartitionRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    for row in &mut self.rows {
+      normalize_keys(row)?;
+    }
+    Ok(())
+  }
+}
+
+impl NormalizeNames for SchemaSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_keys(&mut self.partitioning)?;
+    normalize_keys(&mut self.columns)?;
+    normalize_all(&mut self.sort_columns);
+    normalize_keys(&mut self.computed_columns)?;
+    normalize_keys(&mut self.column_masks)?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for CreateTableRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    self.schema.normalize_names()?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for AlterTableRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.new_columns)?;
+    normalize_keys(&mut self.rename_columns)?;
+    self.rename_columns.values_mut().for_each(normalize_in_place);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for RenameTableRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_in_place(&mut self.new_table_name);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for CopyTableRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_in_place(&mut self.new_table_name);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for AuditLogRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    if let Some(table_name) = &mut self.table_name {
+      normalize_in_place(table_name);
+    }
+    Ok(())
+  }
+}
+
+impl NormalizeNames for StagedSegmentsRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    if let Some(table_name) = &mut self.table_name {
+      normalize_in_place(table_name);
+    }
+    Ok(())
+  }
+}
+
+// requests that name only a table
+macro_rules! impl_normalize_table_name {
+  ($($req:ty),*) => {
+    $(
+      impl NormalizeNames for $req {
+        fn normalize_names(&mut self) -> ServerResult<()> {
+          normalize_in_place(&mut self.table_name);
+          Ok(())
+        }
+      }
+    )*
+  };
+}
+
+impl_normalize_table_name!(
+  DropTableRequestSerde,
+  FreezeTableRequestSerde,
+  UndropTableRequestSerde,
+  GetSchemaRequestSerde,
+  CompactTableRequestSerde,
+  CheckTableRequestSerde,
+  BeginTxRequestSerde,
+  ReadChangesRequestSerde,
+  SubscribeChangesRequestSerde
+);
+
+// requests that name a table and a partition
+macro_rules! impl_normalize_partition {
+  ($($req:ty),*) => {
+    $(
+      impl NormalizeNames for $req {
+        fn normalize_names(&mut self) -> ServerResult<()> {
+          normalize_in_place(&mut self.table_name);
+          normalize_keys(&mut self.partition)?;
+          Ok(())
+        }
+      }
+    )*
+  };
+}
+
+impl_normalize_partition!(
+  BeginReadRequestSerde,
+  BeginSnapshotRequestSerde,
+  CompactSegmentRequestSerde,
+  MergeSegmentsRequestSerde
+);
+
+fn normalize_partition_filter_serdes(filters: &mut [PartitionFilterSerde]) {
+  for filter in filters {
+    normalize_in_place(&mut filter.name);
+  }
+}
+
+impl NormalizeNames for ListTablesRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.name_prefix);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for ListSegmentsRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_partition_filter_serdes(&mut self.partition_filter);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for CountRowsRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    normalize_partition_filter_serdes(&mut self.partition_filter);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for GetColumnSketchRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_in_place(&mut self.column_name);
+    normalize_keys(&mut self.partition)?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for CheckSegmentContainsRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_in_place(&mut self.column_name);
+    normalize_keys(&mut self.partition)?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for SetBloomFilterColumnsRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_all(&mut self.columns);
+    Ok(())
+  }
+}
+
+impl NormalizeNames for SetColumnMasksRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.column_masks)?;
+    Ok(())
+  }
+}
+
+impl NormalizeNames for GetRowsByIdRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    if let Some(columns) = &mut self.columns {
+      normalize_all(columns);
+    }
+    Ok(())
+  }
+}
+
+impl NormalizeNames for ReadSegmentColumnRequestSerde {
+  fn normalize_names(&mut self) -> ServerResult<()> {
+    normalize_in_place(&mut self.table_name);
+    normalize_keys(&mut self.partition)?;
+    normalize_in_place(&mut self.column_name);
+    if let Some(predicate) = &mut self.predicate {
+      normalize_in_place(&mut predicate.column_name);
+    }
+    Ok(())
+  }
+}
+
+// requests that don't name anything
+macro_rules! impl_normalize_nothing {
+  ($($req:ty),*) => {
+    $(
+      impl NormalizeNames for $req {
+        fn normalize_names(&mut self) -> ServerResult<()> {
+          Ok(())
+        }
+      }
+    )*
+  };
+}
+
+impl_normalize_nothing!(
+  EmptySerde,
+  EndReadRequestSerde,
+  CommitTxRequestSerde,
+  AbortTxRequestSerde
+);
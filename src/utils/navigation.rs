@@ -1,50 +1,64 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use async_stream::try_stream;
 use futures::Stream;
-use pancake_db_idl::dml::{PartitionFieldValue, PartitionFilter};
+use pancake_db_idl::dml::PartitionFieldValue;
+use pancake_db_idl::dml::partition_field_comparison::Operator;
+use pancake_db_idl::dml::partition_field_value::Value as PartitionValue;
 use pancake_db_idl::schema::PartitionMeta;
-use tokio::fs;
-use tokio::fs::DirEntry;
 use uuid::Uuid;
 
 use crate::errors::{Contextable, ServerError, ServerResult};
-use crate::types::{NormalizedPartition, PartitionKey};
-use crate::utils::{common, dirs};
+use crate::types::{NormalizedPartition, NormalizedPartitionField, PartitionKey};
+use crate::utils::{common, dirs, vfs};
+use crate::utils::common::PartitionCondition;
 
 pub async fn partitions_for_table(
   dir: &Path,
   table_name: &str,
   partitioning: &HashMap<String, PartitionMeta>,
-  filters: &[PartitionFilter],
+  conditions: &[PartitionCondition],
 ) -> ServerResult<Vec<HashMap<String, PartitionFieldValue>>> {
   let mut partitions: Vec<HashMap<String, PartitionFieldValue>> = vec![HashMap::new()];
   let mut partition_names: Vec<_> = partitioning.keys().cloned().collect();
   partition_names.sort();
   for partition_name in &partition_names {
     let meta = partitioning[partition_name].clone();
+    let maybe_pinned_values = pinned_values(partition_name, conditions);
     let mut new_partitions: Vec<HashMap<String, PartitionFieldValue>> = Vec::new();
     for partition in &partitions {
-      let subdir = dirs::partition_dir(
-        dir,
-        &PartitionKey {
-          table_name: table_name.to_string(),
-          partition: NormalizedPartition::from_raw_fields(partition)?
-        }
-      );
-      let subpartitions = subpartitions(
-        &subdir,
-        partition_name,
-        &meta
-      )
-        .await?;
+      let subpartitions = match &maybe_pinned_values {
+        Some(values) => pinned_subpartitions(
+          dir,
+          table_name,
+          partition,
+          partition_name,
+          &meta,
+          values,
+        ).await?,
+        None => {
+          let subdir = dirs::partition_dir(
+            dir,
+            &PartitionKey {
+              table_name: table_name.to_string(),
+              partition: NormalizedPartition::from_raw_fields(partition)?
+            }
+          );
+          subpartitions(
+            &subdir,
+            partition_name,
+            &meta
+          ).await?
+        },
+      };
 
       for leaf in subpartitions {
         let mut new_partition = partition.clone();
         new_partition.insert(partition_name.to_string(), leaf);
-        if common::satisfies_filters(&new_partition, filters)? {
+        if common::satisfies_filters(&new_partition, conditions)? {
           new_partitions.push(new_partition);
         }
       }
@@ -54,49 +68,156 @@ pub async fn partitions_for_table(
   Ok(partitions)
 }
 
-async fn subpartitions(
+// The values an equality or IN condition limits a field to, if any, so
+// that its directories can be looked up instead of listed. With several,
+// the shortest list is enough, since every partition found is still
+// checked against all the conditions.
+fn pinned_values(name: &str, conditions: &[PartitionCondition]) -> Option<Vec<PartitionValue>> {
+  let mut res: Option<Vec<PartitionValue>> = None;
+  for condition in conditions {
+    let values = match condition {
+      PartitionCondition::Comparison(comparison) if
+        comparison.name == name && comparison.operator == Operator::EqTo as i32 => {
+        match comparison.value.as_ref().and_then(|value| value.value.clone()) {
+          Some(value) => vec![value],
+          // left for satisfies_filters to reject
+          None => continue,
+        }
+      },
+      PartitionCondition::In { name: condition_name, values } if condition_name == name => values.clone(),
+      _ => continue,
+    };
+    if res.as_ref().map(|shortest| values.len() < shortest.len()).unwrap_or(true) {
+      res = Some(values);
+    }
+  }
+  res
+}
+
+// the subpartitions with these values that have directories
+async fn pinned_subpartitions(
   dir: &Path,
+  table_name: &str,
+  partition: &HashMap<String, PartitionFieldValue>,
   name: &str,
   meta: &PartitionMeta,
+  values: &[PartitionValue],
 ) -> ServerResult<Vec<PartitionFieldValue>> {
+  let dtype = common::unwrap_partition_dtype(meta.dtype)?;
+  let mut seen_dirs = HashSet::new();
   let mut res = Vec::new();
-  let mut read_dir = fs::read_dir(&dir).await
-    .map_err(|e| ServerError::from(e).with_context(format!(
-      "while reading directory {:?} for listing subpartitions",
-      dir
-    )))?;
-  while let Ok(Some(entry)) = read_dir.next_entry().await {
-    if !entry.file_type().await.unwrap().is_dir() {
+  for value in values {
+    let field = PartitionFieldValue { value: Some(value.clone()) };
+    // values that can't name a directory, like timestamps between
+    // minutes, have no partition to find
+    let normalized_field = match NormalizedPartitionField::try_from_raw(name, &field) {
+      Ok(normalized_field) => normalized_field,
+      Err(_) => continue,
+    };
+    if !common::partition_dtype_matches_field(&dtype, &normalized_field) {
+      return Err(ServerError::invalid(format!(
+        "partition filter value {:?} does not match data type {:?} of {}",
+        value,
+        dtype,
+        name,
+      )));
+    }
+
+    let mut subpartition = partition.clone();
+    subpartition.insert(name.to_string(), field.clone());
+    let subdir = dirs::partition_dir(
+      dir,
+      &PartitionKey {
+        table_name: table_name.to_string(),
+        partition: NormalizedPartition::from_raw_fields(&subpartition)?,
+      },
+    );
+    if !seen_dirs.insert(subdir.clone()) {
       continue;
     }
+    match vfs::metadata(&subdir).await {
+      Ok(metadata) if metadata.is_dir() => res.push(field),
+      Ok(_) => (),
+      Err(e) if e.kind() == ErrorKind::NotFound => (),
+      Err(e) => return Err(ServerError::from(e).with_context(format!(
+        "while looking up partition directory {:?}",
+        subdir,
+      ))),
+    }
+  }
+  Ok(res)
+}
 
-    let fname = entry.file_name();
-    let parts = fname
-      .to_str()
-      .unwrap()
-      .split('=')
-      .collect::<Vec<&str>>();
+async fn subpartitions(
+  dir: &Path,
+  name: &str,
+  meta: &PartitionMeta,
+) -> ServerResult<Vec<PartitionFieldValue>> {
+  let mut res = Vec::new();
+  for field_parent_dir in field_parent_dirs(dir).await? {
+    let mut read_dir = vfs::read_dir(&field_parent_dir).await
+      .map_err(|e| ServerError::from(e).with_context(format!(
+        "while reading directory {:?} for listing subpartitions",
+        field_parent_dir
+      )))?;
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+      if !entry.file_type().await.unwrap().is_dir() {
+        continue;
+      }
 
-    if parts.len() != 2 {
-      continue;
+      let fname = entry.file_name();
+      let parts = fname
+        .to_str()
+        .unwrap()
+        .split('=')
+        .collect::<Vec<&str>>();
+
+      if parts.len() != 2 {
+        continue;
+      }
+      if parts[0] != name {
+        continue;
+      }
+      let parsed = common::partition_field_value_from_string(
+        parts[1],
+        common::unwrap_partition_dtype(meta.dtype)?,
+      ).with_context(|| format!(
+        "while parsing partition value {} in {:?}",
+        parts[1],
+        field_parent_dir,
+      ))?;
+      res.push(parsed);
     }
-    if parts[0] != name {
-      continue;
+  }
+  Ok(res)
+}
+
+// the dirs that a partition's subpartition dirs are directly in: its own
+// dir, or its bucket dirs when partition dirs are bucketed
+async fn field_parent_dirs(dir: &Path) -> ServerResult<Vec<PathBuf>> {
+  if dirs::partition_dir_fanout() == 0 {
+    return Ok(vec![dir.to_path_buf()]);
+  }
+
+  let mut res = Vec::new();
+  let mut read_dir = vfs::read_dir(&dir).await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while reading directory {:?} for listing partition buckets",
+      dir
+    )))?;
+  while let Some(entry) = read_dir.next_entry().await? {
+    let is_bucket = entry.file_name()
+      .to_str()
+      .map(dirs::is_partition_bucket_name)
+      .unwrap_or(false);
+    if is_bucket && entry.file_type().await?.is_dir() {
+      res.push(entry.path());
     }
-    let parsed = common::partition_field_value_from_string(
-      parts[1],
-      common::unwrap_partition_dtype(meta.dtype)?,
-    ).with_context(|| format!(
-      "while parsing partition value {} in {:?}",
-      parts[1],
-      dir,
-    ))?;
-    res.push(parsed);
   }
   Ok(res)
 }
 
-fn split_segment_id(entry: &DirEntry) -> Option<String> {
+fn split_segment_id(entry: &vfs::DirEntry) -> Option<String> {
   let fname = entry.file_name();
   let parts = fname
     .to_str()
@@ -117,7 +238,7 @@ pub fn stream_segment_ids_for_partition(
 ) -> impl Stream<Item=ServerResult<Uuid>> {
   let partition_dir = dirs::partition_dir(dir, &partition_key);
   try_stream! {
-    let mut read_dir = fs::read_dir(&partition_dir).await
+    let mut read_dir = vfs::read_dir(&partition_dir).await
       .map_err(|e| ServerError::from(e).with_context(format!(
         "while reading directory {:?} for listing segments",
         partition_dir
@@ -0,0 +1,137 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use schemars::schema::Schema;
+use serde_json::{json, Map, Value};
+
+use crate::ops::traits::RestRoute;
+use crate::utils::rest::ErrorResponse;
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+// Builds an OpenAPI 3 document for the REST API from each route's request
+// and response models, so clients in other languages can be generated
+// rather than written by reading the ops.
+pub struct OpenApiBuilder {
+  generator: SchemaGenerator,
+  paths: Map<String, Value>,
+}
+
+impl OpenApiBuilder {
+  pub fn new() -> Self {
+    OpenApiBuilder {
+      generator: SchemaGenerator::new(SchemaSettings::openapi3()),
+      paths: Map::new(),
+    }
+  }
+
+  // a reference to T's schema, which is added to the document's components
+  pub fn schema_ref<T: JsonSchema>(&mut self) -> Value {
+    serde_json::to_value(self.generator.subschema_for::<T>())
+      .expect("schemas always serialize")
+  }
+
+  fn json_content(schema: Value) -> Value {
+    json!({ JSON_CONTENT_TYPE: { "schema": schema } })
+  }
+
+  fn operation<Route>(&mut self, summary: &str) -> Map<String, Value>
+  where Route: RestRoute, Route::Response: JsonSchema {
+    let response_schema = self.schema_ref::<Route::Response>();
+    let error_schema = self.schema_ref::<ErrorResponse>();
+    let mut res = Map::new();
+    res.insert("operationId".to_string(), json!(Route::ROUTE_NAME));
+    res.insert("summary".to_string(), json!(summary));
+    res.insert("responses".to_string(), json!({
+      "200": {
+        "description": "success",
+        "content": Self::json_content(response_schema),
+      },
+      "default": {
+        "description": "error, with the status code following the error's kind",
+        "content": Self::json_content(error_schema),
+      },
+    }));
+    res
+  }
+
+  pub fn post<Route>(mut self, prefix: &str, summary: &str) -> Self
+  where Route: RestRoute, Route::Req: JsonSchema, Route::Response: JsonSchema {
+    let mut operation = self.operation::<Route>(summary);
+    let request_schema = self.schema_ref::<Route::Req>();
+    operation.insert("requestBody".to_string(), json!({
+      "required": true,
+      "content": Self::json_content(request_schema),
+    }));
+    self.insert_operation(&format!("{}/{}", prefix, Route::ROUTE_NAME), "post", Value::Object(operation));
+    self
+  }
+
+  // GET routes also accept the request as a JSON body, but query
+  // parameters are what clients are expected to use
+  pub fn get<Route>(mut self, prefix: &str, summary: &str) -> Self
+  where Route: RestRoute, Route::Req: JsonSchema, Route::Response: JsonSchema {
+    let mut operation = self.operation::<Route>(summary);
+    let request_schema = self.generator.subschema_for::<Route::Req>();
+    operation.insert("parameters".to_string(), Value::Array(self.query_parameters(&request_schema)));
+    self.insert_operation(&format!("{}/{}", prefix, Route::ROUTE_NAME), "get", Value::Object(operation));
+    self
+  }
+
+  fn query_parameters(&self, schema: &Schema) -> Vec<Value> {
+    let object = match self.generator.dereference(schema) {
+      Some(Schema::Object(schema_object)) => schema_object.object.as_ref(),
+      _ => None,
+    };
+    let object = match object {
+      Some(object) => object,
+      None => return Vec::new(),
+    };
+    object.properties.iter()
+      .map(|(name, property_schema)| json!({
+        "name": name,
+        "in": "query",
+        "required": object.required.contains(name),
+        "schema": property_schema,
+      }))
+      .collect()
+  }
+
+  // for paths that aren't plain REST routes; make_operation may add the
+  // schemas it refers to
+  pub fn add_operation<F>(mut self, path: &str, method: &str, make_operation: F) -> Self
+  where F: FnOnce(&mut Self) -> Value {
+    let operation = make_operation(&mut self);
+    self.insert_operation(path, method, operation);
+    self
+  }
+
+  fn insert_operation(&mut self, path: &str, method: &str, operation: Value) {
+    let path_item = self.paths.entry(path.to_string())
+      .or_insert_with(|| Value::Object(Map::new()));
+    path_item[method] = operation;
+  }
+
+  pub fn build(mut self) -> Value {
+    let schemas = serde_json::to_value(self.generator.take_definitions())
+      .expect("schemas always serialize");
+    json!({
+      "openapi": "3.0.3",
+      "info": {
+        "title": "PancakeDB REST API",
+        "version": env!("CARGO_PKG_VERSION"),
+      },
+      "paths": self.paths,
+      "components": {
+        "schemas": schemas,
+        "securitySchemes": {
+          "bearer": {
+            "type": "http",
+            "scheme": "bearer",
+            "description": "only needed when the server has an --authz-file",
+          },
+        },
+      },
+      "security": [{ "bearer": [] }],
+    })
+  }
+}
@@ -6,11 +6,18 @@ use tonic::Status;
 use crate::Server;
 use crate::ops::read_segment_column::{ReadSegmentColumnOp, SegmentColumnContinuation};
 use crate::ops::traits::ServerOp;
+use crate::server::authz::{self, Principal};
+use crate::server::cancel;
 
 pub type ReadSegmentColumnStream = BoxStream<'static, Result<ReadSegmentColumnResponse, Status>>;
 
-pub fn create_stream(req: ReadSegmentColumnRequest, server: Server) -> ReadSegmentColumnStream {
-  let state = ReadSegmentColumnState::new(req, server);
+pub fn create_stream(
+  req: ReadSegmentColumnRequest,
+  reverse: bool,
+  server: Server,
+  principal: Principal,
+) -> ReadSegmentColumnStream {
+  let state = ReadSegmentColumnState::new(req, reverse, server, principal);
   futures::stream::unfold(state, |mut state: ReadSegmentColumnState| async {
     if state.done {
       return None;
@@ -19,8 +26,15 @@ pub fn create_stream(req: ReadSegmentColumnRequest, server: Server) -> ReadSegme
     let op = ReadSegmentColumnOp {
       req: state.req.clone(),
       continuation: state.continuation.clone(),
+      predicate: None,
+      reverse: state.reverse,
     };
-    let resp = op.execute(&state.server).await;
+    // dropping the stream cancels the page being read
+    let server = state.server.clone();
+    let principal = state.principal.clone();
+    let resp = cancel::spawn_request(async move {
+      authz::scope(principal, op.execute(&server)).await
+    }).await;
 
     let grpc_resp = match &resp {
       Ok(ok_resp) => Ok(ok_resp.resp.clone()),
@@ -44,16 +58,20 @@ pub fn create_stream(req: ReadSegmentColumnRequest, server: Server) -> ReadSegme
 
 struct ReadSegmentColumnState {
   pub server: Server,
+  pub principal: Principal,
   pub req: ReadSegmentColumnRequest,
+  pub reverse: bool,
   pub continuation: Option<SegmentColumnContinuation>,
   pub done: bool,
 }
 
 impl ReadSegmentColumnState {
-  pub fn new(req: ReadSegmentColumnRequest, server: Server) -> Self {
+  pub fn new(req: ReadSegmentColumnRequest, reverse: bool, server: Server, principal: Principal) -> Self {
     Self {
       server,
+      principal,
       req,
+      reverse,
       continuation: None,
       done: false,
     }
@@ -1,37 +1,304 @@
 use std::convert::Infallible;
 
-use hyper::body::Bytes;
-use hyper::Response;
+use chrono::SecondsFormat;
+use futures::{pin_mut, Stream, StreamExt};
+use hyper::body::{Buf, Bytes};
+use hyper::{Body, Response};
+use hyper::header::{CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use warp::{Filter, Rejection, Reply};
+use warp::http::StatusCode;
 
 use crate::{Server, ServerResult};
 use crate::errors::ServerError;
+use crate::ops::abort_tx::AbortTxOp;
+use crate::ops::alter_table_rest::AlterTableRestOp;
+use crate::ops::audit_log::AuditLogOp;
+use crate::ops::background_status::BackgroundStatusOp;
+use crate::ops::begin_read::BeginReadOp;
+use crate::ops::begin_snapshot::BeginSnapshotOp;
+use crate::ops::begin_tx::BeginTxOp;
+use crate::ops::cache_stats::CacheStatsOp;
+use crate::ops::check_segment_contains::CheckSegmentContainsOp;
+use crate::ops::check_table::CheckTableOp;
+use crate::ops::commit_tx::CommitTxOp;
+use crate::ops::count_rows::CountRowsOp;
+use crate::ops::disk_usage::DiskUsageOp;
+use crate::ops::compact_segment::CompactSegmentOp;
+use crate::ops::compact_table::CompactTableOp;
+use crate::ops::copy_table::CopyTableOp;
 use crate::ops::create_table_rest::CreateTableRestOp;
 use crate::ops::drop_table_rest::DropTableRestOp;
+use crate::ops::end_read::EndReadOp;
+use crate::ops::freeze_table::FreezeTableOp;
+use crate::ops::get_column_sketch::GetColumnSketchOp;
+use crate::ops::get_rows_by_id::GetRowsByIdOp;
+use crate::ops::get_schema_rest::GetSchemaRestOp;
+use crate::ops::held_locks::HeldLocksOp;
+use crate::ops::list_segments_rest::ListSegmentsRestOp;
 use crate::ops::list_tables_rest::ListTablesRestOp;
+use crate::ops::list_trash::ListTrashOp;
+use crate::ops::merge_segments_rest::MergeSegmentsRestOp;
+use crate::ops::read_changes::ReadChangesOp;
+use crate::ops::recent_errors::RecentErrorsOp;
+use crate::ops::read_segment_column_rest::ReadSegmentColumnRestOp;
+use crate::ops::reload_config::ReloadConfigOp;
+use crate::ops::rename_table::RenameTableOp;
+use crate::ops::set_bloom_filter_columns::SetBloomFilterColumnsOp;
+use crate::ops::set_column_masks::SetColumnMasksOp;
+use crate::ops::staged_segments::StagedSegmentsOp;
 use crate::ops::traits::RestRoute;
+use crate::ops::undrop_table::UndropTableOp;
 use crate::ops::write_to_partition_rest::WriteToPartitionRestOp;
+use crate::serde_models::{LoopHealthSerde, ReadChangesResponseSerde, ReadinessResponseSerde, SubscribeChangesRequestSerde, WriteAckSerde, WriteFrameSerde};
+use crate::server::{authz, cancel};
+use crate::utils::change_stream;
+use crate::utils::compression::{self, ContentEncoding};
+use crate::utils::names::NormalizeNames;
+use crate::utils::openapi::OpenApiBuilder;
+use crate::utils::write_socket;
 
 pub fn warp_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
   warp::path("rest")
     .and(
       warp_post_filter::<CreateTableRestOp>()
         .or(warp_post_filter::<DropTableRestOp>())
+        .or(warp_post_filter::<UndropTableOp>())
+        .or(warp_post_filter::<RenameTableOp>())
+        .or(warp_post_filter::<CopyTableOp>())
+        .or(warp_post_filter::<FreezeTableOp>())
+        .or(warp_post_filter::<AlterTableRestOp>())
         .or(warp_get_filter::<ListTablesRestOp>())
+        .or(warp_get_filter::<GetSchemaRestOp>())
+        .or(warp_get_filter::<ListSegmentsRestOp>())
         .or(warp_post_filter::<WriteToPartitionRestOp>())
+        .or(warp_post_filter::<BeginReadOp>())
+        .or(warp_post_filter::<BeginSnapshotOp>())
+        .or(warp_post_filter::<EndReadOp>())
+        .or(warp_post_filter::<BeginTxOp>())
+        .or(warp_post_filter::<CommitTxOp>())
+        .or(warp_post_filter::<AbortTxOp>())
+        .or(warp_post_filter::<ReadChangesOp>())
+        .or(change_stream::warp_filter())
+        .or(warp_post_filter::<CountRowsOp>())
+        .or(warp_post_filter::<GetColumnSketchOp>())
+        .or(warp_post_filter::<SetBloomFilterColumnsOp>())
+        .or(warp_post_filter::<SetColumnMasksOp>())
+        .or(warp_post_filter::<CheckSegmentContainsOp>())
+        .or(warp_post_filter::<GetRowsByIdOp>())
+        .or(warp_post_filter::<ReadSegmentColumnRestOp>())
     )
+    .or(admin_filter())
+    .or(readyz_filter())
+    .or(openapi_filter())
+    .or(write_socket::warp_filter())
 }
 
+// CORS for browser pages on the allowed origins, answering preflights for
+// the HTTP and grpc-web APIs alike, or None if no origins are allowed
+pub fn warp_cors(cors_allowed_origins: &[String]) -> Option<warp::cors::Builder> {
+  if cors_allowed_origins.is_empty() {
+    return None;
+  }
+  let cors = warp::cors()
+    .allow_methods(vec!["GET", "POST", "OPTIONS"])
+    .allow_headers(vec!["authorization", "content-type", "grpc-timeout", "x-grpc-web", "x-user-agent"])
+    .expose_headers(vec!["grpc-status", "grpc-message"]);
+  if cors_allowed_origins.iter().any(|origin| origin == "*") {
+    Some(cors.allow_any_origin())
+  } else {
+    Some(cors.allow_origins(cors_allowed_origins.iter().map(String::as_str)))
+  }
+}
+
+// grpc-web for browser pages on the same allowed origins; with none
+// allowed, only clients that send no origin, unlike browsers, can call it
+pub fn grpc_web_config(cors_allowed_origins: &[String]) -> tonic_web::Config {
+  let config = tonic_web::config().allow_credentials(false);
+  if cors_allowed_origins.iter().any(|origin| origin == "*") {
+    config.allow_all_origins()
+  } else {
+    config.allow_origins(cors_allowed_origins.iter().map(String::as_str))
+  }
+}
+
+// Describes the routes above; keep it in sync with warp_filter and
+// admin_filter.
+pub fn openapi_spec() -> serde_json::Value {
+  OpenApiBuilder::new()
+    .post::<CreateTableRestOp>("/rest", "Creates a table, or checks or extends an existing one's schema")
+    .post::<DropTableRestOp>("/rest", "Drops a table, moving it to the trash")
+    .post::<UndropTableOp>("/rest", "Restores a dropped table from the trash")
+    .post::<RenameTableOp>("/rest", "Renames a table")
+    .post::<CopyTableOp>("/rest", "Copies a table's schema, and optionally its rows, to a new table")
+    .post::<FreezeTableOp>("/rest", "Makes a table read-only, or writable again")
+    .post::<AlterTableRestOp>("/rest", "Adds or renames a table's columns")
+    .get::<ListTablesRestOp>("/rest", "Lists the tables the caller may read")
+    .get::<GetSchemaRestOp>("/rest", "Gets a table's schema")
+    .get::<ListSegmentsRestOp>("/rest", "Lists a table's segments")
+    .post::<WriteToPartitionRestOp>("/rest", "Writes rows to a partition")
+    .post::<BeginReadOp>("/rest", "Pins a segment's current version for reading")
+    .post::<BeginSnapshotOp>("/rest", "Pins the current versions of several tables' segments for reading")
+    .post::<EndReadOp>("/rest", "Releases a read or snapshot")
+    .post::<BeginTxOp>("/rest", "Begins a transaction")
+    .post::<CommitTxOp>("/rest", "Commits a transaction's writes")
+    .post::<AbortTxOp>("/rest", "Aborts a transaction, discarding its writes")
+    .post::<ReadChangesOp>("/rest", "Reads a table's row writes and deletions since a cursor")
+    .add_operation("/rest/subscribe_changes", "post", |builder| serde_json::json!({
+      "operationId": "subscribe_changes",
+      "summary": "Streams a table's changes until the client disconnects",
+      "requestBody": {
+        "required": true,
+        "content": {"application/json": {"schema": builder.schema_ref::<SubscribeChangesRequestSerde>()}},
+      },
+      "responses": {
+        "200": {
+          "description": "one read_changes response per line; an error ends the stream with a line containing its message",
+          "content": {"application/x-ndjson": {"schema": builder.schema_ref::<ReadChangesResponseSerde>()}},
+        },
+      },
+    }))
+    .post::<CountRowsOp>("/rest", "Counts the live rows in a table's matching partitions from metadata alone")
+    .post::<GetColumnSketchOp>("/rest", "Estimates a column's distinct count")
+    .post::<SetBloomFilterColumnsOp>("/rest", "Sets which columns have bloom filters")
+    .post::<SetColumnMasksOp>("/rest", "Sets a table's column masking rules")
+    .post::<CheckSegmentContainsOp>("/rest", "Checks which segments may contain a column value")
+    .post::<GetRowsByIdOp>("/rest", "Gets a segment's rows by row id")
+    .post::<ReadSegmentColumnRestOp>("/rest", "Reads a page of a segment column's values as JSON")
+    .get::<HeldLocksOp>("/admin", "Lists the locks currently held")
+    .get::<BackgroundStatusOp>("/admin", "Shows the state of the background loops")
+    .get::<StagedSegmentsOp>("/admin", "Lists segments with rows waiting to be flushed")
+    .get::<RecentErrorsOp>("/admin", "Lists recent errors")
+    .get::<CacheStatsOp>("/admin", "Shows cache hit rates and sizes")
+    .get::<DiskUsageOp>("/admin", "Shows disk usage by table")
+    .get::<ListTrashOp>("/admin", "Lists dropped tables that can still be restored")
+    .get::<AuditLogOp>("/admin", "Queries the audit log")
+    .post::<CheckTableOp>("/admin", "Checks a table's metadata and files for problems, optionally repairing them")
+    .post::<ReloadConfigOp>("/admin", "Reloads the runtime config")
+    .post::<CompactTableOp>("/admin", "Compacts a table's segments now, optionally regardless of heuristics")
+    .post::<CompactSegmentOp>("/admin", "Compacts a segment in full regardless of heuristics")
+    .post::<MergeSegmentsRestOp>("/admin", "Merges a partition's segments")
+    .add_operation("/readyz", "get", |builder| {
+      let readiness_content = serde_json::json!({
+        "application/json": {"schema": builder.schema_ref::<ReadinessResponseSerde>()},
+      });
+      serde_json::json!({
+        "operationId": "readyz",
+        "summary": "Checks whether every background loop is running and the server is healthy",
+        "security": [],
+        "responses": {
+          "200": {"description": "ready", "content": readiness_content},
+          "503": {"description": "a loop is waiting to restart, or the server is unhealthy until restarted", "content": readiness_content},
+        },
+      })
+    })
+    .add_operation("/ws", "get", |builder| serde_json::json!({
+      "operationId": "write_socket",
+      "summary": "Upgrades to a WebSocket taking write_to_partition frames, each answered in order by an ack frame",
+      "description": format!(
+        "Frames follow {} and acks follow {}. The API key may be given as a token query parameter.",
+        builder.schema_ref::<WriteFrameSerde>()["$ref"].as_str().unwrap_or_default(),
+        builder.schema_ref::<WriteAckSerde>()["$ref"].as_str().unwrap_or_default(),
+      ),
+      "parameters": [{"name": "token", "in": "query", "required": false, "schema": {"type": "string"}}],
+      "responses": {"101": {"description": "switching to the WebSocket protocol"}},
+    }))
+    .add_operation("/openapi.json", "get", |_| serde_json::json!({
+      "operationId": "openapi",
+      "summary": "Gets this document",
+      "security": [],
+      "responses": {"200": {"description": "an OpenAPI 3 document"}},
+    }))
+    .build()
+}
+
+// unauthenticated, like the docs of any public API
+fn openapi_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::get()
+    .and(warp::path("openapi.json"))
+    .and(warp::path::end())
+    .map(|| warp::reply::json(&openapi_spec()))
+}
+
+// Unauthenticated, so load balancers and orchestrators can probe it. Not
+// ready while any background loop is waiting to restart after a panic, or
+// once the server is unhealthy until restarted.
+fn readyz_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::get()
+    .and(warp::path("readyz"))
+    .and(warp::path::end())
+    .and(warp::filters::ext::get::<Server>())
+    .and_then(readyz)
+}
+
+async fn readyz(server: Server) -> Result<Box<dyn Reply>, Infallible> {
+  let status = server.background_status().await;
+  let loops = status.loops.into_iter()
+    .map(|(name, loop_status)| LoopHealthSerde {
+      name: name.to_string(),
+      healthy: !loop_status.is_restarting,
+      panics: loop_status.panics,
+      last_panic_at: loop_status.last_panic_at
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true)),
+    })
+    .collect::<Vec<_>>();
+  let unhealthy_reason = server.unhealthy_reason();
+  let ready = unhealthy_reason.is_none() && loops.iter().all(|loop_health| loop_health.healthy);
+  let status_code = if ready {
+    StatusCode::OK
+  } else {
+    StatusCode::SERVICE_UNAVAILABLE
+  };
+  Ok(Box::new(warp::reply::with_status(
+    warp::reply::json(&ReadinessResponseSerde { ready, loops, unhealthy_reason }),
+    status_code,
+  )))
+}
+
+// routes for operators inspecting or maintaining a running server
+fn admin_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path("admin")
+    .and(
+      warp_get_filter::<HeldLocksOp>()
+        .or(warp_get_filter::<BackgroundStatusOp>())
+        .or(warp_get_filter::<StagedSegmentsOp>())
+        .or(warp_get_filter::<RecentErrorsOp>())
+        .or(warp_get_filter::<CacheStatsOp>())
+        .or(warp_get_filter::<DiskUsageOp>())
+        .or(warp_get_filter::<ListTrashOp>())
+        .or(warp_get_filter::<AuditLogOp>())
+        .or(warp_post_filter::<CheckTableOp>())
+        .or(warp_post_filter::<ReloadConfigOp>())
+        .or(warp_post_filter::<CompactTableOp>())
+        .or(warp_post_filter::<CompactSegmentOp>())
+        .or(warp_post_filter::<MergeSegmentsRestOp>())
+    )
+}
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+// the first is what protobuf replies are labeled as
+const PROTOBUF_CONTENT_TYPES: [&str; 2] = ["application/x-protobuf", "application/protobuf"];
+
 // it's too hard to DRY when using warp filters
 pub fn warp_get_filter<Route>() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone
   where Route: RestRoute, Route::Response: Serialize {
+  // browsers can't send GET bodies, so GET requests may use a query string
+  // instead
   warp::get()
     .and(warp::path(Route::ROUTE_NAME))
     .and(warp::filters::ext::get::<Server>())
-    .and(warp::filters::body::bytes())
-    .and_then(warp_execute::<Route>)
+    .and(warp::header::optional::<String>("authorization"))
+    .and(warp::header::optional::<String>("accept"))
+    .and(warp::header::optional::<String>("accept-encoding"))
+    .and(warp::filters::body::stream())
+    .and(
+      warp::filters::query::raw()
+        .or(warp::any().map(String::new))
+        .unify()
+    )
+    .and_then(warp_execute::<Route, _, _>)
 }
 
 // it's too hard to DRY when using warp filters
@@ -40,80 +307,221 @@ pub fn warp_post_filter<Route>() -> impl Filter<Extract = impl Reply, Error = Re
   warp::post()
     .and(warp::path(Route::ROUTE_NAME))
     .and(warp::filters::ext::get::<Server>())
-    .and(warp::filters::body::bytes())
-    .and_then(warp_execute::<Route>)
+    .and(warp::header::optional::<String>("authorization"))
+    .and(warp::header::optional::<String>("accept"))
+    .and(warp::header::optional::<String>("accept-encoding"))
+    .and(warp::filters::body::stream())
+    .and(warp::any().map(String::new))
+    .and_then(warp_execute::<Route, _, _>)
 }
 
-async fn warp_execute<Route>(
+async fn warp_execute<Route, S, B>(
   server: Server,
-  body: Bytes,
+  maybe_authorization: Option<String>,
+  maybe_accept: Option<String>,
+  maybe_accept_encoding: Option<String>,
+  body: S,
+  query: String,
 ) -> Result<Box<dyn Reply>, Infallible>
-  where Route: RestRoute, Route::Response: Serialize {
+  where Route: RestRoute, Route::Response: Serialize, S: Stream<Item=Result<B, warp::Error>>, B: Buf {
+  pancake_result_into_warp::<Route>(
+    execute_from_body::<Route, S, B>(&server, maybe_authorization.as_deref(), body, &query).await,
+    accepts_protobuf(maybe_accept.as_deref()),
+    ContentEncoding::negotiate(maybe_accept_encoding.as_deref()),
+  ).await
+}
+
+// whether an Accept header asks for protobuf over JSON
+fn accepts_protobuf(maybe_accept: Option<&str>) -> bool {
+  maybe_accept.unwrap_or_default()
+    .split(',')
+    .any(|media_range| {
+      let mut parts = media_range.split(';');
+      let media_type = parts.next().unwrap_or_default().trim();
+      let is_refused = parts.any(|param| matches!(param.trim(), "q=0" | "q=0.0"));
+      PROTOBUF_CONTENT_TYPES.contains(&media_type) && !is_refused
+    })
+}
+
+async fn execute_from_body<Route, S, B>(
+  server: &Server,
+  maybe_authorization: Option<&str>,
+  body: S,
+  query: &str,
+) -> ServerResult<Route::Response>
+  where Route: RestRoute, Route::Response: Serialize, S: Stream<Item=Result<B, warp::Error>>, B: Buf {
+  let principal = server.authenticate_bearer(maybe_authorization).await?;
+  let permit = server.admit_request(&principal).await?;
+  let body = read_limited_body(server, body).await?;
   log::info!(
     "received REST request for {} containing {} bytes",
     Route::ROUTE_NAME,
     body.len(),
   );
-  pancake_result_into_warp(
-    execute_from_body::<Route>(&server, body).await,
-    Route::ROUTE_NAME,
-  )
+  let mut req: Route::Req = parse_rest_req(body, query)?;
+  req.normalize_names()?;
+  server.check_request_rows(Route::request_row_count(&req)).await?;
+  // in its own task, so that a client hanging up cancels the op at its next
+  // safe point rather than dropping it halfway through
+  let server = server.clone();
+  cancel::spawn_request(async move {
+    let _permit = permit;
+    authz::scope(principal, Route::new_op(req).execute(&server)).await
+  }).await
 }
 
-async fn execute_from_body<Route>(
-  server: &Server,
-  body: Bytes,
-) -> ServerResult<Route::Response>
-  where Route: RestRoute, Route::Response: Serialize {
-  let req = parse_rest_req(body)?;
-  Route::new_op(req).execute(server).await
+// reads a request body, giving up as soon as it exceeds the size limit
+pub async fn read_limited_body<S, B>(server: &Server, body: S) -> ServerResult<Bytes>
+  where S: Stream<Item=Result<B, warp::Error>>, B: Buf {
+  pin_mut!(body);
+  let mut res = Vec::new();
+  while let Some(chunk_res) = body.next().await {
+    let mut chunk = chunk_res
+      .map_err(|e| ServerError::invalid(format!("could not read request body: {}", e)))?;
+    while chunk.has_remaining() {
+      let bytes = chunk.chunk();
+      let n_bytes = bytes.len();
+      res.extend_from_slice(bytes);
+      chunk.advance(n_bytes);
+    }
+    server.check_request_bytes(res.len()).await?;
+  }
+  Ok(Bytes::from(res))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+  // stable and machine-readable, unlike the message
+  pub code: String,
+  pub message: String,
+  // for writes rejected because of invalid rows, why each was rejected
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub row_errors: Vec<RowErrorSerde>,
+  // for requests rejected by rate or concurrency limits; also sent as the
+  // Retry-After header
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub retry_after_seconds: Option<u64>,
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RowErrorSerde {
+  pub row_index: usize,
   pub message: String,
 }
 
-fn pancake_result_into_warp<T: Serialize>(
-  server_res: ServerResult<T>,
-  route_name: &str,
-) -> Result<Box<dyn Reply>, Infallible> {
-  let body_res = server_res.and_then(|pb|
-    serde_json::to_string(&pb)
+impl From<&ServerError> for ErrorResponse {
+  fn from(e: &ServerError) -> Self {
+    ErrorResponse {
+      code: e.code().to_string(),
+      message: e.to_client_string(),
+      row_errors: e.row_errors.iter()
+        .map(|row_error| RowErrorSerde {
+          row_index: row_error.row_index,
+          message: row_error.message.clone(),
+        })
+        .collect(),
+      retry_after_seconds: e.retry_after_seconds(),
+    }
+  }
+}
+
+async fn pancake_result_into_warp<Route>(
+  server_res: ServerResult<Route::Response>,
+  prefers_protobuf: bool,
+  maybe_encoding: Option<ContentEncoding>,
+) -> Result<Box<dyn Reply>, Infallible>
+  where Route: RestRoute, Route::Response: Serialize {
+  let body_res = server_res.and_then(|resp| {
+    let resp = if prefers_protobuf {
+      match Route::into_protobuf(resp) {
+        Ok(body) => return Ok((body, PROTOBUF_CONTENT_TYPES[0])),
+        Err(resp) => resp,
+      }
+    } else {
+      resp
+    };
+    serde_json::to_vec(&resp)
+      .map(|body| (body, JSON_CONTENT_TYPE))
       .map_err(|_| ServerError::internal("unable to write response as json"))
-  );
-  match body_res {
-    Ok(body) => {
+  });
+  let reply_res = match body_res {
+    Ok((body, content_type)) => {
       log::info!(
         "replying OK to {} request with {} bytes",
-        route_name,
-        body.len()
+        Route::ROUTE_NAME,
+        body.len(),
       );
-      Ok(Box::new(Response::new(body)))
+      encoded_reply(body, content_type, maybe_encoding).await
     },
+    Err(e) => Err(e),
+  };
+  match reply_res {
+    Ok(reply) => Ok(Box::new(reply)),
     Err(e) => {
-      let reply = warp::reply::json(&ErrorResponse {
-        message: e.to_client_string(),
-      });
-      let status = e.kind.warp_status_code();
       log::info!(
         "replying ERR to {} request with status {}: {}",
-        route_name,
-        status,
+        Route::ROUTE_NAME,
+        e.kind.warp_status_code(),
         e,
       );
-      Ok(Box::new(warp::reply::with_status(
-        reply,
-        status,
-      )))
+      Ok(error_reply(&e))
     }
   }
 }
 
-fn parse_rest_req<T: DeserializeOwned>(body: Bytes) -> ServerResult<T> {
+// compresses the body if the client accepts it and it's large enough to
+// be worth it
+async fn encoded_reply(
+  body: Vec<u8>,
+  content_type: &'static str,
+  maybe_encoding: Option<ContentEncoding>,
+) -> ServerResult<Response<Body>> {
+  let mut builder = Response::builder()
+    .header(CONTENT_TYPE, content_type)
+    .header(VARY, "accept-encoding");
+  let body = match maybe_encoding {
+    Some(encoding) if body.len() >= compression::MIN_COMPRESSED_BYTES => {
+      builder = builder.header(CONTENT_ENCODING, encoding.header_value());
+      encoding.compress(body).await?
+    },
+    _ => body,
+  };
+  builder.body(Body::from(body))
+    .map_err(|e| ServerError::internal(format!("unable to build response: {}", e)))
+}
+
+pub fn error_reply(e: &ServerError) -> Box<dyn Reply> {
+  let reply = warp::reply::with_status(
+    warp::reply::json(&ErrorResponse::from(e)),
+    e.kind.warp_status_code(),
+  );
+  match e.retry_after_seconds() {
+    Some(retry_after_seconds) => Box::new(warp::reply::with_header(
+      reply,
+      "retry-after",
+      retry_after_seconds.to_string(),
+    )),
+    None => Box::new(reply),
+  }
+}
+
+pub fn parse_rest_req<T: DeserializeOwned>(body: Bytes, query: &str) -> ServerResult<T> {
+  if body.is_empty() && !query.is_empty() {
+    return serde_urlencoded::from_str(query)
+      .map_err(|_| ServerError::invalid("query string does not parse to the correct request format"));
+  }
+
   let body_string = String::from_utf8(body.to_vec())
     .map_err(|_| ServerError::invalid("body bytes do not parse to string"))?;
-  let req = serde_json::from_str(&body_string)
+  // allow requests without a body, e.g. a plain GET from a browser or curl
+  let body_str = if body_string.trim().is_empty() {
+    "{}"
+  } else {
+    &body_string
+  };
+  let req = serde_json::from_str(body_str)
     .map_err(|_| ServerError::invalid("body string does not parse to the correct request format"))?;
   Ok(req)
 }
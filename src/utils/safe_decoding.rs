@@ -0,0 +1,360 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use pancake_db_core::compression::ValueCodec;
+use pancake_db_core::deletion;
+use pancake_db_core::encoding;
+use pancake_db_core::encoding::ByteIdx;
+use pancake_db_core::errors::{CoreError, CoreResult};
+use pancake_db_idl::dml::FieldValue;
+use pancake_db_idl::dtype::DataType;
+
+use crate::utils::common;
+
+// The core decoders and decompressors index into what they've decoded so
+// far, so some corrupt inputs make them panic instead of returning an error.
+// Everything the server decodes from files goes through these wrappers,
+// which turn such panics into corrupt errors like the ones core returns for
+// the corruption it does detect.
+
+// reserved bytes of the core encoding
+const COUNT_BYTE: u8 = 254;
+const ESCAPE_BYTE: u8 = 255;
+
+pub fn guarded<T>(f: impl FnOnce() -> CoreResult<T>) -> CoreResult<T> {
+  panic::catch_unwind(AssertUnwindSafe(f))
+    .unwrap_or_else(|payload| Err(CoreError::corrupt(&format!(
+      "decoding panicked: {}",
+      common::panic_message(&*payload),
+    ))))
+}
+
+// Bytes starting with a count stand for that many nulls, which core pushes
+// all at once, so a corrupt count can ask for billions of values. Returns
+// the count and the byte index after it.
+fn leading_count(bytes: &[u8]) -> Option<(usize, usize)> {
+  if bytes.first() != Some(&COUNT_BYTE) {
+    return None;
+  }
+  let mut count_bytes = [0_u8; 4];
+  let mut i = 1;
+  for count_byte in &mut count_bytes {
+    *count_byte = match *bytes.get(i)? {
+      ESCAPE_BYTE => {
+        i += 1;
+        !*bytes.get(i)?
+      },
+      b => b,
+    };
+    i += 1;
+  }
+  Some((u32::from_be_bytes(count_bytes) as usize, i))
+}
+
+pub fn decode_limited(
+  dtype: DataType,
+  nested_list_depth: u8,
+  bytes: &[u8],
+  limit: usize,
+) -> CoreResult<Vec<FieldValue>> {
+  if let Some((count, _)) = leading_count(bytes) {
+    if count >= limit {
+      return Ok(vec![FieldValue::default(); limit]);
+    }
+  }
+  guarded(|| {
+    encoding::new_field_value_decoder(dtype, nested_list_depth)
+      .decode_limited(bytes, limit)
+  })
+}
+
+// Like decode_limited, but starting at row start. The rows before it are
+// skipped by scanning their byte indices rather than decoding them.
+pub fn decode_range(
+  dtype: DataType,
+  nested_list_depth: u8,
+  bytes: &[u8],
+  start: usize,
+  limit: usize,
+) -> CoreResult<Vec<FieldValue>> {
+  if start == 0 {
+    return decode_limited(dtype, nested_list_depth, bytes, limit);
+  }
+  // seeking into a count would skip the rest of its nulls
+  if let Some((count, end_idx)) = leading_count(bytes) {
+    if start < count {
+      let n_nulls = (count - start).min(limit);
+      let mut res = vec![FieldValue::default(); n_nulls];
+      if n_nulls < limit {
+        res.extend(decode_limited(dtype, nested_list_depth, &bytes[end_idx..], limit - n_nulls)?);
+      }
+      return Ok(res);
+    }
+  }
+  let byte_idxs = decode_byte_idxs(dtype, nested_list_depth, bytes, start)?;
+  if byte_idxs.len() < start {
+    return Ok(Vec::new());
+  }
+  let start_byte_idx = byte_idxs.last().copied().unwrap_or(0);
+  decode_limited(dtype, nested_list_depth, &bytes[start_byte_idx..], limit)
+}
+
+// Only for bytes the server has just encoded itself, since nothing limits
+// how many nulls a leading count can ask for.
+pub fn decode(dtype: DataType, nested_list_depth: u8, bytes: &[u8]) -> CoreResult<Vec<FieldValue>> {
+  decode_limited(dtype, nested_list_depth, bytes, usize::MAX)
+}
+
+pub fn decode_byte_idxs(
+  dtype: DataType,
+  nested_list_depth: u8,
+  bytes: &[u8],
+  limit: usize,
+) -> CoreResult<Vec<ByteIdx>> {
+  if let Some((count, end_idx)) = leading_count(bytes) {
+    if count >= limit {
+      return Ok(vec![end_idx; limit]);
+    }
+  }
+  guarded(|| {
+    encoding::new_byte_idx_decoder(dtype, nested_list_depth)
+      .decode_limited(bytes, limit)
+  })
+}
+
+pub fn decompress(
+  codec: &dyn ValueCodec,
+  bytes: &[u8],
+  nested_list_depth: u8,
+) -> CoreResult<Vec<FieldValue>> {
+  guarded(|| codec.decompress(bytes, nested_list_depth))
+}
+
+pub fn decompress_deletions(bytes: &[u8]) -> CoreResult<Vec<bool>> {
+  guarded(|| deletion::decompress_deletions(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+  use pancake_db_core::compression;
+  use pancake_db_core::encoding;
+  use pancake_db_core::errors::CoreErrorKind;
+  use pancake_db_idl::dml::{FieldValue, RepeatedFieldValue};
+  use pancake_db_idl::dml::field_value::Value;
+  use pancake_db_idl::dtype::DataType;
+  use prost_types::Timestamp;
+  use rand::{Rng, SeedableRng};
+  use rand::rngs::StdRng;
+
+  use crate::constants::MAX_NESTED_LIST_DEPTH;
+
+  const DTYPES: [DataType; 7] = [
+    DataType::Int64,
+    DataType::String,
+    DataType::Float32,
+    DataType::Float64,
+    DataType::Bytes,
+    DataType::Bool,
+    DataType::TimestampMicros,
+  ];
+  const N_CASES: u64 = 25;
+  const MAX_ROWS: usize = 20;
+  const MAX_LIST_LEN: usize = 4;
+  // the null, count, and escape bytes, which values have to be escaped around
+  const RESERVED_BYTES: [u8; 3] = [253, 254, 255];
+  // core's value codecs mix up the schema and traversal depths when
+  // extracting repetition levels, so they reject lists of lists
+  const MAX_COMPRESSIBLE_DEPTH: u8 = 1;
+  // like the segment row counts the server limits its reads to
+  const ADVERSARIAL_LIMIT: usize = 1000;
+
+  fn random_bytes(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.gen_range(0..12);
+    (0..len)
+      .map(|_| if rng.gen_bool(0.5) {
+        RESERVED_BYTES[rng.gen_range(0..RESERVED_BYTES.len())]
+      } else {
+        rng.gen()
+      })
+      .collect()
+  }
+
+  fn random_atom(rng: &mut StdRng, dtype: DataType) -> Value {
+    match dtype {
+      DataType::Int64 => Value::Int64Val(match rng.gen_range(0..4) {
+        0 => i64::MIN,
+        1 => i64::MAX,
+        // big-endian bytes full of 253s through 255s
+        2 => -rng.gen_range(1..1_000_000),
+        _ => rng.gen(),
+      }),
+      DataType::String => {
+        let len = rng.gen_range(0..8);
+        Value::StringVal((0..len).map(|_| rng.gen::<char>()).collect())
+      },
+      DataType::Float32 => Value::Float32Val(rng.gen_range(-1e30..1e30)),
+      DataType::Float64 => Value::Float64Val(rng.gen_range(-1e300..1e300)),
+      DataType::Bytes => Value::BytesVal(random_bytes(rng)),
+      DataType::Bool => Value::BoolVal(rng.gen()),
+      DataType::TimestampMicros => Value::TimestampVal(Timestamp {
+        seconds: rng.gen_range(-10_000_000_000..10_000_000_000),
+        nanos: rng.gen_range(0..1_000_000) * 1000,
+      }),
+    }
+  }
+
+  // nulls are only allowed at the top level
+  fn random_value(rng: &mut StdRng, dtype: DataType, depth: u8) -> Value {
+    if depth == 0 {
+      random_atom(rng, dtype)
+    } else {
+      let len = rng.gen_range(0..=MAX_LIST_LEN);
+      Value::ListVal(RepeatedFieldValue {
+        vals: (0..len)
+          .map(|_| FieldValue {
+            value: Some(random_value(rng, dtype, depth - 1)),
+          })
+          .collect(),
+      })
+    }
+  }
+
+  fn random_field_values(rng: &mut StdRng, dtype: DataType, depth: u8) -> Vec<FieldValue> {
+    let n_rows = rng.gen_range(0..=MAX_ROWS);
+    (0..n_rows)
+      .map(|_| FieldValue {
+        value: if rng.gen_bool(0.2) {
+          None
+        } else {
+          Some(random_value(rng, dtype, depth))
+        },
+      })
+      .collect()
+  }
+
+  // calls f for each case of each dtype and depth, with its own seed so
+  // failures can be reproduced
+  fn for_each_case(mut f: impl FnMut(u64, DataType, u8, Vec<FieldValue>)) {
+    for (dtype_idx, &dtype) in DTYPES.iter().enumerate() {
+      for depth in 0..=MAX_NESTED_LIST_DEPTH as u8 {
+        for case in 0..N_CASES {
+          let seed = (dtype_idx as u64 * 256 + depth as u64) * N_CASES + case;
+          let mut rng = StdRng::seed_from_u64(seed);
+          let values = random_field_values(&mut rng, dtype, depth);
+          f(seed, dtype, depth, values);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn encoding_round_trips() {
+    for_each_case(|seed, dtype, depth, values| {
+      let encoder = encoding::new_encoder(dtype, depth);
+      let bytes = encoder.encode(&values).unwrap();
+      let decoded = super::decode(dtype, depth, &bytes).unwrap();
+      assert_eq!(decoded, values, "seed {}", seed);
+
+      let mut with_count = bytes.clone();
+      with_count.extend(encoder.encode_count(values.len() as u32));
+      let decoded = super::decode(dtype, depth, &with_count).unwrap();
+      assert_eq!(decoded, values, "seed {} with count", seed);
+
+      let limit = values.len() / 2;
+      let byte_idxs = super::decode_byte_idxs(dtype, depth, &bytes, limit).unwrap();
+      assert_eq!(byte_idxs.len(), limit, "seed {}", seed);
+      let end = byte_idxs.last().copied().unwrap_or(0);
+      let decoded = super::decode(dtype, depth, &bytes[end..]).unwrap();
+      assert_eq!(decoded, values[limit..], "seed {} after seeking", seed);
+
+      let decoded = super::decode_range(dtype, depth, &bytes, limit, 2).unwrap();
+      let range_end = (limit + 2).min(values.len());
+      assert_eq!(decoded, values[limit..range_end], "seed {} in range", seed);
+    });
+  }
+
+  #[test]
+  fn compression_round_trips() {
+    for_each_case(|seed, dtype, depth, values| {
+      if depth > MAX_COMPRESSIBLE_DEPTH {
+        return;
+      }
+      let codec = compression::new_codec(dtype, &compression::choose_codec(dtype)).unwrap();
+      let bytes = codec.compress(&values, depth).unwrap();
+      let decompressed = super::decompress(&*codec, &bytes, depth).unwrap();
+      assert_eq!(decompressed, values, "seed {}", seed);
+    });
+  }
+
+  #[test]
+  fn truncated_encodings_do_not_panic() {
+    for_each_case(|seed, dtype, depth, values| {
+      let bytes = encoding::new_encoder(dtype, depth).encode(&values).unwrap();
+      for end in 0..bytes.len() {
+        // a truncation can land between rows, leaving a valid prefix
+        if let Ok(decoded) = super::decode(dtype, depth, &bytes[..end]) {
+          assert!(
+            decoded.len() <= values.len() && decoded[..] == values[..decoded.len()],
+            "seed {} truncated to {}",
+            seed,
+            end,
+          );
+        }
+        let _ = super::decode_byte_idxs(dtype, depth, &bytes[..end], usize::MAX);
+      }
+    });
+  }
+
+  #[test]
+  fn truncated_compressions_do_not_panic() {
+    for_each_case(|_, dtype, depth, values| {
+      if depth > MAX_COMPRESSIBLE_DEPTH {
+        return;
+      }
+      let codec = compression::new_codec(dtype, &compression::choose_codec(dtype)).unwrap();
+      let bytes = codec.compress(&values, depth).unwrap();
+      for end in 0..bytes.len() {
+        let _ = super::decompress(&*codec, &bytes[..end], depth);
+      }
+    });
+  }
+
+  #[test]
+  fn adversarial_bytes_do_not_panic() {
+    for (dtype_idx, &dtype) in DTYPES.iter().enumerate() {
+      let codec = compression::new_codec(dtype, &compression::choose_codec(dtype)).unwrap();
+      for depth in 0..=MAX_NESTED_LIST_DEPTH as u8 {
+        for case in 0..N_CASES {
+          let mut rng = StdRng::seed_from_u64((dtype_idx as u64 * 256 + depth as u64) * N_CASES + case);
+          // mostly escape sequences, including ones that escape nothing or
+          // end the input
+          let len = rng.gen_range(0..64);
+          let bytes = (0..len)
+            .map(|_| if rng.gen_bool(0.6) {
+              RESERVED_BYTES[rng.gen_range(0..RESERVED_BYTES.len())]
+            } else {
+              rng.gen()
+            })
+            .collect::<Vec<u8>>();
+          let _ = super::decode_limited(dtype, depth, &bytes, ADVERSARIAL_LIMIT);
+          let _ = super::decode_byte_idxs(dtype, depth, &bytes, ADVERSARIAL_LIMIT);
+          let _ = super::decompress(&*codec, &bytes, depth);
+          let _ = super::decompress_deletions(&bytes);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn decompressing_at_the_wrong_depth_is_corrupt() {
+    let codec = compression::new_codec(DataType::Int64, compression::Q_COMPRESS).unwrap();
+    let empty_lists = (0..3)
+      .map(|_| FieldValue {
+        value: Some(Value::ListVal(RepeatedFieldValue::default())),
+      })
+      .collect::<Vec<FieldValue>>();
+    let bytes = codec.compress(&empty_lists, 1).unwrap();
+    // core looks for an atom for each list's repetition level and runs out
+    let err = super::decompress(&*codec, &bytes, 0).unwrap_err();
+    assert_eq!(err.kind, CoreErrorKind::Corrupt);
+  }
+}
@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Timelike, Utc};
+
+use crate::errors::{ServerError, ServerResult};
+
+// A daily window of UTC hours, written start-end, like 2-6 for 2am up to
+// 6am. A window ending at or before its start wraps past midnight, so 22-4
+// is 10pm up to 4am and 0-0 is the whole day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HourWindow {
+  start: u32,
+  end: u32,
+}
+
+impl HourWindow {
+  pub fn contains(&self, t: DateTime<Utc>) -> bool {
+    let hour = t.hour();
+    if self.start < self.end {
+      hour >= self.start && hour < self.end
+    } else {
+      hour >= self.start || hour < self.end
+    }
+  }
+}
+
+impl FromStr for HourWindow {
+  type Err = ServerError;
+
+  fn from_str(s: &str) -> ServerResult<Self> {
+    let invalid = || ServerError::invalid(format!(
+      "invalid hour window {}; expected start-end UTC hours like 2-6",
+      s,
+    ));
+    let (start_str, end_str) = s.split_once('-').ok_or_else(invalid)?;
+    let parse_hour = |hour_str: &str| match hour_str.trim().parse::<u32>() {
+      Ok(hour) if hour <= 24 => Ok(hour % 24),
+      _ => Err(invalid()),
+    };
+    Ok(HourWindow {
+      start: parse_hour(start_str)?,
+      end: parse_hour(end_str)?,
+    })
+  }
+}
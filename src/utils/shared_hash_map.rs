@@ -3,33 +3,64 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::hash::Hasher;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use tokio::sync::RwLock;
 
 use crate::errors::{ServerResult, Contextable};
 use crate::metadata::MetadataKey;
-use rand::Rng;
 
 const HASH_BUCKETS: usize = 16;
-const PRUNE_P: f64 = 0.5;
+// when a bucket is full, evict this fraction of it at once so that we don't
+// need to sort the bucket on every insert
+const EVICT_FRACTION: f64 = 0.25;
+
+struct CacheEntry<V> {
+  lock: Arc<RwLock<V>>,
+  last_access: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CacheStats {
+  pub n_entries: usize,
+  pub size_limit: usize,
+  pub hits: u64,
+  pub misses: u64,
+  pub evictions: u64,
+}
 
 pub struct SharedHashMap<K: MetadataKey, V>{
+  size_limit: usize,
   per_bucket_size_limit: usize,
-  maps: Vec<RwLock<HashMap<K, Arc<RwLock<V>>>>>,
+  maps: Vec<RwLock<HashMap<K, CacheEntry<V>>>>,
+  // logical clock for least-recently-used eviction
+  clock: AtomicU64,
+  hits: AtomicU64,
+  misses: AtomicU64,
+  evictions: AtomicU64,
 }
 
 impl<K, V> SharedHashMap<K, V> where K: MetadataKey {
   pub fn new(size_limit: usize) -> Self {
     let mut maps = Vec::new();
     for _ in 0..HASH_BUCKETS {
-      maps.push(RwLock::new(HashMap::<K, Arc<RwLock<V>>>::new()));
+      maps.push(RwLock::new(HashMap::<K, CacheEntry<V>>::new()));
     }
     SharedHashMap {
-      per_bucket_size_limit: size_limit / HASH_BUCKETS,
+      size_limit,
+      per_bucket_size_limit: (size_limit / HASH_BUCKETS).max(1),
       maps,
+      clock: AtomicU64::new(0),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+      evictions: AtomicU64::new(0),
     }
   }
 
+  fn tick(&self) -> u64 {
+    self.clock.fetch_add(1, Ordering::Relaxed)
+  }
+
   fn hash_bucket(k: &K) -> usize {
     let mut hash = DefaultHasher::new();
     k.hash(&mut hash);
@@ -41,7 +72,11 @@ impl<K, V> SharedHashMap<K, V> where K: MetadataKey {
     let map_lock = &self.maps[bucket_idx];
     let map_guard = map_lock.read().await;
 
-    (map_guard.get(k).cloned(), map_guard.len())
+    let maybe_lock = map_guard.get(k).map(|entry| {
+      entry.last_access.store(self.tick(), Ordering::Relaxed);
+      entry.lock.clone()
+    });
+    (maybe_lock, map_guard.len())
   }
 
   pub async fn get_lock_or<Fut, F>(&self, k: &K, load_fn: F) -> ServerResult<Arc<RwLock<V>>>
@@ -52,6 +87,11 @@ impl<K, V> SharedHashMap<K, V> where K: MetadataKey {
     // if the value is already cached and the bucket size is within bounds, we
     // can stop
     let needs_insert = maybe_res.is_none();
+    if needs_insert {
+      self.misses.fetch_add(1, Ordering::Relaxed);
+    } else {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+    }
     let needs_prune = bucket_size + (needs_insert as usize) >
       self.per_bucket_size_limit;
     if !needs_insert && !needs_prune {
@@ -90,37 +130,44 @@ impl<K, V> SharedHashMap<K, V> where K: MetadataKey {
       // entries with no reference counts (other than the map's strong count of
       // 1). It is safe to do this because we have a write lock on the whole
       // map, and if the reference count is 1, no other thread can increase it.
-      let to_prune: Vec<_> = {
-        let mut rng = rand::thread_rng();
-        map.iter()
-          .filter(|(other_k, arc)|
-            rng.gen_bool(PRUNE_P) &&
-              *other_k != k &&
-              Arc::strong_count(arc) <= 1 // the magic
-          )
-          .map(|(other_k, _)| other_k.clone())
-          .collect()
-      };
+      let mut prunable: Vec<_> = map.iter()
+        .filter(|(other_k, entry)|
+          *other_k != k &&
+            Arc::strong_count(&entry.lock) <= 1 // the magic
+        )
+        .map(|(other_k, entry)| (entry.last_access.load(Ordering::Relaxed), other_k.clone()))
+        .collect();
+      // least recently used first
+      prunable.sort_unstable_by_key(|(last_access, _)| *last_access);
+      let target_size = self.per_bucket_size_limit -
+        (self.per_bucket_size_limit as f64 * EVICT_FRACTION) as usize;
+      let n_to_prune = (map.len() + needs_insert as usize)
+        .saturating_sub(target_size)
+        .min(prunable.len());
       log::debug!(
         "pruning {}/{} entries from {} cache bucket {}",
-        to_prune.len(),
+        n_to_prune,
         map.len(),
         K::ENTITY_NAME,
         bucket,
       );
-      for other_k in &to_prune {
+      for (_, other_k) in &prunable[..n_to_prune] {
         map.remove(other_k);
       }
+      self.evictions.fetch_add(n_to_prune as u64, Ordering::Relaxed);
     }
 
     // insert to cache if needed
     if needs_insert {
-      let res = if let Some(lock) = map.get(k) {
-        lock.clone()
+      let res = if let Some(entry) = map.get(k) {
+        entry.lock.clone()
       } else {
-        let entry = Arc::new(RwLock::new(maybe_loaded_value.unwrap()));
-        map.insert(k.clone(), entry.clone());
-        entry
+        let lock = Arc::new(RwLock::new(maybe_loaded_value.unwrap()));
+        map.insert(k.clone(), CacheEntry {
+          lock: lock.clone(),
+          last_access: AtomicU64::new(self.tick()),
+        });
+        lock
       };
       maybe_res = Some(res);
     }
@@ -142,4 +189,45 @@ impl<K, V> SharedHashMap<K, V> where K: MetadataKey {
       }
     }
   }
+
+  pub async fn entries(&self) -> Vec<(K, Arc<RwLock<V>>)> {
+    let mut res = Vec::new();
+    for map_lock in &self.maps {
+      let map_guard = map_lock.read().await;
+      for (k, entry) in map_guard.iter() {
+        res.push((k.clone(), entry.lock.clone()));
+      }
+    }
+    res
+  }
+
+  // keys whose locks are currently held, and whether each is held (or
+  // awaited) for writing
+  pub async fn held_locks(&self) -> Vec<(K, bool)> {
+    let mut res = Vec::new();
+    for map_lock in &self.maps {
+      let map_guard = map_lock.read().await;
+      for (k, entry) in map_guard.iter() {
+        if entry.lock.try_write().is_err() {
+          let is_write = entry.lock.try_read().is_err();
+          res.push((k.clone(), is_write));
+        }
+      }
+    }
+    res
+  }
+
+  pub async fn stats(&self) -> CacheStats {
+    let mut n_entries = 0;
+    for map_lock in &self.maps {
+      n_entries += map_lock.read().await.len();
+    }
+    CacheStats {
+      n_entries,
+      size_limit: self.size_limit,
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      evictions: self.evictions.load(Ordering::Relaxed),
+    }
+  }
 }
@@ -0,0 +1,269 @@
+use std::io::{ErrorKind, SeekFrom};
+use std::path::Path;
+
+use crate::errors::{ServerError, ServerResult};
+use crate::server::slow_ops;
+use crate::utils::common;
+use crate::utils::encryption::{Cipher, FRAME_HEADER_LEN};
+use crate::utils::vfs;
+
+// Reads and writes of column files and zone maps, which are encrypted at
+// rest when their version uses a key. Offsets and lengths are always of the plaintext, so
+// callers can page through files and trim them the same way either way.
+// Without a cipher, these are the plain common helpers.
+
+// counts bytes read toward the current op's profile
+fn counted(bytes: Vec<u8>) -> Vec<u8> {
+  slow_ops::add_bytes(bytes.len());
+  bytes
+}
+
+struct Frame {
+  file_offset: u64,
+  plaintext_offset: u64,
+  plaintext_len: usize,
+}
+
+impl Frame {
+  fn plaintext_end(&self) -> u64 {
+    self.plaintext_offset + self.plaintext_len as u64
+  }
+
+  fn file_end(&self) -> u64 {
+    self.file_offset + (FRAME_HEADER_LEN + Cipher::frame_body_len(self.plaintext_len)) as u64
+  }
+}
+
+async fn open_if_exists(path: &Path) -> ServerResult<Option<vfs::File>> {
+  match vfs::File::open(path).await {
+    Ok(file) => Ok(Some(file)),
+    Err(e) if matches!(e.kind(), ErrorKind::NotFound) => Ok(None),
+    Err(e) => Err(ServerError::from(e).with_context(format!(
+      "while opening encrypted file {:?}",
+      path,
+    ))),
+  }
+}
+
+// Returns false at the end of the file. A partially written frame or header
+// counts as the end, since an append may be in progress or may have been
+// interrupted, and recovery trims it off.
+async fn read_exact_or_end(file: &mut vfs::File, buf: &mut [u8]) -> ServerResult<bool> {
+  match file.read_exact(buf).await {
+    Ok(_) => Ok(true),
+    Err(e) if matches!(e.kind(), ErrorKind::UnexpectedEof) => Ok(false),
+    Err(e) => Err(e.into()),
+  }
+}
+
+// the complete frames of an encrypted file, without reading their bodies
+async fn scan_frames(file: &mut vfs::File, cipher: &Cipher, path: &Path) -> ServerResult<Vec<Frame>> {
+  let file_len = file.metadata().await?.len();
+  let mut header = vec![0_u8; cipher.file_header_len()];
+  file.seek(SeekFrom::Start(0)).await?;
+  if !read_exact_or_end(file, &mut header).await? {
+    return Ok(Vec::new());
+  }
+  cipher.check_file_header(&header, path)?;
+
+  let mut res: Vec<Frame> = Vec::new();
+  let mut frame_header = [0_u8; FRAME_HEADER_LEN];
+  loop {
+    let (file_offset, plaintext_offset) = match res.last() {
+      Some(frame) => (frame.file_end(), frame.plaintext_end()),
+      None => (header.len() as u64, 0),
+    };
+    file.seek(SeekFrom::Start(file_offset)).await?;
+    if !read_exact_or_end(file, &mut frame_header).await? {
+      break;
+    }
+    let frame = Frame {
+      file_offset,
+      plaintext_offset,
+      plaintext_len: Cipher::frame_plaintext_len(&frame_header),
+    };
+    if frame.file_end() > file_len {
+      break;
+    }
+    res.push(frame);
+  }
+  Ok(res)
+}
+
+async fn read_frame(file: &mut vfs::File, frame: &Frame, cipher: &Cipher, path: &Path) -> ServerResult<Vec<u8>> {
+  let mut frame_header = [0_u8; FRAME_HEADER_LEN];
+  let mut body = vec![0_u8; Cipher::frame_body_len(frame.plaintext_len)];
+  file.seek(SeekFrom::Start(frame.file_offset)).await?;
+  file.read_exact(&mut frame_header).await?;
+  file.read_exact(&mut body).await?;
+  cipher.decrypt_frame(&frame_header, body, frame.plaintext_offset, path)
+}
+
+async fn read_frames(
+  file: &mut vfs::File,
+  frames: &[Frame],
+  cipher: &Cipher,
+  path: &Path,
+) -> ServerResult<Vec<u8>> {
+  let mut res = Vec::new();
+  for frame in frames {
+    res.extend(read_frame(file, frame, cipher, path).await?);
+  }
+  Ok(res)
+}
+
+pub async fn read_or_empty(path: &Path, maybe_cipher: Option<&Cipher>) -> ServerResult<Vec<u8>> {
+  let cipher = match maybe_cipher {
+    Some(cipher) => cipher,
+    None => return common::read_or_empty(path).await.map(counted),
+  };
+  let mut file = match open_if_exists(path).await? {
+    Some(file) => file,
+    None => return Ok(Vec::new()),
+  };
+  let frames = scan_frames(&mut file, cipher, path).await?;
+  read_frames(&mut file, &frames, cipher, path).await.map(counted)
+}
+
+pub async fn read_with_offset(
+  path: &Path,
+  offset: u64,
+  bytes: usize,
+  maybe_cipher: Option<&Cipher>,
+) -> ServerResult<Vec<u8>> {
+  let cipher = match maybe_cipher {
+    Some(cipher) => cipher,
+    None => return common::read_with_offset(path, offset, bytes).await.map(counted),
+  };
+  let mut file = match open_if_exists(path).await? {
+    Some(file) => file,
+    None => return Ok(Vec::new()),
+  };
+  let end = offset + bytes as u64;
+  let frames = scan_frames(&mut file, cipher, path).await?
+    .into_iter()
+    .filter(|frame| frame.plaintext_end() > offset && frame.plaintext_offset < end)
+    .collect::<Vec<_>>();
+  let first_offset = match frames.first() {
+    Some(frame) => frame.plaintext_offset,
+    None => return Ok(Vec::new()),
+  };
+  let plaintext = read_frames(&mut file, &frames, cipher, path).await?;
+  let start = (offset - first_offset) as usize;
+  let stop = ((end - first_offset) as usize).min(plaintext.len());
+  Ok(counted(plaintext[start..stop].to_vec()))
+}
+
+pub async fn len_or_zero(path: &Path, maybe_cipher: Option<&Cipher>) -> ServerResult<u64> {
+  let cipher = match maybe_cipher {
+    Some(cipher) => cipher,
+    None => return common::file_len_or_zero(path).await,
+  };
+  let mut file = match open_if_exists(path).await? {
+    Some(file) => file,
+    None => return Ok(0),
+  };
+  let frames = scan_frames(&mut file, cipher, path).await?;
+  Ok(frames.last().map(|frame| frame.plaintext_end()).unwrap_or(0))
+}
+
+// The caller must make sure nothing else writes to the file at the same time.
+pub async fn append(path: &Path, contents: &[u8], maybe_cipher: Option<&Cipher>) -> ServerResult<()> {
+  slow_ops::add_bytes(contents.len());
+  let cipher = match maybe_cipher {
+    Some(cipher) => cipher,
+    None => return common::append_to_file(path, contents).await,
+  };
+  let mut file = vfs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .truncate(false)
+    .open(path)
+    .await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while opening to append to encrypted file {:?}",
+      path,
+    )))?;
+  let frames = scan_frames(&mut file, cipher, path).await?;
+  // write after the last complete frame, overwriting any partial one
+  let (file_offset, plaintext_offset) = match frames.last() {
+    Some(frame) => (frame.file_end(), frame.plaintext_end()),
+    None => (0, 0),
+  };
+  let mut bytes = Vec::new();
+  if file_offset == 0 {
+    bytes.extend(cipher.file_header());
+  }
+  bytes.extend(cipher.encrypt(contents, plaintext_offset)?);
+  file.set_len(file_offset).await?;
+  file.seek(SeekFrom::Start(file_offset)).await?;
+  file.write_all(&bytes).await
+    .map_err(|e| ServerError::from(e).with_context(format!(
+      "while appending to encrypted file {:?}",
+      path,
+    )))?;
+  Ok(())
+}
+
+// Writes a file if it doesn't exist, or checks that its (plaintext)
+// contents match if it does. Returns whether it wrote the file.
+pub async fn assert_file(path: &Path, content: Vec<u8>, maybe_cipher: Option<&Cipher>) -> ServerResult<bool> {
+  let cipher = match maybe_cipher {
+    Some(cipher) => cipher,
+    None => return common::assert_file(path, content).await,
+  };
+  if common::file_exists(path).await? {
+    if read_or_empty(path, Some(cipher)).await? == content {
+      Ok(false)
+    } else {
+      Err(ServerError::invalid(format!(
+        "file {:?} already exists with different content",
+        path
+      )))
+    }
+  } else {
+    append(path, &content, Some(cipher)).await?;
+    Ok(true)
+  }
+}
+
+// Shortens a file to a plaintext length. Encrypted files are cut at the
+// start of the frame containing that length, and the frame's remaining
+// plaintext is re-encrypted.
+pub async fn truncate(path: &Path, len: u64, maybe_cipher: Option<&Cipher>) -> ServerResult<()> {
+  let mut file = vfs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .open(path)
+    .await?;
+  let cipher = match maybe_cipher {
+    Some(cipher) => cipher,
+    None => {
+      file.set_len(len).await?;
+      return Ok(());
+    },
+  };
+  let frames = scan_frames(&mut file, cipher, path).await?;
+  let maybe_cut_frame = frames.iter()
+    .find(|frame| frame.plaintext_end() > len);
+  match maybe_cut_frame {
+    Some(frame) => {
+      let kept = read_frame(&mut file, frame, cipher, path).await?;
+      let n_kept = (len - frame.plaintext_offset) as usize;
+      file.set_len(frame.file_offset).await?;
+      if n_kept > 0 {
+        file.seek(SeekFrom::Start(frame.file_offset)).await?;
+        file.write_all(&cipher.encrypt(&kept[..n_kept], frame.plaintext_offset)?).await?;
+      }
+    },
+    None => {
+      // drop any partially written frame
+      let file_len = frames.last()
+        .map(|frame| frame.file_end())
+        .unwrap_or(0);
+      file.set_len(file_len).await?;
+    },
+  }
+  Ok(())
+}
@@ -0,0 +1,429 @@
+use std::ffi::OsString;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::utils::chaos;
+use crate::utils::memory_fs::{self, MemoryFileData, MemoryFs, NodeMetadata};
+
+// Every file the server keeps under its dir goes through these, which
+// mirror the parts of tokio::fs the server uses. Paths under a dir mounted
+// in memory are served from a MemoryFs instead of disk, so a whole server,
+// metadata and column files alike, can run without touching disk. In
+// chaos mode, reads, writes, and opens sometimes fail before they start.
+
+static MEMORY_MOUNTS: RwLock<Vec<(PathBuf, Arc<MemoryFs>)>> = RwLock::new(Vec::new());
+
+// Serves everything under root from memory, starting with an empty dir.
+pub fn mount_memory(root: &Path) {
+  let root = memory_fs::normalize(root);
+  let memory_fs = Arc::new(MemoryFs::new(&root));
+  let mut mounts = MEMORY_MOUNTS.write().unwrap();
+  mounts.retain(|(mounted_root, _)| mounted_root != &root);
+  mounts.push((root, memory_fs));
+}
+
+// discards everything stored under root
+pub fn unmount_memory(root: &Path) {
+  let root = memory_fs::normalize(root);
+  MEMORY_MOUNTS.write().unwrap()
+    .retain(|(mounted_root, _)| mounted_root != &root);
+}
+
+pub fn memory_fs(path: &Path) -> Option<Arc<MemoryFs>> {
+  MEMORY_MOUNTS.read().unwrap()
+    .iter()
+    .find(|(root, _)| path.starts_with(root))
+    .map(|(_, memory_fs)| memory_fs.clone())
+}
+
+pub fn is_in_memory(path: &Path) -> bool {
+  memory_fs(path).is_some()
+}
+
+fn cross_device(from: &Path, to: &Path) -> io::Error {
+  io::Error::other(format!(
+    "{:?} and {:?} are not both in memory or both on disk",
+    from,
+    to,
+  ))
+}
+
+pub async fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+  let path = path.as_ref();
+  chaos::io_error(path)?;
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.read(path),
+    None => fs::read(path).await,
+  }
+}
+
+pub async fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+  let bytes = read(path).await?;
+  String::from_utf8(bytes)
+    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub async fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+  let path = path.as_ref();
+  chaos::io_error(path)?;
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.write(path, contents.as_ref()),
+    None => fs::write(path, contents).await,
+  }
+}
+
+pub async fn create_dir(path: impl AsRef<Path>) -> io::Result<()> {
+  let path = path.as_ref();
+  chaos::io_error(path)?;
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.create_dir(path),
+    None => fs::create_dir(path).await,
+  }
+}
+
+pub async fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+  let path = path.as_ref();
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.create_dir_all(path),
+    None => fs::create_dir_all(path).await,
+  }
+}
+
+pub async fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+  let path = path.as_ref();
+  chaos::io_error(path)?;
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.remove_file(path),
+    None => fs::remove_file(path).await,
+  }
+}
+
+pub async fn remove_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+  let path = path.as_ref();
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.remove_dir_all(path),
+    None => fs::remove_dir_all(path).await,
+  }
+}
+
+pub async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+  let (from, to) = (from.as_ref(), to.as_ref());
+  chaos::io_error(to)?;
+  match (memory_fs(from), memory_fs(to)) {
+    (Some(memory_fs), Some(_)) => memory_fs.rename(from, to),
+    (None, None) => fs::rename(from, to).await,
+    _ => Err(cross_device(from, to)),
+  }
+}
+
+pub async fn hard_link(original: impl AsRef<Path>, link: impl AsRef<Path>) -> io::Result<()> {
+  let (original, link) = (original.as_ref(), link.as_ref());
+  chaos::io_error(link)?;
+  match (memory_fs(original), memory_fs(link)) {
+    (Some(memory_fs), Some(_)) => memory_fs.hard_link(original, link),
+    (None, None) => fs::hard_link(original, link).await,
+    _ => Err(cross_device(original, link)),
+  }
+}
+
+// returns the number of bytes copied
+pub async fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+  let (from, to) = (from.as_ref(), to.as_ref());
+  if memory_fs(from).is_none() && memory_fs(to).is_none() {
+    return fs::copy(from, to).await;
+  }
+  let bytes = read(from).await?;
+  write(to, &bytes).await?;
+  Ok(bytes.len() as u64)
+}
+
+// Flushes a file's or dir's contents to disk. Memory has nothing to sync.
+pub async fn sync(path: impl AsRef<Path>) -> io::Result<()> {
+  let path = path.as_ref();
+  if memory_fs(path).is_some() {
+    return Ok(());
+  }
+  fs::File::open(path).await?.sync_all().await
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+  is_dir: bool,
+  len: u64,
+  modified: Option<SystemTime>,
+}
+
+impl Metadata {
+  pub fn is_dir(&self) -> bool {
+    self.is_dir
+  }
+
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  pub fn modified(&self) -> io::Result<SystemTime> {
+    self.modified.ok_or_else(|| io::Error::other("modification times are not supported here"))
+  }
+}
+
+impl From<std::fs::Metadata> for Metadata {
+  fn from(metadata: std::fs::Metadata) -> Self {
+    Metadata {
+      is_dir: metadata.is_dir(),
+      len: metadata.len(),
+      modified: metadata.modified().ok(),
+    }
+  }
+}
+
+impl From<NodeMetadata> for Metadata {
+  fn from(metadata: NodeMetadata) -> Self {
+    Metadata {
+      is_dir: metadata.is_dir,
+      len: metadata.len,
+      modified: Some(metadata.modified),
+    }
+  }
+}
+
+pub async fn metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+  let path = path.as_ref();
+  match memory_fs(path) {
+    Some(memory_fs) => memory_fs.metadata(path).map(Metadata::from),
+    None => fs::metadata(path).await.map(Metadata::from),
+  }
+}
+
+pub enum ReadDir {
+  Disk(fs::ReadDir),
+  Memory(std::vec::IntoIter<PathBuf>, Arc<MemoryFs>),
+}
+
+impl ReadDir {
+  pub async fn next_entry(&mut self) -> io::Result<Option<DirEntry>> {
+    match self {
+      ReadDir::Disk(read_dir) => Ok(read_dir.next_entry().await?.map(DirEntry::Disk)),
+      ReadDir::Memory(paths, memory_fs) => Ok(paths.next().map(|path| DirEntry::Memory(path, memory_fs.clone()))),
+    }
+  }
+}
+
+// Lists a dir. Unlike on disk, an in-memory listing is a snapshot, so it
+// misses entries added after it was made.
+pub async fn read_dir(path: impl AsRef<Path>) -> io::Result<ReadDir> {
+  let path = path.as_ref();
+  match memory_fs(path) {
+    Some(memory_fs) => Ok(ReadDir::Memory(memory_fs.read_dir(path)?.into_iter(), memory_fs)),
+    None => Ok(ReadDir::Disk(fs::read_dir(path).await?)),
+  }
+}
+
+pub enum DirEntry {
+  Disk(fs::DirEntry),
+  Memory(PathBuf, Arc<MemoryFs>),
+}
+
+impl DirEntry {
+  pub fn path(&self) -> PathBuf {
+    match self {
+      DirEntry::Disk(entry) => entry.path(),
+      DirEntry::Memory(path, _) => path.clone(),
+    }
+  }
+
+  pub fn file_name(&self) -> OsString {
+    match self {
+      DirEntry::Disk(entry) => entry.file_name(),
+      DirEntry::Memory(path, _) => path.file_name().map(OsString::from).unwrap_or_default(),
+    }
+  }
+
+  pub async fn metadata(&self) -> io::Result<Metadata> {
+    match self {
+      DirEntry::Disk(entry) => entry.metadata().await.map(Metadata::from),
+      DirEntry::Memory(path, memory_fs) => memory_fs.metadata(path).map(Metadata::from),
+    }
+  }
+
+  // the same as its metadata, which for memory costs nothing extra
+  pub async fn file_type(&self) -> io::Result<Metadata> {
+    self.metadata().await
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+  read: bool,
+  write: bool,
+  append: bool,
+  create: bool,
+  truncate: bool,
+}
+
+impl OpenOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn read(&mut self, read: bool) -> &mut Self {
+    self.read = read;
+    self
+  }
+
+  pub fn write(&mut self, write: bool) -> &mut Self {
+    self.write = write;
+    self
+  }
+
+  pub fn append(&mut self, append: bool) -> &mut Self {
+    self.append = append;
+    self
+  }
+
+  pub fn create(&mut self, create: bool) -> &mut Self {
+    self.create = create;
+    self
+  }
+
+  pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+    self.truncate = truncate;
+    self
+  }
+
+  pub async fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+    let path = path.as_ref();
+    chaos::io_error(path)?;
+    if let Some(memory_fs) = memory_fs(path) {
+      // like on disk, a dir can be opened for reading, just not read
+      let is_dir = memory_fs.metadata(path).map(|metadata| metadata.is_dir).unwrap_or(false);
+      if is_dir && !self.write && !self.append {
+        return Ok(File::MemoryDir);
+      }
+      let data = memory_fs.open(path, self.create, self.truncate)?;
+      return Ok(File::Memory {
+        data,
+        position: 0,
+        append: self.append,
+      });
+    }
+    let file = fs::OpenOptions::new()
+      .read(self.read)
+      .write(self.write)
+      .append(self.append)
+      .create(self.create)
+      .truncate(self.truncate)
+      .open(path)
+      .await?;
+    Ok(File::Disk(file))
+  }
+}
+
+pub enum File {
+  Disk(fs::File),
+  Memory {
+    data: MemoryFileData,
+    position: u64,
+    append: bool,
+  },
+  MemoryDir,
+}
+
+fn is_a_dir() -> io::Error {
+  io::Error::other("is a directory")
+}
+
+impl File {
+  pub async fn open(path: impl AsRef<Path>) -> io::Result<File> {
+    OpenOptions::new().read(true).open(path).await
+  }
+
+  pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      File::Disk(file) => file.read(buf).await,
+      File::Memory { data, position, .. } => {
+        let n = data.read_at(*position, buf);
+        *position += n as u64;
+        Ok(n)
+      },
+      File::MemoryDir => Err(is_a_dir()),
+    }
+  }
+
+  pub async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+    match self {
+      File::Disk(file) => file.read_exact(buf).await.map(|_| ()),
+      File::Memory { data, position, .. } => {
+        if data.len() < *position + buf.len() as u64 {
+          return Err(io::Error::new(ErrorKind::UnexpectedEof, "file ended before the read did"));
+        }
+        *position += data.read_at(*position, buf) as u64;
+        Ok(())
+      },
+      File::MemoryDir => Err(is_a_dir()),
+    }
+  }
+
+  pub async fn seek(&mut self, seek: SeekFrom) -> io::Result<u64> {
+    match self {
+      File::Disk(file) => file.seek(seek).await,
+      File::Memory { data, position, .. } => {
+        *position = data.seek_position(*position, seek)?;
+        Ok(*position)
+      },
+      File::MemoryDir => Err(is_a_dir()),
+    }
+  }
+
+  pub async fn write_all(&mut self, contents: &[u8]) -> io::Result<()> {
+    match self {
+      File::Disk(file) => file.write_all(contents).await,
+      File::Memory { data, position, append } => {
+        if *append {
+          *position = data.len();
+        }
+        data.write_at(*position, contents)?;
+        *position += contents.len() as u64;
+        Ok(())
+      },
+      File::MemoryDir => Err(is_a_dir()),
+    }
+  }
+
+  pub async fn set_len(&self, len: u64) -> io::Result<()> {
+    match self {
+      File::Disk(file) => file.set_len(len).await,
+      File::Memory { data, .. } => data.set_len(len),
+      File::MemoryDir => Err(is_a_dir()),
+    }
+  }
+
+  // memory has nothing to sync
+  pub async fn sync_data(&self) -> io::Result<()> {
+    match self {
+      File::Disk(file) => file.sync_data().await,
+      File::Memory { .. } | File::MemoryDir => Ok(()),
+    }
+  }
+
+  pub async fn metadata(&self) -> io::Result<Metadata> {
+    match self {
+      File::Disk(file) => file.metadata().await.map(Metadata::from),
+      File::Memory { data, .. } => Ok(Metadata {
+        is_dir: false,
+        len: data.len(),
+        modified: None,
+      }),
+      File::MemoryDir => Ok(Metadata {
+        is_dir: true,
+        len: 0,
+        modified: None,
+      }),
+    }
+  }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use futures::{SinkExt, StreamExt};
+use warp::{Filter, Rejection, Reply};
+use warp::ws::{Message, WebSocket, Ws};
+
+use crate::{Server, ServerResult};
+use crate::errors::ServerError;
+use crate::ops::traits::{RestRoute, ServerOp};
+use crate::ops::write_to_partition_rest::WriteToPartitionRestOp;
+use crate::serde_models::{WriteAckSerde, WriteFrameSerde, WriteToPartitionRequestSerde, WriteToPartitionResponseSerde};
+use crate::server::{authz, cancel};
+use crate::server::authz::Principal;
+use crate::utils::names::NormalizeNames;
+use crate::utils::rest::{self, ErrorResponse};
+
+// GET /ws upgrades to a WebSocket for publishers, like browsers, that send
+// many small writes. Each text or binary frame is a write_to_partition
+// request, answered in order by an ack frame with the frame's sequence
+// number, its id if it had one, and either the write's response or an
+// error. An error only fails its own frame. Browsers can't set headers on
+// WebSockets, so the API key may also be given as a token query parameter.
+pub fn warp_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+  warp::path("ws")
+    .and(warp::path::end())
+    .and(warp::ws())
+    .and(warp::filters::ext::get::<Server>())
+    .and(warp::header::optional::<String>("authorization"))
+    .and(warp::query::<HashMap<String, String>>())
+    .and_then(upgrade)
+}
+
+async fn upgrade(
+  ws: Ws,
+  server: Server,
+  maybe_authorization: Option<String>,
+  query: HashMap<String, String>,
+) -> Result<Box<dyn Reply>, Infallible> {
+  let principal_res = match query.get("token") {
+    Some(token) => server.authenticate(Some(token)).await,
+    None => server.authenticate_bearer(maybe_authorization.as_deref()).await,
+  };
+  let principal = match principal_res {
+    Ok(principal) => principal,
+    Err(e) => return Ok(rest::error_reply(&e)),
+  };
+  let max_request_bytes = server.runtime_config().await.max_request_bytes;
+  log::info!("opening write socket for {}", principal);
+  Ok(Box::new(
+    ws.max_message_size(max_request_bytes)
+      .on_upgrade(move |socket| serve(server, principal, socket))
+  ))
+}
+
+async fn serve(server: Server, principal: Principal, socket: WebSocket) {
+  let (mut sender, mut receiver) = socket.split();
+  let mut seq = 0;
+  while let Some(message_res) = receiver.next().await {
+    let message = match message_res {
+      Ok(message) => message,
+      Err(e) => {
+        log::info!("write socket for {} failed: {}", principal, e);
+        break;
+      }
+    };
+    if message.is_close() {
+      break;
+    }
+    // pings are answered for us
+    if !message.is_text() && !message.is_binary() {
+      continue;
+    }
+
+    let (id, res) = match serde_json::from_slice::<WriteFrameSerde>(message.as_bytes()) {
+      Ok(frame) => (frame.id, write_frame(&server, &principal, frame.req).await),
+      Err(_) => (None, Err(ServerError::invalid("frame does not parse to the correct request format"))),
+    };
+    let ack = match res {
+      Ok(response) => WriteAckSerde {
+        seq,
+        id,
+        response: Some(response),
+        error: None,
+      },
+      Err(e) => WriteAckSerde {
+        seq,
+        id,
+        response: None,
+        error: Some(ErrorResponse::from(&e)),
+      },
+    };
+    seq += 1;
+    let ack_string = serde_json::to_string(&ack).unwrap_or_default();
+    if sender.send(Message::text(ack_string)).await.is_err() {
+      break;
+    }
+  }
+  // finishes the close handshake, if the client started it
+  let _ = sender.close().await;
+  log::info!("closed write socket for {} after {} frames", principal, seq);
+}
+
+// each frame is limited and cancellable like a REST request of its own
+async fn write_frame(
+  server: &Server,
+  principal: &Principal,
+  mut req: WriteToPartitionRequestSerde,
+) -> ServerResult<WriteToPartitionResponseSerde> {
+  req.normalize_names()?;
+  let permit = server.admit_request(principal).await?;
+  server.check_request_rows(WriteToPartitionRestOp::request_row_count(&req)).await?;
+  let server = server.clone();
+  let principal = principal.clone();
+  cancel::spawn_request(async move {
+    let _permit = permit;
+    authz::scope(principal, WriteToPartitionRestOp::new_op(req).execute(&server)).await
+  }).await
+}
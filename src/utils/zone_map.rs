@@ -0,0 +1,216 @@
+use std::cmp::Ordering;
+use std::convert::TryInto;
+
+use pancake_db_core::encoding;
+use pancake_db_idl::dml::FieldValue;
+use pancake_db_idl::dml::field_value::Value;
+use pancake_db_idl::schema::ColumnMeta;
+use prost::Message;
+
+use crate::errors::ServerResult;
+use crate::utils::common;
+
+// flushes split their rows into blocks of at most this many rows
+pub const BLOCK_N_ROWS: usize = 1024;
+const BLOCK_HEADER_BYTE_SIZE: usize = 8;
+
+// A zone map is a sidecar to a flush column file, listing the file's blocks
+// in order. Each block serializes as its row count and byte length (big
+// endian u32s) followed by its min and max as length-delimited protobuf.
+// A block with no comparable values has null min and max.
+#[derive(Clone, Debug, Default)]
+pub struct ZoneMapBlock {
+  pub n_rows: u32,
+  pub byte_len: u32,
+  pub min: FieldValue,
+  pub max: FieldValue,
+}
+
+impl ZoneMapBlock {
+  fn new(values: &[FieldValue], byte_len: usize) -> Self {
+    let mut min: Option<&Value> = None;
+    let mut max: Option<&Value> = None;
+    for value in values.iter().filter_map(|fv| fv.value.as_ref()) {
+      // NaNs compare to nothing and can't satisfy a range anyway
+      if common::compare_values(value, value).is_none() {
+        continue;
+      }
+      if min.map(|m| common::compare_values(value, m) == Some(Ordering::Less)).unwrap_or(true) {
+        min = Some(value);
+      }
+      if max.map(|m| common::compare_values(value, m) == Some(Ordering::Greater)).unwrap_or(true) {
+        max = Some(value);
+      }
+    }
+    ZoneMapBlock {
+      n_rows: values.len() as u32,
+      byte_len: byte_len as u32,
+      min: FieldValue { value: min.cloned() },
+      max: FieldValue { value: max.cloned() },
+    }
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut res = Vec::with_capacity(BLOCK_HEADER_BYTE_SIZE);
+    res.extend(self.n_rows.to_be_bytes());
+    res.extend(self.byte_len.to_be_bytes());
+    res.extend(self.min.encode_length_delimited_to_vec());
+    res.extend(self.max.encode_length_delimited_to_vec());
+    res
+  }
+}
+
+// An inclusive range on one column's non-null values; either bound may be
+// omitted.
+#[derive(Clone, Debug)]
+pub struct ZoneMapPredicate {
+  pub column_name: String,
+  pub min: Option<FieldValue>,
+  pub max: Option<FieldValue>,
+}
+
+impl ZoneMapPredicate {
+  // false means no row in the block satisfies the predicate
+  pub fn may_match(&self, block: &ZoneMapBlock) -> bool {
+    let (block_min, block_max) = match (&block.min.value, &block.max.value) {
+      (Some(block_min), Some(block_max)) => (block_min, block_max),
+      _ => return false,
+    };
+    if let Some(Some(min)) = self.min.as_ref().map(|fv| &fv.value) {
+      if common::compare_values(block_max, min) == Some(Ordering::Less) {
+        return false;
+      }
+    }
+    if let Some(Some(max)) = self.max.as_ref().map(|fv| &fv.value) {
+      if common::compare_values(block_min, max) == Some(Ordering::Greater) {
+        return false;
+      }
+    }
+    true
+  }
+
+  // whether a single value satisfies the predicate
+  pub fn matches(&self, value: &FieldValue) -> bool {
+    let value = match &value.value {
+      Some(value) => value,
+      None => return false,
+    };
+    if let Some(Some(min)) = self.min.as_ref().map(|fv| &fv.value) {
+      if !matches!(common::compare_values(value, min), Some(Ordering::Equal | Ordering::Greater)) {
+        return false;
+      }
+    }
+    if let Some(Some(max)) = self.max.as_ref().map(|fv| &fv.value) {
+      if !matches!(common::compare_values(value, max), Some(Ordering::Equal | Ordering::Less)) {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+// a row range skipped entirely because of a zone map
+#[derive(Clone, Copy, Debug)]
+pub struct SkippedRows {
+  pub row_offset: u32,
+  pub n_rows: u32,
+}
+
+// Encodes values for a flush column file block by block, returning the
+// encoded bytes and the zone map bytes describing them.
+pub fn encode_blocks(col_meta: &ColumnMeta, values: &[FieldValue]) -> ServerResult<(Vec<u8>, Vec<u8>)> {
+  let encoder = encoding::new_encoder(
+    common::unwrap_dtype(col_meta.dtype)?,
+    col_meta.nested_list_depth as u8,
+  );
+  let mut bytes = Vec::new();
+  let mut zone_map_bytes = Vec::new();
+  for chunk in values.chunks(BLOCK_N_ROWS) {
+    let chunk_bytes = encoder.encode(chunk)?;
+    zone_map_bytes.extend(ZoneMapBlock::new(chunk, chunk_bytes.len()).to_bytes());
+    bytes.extend(chunk_bytes);
+  }
+  Ok((bytes, zone_map_bytes))
+}
+
+// zone map bytes for a block of nulls
+pub fn null_block_bytes(n_rows: u32, byte_len: usize) -> Vec<u8> {
+  ZoneMapBlock {
+    n_rows,
+    byte_len: byte_len as u32,
+    ..Default::default()
+  }.to_bytes()
+}
+
+// Parses a zone map, returning None if it is malformed or doesn't describe
+// exactly flush_byte_len bytes. A crash mid-flush can leave a zone map
+// behind its flush file, in which case it is unusable until the segment's
+// next compaction starts a fresh one.
+pub fn parse(bytes: &[u8], flush_byte_len: u64) -> Option<Vec<ZoneMapBlock>> {
+  let mut rest = bytes;
+  let mut blocks = Vec::new();
+  let mut total_byte_len = 0;
+  while !rest.is_empty() {
+    let (block, block_byte_size) = parse_block(rest)?;
+    total_byte_len += block.byte_len as u64;
+    blocks.push(block);
+    rest = &rest[block_byte_size..];
+  }
+  if total_byte_len == flush_byte_len {
+    Some(blocks)
+  } else {
+    None
+  }
+}
+
+// The leading blocks of a zone map that end within flush_byte_len bytes.
+// Each block still describes the bytes it was written for when the zone
+// map is behind its flush file, or ahead of one trimmed after a crash, so
+// these are usable for seeking even when parse rejects the zone map.
+pub fn parse_prefix(bytes: &[u8], flush_byte_len: u64) -> Vec<ZoneMapBlock> {
+  let mut rest = bytes;
+  let mut blocks = Vec::new();
+  let mut total_byte_len = 0;
+  while let Some((block, block_byte_size)) = parse_block(rest) {
+    total_byte_len += block.byte_len as u64;
+    if total_byte_len > flush_byte_len {
+      break;
+    }
+    blocks.push(block);
+    rest = &rest[block_byte_size..];
+  }
+  blocks
+}
+
+// The length to truncate a zone map to after its flush file has been
+// trimmed to flush_byte_len, keeping only the blocks that end by then.
+pub fn trimmed_len(bytes: &[u8], flush_byte_len: u64) -> usize {
+  let mut zone_map_len = 0;
+  let mut total_byte_len = 0;
+  while let Some((block, block_byte_size)) = parse_block(&bytes[zone_map_len..]) {
+    total_byte_len += block.byte_len as u64;
+    if total_byte_len > flush_byte_len {
+      break;
+    }
+    zone_map_len += block_byte_size;
+  }
+  zone_map_len
+}
+
+fn parse_block(bytes: &[u8]) -> Option<(ZoneMapBlock, usize)> {
+  if bytes.len() < BLOCK_HEADER_BYTE_SIZE {
+    return None;
+  }
+  let n_rows = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+  let byte_len = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+  let mut rest = &bytes[BLOCK_HEADER_BYTE_SIZE..];
+  let min = FieldValue::decode_length_delimited(&mut rest).ok()?;
+  let max = FieldValue::decode_length_delimited(&mut rest).ok()?;
+  let block = ZoneMapBlock {
+    n_rows,
+    byte_len,
+    min,
+    max,
+  };
+  Some((block, bytes.len() - rest.len()))
+}
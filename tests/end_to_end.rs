@@ -0,0 +1,271 @@
+// Runs the server the way the binary does, on a temporary dir and free
+// ports, and drives it over GRPC with the network client through the whole
+// life of a table, restarting the server along the way.
+
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use futures::StreamExt;
+use pancake_db_client::{Client, SegmentKey, new_correlation_id};
+use pancake_db_client::errors::{ClientErrorKind, ClientResult};
+use pancake_db_client_ext::{ColumnValue, PancakeClient, PancakeRow, PartitionBuilder};
+use pancake_db_idl::ddl::{CreateTableRequest, DropTableRequest, GetSchemaRequest, ListTablesRequest};
+use pancake_db_idl::dml::{DeleteFromSegmentRequest, ListSegmentsRequest, ReadSegmentColumnRequest, Segment, WriteToPartitionRequest};
+use pancake_db_idl::dtype::DataType;
+use pancake_db_idl::partition_dtype::PartitionDataType;
+use pancake_db_idl::schema::{ColumnMeta, PartitionMeta, Schema};
+use pancake_db_server::{Opt, ServerResult};
+use structopt::StructOpt;
+use tempfile::TempDir;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep};
+use tonic::Code;
+
+const TABLE_NAME: &str = "end_to_end_events";
+const N_PARTITIONS: i64 = 2;
+const N_ROWS_PER_WRITE: i64 = 50;
+const N_WRITES: i64 = 4;
+const TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, PartialEq, PancakeRow)]
+struct Event {
+  i: i64,
+  tags: Vec<String>,
+}
+
+fn event(i: i64) -> Event {
+  Event {
+    i,
+    tags: (0..i % 3).map(|j| format!("tag_{}", j)).collect(),
+  }
+}
+
+fn schema() -> Schema {
+  let mut schema = Event::schema();
+  schema.partitioning.insert("pk".to_string(), PartitionMeta {
+    dtype: PartitionDataType::Int64 as i32,
+  });
+  schema
+}
+
+fn free_port() -> u16 {
+  TcpListener::bind("127.0.0.1:0")
+    .and_then(|listener| listener.local_addr())
+    .expect("unable to find a free port")
+    .port()
+}
+
+struct TestServer {
+  grpc_port: u16,
+  task: JoinHandle<ServerResult<()>>,
+}
+
+impl TestServer {
+  // background loops run every second, and segments are compacted as soon
+  // as they have flushed rows
+  fn start(dir: &TempDir) -> TestServer {
+    let http_port = free_port().to_string();
+    let grpc_port = free_port();
+    let grpc_port_str = grpc_port.to_string();
+    let opts = Opt::from_iter(&[
+      "pancake-db-server",
+      "--dir", dir.path().to_str().unwrap(),
+      "--http-port", &http_port,
+      "--grpc-port", &grpc_port_str,
+      "--flush-loop-seconds", "1",
+      "--compaction-loop-seconds", "1",
+      "--min-rows-for-compaction", "1",
+      "--min-compaction-intermission-seconds", "0",
+      "--compact-as-constant-seconds", "0",
+    ]);
+    opts.validate();
+    TestServer {
+      grpc_port,
+      task: tokio::spawn(pancake_db_server::serve(opts)),
+    }
+  }
+
+  async fn client(&self) -> Client {
+    let start = Instant::now();
+    loop {
+      match Client::connect(format!("http://127.0.0.1:{}", self.grpc_port)).await {
+        Ok(client) => return client,
+        Err(e) if start.elapsed() > TIMEOUT => panic!("unable to connect: {}", e),
+        Err(_) => sleep(POLL_INTERVAL).await,
+      }
+    }
+  }
+
+  // abruptly, like a crash
+  async fn kill(self) {
+    self.task.abort();
+    let _ = self.task.await;
+  }
+}
+
+async fn list_segments(client: &mut Client) -> ClientResult<Vec<Segment>> {
+  Ok(client.list_segments(ListSegmentsRequest {
+    table_name: TABLE_NAME.to_string(),
+    ..Default::default()
+  }).await?.segments)
+}
+
+fn segment_key(segment: &Segment) -> SegmentKey {
+  SegmentKey {
+    table_name: TABLE_NAME.to_string(),
+    partition: segment.partition.clone(),
+    segment_id: segment.segment_id.clone(),
+  }
+}
+
+// every live event in the table, sorted
+async fn read_events(client: &mut Client) -> ClientResult<Vec<Event>> {
+  let columns = schema().columns;
+  let mut events = Vec::new();
+  for segment in list_segments(client).await? {
+    for row in client.decode_segment(&segment_key(&segment), &columns).await? {
+      events.push(Event::from_row(&row)?);
+    }
+  }
+  events.sort_by_key(|event| event.i);
+  Ok(events)
+}
+
+// whether every segment's column has been compacted, which requires its
+// rows to have been flushed first
+async fn is_compacted(client: &mut Client) -> ClientResult<bool> {
+  for segment in list_segments(client).await? {
+    let mut stream = PancakeClient::read_segment_column(client, ReadSegmentColumnRequest {
+      table_name: TABLE_NAME.to_string(),
+      partition: segment.partition,
+      segment_id: segment.segment_id,
+      column_name: "i".to_string(),
+      correlation_id: new_correlation_id(),
+    }).await?;
+    let resp = stream.next().await.expect("empty read")?;
+    if resp.codec.is_empty() {
+      return Ok(false);
+    }
+  }
+  Ok(true)
+}
+
+async fn wait_for_compaction(client: &mut Client) -> ClientResult<()> {
+  let start = Instant::now();
+  while !is_compacted(client).await? {
+    if start.elapsed() > TIMEOUT {
+      panic!("segments were not compacted within {:?}", TIMEOUT);
+    }
+    sleep(POLL_INTERVAL).await;
+  }
+  Ok(())
+}
+
+// deletes the events whose i is a multiple of 5, returning how many
+async fn delete_multiples_of_five(client: &mut Client) -> ClientResult<u32> {
+  let mut columns = HashMap::new();
+  columns.insert("i".to_string(), ColumnMeta {
+    dtype: DataType::Int64 as i32,
+    ..Default::default()
+  });
+  columns.insert("_row_id".to_string(), ColumnMeta {
+    dtype: DataType::Int64 as i32,
+    ..Default::default()
+  });
+  let mut n_deleted = 0;
+  for segment in list_segments(client).await? {
+    let rows = client.decode_segment(&segment_key(&segment), &columns).await?;
+    let row_ids = rows.iter()
+      .filter(|row| i64::try_from_field_value(&row.fields["i"]).unwrap() % 5 == 0)
+      .map(|row| i64::try_from_field_value(&row.fields["_row_id"]).unwrap() as u32)
+      .collect();
+    n_deleted += client.delete_from_segment(DeleteFromSegmentRequest {
+      table_name: TABLE_NAME.to_string(),
+      partition: segment.partition,
+      segment_id: segment.segment_id,
+      row_ids,
+    }).await?.n_deleted;
+  }
+  Ok(n_deleted)
+}
+
+fn is_not_found<T>(res: ClientResult<T>) -> bool {
+  matches!(res, Err(e) if e.kind == ClientErrorKind::Grpc { code: Code::NotFound })
+}
+
+#[tokio::test]
+async fn table_lifecycle_survives_restarts() -> ClientResult<()> {
+  let dir = TempDir::new().unwrap();
+  let server = TestServer::start(&dir);
+  let mut client = server.client().await;
+
+  client.create_table(CreateTableRequest {
+    table_name: TABLE_NAME.to_string(),
+    schema: Some(schema()),
+    ..Default::default()
+  }).await?;
+  let mut expected = Vec::new();
+  for write_idx in 0..N_WRITES {
+    for pk in 0..N_PARTITIONS {
+      let events = (0..N_ROWS_PER_WRITE)
+        .map(|j| event((write_idx * N_PARTITIONS + pk) * N_ROWS_PER_WRITE + j))
+        .collect::<Vec<_>>();
+      client.write_to_partition(WriteToPartitionRequest {
+        table_name: TABLE_NAME.to_string(),
+        partition: PartitionBuilder::new().field("pk", pk).build(),
+        rows: events.iter().map(Event::to_row).collect(),
+      }).await?;
+      expected.extend(events);
+    }
+  }
+  expected.sort_by_key(|event| event.i);
+  // staged rows are readable right away
+  assert_eq!(read_events(&mut client).await?, expected);
+  assert_eq!(list_segments(&mut client).await?.len(), N_PARTITIONS as usize);
+
+  wait_for_compaction(&mut client).await?;
+  assert_eq!(read_events(&mut client).await?, expected);
+
+  // writes after a restart land next to the compacted rows
+  server.kill().await;
+  let server = TestServer::start(&dir);
+  let mut client = server.client().await;
+  assert_eq!(read_events(&mut client).await?, expected);
+  let late_event = event(expected.len() as i64);
+  client.write_to_partition(WriteToPartitionRequest {
+    table_name: TABLE_NAME.to_string(),
+    partition: PartitionBuilder::new().field("pk", 0_i64).build(),
+    rows: vec![late_event.to_row()],
+  }).await?;
+  expected.push(late_event);
+  assert_eq!(read_events(&mut client).await?, expected);
+
+  let n_deleted = delete_multiples_of_five(&mut client).await?;
+  let n_before_delete = expected.len();
+  expected.retain(|event| event.i % 5 != 0);
+  assert_eq!(n_deleted as usize, n_before_delete - expected.len());
+  assert_eq!(read_events(&mut client).await?, expected);
+
+  // deletions survive another restart
+  server.kill().await;
+  let server = TestServer::start(&dir);
+  let mut client = server.client().await;
+  assert_eq!(read_events(&mut client).await?, expected);
+
+  client.drop_table(DropTableRequest {
+    table_name: TABLE_NAME.to_string(),
+  }).await?;
+  let tables = client.list_tables(ListTablesRequest::default()).await?.tables;
+  assert!(tables.iter().all(|table| table.table_name != TABLE_NAME));
+
+  server.kill().await;
+  let server = TestServer::start(&dir);
+  let mut client = server.client().await;
+  assert!(is_not_found(client.get_schema(GetSchemaRequest {
+    table_name: TABLE_NAME.to_string(),
+  }).await));
+  server.kill().await;
+  Ok(())
+}